@@ -6,7 +6,7 @@ pub use factor_core::{
         value_type::{ObjectField, ObjectType, ValueType, ValueTypeDescriptor},
         DataMap, Id, IdOrIdent, Timestamp, ValueMap,
     },
-    db::{Db, DbClient},
+    db::{Db, DbClient, DbConfig, RetryPolicy, Session},
     map,
     query::{
         self,
@@ -19,12 +19,18 @@ pub use factor_core::{
         self,
         builtin::{AttrId, AttrIdent, AttrType},
         AttrMapExt, Attribute, AttributeMeta, Cardinality, Class, ClassAttribute, ClassContainer,
-        ClassMeta, DbSchema,
+        ClassMeta, DbSchema, ValueConstraint,
     },
+    schema_bundle,
 };
 
+#[cfg(feature = "engine")]
+mod open;
+#[cfg(feature = "engine")]
+pub use self::open::open;
+
 pub mod macros {
-    pub use factor_macros::{Attribute, Class};
+    pub use factor_macros::{factor_check_schema, Attribute, Class};
 }
 
 pub use factor_macros::{Attribute as DeriveAttr, Class as DeriveClass};
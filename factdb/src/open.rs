@@ -0,0 +1,126 @@
+use factor_core::db::Db;
+use factor_engine::{
+    backend::{
+        log::{store_memory::MemoryLogStore, LogDb},
+        memory::MemoryDb,
+    },
+    Engine,
+};
+
+/// Open a database from a connection string.
+///
+/// Supported connection strings:
+/// * `memory://`: a new in-memory database
+/// * `log+memory://`: a new log database with an in-memory log
+/// * `log+file://<PATH>`: a log database stored in a file.
+///   The file is created if it does not exist.
+///   Use `log+file:///<PATH>` for absolute paths.
+/// * `http://<HOST>`: a remote database served by `factor_tools`.
+///   Use [`factor_client_http::connect`] to provide an auth token.
+///
+/// Requires the `log_fs` feature for `log+file` and the `http` feature for
+/// `http`.
+pub async fn open(url: &str) -> Result<Db, anyhow::Error> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| anyhow::anyhow!("Invalid connection string '{}': missing scheme", url))?;
+
+    match scheme {
+        "memory" | "log+memory" if !rest.is_empty() => {
+            anyhow::bail!(
+                "Invalid connection string '{}': {} databases do not take a path",
+                url,
+                scheme
+            );
+        }
+        "memory" => Ok(Engine::new(MemoryDb::new()).into_client()),
+        "log+memory" => {
+            let log = LogDb::open(MemoryLogStore::new()).await?;
+            Ok(Engine::new(log).into_client())
+        }
+        "log+file" => open_log_file(url, rest).await,
+        "http" => open_http(url),
+        "sqlite" => anyhow::bail!(
+            "Invalid connection string '{}': the sqlite backend is not available",
+            url
+        ),
+        other => anyhow::bail!(
+            "Invalid connection string '{}': unknown scheme '{}'",
+            url,
+            other
+        ),
+    }
+}
+
+#[cfg(feature = "log_fs")]
+async fn open_log_file(url: &str, path: &str) -> Result<Db, anyhow::Error> {
+    use anyhow::Context;
+    use factor_engine::backend::log::{convert_json::JsonConverter, store_file::FileLogStore};
+
+    if path.is_empty() {
+        anyhow::bail!("Invalid connection string '{}': missing path", url);
+    }
+    let store = FileLogStore::open(JsonConverter, path)
+        .await
+        .with_context(|| format!("Could not open database file '{}'", path))?;
+    let log = LogDb::open(store)
+        .await
+        .with_context(|| format!("Could not restore database from '{}'", path))?;
+    Ok(Engine::new(log).into_client())
+}
+
+#[cfg(not(feature = "log_fs"))]
+async fn open_log_file(url: &str, _path: &str) -> Result<Db, anyhow::Error> {
+    anyhow::bail!(
+        "Invalid connection string '{}': file databases require the log_fs feature",
+        url
+    );
+}
+
+#[cfg(feature = "http")]
+fn open_http(url: &str) -> Result<Db, anyhow::Error> {
+    factor_client_http::connect(url, None)
+}
+
+#[cfg(not(feature = "http"))]
+fn open_http(url: &str) -> Result<Db, anyhow::Error> {
+    anyhow::bail!(
+        "Invalid connection string '{}': remote databases require the http feature",
+        url
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{data::Id, map};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open() {
+        for url in ["memory://", "log+memory://"] {
+            let db = open(url).await.unwrap();
+            let id = Id::random();
+            db.create(id, map! { "factor/title": "a" }).await.unwrap();
+            db.entity(id).await.unwrap();
+        }
+
+        let path = std::env::temp_dir().join("factdb_open_test.db");
+        if path.is_file() {
+            std::fs::remove_file(&path).unwrap();
+        }
+        let url = format!("log+file://{}", path.display());
+        let id = Id::random();
+        open(&url)
+            .await
+            .unwrap()
+            .create(id, map! { "factor/title": "a" })
+            .await
+            .unwrap();
+        open(&url).await.unwrap().entity(id).await.unwrap();
+
+        assert!(open("memory://x").await.is_err());
+        assert!(open("sqlite:///tmp/db").await.is_err());
+        assert!(open("/tmp/db").await.is_err());
+    }
+}
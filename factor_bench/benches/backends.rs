@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use factor_bench::{bench_backend, BenchConfig};
+use factor_engine::{
+    backend::{
+        log::{store_memory::MemoryLogStore, LogDb},
+        memory::MemoryDb,
+    },
+    Engine,
+};
+
+fn bench_memory(c: &mut Criterion) {
+    bench_backend(c, "memory", &BenchConfig::default(), || {
+        Engine::new(MemoryDb::new()).into_client()
+    });
+}
+
+fn bench_log(c: &mut Criterion) {
+    bench_backend(c, "log_memory", &BenchConfig::default(), || {
+        let log = futures::executor::block_on(LogDb::open(MemoryLogStore::new())).unwrap();
+        Engine::new(log).into_client()
+    });
+}
+
+criterion_group!(benches, bench_memory, bench_log);
+criterion_main!(benches);
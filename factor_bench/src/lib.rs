@@ -0,0 +1,237 @@
+//! Criterion benchmarks for factordb backends.
+//!
+//! The same set of workloads can be run against any backend with
+//! [`bench_backend`]. See `benches/backends.rs` for the built-in backends.
+//!
+//! Regressions are tracked with criterion baselines:
+//! save a baseline with `cargo bench -p factor_bench -- --save-baseline main`
+//! and compare against it with `cargo bench -p factor_bench -- --baseline main`.
+
+use criterion::{async_executor::FuturesExecutor, BatchSize, Criterion};
+use factor_core::{
+    data::{DataMap, Id, Value, ValueType},
+    db::Db,
+    query::{
+        expr::Expr,
+        migrate::Migration,
+        mutate::{Batch, Create},
+        select::{Order, Select},
+    },
+    schema::Attribute,
+};
+
+pub const ATTR_NAME: &str = "bench/name";
+pub const ATTR_GROUP: &str = "bench/group";
+
+/// Number of distinct `bench/group` values.
+pub const GROUPS: u64 = 100;
+
+/// Number of entities created per batch when populating a database.
+const POPULATE_BATCH_SIZE: u64 = 1000;
+
+/// Configuration for [`bench_backend`].
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    /// Number of entities in the database for the query benchmarks.
+    pub entities: u64,
+}
+
+impl BenchConfig {
+    pub fn new() -> Self {
+        Self { entities: 10_000 }
+    }
+
+    pub fn with_entities(mut self, entities: u64) -> Self {
+        self.entities = entities;
+        self
+    }
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Migration creating the benchmark schema.
+///
+/// `bench/name` is unique, and therefore indexed, `bench/group` is not.
+pub fn schema_migration() -> Migration {
+    Migration::new()
+        .attr_upsert(Attribute::new(ATTR_NAME, ValueType::String).with_unique(true))
+        .attr_upsert(Attribute::new(ATTR_GROUP, ValueType::UInt))
+}
+
+fn entity_name(index: u64) -> String {
+    format!("entity-{}", index)
+}
+
+fn entity_data(name: String, group: u64) -> DataMap {
+    let mut data = DataMap::new();
+    data.insert(ATTR_NAME.to_string(), Value::from(name));
+    data.insert(ATTR_GROUP.to_string(), Value::from(group));
+    data
+}
+
+/// Apply the benchmark schema and create `entities` entities.
+///
+/// Returns the ids of the created entities.
+pub async fn populate(db: &Db, entities: u64) -> Result<Vec<Id>, anyhow::Error> {
+    db.migrate(schema_migration()).await?;
+
+    let mut ids = Vec::new();
+    let mut start = 0;
+    while start < entities {
+        let end = (start + POPULATE_BATCH_SIZE).min(entities);
+        let mut batch = Batch::new();
+        for index in start..end {
+            let id = Id::random();
+            batch = batch.and_create(Create {
+                id,
+                data: entity_data(entity_name(index), index % GROUPS),
+            });
+            ids.push(id);
+        }
+        db.batch(batch).await?;
+        start = end;
+    }
+
+    Ok(ids)
+}
+
+/// Load a single entity by id.
+pub async fn point_select(db: &Db, id: Id) -> Result<DataMap, anyhow::Error> {
+    Ok(db.entity(id).await?)
+}
+
+/// Select a single entity by its unique name.
+pub async fn index_select(db: &Db, index: u64) -> Result<Vec<DataMap>, anyhow::Error> {
+    let filter = Expr::eq(Expr::attr_ident(ATTR_NAME), entity_name(index));
+    Ok(db
+        .select_map(Select::new().with_filter(filter).with_limit(1))
+        .await?)
+}
+
+/// Select all entities of a group, which requires a full scan.
+pub async fn filtered_scan(db: &Db, group: u64) -> Result<Vec<DataMap>, anyhow::Error> {
+    let filter = Expr::eq(Expr::attr_ident(ATTR_GROUP), group);
+    Ok(db.select_map(Select::new().with_filter(filter)).await?)
+}
+
+/// Select the first entities sorted by name.
+pub async fn sorted_select(db: &Db) -> Result<Vec<DataMap>, anyhow::Error> {
+    let select = Select::new()
+        .with_sort(Expr::attr_ident(ATTR_NAME), Order::Asc)
+        .with_limit(10);
+    Ok(db.select_map(select).await?)
+}
+
+/// Spread accesses over all entities instead of reading them in insertion
+/// order.
+fn pick(iteration: u64, count: u64) -> u64 {
+    iteration.wrapping_mul(7919) % count
+}
+
+/// Run all benchmarks against a backend.
+///
+/// `new_db` must return a new, empty database.
+/// Benchmarks are grouped under the given backend name, so results of
+/// different backends can be compared.
+pub fn bench_backend<F>(c: &mut Criterion, backend: &str, config: &BenchConfig, new_db: F)
+where
+    F: Fn() -> Db,
+{
+    let entities = config.entities.max(1);
+    let db = new_db();
+    let ids = futures::executor::block_on(populate(&db, entities))
+        .expect("Could not populate benchmark database");
+
+    let mut group = c.benchmark_group(backend);
+
+    let mut iteration = 0u64;
+    group.bench_function("create", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            iteration += 1;
+            let data = entity_data(format!("create-{}", Id::random()), iteration % GROUPS);
+            let db = &db;
+            async move { db.create(Id::random(), data).await.unwrap() }
+        })
+    });
+
+    let mut iteration = 0u64;
+    group.bench_function("point_select", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            iteration += 1;
+            let id = ids[pick(iteration, entities) as usize];
+            let db = &db;
+            async move { point_select(db, id).await.unwrap() }
+        })
+    });
+
+    let mut iteration = 0u64;
+    group.bench_function("index_select", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            iteration += 1;
+            let index = pick(iteration, entities);
+            let db = &db;
+            async move { index_select(db, index).await.unwrap() }
+        })
+    });
+
+    let mut iteration = 0u64;
+    group.bench_function("filtered_scan", |b| {
+        b.to_async(FuturesExecutor).iter(|| {
+            iteration += 1;
+            let db = &db;
+            async move { filtered_scan(db, iteration % GROUPS).await.unwrap() }
+        })
+    });
+
+    group.bench_function("sort", |b| {
+        b.to_async(FuturesExecutor)
+            .iter(|| async { sorted_select(&db).await.unwrap() })
+    });
+
+    group.bench_function("migration", |b| {
+        b.to_async(FuturesExecutor).iter_batched(
+            &new_db,
+            |db| async move {
+                db.migrate(schema_migration()).await.unwrap();
+                db
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_engine::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    #[test]
+    fn test_workloads() {
+        futures::executor::block_on(async {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            let ids = populate(&db, POPULATE_BATCH_SIZE + 10).await.unwrap();
+            assert_eq!(ids.len() as u64, POPULATE_BATCH_SIZE + 10);
+
+            let data = point_select(&db, ids[3]).await.unwrap();
+            assert_eq!(data[ATTR_NAME], Value::from(entity_name(3)));
+
+            let items = index_select(&db, 5).await.unwrap();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0][ATTR_NAME], Value::from(entity_name(5)));
+
+            let items = filtered_scan(&db, 7).await.unwrap();
+            assert_eq!(items.len(), 11);
+
+            let items = sorted_select(&db).await.unwrap();
+            assert_eq!(items[0][ATTR_NAME], Value::from(entity_name(0)));
+            assert_eq!(items.len(), 10);
+        });
+    }
+}
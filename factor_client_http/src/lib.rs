@@ -0,0 +1,193 @@
+//! A [`DbClient`] that talks to the HTTP API provided by
+//! `factor_tools::server`.
+//!
+//! Wrap the client in a [`Db`] to use a remote database with the same API as
+//! an embedded one:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), anyhow::Error> {
+//! let db = factor_client_http::connect("http://127.0.0.1:7700", None)?;
+//! let schema = db.schema().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use factor_core::{
+    data::{DataMap, IdOrIdent},
+    db::{Db, DbClient, DbFuture},
+    error::TransientError,
+    query::{
+        migrate::Migration,
+        mutate::Batch,
+        select::{Item, Page, Select},
+    },
+    schema::DbSchema,
+};
+use futures::FutureExt;
+use hyper::{client::HttpConnector, header, Body, Method, Request, StatusCode};
+
+/// Connect to a remote database.
+///
+/// See [`HttpDbClient::new`].
+pub fn connect(url: &str, token: Option<String>) -> Result<Db, anyhow::Error> {
+    HttpDbClient::new(url, token).map(Db::new)
+}
+
+/// Client for a remote database served over HTTP.
+///
+/// Only plain `http://` urls are supported.
+#[derive(Clone)]
+pub struct HttpDbClient {
+    client: hyper::Client<HttpConnector>,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl HttpDbClient {
+    /// Create a client for the server at `url`.
+    ///
+    /// The `token` is sent as a bearer token in the `Authorization` header.
+    pub fn new(url: &str, token: Option<String>) -> Result<Self, anyhow::Error> {
+        if !url.starts_with("http://") {
+            anyhow::bail!("Invalid url '{}': only http:// urls are supported", url);
+        }
+        Ok(Self {
+            client: hyper::Client::new(),
+            base_url: url.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+
+    /// Send a request.
+    /// Returns `None` if the server responded with 404.
+    async fn request_opt<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Option<T>, anyhow::Error> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.base_url, path));
+        if let Some(token) = &self.token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let req = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))?,
+            None => builder.body(Body::empty())?,
+        };
+
+        let res = self.client.request(req).await.map_err(|err| {
+            if err.is_connect() {
+                anyhow::Error::new(TransientError::Unavailable(err.to_string()))
+            } else {
+                err.into()
+            }
+        })?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let message = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v.get("error")?.as_str().map(|s| s.to_string()))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).to_string());
+            // The server did not process the request, so it can be retried.
+            if status == StatusCode::SERVICE_UNAVAILABLE || status == StatusCode::TOO_MANY_REQUESTS
+            {
+                return Err(TransientError::Unavailable(format!("{}: {}", status, message)).into());
+            }
+            anyhow::bail!("Request failed with status {}: {}", status, message);
+        }
+
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<T, anyhow::Error> {
+        self.request_opt(method, path, body)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Request failed: {} not found", path))
+    }
+
+    /// Send a `GET` request to an arbitrary endpoint of the server.
+    ///
+    /// Used for endpoints that are not part of the [`DbClient`] API.
+    pub async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, anyhow::Error> {
+        self.request(Method::GET, path, None).await
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<T, anyhow::Error> {
+        let body = serde_json::to_vec(body)?;
+        self.request(Method::POST, path, Some(body)).await
+    }
+}
+
+impl DbClient for HttpDbClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn schema(&self) -> DbFuture<'_, DbSchema> {
+        self.request(Method::GET, "/schema", None).boxed()
+    }
+
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        async move {
+            self.request_opt(Method::GET, &format!("/entity/{}", id), None)
+                .await
+        }
+        .boxed()
+    }
+
+    fn select(&self, query: Select) -> DbFuture<'_, Page<Item>> {
+        async move { self.post("/select", &query).await }.boxed()
+    }
+
+    fn select_map(&self, query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        async move {
+            let page: Page<Item> = self.post("/select", &query).await?;
+            Ok(page.take_data())
+        }
+        .boxed()
+    }
+
+    fn batch(&self, batch: Batch) -> DbFuture<'_, ()> {
+        async move { self.post("/batch", &batch).await }.boxed()
+    }
+
+    fn migrate(&self, migration: Migration) -> DbFuture<'_, ()> {
+        async move { self.post("/migrate", &migration).await }.boxed()
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        self.request(Method::GET, "/migrations", None).boxed()
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        futures::future::ready(Err(anyhow::anyhow!(
+            "purge_all_data is not supported for remote databases"
+        )))
+        .boxed()
+    }
+}
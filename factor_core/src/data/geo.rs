@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+
+use ordered_float::OrderedFloat;
+
+use super::{value::ValueCoercionError, Value, ValueType};
+
+/// Mean earth radius in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Length of the geohashes stored in geo indexes.
+///
+/// Cells of this precision are roughly 4 x 2 centimeters in size.
+pub const GEOHASH_MAX_PRECISION: usize = 12;
+
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// A geographic coordinate, in degrees.
+///
+/// Stored in the database as a list of two floats: `[latitude, longitude]`.
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct GeoPoint {
+    lat: OrderedFloat<f64>,
+    lon: OrderedFloat<f64>,
+}
+
+impl GeoPoint {
+    /// Create a new point.
+    ///
+    /// Fails if the latitude is not in `-90..=90` or the longitude is not in
+    /// `-180..=180`.
+    pub fn new(lat: f64, lon: f64) -> Result<Self, anyhow::Error> {
+        if !(-90.0..=90.0).contains(&lat) {
+            anyhow::bail!("Invalid latitude {}: must be between -90 and 90", lat);
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            anyhow::bail!("Invalid longitude {}: must be between -180 and 180", lon);
+        }
+        Ok(Self {
+            lat: lat.into(),
+            lon: lon.into(),
+        })
+    }
+
+    pub fn lat(&self) -> f64 {
+        *self.lat
+    }
+
+    pub fn lon(&self) -> f64 {
+        *self.lon
+    }
+
+    /// Great-circle distance to another point in meters.
+    pub fn distance(&self, other: &Self) -> f64 {
+        let (lat1, lat2) = (self.lat().to_radians(), other.lat().to_radians());
+        let delta_lat = lat2 - lat1;
+        let delta_lon = (other.lon() - self.lon()).to_radians();
+
+        let a = (delta_lat / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+
+    /// Encode the point as a geohash with the given number of characters.
+    ///
+    /// Points in the same cell share a common geohash prefix.
+    pub fn geohash(&self, precision: usize) -> String {
+        let mut lat_range = (-90.0, 90.0);
+        let mut lon_range = (-180.0, 180.0);
+
+        let mut hash = String::with_capacity(precision);
+        let mut is_lon = true;
+        let mut bits = 0;
+        let mut char_index = 0;
+        while hash.len() < precision {
+            let (range, value) = if is_lon {
+                (&mut lon_range, self.lon())
+            } else {
+                (&mut lat_range, self.lat())
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            char_index <<= 1;
+            if value >= mid {
+                char_index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+
+            is_lon = !is_lon;
+            bits += 1;
+            if bits == 5 {
+                hash.push(char::from(GEOHASH_ALPHABET[char_index]));
+                bits = 0;
+                char_index = 0;
+            }
+        }
+        hash
+    }
+
+    /// Compute geohash cells that together contain all points within the
+    /// given distance.
+    ///
+    /// All cells have the same precision, so a point is inside the area if
+    /// its geohash starts with one of the returned cells.
+    ///
+    /// Returns `None` if the area is too large or touches a pole, in which
+    /// case the cells would not narrow down the search.
+    pub fn geohash_cover(&self, meters: f64) -> Option<Vec<String>> {
+        let lat_delta = (meters / EARTH_RADIUS).to_degrees();
+        let min_lat = self.lat() - lat_delta;
+        let max_lat = self.lat() + lat_delta;
+        if min_lat <= -90.0 || max_lat >= 90.0 {
+            return None;
+        }
+        // Longitude degrees get shorter towards the poles, so use the
+        // latitude furthest from the equator.
+        let lon_delta = lat_delta / min_lat.abs().max(max_lat.abs()).to_radians().cos();
+        if lon_delta >= 90.0 {
+            return None;
+        }
+
+        // Cells must be at least as large as the radius, so sampling the
+        // bounding box in a 3x3 grid hits every cell it overlaps with.
+        let precision = (1..=GEOHASH_MAX_PRECISION).rev().find(|precision| {
+            let (height, width) = geohash_cell_size(*precision);
+            height >= lat_delta && width >= lon_delta
+        })?;
+
+        let mut cells = Vec::new();
+        for lat in [min_lat, self.lat(), max_lat] {
+            for lon in [self.lon() - lon_delta, self.lon(), self.lon() + lon_delta] {
+                let point = Self {
+                    lat: lat.into(),
+                    lon: wrap_longitude(lon).into(),
+                };
+                let cell = point.geohash(precision);
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+        }
+        Some(cells)
+    }
+}
+
+/// Height and width of geohash cells with the given precision, in degrees.
+fn geohash_cell_size(precision: usize) -> (f64, f64) {
+    let bits = precision * 5;
+    let lat_bits = bits / 2;
+    let lon_bits = bits - lat_bits;
+    let divisor = |bits: usize| 2f64.powi(i32::try_from(bits).unwrap_or(i32::MAX));
+    (180.0 / divisor(lat_bits), 360.0 / divisor(lon_bits))
+}
+
+fn wrap_longitude(lon: f64) -> f64 {
+    if lon < -180.0 {
+        lon + 360.0
+    } else if lon > 180.0 {
+        lon - 360.0
+    } else {
+        lon
+    }
+}
+
+impl From<GeoPoint> for Value {
+    fn from(p: GeoPoint) -> Self {
+        Value::List(vec![Value::Float(p.lat), Value::Float(p.lon)])
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(v) => Some(**v),
+        Value::Int(v) => Some(*v as f64),
+        Value::UInt(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+impl TryFrom<&Value> for GeoPoint {
+    type Error = ValueCoercionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let err = |message: String| ValueCoercionError {
+            expected_type: ValueType::GeoPoint,
+            actual_type: value.value_type(),
+            path: None,
+            message: Some(message),
+        };
+
+        let (lat, lon) = match value {
+            Value::List(items) if items.len() == 2 => (number(&items[0]), number(&items[1])),
+            Value::Map(map) => (
+                map.get(&Value::from("lat")).and_then(number),
+                map.get(&Value::from("lon")).and_then(number),
+            ),
+            Value::String(s) => {
+                let mut parts = s.splitn(2, ',').map(|p| p.trim().parse::<f64>().ok());
+                (parts.next().flatten(), parts.next().flatten())
+            }
+            _ => (None, None),
+        };
+        match (lat, lon) {
+            (Some(lat), Some(lon)) => Self::new(lat, lon).map_err(|e| err(e.to_string())),
+            _ => Err(err(
+                "Invalid geo point: expected [lat, lon], {lat, lon} or 'lat,lon'".to_string(),
+            )),
+        }
+    }
+}
+
+impl TryFrom<Value> for GeoPoint {
+    type Error = ValueCoercionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geo_point_distance() {
+        let vienna = GeoPoint::new(48.2082, 16.3738).unwrap();
+        let berlin = GeoPoint::new(52.52, 13.405).unwrap();
+        let distance = vienna.distance(&berlin);
+        assert!((distance - 523_000.0).abs() < 2_000.0, "{}", distance);
+        assert_eq!(vienna.distance(&vienna), 0.0);
+
+        assert!(GeoPoint::new(91.0, 0.0).is_err());
+        assert!(GeoPoint::new(0.0, -181.0).is_err());
+    }
+
+    #[test]
+    fn test_geohash() {
+        let p = GeoPoint::new(57.64911, 10.40744).unwrap();
+        assert_eq!(p.geohash(11), "u4pruydqqvj");
+        assert_eq!(p.geohash(3), "u4p");
+    }
+
+    #[test]
+    fn test_geohash_cover() {
+        let center = GeoPoint::new(48.2082, 16.3738).unwrap();
+        let cells = center.geohash_cover(1_000.0).unwrap();
+        assert!(cells.len() <= 9);
+
+        let nearby = [
+            GeoPoint::new(48.2082, 16.3738).unwrap(),
+            GeoPoint::new(48.2160, 16.3738).unwrap(),
+            GeoPoint::new(48.2082, 16.3860).unwrap(),
+            GeoPoint::new(48.2030, 16.3660).unwrap(),
+        ];
+        for point in nearby {
+            assert!(center.distance(&point) <= 1_000.0);
+            let hash = point.geohash(GEOHASH_MAX_PRECISION);
+            assert!(cells.iter().any(|cell| hash.starts_with(cell.as_str())));
+        }
+
+        assert!(GeoPoint::new(89.99, 0.0)
+            .unwrap()
+            .geohash_cover(10_000.0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_geo_point_value_conversion() {
+        let p = GeoPoint::new(1.5, -2.0).unwrap();
+        assert_eq!(GeoPoint::try_from(Value::from(p)).unwrap(), p);
+        assert_eq!(GeoPoint::try_from(Value::from("1.5, -2")).unwrap(), p);
+        assert!(GeoPoint::try_from(Value::from("x")).is_err());
+        assert!(
+            GeoPoint::try_from(Value::List(vec![Value::Float(100.0.into()), 0.into()])).is_err()
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+use super::Timestamp;
+
+/// A timestamp of a hybrid logical clock.
+///
+/// Combines wall clock time with a logical counter, so timestamps of a
+/// [`HybridClock`] are strictly increasing even if the wall clock stalls or
+/// goes backwards.
+/// The node id breaks ties between different clocks, which makes the order
+/// total.
+///
+/// Used to reconcile concurrent merges, see
+/// [`crate::query::mutate::Merge::with_clock`].
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct HlcTimestamp {
+    /// Wall clock time in milliseconds.
+    pub millis: u64,
+    pub counter: u32,
+    pub node: u64,
+}
+
+impl HlcTimestamp {
+    pub fn new(millis: u64, counter: u32, node: u64) -> Self {
+        Self {
+            millis,
+            counter,
+            node,
+        }
+    }
+}
+
+impl std::fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{:x}", self.millis, self.counter, self.node)
+    }
+}
+
+impl FromStr for HlcTimestamp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid hybrid clock timestamp '{}'", s);
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let millis = next()?.parse().map_err(|_| invalid())?;
+        let counter = next()?.parse().map_err(|_| invalid())?;
+        let node = u64::from_str_radix(next()?, 16).map_err(|_| invalid())?;
+        Ok(Self::new(millis, counter, node))
+    }
+}
+
+/// A hybrid logical clock.
+///
+/// Each client that writes with clocks needs its own clock with a unique
+/// node id.
+#[derive(Clone, Debug)]
+pub struct HybridClock {
+    last: HlcTimestamp,
+}
+
+impl HybridClock {
+    pub fn new(node: u64) -> Self {
+        Self {
+            last: HlcTimestamp::new(0, 0, node),
+        }
+    }
+
+    pub fn node(&self) -> u64 {
+        self.last.node
+    }
+
+    /// Produce a new timestamp for a local write.
+    pub fn now(&mut self) -> HlcTimestamp {
+        self.tick(Timestamp::now().as_millis(), None)
+    }
+
+    /// Advance the clock past a timestamp received from another node.
+    ///
+    /// Must be called for timestamps of synced changes, so that later local
+    /// writes are ordered after them.
+    pub fn observe(&mut self, remote: HlcTimestamp) -> HlcTimestamp {
+        self.tick(Timestamp::now().as_millis(), Some(remote))
+    }
+
+    fn tick(&mut self, wall: u64, remote: Option<HlcTimestamp>) -> HlcTimestamp {
+        let last = self.last;
+        let remote_millis = remote.map_or(0, |r| r.millis);
+        let millis = wall.max(last.millis).max(remote_millis);
+
+        let counter = match remote {
+            Some(r) if millis == last.millis && millis == r.millis => {
+                last.counter.max(r.counter) + 1
+            }
+            Some(r) if millis == r.millis => r.counter + 1,
+            _ if millis == last.millis => last.counter + 1,
+            _ => 0,
+        };
+
+        self.last = HlcTimestamp::new(millis, counter, last.node);
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hlc_timestamp_roundtrip() {
+        let ts = HlcTimestamp::new(1_700_000_000_000, 3, 0xab);
+        assert_eq!(ts.to_string(), "1700000000000-3-ab");
+        assert_eq!(ts, ts.to_string().parse().unwrap());
+        assert!("1-2".parse::<HlcTimestamp>().is_err());
+    }
+
+    #[test]
+    fn test_hybrid_clock_monotonic() {
+        let mut clock = HybridClock::new(1);
+
+        let a = clock.tick(100, None);
+        assert_eq!(a, HlcTimestamp::new(100, 0, 1));
+        // Wall clock stalls or goes backwards.
+        let b = clock.tick(100, None);
+        let c = clock.tick(50, None);
+        assert!(a < b && b < c);
+        assert_eq!(c, HlcTimestamp::new(100, 2, 1));
+
+        // Remote timestamps from the future advance the clock.
+        let d = clock.tick(120, Some(HlcTimestamp::new(200, 5, 2)));
+        assert_eq!(d, HlcTimestamp::new(200, 6, 1));
+        let e = clock.tick(150, None);
+        assert_eq!(e, HlcTimestamp::new(200, 7, 1));
+
+        let f = clock.tick(300, None);
+        assert_eq!(f, HlcTimestamp::new(300, 0, 1));
+    }
+}
@@ -27,6 +27,9 @@ impl std::fmt::Display for Id {
 }
 
 impl super::value_type::ValueTypeDescriptor for Id {
+    const STATIC_TYPE: Option<super::value_type::StaticValueType> =
+        super::value_type::StaticValueType::scalar("Ref");
+
     fn value_type() -> super::ValueType {
         super::ValueType::Ref
     }
@@ -75,6 +78,30 @@ impl Id {
         Self(uuid::Uuid::new_v4())
     }
 
+    /// Create a new time-ordered UUIDv7.
+    ///
+    /// The id starts with the current UNIX timestamp in milliseconds, so ids
+    /// created later sort after earlier ones. Ordering of ids created within
+    /// the same millisecond is random.
+    pub fn random_v7() -> Self {
+        Self::v7_from_parts(
+            super::Timestamp::now().as_millis(),
+            *uuid::Uuid::new_v4().as_bytes(),
+        )
+    }
+
+    /// Build a UUIDv7 from a millisecond timestamp and random bytes.
+    ///
+    /// The first 6 bytes and the version and variant bits of `random` are
+    /// overwritten.
+    fn v7_from_parts(millis: u64, random: [u8; 16]) -> Self {
+        let mut bytes = random;
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(uuid::Uuid::from_bytes(bytes))
+    }
+
     pub fn as_non_nil(self) -> Option<Self> {
         if self.is_nil() {
             None
@@ -249,3 +276,21 @@ impl<'a> From<&'a str> for IdOrIdent {
         Self::new_str(v)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_random_v7() {
+        let id = Id::random_v7();
+        assert_eq!(id.as_uuid().get_version_num(), 7);
+        assert_eq!(id.as_uuid().get_variant(), uuid::Variant::RFC4122);
+
+        let a = Id::v7_from_parts(1_000, [0xff; 16]);
+        let b = Id::v7_from_parts(1_001, [0; 16]);
+        assert!(a < b);
+        assert_eq!(a.as_uuid().get_version_num(), 7);
+        assert_eq!(b.as_uuid().get_variant(), uuid::Variant::RFC4122);
+    }
+}
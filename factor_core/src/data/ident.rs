@@ -1,4 +1,4 @@
-use super::value_type::{ConstrainedRefType, ValueTypeDescriptor};
+use super::value_type::{ConstrainedRefType, StaticValueType, ValueTypeDescriptor};
 
 #[derive(Clone)]
 pub struct Ident {
@@ -184,6 +184,8 @@ impl<'de> serde::de::Deserialize<'de> for Ident {
 }
 
 impl ValueTypeDescriptor for Ident {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Ident");
+
     fn value_type() -> super::ValueType {
         super::ValueType::Ident(ConstrainedRefType::new(vec![]))
     }
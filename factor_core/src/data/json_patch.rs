@@ -0,0 +1,342 @@
+//! Conversion between [`Patch`] and [RFC 6902] JSON Patch documents.
+//!
+//! Values are addressed with JSON Pointers ([RFC 6901]). The first segment
+//! of a pointer is the attribute, numeric segments below it are treated as
+//! list indexes. The `/` in attribute names must be escaped as `~1`, like in
+//! `/factor~1title`.
+//!
+//! The operations do not map one to one:
+//! * `add` of a map member sets the value, like a [`PatchOp::Replace`].
+//!   `add` with a list index inserts the value. Appending with `-` adds the
+//!   value unless the list already contains it, like a [`PatchOp::Add`].
+//! * `replace` also inserts missing values.
+//! * `test` is only supported directly before a `replace` of the same path,
+//!   which makes the replace fail if the current value differs.
+//! * `move` and `copy` are not supported.
+//!
+//! Patches produced by [`Patch::diff`] convert without loss.
+//!
+//! [RFC 6902]: https://www.rfc-editor.org/rfc/rfc6902
+//! [RFC 6901]: https://www.rfc-editor.org/rfc/rfc6901
+
+use std::convert::TryFrom;
+
+use anyhow::{bail, Context};
+
+use super::{
+    patch::{Patch, PatchOp, PatchPath, PatchPathElem},
+    Value,
+};
+
+/// A JSON Patch document.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct JsonPatch(pub Vec<JsonPatchOp>);
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatchOp {
+    Add {
+        path: String,
+        value: serde_json::Value,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: serde_json::Value,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    Test {
+        path: String,
+        value: serde_json::Value,
+    },
+}
+
+impl JsonPatchOp {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Add { path, .. }
+            | Self::Remove { path }
+            | Self::Replace { path, .. }
+            | Self::Move { path, .. }
+            | Self::Copy { path, .. }
+            | Self::Test { path, .. } => path,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Add { .. } => "add",
+            Self::Remove { .. } => "remove",
+            Self::Replace { .. } => "replace",
+            Self::Move { .. } => "move",
+            Self::Copy { .. } => "copy",
+            Self::Test { .. } => "test",
+        }
+    }
+}
+
+impl PatchPath {
+    /// Render the path as a JSON Pointer.
+    pub fn to_json_pointer(&self) -> String {
+        let mut s = String::new();
+        for elem in &self.0 {
+            s.push('/');
+            match elem {
+                PatchPathElem::Key(key) => s.push_str(&key.replace('~', "~0").replace('/', "~1")),
+                PatchPathElem::ListIndex(index) => s.push_str(&index.to_string()),
+            }
+        }
+        s
+    }
+
+    /// Parse a JSON Pointer like `/attr/key/0`.
+    ///
+    /// The first segment is always a key, numeric segments after it are
+    /// list indexes.
+    pub fn parse_json_pointer(pointer: &str) -> Result<Self, anyhow::Error> {
+        let rest = pointer
+            .strip_prefix('/')
+            .with_context(|| format!("Invalid JSON pointer '{}': must start with '/'", pointer))?;
+        let elems = rest
+            .split('/')
+            .enumerate()
+            .map(|(index, segment)| match segment.parse::<usize>() {
+                Ok(list_index) if index > 0 => PatchPathElem::ListIndex(list_index),
+                _ => PatchPathElem::Key(segment.replace("~1", "/").replace("~0", "~")),
+            })
+            .collect();
+        Ok(Self(elems))
+    }
+}
+
+fn to_json(value: Value) -> Result<serde_json::Value, anyhow::Error> {
+    serde_json::to_value(value).context("Value can not be represented as JSON")
+}
+
+fn from_json(value: serde_json::Value) -> Result<Value, anyhow::Error> {
+    serde_json::from_value(value).context("Invalid value")
+}
+
+impl TryFrom<JsonPatch> for Patch {
+    type Error = anyhow::Error;
+
+    fn try_from(patch: JsonPatch) -> Result<Self, Self::Error> {
+        let mut ops = Vec::new();
+        // Value of a preceding test operation.
+        let mut expected: Option<(String, serde_json::Value)> = None;
+        for op in patch.0 {
+            if let Some((test_path, _)) = &expected {
+                if !matches!(&op, JsonPatchOp::Replace { path, .. } if path == test_path) {
+                    bail!(
+                        "JSON Patch 'test' is only supported before a 'replace' of the same \
+                         path, got '{}' of '{}'",
+                        op.name(),
+                        op.path()
+                    );
+                }
+            }
+
+            let op = match op {
+                JsonPatchOp::Add { path, value } => {
+                    let value = from_json(value)?;
+                    match path.strip_suffix("/-") {
+                        Some(list) => PatchOp::add(PatchPath::parse_json_pointer(list)?, value),
+                        None => {
+                            let path = PatchPath::parse_json_pointer(&path)?;
+                            match path.0.last() {
+                                Some(PatchPathElem::ListIndex(_)) => PatchOp::add(path, value),
+                                _ => PatchOp::Replace {
+                                    path,
+                                    new_value: value,
+                                    current_value: None,
+                                    must_replace: false,
+                                },
+                            }
+                        }
+                    }
+                }
+                JsonPatchOp::Remove { path } => PatchOp::Remove {
+                    path: PatchPath::parse_json_pointer(&path)?,
+                    value: None,
+                },
+                JsonPatchOp::Replace { path, value } => {
+                    let current_value = match expected.take() {
+                        Some((_, value)) => Some(from_json(value)?),
+                        None => None,
+                    };
+                    PatchOp::Replace {
+                        path: PatchPath::parse_json_pointer(&path)?,
+                        new_value: from_json(value)?,
+                        must_replace: current_value.is_some(),
+                        current_value,
+                    }
+                }
+                JsonPatchOp::Test { path, value } => {
+                    expected = Some((path, value));
+                    continue;
+                }
+                other @ (JsonPatchOp::Move { .. } | JsonPatchOp::Copy { .. }) => {
+                    bail!("JSON Patch operation '{}' is not supported", other.name());
+                }
+            };
+            ops.push(op);
+        }
+        if let Some((path, _)) = expected {
+            bail!(
+                "JSON Patch 'test' of '{}' must be followed by a 'replace'",
+                path
+            );
+        }
+        Ok(Self(ops))
+    }
+}
+
+impl TryFrom<Patch> for JsonPatch {
+    type Error = anyhow::Error;
+
+    fn try_from(patch: Patch) -> Result<Self, Self::Error> {
+        let mut ops = Vec::new();
+        for op in patch.0 {
+            match op {
+                PatchOp::Add { path, value } => ops.push(JsonPatchOp::Add {
+                    path: path.to_json_pointer(),
+                    value: to_json(value)?,
+                }),
+                PatchOp::Remove { path, value: None } => ops.push(JsonPatchOp::Remove {
+                    path: path.to_json_pointer(),
+                }),
+                PatchOp::Remove {
+                    path,
+                    value: Some(_),
+                } => {
+                    bail!(
+                        "Removes of a specific value can not be converted to JSON Patch, at {}",
+                        path
+                    );
+                }
+                PatchOp::Replace {
+                    path,
+                    new_value,
+                    current_value,
+                    must_replace,
+                } => {
+                    let path = path.to_json_pointer();
+                    match current_value {
+                        Some(current) if must_replace => ops.push(JsonPatchOp::Test {
+                            path: path.clone(),
+                            value: to_json(current)?,
+                        }),
+                        Some(_) => {
+                            bail!(
+                                "Replaces that are skipped if the current value differs can not \
+                                 be converted to JSON Patch, at {}",
+                                path
+                            );
+                        }
+                        None => {}
+                    }
+                    ops.push(JsonPatchOp::Replace {
+                        path,
+                        value: to_json(new_value)?,
+                    });
+                }
+            }
+        }
+        Ok(Self(ops))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map;
+
+    use super::*;
+
+    #[test]
+    fn test_json_patch_to_patch() {
+        let json: JsonPatch = serde_json::from_value(serde_json::json!([
+            { "op": "add", "path": "/a", "value": 1 },
+            { "op": "add", "path": "/tags/-", "value": "x" },
+            { "op": "add", "path": "/list/0", "value": 0 },
+            { "op": "remove", "path": "/obj/a~1b" },
+            { "op": "test", "path": "/b", "value": "old" },
+            { "op": "replace", "path": "/b", "value": "new" },
+        ]))
+        .unwrap();
+        let patch = Patch::try_from(json).unwrap();
+        assert_eq!(
+            patch,
+            Patch::new()
+                .replace("a", 1u64)
+                .add("tags", "x")
+                .add(
+                    vec![PatchPathElem::from("list"), PatchPathElem::ListIndex(0)],
+                    0u64
+                )
+                .remove(vec!["obj", "a/b"])
+                .replace_with_old("b", "new", "old", true)
+        );
+
+        let out = patch
+            .apply_map(map! {
+                "a": 0,
+                "b": "old",
+                "tags": vec!["y"],
+                "list": vec![1u64],
+                "obj": map! { "a/b": 1, "c": 2 }.into_inner(),
+            })
+            .unwrap();
+        assert_eq!(
+            out,
+            map! {
+                "a": 1u64,
+                "b": "new",
+                "tags": vec!["y", "x"],
+                "list": vec![0u64, 1],
+                "obj": map! { "c": 2 }.into_inner(),
+            }
+        );
+
+        let unsupported = JsonPatch(vec![JsonPatchOp::Move {
+            from: "/a".to_string(),
+            path: "/b".to_string(),
+        }]);
+        assert!(Patch::try_from(unsupported).is_err());
+        let dangling_test = JsonPatch(vec![JsonPatchOp::Test {
+            path: "/a".to_string(),
+            value: 1.into(),
+        }]);
+        assert!(Patch::try_from(dangling_test).is_err());
+    }
+
+    #[test]
+    fn test_patch_diff_to_json_patch_roundtrip() {
+        // JSON numbers are read as unsigned integers.
+        let old = map! { "a": 1, "b": vec![1u64, 2], "c": map! { "x/y": 1u64 }.into_inner() };
+        let new = map! { "b": vec![0u64, 1, 2], "c": map! { "x/y": 2u64 }.into_inner(), "d": true };
+        let patch = Patch::diff(&old, &new);
+
+        let json = JsonPatch::try_from(patch.clone()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&json).unwrap(),
+            serde_json::json!([
+                { "op": "remove", "path": "/a" },
+                { "op": "add", "path": "/b/0", "value": 0 },
+                { "op": "replace", "path": "/c/x~1y", "value": 2 },
+                { "op": "add", "path": "/d", "value": true },
+            ])
+        );
+
+        let back = Patch::try_from(json).unwrap();
+        assert_eq!(back.apply_map(old).unwrap(), new);
+    }
+}
@@ -75,6 +75,16 @@ impl<K> ValueMap<K> {
     }
 }
 
+impl ValueMap<std::sync::Arc<str>> {
+    /// Convert into a [`super::DataMap`] with owned keys.
+    pub fn into_data_map(self) -> super::DataMap {
+        self.0
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect()
+    }
+}
+
 impl<K> std::ops::Deref for ValueMap<K> {
     type Target = BTreeMap<K, Value>;
 
@@ -99,6 +99,7 @@ pub use self::reference::Ref;
 mod ident;
 pub use ident::{Ident, InvalidIdentError};
 
+pub mod json_patch;
 mod map;
 pub mod patch;
 pub mod value;
@@ -111,7 +112,18 @@ pub use self::{
 };
 
 mod time;
-pub use self::time::Timestamp;
+pub use self::time::{TimeUnit, Timestamp};
+
+pub mod geo;
+pub use self::geo::GeoPoint;
+
+mod hlc;
+pub use self::hlc::{HlcTimestamp, HybridClock};
 
 pub type DataMap = ValueMap<String>;
+/// A [`DataMap`] with shared attribute names.
+///
+/// Keys are shared with the schema registry, so cloning them only increments
+/// a reference count instead of allocating a new string.
+pub type SharedDataMap = ValueMap<std::sync::Arc<str>>;
 pub type IdMap = fnv::FnvHashMap<Id, Value>;
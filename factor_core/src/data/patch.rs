@@ -69,6 +69,35 @@ impl Patch {
         self
     }
 
+    /// Compute a patch that transforms `old` into `new`.
+    ///
+    /// Nested maps and lists are diffed element-wise, so the patch only
+    /// contains the changed values instead of replacing whole attributes.
+    /// Applying the patch to `old` with [`Self::apply_map`] produces `new`.
+    pub fn diff(old: &DataMap, new: &DataMap) -> Self {
+        let mut ops = Vec::new();
+        for key in old.keys() {
+            if !new.contains_key(key) {
+                ops.push(PatchOp::Remove {
+                    path: key.into(),
+                    value: None,
+                });
+            }
+        }
+        for (key, value) in new.iter() {
+            match old.get(key) {
+                Some(old_value) => diff_value(
+                    &mut ops,
+                    &[PatchPathElem::Key(key.clone())],
+                    old_value,
+                    value,
+                ),
+                None => ops.push(PatchOp::add(key, value.clone())),
+            }
+        }
+        Self(ops)
+    }
+
     pub fn apply_map(self, mut target: DataMap) -> Result<DataMap, PatchOpError> {
         for op in self.0 {
             op.apply_map(&mut target)?;
@@ -181,6 +210,61 @@ pub enum PatchPathElem {
 }
 
 impl PatchPath {
+    /// Parse a JSON path like `$.a.b[0]` or `$['a']`.
+    ///
+    /// Only member and list index access is supported.
+    pub fn parse_json_path(path: &str) -> Result<Self, anyhow::Error> {
+        let invalid = |reason: &str| anyhow::anyhow!("Invalid JSON path '{}': {}", path, reason);
+
+        let rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("must start with '$'"))?;
+        let mut elems = Vec::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                        key.push(c);
+                    }
+                    if key.is_empty() {
+                        return Err(invalid("empty key"));
+                    }
+                    elems.push(PatchPathElem::Key(key));
+                }
+                '[' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c) => inner.push(c),
+                            None => return Err(invalid("missing ']'")),
+                        }
+                    }
+                    let inner = inner.trim();
+                    let quoted = ['\'', '"'].iter().find_map(|quote| {
+                        inner
+                            .strip_prefix(*quote)
+                            .and_then(|s| s.strip_suffix(*quote))
+                    });
+                    if let Some(key) = quoted {
+                        elems.push(PatchPathElem::Key(key.to_string()));
+                    } else {
+                        let index = inner
+                            .parse::<usize>()
+                            .map_err(|_| invalid("index must be a number or a quoted key"))?;
+                        elems.push(PatchPathElem::ListIndex(index));
+                    }
+                }
+                other => {
+                    return Err(invalid(&format!("unexpected character '{}'", other)));
+                }
+            }
+        }
+        Ok(Self(elems))
+    }
+
     fn render(&self) -> String {
         let mut s = String::new();
         for elem in &self.0 {
@@ -208,8 +292,15 @@ impl std::fmt::Display for PatchPath {
 pub enum PatchOpErrorKind {
     EmpthPath,
     ListIndexForMap,
-    UnsupportedValue { message: String },
-    ExistingValueMismatch { expected: Value, actual: Value },
+    /// The parent of the path does not exist or is not a map or list.
+    PathNotFound,
+    UnsupportedValue {
+        message: String,
+    },
+    ExistingValueMismatch {
+        expected: Value,
+        actual: Value,
+    },
 }
 
 impl std::fmt::Display for PatchOpErrorKind {
@@ -217,6 +308,7 @@ impl std::fmt::Display for PatchOpErrorKind {
         match self {
             PatchOpErrorKind::EmpthPath => write!(f, "empty path"),
             PatchOpErrorKind::ListIndexForMap => write!(f, "list index used for map"),
+            PatchOpErrorKind::PathNotFound => write!(f, "path not found"),
             PatchOpErrorKind::UnsupportedValue { message } => {
                 write!(f, "unsupported value: {}", message)
             }
@@ -256,148 +348,351 @@ impl std::fmt::Display for PatchOpError {
 impl std::error::Error for PatchOpError {}
 
 impl PatchOp {
+    pub fn path(&self) -> &PatchPath {
+        match self {
+            PatchOp::Add { path, .. }
+            | PatchOp::Replace { path, .. }
+            | PatchOp::Remove { path, .. } => path,
+        }
+    }
+
     fn apply_map(self, target: &mut DataMap) -> Result<(), PatchOpError> {
+        let path = self.path().clone();
+        let err = |kind| PatchOpError::new(path.clone(), kind);
+
+        let (key, rest) = match path.0.split_first() {
+            None => return Err(err(PatchOpErrorKind::EmpthPath)),
+            Some((PatchPathElem::ListIndex(_), _)) => {
+                return Err(err(PatchOpErrorKind::ListIndexForMap))
+            }
+            Some((PatchPathElem::Key(key), rest)) => (key, rest),
+        };
+        let (last, parents) = match rest.split_last() {
+            Some(split) => split,
+            None => return self.apply_entry(target.entry(key.clone())),
+        };
+
+        let parent = target
+            .get_mut(key)
+            .and_then(|value| value.get_path_mut(parents))
+            .ok_or_else(|| err(PatchOpErrorKind::PathNotFound))?;
+        match (parent, last) {
+            (Value::Map(map), PatchPathElem::Key(key)) => {
+                self.apply_entry(map.entry(Value::from(key.as_str())))
+            }
+            (Value::List(items), PatchPathElem::ListIndex(index)) => {
+                self.apply_index(items, *index)
+            }
+            _ => Err(err(PatchOpErrorKind::PathNotFound)),
+        }
+    }
+
+    /// Apply the operation to a map entry.
+    fn apply_entry<K: Ord>(
+        self,
+        entry: btree_map::Entry<'_, K, Value>,
+    ) -> Result<(), PatchOpError> {
         match self {
-            PatchOp::Add { path, value } => match path.0.as_slice() {
-                [] => Err(PatchOpError::new(path.clone(), PatchOpErrorKind::EmpthPath)),
-                [PatchPathElem::ListIndex(_), ..] => Err(PatchOpError::new(
-                    path.clone(),
-                    PatchOpErrorKind::ListIndexForMap,
-                )),
-                [PatchPathElem::Key(key)] => match target.get_mut(key) {
-                    None => {
-                        target.insert(key.to_string(), value);
-                        Ok(())
-                    }
-                    Some(u @ Value::Unit) => {
+            PatchOp::Add { path, value } => match entry {
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(value);
+                    Ok(())
+                }
+                btree_map::Entry::Occupied(mut entry) => match entry.get_mut() {
+                    u @ Value::Unit => {
                         *u = value;
                         Ok(())
                     }
-                    Some(Value::List(items)) => {
+                    Value::List(items) => {
                         if !items.contains(&value) {
                             items.push(value);
                         }
                         Ok(())
                     }
-                    Some(Value::Map(_)) => {
+                    Value::Map(_) => {
                         // FIXME: implement adding to map!
                         Err(PatchOpError::new(
-                            path.clone(),
+                            path,
                             PatchOpErrorKind::UnsupportedValue {
                                 message: "can't add to a map".to_string(),
                             },
                         ))
                     }
-                    Some(literal) => {
+                    literal => {
                         *literal = Value::List(vec![literal.clone(), value]);
                         Ok(())
                     }
                 },
-                [PatchPathElem::Key(_key), _rest @ ..] => {
-                    // TODO: implement nesting.
-                    todo!("Nested patch not implemented");
-                }
             },
             PatchOp::Remove {
                 path,
                 value: old_value,
-            } => match path.0.as_slice() {
-                [] => Err(PatchOpError::new(path.clone(), PatchOpErrorKind::EmpthPath)),
-                [PatchPathElem::ListIndex(_), ..] => Err(PatchOpError::new(
-                    path.clone(),
-                    PatchOpErrorKind::ListIndexForMap,
-                )),
-                [PatchPathElem::Key(key)] => {
-                    if let Some(old_value) = old_value {
-                        match target.entry(key.to_string()) {
-                            btree_map::Entry::Vacant(_) => Ok(()),
-                            btree_map::Entry::Occupied(mut current_value) => {
-                                match current_value.get_mut() {
-                                    Value::List(items) => {
-                                        items.retain(|v| v != &old_value);
-                                        Ok(())
-                                    }
-                                    other if other == &old_value => {
-                                        // Value matches the given old_value, so
-                                        // remove the key.
-                                        current_value.remove();
-                                        Ok(())
-                                    }
-                                    _ => {
-                                        // Value does not match the given old_value, so don't remove.
-                                        Err(PatchOpError::new(
-                                            path,
-                                            PatchOpErrorKind::ExistingValueMismatch {
-                                                expected: old_value,
-                                                actual: current_value.get().clone(),
-                                            },
-                                        ))
-                                    }
+            } => {
+                if let Some(old_value) = old_value {
+                    match entry {
+                        btree_map::Entry::Vacant(_) => Ok(()),
+                        btree_map::Entry::Occupied(mut current_value) => {
+                            match current_value.get_mut() {
+                                Value::List(items) => {
+                                    items.retain(|v| v != &old_value);
+                                    Ok(())
+                                }
+                                other if other == &old_value => {
+                                    // Value matches the given old_value, so
+                                    // remove the key.
+                                    current_value.remove();
+                                    Ok(())
+                                }
+                                _ => {
+                                    // Value does not match the given old_value, so don't remove.
+                                    Err(PatchOpError::new(
+                                        path,
+                                        PatchOpErrorKind::ExistingValueMismatch {
+                                            expected: old_value,
+                                            actual: current_value.get().clone(),
+                                        },
+                                    ))
                                 }
                             }
                         }
-                    } else {
-                        target.remove(key);
-                        Ok(())
                     }
+                } else {
+                    if let btree_map::Entry::Occupied(entry) = entry {
+                        entry.remove();
+                    }
+                    Ok(())
                 }
-                [PatchPathElem::Key(_key), _rest @ ..] => {
-                    // TODO: implement nesting.
-                    todo!("Nested patch not implemented");
-                }
-            },
+            }
             PatchOp::Replace {
                 path,
                 new_value,
                 current_value: old_value,
                 must_replace,
-            } => match path.0.as_slice() {
-                [] => Err(PatchOpError::new(path.clone(), PatchOpErrorKind::EmpthPath)),
-                [PatchPathElem::ListIndex(_), ..] => Err(PatchOpError::new(
-                    path.clone(),
-                    PatchOpErrorKind::ListIndexForMap,
-                )),
-                [PatchPathElem::Key(key)] => {
-                    if let Some(old_value) = old_value {
-                        match target.entry(key.to_string()) {
-                            btree_map::Entry::Vacant(entry) => {
-                                entry.insert(new_value);
-                                Ok(())
-                            }
-                            btree_map::Entry::Occupied(mut current_value) => {
-                                match current_value.get_mut() {
-                                    current if current == &old_value => {
-                                        // Value matches the given old_value, so
-                                        // replace it.
-                                        *current = new_value;
-                                        Ok(())
-                                    }
-                                    _ if !must_replace => Ok(()),
-                                    _ => {
-                                        // Value does not match the given old_value, so don't remove.
-                                        Err(PatchOpError::new(
-                                            path,
-                                            PatchOpErrorKind::ExistingValueMismatch {
-                                                expected: old_value,
-                                                actual: current_value.get().clone(),
-                                            },
-                                        ))
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        target.insert(key.clone(), new_value);
+            } => match entry {
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(new_value);
+                    Ok(())
+                }
+                btree_map::Entry::Occupied(mut current_value) => match old_value {
+                    None => {
+                        current_value.insert(new_value);
                         Ok(())
                     }
+                    Some(old_value) => match current_value.get_mut() {
+                        current if current == &old_value => {
+                            // Value matches the given old_value, so
+                            // replace it.
+                            *current = new_value;
+                            Ok(())
+                        }
+                        _ if !must_replace => Ok(()),
+                        _ => {
+                            // Value does not match the given old_value, so don't remove.
+                            Err(PatchOpError::new(
+                                path,
+                                PatchOpErrorKind::ExistingValueMismatch {
+                                    expected: old_value,
+                                    actual: current_value.get().clone(),
+                                },
+                            ))
+                        }
+                    },
+                },
+            },
+        }
+    }
+
+    /// Apply the operation to a list element.
+    ///
+    /// Adds insert the value at the index, shifting the following elements.
+    fn apply_index(self, items: &mut Vec<Value>, index: usize) -> Result<(), PatchOpError> {
+        let not_found = |path| PatchOpError::new(path, PatchOpErrorKind::PathNotFound);
+        let check_current =
+            |path: PatchPath, current: &Value, expected: Option<Value>| match expected {
+                Some(expected) if current != &expected => Err(PatchOpError::new(
+                    path,
+                    PatchOpErrorKind::ExistingValueMismatch {
+                        expected,
+                        actual: current.clone(),
+                    },
+                )),
+                _ => Ok(()),
+            };
+
+        match self {
+            PatchOp::Add { path, value } => {
+                if index > items.len() {
+                    return Err(not_found(path));
                 }
-                [PatchPathElem::Key(_key), _rest @ ..] => {
-                    // TODO: implement nesting.
-                    todo!("Nested patch not implemented");
+                items.insert(index, value);
+            }
+            PatchOp::Remove { path, value } => {
+                let current = items.get(index).ok_or_else(|| not_found(path.clone()))?;
+                check_current(path, current, value)?;
+                items.remove(index);
+            }
+            PatchOp::Replace {
+                path,
+                new_value,
+                current_value,
+                must_replace,
+            } => {
+                let current = items
+                    .get_mut(index)
+                    .ok_or_else(|| not_found(path.clone()))?;
+                match check_current(path, current, current_value) {
+                    Ok(()) => *current = new_value,
+                    Err(_) if !must_replace => {}
+                    Err(err) => return Err(err),
                 }
-            },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maximum size of the table used to match the elements of two lists.
+///
+/// Longer lists are diffed by position.
+const MAX_LIST_DIFF_CELLS: usize = 1 << 20;
+
+/// Append the operations that transform `old` into `new` at `path`.
+fn diff_value(ops: &mut Vec<PatchOp>, path: &[PatchPathElem], old: &Value, new: &Value) {
+    let nested = |elem: PatchPathElem| {
+        let mut path = path.to_vec();
+        path.push(elem);
+        PatchPath(path)
+    };
+
+    match (old, new) {
+        _ if old == new => {}
+        // Only maps with string keys can be addressed by a path.
+        (Value::Map(old), Value::Map(new))
+            if old.keys().chain(new.keys()).all(|key| key.is_string()) =>
+        {
+            for key in old.keys() {
+                if !new.contains_key(key) {
+                    ops.push(PatchOp::Remove {
+                        path: nested(PatchPathElem::Key(key.as_str().unwrap().to_string())),
+                        value: None,
+                    });
+                }
+            }
+            for (key, value) in new.iter() {
+                let elem = PatchPathElem::Key(key.as_str().unwrap().to_string());
+                match old.get(key) {
+                    Some(old_value) => diff_value(ops, &nested(elem).0, old_value, value),
+                    None => ops.push(PatchOp::add(nested(elem), value.clone())),
+                }
+            }
+        }
+        (Value::List(old), Value::List(new)) => diff_list(ops, path, old, new),
+        _ => ops.push(PatchOp::Replace {
+            path: PatchPath(path.to_vec()),
+            new_value: new.clone(),
+            current_value: None,
+            must_replace: false,
+        }),
+    }
+}
+
+/// Append the operations that transform the list `old` into `new`.
+///
+/// Elements that are kept are found with a longest common subsequence, so
+/// inserting or removing an element does not modify all following elements.
+/// Changed elements in between are diffed recursively.
+fn diff_list(ops: &mut Vec<PatchOp>, path: &[PatchPathElem], old: &[Value], new: &[Value]) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    // Index of the next element in the list as modified by the ops so far.
+    let mut pos = prefix;
+    let (mut i, mut j) = (0, 0);
+    for (old_index, new_index) in lcs_matches(old, new) {
+        diff_run(ops, path, &mut pos, &old[i..old_index], &new[j..new_index]);
+        // The matched element is kept.
+        pos += 1;
+        i = old_index + 1;
+        j = new_index + 1;
+    }
+    diff_run(ops, path, &mut pos, &old[i..], &new[j..]);
+}
+
+/// Append the operations that replace the `removed` list elements at `pos`
+/// with the `inserted` ones.
+fn diff_run(
+    ops: &mut Vec<PatchOp>,
+    path: &[PatchPathElem],
+    pos: &mut usize,
+    removed: &[Value],
+    inserted: &[Value],
+) {
+    let index = |pos| {
+        let mut path = path.to_vec();
+        path.push(PatchPathElem::ListIndex(pos));
+        path
+    };
+
+    let common = removed.len().min(inserted.len());
+    for (old_item, new_item) in removed.iter().zip(inserted) {
+        diff_value(ops, &index(*pos), old_item, new_item);
+        *pos += 1;
+    }
+    for item in &inserted[common..] {
+        ops.push(PatchOp::add(index(*pos), item.clone()));
+        *pos += 1;
+    }
+    for _ in &removed[common..] {
+        ops.push(PatchOp::Remove {
+            path: PatchPath(index(*pos)),
+            value: None,
+        });
+    }
+}
+
+/// Find the index pairs of a longest common subsequence of two lists.
+///
+/// Returns no matches if the lists are too long, see [`MAX_LIST_DIFF_CELLS`].
+fn lcs_matches(old: &[Value], new: &[Value]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    if n == 0 || m == 0 || n.saturating_mul(m) > MAX_LIST_DIFF_CELLS {
+        return Vec::new();
+    }
+
+    // Length of the longest common subsequence of `old[i..]` and `new[j..]`.
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[at(i, j)] = if old[i] == new[j] {
+                lengths[at(i + 1, j + 1)] + 1
+            } else {
+                lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+            };
         }
     }
+
+    let mut matches = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            matches.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[at(i + 1, j)] >= lengths[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
 }
 
 #[cfg(test)]
@@ -433,4 +728,104 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_patch_nested() {
+        let m = map! {
+            "a": map! { "x": 1, "y": vec![1, 2] }.into_inner(),
+            "b": vec![1, 2, 3],
+        };
+        let out = Patch::new()
+            .replace(vec!["a", "x"], 2)
+            .add(
+                PatchPath(vec!["a".into(), "y".into(), PatchPathElem::ListIndex(0)]),
+                0,
+            )
+            .remove(PatchPath(vec!["b".into(), PatchPathElem::ListIndex(1)]))
+            .apply_map(m.clone())
+            .unwrap();
+        assert_eq!(
+            out,
+            map! {
+                "a": map! { "x": 2, "y": vec![0, 1, 2] }.into_inner(),
+                "b": vec![1, 3],
+            }
+        );
+
+        let err = Patch::new()
+            .remove(vec!["missing", "x"])
+            .apply_map(m)
+            .unwrap_err();
+        assert!(matches!(err.kind, PatchOpErrorKind::PathNotFound));
+    }
+
+    #[test]
+    fn test_patch_diff() {
+        let old = map! {
+            "a": 1,
+            "b": "x",
+            "c": map! { "x": 1, "y": map! { "z": vec![1, 2] }.into_inner() }.into_inner(),
+            "d": vec![1, 2, 3, 4],
+            "e": vec![1, 2],
+        };
+        let new = map! {
+            "b": "y",
+            "c": map! { "x": 1, "y": map! { "z": vec![1, 3] }.into_inner(), "w": true }.into_inner(),
+            "d": vec![0, 1, 2, 4],
+            "e": vec![2],
+            "f": 5,
+        };
+
+        let patch = Patch::diff(&old, &new);
+        let index =
+            |key: &str, index: usize| PatchPath(vec![key.into(), PatchPathElem::ListIndex(index)]);
+        assert_eq!(
+            patch,
+            Patch::new()
+                .remove("a")
+                .replace("b", "y")
+                .add(vec!["c", "w"], true)
+                .replace(
+                    PatchPath(vec![
+                        "c".into(),
+                        "y".into(),
+                        "z".into(),
+                        PatchPathElem::ListIndex(1)
+                    ]),
+                    3
+                )
+                .add(index("d", 0), 0)
+                .remove(index("d", 3))
+                .remove(index("e", 0))
+                .add("f", 5)
+        );
+        assert_eq!(patch.apply_map(old.clone()).unwrap(), new);
+        assert_eq!(Patch::diff(&old, &old), Patch::new());
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        assert_eq!(
+            PatchPath::parse_json_path("$.a['b c'][2]").unwrap(),
+            PatchPath(vec![
+                PatchPathElem::Key("a".to_string()),
+                PatchPathElem::Key("b c".to_string()),
+                PatchPathElem::ListIndex(2),
+            ])
+        );
+        assert_eq!(PatchPath::parse_json_path("$").unwrap(), PatchPath(vec![]));
+        assert!(PatchPath::parse_json_path("a.b").is_err());
+        assert!(PatchPath::parse_json_path("$.").is_err());
+        assert!(PatchPath::parse_json_path("$[x]").is_err());
+
+        let value = Value::Map(
+            vec![(Value::from("a"), Value::from(vec![1, 2]))]
+                .into_iter()
+                .collect(),
+        );
+        let path = PatchPath::parse_json_path("$.a[1]").unwrap();
+        assert_eq!(value.get_path(&path), Some(&Value::from(2)));
+        let path = PatchPath::parse_json_path("$.b").unwrap();
+        assert_eq!(value.get_path(&path), None);
+    }
 }
@@ -1,6 +1,9 @@
-use std::convert::TryFrom;
+use std::{convert::TryFrom, str::FromStr};
 
-use time::OffsetDateTime;
+use time::{
+    format_description::well_known::Rfc3339, Date, Duration, Month, OffsetDateTime,
+    PrimitiveDateTime, Time, UtcOffset,
+};
 
 use super::{value::ValueCoercionError, Value, ValueType};
 
@@ -14,6 +17,13 @@ use super::{value::ValueCoercionError, Value, ValueType};
 pub struct Timestamp(u64);
 
 impl Timestamp {
+    #[cfg(target_arch = "wasm32")]
+    pub fn now() -> Self {
+        // SystemTime::now() is not supported on wasm32-unknown-unknown.
+        Self(js_sys::Date::now() as u64)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn now() -> Self {
         let t = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -34,10 +44,161 @@ impl Timestamp {
         std::time::UNIX_EPOCH.checked_add(std::time::Duration::from_millis(self.0))
     }
 
+    /// Convert a date time in any timezone.
+    ///
+    /// Sub-millisecond precision is discarded, and times before the UNIX
+    /// epoch are clamped to the epoch.
+    pub fn from_datetime(datetime: OffsetDateTime) -> Self {
+        let millis = datetime.unix_timestamp_nanos() / 1_000_000;
+        Self(u64::try_from(millis).unwrap_or(0))
+    }
+
+    /// Create a timestamp from a local date time in the timezone with the
+    /// given UTC offset.
+    pub fn from_local(datetime: PrimitiveDateTime, offset: UtcOffset) -> Self {
+        Self::from_datetime(datetime.assume_offset(offset))
+    }
+
+    /// Parse an RFC3339 date time, like `2022-06-01T12:30:00+02:00`.
+    pub fn parse_rfc3339(value: &str) -> Result<Self, time::error::Parse> {
+        OffsetDateTime::parse(value, &Rfc3339).map(Self::from_datetime)
+    }
+
+    /// The date time in UTC.
     pub fn to_datetime(&self) -> OffsetDateTime {
-        OffsetDateTime::from_unix_timestamp_nanos(self.0 as i128 * 1_000)
-            .or_else(|_| OffsetDateTime::from_unix_timestamp(u32::MAX as i64))
-            .unwrap_or(OffsetDateTime::from_unix_timestamp(0).unwrap())
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(self.0) * 1_000_000)
+            .or_else(|_| OffsetDateTime::from_unix_timestamp(i64::from(u32::MAX)))
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
+    /// The date time in the timezone with the given UTC offset.
+    pub fn to_datetime_at(&self, offset: UtcOffset) -> OffsetDateTime {
+        self.to_datetime().to_offset(offset)
+    }
+
+    /// Format as an RFC3339 date time in UTC.
+    pub fn format_rfc3339(&self) -> String {
+        self.format_rfc3339_at(UtcOffset::UTC)
+    }
+
+    /// Format as an RFC3339 date time in the timezone with the given UTC
+    /// offset.
+    pub fn format_rfc3339_at(&self, offset: UtcOffset) -> String {
+        self.to_datetime_at(offset)
+            .format(&Rfc3339)
+            // Only fails for years that can not be represented in RFC3339.
+            .unwrap_or_else(|_| self.0.to_string())
+    }
+
+    /// Truncate to the start of the given unit, in the timezone with the
+    /// given UTC offset.
+    ///
+    /// Weeks start on Monday.
+    pub fn truncate(self, unit: TimeUnit, offset: UtcOffset) -> Self {
+        let datetime = self.to_datetime_at(offset);
+        let date = datetime.date();
+        let time = datetime.time();
+
+        let (date, time) = match unit {
+            TimeUnit::Second => (
+                date,
+                Time::from_hms(time.hour(), time.minute(), time.second()).unwrap_or(time),
+            ),
+            TimeUnit::Minute => (
+                date,
+                Time::from_hms(time.hour(), time.minute(), 0).unwrap_or(time),
+            ),
+            TimeUnit::Hour => (date, Time::from_hms(time.hour(), 0, 0).unwrap_or(time)),
+            TimeUnit::Day => (date, Time::MIDNIGHT),
+            TimeUnit::Week => {
+                let days = i64::from(date.weekday().number_days_from_monday());
+                (date - Duration::days(days), Time::MIDNIGHT)
+            }
+            TimeUnit::Month => (
+                Date::from_calendar_date(date.year(), date.month(), 1).unwrap_or(date),
+                Time::MIDNIGHT,
+            ),
+            TimeUnit::Year => (
+                Date::from_calendar_date(date.year(), Month::January, 1).unwrap_or(date),
+                Time::MIDNIGHT,
+            ),
+        };
+        Self::from_local(PrimitiveDateTime::new(date, time), offset)
+    }
+
+    /// Start of the day, in the timezone with the given UTC offset.
+    pub fn start_of_day(self, offset: UtcOffset) -> Self {
+        self.truncate(TimeUnit::Day, offset)
+    }
+
+    /// Start of the week (Monday), in the timezone with the given UTC
+    /// offset.
+    pub fn start_of_week(self, offset: UtcOffset) -> Self {
+        self.truncate(TimeUnit::Week, offset)
+    }
+
+    /// Start of the month, in the timezone with the given UTC offset.
+    pub fn start_of_month(self, offset: UtcOffset) -> Self {
+        self.truncate(TimeUnit::Month, offset)
+    }
+
+    /// Start of the year, in the timezone with the given UTC offset.
+    pub fn start_of_year(self, offset: UtcOffset) -> Self {
+        self.truncate(TimeUnit::Year, offset)
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format_rfc3339())
+    }
+}
+
+/// Unit of time for truncating timestamps.
+///
+/// See [`Timestamp::truncate`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl TimeUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Second => "second",
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+        }
+    }
+}
+
+impl FromStr for TimeUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "second" => Ok(Self::Second),
+            "minute" => Ok(Self::Minute),
+            "hour" => Ok(Self::Hour),
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "year" => Ok(Self::Year),
+            _ => Err(anyhow::anyhow!("Invalid time unit: '{}'", s)),
+        }
     }
 }
 
@@ -60,7 +221,7 @@ impl TryFrom<Value> for Timestamp {
 
 impl From<OffsetDateTime> for Timestamp {
     fn from(v: OffsetDateTime) -> Self {
-        Self(v.unix_timestamp() as u64 * 1_000)
+        Self::from_datetime(v)
     }
 }
 
@@ -69,3 +230,45 @@ impl From<Timestamp> for OffsetDateTime {
         v.to_datetime()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_timezones() {
+        let ts = Timestamp::parse_rfc3339("2022-06-01T23:30:00.250+02:00").unwrap();
+        assert_eq!(ts.format_rfc3339(), "2022-06-01T21:30:00.25Z");
+        assert_eq!(ts.to_string(), "2022-06-01T21:30:00.25Z");
+
+        let offset = UtcOffset::from_hms(2, 0, 0).unwrap();
+        assert_eq!(ts.format_rfc3339_at(offset), "2022-06-01T23:30:00.25+02:00");
+        assert_eq!(Timestamp::from_datetime(ts.to_datetime()), ts);
+        assert_eq!(ts.to_datetime_at(offset).hour(), 23);
+    }
+
+    #[test]
+    fn test_timestamp_truncate() {
+        // Wednesday.
+        let ts = Timestamp::parse_rfc3339("2022-06-01T23:30:15.250Z").unwrap();
+        let utc = UtcOffset::UTC;
+        let plus_two = UtcOffset::from_hms(2, 0, 0).unwrap();
+
+        let check = |ts: Timestamp, expected: &str| {
+            assert_eq!(ts, Timestamp::parse_rfc3339(expected).unwrap());
+        };
+
+        check(ts.truncate(TimeUnit::Second, utc), "2022-06-01T23:30:15Z");
+        check(ts.truncate(TimeUnit::Minute, utc), "2022-06-01T23:30:00Z");
+        check(ts.truncate(TimeUnit::Hour, utc), "2022-06-01T23:00:00Z");
+        check(ts.start_of_day(utc), "2022-06-01T00:00:00Z");
+        // Already the next day at +02:00.
+        check(ts.start_of_day(plus_two), "2022-06-02T00:00:00+02:00");
+        check(ts.start_of_week(utc), "2022-05-30T00:00:00Z");
+        check(ts.start_of_month(utc), "2022-06-01T00:00:00Z");
+        check(ts.start_of_year(plus_two), "2022-01-01T00:00:00+02:00");
+
+        assert_eq!("Day".parse::<TimeUnit>().unwrap(), TimeUnit::Day);
+        assert!("fortnight".parse::<TimeUnit>().is_err());
+    }
+}
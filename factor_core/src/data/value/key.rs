@@ -0,0 +1,456 @@
+//! Order-preserving binary encoding of values for index keys.
+//!
+//! Encoded keys compare (byte-wise) in the same order as the values they
+//! were created from, so they can be stored in any ordered key-value store
+//! and used for range scans.
+//!
+//! Values of different types are ordered by type:
+//! unit < bool < numbers < strings < bytes < ids < lists < maps.
+//!
+//! All numbers (`Int`, `UInt` and `Float`) share a single encoding, and are
+//! ordered by their numeric value. Numerically equal values produce the same
+//! key, so `Int(5)`, `UInt(5)` and `Float(5.0)` are interchangeable for index
+//! lookups. Timestamps are stored as numbers and thus sort chronologically.
+//!
+//! Strings are compared by their UTF-8 bytes.
+//! Lists (tuples) are compared element by element, with shorter lists
+//! sorting before longer lists with the same prefix.
+
+use std::convert::TryFrom;
+
+use ordered_float::OrderedFloat;
+
+use crate::data::{Id, Value, ValueMap};
+
+/// Terminates strings, bytes, lists and maps.
+const END: u8 = 0x00;
+/// Escape byte for `0x00` bytes in strings and bytes.
+const ESCAPE: u8 = 0xFF;
+
+const TAG_UNIT: u8 = 0x01;
+const TAG_BOOL: u8 = 0x10;
+const TAG_NUMBER: u8 = 0x20;
+const TAG_STRING: u8 = 0x30;
+const TAG_BYTES: u8 = 0x40;
+const TAG_ID: u8 = 0x50;
+const TAG_LIST: u8 = 0x60;
+const TAG_MAP: u8 = 0x70;
+
+const SIGN_BIT_64: u64 = 1 << 63;
+const SIGN_BIT_128: u128 = 1 << 127;
+
+/// An encoded index key.
+///
+/// See the [module docs](self) for the ordering.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct IndexKey(Vec<u8>);
+
+impl IndexKey {
+    pub fn from_value(value: &Value) -> Self {
+        let mut encoder = IndexKeyEncoder::new();
+        encoder.value(value);
+        encoder.finish()
+    }
+
+    /// Key prefix that matches all strings starting with `prefix`.
+    ///
+    /// See [`Self::prefix_end`].
+    pub fn string_prefix(prefix: &str) -> Self {
+        let mut buf = vec![TAG_STRING];
+        push_escaped(&mut buf, prefix.as_bytes());
+        Self(buf)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn starts_with(&self, prefix: &IndexKey) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// The smallest key that is greater than all keys starting with this key.
+    ///
+    /// Keys `k` with `self <= k < self.prefix_end()` are exactly the keys
+    /// starting with `self`.
+    /// Returns `None` if there is no such key.
+    pub fn prefix_end(&self) -> Option<IndexKey> {
+        let mut end = self.0.clone();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(Self(end));
+            }
+        }
+        None
+    }
+
+    /// Decode the key back into a value.
+    ///
+    /// Numbers are decoded to their canonical representation: integral
+    /// values become `Int` (or `UInt` if they exceed `i64`), everything else
+    /// becomes `Float`.
+    ///
+    /// Returns `None` if the key is not a valid encoding.
+    pub fn decode(&self) -> Option<Value> {
+        let mut input = self.0.as_slice();
+        let value = decode_value(&mut input)?;
+        if input.is_empty() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&Value> for IndexKey {
+    fn from(value: &Value) -> Self {
+        Self::from_value(value)
+    }
+}
+
+/// Incrementally builds an [`IndexKey`].
+///
+/// Allows backends to encode their own value representations without
+/// converting them to a [`Value`] first.
+#[derive(Default, Debug)]
+pub struct IndexKeyEncoder {
+    buf: Vec<u8>,
+}
+
+impl IndexKeyEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> IndexKey {
+        IndexKey(self.buf)
+    }
+
+    pub fn value(&mut self, value: &Value) {
+        match value {
+            Value::Unit => self.unit(),
+            Value::Bool(v) => self.bool(*v),
+            Value::UInt(v) => self.int(i128::from(*v)),
+            Value::Int(v) => self.int(i128::from(*v)),
+            Value::Float(v) => self.float(v.into_inner()),
+            Value::String(v) => self.string(v),
+            Value::Bytes(v) => self.bytes(v),
+            Value::List(items) => {
+                self.begin_list();
+                items.iter().for_each(|item| self.value(item));
+                self.end();
+            }
+            Value::Map(map) => {
+                self.begin_map();
+                for (key, value) in &map.0 {
+                    self.value(key);
+                    self.value(value);
+                }
+                self.end();
+            }
+            Value::Id(v) => self.id(*v),
+        }
+    }
+
+    pub fn unit(&mut self) {
+        self.buf.push(TAG_UNIT);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(TAG_BOOL);
+        self.buf.push(u8::from(value));
+    }
+
+    pub fn int(&mut self, value: i128) {
+        // Rounding to the nearest float is monotonic, so the approximation
+        // keeps the order between numbers. The exact value breaks ties.
+        let approx = value as f64;
+        self.number(approx, value);
+    }
+
+    pub fn float(&mut self, value: f64) {
+        self.number(value, float_to_exact_int(value).unwrap_or_default());
+    }
+
+    fn number(&mut self, approx: f64, exact: i128) {
+        self.buf.push(TAG_NUMBER);
+        self.buf
+            .extend_from_slice(&encode_f64(approx).to_be_bytes());
+        let exact = u128::from_be_bytes(exact.to_be_bytes()) ^ SIGN_BIT_128;
+        self.buf.extend_from_slice(&exact.to_be_bytes());
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.buf.push(TAG_STRING);
+        push_escaped(&mut self.buf, value.as_bytes());
+        self.buf.extend_from_slice(&[END, END]);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.buf.push(TAG_BYTES);
+        push_escaped(&mut self.buf, value);
+        self.buf.extend_from_slice(&[END, END]);
+    }
+
+    pub fn id(&mut self, value: Id) {
+        self.buf.push(TAG_ID);
+        self.buf.extend_from_slice(value.as_uuid().as_bytes());
+    }
+
+    /// Start a list. Must be followed by the items and [`Self::end`].
+    pub fn begin_list(&mut self) {
+        self.buf.push(TAG_LIST);
+    }
+
+    /// Start a map. Must be followed by alternating keys and values, in key
+    /// order, and [`Self::end`].
+    pub fn begin_map(&mut self) {
+        self.buf.push(TAG_MAP);
+    }
+
+    /// End a list or map.
+    pub fn end(&mut self) {
+        self.buf.push(END);
+    }
+}
+
+/// Map a float to an integer with the same ordering.
+fn encode_f64(value: f64) -> u64 {
+    // Normalize -0.0 and NaNs, so equal values have equal keys.
+    let value = if value == 0.0 {
+        0.0
+    } else if value.is_nan() {
+        f64::NAN
+    } else {
+        value
+    };
+    let bits = value.to_bits();
+    if bits & SIGN_BIT_64 == 0 {
+        bits ^ SIGN_BIT_64
+    } else {
+        !bits
+    }
+}
+
+fn decode_f64(value: u64) -> f64 {
+    let bits = if value & SIGN_BIT_64 == 0 {
+        !value
+    } else {
+        value ^ SIGN_BIT_64
+    };
+    f64::from_bits(bits)
+}
+
+/// Returns the integer value of integral floats that fit into an `i128`.
+fn float_to_exact_int(value: f64) -> Option<i128> {
+    // 2^127
+    const LIMIT: f64 = 170_141_183_460_469_231_731_687_303_715_884_105_728.0;
+    if value.is_finite() && value.fract() == 0.0 && (-LIMIT..LIMIT).contains(&value) {
+        Some(value as i128)
+    } else {
+        None
+    }
+}
+
+fn push_escaped(buf: &mut Vec<u8>, bytes: &[u8]) {
+    for byte in bytes {
+        buf.push(*byte);
+        if *byte == END {
+            buf.push(ESCAPE);
+        }
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Some(head)
+}
+
+fn decode_escaped(input: &mut &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let (byte, rest) = input.split_first()?;
+        *input = rest;
+        if *byte != END {
+            out.push(*byte);
+            continue;
+        }
+        let (next, rest) = input.split_first()?;
+        *input = rest;
+        match *next {
+            ESCAPE => out.push(END),
+            END => return Some(out),
+            _ => return None,
+        }
+    }
+}
+
+fn decode_value(input: &mut &[u8]) -> Option<Value> {
+    let (tag, rest) = input.split_first()?;
+    *input = rest;
+    let value = match *tag {
+        TAG_UNIT => Value::Unit,
+        TAG_BOOL => match take(input, 1)? {
+            [0] => Value::Bool(false),
+            [1] => Value::Bool(true),
+            _ => return None,
+        },
+        TAG_NUMBER => {
+            let approx = decode_f64(u64::from_be_bytes(
+                <[u8; 8]>::try_from(take(input, 8)?).ok()?,
+            ));
+            let exact = u128::from_be_bytes(<[u8; 16]>::try_from(take(input, 16)?).ok()?);
+            let exact = i128::from_be_bytes((exact ^ SIGN_BIT_128).to_be_bytes());
+            match float_to_exact_int(approx) {
+                Some(_) => {
+                    if let Ok(v) = i64::try_from(exact) {
+                        Value::Int(v)
+                    } else if let Ok(v) = u64::try_from(exact) {
+                        Value::UInt(v)
+                    } else {
+                        Value::Float(OrderedFloat(approx))
+                    }
+                }
+                None => Value::Float(OrderedFloat(approx)),
+            }
+        }
+        TAG_STRING => Value::String(String::from_utf8(decode_escaped(input)?).ok()?),
+        TAG_BYTES => Value::Bytes(decode_escaped(input)?),
+        TAG_ID => Value::Id(Id::from_uuid(
+            uuid::Uuid::from_slice(take(input, 16)?).ok()?,
+        )),
+        TAG_LIST => {
+            let mut items = Vec::new();
+            while *input.first()? != END {
+                items.push(decode_value(input)?);
+            }
+            *input = &input[1..];
+            Value::List(items)
+        }
+        TAG_MAP => {
+            let mut map = ValueMap::new();
+            while *input.first()? != END {
+                let key = decode_value(input)?;
+                let value = decode_value(input)?;
+                map.insert(key, value);
+            }
+            *input = &input[1..];
+            Value::Map(map)
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: impl Into<Value>) -> IndexKey {
+        IndexKey::from_value(&value.into())
+    }
+
+    #[test]
+    fn test_index_key_number_order() {
+        let values: Vec<Value> = vec![
+            Value::from(f64::NEG_INFINITY),
+            Value::Int(i64::MIN),
+            Value::from(-1.5),
+            Value::Int(-1),
+            Value::from(-0.5),
+            Value::UInt(0),
+            Value::from(0.5),
+            Value::Int(1),
+            Value::from(1.5),
+            Value::UInt(2),
+            Value::Int(i64::MAX),
+            Value::UInt(u64::MAX - 1),
+            Value::UInt(u64::MAX),
+            Value::from(1e300),
+            Value::from(f64::INFINITY),
+        ];
+        let keys: Vec<_> = values.iter().map(IndexKey::from_value).collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+        }
+
+        assert_eq!(key(5i64), key(5u64));
+        assert_eq!(key(5i64), key(5.0));
+        assert_eq!(key(0.0), key(-0.0));
+        // Not representable as f64, only distinguished by the exact part.
+        assert!(key(u64::MAX - 1) < key(u64::MAX));
+    }
+
+    #[test]
+    fn test_index_key_type_order() {
+        let values = vec![
+            Value::Unit,
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Int(-10),
+            Value::from(""),
+            Value::from("a"),
+            Value::from("a\0"),
+            Value::from("ab"),
+            Value::from("b"),
+            Value::Bytes(vec![0]),
+            Value::Bytes(vec![0, 0]),
+            Value::Bytes(vec![1]),
+            Value::Id(Id::nil()),
+            Value::List(vec![]),
+            Value::List(vec![Value::Int(1)]),
+            Value::List(vec![Value::Int(1), Value::from("a")]),
+            Value::List(vec![Value::Int(2)]),
+            Value::Map(ValueMap::new()),
+        ];
+        let keys: Vec<_> = values.iter().map(IndexKey::from_value).collect();
+        for pair in keys.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+        }
+
+        for value in values {
+            assert_eq!(IndexKey::from_value(&value).decode(), Some(value));
+        }
+        assert_eq!(key(5.0).decode(), Some(Value::Int(5)));
+        assert_eq!(key(u64::MAX).decode(), Some(Value::UInt(u64::MAX)));
+        assert_eq!(key(-2.5).decode(), Some(Value::from(-2.5)));
+    }
+
+    #[test]
+    fn test_index_key_string_prefix() {
+        let prefix = IndexKey::string_prefix("a1");
+        let end = prefix.prefix_end().unwrap();
+
+        for matching in ["a1", "a10", "a1\0", "a1zz"] {
+            let k = key(matching);
+            assert!(k.starts_with(&prefix));
+            assert!(prefix <= k && k < end);
+        }
+        for other in ["a", "a0", "a2", "b"] {
+            let k = key(other);
+            assert!(!k.starts_with(&prefix));
+            assert!(k < prefix || k >= end);
+        }
+    }
+}
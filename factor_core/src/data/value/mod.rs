@@ -5,6 +5,9 @@ pub use serde_serialize::{to_value, to_value_map, ValueSerializeError};
 
 mod serde_serialize;
 pub use serde_deserialize::{from_value, from_value_map, ValueDeserializeError};
+
+mod key;
+pub use key::{IndexKey, IndexKeyEncoder};
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
 use std::{
@@ -93,6 +96,42 @@ impl ValueCoercionError {
             message,
         }
     }
+
+    /// Additional details about the failure, if available.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Prefix the error path with the location of a nested value.
+    fn nested(mut self, elem: PatchPathElem) -> Self {
+        let mut path = self.path.take().map(|p| p.0).unwrap_or_default();
+        path.insert(0, elem);
+        self.path = Some(PatchPath(path));
+        self
+    }
+}
+
+/// Maximum number of characters of a value included in error messages.
+const ERROR_EXCERPT_LEN: usize = 50;
+
+/// Quoted excerpt of a string value for error messages.
+///
+/// Long values are truncated, so errors stay readable.
+fn value_excerpt(value: &str) -> String {
+    match value.char_indices().nth(ERROR_EXCERPT_LEN) {
+        Some((index, _)) => format!("'{}...'", &value[..index]),
+        None => format!("'{}'", value),
+    }
+}
+
+/// Path element for a map key.
+///
+/// Non-string keys are rendered with their debug representation.
+fn map_key_path_elem(key: &Value) -> PatchPathElem {
+    match key {
+        Value::String(s) => PatchPathElem::Key(s.clone()),
+        other => PatchPathElem::Key(format!("{:?}", other)),
+    }
 }
 
 impl std::fmt::Display for ValueCoercionError {
@@ -187,9 +226,48 @@ impl Value {
                     message: None,
                 }),
             },
-            ValueType::Map(_t) => {
-                todo!()
-            }
+            ValueType::Map(map_ty) => match self {
+                Self::Unit => {
+                    *self = Self::Map(ValueMap::default());
+                    Ok(())
+                }
+                Self::Map(map) => {
+                    // Keys can change during coercion, so they are collected
+                    // and replaced after all values have been coerced.
+                    let mut changed_keys = Vec::new();
+                    for (key, value) in map.iter_mut() {
+                        let mut new_key = key.clone();
+                        new_key
+                            .coerce_mut(&map_ty.key)
+                            .map_err(|err| err.nested(map_key_path_elem(key)))?;
+                        value
+                            .coerce_mut(&map_ty.value)
+                            .map_err(|err| err.nested(map_key_path_elem(key)))?;
+                        if &new_key != key {
+                            changed_keys.push((key.clone(), new_key));
+                        }
+                    }
+                    for (old_key, new_key) in changed_keys {
+                        let value = map.remove(&old_key).unwrap_or(Value::Unit);
+                        if map.contains_key(&new_key) {
+                            return Err(ValueCoercionError {
+                                expected_type: ty.clone(),
+                                actual_type: old_key.value_type(),
+                                path: Some(PatchPath(vec![map_key_path_elem(&old_key)])),
+                                message: Some("duplicate map key after coercion".to_string()),
+                            });
+                        }
+                        map.insert(new_key, value);
+                    }
+                    Ok(())
+                }
+                other => Err(ValueCoercionError {
+                    expected_type: ty.clone(),
+                    actual_type: other.value_type(),
+                    path: None,
+                    message: None,
+                }),
+            },
             ValueType::Int => match self {
                 Value::Int(_) => Ok(()),
                 Value::UInt(x) => {
@@ -347,8 +425,9 @@ impl Value {
                     Ok(())
                 }
                 Self::List(items) => {
-                    for item in items {
-                        item.coerce_mut(item_type)?;
+                    for (index, item) in items.iter_mut().enumerate() {
+                        item.coerce_mut(item_type)
+                            .map_err(|err| err.nested(PatchPathElem::ListIndex(index)))?;
                     }
                     Ok(())
                 }
@@ -377,19 +456,56 @@ impl Value {
                     message: None,
                 })
             }
-            ValueType::Object(_obj) => {
-                // FIXME: coerce objects properly - code below is useless.
-                let actual_ty = self.value_type();
-                if &actual_ty == ty {
-                    Ok(())
-                } else {
-                    Err(ValueCoercionError {
+            ValueType::Object(obj) => {
+                let map = match self {
+                    Self::Map(map) => map,
+                    other => {
+                        return Err(ValueCoercionError {
+                            expected_type: ty.clone(),
+                            actual_type: other.value_type(),
+                            path: None,
+                            message: None,
+                        })
+                    }
+                };
+
+                let unknown = map.iter().find(|(key, _)| {
+                    !obj.fields
+                        .iter()
+                        .any(|field| key.as_str() == Some(field.name.as_str()))
+                });
+                if let Some((key, value)) = unknown {
+                    return Err(ValueCoercionError {
                         expected_type: ty.clone(),
-                        actual_type: self.value_type(),
-                        path: None,
-                        message: None,
-                    })
+                        actual_type: value.value_type(),
+                        path: Some(PatchPath(vec![map_key_path_elem(key)])),
+                        message: Some("unknown field".to_string()),
+                    });
+                }
+
+                for field in &obj.fields {
+                    let key = Value::String(field.name.clone());
+                    let nested = |err: ValueCoercionError| {
+                        err.nested(PatchPathElem::Key(field.name.clone()))
+                    };
+                    match map.get_mut(&key) {
+                        Some(value) => value.coerce_mut(&field.value_type).map_err(nested)?,
+                        None => {
+                            // Missing fields are allowed if the field type
+                            // accepts unit values.
+                            let mut value = Value::Unit;
+                            value.coerce_mut(&field.value_type).map_err(|err| {
+                                let mut err = nested(err);
+                                err.message = Some("missing field".to_string());
+                                err
+                            })?;
+                            if value != Value::Unit {
+                                map.insert(key, value);
+                            }
+                        }
+                    }
                 }
+                Ok(())
             }
             ValueType::DateTime => {
                 // FIXME: coerce from uint/int and convert to special Self::DateTime variant once
@@ -411,16 +527,23 @@ impl Value {
                         if let Ok(x) = s.parse::<u64>() {
                             *self = Value::UInt(x);
                             Ok(())
-                        } else if let Ok(t) = OffsetDateTime::parse(s, &Rfc3339) {
-                            *self = Value::UInt(super::Timestamp::try_from(t).unwrap().as_millis());
-                            Ok(())
                         } else {
-                            Err(ValueCoercionError {
-                                expected_type: ValueType::DateTime,
-                                actual_type: self.value_type(),
-                                path: None,
-                                message: None,
-                            })
+                            match OffsetDateTime::parse(s, &Rfc3339) {
+                                Ok(t) => {
+                                    *self = Value::UInt(super::Timestamp::from(t).as_millis());
+                                    Ok(())
+                                }
+                                Err(err) => Err(ValueCoercionError {
+                                    expected_type: ValueType::DateTime,
+                                    actual_type: ValueType::String,
+                                    path: None,
+                                    message: Some(format!(
+                                        "invalid RFC3339 date time {}: {}",
+                                        value_excerpt(s),
+                                        err
+                                    )),
+                                }),
+                            }
                         }
                     }
                     other => Err(ValueCoercionError {
@@ -431,33 +554,32 @@ impl Value {
                     }),
                 }
             }
-            ValueType::Url => {
-                match self {
-                    Value::String(v) => {
-                        if let Err(_err) = url::Url::parse(v) {
-                            // TODO: propagate url parser error message?
-                            Err(ValueCoercionError {
-                                expected_type: ValueType::Url,
-                                actual_type: ValueType::String,
-                                path: None,
-                                message: None,
-                            })
-                        } else {
-                            Ok(())
-                        }
+            ValueType::Url => match self {
+                Value::String(v) => {
+                    if let Err(err) = url::Url::parse(v) {
+                        Err(ValueCoercionError {
+                            expected_type: ValueType::Url,
+                            actual_type: ValueType::String,
+                            path: None,
+                            message: Some(format!("invalid url {}: {}", value_excerpt(v), err)),
+                        })
+                    } else {
+                        Ok(())
                     }
-                    other => Err(ValueCoercionError {
-                        expected_type: ValueType::Url,
-                        actual_type: other.value_type(),
-                        path: None,
-                        message: None,
-                    }),
                 }
-            }
+                other => Err(ValueCoercionError {
+                    expected_type: ValueType::Url,
+                    actual_type: other.value_type(),
+                    path: None,
+                    message: None,
+                }),
+            },
             ValueType::Ref | ValueType::RefConstrained(_) => {
                 match self {
                     Value::String(strval) => {
-                        // TODO: somehow idents?
+                        // NOTE: the engine resolves idents of schema items
+                        // and of stored entities (including aliases) to ids
+                        // before coercion, so only ids are accepted here.
                         if let Ok(id) = uuid::Uuid::parse_str(strval) {
                             *self = Self::Id(id.into());
                             Ok(())
@@ -479,6 +601,12 @@ impl Value {
                     }),
                 }
             }
+            ValueType::Json => self.coerce_json(),
+            ValueType::GeoPoint => {
+                let point = super::GeoPoint::try_from(&*self)?;
+                *self = point.into();
+                Ok(())
+            }
             ValueType::EmbeddedEntity => match self {
                 Value::Map(_) => Ok(()),
                 other => Err(ValueCoercionError {
@@ -595,6 +723,60 @@ impl Value {
         }
     }
 
+    /// Normalize the value into a JSON compatible representation.
+    ///
+    /// Ids are converted to strings.
+    /// Bytes and maps with non-string keys are rejected.
+    fn coerce_json(&mut self) -> Result<(), ValueCoercionError> {
+        let err = |actual_type: ValueType, message: &str| ValueCoercionError {
+            expected_type: ValueType::Json,
+            actual_type,
+            path: None,
+            message: Some(message.to_string()),
+        };
+        match self {
+            Value::Id(id) => {
+                *self = Value::String(id.to_string());
+                Ok(())
+            }
+            Value::Bytes(_) => Err(err(
+                ValueType::Bytes,
+                "bytes can not be represented as JSON",
+            )),
+            Value::List(items) => items.iter_mut().try_for_each(Self::coerce_json),
+            Value::Map(map) => {
+                if let Some(key) = map.keys().find(|key| !key.is_string()) {
+                    return Err(err(key.value_type(), "JSON object keys must be strings"));
+                }
+                map.values_mut().try_for_each(Self::coerce_json)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Get a nested value in a map or list.
+    pub fn get_path(&self, path: &PatchPath) -> Option<&Value> {
+        path.0
+            .iter()
+            .try_fold(self, |value, elem| match (value, elem) {
+                (Value::Map(map), PatchPathElem::Key(key)) => map.get(&Value::from(key.as_str())),
+                (Value::List(items), PatchPathElem::ListIndex(index)) => items.get(*index),
+                _ => None,
+            })
+    }
+
+    /// Get a mutable nested value in a map or list.
+    pub fn get_path_mut(&mut self, path: &[PatchPathElem]) -> Option<&mut Value> {
+        path.iter()
+            .try_fold(self, |value, elem| match (value, elem) {
+                (Value::Map(map), PatchPathElem::Key(key)) => {
+                    map.get_mut(&Value::from(key.as_str()))
+                }
+                (Value::List(items), PatchPathElem::ListIndex(index)) => items.get_mut(*index),
+                _ => None,
+            })
+    }
+
     pub fn as_list(&self) -> Option<&[Value]> {
         if let Self::List(items) = self {
             Some(items)
@@ -928,7 +1110,13 @@ impl TryFrom<Value> for Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use crate::data::{from_value, from_value_map, to_value, to_value_map, Id, Value, ValueMap};
+    use crate::data::{
+        from_value, from_value_map,
+        patch::{PatchPath, PatchPathElem},
+        to_value, to_value_map,
+        value_type::{MapType, ObjectField, ObjectType},
+        Id, Value, ValueMap, ValueType,
+    };
 
     #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone)]
     struct TestData {
@@ -981,4 +1169,124 @@ mod tests {
         let x: Vec<u8> = from_value(Value::Bytes(vec![1, 2, 3])).unwrap();
         assert_eq!(x, vec![1, 2, 3]);
     }
+
+    fn map_value(items: Vec<(Value, Value)>) -> Value {
+        Value::Map(items.into_iter().collect())
+    }
+
+    #[test]
+    fn test_value_coerce_map() {
+        let ty = ValueType::Map(Box::new(MapType {
+            key: ValueType::String,
+            value: ValueType::Int,
+        }));
+
+        let mut value = map_value(vec![(1u64.into(), 2u64.into())]);
+        value.coerce_mut(&ty).unwrap();
+        assert_eq!(value, map_value(vec![("1".into(), Value::Int(2))]));
+
+        let mut value = map_value(vec![("a".into(), "x".into())]);
+        let err = value.coerce_mut(&ty).unwrap_err();
+        assert_eq!(
+            err.path,
+            Some(PatchPath(vec![PatchPathElem::Key("a".to_string())]))
+        );
+
+        let mut value = Value::Unit;
+        value.coerce_mut(&ty).unwrap();
+        assert_eq!(value, map_value(vec![]));
+    }
+
+    #[test]
+    fn test_value_coerce_object() {
+        let point = ValueType::Object(ObjectType {
+            name: None,
+            fields: vec![
+                ObjectField {
+                    name: "x".to_string(),
+                    value_type: ValueType::Int,
+                },
+                ObjectField {
+                    name: "tags".to_string(),
+                    value_type: ValueType::new_list(ValueType::String),
+                },
+            ],
+        });
+        let ty = ValueType::Object(ObjectType {
+            name: None,
+            fields: vec![ObjectField {
+                name: "points".to_string(),
+                value_type: ValueType::new_list(point),
+            }],
+        });
+
+        let mut value = map_value(vec![(
+            "points".into(),
+            Value::List(vec![map_value(vec![("x".into(), 1u64.into())])]),
+        )]);
+        value.coerce_mut(&ty).unwrap();
+        assert_eq!(
+            value,
+            map_value(vec![(
+                "points".into(),
+                Value::List(vec![map_value(vec![
+                    ("tags".into(), Value::List(vec![])),
+                    ("x".into(), Value::Int(1)),
+                ])]),
+            )])
+        );
+
+        let mut value = map_value(vec![(
+            "points".into(),
+            Value::List(vec![
+                map_value(vec![("x".into(), 1.into())]),
+                map_value(vec![("x".into(), "y".into())]),
+            ]),
+        )]);
+        let err = value.coerce_mut(&ty).unwrap_err();
+        assert_eq!(
+            err.path,
+            Some(PatchPath(vec![
+                PatchPathElem::Key("points".to_string()),
+                PatchPathElem::ListIndex(1),
+                PatchPathElem::Key("x".to_string()),
+            ]))
+        );
+        assert!(err.to_string().contains("at /points/1/x"));
+
+        let mut value = map_value(vec![("other".into(), 1.into())]);
+        let err = value.coerce_mut(&ty).unwrap_err();
+        assert_eq!(err.message.as_deref(), Some("unknown field"));
+
+        let mut value = map_value(vec![(
+            "points".into(),
+            Value::List(vec![map_value(vec![])]),
+        )]);
+        let err = value.coerce_mut(&ty).unwrap_err();
+        assert_eq!(err.message.as_deref(), Some("missing field"));
+
+        assert!(Value::from(1).coerce_mut(&ty).is_err());
+    }
+
+    #[test]
+    fn test_coerce_parse_error_messages() {
+        let mut value = Value::from("not a url");
+        let err = value.coerce_mut(&ValueType::Url).unwrap_err();
+        assert_eq!(
+            err.message(),
+            Some("invalid url 'not a url': relative URL without a base")
+        );
+
+        let mut value = Value::from("2022-13-01T00:00:00Z");
+        let err = value.coerce_mut(&ValueType::DateTime).unwrap_err();
+        let message = err.message().unwrap();
+        assert!(message.starts_with("invalid RFC3339 date time '2022-13-01T00:00:00Z': "));
+        assert!(err.to_string().ends_with(message));
+
+        let long = "x".repeat(100);
+        let mut value = Value::from(long.as_str());
+        let err = value.coerce_mut(&ValueType::DateTime).unwrap_err();
+        let excerpt = format!("'{}...'", "x".repeat(50));
+        assert!(err.message().unwrap().contains(&excerpt));
+    }
 }
@@ -31,6 +31,14 @@ pub enum ValueType {
     DateTime,
     /// Represented as Value::String
     Url,
+    /// Represented as a list of two floats (latitude, longitude).
+    /// See [`super::GeoPoint`].
+    GeoPoint,
+    /// Arbitrary JSON document.
+    ///
+    /// Nested values are not validated, but can be accessed in queries with
+    /// [`crate::query::expr::Expr::json_path`].
+    Json,
     /// Reference to an entity id (uuid).
     Ref,
     /// Reference to an entity using it's ident.
@@ -49,14 +57,27 @@ pub enum ValueType {
 #[cfg_attr(feature = "typescript-schema", ts(export))]
 pub struct ConstrainedRefType {
     pub allowed_entity_types: Vec<IdOrIdent>,
+    /// If set, references of the attribute must not form a cycle, like a
+    /// parent pointer that eventually points back to the entity itself.
+    ///
+    /// Writes are validated by following the attribute from the referenced
+    /// entity up to this many levels. Deeper cycles are not detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acyclic_depth: Option<u32>,
 }
 
 impl ConstrainedRefType {
     pub fn new(allowed_entity_types: Vec<IdOrIdent>) -> Self {
         Self {
             allowed_entity_types,
+            acyclic_depth: None,
         }
     }
+
+    pub fn with_acyclic_depth(mut self, max_depth: u32) -> Self {
+        self.acyclic_depth = Some(max_depth);
+        self
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
@@ -104,13 +125,14 @@ impl ValueType {
             | Self::Ref
             | Self::RefConstrained(_)
             | Self::Url
+            | Self::GeoPoint
             | Self::Map(..) => {
                 // TODO: this is probably not the right thing to do...
                 true
             }
             Self::Union(inner) => inner.iter().all(|t| t.is_scalar()),
             Self::Const(val) => val.value_type().is_scalar(),
-            Self::Any | Self::Unit | Self::List(_) | Self::EmbeddedEntity => false,
+            Self::Any | Self::Unit | Self::List(_) | Self::EmbeddedEntity | Self::Json => false,
             Self::Object(_) => false,
         }
     }
@@ -132,7 +154,7 @@ impl ValueType {
             Value::List(items) => Self::List(Box::new(Self::for_list(items.iter()))),
             Value::Map(map) => {
                 let key = Self::for_list(map.keys());
-                let value = Self::for_list(map.keys());
+                let value = Self::for_list(map.values());
                 Self::Map(Box::new(MapType { key, value }))
             }
             Value::Id(_) => Self::Ref,
@@ -161,34 +183,73 @@ impl ValueType {
 
 /// Trait that allows to statically determine the value type of a Rust type.
 pub trait ValueTypeDescriptor {
+    /// Compile time description of the value type, used by the
+    /// `factor_check_schema!` macro.
+    ///
+    /// `None` if the type can only be compared at runtime.
+    #[doc(hidden)]
+    const STATIC_TYPE: Option<StaticValueType> = None;
+
     fn value_type() -> ValueType;
 }
 
+/// Compile time description of a [`ValueType`] that consists of a scalar
+/// type nested in zero or more lists.
+///
+/// See [`ValueTypeDescriptor::STATIC_TYPE`].
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaticValueType {
+    /// Number of nested [`ValueType::List`]s.
+    pub list_depth: usize,
+    /// Name of the [`ValueType`] variant of the innermost type.
+    pub name: &'static str,
+}
+
+impl StaticValueType {
+    pub const fn scalar(name: &'static str) -> Option<Self> {
+        Some(Self {
+            list_depth: 0,
+            name,
+        })
+    }
+}
+
 impl ValueTypeDescriptor for bool {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Bool");
+
     fn value_type() -> ValueType {
         ValueType::Bool
     }
 }
 
 impl ValueTypeDescriptor for i8 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for i16 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for i32 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for i64 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
@@ -201,54 +262,86 @@ impl ValueTypeDescriptor for i64 {
 } */
 
 impl ValueTypeDescriptor for u16 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for u32 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for u64 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Int");
+
     fn value_type() -> ValueType {
         ValueType::Int
     }
 }
 
 impl ValueTypeDescriptor for f32 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Float");
+
     fn value_type() -> ValueType {
         ValueType::Float
     }
 }
 
 impl ValueTypeDescriptor for f64 {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Float");
+
     fn value_type() -> ValueType {
         ValueType::Float
     }
 }
 
 impl ValueTypeDescriptor for String {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("String");
+
     fn value_type() -> ValueType {
         ValueType::String
     }
 }
 
 impl<T: ValueTypeDescriptor> ValueTypeDescriptor for Vec<T> {
+    const STATIC_TYPE: Option<StaticValueType> = match T::STATIC_TYPE {
+        Some(item) => Some(StaticValueType {
+            list_depth: item.list_depth + 1,
+            name: item.name,
+        }),
+        None => None,
+    };
+
     fn value_type() -> ValueType {
         ValueType::List(Box::new(T::value_type()))
     }
 }
 
 impl ValueTypeDescriptor for super::Timestamp {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("DateTime");
+
     fn value_type() -> ValueType {
         ValueType::DateTime
     }
 }
 
+impl ValueTypeDescriptor for super::GeoPoint {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("GeoPoint");
+
+    fn value_type() -> ValueType {
+        ValueType::GeoPoint
+    }
+}
+
 impl ValueTypeDescriptor for url::Url {
+    const STATIC_TYPE: Option<StaticValueType> = StaticValueType::scalar("Url");
+
     fn value_type() -> ValueType {
         ValueType::Url
     }
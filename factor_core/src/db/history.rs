@@ -0,0 +1,111 @@
+use crate::data::{patch::Patch, DataMap, Timestamp};
+
+/// A point in the history of a database.
+///
+/// See [`super::Db::entity_at`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HistoryPoint {
+    /// The state after the given version (log event id).
+    Version(u64),
+    /// The state at the given time.
+    Time(Timestamp),
+}
+
+impl From<u64> for HistoryPoint {
+    fn from(v: u64) -> Self {
+        Self::Version(v)
+    }
+}
+
+impl From<Timestamp> for HistoryPoint {
+    fn from(v: Timestamp) -> Self {
+        Self::Time(v)
+    }
+}
+
+/// A single version of an entity.
+///
+/// See [`super::Db::history`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+pub struct EntityVersion {
+    /// Version that produced this state.
+    /// For log based backends this is the id of the log event.
+    pub version: u64,
+    /// Time of the modification, if known.
+    pub time: Option<Timestamp>,
+    /// The entity data after the modification.
+    /// `None` if the entity was deleted.
+    pub data: Option<DataMap>,
+    /// Patch that transforms the previous version into this one.
+    pub diff: Patch,
+}
+
+impl EntityVersion {
+    pub fn new(
+        version: u64,
+        time: Option<Timestamp>,
+        previous: Option<&DataMap>,
+        data: Option<DataMap>,
+    ) -> Self {
+        let diff = diff_attributes(previous, data.as_ref());
+        Self {
+            version,
+            time,
+            data,
+            diff,
+        }
+    }
+
+    /// Returns true if the version is visible at the given point.
+    pub fn is_before(&self, point: HistoryPoint) -> bool {
+        match point {
+            HistoryPoint::Version(version) => self.version <= version,
+            HistoryPoint::Time(time) => self.time.map(|t| t <= time).unwrap_or(true),
+        }
+    }
+}
+
+/// Build a patch with one operation for each top-level attribute that
+/// differs between `old` and `new`.
+fn diff_attributes(old: Option<&DataMap>, new: Option<&DataMap>) -> Patch {
+    let mut patch = Patch::new();
+    if let Some(old) = old {
+        for key in old.keys() {
+            if new.map(|new| !new.contains_key(key)).unwrap_or(true) {
+                patch = patch.remove(key);
+            }
+        }
+    }
+    if let Some(new) = new {
+        for (key, value) in new.iter() {
+            patch = match old.and_then(|old| old.get(key)) {
+                Some(old_value) if old_value == value => continue,
+                Some(_) => patch.replace(key, value.clone()),
+                None => patch.add(key, value.clone()),
+            };
+        }
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map;
+
+    use super::*;
+
+    #[test]
+    fn test_entity_version_diff() {
+        let old = map! { "a": 1, "b": 2 };
+        let new = map! { "b": 3, "c": 4 };
+        let version = EntityVersion::new(1, None, Some(&old), Some(new.clone()));
+        assert_eq!(
+            version.diff,
+            Patch::new().remove("a").replace("b", 3).add("c", 4)
+        );
+        assert_eq!(version.diff.clone().apply_map(old).unwrap(), new);
+
+        let deleted = EntityVersion::new(2, None, Some(&new), None);
+        assert_eq!(deleted.diff, Patch::new().remove("b").remove("c"));
+    }
+}
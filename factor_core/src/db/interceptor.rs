@@ -0,0 +1,429 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    data::{DataMap, Id, IdOrIdent, Value},
+    query::{
+        graph::GraphQuery,
+        migrate::Migration,
+        mutate::{Batch, BatchReport},
+        select::{Item, Page, Select},
+    },
+    schema,
+};
+
+use super::{ClassStats, DbClient, DbFuture, EntityVersion, HealthReport};
+
+/// Hooks that run before and after operations of a [`DbClient`].
+///
+/// Used for cross-cutting concerns like authorization, metrics, caching or
+/// logging. Interceptors are added with [`super::Db::with_interceptor`].
+///
+/// `before_*` hooks can modify the request and abort the operation by
+/// returning an error. `after_*` hooks can inspect and modify the result.
+///
+/// All methods have no-op default implementations.
+pub trait Interceptor: Send + Sync {
+    fn before_entity(&self, _id: &mut IdOrIdent) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_entity(&self, _id: &IdOrIdent, _result: &mut Result<Option<DataMap>, anyhow::Error>) {}
+
+    /// Runs before a select.
+    ///
+    /// Returning a page skips the select, which allows serving results from
+    /// a cache. `after_select` is not called in that case.
+    ///
+    /// Also applies to [`DbClient::select_map`].
+    fn before_select(&self, _query: &mut Select) -> Result<Option<Page<Item>>, anyhow::Error> {
+        Ok(None)
+    }
+
+    fn after_select(&self, _query: &Select, _result: &mut Result<Page<Item>, anyhow::Error>) {}
+
+    /// Runs before a batch.
+    ///
+    /// Also applies to [`DbClient::batch_with_savepoints`].
+    fn before_batch(&self, _batch: &mut Batch) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_batch(&self, _batch: &Batch, _result: &mut Result<(), anyhow::Error>) {}
+
+    fn after_batch_with_savepoints(
+        &self,
+        _batch: &Batch,
+        _result: &mut Result<BatchReport, anyhow::Error>,
+    ) {
+    }
+
+    fn before_migrate(&self, _migration: &mut Migration) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_migrate(&self, _migration: &Migration, _result: &mut Result<(), anyhow::Error>) {}
+
+    fn before_purge_all_data(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_purge_all_data(&self, _result: &mut Result<(), anyhow::Error>) {}
+
+    fn before_select_view(&self, _name: &mut String) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_select_view(&self, _name: &str, _result: &mut Result<Page<Item>, anyhow::Error>) {}
+
+    fn before_shortest_path(&self, _query: &mut GraphQuery) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn after_shortest_path(
+        &self,
+        _query: &GraphQuery,
+        _result: &mut Result<Option<Vec<Id>>, anyhow::Error>,
+    ) {
+    }
+}
+
+/// A [`DbClient`] that runs an [`Interceptor`] around an inner client.
+///
+/// Stacking interceptors nests clients, so the interceptor added last runs
+/// first.
+pub struct InterceptedClient {
+    inner: Arc<dyn DbClient + Send + Sync + 'static>,
+    interceptor: Arc<dyn Interceptor + 'static>,
+}
+
+impl InterceptedClient {
+    pub fn new(
+        inner: Arc<dyn DbClient + Send + Sync + 'static>,
+        interceptor: Arc<dyn Interceptor + 'static>,
+    ) -> Self {
+        Self { inner, interceptor }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn DbClient + Send + Sync + 'static> {
+        &self.inner
+    }
+
+    async fn run_select(&self, mut query: Select) -> Result<Page<Item>, anyhow::Error> {
+        if let Some(page) = self.interceptor.before_select(&mut query)? {
+            return Ok(page);
+        }
+        let mut res = self.inner.select(query.clone()).await;
+        self.interceptor.after_select(&query, &mut res);
+        res
+    }
+}
+
+impl DbClient for InterceptedClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn inner_client(&self) -> Option<&(dyn DbClient + Send + Sync + 'static)> {
+        Some(&*self.inner)
+    }
+
+    fn with_auth_variables(
+        &self,
+        variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        let inner = self.inner.with_auth_variables(variables)?;
+        Some(Arc::new(Self::new(inner, self.interceptor.clone())))
+    }
+
+    fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+        self.inner.schema()
+    }
+
+    fn entity(&self, mut id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        Box::pin(async move {
+            self.interceptor.before_entity(&mut id)?;
+            let mut res = self.inner.entity(id.clone()).await;
+            self.interceptor.after_entity(&id, &mut res);
+            res
+        })
+    }
+
+    /// Runs the entity hooks for each id.
+    fn entities(
+        &self,
+        mut ids: Vec<IdOrIdent>,
+    ) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        Box::pin(async move {
+            for id in &mut ids {
+                self.interceptor.before_entity(id)?;
+            }
+            let mut entities = self.inner.entities(ids).await?;
+            for (id, data) in entities.iter_mut() {
+                let mut res = Ok(data.take());
+                self.interceptor.after_entity(id, &mut res);
+                *data = res?;
+            }
+            Ok(entities)
+        })
+    }
+
+    fn select(&self, query: Select) -> DbFuture<'_, Page<Item>> {
+        Box::pin(self.run_select(query))
+    }
+
+    /// Implemented with [`DbClient::select`] so select interceptors apply.
+    fn select_map(&self, query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        Box::pin(async move { self.run_select(query).await.map(Page::take_data) })
+    }
+
+    fn batch(&self, mut batch: Batch) -> DbFuture<'_, ()> {
+        Box::pin(async move {
+            self.interceptor.before_batch(&mut batch)?;
+            let mut res = self.inner.batch(batch.clone()).await;
+            self.interceptor.after_batch(&batch, &mut res);
+            res
+        })
+    }
+
+    fn batch_with_savepoints(&self, mut batch: Batch) -> DbFuture<'_, BatchReport> {
+        Box::pin(async move {
+            self.interceptor.before_batch(&mut batch)?;
+            let mut res = self.inner.batch_with_savepoints(batch.clone()).await;
+            self.interceptor
+                .after_batch_with_savepoints(&batch, &mut res);
+            res
+        })
+    }
+
+    fn migrate(&self, mut migration: Migration) -> DbFuture<'_, ()> {
+        Box::pin(async move {
+            self.interceptor.before_migrate(&mut migration)?;
+            let mut res = self.inner.migrate(migration.clone()).await;
+            self.interceptor.after_migrate(&migration, &mut res);
+            res
+        })
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        self.inner.migrations()
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        self.inner.storage_usage()
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        Box::pin(async move {
+            self.interceptor.before_purge_all_data()?;
+            let mut res = self.inner.purge_all_data().await;
+            self.interceptor.after_purge_all_data(&mut res);
+            res
+        })
+    }
+
+    /// Runs [`Interceptor::before_entity`], so entity access checks also
+    /// apply to the history.
+    fn entity_history(&self, id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        Box::pin(async move {
+            let mut target = IdOrIdent::from(id);
+            self.interceptor.before_entity(&mut target)?;
+            let id = target
+                .as_id()
+                .ok_or_else(|| anyhow::anyhow!("Entity history requires an id"))?;
+            self.inner.entity_history(id).await
+        })
+    }
+
+    fn class_stats(&self) -> DbFuture<'_, Vec<ClassStats>> {
+        self.inner.class_stats()
+    }
+
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        self.inner.health()
+    }
+
+    fn select_view(&self, mut name: String) -> DbFuture<'_, Page<Item>> {
+        Box::pin(async move {
+            self.interceptor.before_select_view(&mut name)?;
+            let mut res = self.inner.select_view(name.clone()).await;
+            self.interceptor.after_select_view(&name, &mut res);
+            res
+        })
+    }
+
+    fn shortest_path(&self, mut query: GraphQuery) -> DbFuture<'_, Option<Vec<Id>>> {
+        Box::pin(async move {
+            self.interceptor.before_shortest_path(&mut query)?;
+            let mut res = self.inner.shortest_path(query.clone()).await;
+            self.interceptor.after_shortest_path(&query, &mut res);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{data::Id, db::Db, query::mutate::Mutate};
+
+    use super::*;
+
+    /// Client that accepts all batches and returns empty pages.
+    struct NoopClient;
+
+    impl DbClient for NoopClient {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+            Box::pin(async { Ok(schema::DbSchema::default()) })
+        }
+
+        fn entity(&self, _id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn select(&self, _query: Select) -> DbFuture<'_, Page<Item>> {
+            Box::pin(async { Ok(Page::new()) })
+        }
+
+        fn select_map(&self, _query: Select) -> DbFuture<'_, Vec<DataMap>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn batch(&self, _batch: Batch) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn purge_all_data(&self) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+        deny_batches: bool,
+    }
+
+    impl Interceptor for Recorder {
+        fn before_select(&self, query: &mut Select) -> Result<Option<Page<Item>>, anyhow::Error> {
+            query.limit = 10;
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}:before_select", self.name));
+            Ok(None)
+        }
+
+        fn after_select(&self, query: &Select, _result: &mut Result<Page<Item>, anyhow::Error>) {
+            assert_eq!(query.limit, 10);
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}:after_select", self.name));
+        }
+
+        fn before_batch(&self, _batch: &mut Batch) -> Result<(), anyhow::Error> {
+            if self.deny_batches {
+                anyhow::bail!("batches are not allowed");
+            }
+            Ok(())
+        }
+
+        fn before_purge_all_data(&self) -> Result<(), anyhow::Error> {
+            if self.deny_batches {
+                anyhow::bail!("purging is not allowed");
+            }
+            Ok(())
+        }
+
+        fn before_select_view(&self, _name: &mut String) -> Result<(), anyhow::Error> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}:before_select_view", self.name));
+            Ok(())
+        }
+
+        fn after_select_view(&self, _name: &str, _result: &mut Result<Page<Item>, anyhow::Error>) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}:after_select_view", self.name));
+        }
+
+        fn before_shortest_path(&self, _query: &mut GraphQuery) -> Result<(), anyhow::Error> {
+            anyhow::bail!("graph queries are not allowed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptor_order_and_abort() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let db = Db::new(NoopClient)
+            .with_interceptor(Recorder {
+                name: "inner",
+                calls: calls.clone(),
+                deny_batches: true,
+            })
+            .with_interceptor(Recorder {
+                name: "outer",
+                calls: calls.clone(),
+                deny_batches: false,
+            });
+
+        db.select_map(Select::new()).await.unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "outer:before_select",
+                "inner:before_select",
+                "inner:after_select",
+                "outer:after_select",
+            ]
+        );
+
+        let err = db.mutate(Mutate::delete(Id::random())).await.unwrap_err();
+        assert_eq!(err.to_string(), "batches are not allowed");
+
+        let err = db.purge_all_data().await.unwrap_err();
+        assert_eq!(err.to_string(), "purging is not allowed");
+
+        // The after hooks also run when the operation fails.
+        calls.lock().unwrap().clear();
+        db.select_view("view").await.unwrap_err();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "outer:before_select_view",
+                "inner:before_select_view",
+                "inner:after_select_view",
+                "outer:after_select_view",
+            ]
+        );
+
+        let err = db
+            .shortest_path(GraphQuery::new(Id::random(), Id::random(), "test/ref", 10))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "graph queries are not allowed");
+
+        assert!(db.client().as_any().is::<InterceptedClient>());
+        assert!(db.find_client::<NoopClient>().is_some());
+    }
+}
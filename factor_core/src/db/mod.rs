@@ -0,0 +1,684 @@
+mod history;
+mod interceptor;
+mod read_only;
+mod retry;
+mod session;
+mod tenant;
+
+pub use self::{
+    history::{EntityVersion, HistoryPoint},
+    interceptor::{InterceptedClient, Interceptor},
+    read_only::ReadOnlyClient,
+    retry::RetryPolicy,
+    session::Session,
+    tenant::TenantClient,
+};
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::{stream::BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    data::{patch::Patch, value::to_value_map, DataMap, Id, IdOrIdent, Value},
+    error::{EntityNotFound, Error},
+    query::{
+        self,
+        expr::Expr,
+        migrate::Migration,
+        mutate::{ActionStatus, Batch, BatchReport, Mutate},
+        select::Page,
+    },
+    schema::{
+        self,
+        builtin::{AttrId, AttrType},
+        AttrMapExt, AttributeMeta, ClassContainer, ClassMeta,
+    },
+};
+
+/// Configuration for a [`Db`].
+#[derive(Clone, Debug)]
+pub struct DbConfig {
+    /// Policy for retrying operations that failed with a
+    /// [`crate::error::TransientError`].
+    ///
+    /// Disabled by default, see [`RetryPolicy::default`] for a policy that
+    /// retries.
+    pub retry_policy: RetryPolicy,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::none(),
+        }
+    }
+}
+
+/// Entity count of a class, returned by [`Db::class_stats`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ClassStats {
+    /// Class ident.
+    pub class: String,
+    /// Number of entities of the class.
+    /// Entities of child classes are not included.
+    pub entities: u64,
+    /// See [`schema::Class::max_entities`].
+    pub max_entities: Option<u64>,
+}
+
+/// Health of a database, returned by [`Db::health`].
+///
+/// Meant for liveness and readiness probes of orchestration systems.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Whether the backing storage can be accessed.
+    /// Always true for in-memory databases.
+    pub storage_reachable: bool,
+    /// Milliseconds since a write was last persisted to the storage.
+    /// `None` for in-memory databases, or if nothing was written yet.
+    pub last_sync_age_ms: Option<u64>,
+    /// Number of batches waiting to be applied.
+    pub pending_batches: u64,
+    /// Whether the schema is fully loaded.
+    /// False while a database is restored from storage.
+    pub registry_loaded: bool,
+}
+
+impl HealthReport {
+    /// Whether the database can serve requests.
+    pub fn is_healthy(&self) -> bool {
+        self.storage_reachable && self.registry_loaded
+    }
+}
+
+#[derive(Clone)]
+pub struct Db {
+    client: Arc<dyn DbClient + Send + Sync + 'static>,
+}
+
+impl Db {
+    /// Page size used by [`Self::select_pages`] for queries without a limit.
+    pub const DEFAULT_PAGE_SIZE: u64 = 100;
+
+    /// Create a new db with the default [`DbConfig`].
+    pub fn new<D>(client: D) -> Self
+    where
+        D: DbClient + Sync + Send + 'static,
+    {
+        Self::with_config(client, DbConfig::default())
+    }
+
+    pub fn with_config<D>(client: D, config: DbConfig) -> Self
+    where
+        D: DbClient + Sync + Send + 'static,
+    {
+        let mut client: Arc<dyn DbClient + Send + Sync + 'static> = Arc::new(client);
+        if config.retry_policy.is_enabled() {
+            client = Arc::new(retry::RetryClient::new(client, config.retry_policy));
+        }
+        Self { client }
+    }
+
+    pub fn client(&self) -> &Arc<dyn DbClient + Send + Sync + 'static> {
+        &self.client
+    }
+
+    /// Find a client of type `T`, looking through wrapping clients like
+    /// [`InterceptedClient`].
+    pub fn find_client<T: 'static>(&self) -> Option<&T> {
+        let mut client: &(dyn DbClient + Send + Sync + 'static) = &*self.client;
+        loop {
+            if let Some(found) = client.as_any().downcast_ref::<T>() {
+                return Some(found);
+            }
+            client = client.inner_client()?;
+        }
+    }
+
+    /// Wrap the client with an [`Interceptor`].
+    ///
+    /// Interceptors can be stacked. The interceptor added last runs first.
+    pub fn with_interceptor<I>(self, interceptor: I) -> Self
+    where
+        I: Interceptor + 'static,
+    {
+        Self {
+            client: Arc::new(InterceptedClient::new(self.client, Arc::new(interceptor))),
+        }
+    }
+
+    /// Create a handle that rejects all modifications.
+    ///
+    /// Batches, migrations and [`Self::purge_all_data`] fail with a
+    /// [`crate::error::ReadOnly`] error.
+    pub fn read_only(&self) -> Self {
+        Self {
+            client: Arc::new(read_only::ReadOnlyClient::new(self.client.clone())),
+        }
+    }
+
+    /// Create a handle scoped to a single tenant.
+    ///
+    /// All selects are restricted to entities whose `factor/tenant` matches
+    /// the given id, and all created entities are assigned to the tenant.
+    /// Entities of other tenants behave as if they did not exist.
+    ///
+    /// The tenant does not need to exist as an entity.
+    /// Migrations must be applied through an unscoped handle.
+    ///
+    /// See [`schema::builtin::AttrTenant`].
+    pub fn with_tenant(&self, tenant: Id) -> Self {
+        Self {
+            client: Arc::new(tenant::TenantClient::new(self.client.clone(), tenant)),
+        }
+    }
+
+    /// Create a handle that runs all operations on behalf of a caller.
+    ///
+    /// The variables are available in policy filters, eg. `$current_user`.
+    /// Wrapping clients like interceptors and read-only handles are kept.
+    ///
+    /// Returns `None` if the client does not support policies.
+    /// See [`DbClient::with_auth_variables`].
+    pub fn with_auth_variables(&self, variables: &HashMap<String, Value>) -> Option<Self> {
+        let client = self.client.with_auth_variables(variables)?;
+        Some(Self { client })
+    }
+
+    /// Start a [`Session`] with read-your-writes consistency.
+    pub fn session(&self) -> Session {
+        Session::new(self.clone())
+    }
+
+    /// Retrieve the full database schema.
+    pub async fn schema(&self) -> Result<schema::DbSchema, Error> {
+        Ok(self.client.schema().await?)
+    }
+
+    /// Select a single entity by its id or ident.
+    pub async fn entity<I>(&self, id: I) -> Result<DataMap, Error>
+    where
+        I: Into<IdOrIdent>,
+    {
+        let id = id.into();
+        self.client
+            .entity(id.clone())
+            .await?
+            .ok_or_else(|| Error::NotFound(EntityNotFound::new(id)))
+    }
+
+    /// Load multiple entities by id or ident.
+    ///
+    /// The returned map contains an entry for every requested id, with
+    /// `None` for entities that do not exist.
+    pub async fn get_many<I>(
+        &self,
+        ids: impl IntoIterator<Item = I>,
+    ) -> Result<HashMap<IdOrIdent, Option<DataMap>>, Error>
+    where
+        I: Into<IdOrIdent>,
+    {
+        let ids = ids.into_iter().map(Into::into).collect();
+        Ok(self.client.entities(ids).await?)
+    }
+
+    /// Query entities.
+    pub async fn select(
+        &self,
+        query: query::select::Select,
+    ) -> Result<query::select::Page<query::select::Item>, Error> {
+        Ok(self.client.select(query).await?)
+    }
+
+    /// Query entities page by page.
+    ///
+    /// Follows the cursor returned by the backend, or advances the offset if
+    /// there is none, until a page is not full. Queries without a limit use
+    /// pages of [`Self::DEFAULT_PAGE_SIZE`].
+    ///
+    /// Offset based pagination can skip or repeat entities if the data
+    /// changes concurrently.
+    pub fn select_pages(
+        &self,
+        mut query: query::select::Select,
+    ) -> BoxStream<'static, Result<Page<query::select::Item>, Error>> {
+        if query.limit == 0 {
+            query.limit = Self::DEFAULT_PAGE_SIZE;
+        }
+        let db = self.clone();
+        futures::stream::try_unfold(Some(query), move |query| {
+            let db = db.clone();
+            async move {
+                let mut query = match query {
+                    Some(query) => query,
+                    None => return Ok(None),
+                };
+                let page = db.select(query.clone()).await?;
+                if page.items.is_empty() {
+                    return Ok(None);
+                }
+
+                let next = if let Some(cursor) = page.next_cursor {
+                    query.cursor = Some(cursor);
+                    Some(query)
+                } else if page.items.len() as u64 >= query.limit {
+                    query.offset += page.items.len() as u64;
+                    Some(query)
+                } else {
+                    None
+                };
+                Ok(Some((page, next)))
+            }
+        })
+        .boxed()
+    }
+
+    pub async fn select_map(&self, query: query::select::Select) -> Result<Vec<DataMap>, Error> {
+        Ok(self.client.select_map(query).await?)
+    }
+
+    /// Read a materialized view by name.
+    ///
+    /// See [`crate::schema::ViewSchema`].
+    pub async fn select_view(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<query::select::Page<query::select::Item>, Error> {
+        Ok(self.client.select_view(name.into()).await?)
+    }
+
+    /// Find the shortest path between two entities.
+    ///
+    /// Returns the ids of all entities on the path, including the start and
+    /// the end, or `None` if no path within the maximum depth exists.
+    ///
+    /// See [`query::graph::GraphQuery`].
+    pub async fn shortest_path(
+        &self,
+        query: query::graph::GraphQuery,
+    ) -> Result<Option<Vec<Id>>, Error> {
+        Ok(self.client.shortest_path(query).await?)
+    }
+
+    // Mutate.
+
+    pub async fn batch(&self, batch: Batch) -> Result<(), Error> {
+        Ok(self.client.batch(batch).await?)
+    }
+
+    /// Apply a batch with partial rollback at savepoints.
+    ///
+    /// The batch is split at [`Mutate::Savepoint`] markers, and each segment
+    /// is applied atomically.
+    /// If a segment fails, only its own actions are rolled back, and the
+    /// following segments are still applied.
+    ///
+    /// Useful for large imports that should skip invalid records.
+    pub async fn batch_with_savepoints(&self, batch: Batch) -> Result<BatchReport, Error> {
+        Ok(self.client.batch_with_savepoints(batch).await?)
+    }
+
+    pub async fn create(&self, id: Id, data: DataMap) -> Result<(), Error> {
+        self.batch(Mutate::create(id, data).into()).await
+    }
+
+    pub async fn create_entity<E: ClassContainer + serde::Serialize>(
+        &self,
+        entity: E,
+    ) -> Result<(), Error> {
+        let id = entity.id();
+        let data = entity.into_map().map_err(|err| Error::Other(err.into()))?;
+        self.create(id, data).await
+    }
+
+    // Typed access.
+
+    /// Load an entity of class `T` by id.
+    ///
+    /// Returns `None` if the entity does not exist or is not of class `T`
+    /// (or a class that extends `T`).
+    pub async fn find<T>(&self, id: Id) -> Result<Option<T>, Error>
+    where
+        T: ClassMeta + ClassContainer + DeserializeOwned,
+    {
+        let filter = Expr::eq(AttrId::expr(), id);
+        let mut items = self.find_by_select::<T>(filter, Some(1)).await?;
+        Ok(items.pop())
+    }
+
+    /// Load all entities of class `T` (or classes that extend `T`) that
+    /// match the filter.
+    pub async fn find_by<T>(&self, filter: Expr) -> Result<Vec<T>, Error>
+    where
+        T: ClassMeta + ClassContainer + DeserializeOwned,
+    {
+        self.find_by_select(filter, None).await
+    }
+
+    async fn find_by_select<T>(&self, filter: Expr, limit: Option<u64>) -> Result<Vec<T>, Error>
+    where
+        T: ClassMeta + ClassContainer + DeserializeOwned,
+    {
+        let mut select = query::select::Select::new()
+            .with_filter(Expr::and(Expr::is_entity_nested::<T>(), filter));
+        if let Some(limit) = limit {
+            select = select.with_limit(limit);
+        }
+
+        self.select_map(select)
+            .await?
+            .into_iter()
+            .map(Self::entity_from_map)
+            .collect()
+    }
+
+    /// Query entities of class `T` (or classes that extend `T`).
+    ///
+    /// The class filter is added to the filter of the query.
+    pub async fn select_as<T>(&self, mut select: query::select::Select) -> Result<Page<T>, Error>
+    where
+        T: ClassMeta + ClassContainer + DeserializeOwned,
+    {
+        let class_filter = Expr::is_entity_nested::<T>();
+        select.filter = Some(match select.filter.take() {
+            Some(filter) => Expr::and(class_filter, filter),
+            None => class_filter,
+        });
+
+        let page = self.select(select).await?;
+        let items = page
+            .items
+            .into_iter()
+            .map(|item| Self::entity_from_map(item.data))
+            .collect::<Result<Vec<T>, _>>()?;
+        Ok(Page {
+            items,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    fn entity_from_map<T>(data: DataMap) -> Result<T, Error>
+    where
+        T: ClassMeta + ClassContainer + DeserializeOwned,
+    {
+        let id = data.get_id();
+        T::try_from_map(data).map_err(|err| {
+            Error::Other(anyhow::Error::from(err).context(format!(
+                "Could not deserialize entity {} as {}",
+                id.map(|id| id.to_string()).unwrap_or_default(),
+                T::QUALIFIED_NAME
+            )))
+        })
+    }
+
+    /// Create a new entity from a class value.
+    ///
+    /// Unlike [`Self::create_entity`], this does not consume the value.
+    pub async fn create_typed<T>(&self, entity: &T) -> Result<(), Error>
+    where
+        T: ClassContainer + serde::Serialize,
+    {
+        self.create(entity.id(), Self::typed_data_map(entity)?)
+            .await
+    }
+
+    /// Update an existing entity with the values of a class value.
+    ///
+    /// Attributes that are not part of the class are kept.
+    pub async fn update_typed<T>(&self, entity: &T) -> Result<(), Error>
+    where
+        T: ClassContainer + serde::Serialize,
+    {
+        self.merge(entity.id(), Self::typed_data_map(entity)?).await
+    }
+
+    fn typed_data_map<T>(entity: &T) -> Result<DataMap, anyhow::Error>
+    where
+        T: ClassContainer + serde::Serialize,
+    {
+        let mut data: DataMap = to_value_map(entity)?;
+        data.insert_attr::<AttrType>(entity.entity_type());
+        Ok(data)
+    }
+
+    pub async fn mutate(&self, mutate: Mutate) -> Result<(), Error> {
+        self.batch(mutate.into()).await
+    }
+
+    pub async fn replace(&self, id: Id, data: DataMap) -> Result<(), Error> {
+        self.batch(Mutate::replace(id, data).into()).await
+    }
+
+    pub async fn merge(&self, id: Id, data: DataMap) -> Result<(), Error> {
+        self.batch(Mutate::merge(id, data).into()).await
+    }
+
+    pub async fn patch(&self, id: Id, patch: Patch) -> Result<(), Error> {
+        self.batch(Mutate::patch(id, patch).into()).await
+    }
+
+    pub async fn delete(&self, id: Id) -> Result<(), Error> {
+        self.batch(Mutate::delete(id).into()).await
+    }
+
+    /// Execute a SQL statement.
+    ///
+    /// Supported statements are SELECT, UPDATE and DELETE.
+    pub async fn sql(
+        &self,
+        sql: String,
+    ) -> Result<query::select::Page<query::select::Item>, Error> {
+        let query = crate::query::sql::parse_sql(&sql).map_err(|err| Error::Other(err.into()))?;
+        match query {
+            query::sql::ParsedSqlQuery::Select(sel) => self.select(sel).await,
+            query::sql::ParsedSqlQuery::Mutate(m) => {
+                self.mutate(Mutate::Select(m)).await?;
+                // TODO: support selections/returning?
+                Ok(Page::new())
+            }
+        }
+    }
+
+    /// Run a migration.
+    pub async fn migrate(&self, migration: query::migrate::Migration) -> Result<(), Error> {
+        self.client.migrate(migration).await.map_err(Error::schema)
+    }
+
+    pub async fn migrations(&self) -> Result<Vec<Migration>, Error> {
+        Ok(self.client.migrations().await?)
+    }
+
+    pub async fn storage_usage(&self) -> Result<Option<u64>, Error> {
+        Ok(self.client.storage_usage().await?)
+    }
+
+    /// Retrieve the live entity counts of all classes, sorted by class
+    /// ident.
+    pub async fn class_stats(&self) -> Result<Vec<ClassStats>, Error> {
+        Ok(self.client.class_stats().await?)
+    }
+
+    /// Check the health of the database.
+    ///
+    /// See [`HealthReport`].
+    pub async fn health(&self) -> Result<HealthReport, Error> {
+        Ok(self.client.health().await?)
+    }
+
+    /// Retrieve all versions of an entity, oldest first.
+    ///
+    /// Each version contains the full entity data and a patch with the
+    /// changes to the previous version.
+    /// Only supported by backends that keep history, like the log backend.
+    pub async fn history(&self, id: Id) -> Result<Vec<EntityVersion>, Error> {
+        Ok(self.client.entity_history(id).await?)
+    }
+
+    /// Load an entity as it was at the given version or time.
+    ///
+    /// Returns `None` if the entity did not exist at that point.
+    ///
+    /// See [`Self::history`].
+    pub async fn entity_at(
+        &self,
+        id: Id,
+        at: impl Into<HistoryPoint>,
+    ) -> Result<Option<DataMap>, Error> {
+        let at = at.into();
+        let history = self.client.entity_history(id).await?;
+        Ok(history
+            .into_iter()
+            .take_while(|version| version.is_before(at))
+            .last()
+            .and_then(|version| version.data))
+    }
+
+    /// Delete all data.
+    pub async fn purge_all_data(&self) -> Result<(), Error> {
+        Ok(self.client.purge_all_data().await?)
+    }
+}
+
+pub type DbFuture<'a, T> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, anyhow::Error>> + Send + 'a>>;
+
+pub trait DbClient {
+    fn as_any(&self) -> &dyn std::any::Any;
+
+    /// The client wrapped by this client, if any.
+    ///
+    /// Implemented by wrapping clients, so [`Db::find_client`] can reach the
+    /// clients below them.
+    fn inner_client(&self) -> Option<&(dyn DbClient + Send + Sync + 'static)> {
+        None
+    }
+
+    /// Build a client that runs all operations on behalf of a caller, with
+    /// the given variables available in policy filters.
+    ///
+    /// Wrapping clients must rebuild themselves around the client returned
+    /// by their inner client.
+    ///
+    /// Returns `None` by default, for clients that do not support policies.
+    fn with_auth_variables(
+        &self,
+        _variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        None
+    }
+
+    fn schema(&self) -> DbFuture<'_, schema::DbSchema>;
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>>;
+
+    /// Load multiple entities at once.
+    /// Missing entities are mapped to `None`.
+    ///
+    /// The default implementation loads the entities one by one.
+    /// Clients should override it if they can load multiple entities in a
+    /// single round trip.
+    fn entities(&self, ids: Vec<IdOrIdent>) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        // The futures are created upfront so the returned future does not
+        // borrow `self`, which is not required to be `Sync`.
+        let futures = ids
+            .into_iter()
+            .map(|id| (id.clone(), self.entity(id)))
+            .collect::<Vec<_>>();
+        Box::pin(async move {
+            let mut entities = HashMap::with_capacity(futures.len());
+            for (id, future) in futures {
+                entities.insert(id, future.await?);
+            }
+            Ok(entities)
+        })
+    }
+
+    fn select(
+        &self,
+        query: query::select::Select,
+    ) -> DbFuture<'_, query::select::Page<query::select::Item>>;
+
+    fn select_map(&self, query: query::select::Select) -> DbFuture<'_, Vec<DataMap>>;
+
+    fn batch(&self, batch: Batch) -> DbFuture<'_, ()>;
+
+    /// Apply a batch with partial rollback at savepoints.
+    /// See [`Db::batch_with_savepoints`].
+    ///
+    /// The default implementation applies each segment with [`Self::batch`].
+    fn batch_with_savepoints(&self, batch: Batch) -> DbFuture<'_, BatchReport> {
+        let segments = batch
+            .into_segments()
+            .into_iter()
+            .map(|segment| {
+                let len = segment.actions.len();
+                let future = if segment.actions.is_empty() {
+                    None
+                } else {
+                    Some(self.batch(segment))
+                };
+                (len, future)
+            })
+            .collect::<Vec<_>>();
+        Box::pin(async move {
+            let mut actions = Vec::new();
+            for (index, (len, future)) in segments.into_iter().enumerate() {
+                // The savepoint marker preceding the segment.
+                if index > 0 {
+                    actions.push(ActionStatus::Applied);
+                }
+                let status = match future {
+                    Some(future) => match future.await {
+                        Ok(()) => ActionStatus::Applied,
+                        Err(err) => ActionStatus::RolledBack {
+                            error: format!("{:#}", err),
+                        },
+                    },
+                    None => ActionStatus::Applied,
+                };
+                actions.extend(std::iter::repeat(status).take(len));
+            }
+            Ok(BatchReport { actions })
+        })
+    }
+
+    fn migrate(&self, migration: query::migrate::Migration) -> DbFuture<'_, ()>;
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>>;
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>>;
+    fn purge_all_data(&self) -> DbFuture<'_, ()>;
+
+    /// Load all versions of an entity, oldest first.
+    ///
+    /// Fails by default, since most clients do not keep history.
+    fn entity_history(&self, _id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        Box::pin(async { Err(anyhow::anyhow!("Entity history is not supported")) })
+    }
+
+    /// Retrieve per-class entity counts.
+    ///
+    /// Fails by default.
+    fn class_stats(&self) -> DbFuture<'_, Vec<ClassStats>> {
+        Box::pin(async { Err(anyhow::anyhow!("Class stats are not supported")) })
+    }
+
+    /// Check the health of the database.
+    ///
+    /// Fails by default.
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        Box::pin(async { Err(anyhow::anyhow!("Health checks are not supported")) })
+    }
+
+    /// Read a materialized view.
+    ///
+    /// Fails by default.
+    fn select_view(&self, _name: String) -> DbFuture<'_, Page<query::select::Item>> {
+        Box::pin(async { Err(anyhow::anyhow!("Views are not supported")) })
+    }
+
+    /// Find the shortest path between two entities.
+    ///
+    /// Fails by default.
+    fn shortest_path(&self, _query: query::graph::GraphQuery) -> DbFuture<'_, Option<Vec<Id>>> {
+        Box::pin(async { Err(anyhow::anyhow!("Graph queries are not supported")) })
+    }
+}
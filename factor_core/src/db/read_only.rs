@@ -0,0 +1,116 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    data::{DataMap, Id, IdOrIdent, Value},
+    error::ReadOnly,
+    query::{
+        graph::GraphQuery,
+        migrate::Migration,
+        mutate::{Batch, BatchReport},
+        select::{Item, Page, Select},
+    },
+    schema,
+};
+
+use super::{ClassStats, DbClient, DbFuture, EntityVersion, HealthReport};
+
+/// A [`DbClient`] that rejects all modifications with a [`ReadOnly`] error.
+pub struct ReadOnlyClient {
+    inner: Arc<dyn DbClient + Send + Sync + 'static>,
+}
+
+impl ReadOnlyClient {
+    pub fn new(inner: Arc<dyn DbClient + Send + Sync + 'static>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn DbClient + Send + Sync + 'static> {
+        &self.inner
+    }
+
+    fn reject<T>(operation: &'static str) -> DbFuture<'static, T> {
+        Box::pin(async move { Err(ReadOnly { operation }.into()) })
+    }
+}
+
+impl DbClient for ReadOnlyClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn inner_client(&self) -> Option<&(dyn DbClient + Send + Sync + 'static)> {
+        Some(&*self.inner)
+    }
+
+    fn with_auth_variables(
+        &self,
+        variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        let inner = self.inner.with_auth_variables(variables)?;
+        Some(Arc::new(Self::new(inner)))
+    }
+
+    fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+        self.inner.schema()
+    }
+
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        self.inner.entity(id)
+    }
+
+    fn entities(&self, ids: Vec<IdOrIdent>) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        self.inner.entities(ids)
+    }
+
+    fn select(&self, query: Select) -> DbFuture<'_, Page<Item>> {
+        self.inner.select(query)
+    }
+
+    fn select_map(&self, query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        self.inner.select_map(query)
+    }
+
+    fn batch(&self, _batch: Batch) -> DbFuture<'_, ()> {
+        Self::reject("batch")
+    }
+
+    fn batch_with_savepoints(&self, _batch: Batch) -> DbFuture<'_, BatchReport> {
+        Self::reject("batch_with_savepoints")
+    }
+
+    fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+        Self::reject("migrate")
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        self.inner.migrations()
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        self.inner.storage_usage()
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        Self::reject("purge_all_data")
+    }
+
+    fn entity_history(&self, id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        self.inner.entity_history(id)
+    }
+
+    fn class_stats(&self) -> DbFuture<'_, Vec<ClassStats>> {
+        self.inner.class_stats()
+    }
+
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        self.inner.health()
+    }
+
+    fn select_view(&self, name: String) -> DbFuture<'_, Page<Item>> {
+        self.inner.select_view(name)
+    }
+
+    fn shortest_path(&self, query: GraphQuery) -> DbFuture<'_, Option<Vec<Id>>> {
+        self.inner.shortest_path(query)
+    }
+}
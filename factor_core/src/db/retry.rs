@@ -0,0 +1,358 @@
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use crate::{
+    data::{DataMap, Id, IdOrIdent, Value},
+    error::ErrorClass,
+    query::{
+        graph::GraphQuery,
+        migrate::Migration,
+        mutate::{Batch, BatchReport},
+        select::{Item, Page, Select},
+    },
+    schema,
+};
+
+use super::{ClassStats, DbClient, DbFuture, EntityVersion, HealthReport};
+
+/// Determines how operations that failed with a
+/// [`crate::error::TransientError`] are retried.
+///
+/// The delay between attempts grows exponentially, starting at
+/// `initial_backoff` and capped at `max_backoff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the initial attempt.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_retries > 0
+    }
+
+    /// Delay before the given retry (starting at 0).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(retry.min(i32::MAX as u32) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        if secs.is_finite() && secs < self.max_backoff.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            self.max_backoff
+        }
+    }
+
+    /// Run an operation, retrying it as long as it fails with a retryable
+    /// error and retries are left.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, anyhow::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            match op().await {
+                Err(err) if retry < self.max_retries && ErrorClass::of(&err).is_retryable() => {
+                    let delay = self.backoff(retry);
+                    tracing::debug!(
+                        error = %err,
+                        retry,
+                        delay_ms = delay.as_millis() as u64,
+                        "retrying operation after transient error"
+                    );
+                    futures_timer::Delay::new(delay).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Retry up to 3 times, starting with a backoff of 50ms.
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+        }
+    }
+}
+
+/// A [`DbClient`] that retries operations of an inner client according to a
+/// [`RetryPolicy`].
+pub(crate) struct RetryClient {
+    inner: Arc<dyn DbClient + Send + Sync + 'static>,
+    policy: RetryPolicy,
+}
+
+impl RetryClient {
+    pub fn new(inner: Arc<dyn DbClient + Send + Sync + 'static>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl DbClient for RetryClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn inner_client(&self) -> Option<&(dyn DbClient + Send + Sync + 'static)> {
+        Some(&*self.inner)
+    }
+
+    fn with_auth_variables(
+        &self,
+        variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        let inner = self.inner.with_auth_variables(variables)?;
+        Some(Arc::new(Self::new(inner, self.policy.clone())))
+    }
+
+    fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+        Box::pin(self.policy.run(move || self.inner.schema()))
+    }
+
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        Box::pin(self.policy.run(move || self.inner.entity(id.clone())))
+    }
+
+    fn entities(&self, ids: Vec<IdOrIdent>) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        Box::pin(self.policy.run(move || self.inner.entities(ids.clone())))
+    }
+
+    fn select(&self, query: Select) -> DbFuture<'_, Page<Item>> {
+        Box::pin(self.policy.run(move || self.inner.select(query.clone())))
+    }
+
+    fn select_map(&self, query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        Box::pin(
+            self.policy
+                .run(move || self.inner.select_map(query.clone())),
+        )
+    }
+
+    fn batch(&self, batch: Batch) -> DbFuture<'_, ()> {
+        Box::pin(self.policy.run(move || self.inner.batch(batch.clone())))
+    }
+
+    fn batch_with_savepoints(&self, batch: Batch) -> DbFuture<'_, BatchReport> {
+        Box::pin(
+            self.policy
+                .run(move || self.inner.batch_with_savepoints(batch.clone())),
+        )
+    }
+
+    fn migrate(&self, migration: Migration) -> DbFuture<'_, ()> {
+        Box::pin(
+            self.policy
+                .run(move || self.inner.migrate(migration.clone())),
+        )
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        Box::pin(self.policy.run(move || self.inner.migrations()))
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        Box::pin(self.policy.run(move || self.inner.storage_usage()))
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        Box::pin(self.policy.run(move || self.inner.purge_all_data()))
+    }
+
+    fn entity_history(&self, id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        Box::pin(self.policy.run(move || self.inner.entity_history(id)))
+    }
+
+    fn class_stats(&self) -> DbFuture<'_, Vec<ClassStats>> {
+        Box::pin(self.policy.run(move || self.inner.class_stats()))
+    }
+
+    /// Not retried, so probes observe failures immediately.
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        self.inner.health()
+    }
+
+    fn select_view(&self, name: String) -> DbFuture<'_, Page<Item>> {
+        Box::pin(
+            self.policy
+                .run(move || self.inner.select_view(name.clone())),
+        )
+    }
+
+    fn shortest_path(&self, query: GraphQuery) -> DbFuture<'_, Option<Vec<Id>>> {
+        Box::pin(
+            self.policy
+                .run(move || self.inner.shortest_path(query.clone())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::{
+        data::Id,
+        db::{Db, DbConfig},
+        error::TransientError,
+        query::mutate::Mutate,
+    };
+
+    use super::*;
+
+    /// Client that fails the first `failures` selects with a transient error
+    /// and rejects all batches.
+    struct FlakyClient {
+        failures: u32,
+        attempts: Arc<AtomicU32>,
+    }
+
+    impl DbClient for FlakyClient {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+            Box::pin(async { Ok(schema::DbSchema::default()) })
+        }
+
+        fn entity(&self, _id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn select(&self, _query: Select) -> DbFuture<'_, Page<Item>> {
+            Box::pin(async move {
+                let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+                if attempt < self.failures {
+                    Err(TransientError::LockTimeout.into())
+                } else {
+                    Ok(Page::new())
+                }
+            })
+        }
+
+        fn select_map(&self, _query: Select) -> DbFuture<'_, Vec<DataMap>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn batch(&self, _batch: Batch) -> DbFuture<'_, ()> {
+            Box::pin(async move {
+                self.attempts.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow::anyhow!("batch rejected"))
+            })
+        }
+
+        fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn purge_all_data(&self) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn flaky_db(failures: u32, policy: RetryPolicy) -> (Db, Arc<AtomicU32>) {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let client = FlakyClient {
+            failures,
+            attempts: attempts.clone(),
+        };
+        let db = Db::with_config(
+            client,
+            DbConfig {
+                retry_policy: policy,
+            },
+        );
+        (db, attempts)
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy::default()
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(500));
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_retry_client() {
+        let policy = RetryPolicy::default()
+            .with_max_retries(3)
+            .with_initial_backoff(Duration::from_millis(1));
+
+        // Transient errors are retried until the operation succeeds.
+        let (db, attempts) = flaky_db(2, policy.clone());
+        db.select(Select::new()).await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(db.client().as_any().is::<RetryClient>());
+        assert!(db.find_client::<FlakyClient>().is_some());
+
+        // Retries are limited.
+        let (db, attempts) = flaky_db(10, policy.clone());
+        let err = db.select(Select::new()).await.unwrap_err();
+        assert_eq!(err.class(), ErrorClass::Retryable);
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+
+        // Permanent errors are not retried.
+        let (db, attempts) = flaky_db(0, policy);
+        let err = db.mutate(Mutate::delete(Id::random())).await.unwrap_err();
+        assert_eq!(err.class(), ErrorClass::Permanent);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // Retries can be disabled.
+        let (db, attempts) = flaky_db(1, RetryPolicy::none());
+        db.select(Select::new()).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(db.client().as_any().is::<FlakyClient>());
+
+        // Retries are opt-in.
+        let (db, attempts) = flaky_db(1, DbConfig::default().retry_policy);
+        db.select(Select::new()).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
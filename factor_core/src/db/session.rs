@@ -0,0 +1,243 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    data::{DataMap, Id, IdOrIdent},
+    error::Error,
+    query::{
+        mutate::{Batch, Mutate},
+        select::{Item, Page, Select},
+    },
+};
+
+use super::Db;
+
+/// A handle that guarantees read-your-writes consistency.
+///
+/// The session tracks the ids of all entities written through it. Reads of
+/// those entities are always served by the primary database, even if a
+/// replica was configured with [`Self::with_replica`], so they observe the
+/// written data.
+///
+/// Created with [`Db::session`].
+/// Clones share the same session state.
+#[derive(Clone)]
+pub struct Session {
+    primary: Db,
+    replica: Option<Db>,
+    state: Arc<Mutex<SessionState>>,
+}
+
+#[derive(Default)]
+struct SessionState {
+    written: HashSet<Id>,
+    /// Set after a [`Mutate::Select`], which can affect any entity.
+    written_unknown: bool,
+}
+
+impl Session {
+    pub(super) fn new(primary: Db) -> Self {
+        Self {
+            primary,
+            replica: None,
+            state: Default::default(),
+        }
+    }
+
+    /// Serve reads that don't depend on writes of this session from a
+    /// replica, which may lag behind the primary.
+    pub fn with_replica(mut self, replica: Db) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Ids of all entities written in this session.
+    pub fn written_ids(&self) -> HashSet<Id> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    /// Returns `true` if the entity was written in this session.
+    pub fn has_written(&self, id: Id) -> bool {
+        let state = self.state.lock().unwrap();
+        state.written_unknown || state.written.contains(&id)
+    }
+
+    fn has_written_any(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.written_unknown || !state.written.is_empty()
+    }
+
+    fn reader(&self, primary: bool) -> &Db {
+        match &self.replica {
+            Some(replica) if !primary => replica,
+            _ => &self.primary,
+        }
+    }
+
+    /// Load an entity by id or ident.
+    ///
+    /// Idents are resolved on the primary once this session has written
+    /// anything, since the ident could belong to a written entity.
+    pub async fn entity<I>(&self, id: I) -> Result<DataMap, Error>
+    where
+        I: Into<IdOrIdent>,
+    {
+        let id = id.into();
+        let primary = match &id {
+            IdOrIdent::Id(id) => self.has_written(*id),
+            IdOrIdent::Name(_) => self.has_written_any(),
+        };
+        self.reader(primary).entity(id).await
+    }
+
+    /// Query entities.
+    ///
+    /// Selects are served by the primary once this session has written
+    /// anything, since written entities could match the query.
+    pub async fn select(&self, query: Select) -> Result<Page<Item>, Error> {
+        self.reader(self.has_written_any()).select(query).await
+    }
+
+    pub async fn select_map(&self, query: Select) -> Result<Vec<DataMap>, Error> {
+        self.reader(self.has_written_any()).select_map(query).await
+    }
+
+    /// Apply a batch and record the written entities.
+    ///
+    /// Entities are recorded even if the batch fails, since a failure does not
+    /// guarantee that nothing was written.
+    pub async fn batch(&self, batch: Batch) -> Result<(), Error> {
+        {
+            let mut state = self.state.lock().unwrap();
+            for action in &batch.actions {
+                match action.entity_id() {
+                    Some(id) => {
+                        state.written.insert(id);
+                    }
+                    None if matches!(action, Mutate::Guard(_)) => {}
+                    None => state.written_unknown = true,
+                }
+            }
+        }
+        self.primary.batch(batch).await
+    }
+
+    pub async fn mutate(&self, mutate: Mutate) -> Result<(), Error> {
+        self.batch(mutate.into()).await
+    }
+
+    pub async fn create(&self, id: Id, data: DataMap) -> Result<(), Error> {
+        self.mutate(Mutate::create(id, data)).await
+    }
+
+    pub async fn merge(&self, id: Id, data: DataMap) -> Result<(), Error> {
+        self.mutate(Mutate::merge(id, data)).await
+    }
+
+    pub async fn delete(&self, id: Id) -> Result<(), Error> {
+        self.mutate(Mutate::delete(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::Value,
+        db::{DbClient, DbFuture},
+        query::migrate::Migration,
+        schema,
+    };
+
+    use super::*;
+
+    /// Client that keeps entities in memory without any validation.
+    #[derive(Default)]
+    struct MapClient {
+        entities: Mutex<std::collections::HashMap<Id, DataMap>>,
+    }
+
+    impl DbClient for MapClient {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+            Box::pin(async { Ok(schema::DbSchema::default()) })
+        }
+
+        fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+            let data = match id {
+                IdOrIdent::Id(id) => self.entities.lock().unwrap().get(&id).cloned(),
+                IdOrIdent::Name(_) => None,
+            };
+            Box::pin(async { Ok(data) })
+        }
+
+        fn select(&self, _query: Select) -> DbFuture<'_, Page<Item>> {
+            Box::pin(async { Ok(Page::new()) })
+        }
+
+        fn select_map(&self, _query: Select) -> DbFuture<'_, Vec<DataMap>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn batch(&self, batch: Batch) -> DbFuture<'_, ()> {
+            let mut entities = self.entities.lock().unwrap();
+            for action in batch.actions {
+                match action {
+                    Mutate::Create(c) => {
+                        entities.insert(c.id, c.data);
+                    }
+                    Mutate::Delete(d) => {
+                        entities.remove(&d.id);
+                    }
+                    _ => unimplemented!(),
+                }
+            }
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn purge_all_data(&self) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_reads_own_writes() {
+        let primary = Db::new(MapClient::default());
+        // A replica that never receives any writes.
+        let replica = Db::new(MapClient::default());
+
+        let replicated = Id::random();
+        let mut data = DataMap::new();
+        data.insert("factor/title".to_string(), Value::from("replica"));
+        replica.create(replicated, data.clone()).await.unwrap();
+
+        let session = primary.session().with_replica(replica);
+        assert_eq!(session.entity(replicated).await.unwrap(), data);
+
+        let id = Id::random();
+        session.create(id, data.clone()).await.unwrap();
+        assert!(session.has_written(id));
+        assert!(!session.has_written(replicated));
+        assert_eq!(session.entity(id).await.unwrap(), data);
+
+        session.delete(id).await.unwrap();
+        assert!(session.entity(id).await.is_err());
+        assert_eq!(session.written_ids(), HashSet::from([id]));
+    }
+}
@@ -0,0 +1,373 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    data::{
+        patch::{Patch, PatchOp, PatchPathElem},
+        DataMap, Id, IdOrIdent, Value,
+    },
+    error::EntityNotFound,
+    query::{
+        expr::Expr,
+        migrate::Migration,
+        mutate::{Batch, BatchReport, Guard, Mutate, MutateSelectAction},
+        select::{Item, Page, Select},
+    },
+    schema::{self, builtin::AttrTenant, AttributeMeta},
+};
+
+use super::{DbClient, DbFuture, EntityVersion, HealthReport};
+
+/// A [`DbClient`] that restricts all operations to the entities of a single
+/// tenant.
+///
+/// * Selects only return entities owned by the tenant, including joined
+///   entities.
+/// * Entities of other tenants are reported as not found.
+/// * Created entities are stamped with the tenant.
+/// * Changing the tenant of an entity is not allowed.
+///
+/// The schema is shared between all tenants, so migrations are rejected and
+/// must be applied through an unscoped client.
+pub struct TenantClient {
+    inner: Arc<dyn DbClient + Send + Sync + 'static>,
+    tenant: Id,
+}
+
+impl TenantClient {
+    pub fn new(inner: Arc<dyn DbClient + Send + Sync + 'static>, tenant: Id) -> Self {
+        Self { inner, tenant }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn DbClient + Send + Sync + 'static> {
+        &self.inner
+    }
+
+    fn owns(&self, data: &DataMap) -> bool {
+        data.get(AttrTenant::QUALIFIED_NAME)
+            .and_then(|value| value.as_id())
+            == Some(self.tenant)
+    }
+
+    fn scope_filter(&self, filter: Option<Expr>) -> Expr {
+        let tenant_filter = Expr::eq(AttrTenant::expr(), self.tenant);
+        match filter {
+            Some(filter) => Expr::and(tenant_filter, filter),
+            None => tenant_filter,
+        }
+    }
+
+    /// Remove joined entities of other tenants.
+    fn scope_joins(&self, items: &mut [Item]) {
+        for item in items {
+            for join in &mut item.joins {
+                join.items.retain(|item| self.owns(&item.data));
+                self.scope_joins(&mut join.items);
+            }
+        }
+    }
+
+    /// Set the tenant on entity data.
+    fn stamp(&self, data: &mut DataMap) -> Result<(), anyhow::Error> {
+        if let Some(value) = data.get(AttrTenant::QUALIFIED_NAME) {
+            if value.as_id() != Some(self.tenant) {
+                anyhow::bail!(
+                    "Invalid value for '{}': entities can not be assigned to another tenant",
+                    AttrTenant::QUALIFIED_NAME
+                );
+            }
+        }
+        data.insert(AttrTenant::QUALIFIED_NAME.to_string(), self.tenant.into());
+        Ok(())
+    }
+
+    /// Restrict a batch to the entities of the tenant.
+    ///
+    /// Modifications of existing entities are preceded by a [`Guard`] that
+    /// requires the entity to belong to the tenant.
+    /// Guards are checked while the batch is applied, so the owner can not
+    /// change between the check and the write.
+    fn scope_batch(&self, batch: Batch) -> Result<Batch, anyhow::Error> {
+        let mut actions = Vec::with_capacity(batch.actions.len());
+        for mut action in batch.actions {
+            match &mut action {
+                Mutate::Create(create) => self.stamp(&mut create.data)?,
+                Mutate::Replace(replace) => self.stamp(&mut replace.data)?,
+                Mutate::Merge(merge) => self.stamp(&mut merge.data)?,
+                Mutate::Patch(patch) => check_patch(&patch.patch)?,
+                Mutate::Delete(_) | Mutate::Savepoint => {}
+                Mutate::Select(select) => {
+                    if let MutateSelectAction::Patch(patch) = &select.action {
+                        check_patch(patch)?;
+                    }
+                    let filter = std::mem::replace(&mut select.filter, Expr::literal(true));
+                    select.filter = self.scope_filter(Some(filter));
+                }
+                Mutate::Guard(guard) => {
+                    // Entities of other tenants must never match.
+                    let filter = std::mem::replace(&mut guard.filter, Expr::literal(true));
+                    guard.filter = self.scope_filter(Some(filter));
+                }
+            }
+
+            if let Some(guard) = self.ownership_guard(&action) {
+                actions.push(Mutate::Guard(guard));
+            }
+            actions.push(action);
+        }
+
+        Ok(Batch { actions })
+    }
+
+    /// The [`Guard`] that precedes an action in [`Self::scope_batch`].
+    fn ownership_guard(&self, action: &Mutate) -> Option<Guard> {
+        match action {
+            Mutate::Replace(_) | Mutate::Merge(_) | Mutate::Patch(_) | Mutate::Delete(_) => {
+                action.entity_id().map(|id| Guard {
+                    id,
+                    filter: self.scope_filter(None),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Patches must not modify the tenant.
+fn check_patch(patch: &Patch) -> Result<(), anyhow::Error> {
+    let modifies_tenant = patch.0.iter().any(|op| {
+        let path = match op {
+            PatchOp::Add { path, .. }
+            | PatchOp::Replace { path, .. }
+            | PatchOp::Remove { path, .. } => path,
+        };
+        matches!(path.0.first(), Some(PatchPathElem::Key(key)) if key == AttrTenant::QUALIFIED_NAME)
+    });
+    if modifies_tenant {
+        anyhow::bail!(
+            "Invalid patch: '{}' can not be modified",
+            AttrTenant::QUALIFIED_NAME
+        );
+    }
+    Ok(())
+}
+
+impl DbClient for TenantClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn inner_client(&self) -> Option<&(dyn DbClient + Send + Sync + 'static)> {
+        Some(&*self.inner)
+    }
+
+    fn with_auth_variables(
+        &self,
+        variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        let inner = self.inner.with_auth_variables(variables)?;
+        Some(Arc::new(Self::new(inner, self.tenant)))
+    }
+
+    fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+        self.inner.schema()
+    }
+
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        Box::pin(async move {
+            let data = self.inner.entity(id).await?;
+            Ok(data.filter(|data| self.owns(data)))
+        })
+    }
+
+    fn entities(&self, ids: Vec<IdOrIdent>) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        Box::pin(async move {
+            let mut entities = self.inner.entities(ids).await?;
+            for data in entities.values_mut() {
+                if !data.as_ref().map(|data| self.owns(data)).unwrap_or(false) {
+                    *data = None;
+                }
+            }
+            Ok(entities)
+        })
+    }
+
+    fn select(&self, mut query: Select) -> DbFuture<'_, Page<Item>> {
+        query.filter = Some(self.scope_filter(query.filter.take()));
+        Box::pin(async move {
+            let mut page = self.inner.select(query).await?;
+            self.scope_joins(&mut page.items);
+            Ok(page)
+        })
+    }
+
+    fn select_map(&self, mut query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        query.filter = Some(self.scope_filter(query.filter.take()));
+        self.inner.select_map(query)
+    }
+
+    fn batch(&self, batch: Batch) -> DbFuture<'_, ()> {
+        match self.scope_batch(batch) {
+            Ok(batch) => self.inner.batch(batch),
+            Err(err) => Box::pin(async { Err(err) }),
+        }
+    }
+
+    /// Invalid modifications fail the whole batch, just like with
+    /// [`DbClient::batch`].
+    fn batch_with_savepoints(&self, batch: Batch) -> DbFuture<'_, BatchReport> {
+        let guarded = batch
+            .actions
+            .iter()
+            .map(|action| self.ownership_guard(action).is_some())
+            .collect::<Vec<_>>();
+        let batch = match self.scope_batch(batch) {
+            Ok(batch) => batch,
+            Err(err) => return Box::pin(async { Err(err) }),
+        };
+        Box::pin(async move {
+            let report = self.inner.batch_with_savepoints(batch).await?;
+            // Drop the statuses of the added guards, which are always in the
+            // same segment as the guarded action.
+            let mut statuses = report.actions.into_iter();
+            let mut actions = Vec::with_capacity(guarded.len());
+            for guarded in guarded {
+                if guarded {
+                    statuses.next();
+                }
+                actions.extend(statuses.next());
+            }
+            Ok(BatchReport { actions })
+        })
+    }
+
+    /// The schema is shared between all tenants, so a tenant can not change
+    /// it.
+    fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+        Box::pin(async {
+            Err(anyhow::anyhow!(
+                "migrate is not supported for tenant scoped databases"
+            ))
+        })
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        self.inner.migrations()
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        self.inner.storage_usage()
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        Box::pin(async {
+            Err(anyhow::anyhow!(
+                "purge_all_data is not supported for tenant scoped databases"
+            ))
+        })
+    }
+
+    /// Entities that belonged to another tenant at any point are reported as
+    /// not found.
+    fn entity_history(&self, id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        Box::pin(async move {
+            let history = self.inner.entity_history(id).await?;
+            let foreign = history
+                .iter()
+                .filter_map(|version| version.data.as_ref())
+                .any(|data| !self.owns(data));
+            if foreign {
+                return Err(EntityNotFound::new(id.into()).into());
+            }
+            Ok(history)
+        })
+    }
+
+    /// The health is not tenant specific.
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        self.inner.health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Db;
+
+    use super::*;
+
+    /// Client that returns a single item with joined entities of two tenants.
+    struct JoinClient {
+        own: Id,
+        other: Id,
+    }
+
+    impl DbClient for JoinClient {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
+            Box::pin(async { Ok(schema::DbSchema::default()) })
+        }
+
+        fn entity(&self, _id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn select(&self, _query: Select) -> DbFuture<'_, Page<Item>> {
+            let item = |tenant: Id| Item::new(map! {"factor/tenant": tenant});
+            let page = Page {
+                items: vec![item(self.own).with_join(
+                    "children",
+                    vec![
+                        item(self.own).with_join("children", vec![item(self.other)]),
+                        item(self.other),
+                    ],
+                )],
+                next_cursor: None,
+            };
+            Box::pin(async { Ok(page) })
+        }
+
+        fn select_map(&self, _query: Select) -> DbFuture<'_, Vec<DataMap>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn batch(&self, _batch: Batch) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrate(&self, _migration: Migration) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn purge_all_data(&self) -> DbFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tenant_client() {
+        let own = Id::random();
+        let db = Db::new(JoinClient {
+            own,
+            other: Id::random(),
+        })
+        .with_tenant(own);
+
+        // Joined entities of other tenants are removed at every level.
+        let page = db.select(Select::new()).await.unwrap();
+        let joined = &page.items[0].joins[0].items;
+        assert_eq!(joined.len(), 1);
+        assert!(joined[0].joins[0].items.is_empty());
+
+        db.migrate(Migration::new()).await.unwrap_err();
+    }
+}
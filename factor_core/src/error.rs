@@ -1,6 +1,10 @@
 // AttributeNotFound
 
-use crate::data::{Id, IdOrIdent, Value};
+use crate::{
+    data::{value::ValueCoercionError, Id, IdOrIdent, Value},
+    query::migrate::UnifyMigrationsError,
+    schema::{SchemaCompatibilityError, ValueConstraint},
+};
 
 #[derive(Debug)]
 pub struct AttributeNotFound {
@@ -63,6 +67,22 @@ impl std::fmt::Display for EntityNotFound {
 
 impl std::error::Error for EntityNotFound {}
 
+// EntityAlreadyExists
+
+/// Returned when creating an entity with an id that is already taken.
+#[derive(Debug)]
+pub struct EntityAlreadyExists {
+    pub id: Id,
+}
+
+impl std::fmt::Display for EntityAlreadyExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entity id already exists: '{}'", self.id)
+    }
+}
+
+impl std::error::Error for EntityAlreadyExists {}
+
 // UnqiueConstraintViolation
 
 #[derive(Debug)]
@@ -111,3 +131,519 @@ impl std::fmt::Display for ReferenceConstraintViolation {
 }
 
 impl std::error::Error for ReferenceConstraintViolation {}
+
+// ValueConstraintViolation
+
+#[derive(Debug)]
+pub struct ValueConstraintViolation {
+    pub attribute: String,
+    pub constraint: ValueConstraint,
+    pub value: Value,
+}
+
+impl ValueConstraintViolation {
+    pub fn new(attribute: String, constraint: ValueConstraint, value: Value) -> Self {
+        Self {
+            attribute,
+            constraint,
+            value,
+        }
+    }
+}
+
+impl std::fmt::Display for ValueConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Value constraint violated for attribute '{}': value {:?} does not satisfy constraint ({})",
+            self.attribute, self.value, self.constraint,
+        )
+    }
+}
+
+impl std::error::Error for ValueConstraintViolation {}
+
+// TransientError
+
+/// A failure that is expected to resolve on its own, like an exhausted
+/// connection pool or a lock timeout.
+///
+/// Backends must only return this error if the operation had no effect, so it
+/// can safely be retried. See [`ErrorClass`].
+#[derive(Debug)]
+pub enum TransientError {
+    /// No connection was available in a connection pool.
+    PoolExhausted,
+    /// A lock could not be acquired in time.
+    LockTimeout,
+    /// The backend is temporarily unavailable.
+    Unavailable(String),
+}
+
+impl std::fmt::Display for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PoolExhausted => write!(f, "Connection pool exhausted"),
+            Self::LockTimeout => write!(f, "Timed out while waiting for a lock"),
+            Self::Unavailable(reason) => write!(f, "Backend unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+/// Classification of errors into retryable and permanent failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The operation failed due to a [`TransientError`] or [`Overloaded`] and
+    /// can be retried.
+    Retryable,
+    /// Retrying the operation would fail again.
+    Permanent,
+}
+
+impl ErrorClass {
+    /// Classify an error.
+    ///
+    /// An error is retryable if it was caused by a [`TransientError`] or
+    /// [`Overloaded`], either as a source or as context.
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            err.class()
+        } else if err.downcast_ref::<TransientError>().is_some()
+            || err
+                .chain()
+                .any(|e| e.is::<TransientError>() || e.is::<Overloaded>())
+        {
+            Self::Retryable
+        } else {
+            Self::Permanent
+        }
+    }
+
+    pub fn is_retryable(self) -> bool {
+        self == Self::Retryable
+    }
+}
+
+// ReadOnly
+
+/// Returned when trying to modify a read-only database.
+///
+/// See [`crate::db::Db::read_only`].
+#[derive(Debug)]
+pub struct ReadOnly {
+    /// The rejected operation.
+    pub operation: &'static str,
+}
+
+impl std::fmt::Display for ReadOnly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database is read-only: {} is not allowed",
+            self.operation
+        )
+    }
+}
+
+impl std::error::Error for ReadOnly {}
+
+// PermissionDenied
+
+/// Returned when a caller is not allowed to perform an operation.
+///
+/// Schema changes and purges bypass row-level security policies, so they
+/// are rejected for clients with an auth context.
+/// See [`crate::db::Db::with_auth_variables`].
+#[derive(Debug)]
+pub struct PermissionDenied {
+    /// The rejected operation.
+    pub operation: &'static str,
+}
+
+impl std::fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Permission denied: {} is not allowed", self.operation)
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+// PolicyViolation
+
+/// Returned when a write produces an entity that does not satisfy a
+/// row-level security policy.
+///
+/// See [`crate::schema::PolicySchema`].
+#[derive(Debug)]
+pub struct PolicyViolation {
+    pub entity: Id,
+    pub policy: String,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Entity {} violates policy '{}'",
+            self.entity, self.policy
+        )
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+// QuotaExceeded
+
+/// Returned when creating an entity of a class that already has the maximum
+/// number of entities.
+///
+/// See [`crate::schema::Class::max_entities`].
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub class: String,
+    pub limit: u64,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Quota exceeded: class '{}' is limited to {} entities",
+            self.class, self.limit
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+// Overloaded
+
+/// Returned when a batch is rejected because too many batches are already
+/// being applied.
+///
+/// The batch had no effect and can be retried later.
+#[derive(Debug)]
+pub struct Overloaded {
+    /// Maximum number of batches that are applied concurrently.
+    pub limit: usize,
+}
+
+impl std::fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Overloaded: the limit of {} pending batches was reached",
+            self.limit
+        )
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+// ReferenceCycle
+
+/// Returned when a write would make the references of an acyclic attribute
+/// form a cycle.
+///
+/// See [`crate::data::value_type::ConstrainedRefType::acyclic_depth`].
+#[derive(Debug)]
+pub struct ReferenceCycle {
+    pub entity: Id,
+    pub attribute: String,
+}
+
+impl std::fmt::Display for ReferenceCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Reference cycle: attribute '{}' of entity {} leads back to the entity",
+            self.attribute, self.entity
+        )
+    }
+}
+
+impl std::error::Error for ReferenceCycle {}
+
+// Error
+
+/// Structured error returned by [`crate::db::Db`].
+///
+/// Allows callers to match on the kind of failure. Errors without a
+/// specific kind are kept in [`Error::Other`].
+///
+/// Backends and clients still produce [`anyhow::Error`]s, which are
+/// classified with the [`From`] impl.
+/// Context attached to typed errors is dropped during classification.
+#[derive(Debug)]
+pub enum Error {
+    /// The entity does not exist.
+    NotFound(EntityNotFound),
+    /// A unique index already contains the value.
+    UniqueViolation(UniqueConstraintViolation),
+    /// A reference points to an entity of the wrong type.
+    ReferenceViolation(ReferenceConstraintViolation),
+    /// A value does not satisfy a class attribute constraint.
+    ValueConstraintViolation(ValueConstraintViolation),
+    /// A written entity does not satisfy a policy.
+    PolicyViolation(PolicyViolation),
+    /// A create exceeds the entity quota of a class.
+    QuotaExceeded(QuotaExceeded),
+    /// References of an acyclic attribute would form a cycle.
+    ReferenceCycle(ReferenceCycle),
+    /// A value could not be coerced to the attribute type.
+    Coercion(ValueCoercionError),
+    /// Invalid schema or migration, or a reference to an unknown attribute
+    /// or index.
+    Schema(anyhow::Error),
+    /// The entity already exists.
+    Conflict(EntityAlreadyExists),
+    /// The database is read-only.
+    ReadOnly(ReadOnly),
+    /// The caller is not allowed to perform the operation.
+    PermissionDenied(PermissionDenied),
+    /// A lock could not be acquired in time.
+    Timeout,
+    /// Too many batches are pending.
+    Overloaded(Overloaded),
+    /// A transient failure other than a timeout.
+    ///
+    /// See [`TransientError`].
+    Transient(TransientError),
+    /// An I/O error in the storage layer.
+    Storage(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// Retry classification of this error.
+    ///
+    /// See [`ErrorClass`].
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::Timeout | Self::Overloaded(_) | Self::Transient(_) => ErrorClass::Retryable,
+            Self::Schema(err) | Self::Storage(err) | Self::Other(err) => ErrorClass::of(err),
+            _ => ErrorClass::Permanent,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.class().is_retryable()
+    }
+
+    /// Ident of the attribute that violated a constraint.
+    ///
+    /// Only set for unique, reference, value and cycle violations.
+    pub fn attribute(&self) -> Option<&str> {
+        match self {
+            Self::UniqueViolation(err) => Some(&err.attribute),
+            Self::ReferenceViolation(err) => Some(&err.attribute),
+            Self::ValueConstraintViolation(err) => Some(&err.attribute),
+            Self::ReferenceCycle(err) => Some(&err.attribute),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the error is or was caused by an error of type `E`.
+    pub fn is<E>(&self) -> bool
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.downcast_ref::<E>().is_some()
+    }
+
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        match self {
+            Self::NotFound(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::UniqueViolation(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::ReferenceViolation(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::ValueConstraintViolation(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::PolicyViolation(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::QuotaExceeded(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::ReferenceCycle(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::Coercion(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::Conflict(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::ReadOnly(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::PermissionDenied(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::Timeout => None,
+            Self::Overloaded(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::Transient(err) => (err as &dyn std::error::Error).downcast_ref(),
+            Self::Schema(err) | Self::Storage(err) | Self::Other(err) => err.downcast_ref(),
+        }
+    }
+
+    /// Extract the underlying error of type `E`.
+    pub fn downcast<E>(self) -> Result<E, Self>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.into_anyhow().downcast().map_err(Self::from)
+    }
+
+    /// Convert into an [`anyhow::Error`] wrapping the underlying error.
+    pub fn into_anyhow(self) -> anyhow::Error {
+        match self {
+            Self::NotFound(err) => err.into(),
+            Self::UniqueViolation(err) => err.into(),
+            Self::ReferenceViolation(err) => err.into(),
+            Self::ValueConstraintViolation(err) => err.into(),
+            Self::PolicyViolation(err) => err.into(),
+            Self::QuotaExceeded(err) => err.into(),
+            Self::ReferenceCycle(err) => err.into(),
+            Self::Coercion(err) => err.into(),
+            Self::Conflict(err) => err.into(),
+            Self::ReadOnly(err) => err.into(),
+            Self::PermissionDenied(err) => err.into(),
+            Self::Timeout => TransientError::LockTimeout.into(),
+            Self::Overloaded(err) => err.into(),
+            Self::Transient(err) => err.into(),
+            Self::Schema(err) | Self::Storage(err) | Self::Other(err) => err,
+        }
+    }
+
+    /// Classify errors of a schema operation.
+    ///
+    /// Errors without a specific kind become [`Error::Schema`].
+    pub fn schema(err: anyhow::Error) -> Self {
+        match Self::from(err) {
+            Self::Other(err) => Self::Schema(err),
+            other => other,
+        }
+    }
+
+    fn from_transient(err: TransientError) -> Self {
+        match err {
+            TransientError::LockTimeout => Self::Timeout,
+            other => Self::Transient(other),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(err) => std::fmt::Display::fmt(err, f),
+            Self::UniqueViolation(err) => std::fmt::Display::fmt(err, f),
+            Self::ReferenceViolation(err) => std::fmt::Display::fmt(err, f),
+            Self::ValueConstraintViolation(err) => std::fmt::Display::fmt(err, f),
+            Self::PolicyViolation(err) => std::fmt::Display::fmt(err, f),
+            Self::QuotaExceeded(err) => std::fmt::Display::fmt(err, f),
+            Self::ReferenceCycle(err) => std::fmt::Display::fmt(err, f),
+            Self::Coercion(err) => std::fmt::Display::fmt(err, f),
+            Self::Conflict(err) => std::fmt::Display::fmt(err, f),
+            Self::ReadOnly(err) => std::fmt::Display::fmt(err, f),
+            Self::PermissionDenied(err) => std::fmt::Display::fmt(err, f),
+            Self::Timeout => std::fmt::Display::fmt(&TransientError::LockTimeout, f),
+            Self::Overloaded(err) => std::fmt::Display::fmt(err, f),
+            Self::Transient(err) => std::fmt::Display::fmt(err, f),
+            // Forwarding keeps the alternate flag, which prints the context.
+            Self::Schema(err) | Self::Storage(err) | Self::Other(err) => {
+                std::fmt::Display::fmt(err, f)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Schema(err) | Self::Storage(err) | Self::Other(err) => {
+                std::error::Error::source(&**err)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Take the error if it is of type `E`.
+fn take<E>(err: anyhow::Error, f: impl FnOnce(E) -> Error) -> Result<Error, anyhow::Error>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    err.downcast::<E>().map(f)
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        take(err, Self::NotFound)
+            .or_else(|err| take(err, Self::UniqueViolation))
+            .or_else(|err| take(err, Self::ReferenceViolation))
+            .or_else(|err| take(err, Self::ValueConstraintViolation))
+            .or_else(|err| take(err, Self::PolicyViolation))
+            .or_else(|err| take(err, Self::QuotaExceeded))
+            .or_else(|err| take(err, Self::ReferenceCycle))
+            .or_else(|err| take(err, Self::Coercion))
+            .or_else(|err| take(err, Self::Conflict))
+            .or_else(|err| take(err, Self::ReadOnly))
+            .or_else(|err| take(err, Self::PermissionDenied))
+            .or_else(|err| take(err, Self::Overloaded))
+            .or_else(|err| take(err, Self::from_transient))
+            .unwrap_or_else(|err| {
+                if err.is::<AttributeNotFound>()
+                    || err.is::<IndexNotFound>()
+                    || err.is::<SchemaCompatibilityError>()
+                    || err.is::<UnifyMigrationsError>()
+                {
+                    Self::Schema(err)
+                } else if err.chain().any(|e| e.is::<std::io::Error>()) {
+                    Self::Storage(err)
+                } else {
+                    Self::Other(err)
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_error_from_anyhow() {
+        let id = Id::random();
+        let err = Error::from(
+            anyhow::Error::from(EntityNotFound::new(id.into())).context("loading entity"),
+        );
+        assert!(matches!(&err, Error::NotFound(e) if e.ident == IdOrIdent::from(id)));
+        assert!(err.is::<EntityNotFound>());
+        assert_eq!(err.downcast::<EntityNotFound>().unwrap().ident, id.into());
+
+        let err = Error::from(anyhow::Error::from(TransientError::LockTimeout));
+        assert!(matches!(err, Error::Timeout));
+        assert!(err.is_retryable());
+
+        let err = Error::from(anyhow::Error::from(Overloaded { limit: 2 }));
+        assert!(matches!(&err, Error::Overloaded(e) if e.limit == 2));
+        assert!(err.is_retryable());
+        assert!(ErrorClass::of(&err.into_anyhow().context("applying batch")).is_retryable());
+
+        let err = Error::from(anyhow::Error::from(AttributeNotFound::new(
+            IdOrIdent::new_static("test/missing"),
+        )));
+        assert!(matches!(err, Error::Schema(_)));
+        assert!(err.is::<AttributeNotFound>());
+
+        let io = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+        let err = Error::from(Err::<(), _>(io).context("writing log").unwrap_err());
+        assert!(matches!(err, Error::Storage(_)));
+        assert_eq!(format!("{:#}", err), "writing log: disk full");
+
+        let err = Error::from(anyhow::anyhow!("boom"));
+        assert!(matches!(err, Error::Other(_)));
+        assert!(!err.is_retryable());
+        assert!(matches!(
+            Error::schema(anyhow::anyhow!("invalid migration")),
+            Error::Schema(_)
+        ));
+    }
+}
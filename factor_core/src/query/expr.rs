@@ -1,5 +1,9 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use ordered_float::OrderedFloat;
+
 use crate::{
-    data::{IdOrIdent, Value},
+    data::{GeoPoint, IdOrIdent, TimeUnit, Timestamp, Value},
     schema::{builtin::AttrType, AttributeMeta, ClassMeta},
 };
 
@@ -20,6 +24,108 @@ pub enum BinaryOp {
     Contains,
     RegexMatch,
     RegexMatchCaseInsensitive,
+    /// Numeric addition.
+    Add,
+    /// Numeric subtraction.
+    Sub,
+    /// Numeric multiplication.
+    Mul,
+    /// String concatenation.
+    Concat,
+}
+
+impl BinaryOp {
+    /// Returns `true` for operators that compute a new value instead of a
+    /// boolean.
+    pub fn is_computed(&self) -> bool {
+        matches!(self, Self::Add | Self::Sub | Self::Mul | Self::Concat)
+    }
+
+    /// Apply an arithmetic operator to two numbers.
+    ///
+    /// Returns `None` for non-arithmetic operators or if the result
+    /// overflows.
+    pub fn eval_numbers(&self, left: Number, right: Number) -> Option<Number> {
+        match (left, right) {
+            (Number::Int(l), Number::Int(r)) => match self {
+                Self::Add => l.checked_add(r),
+                Self::Sub => l.checked_sub(r),
+                Self::Mul => l.checked_mul(r),
+                _ => None,
+            }
+            .map(Number::Int),
+            (l, r) => {
+                let (l, r) = (l.as_f64(), r.as_f64());
+                let value = match self {
+                    Self::Add => l + r,
+                    Self::Sub => l - r,
+                    Self::Mul => l * r,
+                    _ => return None,
+                };
+                Some(Number::Float(value))
+            }
+        }
+    }
+
+    /// Evaluate a computed operator (see [`Self::is_computed`]) on two
+    /// values.
+    ///
+    /// Produces [`Value::Unit`] for unsupported operand types.
+    pub fn eval_computed(&self, left: &Value, right: &Value) -> Value {
+        match self {
+            Self::Concat => match (left, right) {
+                (Value::String(l), Value::String(r)) => Value::String(format!("{}{}", l, r)),
+                _ => Value::Unit,
+            },
+            _ => Number::from_value(left)
+                .zip(Number::from_value(right))
+                .and_then(|(l, r)| self.eval_numbers(l, r))
+                .and_then(Number::into_value)
+                .unwrap_or(Value::Unit),
+        }
+    }
+}
+
+/// Numeric operand for arithmetic operators.
+///
+/// Integers are widened so mixed signed and unsigned arithmetic can not
+/// overflow the intermediate representation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Number {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::UInt(v) => Some(Self::Int(i128::from(*v))),
+            Value::Int(v) => Some(Self::Int(i128::from(*v))),
+            Value::Float(v) => Some(Self::Float(v.into_inner())),
+            _ => None,
+        }
+    }
+
+    /// Convert into a value.
+    ///
+    /// Integers are represented as [`Value::Int`] if possible.
+    /// Returns `None` if an integer does not fit into 64 bits.
+    pub fn into_value(self) -> Option<Value> {
+        match self {
+            Self::Int(v) => i64::try_from(v)
+                .map(Value::Int)
+                .or_else(|_| u64::try_from(v).map(Value::UInt))
+                .ok(),
+            Self::Float(v) => Some(Value::Float(v.into())),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Self::Int(v) => v as f64,
+            Self::Float(v) => v,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -28,6 +134,58 @@ pub enum BinaryOp {
 #[cfg_attr(feature = "typescript-schema", ts(export))]
 pub enum UnaryOp {
     Not,
+    /// Lowercase a string.
+    Lower,
+    /// Uppercase a string.
+    Upper,
+    /// Number of characters of a string, or the number of items in a list or
+    /// map.
+    Length,
+    /// Year of a timestamp, in UTC.
+    Year,
+    /// Month (1-12) of a timestamp, in UTC.
+    Month,
+    /// Day of the month of a timestamp, in UTC.
+    Day,
+    /// Truncate a timestamp to the start of the given unit, in UTC.
+    DateTrunc(TimeUnit),
+}
+
+impl UnaryOp {
+    /// Evaluate the operator.
+    ///
+    /// Produces [`Value::Unit`] for unsupported operand types.
+    pub fn eval(&self, value: &Value) -> Value {
+        match (self, value) {
+            (Self::Not, value) => Value::Bool(value.as_bool() != Some(true)),
+            (Self::Lower, Value::String(s)) => Value::String(s.to_lowercase()),
+            (Self::Upper, Value::String(s)) => Value::String(s.to_uppercase()),
+            (Self::Length, Value::String(s)) => Value::UInt(s.chars().count() as u64),
+            (Self::Length, Value::List(items)) => Value::UInt(items.len() as u64),
+            (Self::Length, Value::Map(map)) => Value::UInt(map.len() as u64),
+            (Self::Length, Value::Bytes(bytes)) => Value::UInt(bytes.len() as u64),
+            (Self::Year, value) => timestamp_value(value)
+                .map(|ts| Value::Int(i64::from(ts.to_datetime().year())))
+                .unwrap_or(Value::Unit),
+            (Self::Month, value) => timestamp_value(value)
+                .map(|ts| Value::UInt(u64::from(u8::from(ts.to_datetime().month()))))
+                .unwrap_or(Value::Unit),
+            (Self::Day, value) => timestamp_value(value)
+                .map(|ts| Value::UInt(u64::from(ts.to_datetime().day())))
+                .unwrap_or(Value::Unit),
+            (Self::DateTrunc(unit), value) => timestamp_value(value)
+                .map(|ts| ts.truncate(*unit, time::UtcOffset::UTC).into())
+                .unwrap_or(Value::Unit),
+            _ => Value::Unit,
+        }
+    }
+}
+
+fn timestamp_value(value: &Value) -> Option<Timestamp> {
+    match value {
+        Value::UInt(_) | Value::Int(_) => Timestamp::try_from(value.clone()).ok(),
+        _ => None,
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -38,6 +196,10 @@ pub enum Expr {
     /// Match entities that either match the given entity type or inherit from
     /// it.
     InheritsEntityType(String),
+    /// Match entities of any class in the given namespace.
+    ///
+    /// Expanded to the classes known when the query is planned.
+    TypeInNamespace(String),
     Literal(Value),
     List(Vec<Self>),
     /// Select the value of an attribute.
@@ -59,6 +221,21 @@ pub enum Expr {
         then: Box<Self>,
         or: Box<Self>,
     },
+    /// Match if the value is a [`GeoPoint`] at most `meters` away from
+    /// `center`.
+    WithinRadius {
+        value: Box<Self>,
+        center: GeoPoint,
+        meters: OrderedFloat<f64>,
+    },
+    /// Access a nested value in a [`crate::data::ValueType::Json`] value
+    /// with a JSON path like `$.a.b[0]`.
+    ///
+    /// Evaluates to unit if the path does not exist.
+    JsonPath {
+        value: Box<Self>,
+        path: String,
+    },
 }
 
 impl Expr {
@@ -70,6 +247,12 @@ impl Expr {
         super::mongo::parse_mongo_query(query)
     }
 
+    /// Parse a filter expression using the syntax of a SQL `WHERE` clause.
+    #[cfg(feature = "sql")]
+    pub fn parse_sql_filter(filter: &str) -> Result<Self, super::sql::SqlParseError> {
+        super::sql::parse_filter(filter)
+    }
+
     pub fn as_literal(&self) -> Option<&Value> {
         if let Self::Literal(v) = self {
             Some(v)
@@ -128,6 +311,55 @@ impl Expr {
         Self::unary(UnaryOp::Not, expr)
     }
 
+    pub fn lower<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Lower, expr)
+    }
+
+    pub fn upper<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Upper, expr)
+    }
+
+    pub fn length<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Length, expr)
+    }
+
+    pub fn year<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Year, expr)
+    }
+
+    pub fn month<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Month, expr)
+    }
+
+    pub fn day<I>(expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::Day, expr)
+    }
+
+    pub fn date_trunc<I>(unit: TimeUnit, expr: I) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::unary(UnaryOp::DateTrunc(unit), expr)
+    }
+
     pub fn binary<I1, I2>(left: I1, op: BinaryOp, right: I2) -> Self
     where
         I1: Into<Self>,
@@ -164,6 +396,38 @@ impl Expr {
         )
     }
 
+    pub fn add<I1, I2>(left: I1, right: I2) -> Self
+    where
+        I1: Into<Self>,
+        I2: Into<Self>,
+    {
+        Self::binary(left, BinaryOp::Add, right)
+    }
+
+    pub fn sub<I1, I2>(left: I1, right: I2) -> Self
+    where
+        I1: Into<Self>,
+        I2: Into<Self>,
+    {
+        Self::binary(left, BinaryOp::Sub, right)
+    }
+
+    pub fn mul<I1, I2>(left: I1, right: I2) -> Self
+    where
+        I1: Into<Self>,
+        I2: Into<Self>,
+    {
+        Self::binary(left, BinaryOp::Mul, right)
+    }
+
+    pub fn concat<I1, I2>(left: I1, right: I2) -> Self
+    where
+        I1: Into<Self>,
+        I2: Into<Self>,
+    {
+        Self::binary(left, BinaryOp::Concat, right)
+    }
+
     pub fn in_<I1, I2>(left: I1, right: I2) -> Self
     where
         I1: Into<Self>,
@@ -228,6 +492,19 @@ impl Expr {
         Self::binary(left, BinaryOp::Eq, right)
     }
 
+    /// Case insensitive string equality.
+    ///
+    /// Lowercases both sides before comparing.
+    /// Can use indexes of attributes with
+    /// [`crate::schema::Collation::CaseInsensitive`].
+    pub fn eq_ci<I1, I2>(left: I1, right: I2) -> Self
+    where
+        I1: Into<Self>,
+        I2: Into<Self>,
+    {
+        Self::eq(Self::lower(left), Self::lower(right))
+    }
+
     pub fn neq<I1, I2>(left: I1, right: I2) -> Self
     where
         I1: Into<Self>,
@@ -282,6 +559,33 @@ impl Expr {
         Self::neq(expr, Self::Literal(Value::Unit))
     }
 
+    /// Match geo points within the given distance (in meters) of `center`.
+    ///
+    /// Can use a geo index if the value is an indexed attribute.
+    pub fn within_radius<I>(value: I, center: GeoPoint, meters: f64) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::WithinRadius {
+            value: Box::new(value.into()),
+            center,
+            meters: meters.into(),
+        }
+    }
+
+    /// Access a nested value with a JSON path like `$.a.b[0]`.
+    ///
+    /// Only member access and list indexes are supported.
+    pub fn json_path<I>(value: I, path: impl Into<String>) -> Self
+    where
+        I: Into<Self>,
+    {
+        Self::JsonPath {
+            value: Box::new(value.into()),
+            path: path.into(),
+        }
+    }
+
     pub fn is_entity<T: ClassMeta>() -> Self {
         Self::eq(Expr::attr::<AttrType>(), T::QUALIFIED_NAME)
     }
@@ -293,6 +597,107 @@ impl Expr {
     pub fn is_entity_nested<T: ClassMeta>() -> Self {
         Self::InheritsEntityType(T::QUALIFIED_NAME.to_string())
     }
+
+    /// Match entities of any class in a namespace, like `myapp` for the
+    /// classes `myapp/User` and `myapp/Post`.
+    pub fn type_in_namespace(namespace: impl Into<String>) -> Self {
+        Self::TypeInNamespace(namespace.into())
+    }
+
+    /// Replace all [`Self::Variable`]s with their literal value.
+    ///
+    /// Fails if a variable is not defined.
+    pub fn bind_variables(self, variables: &HashMap<String, Value>) -> Result<Self, anyhow::Error> {
+        let bind = |expr: Box<Self>| expr.bind_variables(variables).map(Box::new);
+        match self {
+            Self::Variable(name) => match variables.get(&name) {
+                Some(value) => Ok(Self::Literal(value.clone())),
+                None => Err(anyhow::anyhow!("Undefined variable '{}'", name)),
+            },
+            Self::List(items) => items
+                .into_iter()
+                .map(|item| item.bind_variables(variables))
+                .collect::<Result<_, _>>()
+                .map(Self::List),
+            Self::UnaryOp { op, expr } => Ok(Self::UnaryOp {
+                op,
+                expr: bind(expr)?,
+            }),
+            Self::BinaryOp { left, op, right } => Ok(Self::BinaryOp {
+                left: bind(left)?,
+                op,
+                right: bind(right)?,
+            }),
+            Self::If { value, then, or } => Ok(Self::If {
+                value: bind(value)?,
+                then: bind(then)?,
+                or: bind(or)?,
+            }),
+            Self::WithinRadius {
+                value,
+                center,
+                meters,
+            } => Ok(Self::WithinRadius {
+                value: bind(value)?,
+                center,
+                meters,
+            }),
+            Self::JsonPath { value, path } => Ok(Self::JsonPath {
+                value: bind(value)?,
+                path,
+            }),
+            other @ (Self::InheritsEntityType(_)
+            | Self::TypeInNamespace(_)
+            | Self::Literal(_)
+            | Self::Attr(_)
+            | Self::Ident(_)) => Ok(other),
+        }
+    }
+
+    /// Replace all literal values with [`Value::Unit`].
+    ///
+    /// Lists of literals are collapsed into a single literal, so only the
+    /// shape of the expression remains.
+    pub fn without_literals(self) -> Self {
+        let strip = |expr: Box<Self>| Box::new(expr.without_literals());
+        match self {
+            Self::Literal(_) => Self::Literal(Value::Unit),
+            Self::List(items) if items.iter().all(|item| item.as_literal().is_some()) => {
+                Self::Literal(Value::Unit)
+            }
+            Self::List(items) => {
+                Self::List(items.into_iter().map(Self::without_literals).collect())
+            }
+            Self::UnaryOp { op, expr } => Self::UnaryOp {
+                op,
+                expr: strip(expr),
+            },
+            Self::BinaryOp { left, op, right } => Self::BinaryOp {
+                left: strip(left),
+                op,
+                right: strip(right),
+            },
+            Self::If { value, then, or } => Self::If {
+                value: strip(value),
+                then: strip(then),
+                or: strip(or),
+            },
+            Self::WithinRadius { value, .. } => Self::WithinRadius {
+                value: strip(value),
+                center: GeoPoint::default(),
+                meters: OrderedFloat(0.0),
+            },
+            Self::JsonPath { value, path } => Self::JsonPath {
+                value: strip(value),
+                path,
+            },
+            other @ (Self::InheritsEntityType(_)
+            | Self::TypeInNamespace(_)
+            | Self::Attr(_)
+            | Self::Ident(_)
+            | Self::Variable(_)) => other,
+        }
+    }
 }
 
 impl<V> From<V> for Expr
@@ -0,0 +1,55 @@
+use crate::data::Id;
+
+/// Direction in which a [`GraphQuery`] follows references.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum GraphDirection {
+    /// Follow references from an entity to the entities it points to.
+    #[default]
+    Outgoing,
+    /// Follow references backwards, to the entities pointing to an entity.
+    Incoming,
+    /// Treat references as undirected edges.
+    Both,
+}
+
+/// Find the shortest path between two entities.
+///
+/// Entities are connected by the values of a single reference attribute.
+/// The path is found with a breadth first search that stops after
+/// `max_depth` edges.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct GraphQuery {
+    pub from: Id,
+    pub to: Id,
+    /// Ident of the attribute that connects entities.
+    ///
+    /// Must hold a reference or a list of references.
+    pub via: String,
+    #[serde(default)]
+    pub direction: GraphDirection,
+    /// Maximum number of edges in the path.
+    pub max_depth: u32,
+}
+
+impl GraphQuery {
+    pub fn new(from: Id, to: Id, via: impl Into<String>, max_depth: u32) -> Self {
+        Self {
+            from,
+            to,
+            via: via.into(),
+            direction: GraphDirection::default(),
+            max_depth,
+        }
+    }
+
+    pub fn with_direction(mut self, direction: GraphDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+}
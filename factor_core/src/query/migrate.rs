@@ -4,7 +4,7 @@ use schema::{Attribute, Class};
 
 use crate::{
     data::{Value, ValueType},
-    schema::{self, Cardinality, IndexSchema},
+    schema::{self, Cardinality, IndexSchema, Mixin, PolicySchema, RuleSchema, ViewSchema},
 };
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -88,6 +88,46 @@ pub struct IndexDelete {
     pub name: String,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PolicyCreate {
+    pub schema: PolicySchema,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PolicyDelete {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ViewCreate {
+    pub schema: ViewSchema,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ViewDelete {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RuleCreate {
+    pub schema: RuleSchema,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RuleDelete {
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MixinCreate {
+    pub schema: Mixin,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MixinDelete {
+    pub name: String,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum SchemaAction {
     AttributeCreate(AttributeCreate),
@@ -103,6 +143,133 @@ pub enum SchemaAction {
     EntityDelete(EntityDelete),
     IndexCreate(IndexCreate),
     IndexDelete(IndexDelete),
+    PolicyCreate(PolicyCreate),
+    PolicyDelete(PolicyDelete),
+    ViewCreate(ViewCreate),
+    ViewDelete(ViewDelete),
+    RuleCreate(RuleCreate),
+    RuleDelete(RuleDelete),
+    MixinCreate(MixinCreate),
+    MixinDelete(MixinDelete),
+}
+
+/// Renders a short, human readable summary of the action.
+impl std::fmt::Display for SchemaAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AttributeCreate(a) => write!(f, "create attribute '{}'", a.schema.ident),
+            Self::AttributeUpsert(a) => write!(f, "upsert attribute '{}'", a.schema.ident),
+            Self::AttributeChangeType(a) => write!(
+                f,
+                "change type of attribute '{}' to {:?}",
+                a.attribute, a.new_type
+            ),
+            Self::AttributeCreateIndex(a) => write!(
+                f,
+                "create {}index for attribute '{}'",
+                if a.unique { "unique " } else { "" },
+                a.attribute
+            ),
+            Self::AttributeDelete(a) => write!(f, "delete attribute '{}'", a.name),
+            Self::EntityCreate(a) => write!(f, "create class '{}'", a.schema.ident),
+            Self::EntityAttributeAdd(a) => {
+                write!(f, "add attribute '{}' to class '{}'", a.attribute, a.entity)
+            }
+            Self::EntityAttributeChangeCardinality(a) => write!(
+                f,
+                "change cardinality of attribute '{}' in class '{}' to {:?}",
+                a.attribute, a.entity_type, a.new_cardinality
+            ),
+            Self::EntityAttributeRemove(a) => write!(
+                f,
+                "remove attribute '{}' from class '{}'{}",
+                a.attribute,
+                a.entity_type,
+                if a.delete_values {
+                    " (deleting values)"
+                } else {
+                    ""
+                }
+            ),
+            Self::EntityUpsert(a) => write!(f, "upsert class '{}'", a.schema.ident),
+            Self::EntityDelete(a) => write!(
+                f,
+                "delete class '{}'{}",
+                a.name,
+                if a.delete_all {
+                    " (deleting all entities)"
+                } else {
+                    ""
+                }
+            ),
+            Self::IndexCreate(a) => write!(f, "create index '{}'", a.schema.ident),
+            Self::IndexDelete(a) => write!(f, "delete index '{}'", a.name),
+            Self::PolicyCreate(a) => write!(
+                f,
+                "create policy '{}' for class '{}'",
+                a.schema.ident, a.schema.class
+            ),
+            Self::PolicyDelete(a) => write!(f, "delete policy '{}'", a.name),
+            Self::ViewCreate(a) => write!(f, "create view '{}'", a.schema.ident),
+            Self::ViewDelete(a) => write!(f, "delete view '{}'", a.name),
+            Self::RuleCreate(a) => write!(
+                f,
+                "create rule '{}' for class '{}'",
+                a.schema.ident, a.schema.class
+            ),
+            Self::RuleDelete(a) => write!(f, "delete rule '{}'", a.name),
+            Self::MixinCreate(a) => write!(f, "create mixin '{}'", a.schema.ident),
+            Self::MixinDelete(a) => write!(f, "delete mixin '{}'", a.name),
+        }
+    }
+}
+
+impl From<MixinDelete> for SchemaAction {
+    fn from(action: MixinDelete) -> Self {
+        SchemaAction::MixinDelete(action)
+    }
+}
+
+impl From<MixinCreate> for SchemaAction {
+    fn from(action: MixinCreate) -> Self {
+        SchemaAction::MixinCreate(action)
+    }
+}
+
+impl From<RuleDelete> for SchemaAction {
+    fn from(action: RuleDelete) -> Self {
+        SchemaAction::RuleDelete(action)
+    }
+}
+
+impl From<RuleCreate> for SchemaAction {
+    fn from(action: RuleCreate) -> Self {
+        SchemaAction::RuleCreate(action)
+    }
+}
+
+impl From<ViewDelete> for SchemaAction {
+    fn from(action: ViewDelete) -> Self {
+        SchemaAction::ViewDelete(action)
+    }
+}
+
+impl From<ViewCreate> for SchemaAction {
+    fn from(action: ViewCreate) -> Self {
+        SchemaAction::ViewCreate(action)
+    }
+}
+
+impl From<PolicyDelete> for SchemaAction {
+    fn from(action: PolicyDelete) -> Self {
+        SchemaAction::PolicyDelete(action)
+    }
+}
+
+impl From<PolicyCreate> for SchemaAction {
+    fn from(action: PolicyCreate) -> Self {
+        SchemaAction::PolicyCreate(action)
+    }
 }
 
 impl From<IndexDelete> for SchemaAction {
@@ -254,6 +421,35 @@ impl Migration {
         self
     }
 
+    /// Upsert all attributes and classes of a schema.
+    ///
+    /// Attributes are upserted first, followed by the classes in the order
+    /// they appear in the schema.
+    /// Indexes are not included, since they are derived from the attributes.
+    /// Policies, views and rules are not included either, use
+    /// [`Self::policy_create`], [`Self::view_create`] and
+    /// [`Self::rule_create`].
+    /// Mixins are not included, and must be created with
+    /// [`Self::mixin_create`] before the classes that use them.
+    pub fn upsert_schema(self, schema: schema::DbSchema) -> Self {
+        let schema::DbSchema {
+            attributes,
+            classes,
+            indexes: _,
+            policies: _,
+            views: _,
+            rules: _,
+            mixins: _,
+        } = schema;
+
+        let mig = attributes
+            .into_iter()
+            .fold(self, |mig, attr| mig.attr_upsert(attr));
+        classes
+            .into_iter()
+            .fold(mig, |mig, class| mig.entity_upsert(class))
+    }
+
     pub fn entity_delete(mut self, name: impl Into<String>, delete_all: bool) -> Self {
         self.actions.push(SchemaAction::EntityDelete(EntityDelete {
             name: name.into(),
@@ -261,6 +457,55 @@ impl Migration {
         }));
         self
     }
+
+    pub fn policy_create(mut self, policy: PolicySchema) -> Self {
+        self.actions
+            .push(SchemaAction::PolicyCreate(PolicyCreate { schema: policy }));
+        self
+    }
+
+    pub fn policy_delete(mut self, name: impl Into<String>) -> Self {
+        self.actions.push(SchemaAction::PolicyDelete(PolicyDelete {
+            name: name.into(),
+        }));
+        self
+    }
+
+    pub fn view_create(mut self, view: ViewSchema) -> Self {
+        self.actions
+            .push(SchemaAction::ViewCreate(ViewCreate { schema: view }));
+        self
+    }
+
+    pub fn view_delete(mut self, name: impl Into<String>) -> Self {
+        self.actions
+            .push(SchemaAction::ViewDelete(ViewDelete { name: name.into() }));
+        self
+    }
+
+    pub fn rule_create(mut self, rule: RuleSchema) -> Self {
+        self.actions
+            .push(SchemaAction::RuleCreate(RuleCreate { schema: rule }));
+        self
+    }
+
+    pub fn rule_delete(mut self, name: impl Into<String>) -> Self {
+        self.actions
+            .push(SchemaAction::RuleDelete(RuleDelete { name: name.into() }));
+        self
+    }
+
+    pub fn mixin_create(mut self, mixin: Mixin) -> Self {
+        self.actions
+            .push(SchemaAction::MixinCreate(MixinCreate { schema: mixin }));
+        self
+    }
+
+    pub fn mixin_delete(mut self, name: impl Into<String>) -> Self {
+        self.actions
+            .push(SchemaAction::MixinDelete(MixinDelete { name: name.into() }));
+        self
+    }
 }
 
 impl Default for Migration {
@@ -303,6 +548,10 @@ pub fn unify_migrations(migrations: Vec<Migration>) -> Result<Migration, UnifyMi
     let mut attributes = Vec::<Attribute>::new();
     let mut entities = Vec::<Class>::new();
     let mut indexes = Vec::<IndexSchema>::new();
+    let mut policies = Vec::<PolicySchema>::new();
+    let mut views = Vec::<ViewSchema>::new();
+    let mut rules = Vec::<RuleSchema>::new();
+    let mut mixins = Vec::<Mixin>::new();
 
     for mig in migrations {
         for action in mig.actions {
@@ -389,6 +638,7 @@ pub fn unify_migrations(migrations: Vec<Migration>) -> Result<Migration, UnifyMi
                         entity.attributes.push(schema::ClassAttribute {
                             attribute: add.attribute.clone().into(),
                             required: add.cardinality.is_required(),
+                            constraints: Vec::new(),
                         });
                     }
                 }
@@ -446,6 +696,74 @@ pub fn unify_migrations(migrations: Vec<Migration>) -> Result<Migration, UnifyMi
                 SchemaAction::IndexDelete(del) => {
                     indexes.retain(|i| i.ident != del.name);
                 }
+                SchemaAction::PolicyCreate(create) => {
+                    let old_create = policies.iter().find(|p| p.ident == create.schema.ident);
+
+                    if let Some(old) = old_create {
+                        if old != &create.schema {
+                            return Err(UnifyMigrationsError::new(format!(
+                                "Duplicate PolicyCreate action for policy {}",
+                                create.schema.ident
+                            )));
+                        }
+                    } else {
+                        policies.push(create.schema);
+                    }
+                }
+                SchemaAction::PolicyDelete(del) => {
+                    policies.retain(|p| p.ident != del.name);
+                }
+                SchemaAction::ViewCreate(create) => {
+                    let old_create = views.iter().find(|v| v.ident == create.schema.ident);
+
+                    if let Some(old) = old_create {
+                        if old != &create.schema {
+                            return Err(UnifyMigrationsError::new(format!(
+                                "Duplicate ViewCreate action for view {}",
+                                create.schema.ident
+                            )));
+                        }
+                    } else {
+                        views.push(create.schema);
+                    }
+                }
+                SchemaAction::ViewDelete(del) => {
+                    views.retain(|v| v.ident != del.name);
+                }
+                SchemaAction::RuleCreate(create) => {
+                    let old_create = rules.iter().find(|r| r.ident == create.schema.ident);
+
+                    if let Some(old) = old_create {
+                        if old != &create.schema {
+                            return Err(UnifyMigrationsError::new(format!(
+                                "Duplicate RuleCreate action for rule {}",
+                                create.schema.ident
+                            )));
+                        }
+                    } else {
+                        rules.push(create.schema);
+                    }
+                }
+                SchemaAction::RuleDelete(del) => {
+                    rules.retain(|r| r.ident != del.name);
+                }
+                SchemaAction::MixinCreate(create) => {
+                    let old_create = mixins.iter().find(|m| m.ident == create.schema.ident);
+
+                    if let Some(old) = old_create {
+                        if old != &create.schema {
+                            return Err(UnifyMigrationsError::new(format!(
+                                "Duplicate MixinCreate action for mixin {}",
+                                create.schema.ident
+                            )));
+                        }
+                    } else {
+                        mixins.push(create.schema);
+                    }
+                }
+                SchemaAction::MixinDelete(del) => {
+                    mixins.retain(|m| m.ident != del.name);
+                }
             }
         }
     }
@@ -453,18 +771,35 @@ pub fn unify_migrations(migrations: Vec<Migration>) -> Result<Migration, UnifyMi
     let attr_create = attributes
         .into_iter()
         .map(|a| SchemaAction::from(AttributeCreate { schema: a }));
+    // Mixins must exist before the classes that use them.
+    let mixin_creates = mixins
+        .into_iter()
+        .map(|m| SchemaAction::from(MixinCreate { schema: m }));
     let entity_creates = entities
         .into_iter()
         .map(|e| SchemaAction::from(EntityCreate { schema: e }));
     let index_creates = indexes
         .into_iter()
         .map(|i| SchemaAction::from(IndexCreate { schema: i }));
+    let policy_creates = policies
+        .into_iter()
+        .map(|p| SchemaAction::from(PolicyCreate { schema: p }));
+    let view_creates = views
+        .into_iter()
+        .map(|v| SchemaAction::from(ViewCreate { schema: v }));
+    let rule_creates = rules
+        .into_iter()
+        .map(|r| SchemaAction::from(RuleCreate { schema: r }));
 
     let main = Migration {
         name: None,
         actions: attr_create
+            .chain(mixin_creates)
             .chain(entity_creates)
             .chain(index_creates)
+            .chain(policy_creates)
+            .chain(view_creates)
+            .chain(rule_creates)
             .collect(),
     };
 
@@ -1,4 +1,5 @@
 pub mod expr;
+pub mod graph;
 pub mod migrate;
 pub mod mutate;
 pub mod select;
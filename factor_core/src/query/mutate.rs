@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 
 use crate::{
-    data::{patch::Patch, DataMap, Id, NilIdError, Value},
-    schema::AttrMapExt,
+    data::{patch::Patch, DataMap, HlcTimestamp, Id, NilIdError, Value, ValueMap},
+    schema::{
+        builtin::{AttrClocks, AttrId},
+        AttrMapExt, AttributeMeta,
+    },
 };
 
 use super::expr::Expr;
@@ -32,15 +35,74 @@ pub struct Replace {
 pub struct Merge {
     pub id: Id,
     pub data: DataMap,
+    /// Hybrid logical clock timestamp of the write.
+    ///
+    /// If set, the merge is reconciled attribute-wise with the stored entity
+    /// instead of overwriting it: each attribute is only written if the
+    /// clock is newer than the clock of the last clocked write of that
+    /// attribute (last-writer-wins).
+    /// Clocks are stored per attribute in [`AttrClocks`].
+    ///
+    /// Used by offline-first clients that write concurrently and sync later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<HlcTimestamp>,
 }
 
 impl Merge {
+    pub fn new(id: Id, data: DataMap) -> Self {
+        Self {
+            id,
+            data,
+            clock: None,
+        }
+    }
+
     pub fn try_from_map(map: DataMap) -> Result<Self, NilIdError> {
         let id = map
             .get_id()
             .and_then(Id::as_non_nil)
             .ok_or_else(|| NilIdError::with_message("Merge data must have a non-nil id"))?;
-        Ok(Self { id, data: map })
+        Ok(Self::new(id, map))
+    }
+
+    pub fn with_clock(mut self, clock: HlcTimestamp) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Reconcile a clocked merge with the current data of the entity.
+    ///
+    /// Drops all attributes that were already written with a newer or equal
+    /// clock, and adds the updated [`AttrClocks`] to the data.
+    /// Merges without a clock are returned unchanged.
+    pub fn resolve_clocks(mut self, current: Option<&DataMap>) -> Self {
+        let clock = match self.clock {
+            Some(clock) => clock,
+            None => return self,
+        };
+
+        let mut clocks = match current.and_then(|c| c.get(AttrClocks::QUALIFIED_NAME)) {
+            Some(Value::Map(clocks)) => clocks.clone(),
+            _ => ValueMap::new(),
+        };
+        self.data.remove(AttrClocks::QUALIFIED_NAME);
+        self.data.retain(|attr, _| {
+            if attr == AttrId::QUALIFIED_NAME {
+                return true;
+            }
+            let key = Value::String(attr.clone());
+            let newer = clocks
+                .get(&key)
+                .and_then(|v| v.as_str()?.parse::<HlcTimestamp>().ok())
+                .map_or(true, |last| clock > last);
+            if newer {
+                clocks.insert(key, Value::String(clock.to_string()));
+            }
+            newer
+        });
+        self.data
+            .insert(AttrClocks::QUALIFIED_NAME.to_string(), Value::Map(clocks));
+        self
     }
 }
 
@@ -80,6 +142,22 @@ pub struct Delete {
     pub id: Id,
 }
 
+/// Require an existing entity to match a filter.
+///
+/// Fails the batch with [`crate::error::EntityNotFound`] if the entity exists
+/// but does not match the filter. Missing entities are accepted.
+///
+/// The filter is evaluated while the batch is applied, so it observes the
+/// preceding actions of the batch and can not race with concurrent writes.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct Guard {
+    pub id: Id,
+    pub filter: Expr,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
@@ -97,6 +175,12 @@ pub struct MutateSelect {
     pub filter: Expr,
     pub variables: HashMap<String, Value>,
     pub action: MutateSelectAction,
+    /// Filter that patched entities must match after the patch.
+    ///
+    /// Fails the batch with [`crate::error::EntityNotFound`] otherwise, just
+    /// like a [`Guard`]. Ignored for deletes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guard: Option<Expr>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -110,6 +194,13 @@ pub enum Mutate {
     Patch(EntityPatch),
     Delete(Delete),
     Select(MutateSelect),
+    /// Savepoint marker.
+    ///
+    /// Ignored by [`crate::db::Db::batch`], which applies the whole batch
+    /// atomically.
+    /// See [`crate::db::Db::batch_with_savepoints`].
+    Savepoint,
+    Guard(Guard),
 }
 
 impl Mutate {
@@ -127,7 +218,7 @@ impl Mutate {
     }
 
     pub fn merge(id: Id, data: DataMap) -> Self {
-        Self::Merge(Merge { id, data })
+        Self::Merge(Merge::new(id, data))
     }
 
     pub fn merge_map(data: DataMap) -> Result<Self, NilIdError> {
@@ -141,6 +232,26 @@ impl Mutate {
     pub fn delete(id: Id) -> Self {
         Self::Delete(Delete { id })
     }
+
+    pub fn guard(id: Id, filter: Expr) -> Self {
+        Self::Guard(Guard { id, filter })
+    }
+
+    /// The id of the affected entity.
+    ///
+    /// Returns `None` for [`Self::Select`], which can affect any number of
+    /// entities, and for [`Self::Savepoint`] and [`Self::Guard`], which do
+    /// not modify any entity.
+    pub fn entity_id(&self) -> Option<Id> {
+        match self {
+            Self::Create(v) => Some(v.id),
+            Self::Replace(v) => Some(v.id),
+            Self::Merge(v) => Some(v.id),
+            Self::Patch(v) => Some(v.id),
+            Self::Delete(v) => Some(v.id),
+            Self::Select(_) | Self::Savepoint | Self::Guard(_) => None,
+        }
+    }
 }
 
 impl From<Create> for Mutate {
@@ -167,6 +278,12 @@ impl From<Delete> for Mutate {
     }
 }
 
+impl From<Guard> for Mutate {
+    fn from(v: Guard) -> Self {
+        Self::Guard(v)
+    }
+}
+
 impl From<MutateSelect> for Mutate {
     fn from(v: MutateSelect) -> Self {
         Self::Select(v)
@@ -223,6 +340,66 @@ impl Batch {
         self.actions.push(Mutate::Select(sel));
         self
     }
+
+    pub fn and_guard(mut self, guard: Guard) -> Self {
+        self.actions.push(Mutate::Guard(guard));
+        self
+    }
+
+    pub fn and_savepoint(mut self) -> Self {
+        self.actions.push(Mutate::Savepoint);
+        self
+    }
+
+    /// Split the batch at [`Mutate::Savepoint`] markers.
+    ///
+    /// Returns one batch more than there are markers, so segments can be
+    /// empty.
+    pub fn into_segments(self) -> Vec<Batch> {
+        let mut segments = vec![Batch::new()];
+        for action in self.actions {
+            match action {
+                Mutate::Savepoint => segments.push(Batch::new()),
+                action => segments.last_mut().unwrap().actions.push(action),
+            }
+        }
+        segments
+    }
+}
+
+/// Status of a single action of a batch applied with
+/// [`crate::db::Db::batch_with_savepoints`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum ActionStatus {
+    Applied,
+    /// The segment containing the action failed, and all of its actions were
+    /// rolled back.
+    RolledBack {
+        error: String,
+    },
+}
+
+/// Result of [`crate::db::Db::batch_with_savepoints`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct BatchReport {
+    /// Status of each action, in batch order.
+    /// Savepoint markers are always [`ActionStatus::Applied`].
+    pub actions: Vec<ActionStatus>,
+}
+
+impl BatchReport {
+    /// Whether all actions were applied.
+    pub fn is_complete(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|status| *status == ActionStatus::Applied)
+    }
 }
 
 impl Default for Batch {
@@ -55,6 +55,10 @@ pub struct Select {
     #[serde(default)]
     pub offset: u64,
     pub cursor: Option<Id>,
+    /// Cache the result for this many milliseconds.
+    /// See [`Select::cached`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl_ms: Option<u64>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -83,6 +87,7 @@ impl Select {
             limit: 0,
             offset: 0,
             cursor: None,
+            cache_ttl_ms: None,
         }
     }
 
@@ -123,6 +128,48 @@ impl Select {
         self.aggregate.push(Aggregation { name, op });
         self
     }
+
+    /// Allow the result to be served from a cache for up to `ttl`.
+    ///
+    /// Cached results are invalidated early when entities of the queried
+    /// classes change, so the TTL only bounds how long a result is kept.
+    /// Backends without a result cache ignore this setting.
+    pub fn cached(mut self, ttl: std::time::Duration) -> Self {
+        self.cache_ttl_ms = Some(ttl.as_millis().min(u64::MAX as u128) as u64);
+        self
+    }
+
+    pub fn cache_ttl(&self) -> Option<std::time::Duration> {
+        self.cache_ttl_ms.map(std::time::Duration::from_millis)
+    }
+
+    /// A stable fingerprint of the query shape.
+    ///
+    /// Literal values, variables, limits, offsets and cursors are ignored, so
+    /// queries that only differ in parameters share a fingerprint.
+    /// Useful for grouping queries in logs and traces.
+    pub fn fingerprint(&self) -> String {
+        use std::hash::Hasher;
+
+        let shape = Self {
+            filter: self.filter.clone().map(Expr::without_literals),
+            joins: self.joins.clone(),
+            sort: self
+                .sort
+                .iter()
+                .map(|sort| Sort {
+                    on: sort.on.clone().without_literals(),
+                    order: sort.order,
+                })
+                .collect(),
+            aggregate: self.aggregate.clone(),
+            ..Self::new()
+        };
+        let mut hasher = fnv::FnvHasher::default();
+        // Serializing a select can not fail.
+        hasher.write(&serde_json::to_vec(&shape).unwrap_or_default());
+        format!("{:016x}", hasher.finish())
+    }
 }
 
 impl Default for Select {
@@ -245,3 +292,24 @@ impl Page<Item<DataMap>> {
 }
 
 pub type ItemPage<T = DataMap> = Page<Item<T>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_fingerprint() {
+        let query = |title: &str, limit: u64| {
+            Select::new()
+                .with_filter(Expr::eq(Expr::attr_ident("factor/title"), title))
+                .with_limit(limit)
+        };
+        assert_eq!(query("a", 1).fingerprint(), query("b", 10).fingerprint());
+        assert_ne!(
+            query("a", 1).fingerprint(),
+            Select::new()
+                .with_filter(Expr::eq(Expr::attr_ident("factor/description"), "a"))
+                .fingerprint()
+        );
+    }
+}
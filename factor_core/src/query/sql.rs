@@ -3,13 +3,13 @@ use std::{collections::HashMap, convert::TryInto};
 use crate::{
     data::{
         patch::{Patch, PatchPathElem},
-        Value, ValueType,
+        TimeUnit, Value, ValueType,
     },
     query::select::Aggregation,
 };
 
 use super::{
-    expr::{BinaryOp, Expr},
+    expr::{BinaryOp, Expr, UnaryOp},
     mutate::{MutateSelect, MutateSelectAction},
     select::{Order, Select, Sort},
 };
@@ -57,6 +57,14 @@ pub enum ParsedSqlQuery {
 }
 
 impl ParsedSqlQuery {
+    pub fn as_select(&self) -> Option<&Select> {
+        if let Self::Select(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
     pub fn as_mutate(&self) -> Option<&MutateSelect> {
         if let Self::Mutate(v) = self {
             Some(v)
@@ -125,6 +133,21 @@ pub fn parse_select(sql: &str) -> Result<Select, SqlParseError> {
     build_select(query)
 }
 
+/// Parse a standalone filter expression, using the syntax of a `WHERE` clause.
+pub fn parse_filter(filter: &str) -> Result<Expr, SqlParseError> {
+    let mut select = parse_select(&format!("SELECT * FROM entities WHERE {}", filter))?;
+    let expr = select
+        .filter
+        .take()
+        .ok_or_else(|| SqlParseError::new("Empty filter expression"))?;
+    if select != Select::new() {
+        return Err(SqlParseError::new(
+            "Invalid filter: expected a single expression",
+        ));
+    }
+    Ok(expr)
+}
+
 fn parse_single_statement(sql: &str) -> Result<ast::Statement, SqlParseError> {
     let statements =
         sqlparser::parser::Parser::parse_sql(&sqlparser::dialect::GenericDialect {}, sql).map_err(
@@ -337,6 +360,7 @@ pub fn build_select(query: ast::Query) -> Result<Select, SqlParseError> {
         limit,
         offset,
         cursor: None,
+        cache_ttl_ms: None,
     })
 }
 
@@ -366,6 +390,72 @@ fn select_item_as_count(item: &SelectItem) -> (bool, Option<String>) {
     }
 }
 
+/// Build a scalar function call.
+///
+/// Supports LOWER(x), UPPER(x), LENGTH(x), YEAR(x), MONTH(x), DAY(x) and
+/// DATE_TRUNC('unit', x).
+fn build_function(f: ast::Function) -> Result<Expr, SqlParseError> {
+    let name = match f.name.0.as_slice() {
+        [name] => name.value.to_lowercase(),
+        _ => {
+            return Err(SqlParseError::new(format!(
+                "function {} not supported",
+                f.name
+            )));
+        }
+    };
+
+    let mut args = Vec::new();
+    for arg in f.args {
+        match arg {
+            ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(arg)) => args.push(arg),
+            _ => {
+                return Err(SqlParseError::new(format!(
+                    "unsupported argument for function {}",
+                    name
+                )));
+            }
+        }
+    }
+
+    let op = match name.as_str() {
+        "lower" => UnaryOp::Lower,
+        "upper" => UnaryOp::Upper,
+        "length" => UnaryOp::Length,
+        "year" => UnaryOp::Year,
+        "month" => UnaryOp::Month,
+        "day" => UnaryOp::Day,
+        "date_trunc" => {
+            let unit = match args.as_slice() {
+                [SqlExpr::Value(SqlValue::SingleQuotedString(unit)), _] => unit
+                    .parse::<TimeUnit>()
+                    .map_err(|err| SqlParseError::new(err.to_string()))?,
+                _ => {
+                    return Err(SqlParseError::new(
+                        "function date_trunc expects a unit string and a value",
+                    ));
+                }
+            };
+            args.remove(0);
+            UnaryOp::DateTrunc(unit)
+        }
+        _ => {
+            return Err(SqlParseError::new(format!(
+                "function {} not supported",
+                f.name
+            )));
+        }
+    };
+
+    match (args.pop(), args.is_empty()) {
+        (Some(arg), true) => Ok(Expr::unary(op, build_expr(arg)?)),
+        _ => Err(SqlParseError::new(format!(
+            "function {} expects exactly one argument",
+            name
+        ))),
+    }
+}
+
 fn build_expr(expr: SqlExpr) -> Result<Expr, SqlParseError> {
     let e = match expr {
         SqlExpr::Identifier(ident) => Expr::Attr(ident.value.into()),
@@ -445,12 +535,12 @@ fn build_expr(expr: SqlExpr) -> Result<Expr, SqlParseError> {
                     ast::BinaryOperator::NotEq => BinaryOp::Neq,
                     ast::BinaryOperator::And => BinaryOp::And,
                     ast::BinaryOperator::Or => BinaryOp::Or,
-                    // ast::BinaryOperator::Plus => todo!(),
-                    // ast::BinaryOperator::Minus => todo!(),
-                    // ast::BinaryOperator::Multiply => todo!(),
+                    ast::BinaryOperator::Plus => BinaryOp::Add,
+                    ast::BinaryOperator::Minus => BinaryOp::Sub,
+                    ast::BinaryOperator::Multiply => BinaryOp::Mul,
                     // ast::BinaryOperator::Divide => todo!(),
                     // ast::BinaryOperator::Modulo => todo!(),
-                    // ast::BinaryOperator::StringConcat => todo!(),
+                    ast::BinaryOperator::StringConcat => BinaryOp::Concat,
                     // ast::BinaryOperator::Spaceship => todo!(),
                     // ast::BinaryOperator::Xor => todo!(),
                     // ast::BinaryOperator::NotLike => todo!(),
@@ -595,9 +685,7 @@ fn build_expr(expr: SqlExpr) -> Result<Expr, SqlParseError> {
         SqlExpr::MapAccess { .. } => {
             return Err(SqlParseError::new("map accessors not supported"));
         }
-        SqlExpr::Function(_) => {
-            return Err(SqlParseError::new("functions not supported"));
-        }
+        SqlExpr::Function(f) => build_function(f)?,
         SqlExpr::Case { .. } => {
             return Err(SqlParseError::new("CASE is not supported"));
         }
@@ -726,6 +814,7 @@ fn build_delete(del: SqlDelete) -> Result<MutateSelect, SqlParseError> {
         filter,
         variables: Default::default(),
         action: MutateSelectAction::Delete,
+        guard: None,
     })
 }
 
@@ -763,6 +852,7 @@ fn build_update(up: SqlUpdate) -> Result<MutateSelect, SqlParseError> {
         filter,
         variables: Default::default(),
         action: MutateSelectAction::Patch(patch),
+        guard: None,
     })
 }
 
@@ -874,6 +964,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_computed_expressions() {
+        assert_eq!(
+            parse_filter(r#""price" * "quantity" > 100"#).unwrap(),
+            Expr::gt(
+                Expr::mul(Expr::attr_ident("price"), Expr::attr_ident("quantity")),
+                100u64
+            ),
+        );
+        assert_eq!(
+            parse_filter(r#""a" + 1 - "b" = 0"#).unwrap(),
+            Expr::eq(
+                Expr::sub(
+                    Expr::add(Expr::attr_ident("a"), 1u64),
+                    Expr::attr_ident("b")
+                ),
+                0u64
+            ),
+        );
+        assert_eq!(
+            parse_filter(r#"LOWER("a" || 'x') = 'hx'"#).unwrap(),
+            Expr::eq(Expr::lower(Expr::concat(Expr::attr_ident("a"), "x")), "hx"),
+        );
+        assert_eq!(
+            parse_filter(r#"length(upper("a")) >= 3"#).unwrap(),
+            Expr::gte(Expr::length(Expr::upper(Expr::attr_ident("a"))), 3u64),
+        );
+        assert!(parse_filter(r#"length("a", "b") = 1"#).is_err());
+        assert!(parse_filter(r#"trim("a") = 'x'"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_date_functions() {
+        assert_eq!(
+            parse_filter(r#"YEAR("createdAt") = 2022"#).unwrap(),
+            Expr::eq(Expr::year(Expr::attr_ident("createdAt")), 2022u64),
+        );
+        assert_eq!(
+            parse_filter(r#"date_trunc('Day', "createdAt") = 0"#).unwrap(),
+            Expr::eq(
+                Expr::date_trunc(TimeUnit::Day, Expr::attr_ident("createdAt")),
+                0u64
+            ),
+        );
+        assert!(parse_filter(r#"date_trunc('fortnight', "a") = 0"#).is_err());
+        assert!(parse_filter(r#"date_trunc("a") = 0"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            parse_filter(r#""factor/type" = 'sometype'"#).unwrap(),
+            Expr::eq(Expr::attr::<AttrType>(), "sometype"),
+        );
+        assert!(parse_filter("").is_err());
+        assert!(parse_filter(r#""a" = 1 LIMIT 5"#).is_err());
+    }
+
     #[test]
     fn test_sql_parse_delete() {
         let m1 = parse_sql(r#"DELETE FROM entities WHERE "factor/id" = 42"#).unwrap();
@@ -883,6 +1031,7 @@ mod tests {
                 filter: Expr::eq(AttrId::expr(), 42u64),
                 variables: Default::default(),
                 action: MutateSelectAction::Delete,
+                guard: None,
             }
         );
     }
@@ -898,6 +1047,7 @@ mod tests {
                 filter: Expr::eq(AttrId::expr(), 42u64),
                 variables: Default::default(),
                 action: MutateSelectAction::Patch(Patch::new().replace("factor/title", "hello")),
+                guard: None,
             }
         );
     }
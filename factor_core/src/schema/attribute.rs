@@ -4,6 +4,45 @@ use crate::data::{Id, IdOrIdent, InvalidIdentError, Value, ValueMap, ValueType};
 
 use super::ClassContainer;
 
+/// Collation of string values.
+///
+/// Determines how string values of an attribute are normalized in indexes
+/// and compared when sorting.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum Collation {
+    /// Strings are compared as is.
+    #[default]
+    Binary,
+    /// Strings are compared ignoring case.
+    ///
+    /// Unique indexes reject values that only differ in case.
+    CaseInsensitive,
+}
+
+impl Collation {
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Binary)
+    }
+
+    /// Normalize a value so that values that are equal under this collation
+    /// are also equal with [`PartialEq`].
+    ///
+    /// Strings in lists are normalized as well.
+    pub fn normalize(&self, value: Value) -> Value {
+        match (self, value) {
+            (Self::Binary, value) => value,
+            (Self::CaseInsensitive, Value::String(s)) => Value::String(s.to_lowercase()),
+            (Self::CaseInsensitive, Value::List(items)) => {
+                Value::List(items.into_iter().map(|v| self.normalize(v)).collect())
+            }
+            (Self::CaseInsensitive, other) => other,
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
@@ -27,6 +66,14 @@ pub struct Attribute {
     /// in entities with a class that specifies the attribute.
     #[serde(rename = "factor/isStrict", default)]
     pub strict: bool,
+    /// Collation of string values.
+    /// Only valid for string attributes.
+    #[serde(
+        rename = "factor/collation",
+        default,
+        skip_serializing_if = "Collation::is_binary"
+    )]
+    pub collation: Collation,
 }
 
 impl Attribute {
@@ -40,6 +87,7 @@ impl Attribute {
             unique: false,
             index: false,
             strict: false,
+            collation: Collation::Binary,
         }
     }
 
@@ -72,6 +120,11 @@ impl Attribute {
         self
     }
 
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
     /// Split the ident into (namespace, name)
     pub fn parse_split_ident(&self) -> Result<(&str, &str), InvalidIdentError> {
         crate::data::Ident::parse_parts(&self.ident)
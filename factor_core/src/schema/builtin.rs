@@ -6,8 +6,11 @@
 //! which are statically defined.
 
 use crate::{
-    data::{value_type::ConstrainedRefType, Id, IdOrIdent, Ident, ValueType},
-    schema::{Attribute, AttributeMeta, Class, ClassAttribute, ClassMeta},
+    data::{
+        value_type::{ConstrainedRefType, MapType},
+        Id, IdOrIdent, Ident, ValueType,
+    },
+    schema::{Attribute, AttributeMeta, Class, ClassAttribute, ClassMeta, Collation, IdStrategy},
 };
 
 use super::IndexSchema;
@@ -33,17 +36,37 @@ pub const ATTR_COUNT: Id = Id::from_u128(14);
 pub const ATTR_ATTRIBUTE: Id = Id::from_u128(15);
 pub const ATTR_REQUIRED: Id = Id::from_u128(16);
 pub const ATTR_CLASSES: Id = Id::from_u128(17);
+pub const ATTR_TENANT: Id = Id::from_u128(18);
+pub const ATTR_TENANT_SCOPED: Id = Id::from_u128(19);
+pub const ATTR_AUDIT_ENTITY: Id = Id::from_u128(20);
+pub const ATTR_AUDIT_ACTOR: Id = Id::from_u128(21);
+pub const ATTR_AUDIT_TIMESTAMP: Id = Id::from_u128(22);
+pub const ATTR_AUDIT_OPERATION: Id = Id::from_u128(23);
+pub const ATTR_AUDIT_ATTRIBUTES: Id = Id::from_u128(24);
+pub const ATTR_AUDIT_REVERT: Id = Id::from_u128(25);
+pub const ATTR_ID_STRATEGY: Id = Id::from_u128(26);
+pub const ATTR_COLLATION: Id = Id::from_u128(27);
+pub const ATTR_MAX_ENTITIES: Id = Id::from_u128(28);
+pub const ATTR_SPARSE: Id = Id::from_u128(29);
+pub const ATTR_COMPUTED_ATTRIBUTES: Id = Id::from_u128(30);
+pub const ATTR_CLOCKS: Id = Id::from_u128(31);
+pub const ATTR_ALIASES: Id = Id::from_u128(32);
+pub const ATTR_MIXINS: Id = Id::from_u128(33);
+pub const ATTR_IS_ABSTRACT: Id = Id::from_u128(34);
 
 // Built-in entity types.
 // Constants are kept together to see ids at a glance.
 pub const ATTRIBUTE_ID: Id = Id::from_u128(1000);
 pub const ENTITY_ID: Id = Id::from_u128(1001);
 pub const INDEX_ID: Id = Id::from_u128(1002);
+pub const AUDIT_ENTRY_ID: Id = Id::from_u128(1003);
 
 // Built-in indexes.
 // Constants are kept together to see ids at a glance.
 pub const INDEX_ENTITY_TYPE: Id = Id::from_u128(2001);
 pub const INDEX_IDENT: Id = Id::from_u128(2002);
+pub const INDEX_TENANT: Id = Id::from_u128(2003);
+pub const INDEX_ALIASES: Id = Id::from_u128(2004);
 
 pub struct AttrId;
 
@@ -63,6 +86,7 @@ impl AttributeMeta for AttrId {
             unique: true,
             index: true,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -85,6 +109,36 @@ impl AttributeMeta for AttrIdent {
             unique: true,
             index: true,
             strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+/// Alternative idents of an entity.
+///
+/// Entities can be resolved by any of their aliases, which allows renaming
+/// an entity by moving the old ident to the aliases.
+/// Aliases are unique across all entities, but are not checked against
+/// idents. Idents take precedence when resolving a name.
+pub struct AttrAliases;
+
+impl AttributeMeta for AttrAliases {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "aliases";
+    const QUALIFIED_NAME: &'static str = "factor/aliases";
+    type Type = Vec<String>;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_ALIASES,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Aliases".into()),
+            description: None,
+            value_type: ValueType::List(Box::new(ValueType::String)),
+            unique: true,
+            index: true,
+            strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -107,6 +161,7 @@ impl AttributeMeta for AttrTitle {
             unique: true,
             index: true,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -125,12 +180,11 @@ impl AttributeMeta for AttrType {
             ident: Self::QUALIFIED_NAME.to_string(),
             title: Some("Type".into()),
             description: None,
-            value_type: ValueType::Ident(ConstrainedRefType {
-                allowed_entity_types: vec![Class::IDENT],
-            }),
+            value_type: ValueType::Ident(ConstrainedRefType::new(vec![Class::IDENT])),
             unique: false,
             index: true,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -153,6 +207,7 @@ impl AttributeMeta for AttrValueType {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -175,6 +230,7 @@ impl AttributeMeta for AttrUnique {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -197,6 +253,7 @@ impl AttributeMeta for AttrIndex {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -219,6 +276,7 @@ impl AttributeMeta for AttrDescription {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -241,6 +299,7 @@ impl AttributeMeta for AttrStrict {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -262,6 +321,11 @@ impl ClassMeta for AttributeConstraint {
             attributes: vec![],
             extends: vec![],
             strict: false,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -286,6 +350,7 @@ impl AttributeMeta for AttrClasses {
             unique: false,
             index: false,
             strict: false,
+            collation: Collation::Binary,
         }
     }
 }
@@ -309,9 +374,15 @@ impl ClassMeta for AttributeConstraintReferenceClasses {
             attributes: vec![ClassAttribute {
                 attribute: AttrClasses::QUALIFIED_NAME.to_string(),
                 required: true,
+                constraints: Vec::new(),
             }],
             extends: vec![],
             strict: false,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -336,9 +407,15 @@ impl ClassMeta for super::Attribute {
                 ClassAttribute::from_schema_required::<AttrUnique>(),
                 ClassAttribute::from_schema_required::<AttrIndex>(),
                 ClassAttribute::from_schema_required::<AttrStrict>(),
+                ClassAttribute::from_schema_optional::<AttrCollation>(),
             ],
             extends: Vec::new(),
             strict: true,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -362,6 +439,7 @@ impl AttributeMeta for AttrAttribute {
             unique: false,
             index: false,
             strict: false,
+            collation: Collation::Binary,
         }
     }
 }
@@ -385,6 +463,7 @@ impl AttributeMeta for AttrRequired {
             unique: false,
             index: false,
             strict: false,
+            collation: Collation::Binary,
         }
     }
 }
@@ -411,9 +490,9 @@ impl AttributeMeta for AttrClassAttributes {
                     fields: vec![
                         crate::data::value_type::ObjectField {
                             name: "attribute".to_string(),
-                            value_type: ValueType::Ident(ConstrainedRefType {
-                                allowed_entity_types: vec![Attribute::IDENT],
-                            }),
+                            value_type: ValueType::Ident(ConstrainedRefType::new(vec![
+                                Attribute::IDENT,
+                            ])),
                         },
                         crate::data::value_type::ObjectField {
                             name: "cardinality".to_string(),
@@ -428,6 +507,7 @@ impl AttributeMeta for AttrClassAttributes {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -446,12 +526,13 @@ impl AttributeMeta for AttrExtend {
             ident: Self::QUALIFIED_NAME.to_string(),
             title: Some("Extends".into()),
             description: None,
-            value_type: ValueType::List(Box::new(ValueType::Ident(ConstrainedRefType {
-                allowed_entity_types: vec![Class::IDENT],
-            }))),
+            value_type: ValueType::List(Box::new(ValueType::Ident(ConstrainedRefType::new(vec![
+                Class::IDENT,
+            ])))),
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -474,6 +555,7 @@ impl AttributeMeta for AttrIsRelation {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -498,9 +580,19 @@ impl ClassMeta for super::Class {
                 ClassAttribute::from_schema_required::<AttrIsRelation>(),
                 ClassAttribute::from_schema_required::<AttrExtend>(),
                 ClassAttribute::from_schema_required::<AttrClassAttributes>(),
+                ClassAttribute::from_schema_optional::<AttrIdStrategy>(),
+                ClassAttribute::from_schema_optional::<AttrMaxEntities>(),
+                ClassAttribute::from_schema_optional::<AttrComputedAttributes>(),
+                ClassAttribute::from_schema_optional::<AttrMixins>(),
+                ClassAttribute::from_schema_optional::<AttrIsAbstract>(),
             ],
             extends: Vec::new(),
             strict: true,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -523,6 +615,207 @@ impl AttributeMeta for AttrCount {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+// Multi-tenancy.
+
+pub struct AttrTenant;
+
+impl AttributeMeta for AttrTenant {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "tenant";
+    const QUALIFIED_NAME: &'static str = "factor/tenant";
+    type Type = Id;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_TENANT,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Tenant".into()),
+            description: Some("The tenant that owns an entity.".into()),
+            value_type: ValueType::Ref,
+            unique: false,
+            index: true,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+// Audit log attributes and entity type.
+
+pub struct AttrAuditEntity;
+
+impl AttributeMeta for AttrAuditEntity {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditEntity";
+    const QUALIFIED_NAME: &'static str = "factor/auditEntity";
+    type Type = Id;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_ENTITY,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Audited Entity".into()),
+            description: Some("The entity that was modified.".into()),
+            value_type: ValueType::Ref,
+            unique: false,
+            index: true,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrAuditActor;
+
+impl AttributeMeta for AttrAuditActor {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditActor";
+    const QUALIFIED_NAME: &'static str = "factor/auditActor";
+    type Type = Id;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_ACTOR,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Actor".into()),
+            description: Some("The user that performed the modification.".into()),
+            value_type: ValueType::Ref,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrAuditTimestamp;
+
+impl AttributeMeta for AttrAuditTimestamp {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditTimestamp";
+    const QUALIFIED_NAME: &'static str = "factor/auditTimestamp";
+    type Type = u64;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_TIMESTAMP,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Timestamp".into()),
+            description: None,
+            value_type: ValueType::DateTime,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrAuditOperation;
+
+impl AttributeMeta for AttrAuditOperation {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditOperation";
+    const QUALIFIED_NAME: &'static str = "factor/auditOperation";
+    type Type = String;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_OPERATION,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Operation".into()),
+            description: Some("One of create, replace, merge, patch or delete.".into()),
+            value_type: ValueType::String,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrAuditAttributes;
+
+impl AttributeMeta for AttrAuditAttributes {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditAttributes";
+    const QUALIFIED_NAME: &'static str = "factor/auditAttributes";
+    type Type = Vec<String>;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_ATTRIBUTES,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Changed Attributes".into()),
+            description: None,
+            value_type: ValueType::List(Box::new(ValueType::String)),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrAuditRevert;
+
+impl AttributeMeta for AttrAuditRevert {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "auditRevert";
+    const QUALIFIED_NAME: &'static str = "factor/auditRevert";
+    type Type = crate::data::Value;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_AUDIT_REVERT,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Revert Patch".into()),
+            description: Some(
+                "A serialized Patch that restores the previous values of the entity.".into(),
+            ),
+            value_type: ValueType::Json,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AuditEntryType;
+
+impl ClassMeta for AuditEntryType {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "AuditEntry";
+    const QUALIFIED_NAME: &'static str = "factor/AuditEntry";
+
+    fn schema() -> Class {
+        Class {
+            id: AUDIT_ENTRY_ID,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Audit Entry".into()),
+            description: Some("A recorded mutation of an entity.".into()),
+            attributes: vec![
+                ClassAttribute::from_schema_required::<AttrId>(),
+                ClassAttribute::from_schema_required::<AttrAuditEntity>(),
+                ClassAttribute::from_schema_optional::<AttrAuditActor>(),
+                ClassAttribute::from_schema_required::<AttrAuditTimestamp>(),
+                ClassAttribute::from_schema_required::<AttrAuditOperation>(),
+                ClassAttribute::from_schema_required::<AttrAuditAttributes>(),
+                ClassAttribute::from_schema_required::<AttrAuditRevert>(),
+            ],
+            extends: Vec::new(),
+            strict: true,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -547,6 +840,227 @@ impl AttributeMeta for AttrIndexAttributes {
             unique: false,
             index: false,
             strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrTenantScoped;
+
+impl AttributeMeta for AttrTenantScoped {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "tenantScoped";
+    const QUALIFIED_NAME: &'static str = "factor/tenantScoped";
+    type Type = bool;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_TENANT_SCOPED,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Tenant Scoped".into()),
+            description: None,
+            value_type: ValueType::Bool,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrSparse;
+
+impl AttributeMeta for AttrSparse {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "sparse";
+    const QUALIFIED_NAME: &'static str = "factor/sparse";
+    type Type = bool;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_SPARSE,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Sparse".into()),
+            description: Some("Skip unit values when indexing.".into()),
+            value_type: ValueType::Bool,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrIdStrategy;
+
+impl AttributeMeta for AttrIdStrategy {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "idStrategy";
+    const QUALIFIED_NAME: &'static str = "factor/idStrategy";
+    type Type = IdStrategy;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_ID_STRATEGY,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Id Strategy".into()),
+            description: Some("How ids of new entities of a class are generated.".into()),
+            value_type: ValueType::Union(vec![
+                ValueType::Const("Random".into()),
+                ValueType::Const("TimeOrdered".into()),
+            ]),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrCollation;
+
+impl AttributeMeta for AttrCollation {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "collation";
+    const QUALIFIED_NAME: &'static str = "factor/collation";
+    type Type = Collation;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_COLLATION,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Collation".into()),
+            description: Some("How string values of an attribute are compared.".into()),
+            value_type: ValueType::Union(vec![
+                ValueType::Const("Binary".into()),
+                ValueType::Const("CaseInsensitive".into()),
+            ]),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrMaxEntities;
+
+impl AttributeMeta for AttrMaxEntities {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "maxEntities";
+    const QUALIFIED_NAME: &'static str = "factor/maxEntities";
+    type Type = u64;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_MAX_ENTITIES,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Max Entities".into()),
+            description: Some("Maximum number of entities of a class.".into()),
+            value_type: ValueType::UInt,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrComputedAttributes;
+
+impl AttributeMeta for AttrComputedAttributes {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "computedAttributes";
+    const QUALIFIED_NAME: &'static str = "factor/computedAttributes";
+    type Type = Vec<super::ComputedAttribute>;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_COMPUTED_ATTRIBUTES,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Computed Attributes".into()),
+            description: Some("Attributes computed from other attributes of a class.".into()),
+            value_type: ValueType::List(Box::new(ValueType::Any)),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrMixins;
+
+impl AttributeMeta for AttrMixins {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "mixins";
+    const QUALIFIED_NAME: &'static str = "factor/mixins";
+    type Type = Vec<String>;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_MIXINS,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Mixins".into()),
+            description: Some("Mixins whose attributes are added to a class.".into()),
+            value_type: ValueType::List(Box::new(ValueType::String)),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+pub struct AttrIsAbstract;
+
+impl AttributeMeta for AttrIsAbstract {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "isAbstract";
+    const QUALIFIED_NAME: &'static str = "factor/isAbstract";
+    type Type = bool;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_IS_ABSTRACT,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Is abstract".into()),
+            description: Some("Abstract classes can only be extended, not instantiated.".into()),
+            value_type: ValueType::Bool,
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
+        }
+    }
+}
+
+/// Hybrid logical clocks of the last clocked write of each attribute.
+///
+/// Maintained by merges with a clock, see
+/// [`crate::query::mutate::Merge::clock`].
+pub struct AttrClocks;
+
+impl AttributeMeta for AttrClocks {
+    const NAMESPACE: &'static str = "factor";
+    const PLAIN_NAME: &'static str = "clocks";
+    const QUALIFIED_NAME: &'static str = "factor/clocks";
+    type Type = crate::data::ValueMap<crate::data::Value>;
+
+    fn schema() -> Attribute {
+        Attribute {
+            id: ATTR_CLOCKS,
+            ident: Self::QUALIFIED_NAME.to_string(),
+            title: Some("Clocks".into()),
+            description: Some("Hybrid logical clocks of the last write of each attribute.".into()),
+            value_type: ValueType::Map(Box::new(MapType {
+                key: ValueType::String,
+                value: ValueType::String,
+            })),
+            unique: false,
+            index: false,
+            strict: true,
+            collation: Collation::Binary,
         }
     }
 }
@@ -573,6 +1087,11 @@ impl ClassMeta for IndexSchemaType {
             ],
             extends: Vec::new(),
             strict: true,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -585,6 +1104,10 @@ fn index_entity_type() -> IndexSchema {
         attributes: vec![ATTR_TYPE],
         description: None,
         unique: false,
+        tenant_scoped: false,
+        sparse: false,
+        filter: None,
+        multi_valued: false,
     }
 }
 
@@ -596,6 +1119,40 @@ fn index_ident() -> IndexSchema {
         attributes: vec![ATTR_IDENT],
         description: None,
         unique: true,
+        tenant_scoped: false,
+        sparse: false,
+        filter: None,
+        multi_valued: false,
+    }
+}
+
+fn index_aliases() -> IndexSchema {
+    IndexSchema {
+        id: INDEX_ALIASES,
+        ident: "factor/index_aliases".into(),
+        title: Some("Global ident alias index".into()),
+        attributes: vec![ATTR_ALIASES],
+        description: None,
+        unique: true,
+        tenant_scoped: false,
+        sparse: false,
+        filter: None,
+        multi_valued: true,
+    }
+}
+
+fn index_tenant() -> IndexSchema {
+    IndexSchema {
+        id: INDEX_TENANT,
+        ident: "factor/index_tenant".into(),
+        title: Some("Global tenant attribute index".into()),
+        attributes: vec![ATTR_TENANT],
+        description: None,
+        unique: false,
+        tenant_scoped: false,
+        sparse: false,
+        filter: None,
+        multi_valued: false,
     }
 }
 
@@ -616,18 +1173,45 @@ pub fn builtin_db_schema() -> super::DbSchema {
             AttrIsRelation::schema(),
             AttrIndexAttributes::schema(),
             AttrCount::schema(),
+            AttrTenant::schema(),
+            AttrTenantScoped::schema(),
+            AttrAuditEntity::schema(),
+            AttrAuditActor::schema(),
+            AttrAuditTimestamp::schema(),
+            AttrAuditOperation::schema(),
+            AttrAuditAttributes::schema(),
+            AttrAuditRevert::schema(),
+            AttrIdStrategy::schema(),
+            AttrCollation::schema(),
+            AttrMaxEntities::schema(),
+            AttrSparse::schema(),
+            AttrComputedAttributes::schema(),
+            AttrClocks::schema(),
+            AttrAliases::schema(),
+            AttrMixins::schema(),
+            AttrIsAbstract::schema(),
         ],
         classes: vec![
             Attribute::schema(),
             Class::schema(),
             IndexSchemaType::schema(),
+            AuditEntryType::schema(),
+        ],
+        indexes: vec![
+            index_entity_type(),
+            index_ident(),
+            index_tenant(),
+            index_aliases(),
         ],
-        indexes: vec![index_entity_type(), index_ident()],
+        policies: vec![],
+        views: vec![],
+        rules: vec![],
+        mixins: vec![],
     }
 }
 
 /// Check if an [`Id`] is a builtin entity *type*.
 #[inline]
 pub fn id_is_builtin_entity_type(id: Id) -> bool {
-    matches!(id, ATTRIBUTE_ID | ENTITY_ID | INDEX_ID)
+    matches!(id, ATTRIBUTE_ID | ENTITY_ID | INDEX_ID | AUDIT_ENTRY_ID)
 }
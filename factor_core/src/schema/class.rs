@@ -1,8 +1,11 @@
 use serde::de::DeserializeOwned;
 
-use crate::data::{value::ValueDeserializeError, DataMap, Id, IdOrIdent, InvalidIdentError};
+use crate::{
+    data::{value::ValueDeserializeError, DataMap, Id, IdOrIdent, InvalidIdentError},
+    query::expr::Expr,
+};
 
-use super::{AttrMapExt, AttributeMeta};
+use super::{AttrMapExt, AttributeMeta, ValueConstraint};
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
@@ -13,6 +16,35 @@ pub enum Cardinality {
     Required,
 }
 
+/// How ids are generated for new entities of a class if no id is provided.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum IdStrategy {
+    /// Random UUIDv4 ids.
+    #[default]
+    Random,
+    /// Time-ordered UUIDv7 ids. See [`Id::random_v7`].
+    ///
+    /// Entities created close together get ids close together, which
+    /// improves locality of range scans.
+    TimeOrdered,
+}
+
+impl IdStrategy {
+    pub fn generate(self) -> Id {
+        match self {
+            Self::Random => Id::random(),
+            Self::TimeOrdered => Id::random_v7(),
+        }
+    }
+
+    pub fn is_random(&self) -> bool {
+        matches!(self, Self::Random)
+    }
+}
+
 impl Cardinality {
     #[inline]
     pub fn is_optional(&self) -> bool {
@@ -38,6 +70,14 @@ pub struct ClassAttribute {
 
     #[serde(rename = "factor/required", default)]
     pub required: bool,
+
+    /// Validation rules that values of this attribute must satisfy.
+    #[serde(
+        rename = "factor/constraints",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub constraints: Vec<ValueConstraint>,
 }
 
 #[allow(deprecated)]
@@ -54,6 +94,7 @@ impl ClassAttribute {
         Self {
             attribute: A::QUALIFIED_NAME.to_string(),
             required: true,
+            constraints: Vec::new(),
         }
     }
 
@@ -61,6 +102,7 @@ impl ClassAttribute {
         Self {
             attribute: A::QUALIFIED_NAME.to_string(),
             required: false,
+            constraints: Vec::new(),
         }
     }
 
@@ -68,6 +110,7 @@ impl ClassAttribute {
         Self {
             attribute: attribute.into(),
             required: false,
+            constraints: Vec::new(),
         }
     }
 
@@ -75,22 +118,28 @@ impl ClassAttribute {
         Self {
             attribute: attribute.into(),
             required: true,
+            constraints: Vec::new(),
         }
     }
 
     pub fn into_optional(self) -> Self {
         Self {
-            attribute: self.attribute,
             required: false,
+            ..self
         }
     }
 
     pub fn into_required(self) -> Self {
         Self {
-            attribute: self.attribute,
             required: true,
+            ..self
         }
     }
+
+    pub fn with_constraint(mut self, constraint: ValueConstraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
 }
 
 impl ClassMeta for ClassAttribute {
@@ -108,6 +157,11 @@ impl ClassMeta for ClassAttribute {
             attributes: vec![],
             extends: vec![],
             strict: false,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 }
@@ -136,6 +190,7 @@ impl<'de> serde::Deserialize<'de> for ClassAttribute {
             {
                 let mut attr: Option<String> = None;
                 let mut required: Option<bool> = None;
+                let mut constraints: Vec<ValueConstraint> = Vec::new();
 
                 loop {
                     match map.next_key::<String>()?.as_deref() {
@@ -164,6 +219,9 @@ impl<'de> serde::Deserialize<'de> for ClassAttribute {
                             let c = map.next_value::<bool>()?;
                             required = Some(c);
                         }
+                        Some("factor/constraints") => {
+                            constraints = map.next_value()?;
+                        }
                         Some(_) => {
                             continue;
                         }
@@ -181,6 +239,7 @@ impl<'de> serde::Deserialize<'de> for ClassAttribute {
                 Ok(ClassAttribute {
                     attribute,
                     required,
+                    constraints,
                 })
             }
         }
@@ -204,6 +263,7 @@ fn test_class_attribute_deser() {
         ClassAttribute {
             attribute: "test".to_string(),
             required: true,
+            constraints: Vec::new(),
         }
     );
 
@@ -220,8 +280,57 @@ fn test_class_attribute_deser() {
         ClassAttribute {
             attribute: "test".to_string(),
             required: true,
+            constraints: Vec::new(),
         }
     );
+
+    // with constraints
+    let attr = serde_json::from_str::<ClassAttribute>(
+        r#"{
+        "factor/attribute": "test",
+        "factor/constraints": [{"MinLength": 1}, {"Regex": "^a"}]
+    }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        attr,
+        ClassAttribute {
+            attribute: "test".to_string(),
+            required: false,
+            constraints: vec![
+                ValueConstraint::MinLength(1),
+                ValueConstraint::Regex("^a".to_string())
+            ],
+        }
+    );
+}
+
+/// An attribute of a class that is computed from other attributes of the
+/// entity, like `full_name = first_name + " " + last_name`.
+///
+/// Computed values are not stored. They are added to entity data when it is
+/// read, and filters and sorts that use the attribute are rewritten to the
+/// expression.
+/// The expression can only use the attributes of the class, including
+/// inherited ones.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct ComputedAttribute {
+    #[serde(rename = "factor/ident")]
+    pub ident: String,
+    #[serde(rename = "factor/expression")]
+    pub expr: Expr,
+}
+
+impl ComputedAttribute {
+    pub fn new(ident: impl Into<String>, expr: Expr) -> Self {
+        Self {
+            ident: ident.into(),
+            expr,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -245,6 +354,51 @@ pub struct Class {
     /// by the schema will be rejected.
     #[serde(rename = "factor/isStrict", default)]
     pub strict: bool,
+    /// Id generation for new entities created without an id.
+    #[serde(
+        rename = "factor/idStrategy",
+        default,
+        skip_serializing_if = "IdStrategy::is_random"
+    )]
+    pub id_strategy: IdStrategy,
+    /// Maximum number of entities of this class.
+    ///
+    /// Creates beyond the limit are rejected with a
+    /// [`crate::error::QuotaExceeded`] error.
+    /// Entities of child classes are not counted.
+    #[serde(
+        rename = "factor/maxEntities",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_entities: Option<u64>,
+    /// Attributes that are computed from the other attributes of an entity.
+    ///
+    /// See [`ComputedAttribute`].
+    #[serde(
+        rename = "factor/computedAttributes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub computed_attributes: Vec<ComputedAttribute>,
+    /// Idents of [`super::Mixin`]s whose attributes are added to the class.
+    #[serde(
+        rename = "factor/mixins",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub mixins: Vec<String>,
+    /// Abstract classes can not be instantiated, only extended.
+    ///
+    /// They act like interfaces: queries for entities of an abstract class
+    /// match the entities of all concrete child classes.
+    /// Abstract classes can only extend other abstract classes.
+    #[serde(
+        rename = "factor/isAbstract",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub is_abstract: bool,
     // TODO: refactor to embedded/compound entity
     // #[serde(rename = "factor/isRelation")]
     // pub is_relation: bool,
@@ -264,6 +418,11 @@ impl Class {
             attributes: vec![],
             extends: vec![],
             strict: false,
+            id_strategy: IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         }
     }
 
@@ -291,6 +450,7 @@ impl Class {
         self.attributes.push(ClassAttribute {
             attribute: attr.into(),
             required,
+            constraints: Vec::new(),
         });
         self
     }
@@ -300,6 +460,38 @@ impl Class {
         self
     }
 
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    pub fn with_max_entities(mut self, max_entities: u64) -> Self {
+        self.max_entities = Some(max_entities);
+        self
+    }
+
+    pub fn with_computed_attribute(mut self, ident: impl Into<String>, expr: Expr) -> Self {
+        self.computed_attributes
+            .push(ComputedAttribute::new(ident, expr));
+        self
+    }
+
+    pub fn computed_attribute(&self, ident: &str) -> Option<&ComputedAttribute> {
+        self.computed_attributes
+            .iter()
+            .find(|computed| computed.ident == ident)
+    }
+
+    pub fn with_mixin(mut self, mixin: impl Into<String>) -> Self {
+        self.mixins.push(mixin.into());
+        self
+    }
+
+    pub fn into_abstract(mut self) -> Self {
+        self.is_abstract = true;
+        self
+    }
+
     pub fn with_extend(mut self, extend: impl Into<String>) -> Self {
         self.extends.push(extend.into());
         self
@@ -0,0 +1,400 @@
+use anyhow::Context;
+
+use crate::data::value_type::StaticValueType;
+
+use super::{DbSchema, SchemaChange};
+
+/// Error returned by [`check_schema_compatibility`].
+///
+/// The [`Display`](std::fmt::Display) output contains a human readable diff
+/// of all diverging items.
+#[derive(Debug)]
+pub struct SchemaCompatibilityError {
+    mismatches: Vec<String>,
+}
+
+impl SchemaCompatibilityError {
+    pub fn mismatches(&self) -> &[String] {
+        &self.mismatches
+    }
+}
+
+impl std::fmt::Display for SchemaCompatibilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Schema does not match the committed schema file:")?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaCompatibilityError {}
+
+fn pretty<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|err| err.to_string())
+}
+
+/// Line based diff of two documents.
+///
+/// Removed lines are prefixed with `-`, added lines with `+`.
+/// Based on the longest common subsequence of lines, which is fine for the
+/// small documents of single schema items.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let mut push = |prefix: &str, line: &str| {
+        out.push_str(prefix);
+        out.push_str(line);
+        out.push('\n');
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            push("   ", old[i]);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            push("-  ", old[i]);
+            i += 1;
+        } else {
+            push("+  ", new[j]);
+            j += 1;
+        }
+    }
+    for line in &old[i..] {
+        push("-  ", line);
+    }
+    for line in &new[j..] {
+        push("+  ", line);
+    }
+    out
+}
+
+/// Check that all attributes and classes of `actual` are present in the
+/// `expected` schema with an identical definition.
+///
+/// Ids are ignored, since statically defined schemas usually don't specify
+/// them.
+/// Items that only exist in `expected` are ignored.
+///
+/// Used by the `factor_check_schema!` macro.
+pub fn check_schema_compatibility(
+    expected: &DbSchema,
+    actual: &DbSchema,
+) -> Result<(), SchemaCompatibilityError> {
+    let mismatches = expected
+        .diff(actual)
+        .changes
+        .into_iter()
+        .filter_map(|change| match change {
+            SchemaChange::AttributeAdded(attr) => Some(format!(
+                "attribute '{}' is missing in the schema file",
+                attr.ident
+            )),
+            SchemaChange::AttributeChanged { old, new } => Some(format!(
+                "attribute '{}' differs:\n{}",
+                new.ident,
+                diff_lines(&pretty(&old), &pretty(&new))
+            )),
+            SchemaChange::ClassAdded(class) => Some(format!(
+                "class '{}' is missing in the schema file",
+                class.ident
+            )),
+            SchemaChange::ClassChanged { old, new } => Some(format!(
+                "class '{}' differs:\n{}",
+                new.ident,
+                diff_lines(&pretty(&old), &pretty(&new))
+            )),
+            SchemaChange::AttributeRemoved(_) | SchemaChange::ClassRemoved(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaCompatibilityError { mismatches })
+    }
+}
+
+/// Parse the JSON of a serialized [`DbSchema`] and check it with
+/// [`check_schema_compatibility`].
+pub fn check_schema_json_compatibility(
+    expected_json: &str,
+    actual: &DbSchema,
+) -> Result<(), anyhow::Error> {
+    let expected: DbSchema = serde_json::from_str(expected_json).context("Invalid schema file")?;
+    check_schema_compatibility(&expected, actual)?;
+    Ok(())
+}
+
+/// Compile time description of a statically defined schema item.
+///
+/// Generated by the `Attribute` and `Class` derives, and by the
+/// `factor_check_schema!` macro for the items of the schema file, which
+/// compares them with [`check_static_item`] in a const assertion.
+/// Only contains the properties that are known at compile time.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub enum StaticItemCheck {
+    Attribute(StaticAttributeCheck),
+    Class(StaticClassCheck),
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub struct StaticAttributeCheck {
+    pub ident: &'static str,
+    /// `None` if the type can only be compared at runtime.
+    pub value_type: Option<StaticValueType>,
+    pub unique: bool,
+    pub index: bool,
+    pub strict: bool,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub struct StaticClassCheck {
+    pub ident: &'static str,
+    /// Attribute idents and whether they are required.
+    pub attributes: &'static [(&'static str, bool)],
+    pub extends: &'static [&'static str],
+    pub strict: bool,
+}
+
+/// Result of [`check_static_item`].
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaticCheckResult {
+    Ok,
+    Missing,
+    ValueType,
+    Flags,
+    Attributes,
+    Extends,
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn value_type_eq(a: Option<StaticValueType>, b: Option<StaticValueType>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.list_depth == b.list_depth && str_eq(a.name, b.name),
+        // Types that can't be described at compile time are only compared
+        // at runtime.
+        _ => true,
+    }
+}
+
+const fn class_attributes_eq(a: &[(&str, bool)], b: &[(&str, bool)]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if !str_eq(a[i].0, b[i].0) || a[i].1 != b[i].1 {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn idents_eq(a: &[&str], b: &[&str]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if !str_eq(a[i], b[i]) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Compare a derived item with the item of the same ident in `expected`.
+///
+/// Used by the `factor_check_schema!` macro in a const assertion, so
+/// mismatches fail the build.
+#[doc(hidden)]
+pub const fn check_static_item(
+    expected: &[StaticItemCheck],
+    actual: &StaticItemCheck,
+) -> StaticCheckResult {
+    let mut i = 0;
+    while i < expected.len() {
+        match (&expected[i], actual) {
+            (StaticItemCheck::Attribute(exp), StaticItemCheck::Attribute(act))
+                if str_eq(exp.ident, act.ident) =>
+            {
+                if !value_type_eq(exp.value_type, act.value_type) {
+                    return StaticCheckResult::ValueType;
+                }
+                if exp.unique != act.unique || exp.index != act.index || exp.strict != act.strict {
+                    return StaticCheckResult::Flags;
+                }
+                return StaticCheckResult::Ok;
+            }
+            (StaticItemCheck::Class(exp), StaticItemCheck::Class(act))
+                if str_eq(exp.ident, act.ident) =>
+            {
+                if !class_attributes_eq(exp.attributes, act.attributes) {
+                    return StaticCheckResult::Attributes;
+                }
+                if !idents_eq(exp.extends, act.extends) {
+                    return StaticCheckResult::Extends;
+                }
+                if exp.strict != act.strict {
+                    return StaticCheckResult::Flags;
+                }
+                return StaticCheckResult::Ok;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    StaticCheckResult::Missing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{Id, ValueType},
+        schema::{Attribute, Class},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_check_schema_compatibility() {
+        let expected = DbSchema {
+            attributes: vec![Attribute::new("test/a", ValueType::String)],
+            classes: vec![Class::new("test/A").with_attribute("test/a", true)],
+            indexes: vec![],
+            policies: vec![],
+            views: vec![],
+            rules: vec![],
+            mixins: vec![],
+        };
+
+        let mut actual = expected.clone();
+        actual.attributes[0].id = Id::random();
+        check_schema_compatibility(&expected, &actual).unwrap();
+
+        actual.classes[0].attributes[0].required = false;
+        actual
+            .attributes
+            .push(Attribute::new("test/b", ValueType::Int));
+        let err = check_schema_compatibility(&expected, &actual).unwrap_err();
+        assert_eq!(err.mismatches().len(), 2);
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let diff = diff_lines("a\nb\nc\nd", "a\nc\nx\nd");
+        assert_eq!(diff, "   a\n-  b\n   c\n+  x\n   d\n");
+    }
+
+    #[test]
+    fn test_check_static_item() {
+        const EXPECTED: &[StaticItemCheck] = &[
+            StaticItemCheck::Attribute(StaticAttributeCheck {
+                ident: "test/a",
+                value_type: StaticValueType::scalar("String"),
+                unique: false,
+                index: true,
+                strict: false,
+            }),
+            StaticItemCheck::Class(StaticClassCheck {
+                ident: "test/A",
+                attributes: &[("test/a", true)],
+                extends: &[],
+                strict: false,
+            }),
+        ];
+        const ATTR: StaticAttributeCheck = StaticAttributeCheck {
+            ident: "test/a",
+            value_type: StaticValueType::scalar("String"),
+            unique: false,
+            index: true,
+            strict: false,
+        };
+        const CLASS: StaticClassCheck = StaticClassCheck {
+            ident: "test/A",
+            attributes: &[("test/a", true)],
+            extends: &[],
+            strict: false,
+        };
+        // Evaluated at compile time.
+        const OK: StaticCheckResult =
+            check_static_item(EXPECTED, &StaticItemCheck::Attribute(ATTR));
+        assert_eq!(OK, StaticCheckResult::Ok);
+
+        let check = |item| check_static_item(EXPECTED, &item);
+        assert_eq!(check(StaticItemCheck::Class(CLASS)), StaticCheckResult::Ok);
+        assert_eq!(
+            check(StaticItemCheck::Attribute(StaticAttributeCheck {
+                value_type: StaticValueType::scalar("Int"),
+                ..ATTR
+            })),
+            StaticCheckResult::ValueType
+        );
+        assert_eq!(
+            check(StaticItemCheck::Attribute(StaticAttributeCheck {
+                value_type: None,
+                ..ATTR
+            })),
+            StaticCheckResult::Ok
+        );
+        assert_eq!(
+            check(StaticItemCheck::Attribute(StaticAttributeCheck {
+                unique: true,
+                ..ATTR
+            })),
+            StaticCheckResult::Flags
+        );
+        assert_eq!(
+            check(StaticItemCheck::Class(StaticClassCheck {
+                attributes: &[("test/a", false)],
+                ..CLASS
+            })),
+            StaticCheckResult::Attributes
+        );
+        assert_eq!(
+            check(StaticItemCheck::Class(StaticClassCheck {
+                ident: "test/B",
+                ..CLASS
+            })),
+            StaticCheckResult::Missing
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::data::Value;
+
+/// A validation rule for the values of a class attribute.
+///
+/// Constraints are declared on [`super::ClassAttribute`]s and are checked
+/// by the backend after the value was coerced to the attribute type.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum ValueConstraint {
+    /// Minimum length of a string (in characters), bytes or list value.
+    MinLength(u64),
+    /// Maximum length of a string (in characters), bytes or list value.
+    MaxLength(u64),
+    /// Minimum (inclusive) for numeric values.
+    Min(Value),
+    /// Maximum (inclusive) for numeric values.
+    Max(Value),
+    /// String values must match the regular expression.
+    Regex(String),
+}
+
+impl ValueConstraint {
+    /// Check a length based constraint.
+    ///
+    /// Returns `None` if the constraint is not a length constraint or if the
+    /// value has no length.
+    pub fn check_length(&self, value: &Value) -> Option<bool> {
+        let len = match value {
+            Value::String(s) => s.chars().count() as u64,
+            Value::Bytes(b) => b.len() as u64,
+            Value::List(items) => items.len() as u64,
+            _ => return None,
+        };
+
+        match self {
+            Self::MinLength(min) => Some(len >= *min),
+            Self::MaxLength(max) => Some(len <= *max),
+            _ => None,
+        }
+    }
+
+    /// Check a numeric range constraint.
+    ///
+    /// Returns `None` if the constraint is not a range constraint or if either
+    /// the value or the bound is not numeric.
+    pub fn check_range(&self, value: &Value) -> Option<bool> {
+        let value = numeric_value(value)?;
+
+        match self {
+            Self::Min(min) => Some(value >= numeric_value(min)?),
+            Self::Max(max) => Some(value <= numeric_value(max)?),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ValueConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MinLength(v) => write!(f, "min_len = {}", v),
+            Self::MaxLength(v) => write!(f, "max_len = {}", v),
+            Self::Min(v) => write!(f, "min = {:?}", v),
+            Self::Max(v) => write!(f, "max = {:?}", v),
+            Self::Regex(v) => write!(f, "regex = {:?}", v),
+        }
+    }
+}
+
+fn numeric_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::UInt(v) => Some(*v as f64),
+        Value::Int(v) => Some(*v as f64),
+        Value::Float(v) => Some(**v),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_constraint_checks() {
+        let c = ValueConstraint::MinLength(2);
+        assert_eq!(c.check_length(&"a".into()), Some(false));
+        assert_eq!(c.check_length(&"ab".into()), Some(true));
+        assert_eq!(c.check_length(&Value::Int(1)), None);
+
+        let c = ValueConstraint::MaxLength(1);
+        assert_eq!(c.check_length(&Value::new_list([1, 2])), Some(false));
+
+        let c = ValueConstraint::Max(Value::UInt(100));
+        assert_eq!(c.check_range(&Value::Int(-5)), Some(true));
+        assert_eq!(c.check_range(&Value::Float(100.5.into())), Some(false));
+        assert_eq!(c.check_range(&"x".into()), None);
+    }
+}
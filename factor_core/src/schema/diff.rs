@@ -0,0 +1,264 @@
+use crate::{data::Id, query::migrate::Migration};
+
+use super::{Attribute, Class, DbSchema};
+
+/// A single difference between two schemas.
+///
+/// See [`DbSchema::diff`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaChange {
+    AttributeAdded(Attribute),
+    AttributeRemoved(Attribute),
+    AttributeChanged { old: Attribute, new: Attribute },
+    ClassAdded(Class),
+    ClassRemoved(Class),
+    ClassChanged { old: Class, new: Class },
+}
+
+impl SchemaChange {
+    /// The ident of the changed attribute or class.
+    pub fn ident(&self) -> &str {
+        match self {
+            Self::AttributeAdded(a) | Self::AttributeRemoved(a) => &a.ident,
+            Self::AttributeChanged { new, .. } => &new.ident,
+            Self::ClassAdded(c) | Self::ClassRemoved(c) => &c.ident,
+            Self::ClassChanged { new, .. } => &new.ident,
+        }
+    }
+}
+
+/// Renders a line based diff, listing the changed fields of modified items.
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AttributeAdded(a) => writeln!(f, "+ attribute '{}'", a.ident),
+            Self::AttributeRemoved(a) => writeln!(f, "- attribute '{}'", a.ident),
+            Self::AttributeChanged { old, new } => {
+                writeln!(f, "~ attribute '{}'", new.ident)?;
+                write_field_changes(f, old, new)
+            }
+            Self::ClassAdded(c) => writeln!(f, "+ class '{}'", c.ident),
+            Self::ClassRemoved(c) => writeln!(f, "- class '{}'", c.ident),
+            Self::ClassChanged { old, new } => {
+                writeln!(f, "~ class '{}'", new.ident)?;
+                write_field_changes(f, old, new)
+            }
+        }
+    }
+}
+
+fn write_field_changes<T: serde::Serialize>(
+    f: &mut std::fmt::Formatter<'_>,
+    old: &T,
+    new: &T,
+) -> std::fmt::Result {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (old, new) = match (old.as_object(), new.as_object()) {
+        (Some(o), Some(n)) => (o, n),
+        _ => return Ok(()),
+    };
+
+    let null = serde_json::Value::Null;
+    let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let a = old.get(key).unwrap_or(&null);
+        let b = new.get(key).unwrap_or(&null);
+        if a != b {
+            writeln!(f, "    {}: {} -> {}", key, a, b)?;
+        }
+    }
+    Ok(())
+}
+
+/// The differences between two schemas.
+///
+/// Created with [`DbSchema::diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Build a migration that transforms the old schema into the new one.
+    ///
+    /// Added and changed items are upserted.
+    /// Removed items are only deleted if `delete_removed` is true, which
+    /// also deletes all values of removed attributes and all entities of
+    /// removed classes.
+    pub fn to_migration(&self, delete_removed: bool) -> Migration {
+        let mut mig = Migration::new();
+
+        // Attributes must exist before the classes that use them, and classes
+        // must be deleted before their attributes.
+        for change in &self.changes {
+            match change {
+                SchemaChange::AttributeAdded(attr)
+                | SchemaChange::AttributeChanged { new: attr, .. } => {
+                    mig = mig.attr_upsert(attr.clone());
+                }
+                _ => {}
+            }
+        }
+        for change in &self.changes {
+            match change {
+                SchemaChange::ClassAdded(class) | SchemaChange::ClassChanged { new: class, .. } => {
+                    mig = mig.entity_upsert(class.clone());
+                }
+                SchemaChange::ClassRemoved(class) if delete_removed => {
+                    mig = mig.entity_delete(class.ident.clone(), true);
+                }
+                _ => {}
+            }
+        }
+        if delete_removed {
+            for change in &self.changes {
+                if let SchemaChange::AttributeRemoved(attr) = change {
+                    mig = mig.attr_delete(attr.ident.clone());
+                }
+            }
+        }
+
+        mig
+    }
+}
+
+impl std::fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl DbSchema {
+    /// Compute the changes required to turn `self` into `new`.
+    ///
+    /// Items are matched by ident. Ids are ignored, since statically defined
+    /// schemas usually don't specify them, and are nil in the returned
+    /// changes.
+    /// Indexes are not compared, since they are derived from the attributes.
+    pub fn diff(&self, new: &DbSchema) -> SchemaDiff {
+        let mut changes = Vec::new();
+
+        for attr in &new.attributes {
+            let attr = Attribute {
+                id: Id::nil(),
+                ..attr.clone()
+            };
+            match self.attr_by_ident(&attr.ident) {
+                Some(old) => {
+                    let old = Attribute {
+                        id: Id::nil(),
+                        ..old.clone()
+                    };
+                    if old != attr {
+                        changes.push(SchemaChange::AttributeChanged { old, new: attr });
+                    }
+                }
+                None => changes.push(SchemaChange::AttributeAdded(attr)),
+            }
+        }
+        for attr in &self.attributes {
+            if new.attr_by_ident(&attr.ident).is_none() {
+                changes.push(SchemaChange::AttributeRemoved(Attribute {
+                    id: Id::nil(),
+                    ..attr.clone()
+                }));
+            }
+        }
+
+        for class in &new.classes {
+            let class = Class {
+                id: Id::nil(),
+                ..class.clone()
+            };
+            match self.class_by_ident(&class.ident) {
+                Some(old) => {
+                    let old = Class {
+                        id: Id::nil(),
+                        ..old.clone()
+                    };
+                    if old != class {
+                        changes.push(SchemaChange::ClassChanged { old, new: class });
+                    }
+                }
+                None => changes.push(SchemaChange::ClassAdded(class)),
+            }
+        }
+        for class in &self.classes {
+            if new.class_by_ident(&class.ident).is_none() {
+                changes.push(SchemaChange::ClassRemoved(Class {
+                    id: Id::nil(),
+                    ..class.clone()
+                }));
+            }
+        }
+
+        SchemaDiff { changes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::ValueType, query::migrate::SchemaAction};
+
+    use super::*;
+
+    #[test]
+    fn test_schema_diff() {
+        let old = DbSchema {
+            attributes: vec![
+                Attribute::new("test/a", ValueType::String),
+                Attribute::new("test/b", ValueType::Int),
+            ],
+            classes: vec![Class::new("test/A").with_attribute("test/a", true)],
+            indexes: vec![],
+            policies: vec![],
+            views: vec![],
+            rules: vec![],
+            mixins: vec![],
+        };
+
+        let mut new = old.clone();
+        new.attributes[0].id = Id::random();
+        assert!(old.diff(&new).is_empty());
+
+        new.attributes.remove(1);
+        new.attributes
+            .push(Attribute::new("test/c", ValueType::Bool));
+        new.classes[0].attributes[0].required = false;
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.changes,
+            vec![
+                SchemaChange::AttributeAdded(Attribute::new("test/c", ValueType::Bool)),
+                SchemaChange::AttributeRemoved(Attribute::new("test/b", ValueType::Int)),
+                SchemaChange::ClassChanged {
+                    old: old.classes[0].clone(),
+                    new: new.classes[0].clone(),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "+ attribute 'test/c'\n- attribute 'test/b'\n~ class 'test/A'\n    factor/entityAttributes: [{\"factor/attribute\":\"test/a\",\"factor/required\":true}] -> [{\"factor/attribute\":\"test/a\",\"factor/required\":false}]\n"
+        );
+
+        let mig = diff.to_migration(false);
+        assert_eq!(mig.actions.len(), 2);
+        let mig = diff.to_migration(true);
+        assert!(matches!(
+            mig.actions.last(),
+            Some(SchemaAction::AttributeDelete(_))
+        ));
+    }
+}
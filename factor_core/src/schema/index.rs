@@ -1,4 +1,4 @@
-use crate::data::Id;
+use crate::{data::Id, query::expr::Expr};
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
@@ -17,6 +17,47 @@ pub struct IndexSchema {
     pub description: Option<String>,
     #[serde(rename = "factor/unique")]
     pub unique: bool,
+    /// Scope index entries to the tenant of an entity (see
+    /// [`super::builtin::AttrTenant`]).
+    ///
+    /// For unique indexes, values only need to be unique within a tenant.
+    #[serde(
+        rename = "factor/tenantScoped",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub tenant_scoped: bool,
+    /// Do not index unit values.
+    ///
+    /// Keeps indexes of attributes that are mostly empty small.
+    /// Queries for unit values can not use a sparse index.
+    #[serde(
+        rename = "factor/sparse",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub sparse: bool,
+    /// Only index entities that match the filter.
+    ///
+    /// Queries only use a partial index if their filter contains the index
+    /// filter as an `AND` clause.
+    #[serde(
+        rename = "factor/indexFilter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub filter: Option<Expr>,
+    /// Index each item of a list attribute separately, instead of the list
+    /// as a whole.
+    ///
+    /// For unique indexes, an item may only appear in the list of a single
+    /// entity. Queries do not use multi-valued indexes.
+    #[serde(
+        rename = "factor/multiValued",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub multi_valued: bool,
 }
 
 impl IndexSchema {
@@ -27,7 +68,36 @@ impl IndexSchema {
             title: None,
             description: None,
             unique: false,
+            tenant_scoped: false,
+            sparse: false,
+            filter: None,
+            multi_valued: false,
             attributes,
         }
     }
+
+    pub fn with_unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    pub fn with_tenant_scoped(mut self, tenant_scoped: bool) -> Self {
+        self.tenant_scoped = tenant_scoped;
+        self
+    }
+
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Expr) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_multi_valued(mut self, multi_valued: bool) -> Self {
+        self.multi_valued = multi_valued;
+        self
+    }
 }
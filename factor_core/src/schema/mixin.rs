@@ -0,0 +1,49 @@
+use crate::data::Id;
+
+use super::ClassAttribute;
+
+/// A reusable group of class attributes, like `createdAt` and `updatedAt`
+/// for timestamps.
+///
+/// Classes list mixins in [`super::Class::mixins`]. When a class is
+/// registered, the attributes of its mixins are added to the class, unless
+/// the class declares the attribute itself.
+/// Mixins can not be changed or deleted while a class uses them.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct Mixin {
+    #[serde(rename = "factor/id", default)]
+    pub id: Id,
+    #[serde(rename = "factor/ident")]
+    pub ident: String,
+    #[serde(rename = "factor/title")]
+    pub title: Option<String>,
+    #[serde(rename = "factor/description")]
+    pub description: Option<String>,
+    #[serde(rename = "factor/entityAttributes", default)]
+    pub attributes: Vec<ClassAttribute>,
+}
+
+impl Mixin {
+    pub fn new(ident: impl Into<String>) -> Self {
+        Self {
+            id: Id::nil(),
+            ident: ident.into(),
+            title: None,
+            description: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_attribute(mut self, attribute: ClassAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+}
@@ -1,14 +1,41 @@
 pub mod builtin;
 
 mod attribute;
-pub use self::attribute::{AttrMapExt, Attribute, AttributeMeta};
+pub use self::attribute::{AttrMapExt, Attribute, AttributeMeta, Collation};
 
 mod class;
-pub use self::class::{Cardinality, Class, ClassAttribute, ClassContainer, ClassMeta};
+pub use self::class::{
+    Cardinality, Class, ClassAttribute, ClassContainer, ClassMeta, ComputedAttribute, IdStrategy,
+};
+
+mod compat;
+pub use self::compat::{
+    check_schema_compatibility, check_schema_json_compatibility, check_static_item,
+    SchemaCompatibilityError, StaticAttributeCheck, StaticCheckResult, StaticClassCheck,
+    StaticItemCheck,
+};
+
+mod constraint;
+pub use self::constraint::ValueConstraint;
+
+mod diff;
+pub use self::diff::{SchemaChange, SchemaDiff};
 
 mod index;
 pub use self::index::IndexSchema;
 
+mod policy;
+pub use self::policy::PolicySchema;
+
+mod view;
+pub use self::view::ViewSchema;
+
+mod rule;
+pub use self::rule::{RuleAction, RuleEvent, RuleSchema};
+
+mod mixin;
+pub use self::mixin::Mixin;
+
 mod commit;
 pub use commit::{PreBatchCommit, PreCommit, PreMigration, StaticSchema};
 
@@ -20,6 +47,40 @@ pub enum SchemaItem {
     Entity(Class),
 }
 
+/// Provides the [`SchemaItem`] of a statically defined attribute or class.
+///
+/// Implemented by the `Attribute` and `Class` derives.
+/// Used by [`crate::schema_bundle`] to accept both attributes and classes.
+pub trait SchemaItemMeta {
+    /// Compile time description of the item, used by the
+    /// `factor_check_schema!` macro.
+    #[doc(hidden)]
+    const STATIC_CHECK: StaticItemCheck;
+
+    fn schema_item() -> SchemaItem;
+}
+
+/// Build a [`DbSchema`] from a list of types implementing [`SchemaItemMeta`].
+///
+/// Classes must be listed after the classes they extend.
+///
+/// ```ignore
+/// let schema = schema_bundle!(AttrTitle, Todo, Project);
+/// db.migrate(Migration::new().upsert_schema(schema)).await?;
+/// ```
+#[macro_export]
+macro_rules! schema_bundle {
+    ( $( $item:ty ),* $(,)? ) => {
+        {
+            let mut schema = $crate::schema::DbSchema::default();
+            $(
+            schema.add_item(<$item as $crate::schema::SchemaItemMeta>::schema_item());
+            )*
+            schema
+        }
+    };
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Default, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
@@ -30,6 +91,14 @@ pub struct DbSchema {
     pub attributes: Vec<Attribute>,
     pub classes: Vec<Class>,
     pub indexes: Vec<IndexSchema>,
+    #[serde(default)]
+    pub policies: Vec<PolicySchema>,
+    #[serde(default)]
+    pub views: Vec<ViewSchema>,
+    #[serde(default)]
+    pub rules: Vec<RuleSchema>,
+    #[serde(default)]
+    pub mixins: Vec<Mixin>,
 }
 
 impl DbSchema {
@@ -75,10 +144,35 @@ impl DbSchema {
         None
     }
 
+    /// Add an attribute or class to the schema.
+    pub fn add_item(&mut self, item: SchemaItem) {
+        match item {
+            SchemaItem::Attribute(attr) => self.attributes.push(attr),
+            SchemaItem::Entity(class) => self.classes.push(class),
+        }
+    }
+
+    /// Remove all builtin items in the `factor/` namespace.
+    pub fn without_builtins(mut self) -> Self {
+        let is_builtin = |ident: &str| ident.split('/').next() == Some(builtin::NS_FACTOR);
+        self.attributes.retain(|a| !is_builtin(&a.ident));
+        self.classes.retain(|c| !is_builtin(&c.ident));
+        self.indexes.retain(|i| !is_builtin(&i.ident));
+        self.policies.retain(|p| !is_builtin(&p.ident));
+        self.views.retain(|v| !is_builtin(&v.ident));
+        self.rules.retain(|r| !is_builtin(&r.ident));
+        self.mixins.retain(|m| !is_builtin(&m.ident));
+        self
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
         self.attributes.extend(other.attributes);
         self.classes.extend(other.classes);
         self.indexes.extend(other.indexes);
+        self.policies.extend(other.policies);
+        self.views.extend(other.views);
+        self.rules.extend(other.rules);
+        self.mixins.extend(other.mixins);
 
         self
     }
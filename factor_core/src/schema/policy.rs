@@ -0,0 +1,47 @@
+use crate::{data::Id, query::expr::Expr};
+
+/// A row-level security policy.
+///
+/// Restricts access to entities of a class (including child classes) to
+/// the entities matching the filter expression.
+///
+/// The filter can reference variables like `$current_user`, which are
+/// provided by the auth context of the engine. Policies are only enforced
+/// when an auth context is supplied.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct PolicySchema {
+    #[serde(rename = "factor/id")]
+    pub id: Id,
+    #[serde(rename = "factor/ident")]
+    pub ident: String,
+    #[serde(rename = "factor/title")]
+    pub title: Option<String>,
+    #[serde(rename = "factor/description")]
+    pub description: Option<String>,
+    /// Ident of the class the policy applies to.
+    #[serde(rename = "factor/policyClass")]
+    pub class: String,
+    #[serde(rename = "factor/policyFilter")]
+    pub filter: Expr,
+}
+
+impl PolicySchema {
+    pub fn new(
+        namespace: impl Into<String>,
+        name: impl Into<String>,
+        class: impl Into<String>,
+        filter: Expr,
+    ) -> Self {
+        Self {
+            id: Id::nil(),
+            ident: format!("{}/{}", namespace.into(), name.into()),
+            title: None,
+            description: None,
+            class: class.into(),
+            filter,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use crate::{data::Id, query::expr::Expr};
+
+/// Changes of an entity that trigger a [`RuleSchema`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum RuleEvent {
+    /// The entity was created.
+    Create,
+    /// The entity was replaced, merged or patched.
+    Update,
+}
+
+/// A write performed by a [`RuleSchema`].
+///
+/// Attribute values are expressions evaluated against the current data of
+/// the written entity.
+/// The triggering entity is available through variables: `$entity` is its
+/// id, and each attribute is available as a variable with the attribute
+/// name, eg. `$blog/post`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub enum RuleAction {
+    /// Create a new entity.
+    Create { data: BTreeMap<String, Expr> },
+    /// Merge attributes into an existing entity.
+    ///
+    /// `entity` must evaluate to the id of the entity.
+    Merge {
+        entity: Expr,
+        data: BTreeMap<String, Expr>,
+    },
+}
+
+/// A trigger rule.
+///
+/// Whenever an entity of the class (including child classes) is created or
+/// updated, the actions of the rule are appended to the same batch, so they
+/// are applied atomically with the triggering change.
+///
+/// Writes performed by rules do not trigger other rules.
+/// Entities modified by [`crate::query::mutate::Mutate::Select`] do not
+/// trigger rules.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct RuleSchema {
+    #[serde(rename = "factor/id")]
+    pub id: Id,
+    #[serde(rename = "factor/ident")]
+    pub ident: String,
+    #[serde(rename = "factor/title")]
+    pub title: Option<String>,
+    #[serde(rename = "factor/description")]
+    pub description: Option<String>,
+    /// Ident of the class the rule applies to.
+    #[serde(rename = "factor/ruleClass")]
+    pub class: String,
+    #[serde(rename = "factor/ruleEvents")]
+    pub events: Vec<RuleEvent>,
+    #[serde(rename = "factor/ruleActions")]
+    pub actions: Vec<RuleAction>,
+}
+
+impl RuleSchema {
+    pub fn new(
+        namespace: impl Into<String>,
+        name: impl Into<String>,
+        class: impl Into<String>,
+        events: Vec<RuleEvent>,
+    ) -> Self {
+        Self {
+            id: Id::nil(),
+            ident: format!("{}/{}", namespace.into(), name.into()),
+            title: None,
+            description: None,
+            class: class.into(),
+            events,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn with_action(mut self, action: RuleAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    pub fn triggers_on(&self, event: RuleEvent) -> bool {
+        self.events.contains(&event)
+    }
+}
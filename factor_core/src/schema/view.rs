@@ -0,0 +1,37 @@
+use crate::{data::Id, query::select::Select};
+
+/// A named, materialized query.
+///
+/// The engine keeps the entities matching the filter of the query up to
+/// date on every change, so reading a view does not need to scan all
+/// entities.
+/// Sorting, offset, limit and aggregations of the query are applied when
+/// the view is read.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "jsonschema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "typescript-schema", derive(ts_rs::TS))]
+#[cfg_attr(feature = "typescript-schema", ts(export))]
+pub struct ViewSchema {
+    #[serde(rename = "factor/id")]
+    pub id: Id,
+    #[serde(rename = "factor/ident")]
+    pub ident: String,
+    #[serde(rename = "factor/title")]
+    pub title: Option<String>,
+    #[serde(rename = "factor/description")]
+    pub description: Option<String>,
+    #[serde(rename = "factor/viewQuery")]
+    pub query: Select,
+}
+
+impl ViewSchema {
+    pub fn new(namespace: impl Into<String>, name: impl Into<String>, query: Select) -> Self {
+        Self {
+            id: Id::nil(),
+            ident: format!("{}/{}", namespace.into(), name.into()),
+            title: None,
+            description: None,
+            query,
+        }
+    }
+}
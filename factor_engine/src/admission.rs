@@ -0,0 +1,126 @@
+//! Write admission control.
+//!
+//! Backends apply batches one at a time, so without a limit a burst of
+//! writes queues up without bound on the backend write lock.
+
+use std::{sync::Mutex, time::Duration};
+
+use factor_core::error::Overloaded;
+use futures::{channel::oneshot, future::Either};
+
+/// Limits the number of batches that are applied concurrently.
+///
+/// See [`crate::Engine::with_max_pending_batches`].
+pub(crate) struct Admission {
+    limit: usize,
+    timeout: Option<Duration>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    pending: usize,
+    /// Batches waiting for a slot, notified whenever a slot is released.
+    waiters: Vec<oneshot::Sender<()>>,
+}
+
+/// Slot of an admitted batch, released on drop.
+pub(crate) struct Permit<'a> {
+    admission: &'a Admission,
+}
+
+impl Admission {
+    pub fn new(limit: usize, timeout: Option<Duration>) -> Self {
+        assert!(limit > 0, "the limit of pending batches must not be 0");
+        Self {
+            limit,
+            timeout,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Admit a batch.
+    ///
+    /// Without a timeout, fails immediately if all slots are taken.
+    /// Otherwise waits for a free slot until the timeout expires.
+    pub async fn acquire(&self) -> Result<Permit<'_>, Overloaded> {
+        let mut deadline = None;
+        loop {
+            let (notified, timeout) = {
+                let mut state = self.state.lock().unwrap();
+                if state.pending < self.limit {
+                    state.pending += 1;
+                    return Ok(Permit { admission: self });
+                }
+                let timeout = match self.timeout {
+                    Some(timeout) => timeout,
+                    None => return Err(self.overloaded()),
+                };
+                let (sender, receiver) = oneshot::channel();
+                state.waiters.push(sender);
+                (receiver, timeout)
+            };
+
+            // Waiters are woken up together and race for the free slots, so
+            // the deadline covers all attempts.
+            let delay = deadline.get_or_insert_with(|| futures_timer::Delay::new(timeout));
+            if let Either::Right(_) = futures::future::select(notified, delay).await {
+                return Err(self.overloaded());
+            }
+        }
+    }
+
+    fn overloaded(&self) -> Overloaded {
+        tracing::debug!(
+            limit = self.limit,
+            "rejecting batch, too many pending batches"
+        );
+        Overloaded { limit: self.limit }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.admission.state.lock().unwrap();
+        state.pending -= 1;
+        for waiter in state.waiters.drain(..) {
+            // Waiters that gave up are dropped.
+            waiter.send(()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_admission_rejects_beyond_limit() {
+        let admission = Admission::new(2, None);
+        let a = admission.acquire().await.unwrap();
+        let _b = admission.acquire().await.unwrap();
+        let err = admission.acquire().await.err().unwrap();
+        assert_eq!(err.limit, 2);
+
+        drop(a);
+        admission.acquire().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admission_waits_with_deadline() {
+        let admission = Admission::new(1, Some(Duration::from_millis(50)));
+        let a = admission.acquire().await.unwrap();
+
+        // Times out while the slot is taken.
+        assert!(admission.acquire().await.is_err());
+
+        // Admitted once the slot is released.
+        let waiting = admission.acquire();
+        futures::pin_mut!(waiting);
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(a);
+        waiting.await.unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeSet;
+
+use factor_core::{
+    data::{patch::Patch, value::to_value, DataMap, Id, Timestamp, Value},
+    query::mutate::{Create, Mutate, MutateSelectAction},
+    schema::{
+        builtin::{
+            AttrAuditActor, AttrAuditAttributes, AttrAuditEntity, AttrAuditOperation,
+            AttrAuditRevert, AttrAuditTimestamp, AttrId, AttrType, AuditEntryType, AUDIT_ENTRY_ID,
+        },
+        AttrMapExt, AttributeMeta, ClassMeta,
+    },
+};
+
+use crate::{authorizer::Operation, backend::Audit, registry::Registry};
+
+/// A single recorded change of an entity.
+pub(crate) struct Change {
+    pub entity: Id,
+    pub operation: Operation,
+    pub old: Option<DataMap>,
+    pub new: Option<DataMap>,
+}
+
+fn operation_name(op: Operation) -> &'static str {
+    match op {
+        Operation::Read => "read",
+        Operation::Create => "create",
+        Operation::Replace => "replace",
+        Operation::Merge => "merge",
+        Operation::Patch => "patch",
+        Operation::Delete => "delete",
+    }
+}
+
+/// The operation recorded for the entities changed by an action.
+pub(crate) fn action_operation(action: &Mutate) -> Option<Operation> {
+    match action {
+        Mutate::Create(_) => Some(Operation::Create),
+        Mutate::Replace(_) => Some(Operation::Replace),
+        Mutate::Merge(_) => Some(Operation::Merge),
+        Mutate::Patch(_) => Some(Operation::Patch),
+        Mutate::Delete(_) => Some(Operation::Delete),
+        Mutate::Select(select) => match select.action {
+            MutateSelectAction::Delete => Some(Operation::Delete),
+            MutateSelectAction::Patch(_) => Some(Operation::Patch),
+        },
+        Mutate::Savepoint | Mutate::Guard(_) => None,
+    }
+}
+
+fn is_audit_entry(reg: &Registry, data: Option<&DataMap>) -> bool {
+    data.and_then(|data| data.get_type())
+        .and_then(|ty| reg.entity_by_ident(&ty))
+        .map(|class| class.schema.id == AUDIT_ENTRY_ID)
+        .unwrap_or(false)
+}
+
+/// Build the patch that restores the previous values of the changed
+/// attributes.
+fn revert_patch(old: Option<&DataMap>, new: Option<&DataMap>, changed: &[String]) -> Patch {
+    changed.iter().fold(Patch::new(), |patch, key| {
+        let old_value = old.and_then(|old| old.get(key));
+        let new_value = new.and_then(|new| new.get(key));
+        match (old_value, new_value) {
+            (Some(value), Some(_)) => patch.replace(key, value.clone()),
+            (Some(value), None) => patch.add(key, value.clone()),
+            (None, _) => patch.remove(key),
+        }
+    })
+}
+
+fn changed_attributes(old: Option<&DataMap>, new: Option<&DataMap>) -> Vec<String> {
+    let keys = old
+        .into_iter()
+        .chain(new)
+        .flat_map(|data| data.keys())
+        .collect::<BTreeSet<_>>();
+    keys.into_iter()
+        .filter(|key| old.and_then(|d| d.get(*key)) != new.and_then(|d| d.get(*key)))
+        .cloned()
+        .collect()
+}
+
+fn audit_entry(
+    id: Id,
+    change: &Change,
+    actor: Option<Id>,
+    now: Timestamp,
+) -> Result<DataMap, anyhow::Error> {
+    let changed = changed_attributes(change.old.as_ref(), change.new.as_ref());
+    let revert = revert_patch(change.old.as_ref(), change.new.as_ref(), &changed);
+
+    let mut data = DataMap::new();
+    data.insert(AttrId::QUALIFIED_NAME.to_string(), id.into());
+    data.insert(
+        AttrType::QUALIFIED_NAME.to_string(),
+        AuditEntryType::QUALIFIED_NAME.into(),
+    );
+    data.insert(
+        AttrAuditEntity::QUALIFIED_NAME.to_string(),
+        change.entity.into(),
+    );
+    if let Some(actor) = actor {
+        data.insert(AttrAuditActor::QUALIFIED_NAME.to_string(), actor.into());
+    }
+    data.insert(
+        AttrAuditTimestamp::QUALIFIED_NAME.to_string(),
+        Value::UInt(now.as_millis()),
+    );
+    data.insert(
+        AttrAuditOperation::QUALIFIED_NAME.to_string(),
+        operation_name(change.operation).into(),
+    );
+    data.insert(
+        AttrAuditAttributes::QUALIFIED_NAME.to_string(),
+        changed.into(),
+    );
+    data.insert(
+        AttrAuditRevert::QUALIFIED_NAME.to_string(),
+        to_value(revert)?,
+    );
+    Ok(data)
+}
+
+/// Build the [`AuditEntryType`] entities for the changes of a batch.
+///
+/// The changes are captured by the backend while the batch is applied, and
+/// the entries are created as part of the same write, see
+/// [`crate::backend::Backend::apply_batch_audited`].
+///
+/// Fails if an audit entry is modified, since audit entries can not be
+/// modified through an audited engine.
+pub(crate) fn audit_entries(
+    reg: &Registry,
+    changes: &[Change],
+    audit: &Audit,
+) -> Result<Vec<Create>, anyhow::Error> {
+    for change in changes {
+        if is_audit_entry(reg, change.old.as_ref()) || is_audit_entry(reg, change.new.as_ref()) {
+            anyhow::bail!(
+                "Invalid mutation of entity {}: {} entities can not be modified",
+                change.entity,
+                AuditEntryType::QUALIFIED_NAME
+            );
+        }
+    }
+
+    let now = Timestamp::now();
+    changes
+        .iter()
+        .map(|change| {
+            let id = Id::random();
+            let data = audit_entry(id, change, audit.actor, now)?;
+            Ok(Create { id, data })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::value::from_value,
+        map,
+        query::{
+            expr::Expr,
+            mutate::{Batch, MutateSelect},
+            select::Select,
+        },
+        schema::builtin::AttrTitle,
+    };
+
+    use crate::{backend::memory::MemoryDb, AuthContext, Engine};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_engine_audit_log() {
+        let user = Id::random();
+        let db = Engine::new(MemoryDb::new())
+            .with_audit_log()
+            .with_auth_context(AuthContext::new().with_user(user))
+            .into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        db.merge(id, map! { "factor/title": "b", "factor/description": "d" })
+            .await
+            .unwrap();
+        db.delete(id).await.unwrap();
+
+        let mut entries = db
+            .select_map(Select::new().with_filter(Expr::and(
+                Expr::is_entity::<AuditEntryType>(),
+                Expr::eq(AttrAuditEntity::expr(), id),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        entries.sort_by_key(|e| e.get_attr::<AttrAuditOperation>());
+        for entry in &entries {
+            assert_eq!(entry.get_attr::<AttrAuditActor>(), Some(user));
+        }
+
+        let ops = entries
+            .iter()
+            .map(|e| e.get_attr::<AttrAuditOperation>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["create", "delete", "merge"]);
+
+        let merge = &entries[2];
+        let changed: Vec<String> = from_value(
+            merge
+                .get(AttrAuditAttributes::QUALIFIED_NAME)
+                .unwrap()
+                .clone(),
+        )
+        .unwrap();
+        assert_eq!(changed, vec!["factor/description", "factor/title"]);
+        let revert: Patch =
+            from_value(merge.get(AttrAuditRevert::QUALIFIED_NAME).unwrap().clone()).unwrap();
+        let reverted = revert
+            .apply_map(map! { "factor/id": id, "factor/title": "b", "factor/description": "d" })
+            .unwrap();
+        assert_eq!(reverted, map! { "factor/id": id, "factor/title": "a" });
+
+        // The audit log can not be tampered with.
+        let err = db.delete(entries[0].get_id().unwrap()).await.unwrap_err();
+        assert!(err.to_string().contains("can not be modified"));
+    }
+
+    #[tokio::test]
+    async fn test_engine_audit_log_records_applied_changes() {
+        let db = Engine::new(MemoryDb::new()).with_audit_log().into_client();
+
+        // The select only matches the entity created in the same batch, so
+        // the delete is only visible while the batch is applied.
+        let id = Id::random();
+        db.batch(
+            Batch::new()
+                .and_create(Create {
+                    id,
+                    data: map! { "factor/title": "a" },
+                })
+                .and_select(MutateSelect {
+                    filter: Expr::eq(AttrTitle::expr(), "a"),
+                    variables: Default::default(),
+                    action: MutateSelectAction::Delete,
+                    guard: None,
+                }),
+        )
+        .await
+        .unwrap();
+
+        let mut entries = db
+            .select_map(Select::new().with_filter(Expr::and(
+                Expr::is_entity::<AuditEntryType>(),
+                Expr::eq(AttrAuditEntity::expr(), id),
+            )))
+            .await
+            .unwrap();
+        entries.sort_by_key(|e| e.get_attr::<AttrAuditOperation>());
+        let ops = entries
+            .iter()
+            .map(|e| e.get_attr::<AttrAuditOperation>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec!["create", "delete"]);
+
+        let revert: Patch = from_value(
+            entries[1]
+                .get(AttrAuditRevert::QUALIFIED_NAME)
+                .unwrap()
+                .clone(),
+        )
+        .unwrap();
+        let restored = revert.apply_map(DataMap::new()).unwrap();
+        assert_eq!(restored, map! { "factor/id": id, "factor/title": "a" });
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use factor_core::{
+    data::{
+        patch::{Patch, PatchOp, PatchPathElem},
+        DataMap, Id, IdOrIdent,
+    },
+    query::{
+        mutate::{Batch, Mutate, MutateSelectAction},
+        select::Item,
+    },
+    schema::AttrMapExt,
+};
+
+use crate::{policy::AuthContext, registry::Registry};
+
+/// The kind of access that is authorized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operation {
+    Read,
+    Create,
+    Replace,
+    Merge,
+    Patch,
+    Delete,
+}
+
+/// Describes a single access to an entity.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Access {
+    pub operation: Operation,
+    pub entity: Option<Id>,
+    /// Ident of the entity class, if the entity has a type.
+    pub class: Option<String>,
+    /// Attributes that are read or written.
+    ///
+    /// For deletes this contains all attributes of the deleted entity.
+    pub attributes: Vec<String>,
+}
+
+/// Central permission check consulted by the [`crate::Engine`] for every
+/// entity that is read or mutated.
+///
+/// Returning an error denies the access and fails the whole operation.
+///
+/// Registered with [`crate::Engine::with_authorizer`].
+///
+/// Mutations are authorized before any [`crate::Hook`] runs, so changes made
+/// by hooks are not checked.
+/// Entities affected by a [`Mutate::Select`] are authorized based on the
+/// state before the batch is applied.
+pub trait Authorizer: Send + Sync {
+    fn authorize(&self, access: &Access) -> Result<(), anyhow::Error>;
+
+    /// Authorize an access with the [`AuthContext`] of the engine, if one
+    /// was set with [`crate::Engine::with_auth_context`].
+    ///
+    /// Called by the engine. Defaults to [`Self::authorize`].
+    fn authorize_with_context(
+        &self,
+        access: &Access,
+        _ctx: Option<&AuthContext>,
+    ) -> Result<(), anyhow::Error> {
+        self.authorize(access)
+    }
+}
+
+impl Access {
+    fn new(operation: Operation, entity: Option<Id>, class: Option<String>) -> Self {
+        Self {
+            operation,
+            entity,
+            class,
+            attributes: Vec::new(),
+        }
+    }
+
+    fn with_data(mut self, data: &DataMap) -> Self {
+        self.attributes = data.keys().cloned().collect();
+        self.attributes.sort();
+        self
+    }
+
+    fn with_patch(mut self, patch: &Patch) -> Self {
+        let mut attributes = patch
+            .0
+            .iter()
+            .filter_map(|op| {
+                let path = match op {
+                    PatchOp::Add { path, .. }
+                    | PatchOp::Replace { path, .. }
+                    | PatchOp::Remove { path, .. } => path,
+                };
+                match path.0.first() {
+                    Some(PatchPathElem::Key(key)) => Some(key.clone()),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        attributes.sort();
+        attributes.dedup();
+        self.attributes = attributes;
+        self
+    }
+}
+
+/// Ident of the class of an entity.
+fn class_of(reg: &Registry, data: &DataMap) -> Option<String> {
+    let ty = data.get_type()?;
+    reg.entity_by_ident(&ty).map(|e| e.schema.ident.clone())
+}
+
+/// Build the [`Access`] for reading an entity.
+pub(crate) fn read_access(reg: &Registry, data: &DataMap) -> Access {
+    Access::new(Operation::Read, data.get_id(), class_of(reg, data)).with_data(data)
+}
+
+/// Authorize all entities of a select result, including joined entities.
+pub(crate) fn authorize_items(
+    authorizer: &dyn Authorizer,
+    ctx: Option<&AuthContext>,
+    reg: &Registry,
+    items: &[Item],
+) -> Result<(), anyhow::Error> {
+    for item in items {
+        authorizer.authorize_with_context(&read_access(reg, &item.data), ctx)?;
+        for join in &item.joins {
+            authorize_items(authorizer, ctx, reg, &join.items)?;
+        }
+    }
+    Ok(())
+}
+
+/// Build the accesses for all mutations of a batch.
+///
+/// `existing` must contain the current data of modified entities, and
+/// `selected` the entities matched by each [`Mutate::Select`], keyed by the
+/// index of the action.
+pub(crate) fn batch_accesses(
+    reg: &Registry,
+    batch: &Batch,
+    existing: &HashMap<IdOrIdent, Option<DataMap>>,
+    selected: &HashMap<usize, Vec<DataMap>>,
+) -> Vec<Access> {
+    let existing = |id: Id| existing.get(&IdOrIdent::from(id)).and_then(Option::as_ref);
+    let mut accesses = Vec::new();
+    for (index, action) in batch.actions.iter().enumerate() {
+        match action {
+            Mutate::Create(create) => {
+                accesses.push(
+                    Access::new(
+                        Operation::Create,
+                        Some(create.id),
+                        class_of(reg, &create.data),
+                    )
+                    .with_data(&create.data),
+                );
+            }
+            Mutate::Replace(replace) => {
+                // A replace can change the class, so both the old and the new
+                // class must be allowed.
+                if let Some(old) = existing(replace.id) {
+                    accesses.push(
+                        Access::new(Operation::Replace, Some(replace.id), class_of(reg, old))
+                            .with_data(old),
+                    );
+                }
+                accesses.push(
+                    Access::new(
+                        Operation::Replace,
+                        Some(replace.id),
+                        class_of(reg, &replace.data),
+                    )
+                    .with_data(&replace.data),
+                );
+            }
+            Mutate::Merge(merge) => {
+                let class = existing(merge.id)
+                    .and_then(|old| class_of(reg, old))
+                    .or_else(|| class_of(reg, &merge.data));
+                accesses.push(
+                    Access::new(Operation::Merge, Some(merge.id), class).with_data(&merge.data),
+                );
+            }
+            Mutate::Patch(patch) => {
+                let class = existing(patch.id).and_then(|old| class_of(reg, old));
+                accesses.push(
+                    Access::new(Operation::Patch, Some(patch.id), class).with_patch(&patch.patch),
+                );
+            }
+            Mutate::Delete(delete) => {
+                let old = existing(delete.id);
+                let access = Access::new(
+                    Operation::Delete,
+                    Some(delete.id),
+                    old.and_then(|old| class_of(reg, old)),
+                );
+                accesses.push(match old {
+                    Some(old) => access.with_data(old),
+                    None => access,
+                });
+            }
+            Mutate::Select(select) => {
+                for data in selected.get(&index).into_iter().flatten() {
+                    let access = match &select.action {
+                        MutateSelectAction::Delete => {
+                            Access::new(Operation::Delete, data.get_id(), class_of(reg, data))
+                                .with_data(data)
+                        }
+                        MutateSelectAction::Patch(patch) => {
+                            Access::new(Operation::Patch, data.get_id(), class_of(reg, data))
+                                .with_patch(patch)
+                        }
+                    };
+                    accesses.push(access);
+                }
+            }
+            Mutate::Savepoint | Mutate::Guard(_) => {}
+        }
+    }
+    accesses
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        map,
+        query::{expr::Expr, mutate::MutateSelect, select::Select},
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    /// Denies all access to `factor/description`.
+    struct NoDescription;
+
+    impl Authorizer for NoDescription {
+        fn authorize(&self, access: &Access) -> Result<(), anyhow::Error> {
+            if access.attributes.iter().any(|a| a == "factor/description") {
+                anyhow::bail!("{:?} of factor/description denied", access.operation);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_engine_authorizer() {
+        let engine = Engine::new(MemoryDb::new());
+        let admin = engine.clone().into_client();
+        let db = engine.with_authorizer(NoDescription).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        db.entity(id).await.unwrap();
+
+        let err = db
+            .merge(id, map! { "factor/description": "d" })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Merge of factor/description denied");
+
+        let err = db
+            .create(
+                Id::random(),
+                map! { "factor/title": "b", "factor/description": "d" },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Create of factor/description denied");
+
+        // Reads of entities with denied attributes fail.
+        let hidden = Id::random();
+        admin
+            .create(
+                hidden,
+                map! { "factor/title": "c", "factor/description": "d" },
+            )
+            .await
+            .unwrap();
+        let err = db.entity(hidden).await.unwrap_err();
+        assert_eq!(err.to_string(), "Read of factor/description denied");
+        db.select(Select::new()).await.unwrap_err();
+        db.delete(hidden).await.unwrap_err();
+
+        // Entities matched by a select mutation are authorized.
+        let delete = |title: &str| {
+            Batch::new().and_select(MutateSelect {
+                filter: Expr::eq(Expr::attr_ident("factor/title"), title),
+                variables: Default::default(),
+                action: MutateSelectAction::Delete,
+                guard: None,
+            })
+        };
+        db.batch(delete("c")).await.unwrap_err();
+        db.batch(delete("a")).await.unwrap();
+        assert!(admin.entity(id).await.is_err());
+        assert!(admin.entity(hidden).await.is_ok());
+    }
+}
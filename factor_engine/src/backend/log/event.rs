@@ -1,10 +1,37 @@
-use factor_core::query::{migrate::Migration, mutate::Batch};
+use std::collections::BTreeMap;
+
+use factor_core::{
+    data::{Id, Timestamp},
+    query::{migrate::Migration, mutate::Batch},
+};
 
 /// A event persisted in the log.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct LogEvent {
     pub(super) id: super::EventId,
+    /// Time the event was written.
+    /// Not available for events of compacted logs or logs written by older
+    /// versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) time: Option<Timestamp>,
     pub(super) op: LogOp,
+    /// Generation of the log, see [`super::LogDb::generation`].
+    ///
+    /// Only set on the first event of a log. Logs written by older versions
+    /// have no generation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) generation: Option<Id>,
+    /// Sync cursors advanced by this event, by source name.
+    ///
+    /// Set for events pulled from another database.
+    /// See [`super::LogDb::apply_synced_events`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) sync_cursors: BTreeMap<String, super::EventId>,
+    /// Log generations of the sources in [`Self::sync_cursors`].
+    ///
+    /// Stored separately to keep the format of `sync_cursors`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) sync_generations: BTreeMap<String, Id>,
 }
 
 impl LogEvent {
@@ -13,6 +40,33 @@ impl LogEvent {
         self.id
     }
 
+    /// Time the event was written, if known.
+    pub fn time(&self) -> Option<Timestamp> {
+        self.time
+    }
+
+    /// Sync cursors advanced by this event.
+    pub(super) fn sync_cursor_updates(
+        &self,
+    ) -> impl Iterator<Item = (String, super::SyncCursor)> + '_ {
+        self.sync_cursors.iter().map(move |(source, event_id)| {
+            let cursor = super::SyncCursor {
+                generation: self.sync_generations.get(source).copied(),
+                event_id: *event_id,
+            };
+            (source.clone(), cursor)
+        })
+    }
+
+    pub(super) fn set_sync_cursors(&mut self, cursors: &BTreeMap<String, super::SyncCursor>) {
+        for (source, cursor) in cursors {
+            self.sync_cursors.insert(source.clone(), cursor.event_id);
+            if let Some(generation) = cursor.generation {
+                self.sync_generations.insert(source.clone(), generation);
+            }
+        }
+    }
+
     // fn from_op(op: super::DbOp) -> Option<Self> {
     //     use super::{DbOp, TupleOp};
     //     match op {
@@ -4,6 +4,9 @@
 pub mod convert_json;
 pub mod store_memory;
 
+#[cfg(test)]
+pub mod store_faulty;
+
 #[cfg(feature = "log_fs")]
 pub mod store_file;
 
@@ -11,28 +14,35 @@ mod event;
 use anyhow::Context;
 pub use event::LogEvent;
 use factor_core::{
-    data::{self, DataMap, Id, Value},
+    data::{self, DataMap, Id, Timestamp, Value},
+    db::{ClassStats, EntityVersion, HealthReport},
     query::{
         self,
         migrate::SchemaAction,
         mutate::{Batch, Mutate},
-        select::Item,
+        select::{Item, Order, Select},
     },
     schema::{
         builtin::{AttrId, AttrType},
-        AttributeMeta,
+        AttrMapExt, AttributeMeta,
     },
 };
+use tracing::Instrument;
 
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, RwLock},
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, RwLockReadGuard,
+    },
 };
 
 use futures::{
+    channel::mpsc::UnboundedSender,
     future::{ready, BoxFuture},
     stream::BoxStream,
-    FutureExt, StreamExt,
+    FutureExt, StreamExt, TryStreamExt,
 };
 
 use crate::registry;
@@ -41,13 +51,85 @@ use self::event::LogOp;
 
 use super::{
     memory::store::{MemoryStore, RevertEpoch},
-    Backend, BackendFuture,
+    Audit, Backend, BackendFuture, BackendStats, VerifyIssue,
 };
 
 pub struct LogConfig {}
 
 pub type EventId = u64;
 
+/// Number of entities per batch event in a compacted log.
+const COMPACT_BATCH_SIZE: usize = 1000;
+
+/// Number of events read per store access when replaying past events in
+/// [`LogDb::tail_events`].
+const TAIL_REPLAY_CHUNK_SIZE: usize = 1000;
+
+/// Number of events applied per chunk when restoring the log.
+///
+/// Progress is reported after each chunk.
+/// See [`LogDb::open_with_progress`].
+const RESTORE_CHUNK_SIZE: usize = 1000;
+
+/// Progress of restoring a [`LogDb`] from the log.
+///
+/// See [`LogDb::open_with_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestoreProgress {
+    /// Number of events applied so far.
+    pub events: u64,
+    pub last_event_id: EventId,
+}
+
+/// Error returned when a restore was aborted by the progress callback.
+#[derive(Clone, Copy, Debug)]
+pub struct RestoreAborted {
+    pub progress: RestoreProgress,
+}
+
+impl std::fmt::Display for RestoreAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Log restore aborted after event {}",
+            self.progress.last_event_id
+        )
+    }
+}
+
+impl std::error::Error for RestoreAborted {}
+
+/// Statistics returned by [`LogDb::compact`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompactStats {
+    pub events_before: u64,
+    pub events_after: u64,
+}
+
+/// Position of a database in the log of a sync source.
+///
+/// See [`LogDb::apply_synced_events`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncCursor {
+    /// Generation of the source log, see [`LogDb::generation`].
+    ///
+    /// `None` if nothing was synced yet, or the cursor was written by an
+    /// older version.
+    pub generation: Option<Id>,
+    /// Id of the last applied event of the source, or 0.
+    pub event_id: EventId,
+}
+
+/// Events of a log that follow a given event, see [`LogDb::event_page`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct EventPage {
+    pub events: Vec<LogEvent>,
+    /// Id of the last event of the log.
+    pub last_event_id: EventId,
+    /// Generation of the log, see [`LogDb::generation`].
+    pub generation: Id,
+}
+
 /// LogDb is a simple database backend that is based on an event log.
 /// Mutations are written to the event log.
 /// On restart, the log is read and aggregated.
@@ -57,7 +139,10 @@ pub type EventId = u64;
 ///
 /// Since the log grows large over time, the log can be compacted by rewriting
 /// the event stream and only retaining relevant events.
-/// TODO: implement compaction.
+/// See [`LogDb::compact`].
+///
+/// Reads never observe a write before its event was written to the log, so
+/// writes that fail to persist and are reverted stay invisible.
 ///
 /// TODO: implement mechanism for only keeping some data in memory and loading
 /// the rest on demand.
@@ -71,12 +156,31 @@ struct State {
     registry: registry::SharedRegistry,
     mutable: futures::lock::Mutex<MutableState>,
     mem: RwLock<MemoryStore>,
+    /// Held while `mem` contains a write that is not written to the log yet.
+    /// See [`LogDb::commit`].
+    pending: futures::lock::Mutex<()>,
+    /// Set while `mem` contains a write that is not written to the log yet.
+    uncommitted: AtomicBool,
+    /// Number of batches that wait for or hold the write lock.
+    pending_batches: AtomicU64,
+    /// When an event was last written to the store.
+    last_write: Mutex<Option<instant::Instant>>,
+    /// Cleared while the state is restored from the log.
+    restored: AtomicBool,
 }
 
 struct MutableState {
     store: Box<dyn LogStore + Send + Sync + 'static>,
     current_event_id: EventId,
     migrations: Vec<query::migrate::Migration>,
+    /// Receivers of new events.
+    /// See [`LogDb::tail_events`].
+    subscribers: Vec<UnboundedSender<LogEvent>>,
+    /// See [`LogDb::generation`].
+    generation: Id,
+    /// Position of each sync source.
+    /// See [`LogDb::apply_synced_events`].
+    sync_cursors: BTreeMap<String, SyncCursor>,
 }
 
 impl MutableState {
@@ -86,6 +190,22 @@ impl MutableState {
     }
 }
 
+/// Counts a batch in [`State::pending_batches`] until dropped.
+struct PendingBatch<'a>(&'a AtomicU64);
+
+impl<'a> PendingBatch<'a> {
+    fn new(counter: &'a AtomicU64) -> Self {
+        counter.fetch_add(1, Ordering::AcqRel);
+        Self(counter)
+    }
+}
+
+impl<'a> Drop for PendingBatch<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 impl LogDb {
     /// Get access to the store.
     ///
@@ -102,34 +222,47 @@ impl LogDb {
     where
         S: LogStore + Send + Sync + 'static,
     {
-        let registry = registry::Registry::new().into_shared();
+        Self::open_with_progress(store, |_| ControlFlow::Continue(())).await
+    }
 
-        // Ignore index constraints during the restore phase.
-        // Constraints are re-enabled after the restore.
-        let mut memory = MemoryStore::new(registry.clone());
-        memory.set_ignore_index_constraints(true);
+    /// Open the database and report the progress of restoring the log.
+    ///
+    /// Events are applied in chunks, and `on_progress` is called after each
+    /// chunk.
+    /// Returning [`ControlFlow::Break`] aborts the restore, and opening fails
+    /// with a [`RestoreAborted`] error.
+    pub async fn open_with_progress<S, F>(
+        store: S,
+        mut on_progress: F,
+    ) -> Result<Self, anyhow::Error>
+    where
+        S: LogStore + Send + Sync + 'static,
+        F: FnMut(RestoreProgress) -> ControlFlow<()> + Send,
+    {
+        let registry = registry::Registry::new().into_shared();
 
         let state = State {
-            mem: RwLock::new(memory),
+            mem: RwLock::new(MemoryStore::new(registry.clone())),
             registry,
             mutable: futures::lock::Mutex::new(MutableState {
                 migrations: Vec::new(),
                 store: Box::new(store),
                 current_event_id: 0,
+                subscribers: Vec::new(),
+                generation: Id::random(),
+                sync_cursors: BTreeMap::new(),
             }),
+            pending: futures::lock::Mutex::new(()),
+            uncommitted: AtomicBool::new(false),
+            pending_batches: AtomicU64::new(0),
+            last_write: Mutex::new(None),
+            restored: AtomicBool::new(false),
         };
         let s = Self {
             state: Arc::new(state),
         };
 
-        s.restore().await?;
-
-        // Re-enable index constraints.
-        s.state
-            .mem
-            .write()
-            .unwrap()
-            .set_ignore_index_constraints(false);
+        s.restore(&mut on_progress).await?;
 
         Ok(s)
     }
@@ -214,6 +347,7 @@ impl LogDb {
                             Mutate::Select(_sel) => {
                                 todo!("recover_data does not yet support Mutate::Select");
                             }
+                            Mutate::Savepoint | Mutate::Guard(_) => {}
                         }
                     }
                 }
@@ -268,6 +402,14 @@ impl LogDb {
                             }
                             SchemaAction::IndexCreate(_) => {}
                             SchemaAction::IndexDelete(_) => {}
+                            SchemaAction::PolicyCreate(_) => {}
+                            SchemaAction::PolicyDelete(_) => {}
+                            SchemaAction::ViewCreate(_) => {}
+                            SchemaAction::ViewDelete(_) => {}
+                            SchemaAction::RuleCreate(_) => {}
+                            SchemaAction::RuleDelete(_) => {}
+                            SchemaAction::MixinCreate(_) => {}
+                            SchemaAction::MixinDelete(_) => {}
                             SchemaAction::EntityAttributeRemove(rem) => {
                                 if rem.delete_values {
                                     for values in data.values_mut() {
@@ -344,52 +486,482 @@ impl LogDb {
         Ok(())
     }
 
-    async fn restore(&self) -> Result<(), anyhow::Error> {
-        tracing::debug!("log restore started");
+    /// Load all versions of an entity by replaying the log.
+    ///
+    /// A new version is recorded for each event that changed the entity.
+    /// Versions are identified by the event id.
+    ///
+    /// Compacting the log drops the history, since only the current state is
+    /// retained.
+    ///
+    /// WARNING: replays the full log and locks the database until done!
+    pub async fn entity_history(&self, id: Id) -> Result<Vec<EntityVersion>, anyhow::Error> {
+        let mutable = self.state.mutable.lock().await;
+
+        let mut versions = Vec::new();
+        let mut current: Option<DataMap> = None;
+        replay_events(&*mutable.store, EventId::MAX, |event_id, time, mem| {
+            let data = mem.entity_opt(id.into())?;
+            if data != current {
+                versions.push(EntityVersion::new(
+                    event_id,
+                    time,
+                    current.as_ref(),
+                    data.clone(),
+                ));
+                current = data;
+            }
+            Ok(())
+        })
+        .await?;
+
+        Ok(versions)
+    }
+
+    /// Materialize the state of the database as of the given event.
+    ///
+    /// The event itself is included. Useful for inspecting what the data
+    /// looked like at some point in the past.
+    ///
+    /// WARNING: replays the log and locks the database until done!
+    pub async fn snapshot_at(&self, event_id: EventId) -> Result<LogSnapshot, anyhow::Error> {
+        let mutable = self.state.mutable.lock().await;
+        if event_id > mutable.current_event_id {
+            anyhow::bail!(
+                "Invalid event id {}: the log only contains {} events",
+                event_id,
+                mutable.current_event_id
+            );
+        }
+
+        let store = replay_events(&*mutable.store, event_id, |_, _, _| Ok(())).await?;
+        Ok(LogSnapshot { event_id, store })
+    }
+
+    /// Apply a batch without validating unique indexes and references.
+    ///
+    /// Intended for importing data that is known to be consistent, like a
+    /// previous export. Since references are not checked, entities can be
+    /// loaded in any order, even with circular references.
+    pub async fn bulk_load(&self, batch: Batch) -> Result<(), anyhow::Error> {
         let mut mutable = self.state.mutable.lock().await;
+        let op = LogOp::Batch(batch.clone());
+        let apply = move |mem: &mut MemoryStore| {
+            mem.set_ignore_index_constraints(true);
+            let res = mem.apply_batch_revertable(batch);
+            mem.set_ignore_index_constraints(false);
+            res
+        };
+        self.commit(&mut mutable, apply, op, BTreeMap::new()).await
+    }
+
+    /// Rewrite the log so it only contains the current state.
+    ///
+    /// The compacted log consists of all applied migrations, followed by
+    /// batches that create the current entities. Overwritten and deleted
+    /// data is dropped.
+    ///
+    /// Writes are blocked while the log is rewritten.
+    pub async fn compact(&self) -> Result<CompactStats, anyhow::Error> {
+        let mut mutable = self.state.mutable.lock().await;
+
+        let generation = Id::random();
+        let events = self.compacted_events(&mutable, generation)?;
+        let stats = CompactStats {
+            events_before: mutable.current_event_id,
+            events_after: events.len() as u64,
+        };
+
+        mutable.store.replace_events(events).await?;
+        mutable.current_event_id = stats.events_after;
+        // Event ids were reassigned, so tails can't continue and replicas
+        // must start over.
+        mutable.subscribers.clear();
+        mutable.generation = generation;
+
+        tracing::debug!(?stats, "log compaction finished");
+
+        Ok(stats)
+    }
+
+    /// Stream all events starting at `from`, followed by new events as they
+    /// are written.
+    ///
+    /// Past events are read from the store in chunks, so writes are only
+    /// blocked briefly. New events are buffered in memory until they are
+    /// consumed.
+    ///
+    /// The stream ends when the log is compacted or purged, since event ids
+    /// are reassigned. Consumers must start over in that case.
+    pub async fn tail_events(
+        &self,
+        from: EventId,
+    ) -> Result<BoxStream<'static, Result<LogEvent, anyhow::Error>>, anyhow::Error> {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+
+        // Registering under the lock guarantees that every event after
+        // `until` is delivered to the receiver.
+        let until = {
+            let mut mutable = self.state.mutable.lock().await;
+            mutable.subscribers.push(sender);
+            mutable.current_event_id
+        };
+
+        let db = self.clone();
+        let replay = futures::stream::try_unfold(from, move |next| {
+            let db = db.clone();
+            async move {
+                if next > until {
+                    return Ok(None);
+                }
+                let mutable = db.state.mutable.lock().await;
+                let events = mutable
+                    .store
+                    .iter_events(next, until)
+                    .await?
+                    .take(TAIL_REPLAY_CHUNK_SIZE)
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                let next = match events.last() {
+                    Some(event) => event.id + 1,
+                    None => return Ok(None),
+                };
+                Ok::<_, anyhow::Error>(Some((events, next)))
+            }
+        })
+        .map_ok(|events| futures::stream::iter(events.into_iter().map(Ok::<_, anyhow::Error>)))
+        .try_flatten();
+
+        let live = receiver
+            .filter(move |event| ready(event.id >= from))
+            .map(Ok);
+
+        Ok(replay.chain(live).boxed())
+    }
+
+    /// Read up to `limit` events that follow the event `since`.
+    ///
+    /// Used to pull changes into another database, see
+    /// [`Self::apply_synced_events`].
+    pub async fn events_since(
+        &self,
+        since: EventId,
+        limit: usize,
+    ) -> Result<Vec<LogEvent>, anyhow::Error> {
+        let mutable = self.state.mutable.lock().await;
+        if since >= mutable.current_event_id {
+            return Ok(Vec::new());
+        }
+        let events = mutable
+            .store
+            .iter_events(since + 1, mutable.current_event_id)
+            .await?
+            .take(limit)
+            .try_collect()
+            .await?;
+        Ok(events)
+    }
+
+    /// Like [`Self::events_since`], but also returns the position of the
+    /// log.
+    ///
+    /// The events and the position are read atomically, so the events
+    /// always belong to the returned generation.
+    pub async fn event_page(
+        &self,
+        since: EventId,
+        limit: usize,
+    ) -> Result<EventPage, anyhow::Error> {
+        let mutable = self.state.mutable.lock().await;
+        let events = if since >= mutable.current_event_id {
+            Vec::new()
+        } else {
+            mutable
+                .store
+                .iter_events(since + 1, mutable.current_event_id)
+                .await?
+                .take(limit)
+                .try_collect()
+                .await?
+        };
+        Ok(EventPage {
+            events,
+            last_event_id: mutable.current_event_id,
+            generation: mutable.generation,
+        })
+    }
+
+    /// Id of the last written event.
+    pub async fn last_event_id(&self) -> EventId {
+        self.state.mutable.lock().await.current_event_id
+    }
+
+    /// Generation of the log.
+    ///
+    /// Event ids are only comparable within a generation. Compacting or
+    /// purging the log reassigns event ids and starts a new generation.
+    /// Logs written by older versions have the nil generation until they are
+    /// compacted.
+    pub async fn generation(&self) -> Id {
+        self.state.mutable.lock().await.generation
+    }
+
+    /// Position of the last event of `source` applied with
+    /// [`Self::apply_synced_events`].
+    pub async fn sync_cursor(&self, source: &str) -> SyncCursor {
+        let mutable = self.state.mutable.lock().await;
+        mutable
+            .sync_cursors
+            .get(source)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Apply events pulled from another database.
+    ///
+    /// `source` identifies the other database. Each applied event records the
+    /// sync cursor of the source in the log, together with the changes, so
+    /// the cursor survives restarts and compaction.
+    /// Events up to the current cursor are skipped, so applying the same
+    /// events again is a no-op.
+    /// `generation` is the generation of the source log. Event ids of
+    /// different generations are unrelated, so applying events of a new
+    /// generation fails once events were synced. The database must be purged
+    /// and synced again in that case.
+    /// Like when replaying the log, unique indexes and references are not
+    /// validated, since the events were already validated by the source.
+    ///
+    /// Returns the number of applied events.
+    pub async fn apply_synced_events(
+        &self,
+        source: &str,
+        generation: Id,
+        events: Vec<LogEvent>,
+    ) -> Result<u64, anyhow::Error> {
+        let mut mutable = self.state.mutable.lock().await;
+        let cursor = mutable
+            .sync_cursors
+            .get(source)
+            .copied()
+            .unwrap_or_default();
+        if cursor.event_id > 0 && cursor.generation != Some(generation) {
+            anyhow::bail!(
+                "The log of '{}' was compacted or purged since the last sync, a full resync is required",
+                source
+            );
+        }
+
+        let mut applied = 0;
+        for event in events {
+            let cursor = mutable
+                .sync_cursors
+                .get(source)
+                .copied()
+                .unwrap_or_default();
+            if event.id <= cursor.event_id {
+                continue;
+            }
+
+            let op = event.op.clone();
+            let event_id = event.id;
+            let apply = move |mem: &mut MemoryStore| {
+                // Events were validated by the source, and may reference
+                // entities that are only created by later events.
+                mem.set_ignore_index_constraints(true);
+                let res = match op {
+                    LogOp::Batch(batch) => mem.apply_batch_revertable(batch),
+                    LogOp::Migrate(migration) => mem.migrate_revertable(migration),
+                };
+                mem.set_ignore_index_constraints(false);
+                res.with_context(|| {
+                    format!(
+                        "Could not apply event '{}' synced from '{}'",
+                        event_id, source
+                    )
+                })
+            };
+            let cursor = SyncCursor {
+                generation: Some(generation),
+                event_id: event.id,
+            };
+            let cursors = BTreeMap::from([(source.to_string(), cursor)]);
+            self.commit(&mut mutable, apply, event.op.clone(), cursors)
+                .await?;
 
-        self.state.mem.write().unwrap().purge_all_data();
+            if let LogOp::Migrate(migration) = event.op {
+                mutable.migrations.push(migration);
+            }
+            mutable.sync_cursors.insert(source.to_string(), cursor);
+            applied += 1;
+        }
+        Ok(applied)
+    }
 
-        let mut migrations = Vec::new();
+    /// Build the events of a compacted log from the current state.
+    ///
+    /// See [`Self::compact`].
+    fn compacted_events(
+        &self,
+        mutable: &MutableState,
+        generation: Id,
+    ) -> Result<Vec<LogEvent>, anyhow::Error> {
+        let entities = self
+            .state
+            .mem
+            .read()
+            .unwrap()
+            .select_map(Select::new().with_sort(AttrId::expr(), Order::Asc))?;
+
+        let mut ops = mutable
+            .migrations
+            .iter()
+            .cloned()
+            .map(LogOp::Migrate)
+            .collect::<Vec<_>>();
+        for chunk in entities.chunks(COMPACT_BATCH_SIZE) {
+            let actions = chunk
+                .iter()
+                .map(|data| {
+                    let id = data.get_id().context("Entity without id")?;
+                    Ok(Mutate::create(id, data.clone()))
+                })
+                .collect::<Result<Vec<_>, anyhow::Error>>()?;
+            ops.push(LogOp::Batch(Batch { actions }));
+        }
+
+        // Sync cursors must survive compaction, so they are attached to the
+        // last event.
+        if ops.is_empty() && !mutable.sync_cursors.is_empty() {
+            ops.push(LogOp::Batch(Batch::new()));
+        }
+        let count = ops.len();
+        let events = ops
+            .into_iter()
+            .zip(1..)
+            .map(|(op, id)| {
+                let mut event = LogEvent {
+                    id,
+                    time: None,
+                    op,
+                    generation: (id == 1).then_some(generation),
+                    sync_cursors: BTreeMap::new(),
+                    sync_generations: BTreeMap::new(),
+                };
+                if id == count as EventId {
+                    event.set_sync_cursors(&mutable.sync_cursors);
+                }
+                event
+            })
+            .collect();
+        Ok(events)
+    }
+
+    async fn restore(
+        &self,
+        on_progress: &mut (dyn FnMut(RestoreProgress) -> ControlFlow<()> + Send),
+    ) -> Result<(), anyhow::Error> {
+        tracing::debug!("log restore started");
+        let mut mutable = self.state.mutable.lock().await;
+        self.state.restored.store(false, Ordering::Release);
 
-        let mut event_id = 0;
         {
-            let mut stream = mutable.store.iter_events(0, EventId::MAX).await?;
-
-            while let Some(res) = stream.next().await {
-                let event = res?;
-                event_id = event.id;
-
-                tracing::trace!(?event, "restoring logdb event");
-
-                match event.op {
-                    LogOp::Batch(batch) => {
-                        self.state
-                            .mem
-                            .write()
-                            .unwrap()
-                            .apply_batch(batch.clone())
-                            .context(format!(
-                                "Could not apply event '{event_id}' to memory state ({batch:?})",
-                            ))?;
-                    }
-                    LogOp::Migrate(migration) => {
-                        self.state
-                            .mem
-                            .write()
-                            .unwrap()
-                            .migrate(migration.clone())
-                            .context(format!(
-                            "Could not apply event '{event_id}' to memory state ({migration:?})",
-                        ))?;
-                        migrations.push(migration);
+            let mut mem = self.state.mem.write().unwrap();
+            mem.purge_all_data();
+            // Events were validated when they were written, and bulk loaded
+            // data may reference entities that are created later.
+            // Constraints are re-enabled after the restore.
+            mem.set_ignore_index_constraints(true);
+            // Indexes are rebuilt in bulk after all events are applied.
+            mem.set_defer_index_updates(true)?;
+        }
+
+        let replay = async {
+            let mut migrations = Vec::new();
+            let mut generation = None;
+            let mut sync_cursors = BTreeMap::new();
+            let mut progress = RestoreProgress {
+                events: 0,
+                last_event_id: 0,
+            };
+
+            loop {
+                let events = mutable
+                    .store
+                    .iter_events(progress.last_event_id + 1, EventId::MAX)
+                    .await?
+                    .take(RESTORE_CHUNK_SIZE)
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                if events.is_empty() {
+                    break;
+                }
+
+                {
+                    let mut mem = self.state.mem.write().unwrap();
+                    for event in events {
+                        let event_id = event.id;
+                        tracing::trace!(?event, "restoring logdb event");
+                        if event_id == 1 {
+                            generation = Some(event.generation.unwrap_or_else(Id::nil));
+                        }
+                        sync_cursors.extend(event.sync_cursor_updates());
+
+                        match event.op {
+                            LogOp::Batch(batch) => {
+                                mem.apply_batch(batch.clone()).with_context(|| {
+                                    format!(
+                                        "Could not apply event '{event_id}' to memory state ({batch:?})",
+                                    )
+                                })?;
+                            }
+                            LogOp::Migrate(migration) => {
+                                mem.migrate(migration.clone()).with_context(|| {
+                                    format!(
+                                        "Could not apply event '{event_id}' to memory state ({migration:?})",
+                                    )
+                                })?;
+                                migrations.push(migration);
+                            }
+                        }
+
+                        progress.events += 1;
+                        progress.last_event_id = event_id;
                     }
                 }
+
+                tracing::debug!(?progress, "log restore progress");
+                if on_progress(progress).is_break() {
+                    return Err(RestoreAborted { progress }.into());
+                }
             }
+
+            Ok::<_, anyhow::Error>((
+                migrations,
+                generation,
+                sync_cursors,
+                progress.last_event_id,
+            ))
         }
+        .await;
+
+        let rebuild = {
+            let mut mem = self.state.mem.write().unwrap();
+            let rebuild = mem.set_defer_index_updates(false);
+            mem.set_ignore_index_constraints(false);
+            rebuild
+        };
+        let (migrations, generation, sync_cursors, event_id) = replay?;
+        rebuild.context("Could not rebuild indexes")?;
 
         mutable.migrations = migrations;
+        // Empty logs keep the current generation, which is written with the
+        // first event.
+        if let Some(generation) = generation {
+            mutable.generation = generation;
+        }
+        mutable.sync_cursors = sync_cursors;
         mutable.current_event_id = event_id;
+        self.state.restored.store(true, Ordering::Release);
 
         tracing::debug!("log restore finished");
 
@@ -400,16 +972,97 @@ impl LogDb {
     ///
     /// Primarily used for testing.
     pub async fn force_rebuild(&self) -> Result<(), anyhow::Error> {
-        self.restore().await?;
+        self.restore(&mut |_| ControlFlow::Continue(())).await?;
         Ok(())
     }
 
+    /// Apply a write to the memory state and write the event for it.
+    ///
+    /// Readers only observe the write after the event was written, see
+    /// [`Self::read_committed`]. If writing the event fails, the write is
+    /// reverted.
+    async fn commit(
+        &self,
+        mutable: &mut MutableState,
+        apply: impl FnOnce(&mut MemoryStore) -> Result<RevertEpoch, anyhow::Error> + Send,
+        op: LogOp,
+        sync_cursors: BTreeMap<String, SyncCursor>,
+    ) -> Result<(), anyhow::Error> {
+        let apply = move |mem: &mut MemoryStore| Ok(Some((apply(mem)?, op)));
+        self.commit_with(mutable, apply, sync_cursors).await
+    }
+
+    /// Like [`Self::commit`], but the written operation is determined by the
+    /// apply function.
+    ///
+    /// No event is written if the apply function returns `None`.
+    async fn commit_with(
+        &self,
+        mutable: &mut MutableState,
+        apply: impl FnOnce(&mut MemoryStore) -> Result<Option<(RevertEpoch, LogOp)>, anyhow::Error>
+            + Send,
+        sync_cursors: BTreeMap<String, SyncCursor>,
+    ) -> Result<(), anyhow::Error> {
+        let _pending = self.state.pending.lock().await;
+        let (revert_epoch, op) = {
+            let mut mem = self.state.mem.write().unwrap();
+            match apply(&mut mem)? {
+                Some(applied) => {
+                    self.state.uncommitted.store(true, Ordering::Release);
+                    applied
+                }
+                None => return Ok(()),
+            }
+        };
+
+        let id = mutable.increment_event_id();
+        let mut event = LogEvent {
+            id,
+            time: Some(Timestamp::now()),
+            op,
+            generation: (id == 1).then_some(mutable.generation),
+            sync_cursors: BTreeMap::new(),
+            sync_generations: BTreeMap::new(),
+        };
+        event.set_sync_cursors(&sync_cursors);
+        let res = self
+            .write_event_revertable(mutable, event, revert_epoch)
+            .await;
+        self.state.uncommitted.store(false, Ordering::Release);
+        res
+    }
+
+    /// Lock the memory state for reading.
+    ///
+    /// Waits for a pending write to be committed or reverted, so reads
+    /// observe the state either before or after a write.
+    /// See [`Self::commit`].
+    async fn read_committed(&self) -> RwLockReadGuard<'_, MemoryStore> {
+        loop {
+            {
+                let mem = self.state.mem.read().unwrap();
+                if !self.state.uncommitted.load(Ordering::Acquire) {
+                    return mem;
+                }
+            }
+            drop(self.state.pending.lock().await);
+        }
+    }
+
     async fn write_event(
         &self,
         mutable: &mut MutableState,
         event: LogEvent,
     ) -> Result<(), anyhow::Error> {
-        mutable.store.write_event(event).await?;
+        if mutable.subscribers.is_empty() {
+            mutable.store.write_event(event).await?;
+        } else {
+            mutable.store.write_event(event.clone()).await?;
+            mutable
+                .subscribers
+                .retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+        }
+        *self.state.last_write.lock().unwrap() = Some(instant::Instant::now());
         Ok(())
     }
 
@@ -419,9 +1072,12 @@ impl LogDb {
         event: LogEvent,
         revert_epoch: RevertEpoch,
     ) -> Result<(), anyhow::Error> {
+        let event_id = event.id;
         match self.write_event(mutable, event).await {
             Ok(_) => Ok(()),
             Err(err) => {
+                // The event was not written, so the id must be re-used.
+                mutable.current_event_id = event_id.wrapping_sub(1);
                 self.state
                     .mem
                     .write()
@@ -438,11 +1094,13 @@ impl LogDb {
         migration: query::migrate::Migration,
         is_internal: bool,
     ) -> Result<(), anyhow::Error> {
+        // Hold the lock for the whole migration, so the registry can not
+        // change between building the migration and storing the result.
+        let mut mutable = self.state.mutable.lock().await;
+
         if let Some(name) = &migration.name {
             // Ensure name uniqueness.
-            let state = self.state.mutable.lock().await;
-
-            let name_exists = state
+            let name_exists = mutable
                 .migrations
                 .iter()
                 .filter_map(|m| m.name.as_ref())
@@ -456,7 +1114,7 @@ impl LogDb {
         // If not, we do not write it.
         // This is important to not spam the log with migrations when UPSERTS
         // happen.
-        let mut reg = self.state.registry.read().unwrap().clone();
+        let mut reg = registry::Registry::clone(&self.state.registry.load());
         let (mig, ops) =
             crate::schema_builder::build_migration(&mut reg, migration.clone(), is_internal)?;
 
@@ -464,42 +1122,110 @@ impl LogDb {
             return Ok(());
         }
 
-        let mut mutable = self.state.mutable.lock().await;
-        let revert_epoch = self
-            .state
-            .mem
-            .write()
-            .unwrap()
-            .migrate_revertable(migration.clone())?;
-
-        let event = LogEvent {
-            id: mutable.increment_event_id(),
-            op: LogOp::Migrate(migration),
-        };
-        self.write_event_revertable(&mut mutable, event, revert_epoch)
-            .await?;
+        // Reverting the memory state does not restore the schema.
+        let previous_registry = self.state.registry.load_full();
+        let apply = |mem: &mut MemoryStore| mem.migrate_revertable(migration.clone());
+        let op = LogOp::Migrate(migration.clone());
+        if let Err(err) = self.commit(&mut mutable, apply, op, BTreeMap::new()).await {
+            self.state.registry.store(previous_registry);
+            return Err(err);
+        }
+        mutable.migrations.push(migration);
 
-        *self.state.registry.write().unwrap() = reg;
+        self.state.registry.store(Arc::new(reg));
         Ok(())
     }
 
     async fn apply_batch(self, batch: Batch) -> Result<(), anyhow::Error> {
+        let _pending = PendingBatch::new(&self.state.pending_batches);
         let mut mutable = self.state.mutable.lock().await;
-        let revert_epoch = self
-            .state
-            .mem
-            .write()
-            .unwrap()
-            .apply_batch_revertable(batch.clone())?;
+        let op = LogOp::Batch(batch.clone());
+        let apply = move |mem: &mut MemoryStore| mem.apply_batch_revertable(batch);
+        self.commit(&mut mutable, apply, op, BTreeMap::new()).await
+    }
 
-        let event = LogEvent {
-            id: mutable.increment_event_id(),
-            op: LogOp::Batch(batch),
+    async fn apply_batch_audited(self, batch: Batch, audit: Audit) -> Result<(), anyhow::Error> {
+        let _pending = PendingBatch::new(&self.state.pending_batches);
+        let mut mutable = self.state.mutable.lock().await;
+        let apply = move |mem: &mut MemoryStore| {
+            let mut logged = batch.clone();
+            let (epoch, entries) = mem.apply_batch_audited_revertable(batch, &audit)?;
+            // The entries are logged with the batch, so replaying the event
+            // restores them without recording the changes again.
+            logged
+                .actions
+                .extend(entries.into_iter().map(Mutate::Create));
+            Ok(Some((epoch, LogOp::Batch(logged))))
         };
-        self.write_event_revertable(&mut mutable, event, revert_epoch)
+        self.commit_with(&mut mutable, apply, BTreeMap::new()).await
+    }
+
+    async fn apply_batch_with_savepoints(
+        self,
+        batch: Batch,
+        audit: Option<Audit>,
+    ) -> Result<Vec<Result<(), anyhow::Error>>, anyhow::Error> {
+        let _pending = PendingBatch::new(&self.state.pending_batches);
+        let mut mutable = self.state.mutable.lock().await;
+        let segments = batch.clone().into_segments();
+        let mut results = Vec::new();
+        let apply = |mem: &mut MemoryStore| {
+            let (epoch, segment_results) =
+                mem.apply_batch_with_savepoints_revertable(batch, audit.as_ref());
+            // Only the applied segments are logged, so replaying the event
+            // does not depend on the failed ones.
+            let mut applied = Batch::new();
+            for (segment, res) in segments.into_iter().zip(segment_results) {
+                match res {
+                    Ok(entries) => {
+                        applied.actions.extend(segment.actions);
+                        applied
+                            .actions
+                            .extend(entries.into_iter().map(Mutate::Create));
+                        results.push(Ok(()));
+                    }
+                    Err(err) => results.push(Err(err)),
+                }
+            }
+            if applied.actions.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some((epoch, LogOp::Batch(applied))))
+            }
+        };
+        self.commit_with(&mut mutable, apply, BTreeMap::new())
             .await?;
+        Ok(results)
+    }
 
-        Ok(())
+    async fn health(self) -> Result<HealthReport, anyhow::Error> {
+        let pending_batches = self.state.pending_batches.load(Ordering::Acquire);
+        let last_sync_age_ms = self
+            .state
+            .last_write
+            .lock()
+            .unwrap()
+            .map(|time| time.elapsed().as_millis() as u64);
+
+        // Waits for in-progress writes, so a stuck store makes the health
+        // check time out instead of reporting a healthy database.
+        let storage_reachable = {
+            let mut mutable = self.state.mutable.lock().await;
+            match mutable.store.size_log().await {
+                Ok(_) => true,
+                Err(err) => {
+                    tracing::warn!(error = %err, "log store is not reachable");
+                    false
+                }
+            }
+        };
+
+        Ok(HealthReport {
+            storage_reachable,
+            last_sync_age_ms,
+            pending_batches,
+            registry_loaded: self.state.restored.load(Ordering::Acquire),
+        })
     }
 }
 
@@ -509,25 +1235,57 @@ impl Backend for LogDb {
     }
 
     fn entity(&self, id: data::IdOrIdent) -> BackendFuture<Option<data::DataMap>> {
-        let res = self.state.mem.read().unwrap().entity_opt(id);
-        ready(res).boxed()
+        let s = self.clone();
+        async move { s.read_committed().await.entity_opt(id) }.boxed()
+    }
+
+    fn entities(
+        &self,
+        ids: Vec<data::IdOrIdent>,
+    ) -> BackendFuture<HashMap<data::IdOrIdent, Option<DataMap>>> {
+        let s = self.clone();
+        async move { s.read_committed().await.entities(ids) }.boxed()
     }
 
     fn select(
         &self,
         query: query::select::Select,
     ) -> super::BackendFuture<query::select::Page<Item>> {
-        let res = self.state.mem.read().unwrap().select(query);
-        ready(res).boxed()
+        let s = self.clone();
+        async move { s.read_committed().await.select(query) }.boxed()
     }
 
     fn select_map(&self, query: query::select::Select) -> BackendFuture<Vec<DataMap>> {
-        let res = self.state.mem.read().unwrap().select_map(query);
-        ready(res).boxed()
+        let s = self.clone();
+        async move { s.read_committed().await.select_map(query) }.boxed()
     }
 
     fn apply_batch(&self, batch: Batch) -> super::BackendFuture<()> {
-        self.clone().apply_batch(batch).boxed()
+        let span = tracing::debug_span!("log apply_batch", mutations = batch.actions.len());
+        self.clone().apply_batch(batch).instrument(span).boxed()
+    }
+
+    fn apply_batch_audited(&self, batch: Batch, audit: Audit) -> super::BackendFuture<()> {
+        let span = tracing::debug_span!("log apply_batch_audited", mutations = batch.actions.len());
+        self.clone()
+            .apply_batch_audited(batch, audit)
+            .instrument(span)
+            .boxed()
+    }
+
+    fn apply_batch_with_savepoints(
+        &self,
+        batch: Batch,
+        audit: Option<Audit>,
+    ) -> super::BackendFuture<Vec<Result<(), anyhow::Error>>> {
+        let span = tracing::debug_span!(
+            "log apply_batch_with_savepoints",
+            mutations = batch.actions.len()
+        );
+        self.clone()
+            .apply_batch_with_savepoints(batch, audit)
+            .instrument(span)
+            .boxed()
     }
 
     fn migrate(&self, migration: query::migrate::Migration) -> super::BackendFuture<()> {
@@ -540,6 +1298,9 @@ impl Backend for LogDb {
             let mut mutable = s.state.mutable.lock().await;
             mutable.store.clear().await?;
             mutable.current_event_id = 0;
+            mutable.subscribers.clear();
+            mutable.generation = Id::random();
+            mutable.sync_cursors.clear();
             // FIXME: handle a failed purge by tainting the state and
             // rejecting all usage.
             s.state.mem.write().unwrap().purge_all_data();
@@ -552,13 +1313,19 @@ impl Backend for LogDb {
         Some(self)
     }
 
+    fn entity_history(&self, id: Id) -> BackendFuture<Vec<EntityVersion>> {
+        let s = self.clone();
+        async move { s.entity_history(id).await }.boxed()
+    }
+
     fn migrations(&self) -> BackendFuture<Vec<query::migrate::Migration>> {
         let s = self.clone();
         async move { Ok(s.state.mutable.lock().await.migrations.clone()) }.boxed()
     }
 
     fn memory_usage(&self) -> BackendFuture<Option<u64>> {
-        ready(Ok(None)).boxed()
+        let s = self.clone();
+        async move { Ok(s.read_committed().await.stats().memory_usage) }.boxed()
     }
 
     fn storage_usage(&self) -> BackendFuture<Option<u64>> {
@@ -570,6 +1337,114 @@ impl Backend for LogDb {
         }
         .boxed()
     }
+
+    /// If the store can not report the data size, it is estimated as the
+    /// size of the compacted log in JSON format.
+    fn stats(&self) -> BackendFuture<BackendStats> {
+        let s = self.clone();
+        async move {
+            let mut stats = s.read_committed().await.stats();
+
+            let mut mutable = s.state.mutable.lock().await;
+            stats.size_log = mutable.store.size_log().await?;
+            stats.size_data = match mutable.store.size_data().await? {
+                Some(size) => Some(size),
+                None => {
+                    let mut size = 0;
+                    for event in s.compacted_events(&mutable, mutable.generation)? {
+                        size += serde_json::to_vec(&event)?.len() as u64 + 1;
+                    }
+                    Some(size)
+                }
+            };
+
+            Ok(stats)
+        }
+        .boxed()
+    }
+
+    fn verify(&self) -> BackendFuture<Vec<VerifyIssue>> {
+        let s = self.clone();
+        async move { Ok(s.read_committed().await.verify()) }.boxed()
+    }
+
+    fn class_stats(&self) -> BackendFuture<Vec<ClassStats>> {
+        let s = self.clone();
+        async move { Ok(s.read_committed().await.class_stats()) }.boxed()
+    }
+
+    fn health(&self) -> BackendFuture<HealthReport> {
+        self.clone().health().boxed()
+    }
+
+    fn select_view(
+        &self,
+        name: String,
+        filter: Option<query::expr::Expr>,
+    ) -> BackendFuture<query::select::Page<Item>> {
+        let s = self.clone();
+        async move { s.read_committed().await.select_view(&name, filter) }.boxed()
+    }
+
+    fn shortest_path(
+        &self,
+        query: query::graph::GraphQuery,
+        filter: Option<query::expr::Expr>,
+    ) -> BackendFuture<Option<Vec<Id>>> {
+        let s = self.clone();
+        async move { s.read_committed().await.shortest_path(&query, filter) }.boxed()
+    }
+}
+
+/// Replay the events up to and including `until` into a new [`MemoryStore`].
+///
+/// `on_event` is called after each applied event.
+async fn replay_events(
+    store: &(dyn LogStore + Send + Sync),
+    until: EventId,
+    mut on_event: impl FnMut(EventId, Option<Timestamp>, &MemoryStore) -> Result<(), anyhow::Error>,
+) -> Result<MemoryStore, anyhow::Error> {
+    let mut mem = MemoryStore::new(registry::Registry::new().into_shared());
+    mem.set_ignore_index_constraints(true);
+
+    let mut stream = store.iter_events(0, until).await?;
+    while let Some(res) = stream.next().await {
+        let event = res?;
+        let event_id = event.id;
+        let res = match event.op {
+            LogOp::Batch(batch) => mem.apply_batch(batch),
+            LogOp::Migrate(migration) => mem.migrate(migration),
+        };
+        res.with_context(|| format!("Could not replay event '{event_id}'"))?;
+        on_event(event_id, event.time, &mem)?;
+    }
+
+    mem.set_ignore_index_constraints(false);
+    Ok(mem)
+}
+
+/// A read-only view of the state of a [`LogDb`] as of a past event.
+///
+/// Created with [`LogDb::snapshot_at`]. Derefs to the underlying
+/// [`MemoryStore`], so selects can be run against it.
+pub struct LogSnapshot {
+    event_id: EventId,
+    store: MemoryStore,
+}
+
+impl LogSnapshot {
+    /// The id of the last event included in the snapshot.
+    pub fn event_id(&self) -> EventId {
+        self.event_id
+    }
+}
+
+impl std::ops::Deref for LogSnapshot {
+    type Target = MemoryStore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
 }
 
 /// Defines a storage backend used by a [LogStore].
@@ -577,6 +1452,7 @@ pub trait LogStore {
     fn as_any(&self) -> &dyn std::any::Any;
 
     /// Iterate over the event log.
+    /// Both `from` and `until` are inclusive.
     /// use until: EventId::MAX to read until the end.
     fn iter_events(
         &self,
@@ -595,6 +1471,13 @@ pub trait LogStore {
     /// Delete all events.
     fn clear(&mut self) -> BoxFuture<'_, Result<(), anyhow::Error>>;
 
+    /// Replace all events in the log.
+    ///
+    /// Used for compaction, so implementations should make sure that the
+    /// old log is kept intact if writing the new events fails.
+    fn replace_events(&mut self, events: Vec<LogEvent>)
+        -> BoxFuture<'_, Result<(), anyhow::Error>>;
+
     /// Get the full size of the log in bytes.
     fn size_log(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>>;
 
@@ -666,13 +1549,102 @@ mod tests {
         assert_eq!(data::Value::from("hello"), data["test/text"]);
 
         // Restore.
-        log.restore().await.unwrap();
+        log.restore(&mut |_| ControlFlow::Continue(()))
+            .await
+            .unwrap();
 
         // Test that data is still there.
         let data = db.entity(id).await.unwrap();
         assert_eq!(data::Value::from("hello"), data["test/text"]);
     }
 
+    #[tokio::test]
+    async fn test_log_backend_entity_history() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        db.create(Id::random(), map! { "factor/title": "other" })
+            .await
+            .unwrap();
+        db.merge(id, map! { "factor/title": "b" }).await.unwrap();
+        db.delete(id).await.unwrap();
+
+        let history = db.history(id).await.unwrap();
+        let versions = history.iter().map(|v| v.version).collect::<Vec<_>>();
+        assert_eq!(versions, vec![1, 3, 4]);
+        assert!(history.iter().all(|v| v.time.is_some()));
+        assert_eq!(
+            history[1].diff,
+            data::patch::Patch::new().replace("factor/title", "b")
+        );
+        assert_eq!(history[2].data, None);
+
+        let title =
+            |data: Option<DataMap>| data.and_then(|d| d.get_attr::<schema::builtin::AttrTitle>());
+        assert_eq!(title(db.entity_at(id, 0u64).await.unwrap()), None);
+        assert_eq!(
+            title(db.entity_at(id, 2u64).await.unwrap()),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            title(db.entity_at(id, 3u64).await.unwrap()),
+            Some("b".to_string())
+        );
+        assert_eq!(db.entity_at(id, 4u64).await.unwrap(), None);
+        assert_eq!(
+            db.entity_at(id, Timestamp::from_millis(0)).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_snapshot_at() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        db.merge(id, map! { "factor/title": "b" }).await.unwrap();
+        let other = Id::random();
+        db.create(other, map! { "factor/title": "c" })
+            .await
+            .unwrap();
+
+        let snapshot = log.snapshot_at(1).await.unwrap();
+        assert_eq!(snapshot.event_id(), 1);
+        assert_eq!(
+            snapshot
+                .entity(id.into())
+                .unwrap()
+                .get_attr::<schema::builtin::AttrTitle>(),
+            Some("a".to_string())
+        );
+        assert_eq!(snapshot.entity_opt(other.into()).unwrap(), None);
+        let items = snapshot
+            .select_map(Select::new().with_filter(query::expr::Expr::eq(
+                schema::builtin::AttrTitle::expr(),
+                "a",
+            )))
+            .unwrap();
+        assert_eq!(items.len(), 1);
+
+        // The snapshot is independent of the current state.
+        assert_eq!(
+            db.entity(id)
+                .await
+                .unwrap()
+                .get_attr::<schema::builtin::AttrTitle>(),
+            Some("b".to_string())
+        );
+        assert!(log.snapshot_at(4).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_log_backend_with_memory_store_export() {
         let log = LogDb::open(store_memory::MemoryLogStore::new())
@@ -703,18 +1675,26 @@ mod tests {
             vec![
                 LogEvent {
                     id: 1,
+                    time: events[0].time,
                     op: LogOp::Batch(Batch {
                         actions: vec![query::mutate::Mutate::Create(query::mutate::Create {
                             id,
                             data
                         }),]
-                    })
+                    }),
+                    generation: Some(log.generation().await),
+                    sync_cursors: BTreeMap::new(),
+                    sync_generations: BTreeMap::new(),
                 },
                 LogEvent {
                     id: 2,
+                    time: events[1].time,
                     op: LogOp::Batch(Batch {
                         actions: vec![query::mutate::Mutate::Delete(query::mutate::Delete { id }),]
-                    })
+                    }),
+                    generation: None,
+                    sync_cursors: BTreeMap::new(),
+                    sync_generations: BTreeMap::new(),
                 }
             ]
         );
@@ -758,4 +1738,337 @@ mod tests {
         assert_eq!(id2, restored[1].get_id().unwrap());
         assert_eq!(id3, restored[2].get_id().unwrap());
     }
+
+    #[tokio::test]
+    async fn test_log_backend_bulk_load() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        db.migrate(
+            query::migrate::Migration::new()
+                .attr_create(schema::Attribute::new("test/ref", data::ValueType::Ref)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(1, db.migrations().await.unwrap().len());
+
+        // Entities referencing each other can be loaded in any order.
+        let id1 = Id::random();
+        let id2 = Id::random();
+        let batch = Batch::new()
+            .and_create(query::mutate::Create {
+                id: id1,
+                data: map! { "test/ref": id2 },
+            })
+            .and_create(query::mutate::Create {
+                id: id2,
+                data: map! { "test/ref": id1 },
+            });
+        log.bulk_load(batch).await.unwrap();
+
+        log.force_rebuild().await.unwrap();
+        let data = db.entity(id1).await.unwrap();
+        assert_eq!(data::Value::from(id2), data["test/ref"]);
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_compact() {
+        let mem = store_memory::MemoryLogStore::new();
+        let log = LogDb::open(mem.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        db.migrate(
+            query::migrate::Migration::new()
+                .attr_create(schema::Attribute::new("test/text", data::ValueType::String)),
+        )
+        .await
+        .unwrap();
+
+        let id1 = Id::random();
+        let id2 = Id::random();
+        db.create(id1, map! { "test/text": "a" }).await.unwrap();
+        db.create(id2, map! { "test/text": "b" }).await.unwrap();
+        db.merge(id1, map! { "test/text": "c" }).await.unwrap();
+        db.delete(id2).await.unwrap();
+
+        let stats = log.compact().await.unwrap();
+        assert_eq!(
+            stats,
+            CompactStats {
+                events_before: 5,
+                events_after: 2,
+            }
+        );
+
+        // New writes continue after the compacted events.
+        db.create(id2, map! { "test/text": "d" }).await.unwrap();
+
+        let restored = LogDb::open(mem).await.unwrap();
+        assert_eq!(1, Backend::migrations(&restored).await.unwrap().len());
+        let data = Backend::entity(&restored, id1.into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data::Value::from("c"), data["test/text"]);
+        let data = Backend::entity(&restored, id2.into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data::Value::from("d"), data["test/text"]);
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_health() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let report = db.health().await.unwrap();
+        assert!(report.is_healthy());
+        assert_eq!(report.pending_batches, 0);
+
+        db.create(Id::random(), map! { "factor/title": "a" })
+            .await
+            .unwrap();
+        let report = db.health().await.unwrap();
+        assert!(report.is_healthy());
+        assert!(report.last_sync_age_ms.is_some());
+        assert_eq!(report.pending_batches, 0);
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_tail_events() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        for title in ["a", "b"] {
+            db.create(Id::random(), map! { "factor/title": title })
+                .await
+                .unwrap();
+        }
+
+        let mut tail = log.tail_events(2).await.unwrap();
+        db.create(Id::random(), map! { "factor/title": "c" })
+            .await
+            .unwrap();
+
+        let ids = tail
+            .by_ref()
+            .take(2)
+            .map(|res| res.unwrap().id())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ids, vec![2, 3]);
+
+        // Compaction ends the stream.
+        log.compact().await.unwrap();
+        assert!(tail.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_sync_events() {
+        let source_mem = store_memory::MemoryLogStore::new();
+        let source = LogDb::open(source_mem.clone()).await.unwrap();
+        let source_db = Engine::new(source.clone()).into_client();
+
+        source_db
+            .migrate(
+                query::migrate::Migration::new()
+                    .attr_create(schema::Attribute::new("test/text", data::ValueType::String)),
+            )
+            .await
+            .unwrap();
+        let id = Id::random();
+        source_db
+            .create(id, map! { "test/text": "a" })
+            .await
+            .unwrap();
+
+        let mem = store_memory::MemoryLogStore::new();
+        let replica = LogDb::open(mem.clone()).await.unwrap();
+        assert_eq!(SyncCursor::default(), replica.sync_cursor("source").await);
+        let generation = source.generation().await;
+
+        let events = source.events_since(0, 100).await.unwrap();
+        assert_eq!(2, events.len());
+        let applied = replica
+            .apply_synced_events("source", generation, events.clone())
+            .await
+            .unwrap();
+        assert_eq!(2, applied);
+        assert_eq!(
+            SyncCursor {
+                generation: Some(generation),
+                event_id: 2
+            },
+            replica.sync_cursor("source").await
+        );
+
+        // Applying the same events again is a no-op.
+        let applied = replica
+            .apply_synced_events("source", generation, events)
+            .await
+            .unwrap();
+        assert_eq!(0, applied);
+
+        source_db
+            .merge(id, map! { "test/text": "b" })
+            .await
+            .unwrap();
+        let events = source.events_since(2, 100).await.unwrap();
+        replica
+            .apply_synced_events("source", generation, events)
+            .await
+            .unwrap();
+
+        // The cursor survives restarts and compaction.
+        replica.compact().await.unwrap();
+        let restored = LogDb::open(mem).await.unwrap();
+        assert_eq!(
+            SyncCursor {
+                generation: Some(generation),
+                event_id: 3
+            },
+            restored.sync_cursor("source").await
+        );
+        let data = Backend::entity(&restored, id.into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data::Value::from("b"), data["test/text"]);
+
+        // Events are not validated again, so bulk loaded entities with
+        // circular references can be synced.
+        source_db
+            .migrate(
+                query::migrate::Migration::new()
+                    .attr_create(schema::Attribute::new("test/ref", data::ValueType::Ref)),
+            )
+            .await
+            .unwrap();
+        let id1 = Id::random();
+        let id2 = Id::random();
+        source
+            .bulk_load(
+                Batch::new()
+                    .and_create(query::mutate::Create {
+                        id: id1,
+                        data: map! { "test/ref": id2 },
+                    })
+                    .and_create(query::mutate::Create {
+                        id: id2,
+                        data: map! { "test/ref": id1 },
+                    }),
+            )
+            .await
+            .unwrap();
+        let events = source.events_since(3, 100).await.unwrap();
+        assert_eq!(
+            2,
+            restored
+                .apply_synced_events("source", generation, events)
+                .await
+                .unwrap()
+        );
+        let data = Backend::entity(&restored, id1.into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data::Value::from(id2), data["test/ref"]);
+
+        // Compaction reassigns event ids, so events of the new generation
+        // are rejected, even once the log grew past the cursor again.
+        source.compact().await.unwrap();
+        let compacted = source.generation().await;
+        assert_ne!(generation, compacted);
+        for _ in 0..8 {
+            source_db
+                .create(Id::random(), map! { "test/text": "c" })
+                .await
+                .unwrap();
+        }
+        let page = source.event_page(5, 100).await.unwrap();
+        assert_eq!(compacted, page.generation);
+        assert!(!page.events.is_empty());
+        restored
+            .apply_synced_events("source", page.generation, page.events)
+            .await
+            .unwrap_err();
+
+        // The generation survives restarts.
+        let reopened = LogDb::open(source_mem).await.unwrap();
+        assert_eq!(compacted, reopened.generation().await);
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_concurrent_migrations() {
+        let log = LogDb::open(store_memory::MemoryLogStore::new())
+            .await
+            .unwrap();
+        let db = Engine::new(log).into_client();
+
+        // No migration may overwrite the schema changes of another.
+        let names = (0..8)
+            .map(|i| format!("test/concurrent_{i}"))
+            .collect::<Vec<_>>();
+        let migrations = names.iter().map(|name| {
+            db.migrate(
+                query::migrate::Migration::new()
+                    .attr_create(schema::Attribute::new(name, data::ValueType::String)),
+            )
+        });
+        for res in futures::future::join_all(migrations).await {
+            res.unwrap();
+        }
+
+        let schema = db.schema().await.unwrap();
+        for name in &names {
+            assert!(schema.attr_by_ident(name).is_some(), "missing {name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_backend_restore_progress() {
+        let mem = store_memory::MemoryLogStore::new();
+        let log = LogDb::open(mem.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let events = u64::try_from(RESTORE_CHUNK_SIZE).unwrap() + 200;
+        for _ in 0..events {
+            db.create(Id::random(), map! { "factor/title": "a" })
+                .await
+                .unwrap();
+        }
+
+        let mut reports = Vec::new();
+        LogDb::open_with_progress(mem.clone(), |progress| {
+            reports.push(progress);
+            ControlFlow::Continue(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            reports.last(),
+            Some(&RestoreProgress {
+                events,
+                last_event_id: events,
+            })
+        );
+
+        let err = LogDb::open_with_progress(mem, |_| ControlFlow::Break(()))
+            .await
+            .err()
+            .unwrap();
+        let aborted = err.downcast_ref::<RestoreAborted>().unwrap();
+        assert_eq!(
+            aborted.progress.events,
+            u64::try_from(RESTORE_CHUNK_SIZE).unwrap()
+        );
+    }
 }
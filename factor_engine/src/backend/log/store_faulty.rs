@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use futures::{channel::oneshot, future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+
+use super::{convert_json::JsonConverter, EventId, LogConverter, LogEvent, LogStore};
+
+/// Damage applied to a stored event.
+#[derive(Clone, Copy, Debug)]
+pub enum EventFault {
+    /// Only keep the first `len` bytes of the serialized event, like a torn
+    /// write would.
+    Truncate { len: usize },
+    /// Flip all bits of the byte at `offset` in the serialized event.
+    Corrupt { offset: usize },
+}
+
+#[derive(Default, Debug)]
+struct Faults {
+    /// Number of writes that succeed before the next write fails.
+    fail_write_after: Option<u64>,
+    /// The next write waits until the sender is used or dropped.
+    hold_write: Option<oneshot::Receiver<()>>,
+    events: BTreeMap<EventId, EventFault>,
+}
+
+/// Log store wrapper that injects faults.
+/// Only useful for testing.
+///
+/// Writes can be configured to fail, and stored events can be truncated or
+/// corrupted.
+/// Damaged events are round-tripped through the [`JsonConverter`] when read,
+/// so they either fail to deserialize or come back modified, just like
+/// damaged data on disk.
+#[derive(Clone)]
+pub struct FaultyLogStore<S> {
+    inner: S,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<S> FaultyLogStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            faults: Default::default(),
+        }
+    }
+
+    /// Let `successful` writes pass, then fail the next one.
+    ///
+    /// Failed writes do not reach the wrapped store.
+    pub fn fail_write_after(&self, successful: u64) {
+        self.faults.lock().unwrap().fail_write_after = Some(successful);
+    }
+
+    /// Hold the next write until the returned sender is used or dropped.
+    ///
+    /// Allows observing the database while a write is in progress.
+    pub fn hold_next_write(&self) -> oneshot::Sender<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.faults.lock().unwrap().hold_write = Some(receiver);
+        sender
+    }
+
+    /// Truncate the serialized event to `len` bytes when it is read.
+    pub fn truncate_event(&self, id: EventId, len: usize) {
+        self.faults
+            .lock()
+            .unwrap()
+            .events
+            .insert(id, EventFault::Truncate { len });
+    }
+
+    /// Corrupt the byte at `offset` of the serialized event when it is read.
+    pub fn corrupt_event(&self, id: EventId, offset: usize) {
+        self.faults
+            .lock()
+            .unwrap()
+            .events
+            .insert(id, EventFault::Corrupt { offset });
+    }
+
+    /// Remove all configured faults.
+    pub fn clear_faults(&self) {
+        *self.faults.lock().unwrap() = Faults::default();
+    }
+
+    fn event_faults(&self) -> BTreeMap<EventId, EventFault> {
+        self.faults.lock().unwrap().events.clone()
+    }
+}
+
+fn damage_event(
+    faults: &BTreeMap<EventId, EventFault>,
+    event: LogEvent,
+) -> Result<LogEvent, anyhow::Error> {
+    let fault = match faults.get(&event.id) {
+        Some(fault) => *fault,
+        None => return Ok(event),
+    };
+    let id = event.id;
+
+    let mut data = JsonConverter.serialize(&event)?;
+    match fault {
+        EventFault::Truncate { len } => {
+            data.truncate(len);
+        }
+        EventFault::Corrupt { offset } => {
+            if let Some(byte) = data.get_mut(offset) {
+                *byte = !*byte;
+            }
+        }
+    }
+    JsonConverter
+        .deserialize(&data)
+        .with_context(|| format!("Could not read damaged event {id}"))
+}
+
+type StreamFuture<'a> =
+    BoxFuture<'a, Result<BoxStream<'a, Result<LogEvent, anyhow::Error>>, anyhow::Error>>;
+
+impl<S> LogStore for FaultyLogStore<S>
+where
+    S: LogStore + Send + Sync + 'static,
+{
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn iter_events(&self, from: EventId, until: EventId) -> StreamFuture {
+        let faults = self.event_faults();
+        async move {
+            let stream = self.inner.iter_events(from, until).await?;
+            let damaged = stream.map(move |res| res.and_then(|event| damage_event(&faults, event)));
+            Ok(damaged.boxed())
+        }
+        .boxed()
+    }
+
+    fn read_event(&self, id: EventId) -> BoxFuture<Result<Option<LogEvent>, anyhow::Error>> {
+        let faults = self.event_faults();
+        async move {
+            self.inner
+                .read_event(id)
+                .await?
+                .map(|event| damage_event(&faults, event))
+                .transpose()
+        }
+        .boxed()
+    }
+
+    fn write_event(&mut self, event: LogEvent) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        let (fail, hold) = {
+            let mut faults = self.faults.lock().unwrap();
+            let fail = match faults.fail_write_after {
+                Some(0) => {
+                    faults.fail_write_after = None;
+                    true
+                }
+                Some(successful) => {
+                    faults.fail_write_after = Some(successful - 1);
+                    false
+                }
+                None => false,
+            };
+            (fail, faults.hold_write.take())
+        };
+
+        async move {
+            if let Some(hold) = hold {
+                hold.await.ok();
+            }
+            if fail {
+                Err(anyhow::anyhow!(
+                    "Injected write failure for event {}",
+                    event.id
+                ))
+            } else {
+                self.inner.write_event(event).await
+            }
+        }
+        .boxed()
+    }
+
+    fn clear(&mut self) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        self.inner.clear()
+    }
+
+    fn replace_events(
+        &mut self,
+        events: Vec<LogEvent>,
+    ) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        self.inner.replace_events(events)
+    }
+
+    fn size_log(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>> {
+        self.inner.size_log()
+    }
+
+    fn size_data(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>> {
+        self.inner.size_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::{Id, Value, ValueType},
+        map,
+        query::migrate::Migration,
+        schema::Attribute,
+    };
+    use futures::TryStreamExt;
+
+    use super::{super::store_memory::MemoryLogStore, *};
+    use crate::{backend::log::LogDb, Engine};
+
+    async fn event_count(store: &MemoryLogStore) -> EventId {
+        let events = store
+            .iter_events(0, EventId::MAX)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        events.last().map(|event| event.id).unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_log_write_failure_reverts_changes() {
+        let mem = MemoryLogStore::new();
+        let store = FaultyLogStore::new(mem.clone());
+        let log = LogDb::open(store.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id1 = Id::random();
+        db.create(id1, map! { "factor/title": "a" }).await.unwrap();
+        let events = event_count(&mem).await;
+
+        // Failed batch.
+        let id2 = Id::random();
+        store.fail_write_after(0);
+        assert!(db.create(id2, map! { "factor/title": "b" }).await.is_err());
+        assert!(db.entity(id2).await.is_err());
+        assert_eq!(events, event_count(&mem).await);
+
+        // Failed migration.
+        let mig = Migration::new().attr_create(Attribute::new("test/faulty", ValueType::Int));
+        store.fail_write_after(0);
+        assert!(db.migrate(mig.clone()).await.is_err());
+        assert!(db
+            .schema()
+            .await
+            .unwrap()
+            .attributes
+            .iter()
+            .all(|attr| attr.ident != "test/faulty"));
+
+        // Subsequent writes succeed.
+        db.migrate(mig).await.unwrap();
+        let id3 = Id::random();
+        db.create(id3, map! { "test/faulty": 3 }).await.unwrap();
+
+        // The log matches the live state.
+        let restored = Engine::new(LogDb::open(mem).await.unwrap()).into_client();
+        assert_eq!(
+            db.entity(id1).await.unwrap(),
+            restored.entity(id1).await.unwrap()
+        );
+        assert!(restored.entity(id2).await.is_err());
+        assert_eq!(
+            Value::from(3),
+            restored.entity(id3).await.unwrap()["test/faulty"]
+        );
+    }
+
+    /// Wait until a write was applied to the memory state, but not written
+    /// to the log yet.
+    async fn wait_for_pending_write(log: &LogDb) {
+        while !log
+            .state
+            .uncommitted
+            .load(std::sync::atomic::Ordering::Acquire)
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_reads_do_not_observe_pending_writes() {
+        let store = FaultyLogStore::new(MemoryLogStore::new());
+        let log = LogDb::open(store.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+
+        // A write that fails after readers started waiting for it.
+        let release = store.hold_next_write();
+        store.fail_write_after(0);
+        let write = tokio::spawn({
+            let db = db.clone();
+            async move { db.merge(id, map! { "factor/title": "b" }).await }
+        });
+        wait_for_pending_write(&log).await;
+        let read = tokio::spawn({
+            let db = db.clone();
+            async move { db.entity(id).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!read.is_finished());
+
+        release.send(()).unwrap();
+        assert!(write.await.unwrap().is_err());
+        let data = read.await.unwrap().unwrap();
+        assert_eq!(Value::from("a"), data["factor/title"]);
+
+        // Successful writes become visible once written.
+        let release = store.hold_next_write();
+        let write = tokio::spawn({
+            let db = db.clone();
+            async move { db.merge(id, map! { "factor/title": "c" }).await }
+        });
+        wait_for_pending_write(&log).await;
+        let read = tokio::spawn({
+            let db = db.clone();
+            async move { db.entity(id).await }
+        });
+        drop(release);
+        write.await.unwrap().unwrap();
+        let data = read.await.unwrap().unwrap();
+        assert_eq!(Value::from("c"), data["factor/title"]);
+    }
+
+    #[tokio::test]
+    async fn test_engine_rejects_batches_beyond_limit() {
+        let store = FaultyLogStore::new(MemoryLogStore::new());
+        let log = LogDb::open(store.clone()).await.unwrap();
+        let db = Engine::new(log.clone())
+            .with_max_pending_batches(1, None)
+            .into_client();
+
+        let release = store.hold_next_write();
+        let write = tokio::spawn({
+            let db = db.clone();
+            async move { db.create(Id::random(), map! { "factor/title": "a" }).await }
+        });
+        wait_for_pending_write(&log).await;
+
+        let err = db
+            .create(Id::random(), map! { "factor/title": "b" })
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, factor_core::error::Error::Overloaded(e) if e.limit == 1));
+        assert!(err.is_retryable());
+
+        drop(release);
+        write.await.unwrap().unwrap();
+        db.create(Id::random(), map! { "factor/title": "c" })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_log_restore_truncated_event() {
+        let mem = MemoryLogStore::new();
+        let store = FaultyLogStore::new(mem.clone());
+        let log = LogDb::open(store.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        let last = event_count(&mem).await;
+
+        store.truncate_event(last, 10);
+        assert!(LogDb::open(store.clone()).await.is_err());
+        assert!(log.force_rebuild().await.is_err());
+
+        // The underlying log is intact.
+        store.clear_faults();
+        log.force_rebuild().await.unwrap();
+        assert_eq!(
+            Value::from("a"),
+            db.entity(id).await.unwrap()["factor/title"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_restore_corrupted_event() {
+        let mem = MemoryLogStore::new();
+        let store = FaultyLogStore::new(mem.clone());
+        let log = LogDb::open(store.clone()).await.unwrap();
+        let db = Engine::new(log.clone()).into_client();
+
+        let id = Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        let last = event_count(&mem).await;
+
+        store.corrupt_event(last, 0);
+        assert!(LogDb::open(store.clone()).await.is_err());
+        assert!(LogDb::recover_data(store.clone()).await.is_err());
+
+        // Corruption past the end of the event is a no-op.
+        store.corrupt_event(last, usize::MAX);
+        let restored = Engine::new(LogDb::open(store).await.unwrap()).into_client();
+        assert_eq!(
+            Value::from("a"),
+            restored.entity(id).await.unwrap()["factor/title"]
+        );
+    }
+}
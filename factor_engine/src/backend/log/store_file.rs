@@ -7,6 +7,8 @@ use futures::{
 };
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt};
 
+use crate::backend::VerifyIssue;
+
 use super::{EventId, LogConverter, LogEvent};
 
 /// Mock memory log store.
@@ -36,6 +38,45 @@ impl<C: LogConverter> FileLogStore<C> {
             file: tokio::sync::Mutex::new(file),
         })
     }
+
+    /// Check that all events in the log file can be decoded and that event
+    /// ids are increasing.
+    ///
+    /// The log format has no checksums, so a corrupted event is detected by
+    /// failing to decode it.
+    /// Unlike [`super::LogStore::iter_events`], which stops at the first
+    /// invalid event, this reports all invalid events.
+    pub async fn verify_events(&self) -> Result<Vec<VerifyIssue>, anyhow::Error> {
+        let file = tokio::fs::File::open(&self.path).await?;
+        let mut lines = tokio::io::BufReader::new(file).lines();
+
+        let mut issues = Vec::new();
+        let mut line_number = 0;
+        let mut previous_id = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            match self.converter.deserialize(line.as_bytes()) {
+                Ok(event) => {
+                    if event.id <= previous_id {
+                        issues.push(VerifyIssue::LogEventOutOfOrder {
+                            line: line_number,
+                            id: event.id,
+                            previous_id,
+                        });
+                    }
+                    previous_id = previous_id.max(event.id);
+                }
+                Err(err) => {
+                    issues.push(VerifyIssue::LogEventInvalid {
+                        line: line_number,
+                        error: format!("{:#}", err),
+                    });
+                }
+            }
+        }
+
+        Ok(issues)
+    }
 }
 
 impl<C: LogConverter> super::LogStore for FileLogStore<C> {
@@ -108,8 +149,51 @@ impl<C: LogConverter> super::LogStore for FileLogStore<C> {
         .boxed()
     }
 
+    /// Writes the events to a temporary file, which then atomically replaces
+    /// the log file.
+    fn replace_events(
+        &mut self,
+        events: Vec<LogEvent>,
+    ) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        async move {
+            let mut tmp_path = self.path.clone().into_os_string();
+            tmp_path.push(".compact");
+            let tmp_path = PathBuf::from(tmp_path);
+
+            let mut file = self.file.lock().await;
+
+            let mut tmp = tokio::fs::File::create(&tmp_path).await?;
+            for event in &events {
+                let mut converted = self.converter.serialize(event)?;
+                converted.push(b'\n');
+                tmp.write_all(&converted).await?;
+            }
+            tmp.flush().await?;
+            tmp.sync_all().await?;
+            drop(tmp);
+
+            tokio::fs::rename(&tmp_path, &self.path).await?;
+
+            let mut new_file = tokio::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.path)
+                .await?;
+            new_file.seek(std::io::SeekFrom::End(0)).await?;
+            *file = new_file;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
     fn size_log(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>> {
-        ready(Ok(None)).boxed()
+        let path = self.path.clone();
+        async move {
+            let meta = tokio::fs::metadata(&path).await?;
+            Ok(Some(meta.len()))
+        }
+        .boxed()
     }
 
     fn size_data(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>> {
@@ -119,6 +203,8 @@ impl<C: LogConverter> super::LogStore for FileLogStore<C> {
 
 #[cfg(test)]
 mod tests {
+    use factor_core::map;
+
     use crate::backend::log::convert_json::JsonConverter;
 
     use super::*;
@@ -140,4 +226,80 @@ mod tests {
         });
         crate::tests::test_backend(log, move |f| handle.block_on(f));
     }
+
+    #[tokio::test]
+    async fn test_backend_log_store_file_compact() {
+        let test_path = std::env::temp_dir().join("factordb_log_fs_compact_test.db");
+        if test_path.is_file() {
+            std::fs::remove_file(&test_path).unwrap();
+        }
+
+        let fs = FileLogStore::open(JsonConverter, &test_path).await.unwrap();
+        let log = super::super::LogDb::open(fs).await.unwrap();
+        let db = crate::Engine::new(log.clone()).into_client();
+
+        let id = factor_core::data::Id::random();
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        for title in ["b", "c"] {
+            db.merge(id, map! { "factor/title": title }).await.unwrap();
+        }
+        let size_before = std::fs::metadata(&test_path).unwrap().len();
+
+        log.compact().await.unwrap();
+        let size_after = std::fs::metadata(&test_path).unwrap().len();
+        assert!(size_after < size_before);
+
+        // Writes go to the new file.
+        let id2 = factor_core::data::Id::random();
+        db.create(id2, map! { "factor/title": "d" }).await.unwrap();
+
+        let fs = FileLogStore::open(JsonConverter, &test_path).await.unwrap();
+        let restored =
+            crate::Engine::new(super::super::LogDb::open(fs).await.unwrap()).into_client();
+        assert_eq!(
+            factor_core::data::Value::from("c"),
+            restored.entity(id).await.unwrap()["factor/title"]
+        );
+        assert!(restored.entity(id2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_backend_log_store_file_verify_events() {
+        let test_path = std::env::temp_dir().join("factordb_log_fs_verify_test.db");
+        if test_path.is_file() {
+            std::fs::remove_file(&test_path).unwrap();
+        }
+
+        let fs = FileLogStore::open(JsonConverter, &test_path).await.unwrap();
+        let db = crate::Engine::new(super::super::LogDb::open(fs).await.unwrap()).into_client();
+        for title in ["a", "b"] {
+            db.create(
+                factor_core::data::Id::random(),
+                map! { "factor/title": title },
+            )
+            .await
+            .unwrap();
+        }
+
+        let fs = FileLogStore::open(JsonConverter, &test_path).await.unwrap();
+        assert_eq!(fs.verify_events().await.unwrap(), Vec::new());
+
+        let mut contents = std::fs::read_to_string(&test_path).unwrap();
+        let first_line = contents.lines().next().unwrap().to_string();
+        contents.insert_str(0, "{\"broken\n");
+        contents.push_str(&first_line);
+        contents.push('\n');
+        std::fs::write(&test_path, contents).unwrap();
+
+        let issues = fs.verify_events().await.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(
+            issues[0],
+            VerifyIssue::LogEventInvalid { line: 1, .. }
+        ));
+        assert!(matches!(
+            issues[1],
+            VerifyIssue::LogEventOutOfOrder { line: 4, .. }
+        ));
+    }
 }
@@ -48,7 +48,7 @@ impl super::LogStore for MemoryLogStore {
             .events
             .read()
             .unwrap()
-            .range(from..until)
+            .range(from..=until)
             .map(|(_key, value)| Ok(value.clone()))
             .collect::<Vec<_>>();
         let boxed_stream = futures::stream::iter(stream).boxed();
@@ -92,6 +92,15 @@ impl super::LogStore for MemoryLogStore {
         ready(Ok(())).boxed()
     }
 
+    fn replace_events(
+        &mut self,
+        events: Vec<LogEvent>,
+    ) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        let events = events.into_iter().map(|event| (event.id, event)).collect();
+        *self.events.write().unwrap() = events;
+        ready(Ok(())).boxed()
+    }
+
     fn size_log(&mut self) -> BoxFuture<'static, Result<Option<u64>, anyhow::Error>> {
         ready(Ok(None)).boxed()
     }
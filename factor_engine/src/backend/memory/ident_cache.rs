@@ -0,0 +1,53 @@
+use std::sync::RwLock;
+
+use factor_core::data::Id;
+use fnv::FnvHashMap;
+
+/// Cache of resolved entity idents.
+///
+/// Resolving a name otherwise goes through the ident index, which needs a
+/// string allocation for every lookup.
+/// Only names that resolve to a committed entity are cached, since the cache
+/// is read without locking the indexes. Entries must be invalidated when a
+/// change to the ident index is committed or reverted, see
+/// [`Self::invalidate`].
+#[derive(Debug, Default)]
+pub(super) struct IdentCache {
+    entries: RwLock<FnvHashMap<String, Id>>,
+}
+
+impl IdentCache {
+    /// Maximum number of cached idents.
+    /// The cache is cleared when full, since only a small set of well-known
+    /// entities is expected to be looked up repeatedly.
+    const CAPACITY: usize = 1024;
+
+    pub fn get(&self, name: &str) -> Option<Id> {
+        self.entries.read().unwrap().get(name).copied()
+    }
+
+    pub fn insert(&self, name: &str, id: Id) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= Self::CAPACITY {
+            entries.clear();
+        }
+        entries.insert(name.to_string(), id);
+    }
+
+    /// Drop the entry of a name whose ident index entry changed.
+    pub fn invalidate(&self, name: &str) {
+        let mut entries = self.entries.write().unwrap();
+        if !entries.is_empty() {
+            entries.remove(name);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.get_mut().unwrap().clear();
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+}
@@ -1,7 +1,14 @@
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Bound,
+    sync::RwLock,
+};
 
 use anyhow::Result;
-use factor_core::{data::Id, query::select::Order};
+use factor_core::{
+    data::{value::IndexKey, Id},
+    query::select::Order,
+};
 
 use crate::{registry::LocalIndexId, util::stable_map::DerivedStableMap};
 
@@ -10,9 +17,12 @@ use super::memory_data::MemoryValue;
 /// A unique index.
 ///
 /// Can only map values to a single id.
+///
+/// Values are stored as [`IndexKey`]s, so values of different types are
+/// ordered consistently with other backends.
 #[derive(Debug)]
 pub(super) struct UniqueIndex {
-    data: BTreeMap<MemoryValue, Id>,
+    data: BTreeMap<IndexKey, Id>,
 }
 
 pub struct InsertUniqueError;
@@ -24,8 +34,18 @@ impl UniqueIndex {
         }
     }
 
+    /// Build an index from unsorted entries in bulk.
+    ///
+    /// For duplicate keys the last entry wins, like with
+    /// [`Self::insert_unchecked`].
+    pub fn from_entries(entries: Vec<(IndexKey, Id)>) -> Self {
+        Self {
+            data: entries.into_iter().collect(),
+        }
+    }
+
     pub fn get(&self, value: &MemoryValue) -> Option<Id> {
-        self.data.get(value).cloned()
+        self.data.get(&value.index_key()).cloned()
     }
 
     pub fn range<'a>(
@@ -34,6 +54,8 @@ impl UniqueIndex {
         until: Option<MemoryValue>,
         direction: Order,
     ) -> Box<dyn Iterator<Item = Id> + 'a> {
+        let from = from.as_ref().map(MemoryValue::index_key);
+        let until = until.as_ref().map(MemoryValue::index_key);
         match (from, until, direction) {
             (None, None, Order::Asc) => {
                 let out = self.data.values().cloned();
@@ -76,52 +98,27 @@ impl UniqueIndex {
         direction: Order,
     ) -> Box<dyn Iterator<Item = Id> + 'a> {
         // TODO: earlier error if values not string?
-        match (&prefix, direction) {
-            (v @ MemoryValue::String(s), Order::Asc) => {
-                let prefix: String = s.as_ref().to_string();
-                let out = self
-                    .data
-                    .range(v.clone()..)
-                    .take_while(move |(key, _value)| match key {
-                        MemoryValue::String(value) => value.as_ref().starts_with(&prefix),
-                        // Should never happen!
-                        _ => true,
-                    })
-                    .map(|(_key, id)| *id);
-                Box::new(out)
-            }
-            (v @ MemoryValue::String(s), Order::Desc) => {
-                let prefix: String = s.as_ref().to_string();
-                let out = self
-                    .data
-                    .range(v..)
-                    .rev()
-                    // TODO: can this be faster? (start iterating from reverse direction?)
-                    .skip_while(move |(key, _value)| match key {
-                        MemoryValue::String(value) => !value.as_ref().starts_with(&prefix),
-                        // Should never happen!
-                        _ => true,
-                    })
-                    .map(|(_key, id)| *id);
-                Box::new(out)
-            }
-            (_, Order::Asc) => {
-                let out = self.data.values().cloned();
-                Box::new(out)
-            }
-            (_, Order::Desc) => {
-                let out = self.data.values().rev().cloned();
-                Box::new(out)
+        let range = match &prefix {
+            MemoryValue::String(s) => prefix_range(IndexKey::string_prefix(s.as_ref())),
+            _ => {
+                return match direction {
+                    Order::Asc => Box::new(self.data.values().cloned()),
+                    Order::Desc => Box::new(self.data.values().rev().cloned()),
+                };
             }
+        };
+        match direction {
+            Order::Asc => Box::new(self.data.range(range).map(|(_key, id)| *id)),
+            Order::Desc => Box::new(self.data.range(range).rev().map(|(_key, id)| *id)),
         }
     }
 
-    pub fn insert_unchecked(&mut self, value: MemoryValue, id: Id) {
-        self.data.insert(value, id);
+    pub fn insert_unchecked(&mut self, value: &MemoryValue, id: Id) {
+        self.data.insert(value.index_key(), id);
     }
 
-    pub fn insert_unique(&mut self, value: MemoryValue, id: Id) -> Result<(), InsertUniqueError> {
-        match self.data.entry(value) {
+    pub fn insert_unique(&mut self, value: &MemoryValue, id: Id) -> Result<(), InsertUniqueError> {
+        match self.data.entry(value.index_key()) {
             std::collections::btree_map::Entry::Vacant(entry) => {
                 entry.insert(id);
                 Ok(())
@@ -141,7 +138,7 @@ impl UniqueIndex {
     // }
 
     pub fn remove(&mut self, value: &MemoryValue) -> Option<Id> {
-        self.data.remove(value)
+        self.data.remove(&value.index_key())
     }
 
     pub fn clear(&mut self) {
@@ -155,9 +152,12 @@ impl Default for UniqueIndex {
     }
 }
 
+/// An index that maps values to multiple ids.
+///
+/// See [`UniqueIndex`].
 #[derive(Debug)]
 pub(super) struct MultiIndex {
-    data: BTreeMap<MemoryValue, HashSet<Id>>,
+    data: BTreeMap<IndexKey, HashSet<Id>>,
 }
 
 impl MultiIndex {
@@ -167,12 +167,31 @@ impl MultiIndex {
         }
     }
 
+    /// Build an index from unsorted entries in bulk.
+    pub fn from_entries(mut entries: Vec<(IndexKey, Id)>) -> Self {
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut grouped: Vec<(IndexKey, HashSet<Id>)> = Vec::new();
+        for (key, id) in entries {
+            match grouped.last_mut() {
+                Some((last, ids)) if *last == key => {
+                    ids.insert(id);
+                }
+                _ => grouped.push((key, HashSet::from([id]))),
+            }
+        }
+
+        Self {
+            data: grouped.into_iter().collect(),
+        }
+    }
+
     pub fn get(&self, value: &MemoryValue) -> Option<&HashSet<Id>> {
-        self.data.get(value)
+        self.data.get(&value.index_key())
     }
 
-    pub fn add(&mut self, value: MemoryValue, id: Id) {
-        self.data.entry(value).or_default().insert(id);
+    pub fn add(&mut self, value: &MemoryValue, id: Id) {
+        self.data.entry(value.index_key()).or_default().insert(id);
     }
 
     // pub fn replace(&mut self, old_value: MemoryValue, new_value: MemoryValue, id: Id) {
@@ -181,14 +200,15 @@ impl MultiIndex {
     // }
 
     pub fn remove(&mut self, value: &MemoryValue, id: Id) -> Option<Id> {
-        let (removed, purge) = if let Some(set) = self.data.get_mut(value) {
+        let key = value.index_key();
+        let (removed, purge) = if let Some(set) = self.data.get_mut(&key) {
             set.remove(&id);
             (Some(id), set.is_empty())
         } else {
             (None, false)
         };
         if purge {
-            self.data.remove(value);
+            self.data.remove(&key);
         }
         removed
     }
@@ -199,6 +219,8 @@ impl MultiIndex {
         until: Option<MemoryValue>,
         direction: Order,
     ) -> Box<dyn Iterator<Item = Id> + 'a> {
+        let from = from.as_ref().map(MemoryValue::index_key);
+        let until = until.as_ref().map(MemoryValue::index_key);
         match (from, until, direction) {
             (None, None, Order::Asc) => {
                 let out = self.data.values().flatten().cloned();
@@ -253,43 +275,23 @@ impl MultiIndex {
         direction: Order,
     ) -> Box<dyn Iterator<Item = Id> + 'a> {
         // TODO: earlier error if values not string?
-        match (&prefix, direction) {
-            (v @ MemoryValue::String(s), Order::Asc) => {
-                let prefix: String = s.as_ref().to_string();
-                let out = self
-                    .data
-                    .range(v.clone()..)
-                    .take_while(move |(key, _value)| match key {
-                        MemoryValue::String(value) => value.as_ref().starts_with(&prefix),
-                        // Should never happen!
-                        _ => true,
-                    })
-                    .flat_map(|(_key, id)| id.clone());
-                Box::new(out)
+        let range = match &prefix {
+            MemoryValue::String(s) => prefix_range(IndexKey::string_prefix(s.as_ref())),
+            _ => {
+                return match direction {
+                    Order::Asc => Box::new(self.data.values().flatten().cloned()),
+                    Order::Desc => Box::new(self.data.values().rev().flatten().cloned()),
+                };
             }
-            (v @ MemoryValue::String(s), Order::Desc) => {
-                let prefix: String = s.as_ref().to_string();
-                let out = self
-                    .data
-                    .range(v..)
+        };
+        match direction {
+            Order::Asc => Box::new(self.data.range(range).flat_map(|(_key, ids)| ids.clone())),
+            Order::Desc => Box::new(
+                self.data
+                    .range(range)
                     .rev()
-                    // TODO: can this be faster? (start iterating from reverse direction?)
-                    .skip_while(move |(key, _value)| match key {
-                        MemoryValue::String(value) => !value.as_ref().starts_with(&prefix),
-                        // Should never happen!
-                        _ => true,
-                    })
-                    .flat_map(|(_key, id)| id.clone());
-                Box::new(out)
-            }
-            (_, Order::Asc) => {
-                let out = self.data.values().flatten().cloned();
-                Box::new(out)
-            }
-            (_, Order::Desc) => {
-                let out = self.data.values().rev().flatten().cloned();
-                Box::new(out)
-            }
+                    .flat_map(|(_key, ids)| ids.clone()),
+            ),
         }
     }
 
@@ -308,25 +310,81 @@ impl Default for MultiIndex {
 pub(super) enum Index {
     Unique(UniqueIndex),
     Multi(MultiIndex),
+    /// Index of [`factor_core::data::GeoPoint`] values.
+    ///
+    /// Keys are geohashes (see [`crate::registry::RegisteredIndex::index_key`]),
+    /// so all points in a geohash cell can be found with a prefix scan.
+    Geo(MultiIndex),
 }
 
 impl Index {
     pub fn clear(&mut self) {
         match self {
             Index::Unique(idx) => idx.clear(),
-            Index::Multi(idx) => idx.clear(),
+            Index::Multi(idx) | Index::Geo(idx) => idx.clear(),
+        }
+    }
+
+    /// Replace the contents of the index with the given entries.
+    pub fn rebuild(&mut self, entries: Vec<(IndexKey, Id)>) {
+        match self {
+            Index::Unique(idx) => *idx = UniqueIndex::from_entries(entries),
+            Index::Multi(idx) | Index::Geo(idx) => *idx = MultiIndex::from_entries(entries),
         }
     }
 
     pub fn get_unique(&self, value: &MemoryValue) -> Option<Id> {
         match self {
             Index::Unique(idx) => idx.get(value),
-            Index::Multi(_) => None,
+            Index::Multi(_) | Index::Geo(_) => None,
+        }
+    }
+
+    /// Iterate over all indexed (key, id) pairs.
+    pub fn iter_entries(&self) -> Box<dyn Iterator<Item = (&IndexKey, Id)> + '_> {
+        match self {
+            Index::Unique(idx) => Box::new(idx.data.iter().map(|(value, id)| (value, *id))),
+            Index::Multi(idx) | Index::Geo(idx) => Box::new(
+                idx.data
+                    .iter()
+                    .flat_map(|(value, ids)| ids.iter().map(move |id| (value, *id))),
+            ),
         }
     }
+
+    /// Number of indexed (value, id) pairs.
+    pub fn len(&self) -> usize {
+        match self {
+            Index::Unique(idx) => idx.data.len(),
+            Index::Multi(idx) | Index::Geo(idx) => idx.data.values().map(|ids| ids.len()).sum(),
+        }
+    }
+
+    /// Rough estimate of the memory used by this index, in bytes.
+    pub fn estimated_size(&self) -> usize {
+        let id_size = std::mem::size_of::<Id>();
+        match self {
+            Index::Unique(idx) => idx.data.keys().map(|key| key.len() + id_size).sum(),
+            Index::Multi(idx) | Index::Geo(idx) => idx
+                .data
+                .iter()
+                .map(|(key, ids)| key.len() + ids.len() * id_size)
+                .sum(),
+        }
+    }
+}
+
+/// Range of all keys starting with `prefix`.
+fn prefix_range(prefix: IndexKey) -> (Bound<IndexKey>, Bound<IndexKey>) {
+    let end = match prefix.prefix_end() {
+        Some(end) => Bound::Excluded(end),
+        None => Bound::Unbounded,
+    };
+    (Bound::Included(prefix), end)
 }
 
-pub(super) type MemoryIndexMap = DerivedStableMap<LocalIndexId, Index>;
+/// Indexes have their own locks, see [`super::store::MemoryStore`].
+pub(super) type MemoryIndexMap = DerivedStableMap<LocalIndexId, RwLock<Index>>;
 
 pub(super) fn new_memory_index_map() -> MemoryIndexMap {
     MemoryIndexMap::new()
@@ -1,28 +1,69 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
 use factor_core::data::Value;
 
 use super::memory_data::{MemoryValue, SharedStr};
 
+/// Strings are split into shards by hash, since values are interned by
+/// concurrent writes.
 pub(super) struct Interner {
-    strings: std::collections::HashMap<Box<str>, SharedStr>,
+    shards: Box<[Mutex<Strings>]>,
 }
 
+type Strings = HashMap<Box<str>, SharedStr>;
+
 impl Interner {
+    /// Number of shards.
+    const SHARDS: usize = 16;
+
     pub fn new() -> Self {
         Self {
-            strings: std::collections::HashMap::new(),
+            shards: (0..Self::SHARDS).map(|_| Mutex::default()).collect(),
         }
     }
 
     pub fn clear(&mut self) {
-        self.strings.clear();
+        for shard in self.shards.iter_mut() {
+            shard.get_mut().unwrap().clear();
+        }
+    }
+
+    /// Rough estimate of the memory used by the interned strings, in bytes.
+    ///
+    /// Each string is stored twice: as the map key and in the shared value.
+    pub fn estimated_size(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .map(|s| {
+                        s.len() * 2
+                            + std::mem::size_of::<Box<str>>()
+                            + std::mem::size_of::<SharedStr>()
+                    })
+                    .sum::<usize>()
+            })
+            .sum()
     }
 
-    pub fn intern_str(&mut self, value: String) -> SharedStr {
-        match self.strings.get(value.as_str()) {
+    pub fn intern_str(&self, value: String) -> SharedStr {
+        let mut hasher = fnv::FnvHasher::default();
+        value.hash(&mut hasher);
+        // The low byte of the hash is enough to pick a shard.
+        let shard = usize::from(hasher.finish().to_le_bytes()[0]) % Self::SHARDS;
+        let mut strings = self.shards[shard].lock().unwrap();
+        match strings.get(value.as_str()) {
             Some(v) => v.clone(),
             None => {
                 let shared = SharedStr::from_string(value.clone());
-                self.strings.insert(Box::from(value), shared.clone());
+                strings.insert(Box::from(value), shared.clone());
                 shared
             }
         }
@@ -41,7 +82,7 @@ impl Interner {
     //     }
     // }
 
-    pub fn intern_value(&mut self, value: Value) -> MemoryValue {
+    pub fn intern_value(&self, value: Value) -> MemoryValue {
         use MemoryValue as M;
         match value {
             Value::Unit => M::Unit,
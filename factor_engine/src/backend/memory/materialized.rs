@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+
+use factor_core::data::Id;
+use fnv::FnvHashMap;
+
+use super::{
+    memory_data::{MemoryExpr, MemoryTuple},
+    store::MemoryStore,
+};
+
+/// Materialized entities of views.
+///
+/// Each view keeps the ids of all entities that match its filter.
+/// Only entities that changed are checked again, so keeping views up to
+/// date is cheap compared to running the view query.
+#[derive(Default, Debug)]
+pub(super) struct MaterializedViews {
+    views: FnvHashMap<String, MaterializedView>,
+}
+
+#[derive(Debug)]
+struct MaterializedView {
+    /// `None` for views without a filter, which contain all entities.
+    filter: Option<MemoryExpr>,
+    entities: BTreeSet<Id>,
+}
+
+impl MaterializedView {
+    fn matches(&self, tuple: &MemoryTuple) -> bool {
+        self.filter
+            .as_ref()
+            .map_or(true, |filter| MemoryStore::entity_filter(tuple, filter))
+    }
+}
+
+impl MaterializedViews {
+    /// Add or replace a view and materialize it from the given entities.
+    pub fn insert<'a>(
+        &mut self,
+        name: String,
+        filter: Option<MemoryExpr>,
+        entities: impl IntoIterator<Item = (&'a Id, &'a MemoryTuple)>,
+    ) {
+        let mut view = MaterializedView {
+            filter,
+            entities: BTreeSet::new(),
+        };
+        view.entities = entities
+            .into_iter()
+            .filter(|(_, tuple)| view.matches(tuple))
+            .map(|(id, _)| *id)
+            .collect();
+        self.views.insert(name, view);
+    }
+
+    pub fn clear(&mut self) {
+        self.views.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// Update all views for a changed entity.
+    ///
+    /// `None` means the entity does not exist anymore.
+    pub fn update(&mut self, id: Id, tuple: Option<&MemoryTuple>) {
+        for view in self.views.values_mut() {
+            if tuple.map_or(false, |tuple| view.matches(tuple)) {
+                view.entities.insert(id);
+            } else {
+                view.entities.remove(&id);
+            }
+        }
+    }
+
+    /// Ids of the entities in a view, ordered by id.
+    pub fn entities(&self, name: &str) -> Option<Vec<Id>> {
+        self.views
+            .get(name)
+            .map(|view| view.entities.iter().copied().collect())
+    }
+}
@@ -4,7 +4,11 @@ use fnv::FnvHashMap;
 use ordered_float::OrderedFloat;
 
 use factor_core::{
-    data::{Id, Value},
+    data::{
+        patch::{PatchPath, PatchPathElem},
+        value::{IndexKey, IndexKeyEncoder},
+        GeoPoint, Id, Value,
+    },
     query::expr,
 };
 
@@ -192,6 +196,58 @@ impl Ord for MemoryValue {
 }
 
 impl MemoryValue {
+    /// Rough estimate of the memory used by this value, in bytes.
+    ///
+    /// Strings are interned and shared, so their content is not included.
+    pub fn estimated_size(&self) -> usize {
+        let heap = match self {
+            Self::Bytes(v) => v.len(),
+            Self::List(items) => items.iter().map(|v| v.estimated_size()).sum(),
+            Self::Map(map) => map
+                .iter()
+                .map(|(key, value)| key.estimated_size() + value.estimated_size())
+                .sum(),
+            _ => 0,
+        };
+        std::mem::size_of::<Self>() + heap
+    }
+
+    /// Encode the value as an [`IndexKey`].
+    ///
+    /// Produces the same key as [`IndexKey::from_value`] for the equivalent
+    /// [`Value`].
+    pub fn index_key(&self) -> IndexKey {
+        fn encode(value: &MemoryValue, encoder: &mut IndexKeyEncoder) {
+            match value {
+                MemoryValue::Unit => encoder.unit(),
+                MemoryValue::Bool(v) => encoder.bool(*v),
+                MemoryValue::UInt(v) => encoder.int(i128::from(*v)),
+                MemoryValue::Int(v) => encoder.int(i128::from(*v)),
+                MemoryValue::Float(v) => encoder.float(v.into_inner()),
+                MemoryValue::String(v) => encoder.string(v.as_ref()),
+                MemoryValue::Bytes(v) => encoder.bytes(v),
+                MemoryValue::List(items) => {
+                    encoder.begin_list();
+                    items.iter().for_each(|item| encode(item, encoder));
+                    encoder.end();
+                }
+                MemoryValue::Map(map) => {
+                    encoder.begin_map();
+                    for (key, value) in map {
+                        encode(key, encoder);
+                        encode(value, encoder);
+                    }
+                    encoder.end();
+                }
+                MemoryValue::Id(v) => encoder.id(*v),
+            }
+        }
+
+        let mut encoder = IndexKeyEncoder::new();
+        encode(self, &mut encoder);
+        encoder.finish()
+    }
+
     pub fn is_true(&self) -> bool {
         match self {
             Self::Bool(b) => *b,
@@ -209,6 +265,78 @@ impl MemoryValue {
         }
     }
 
+    /// Get a nested value in a map or list.
+    pub fn get_path(&self, path: &PatchPath) -> Option<&Self> {
+        path.0
+            .iter()
+            .try_fold(self, |value, elem| match (value, elem) {
+                (Self::Map(map), PatchPathElem::Key(key)) => map
+                    .iter()
+                    .find(|(k, _)| matches!(k, Self::String(s) if s.as_ref() == key.as_str()))
+                    .map(|(_, v)| v),
+                (Self::List(items), PatchPathElem::ListIndex(index)) => items.get(*index),
+                _ => None,
+            })
+    }
+
+    fn as_number(&self) -> Option<expr::Number> {
+        match self {
+            Self::UInt(v) => Some(expr::Number::Int(i128::from(*v))),
+            Self::Int(v) => Some(expr::Number::Int(i128::from(*v))),
+            Self::Float(v) => Some(expr::Number::Float(v.into_inner())),
+            _ => None,
+        }
+    }
+
+    /// Evaluate a computed binary operator.
+    ///
+    /// See [`expr::BinaryOp::eval_computed`].
+    pub fn eval_computed(op: &expr::BinaryOp, left: &Self, right: &Self) -> Self {
+        match op {
+            expr::BinaryOp::Concat => match (left, right) {
+                (Self::String(l), Self::String(r)) => {
+                    Self::String(SharedStr::from_string(format!("{}{}", l, r)))
+                }
+                _ => Self::Unit,
+            },
+            op => left
+                .as_number()
+                .zip(right.as_number())
+                .and_then(|(l, r)| op.eval_numbers(l, r))
+                .and_then(expr::Number::into_value)
+                .map(Self::from_value_standalone)
+                .unwrap_or(Self::Unit),
+        }
+    }
+
+    /// Evaluate a unary operator.
+    ///
+    /// See [`expr::UnaryOp::eval`].
+    pub fn eval_unary(op: &expr::UnaryOp, value: &Self) -> Self {
+        let len = |len: usize| Self::UInt(u64::try_from(len).unwrap_or(u64::MAX));
+        match (op, value) {
+            (expr::UnaryOp::Not, value) => Self::Bool(!value.as_bool_discard_other()),
+            (expr::UnaryOp::Lower, Self::String(s)) => {
+                Self::String(SharedStr::from_string(s.as_ref().to_lowercase()))
+            }
+            (expr::UnaryOp::Upper, Self::String(s)) => {
+                Self::String(SharedStr::from_string(s.as_ref().to_uppercase()))
+            }
+            (expr::UnaryOp::Length, Self::String(s)) => len(s.as_ref().chars().count()),
+            (expr::UnaryOp::Length, Self::List(items)) => len(items.len()),
+            (expr::UnaryOp::Length, Self::Map(map)) => len(map.len()),
+            (expr::UnaryOp::Length, Self::Bytes(bytes)) => len(bytes.len()),
+            (
+                op @ (expr::UnaryOp::Year
+                | expr::UnaryOp::Month
+                | expr::UnaryOp::Day
+                | expr::UnaryOp::DateTrunc(_)),
+                value @ (Self::UInt(_) | Self::Int(_)),
+            ) => Self::from_value_standalone(op.eval(&value.to_value())),
+            _ => Self::Unit,
+        }
+    }
+
     pub fn to_value(&self) -> Value {
         use MemoryValue as V;
         match self {
@@ -284,6 +412,16 @@ impl MemoryTuple {
     pub fn get_id(&self) -> Option<Id> {
         self.0.get(&ATTR_ID_LOCAL).and_then(|v| v.as_id())
     }
+
+    /// Rough estimate of the memory used by this tuple, in bytes.
+    pub fn estimated_size(&self) -> usize {
+        let entries: usize = self
+            .0
+            .values()
+            .map(|v| std::mem::size_of::<LocalAttributeId>() + v.estimated_size())
+            .sum();
+        std::mem::size_of::<Self>() + entries
+    }
 }
 
 impl std::ops::Deref for MemoryTuple {
@@ -337,6 +475,15 @@ pub(super) enum MemoryExpr {
         value: Box<Self>,
         items: HashSet<MemoryValue>,
     },
+    WithinRadius {
+        value: Box<Self>,
+        center: GeoPoint,
+        meters: f64,
+    },
+    JsonPath {
+        value: Box<Self>,
+        path: PatchPath,
+    },
 }
 
 #[cfg(test)]
@@ -376,4 +523,36 @@ mod tests {
 
         // TODO: more tests!
     }
+
+    #[test]
+    fn test_memoryvalue_index_key_matches_value() {
+        let values = vec![
+            Value::Unit,
+            Value::Bool(true),
+            Value::UInt(5),
+            Value::Int(-3),
+            Value::from(2.5),
+            Value::from("hello"),
+            Value::Bytes(vec![0, 1]),
+            Value::Id(Id::random()),
+            Value::List(vec![Value::Int(1), Value::from("a")]),
+            Value::Map(
+                vec![(Value::from("a"), Value::Int(1))]
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>()
+                    .into(),
+            ),
+        ];
+        for value in values {
+            assert_eq!(
+                MemoryValue::from_value_standalone(value.clone()).index_key(),
+                IndexKey::from_value(&value),
+            );
+        }
+
+        assert_eq!(
+            MemoryValue::Int(5).index_key(),
+            MemoryValue::Float(5.0.into()).index_key()
+        );
+    }
 }
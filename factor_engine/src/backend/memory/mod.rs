@@ -1,16 +1,33 @@
+mod ident_cache;
 mod index;
 mod interner;
+mod materialized;
 mod memory_data;
+mod query_stats;
+mod result_cache;
+mod shards;
 pub mod store;
+mod type_index;
+mod view;
+pub use self::view::EntityView;
+
+use std::collections::HashMap;
 
 use factor_core::{
     data::{self, DataMap},
+    db::{ClassStats, HealthReport},
     query::{self, select::Item},
 };
 use futures::{future::ready, FutureExt};
 
 use super::BackendFuture;
 
+/// Purely in-memory backend.
+///
+/// Batches only lock the entity shards and indexes they touch, so writes to
+/// unrelated entities run concurrently. See [`store::MemoryStore`].
+/// Migrations and purges rewrite all data and hold the store lock
+/// exclusively.
 #[derive(Clone)]
 pub struct MemoryDb {
     registry: crate::registry::SharedRegistry,
@@ -26,6 +43,30 @@ impl MemoryDb {
             state: std::sync::Arc::new(std::sync::RwLock::new(store::MemoryStore::new(registry))),
         }
     }
+
+    /// Execute a select with attribute names shared between all rows.
+    ///
+    /// See [`store::MemoryStore::select_map_shared`].
+    pub fn select_map_shared(
+        &self,
+        query: query::select::Select,
+    ) -> Result<Vec<data::SharedDataMap>, anyhow::Error> {
+        self.state.read().unwrap().select_map_shared(query)
+    }
+
+    /// Execute a select without copying the result data.
+    ///
+    /// See [`store::MemoryStore::select_visit`].
+    pub fn select_visit<F>(
+        &self,
+        query: query::select::Select,
+        visit: F,
+    ) -> Result<usize, anyhow::Error>
+    where
+        F: FnMut(EntityView<'_>),
+    {
+        self.state.read().unwrap().select_visit(query, visit)
+    }
 }
 
 impl Default for MemoryDb {
@@ -55,6 +96,14 @@ impl super::Backend for MemoryDb {
         ready(res).boxed()
     }
 
+    fn entities(
+        &self,
+        ids: Vec<data::IdOrIdent>,
+    ) -> BackendFuture<HashMap<data::IdOrIdent, Option<DataMap>>> {
+        let res = self.state.read().unwrap().entities(ids);
+        ready(res).boxed()
+    }
+
     fn select(&self, query: query::select::Select) -> BackendFuture<query::select::Page<Item>> {
         let res = self.state.read().unwrap().select(query);
         ready(res).boxed()
@@ -66,10 +115,36 @@ impl super::Backend for MemoryDb {
     }
 
     fn apply_batch(&self, batch: query::mutate::Batch) -> BackendFuture<()> {
-        let res = self.state.write().unwrap().apply_batch(batch);
+        let res = self.state.read().unwrap().apply_batch(batch);
         ready(res).boxed()
     }
 
+    fn apply_batch_audited(
+        &self,
+        batch: query::mutate::Batch,
+        audit: super::Audit,
+    ) -> BackendFuture<()> {
+        let res = self
+            .state
+            .read()
+            .unwrap()
+            .apply_batch_audited(batch, &audit);
+        ready(res).boxed()
+    }
+
+    fn apply_batch_with_savepoints(
+        &self,
+        batch: query::mutate::Batch,
+        audit: Option<super::Audit>,
+    ) -> BackendFuture<Vec<Result<(), anyhow::Error>>> {
+        let results = self
+            .state
+            .read()
+            .unwrap()
+            .apply_batch_with_savepoints(batch, audit.as_ref());
+        ready(Ok(results)).boxed()
+    }
+
     fn migrate(&self, migration: query::migrate::Migration) -> super::BackendFuture<()> {
         let res = self.state.write().unwrap().migrate(migration).map(|_| ());
         ready(res).boxed()
@@ -81,12 +156,57 @@ impl super::Backend for MemoryDb {
     }
 
     fn memory_usage(&self) -> BackendFuture<Option<u64>> {
-        ready(Ok(None)).boxed()
+        let usage = self.state.read().unwrap().stats().memory_usage;
+        ready(Ok(usage)).boxed()
     }
 
     fn storage_usage(&self) -> BackendFuture<Option<u64>> {
         ready(Ok(None)).boxed()
     }
+
+    fn stats(&self) -> BackendFuture<super::BackendStats> {
+        let stats = self.state.read().unwrap().stats();
+        ready(Ok(stats)).boxed()
+    }
+
+    fn verify(&self) -> BackendFuture<Vec<super::VerifyIssue>> {
+        let issues = self.state.read().unwrap().verify();
+        ready(Ok(issues)).boxed()
+    }
+
+    /// Batches are applied synchronously, so nothing is ever pending.
+    fn health(&self) -> BackendFuture<HealthReport> {
+        ready(Ok(HealthReport {
+            storage_reachable: true,
+            last_sync_age_ms: None,
+            pending_batches: 0,
+            registry_loaded: true,
+        }))
+        .boxed()
+    }
+
+    fn class_stats(&self) -> BackendFuture<Vec<ClassStats>> {
+        let stats = self.state.read().unwrap().class_stats();
+        ready(Ok(stats)).boxed()
+    }
+
+    fn select_view(
+        &self,
+        name: String,
+        filter: Option<query::expr::Expr>,
+    ) -> BackendFuture<query::select::Page<Item>> {
+        let res = self.state.read().unwrap().select_view(&name, filter);
+        ready(res).boxed()
+    }
+
+    fn shortest_path(
+        &self,
+        query: query::graph::GraphQuery,
+        filter: Option<query::expr::Expr>,
+    ) -> BackendFuture<Option<Vec<data::Id>>> {
+        let res = self.state.read().unwrap().shortest_path(&query, filter);
+        ready(res).boxed()
+    }
 }
 
 #[cfg(test)]
@@ -96,6 +216,220 @@ mod tests {
     #[test]
     fn test_memory_backend() {
         let mem = MemoryDb::new();
-        crate::tests::test_backend(mem, |f| futures::executor::block_on(f));
+        crate::tests::test_backend(mem.clone(), |f| futures::executor::block_on(f));
+        assert_eq!(mem.state.read().unwrap().check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_backend_stats() {
+        use factor_core::{data::Id, map, query::mutate::Batch, schema::builtin::NS_FACTOR};
+
+        use super::super::Backend;
+
+        let mem = MemoryDb::new();
+        let before = futures::executor::block_on(mem.stats()).unwrap();
+
+        let batch = Batch::new()
+            .and_create(query::mutate::Create {
+                id: Id::random(),
+                data: map! { "factor/title": "a" },
+            })
+            .and_create(query::mutate::Create {
+                id: Id::random(),
+                data: map! { "factor/title": "b" },
+            });
+        futures::executor::block_on(mem.apply_batch(batch)).unwrap();
+
+        let stats = futures::executor::block_on(mem.stats()).unwrap();
+        assert_eq!(before.entity_count + 2, stats.entity_count);
+        assert!(stats.memory_usage.unwrap() > before.memory_usage.unwrap());
+        assert!(stats
+            .indexes
+            .iter()
+            .any(|index| index.ident.starts_with(NS_FACTOR)));
+    }
+
+    #[test]
+    fn test_memory_backend_select_visit() {
+        use factor_core::{
+            data::{Id, Value},
+            map,
+            query::{expr::Expr, mutate::Batch, select::Select},
+            schema::{builtin::AttrTitle, AttributeMeta},
+        };
+
+        use super::super::Backend;
+
+        let mem = MemoryDb::new();
+        let id1 = Id::random();
+        let id2 = Id::random();
+        let batch = Batch::new()
+            .and_create(query::mutate::Create {
+                id: id1,
+                data: map! { "factor/title": "a", "factor/description": "x" },
+            })
+            .and_create(query::mutate::Create {
+                id: id2,
+                data: map! { "factor/title": "b" },
+            });
+        futures::executor::block_on(mem.apply_batch(batch)).unwrap();
+
+        let select = Select::new().with_filter(Expr::eq(AttrTitle::expr(), "a"));
+        let expected = futures::executor::block_on(mem.select_map(select.clone())).unwrap();
+
+        let mut visited = Vec::new();
+        let rows = mem
+            .select_visit(select, |entity| {
+                assert_eq!(entity.id(), Some(id1));
+                assert_eq!(entity.get_str("factor/title"), Some("a"));
+                assert_eq!(entity.get("factor/description"), Some(Value::from("x")));
+                assert!(!entity.contains("factor/ident"));
+                assert!(entity.attributes().any(|attr| attr == "factor/title"));
+                visited.push(entity.to_data_map());
+            })
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn test_memory_backend_select_map_shared() {
+        use factor_core::{
+            data::Id,
+            map,
+            query::{mutate::Batch, select::Select},
+        };
+
+        use super::super::Backend;
+
+        let mem = MemoryDb::new();
+        let batch = (0..3).fold(Batch::new(), |batch, _| {
+            batch.and_create(query::mutate::Create {
+                id: Id::random(),
+                data: map! { "factor/title": "a" },
+            })
+        });
+        futures::executor::block_on(mem.apply_batch(batch)).unwrap();
+
+        let expected = futures::executor::block_on(mem.select_map(Select::new())).unwrap();
+        let shared = mem.select_map_shared(Select::new()).unwrap();
+
+        // Keys point to the same shared name.
+        let titles = shared
+            .iter()
+            .filter_map(|data| data.keys().find(|key| &***key == "factor/title"))
+            .collect::<Vec<_>>();
+        assert_eq!(titles.len(), 3);
+        assert!(titles
+            .iter()
+            .all(|key| std::sync::Arc::ptr_eq(key, titles[0])));
+
+        let mut converted = shared
+            .into_iter()
+            .map(|data| data.into_data_map())
+            .collect::<Vec<_>>();
+        let mut expected = expected;
+        converted.sort_by_key(|data| data.get("factor/id").cloned());
+        expected.sort_by_key(|data| data.get("factor/id").cloned());
+        assert_eq!(converted, expected);
+    }
+
+    #[test]
+    fn test_memory_backend_query_stats() {
+        use factor_core::{
+            query::{expr::Expr, select::Select},
+            schema::{
+                builtin::{AttrIdent, AttrTitle},
+                AttributeMeta,
+            },
+        };
+
+        use super::super::Backend;
+        use crate::registry::INDEX_IDENT_LOCAL;
+
+        let mem = MemoryDb::new();
+        let ident_index = mem
+            .registry()
+            .load()
+            .index_by_local_id(INDEX_IDENT_LOCAL)
+            .unwrap()
+            .schema
+            .ident
+            .clone();
+        let index_usage = |stats: &super::super::BackendStats| {
+            stats
+                .indexes
+                .iter()
+                .find(|index| index.ident == ident_index)
+                .unwrap()
+                .usage
+        };
+
+        let before = futures::executor::block_on(mem.stats()).unwrap();
+
+        let by_ident = Select::new().with_filter(Expr::eq(AttrIdent::expr(), "test/missing"));
+        futures::executor::block_on(mem.select(by_ident)).unwrap();
+        let filtered = Select::new().with_filter(Expr::eq(AttrTitle::expr(), "a"));
+        futures::executor::block_on(mem.select_map(filtered)).unwrap();
+
+        let stats = futures::executor::block_on(mem.stats()).unwrap();
+        assert_eq!(stats.queries.queries, before.queries.queries + 2);
+        assert_eq!(stats.queries.full_scans, before.queries.full_scans + 1);
+        assert_eq!(stats.queries.index_reads, before.queries.index_reads + 1);
+        assert_eq!(index_usage(&stats), index_usage(&before) + 1);
+    }
+
+    #[test]
+    fn test_memory_backend_concurrent_batches() {
+        use factor_core::{data::Id, map, query::mutate::Batch};
+
+        use super::super::Backend;
+
+        const THREADS: usize = 8;
+        const ENTITIES: usize = 50;
+
+        let mem = MemoryDb::new();
+        let initial = futures::executor::block_on(mem.stats())
+            .unwrap()
+            .entity_count;
+        let ids = (0..THREADS)
+            .map(|_| (0..ENTITIES).map(|_| Id::random()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        std::thread::scope(|scope| {
+            for (thread, ids) in ids.iter().enumerate() {
+                let mem = &mem;
+                scope.spawn(move || {
+                    for (index, id) in ids.iter().enumerate() {
+                        let title = format!("{thread}-{index}");
+                        let batch = Batch::new().and_create(query::mutate::Create {
+                            id: *id,
+                            data: map! { "factor/title": title.clone() },
+                        });
+                        futures::executor::block_on(mem.apply_batch(batch)).unwrap();
+
+                        let data = futures::executor::block_on(mem.entity((*id).into()))
+                            .unwrap()
+                            .unwrap();
+                        assert_eq!(data.get("factor/title"), Some(&title.into()));
+                    }
+                });
+            }
+        });
+
+        let stats = futures::executor::block_on(mem.stats()).unwrap();
+        assert_eq!(stats.entity_count, initial + (THREADS * ENTITIES) as u64);
+        for (thread, ids) in ids.iter().enumerate() {
+            for (index, id) in ids.iter().enumerate() {
+                let data = futures::executor::block_on(mem.entity((*id).into()))
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(
+                    data.get("factor/title"),
+                    Some(&format!("{thread}-{index}").into())
+                );
+            }
+        }
+        assert_eq!(mem.state.read().unwrap().check_consistency(), Vec::new());
     }
 }
@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use factor_core::data::{Id, Value};
+use fnv::FnvHashMap;
+
+use crate::{
+    backend::QueryStats,
+    plan::{self, QueryPlan, ResolvedExpr},
+    registry::{LocalIndexId, Registry},
+};
+
+/// Execution counters for query plans.
+///
+/// Queries run with a shared reference to the store, so the counters are
+/// kept behind a mutex.
+#[derive(Default, Debug)]
+pub(super) struct QueryCounters {
+    counts: Mutex<Counts>,
+}
+
+#[derive(Default, Debug)]
+struct Counts {
+    queries: u64,
+    nodes: BTreeMap<&'static str, u64>,
+    index_usage: FnvHashMap<LocalIndexId, u64>,
+    /// Keyed by attribute id, since local ids are not stable across
+    /// registry resets.
+    scan_lookups: FnvHashMap<Id, u64>,
+}
+
+impl Counts {
+    fn record_node<V, E>(&mut self, plan: &QueryPlan<V, E>) {
+        *self.nodes.entry(plan.node_name()).or_default() += 1;
+        if let Some(index) = plan.index() {
+            *self.index_usage.entry(index).or_default() += 1;
+        }
+        for input in plan.inputs() {
+            self.record_node(input);
+        }
+    }
+}
+
+impl QueryCounters {
+    /// Record the execution of a query plan.
+    pub fn record<V, E>(&self, plan: &QueryPlan<V, E>) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.queries += 1;
+        counts.record_node(plan);
+    }
+
+    /// Record the attributes that full scans of a planned query look up by
+    /// value.
+    ///
+    /// Takes the plan before it is converted for execution, since the
+    /// lookups are detected on resolved expressions.
+    /// `reg` must be the registry the plan was built with.
+    pub fn record_scan_lookups(&self, plan: &QueryPlan<Value, ResolvedExpr>, reg: &Registry) {
+        let mut attrs = Vec::new();
+        plan::scan_lookup_attributes(plan, &mut attrs);
+        if attrs.is_empty() {
+            return;
+        }
+        let mut counts = self.counts.lock().unwrap();
+        for attr in attrs {
+            *counts
+                .scan_lookups
+                .entry(reg.attr(attr).schema.id)
+                .or_default() += 1;
+        }
+    }
+
+    /// Number of executed plan nodes that read the index.
+    pub fn index_usage(&self, index: LocalIndexId) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        counts.index_usage.get(&index).copied().unwrap_or_default()
+    }
+
+    /// Attribute idents are resolved with `reg`, unknown attributes are
+    /// skipped.
+    pub fn stats(&self, reg: &Registry) -> QueryStats {
+        let counts = self.counts.lock().unwrap();
+        let node_count = |name: &str| counts.nodes.get(name).copied().unwrap_or_default();
+        QueryStats {
+            queries: counts.queries,
+            full_scans: node_count("scan"),
+            index_reads: node_count("index_select")
+                + node_count("index_scan")
+                + node_count("index_scan_prefix"),
+            nodes: counts
+                .nodes
+                .iter()
+                .map(|(name, count)| (name.to_string(), *count))
+                .collect(),
+            scan_lookups: counts
+                .scan_lookups
+                .iter()
+                .filter_map(|(attr, count)| {
+                    let attr = reg.require_attr(*attr).ok()?;
+                    Some((attr.schema.ident.clone(), *count))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::INDEX_IDENT_LOCAL;
+
+    use super::*;
+
+    #[test]
+    fn test_query_counters() {
+        let counters = QueryCounters::default();
+        let plan: QueryPlan = QueryPlan::Limit {
+            limit: 1,
+            input: Box::new(QueryPlan::Merge {
+                left: Box::new(QueryPlan::Scan { filter: None }),
+                right: Box::new(QueryPlan::IndexSelect {
+                    index: INDEX_IDENT_LOCAL,
+                    value: "a".into(),
+                }),
+            }),
+        };
+        counters.record(&plan);
+        counters.record(&QueryPlan::<(), ()>::Scan { filter: None });
+
+        let stats = counters.stats(&Registry::new());
+        assert_eq!(stats.queries, 2);
+        assert_eq!(stats.full_scans, 2);
+        assert_eq!(stats.index_reads, 1);
+        assert_eq!(stats.nodes["merge"], 1);
+        assert_eq!(counters.index_usage(INDEX_IDENT_LOCAL), 1);
+        assert!(stats.scan_lookups.is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use factor_core::{
+    data::{DataMap, Id, Value},
+    query::select::Select,
+};
+use fnv::FnvHashSet;
+use instant::Instant;
+
+use crate::{
+    plan::{self, ResolvedExpr},
+    registry::{LocalAttributeId, Registry, ATTR_TYPE_LOCAL},
+};
+
+use super::memory_data::{MemoryTuple, MemoryValue};
+
+/// Least recently used cache of select results.
+///
+/// Only queries that opt in with [`Select::cached`] are cached.
+/// Results are keyed by the plan fingerprint of the query (see
+/// [`plan::PlanCache::fingerprint`]), and are dropped when their TTL expires
+/// or when a write touches a class or attribute they depend on.
+#[derive(Debug)]
+pub(super) struct ResultCache {
+    capacity: usize,
+    /// Incremented on every access, to track recent use.
+    tick: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    last_used: u64,
+    expires_at: Instant,
+    dependencies: Dependencies,
+    items: Vec<DataMap>,
+}
+
+impl ResultCache {
+    /// Default number of cached results.
+    pub const DEFAULT_CAPACITY: usize = 128;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Vec<DataMap>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        if entry.expires_at <= Instant::now() {
+            self.entries.remove(key);
+            return None;
+        }
+        entry.last_used = self.tick;
+        Some(entry.items.clone())
+    }
+
+    /// Add the result of a query, evicting expired results first and the
+    /// least recently used result if the cache is still full.
+    pub fn insert(
+        &mut self,
+        key: String,
+        ttl: Duration,
+        query: &Select,
+        reg: &Registry,
+        items: Vec<DataMap>,
+    ) {
+        if self.capacity == 0 || ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                last_used: self.tick,
+                expires_at: now + ttl,
+                dependencies: Dependencies::for_query(query, reg),
+                items,
+            },
+        );
+    }
+
+    /// Drop all results that may have been changed by a write.
+    pub fn invalidate(&mut self, touched: &TouchedSet) {
+        if touched.is_empty() {
+            return;
+        }
+        self.entries
+            .retain(|_, entry| !entry.dependencies.is_affected_by(touched));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+/// The classes and attributes a cached result depends on.
+#[derive(Debug)]
+struct Dependencies {
+    /// `None` if the query can match entities of any class.
+    classes: Option<FnvHashSet<Id>>,
+    /// `None` if the result contains entity data, in which case a change to
+    /// any attribute of a matching entity affects the result.
+    /// Aggregations only depend on the attributes used in the filter.
+    attributes: Option<FnvHashSet<LocalAttributeId>>,
+}
+
+impl Dependencies {
+    fn for_query(query: &Select, reg: &Registry) -> Self {
+        let filter = match query.filter.clone().map(|f| plan::resolve_expr(f, reg)) {
+            Some(Ok(filter)) => Some(filter),
+            // The query can not have produced a result.
+            Some(Err(_)) => {
+                return Self {
+                    classes: None,
+                    attributes: None,
+                }
+            }
+            None => None,
+        };
+
+        let mut attributes = FnvHashSet::default();
+        if let Some(filter) = &filter {
+            // Idents are resolved while the query runs, so the result can
+            // depend on any entity.
+            if !collect_attributes(filter, &mut attributes) {
+                return Self {
+                    classes: None,
+                    attributes: None,
+                };
+            }
+        }
+
+        Self {
+            classes: filter
+                .as_ref()
+                .and_then(|filter| filter_classes(filter, reg)),
+            attributes: if query.aggregate.is_empty() {
+                None
+            } else {
+                Some(attributes)
+            },
+        }
+    }
+
+    fn is_affected_by(&self, touched: &TouchedSet) -> bool {
+        let classes = match &self.classes {
+            Some(classes) => touched.all_classes || !classes.is_disjoint(&touched.classes),
+            None => true,
+        };
+        let attributes = match &self.attributes {
+            Some(attrs) => touched.all_attributes || !attrs.is_disjoint(&touched.attributes),
+            None => true,
+        };
+        classes && attributes
+    }
+}
+
+/// Find the classes a filter is restricted to by a top-level
+/// `factor/type == X`, `factor/type IN (...)` or type inheritance clause.
+fn filter_classes(filter: &ResolvedExpr, reg: &Registry) -> Option<FnvHashSet<Id>> {
+    let mut clauses = Vec::new();
+    plan::and_clauses(filter, &mut clauses);
+
+    clauses.into_iter().find_map(|clause| {
+        if let Some((attr, value)) = clause.as_binary_op_attr_eq_value() {
+            if attr == ATTR_TYPE_LOCAL {
+                return value_class(value, reg).map(|id| std::iter::once(id).collect());
+            }
+        }
+        if let ResolvedExpr::InheritsClass(classes) = clause {
+            return classes
+                .iter()
+                .map(|class| reg.entity(class).map(|class| class.schema.id))
+                .collect();
+        }
+        match clause.as_in_literal_attr() {
+            Some((attr, items)) if attr == ATTR_TYPE_LOCAL => {
+                items.iter().map(|value| value_class(value, reg)).collect()
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Collect all attributes used by an expression.
+///
+/// Returns false if the expression resolves idents.
+fn collect_attributes(expr: &ResolvedExpr, attrs: &mut FnvHashSet<LocalAttributeId>) -> bool {
+    match expr {
+        ResolvedExpr::Literal(_) | ResolvedExpr::Regex(_) => true,
+        ResolvedExpr::Ident(_) => false,
+        ResolvedExpr::Attr(attr) => {
+            attrs.insert(*attr);
+            true
+        }
+        ResolvedExpr::List(items) => items.iter().all(|item| collect_attributes(item, attrs)),
+        ResolvedExpr::UnaryOp { expr, .. } => collect_attributes(expr, attrs),
+        ResolvedExpr::BinaryOp(bin) => {
+            collect_attributes(&bin.left, attrs) && collect_attributes(&bin.right, attrs)
+        }
+        ResolvedExpr::InLiteral { value, .. } => collect_attributes(value, attrs),
+        ResolvedExpr::If { value, then, or } => {
+            collect_attributes(value, attrs)
+                && collect_attributes(then, attrs)
+                && collect_attributes(or, attrs)
+        }
+        ResolvedExpr::WithinRadius { value, .. } | ResolvedExpr::JsonPath { value, .. } => {
+            collect_attributes(value, attrs)
+        }
+        ResolvedExpr::InheritsClass(_) => {
+            attrs.insert(ATTR_TYPE_LOCAL);
+            true
+        }
+    }
+}
+
+fn value_class(value: &Value, reg: &Registry) -> Option<Id> {
+    match value {
+        Value::Id(id) => Some(*id),
+        Value::String(name) => name_class(name, reg),
+        _ => None,
+    }
+}
+
+fn name_class(name: &str, reg: &Registry) -> Option<Id> {
+    reg.entity_by_name(name)
+        .map(|class| class.schema.id)
+        .or_else(|| Id::from_str(name).ok())
+}
+
+/// Classes and attributes changed by a write.
+#[derive(Default, Debug)]
+pub(super) struct TouchedSet {
+    classes: FnvHashSet<Id>,
+    /// Set if the type of a changed entity could not be resolved to a class.
+    all_classes: bool,
+    attributes: FnvHashSet<LocalAttributeId>,
+    /// Set when entities were created or removed, which can change the
+    /// result of any filter.
+    all_attributes: bool,
+}
+
+impl TouchedSet {
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+            && !self.all_classes
+            && self.attributes.is_empty()
+            && !self.all_attributes
+    }
+
+    /// Record an entity that was created, replaced or removed.
+    pub fn add_tuple(&mut self, tuple: &MemoryTuple, reg: &Registry) {
+        self.add_type(tuple.get(&ATTR_TYPE_LOCAL), reg);
+        self.all_attributes = true;
+    }
+
+    /// Record a changed attribute of an entity with the given type.
+    pub fn add_attribute(
+        &mut self,
+        attr: LocalAttributeId,
+        ty: Option<&MemoryValue>,
+        reg: &Registry,
+    ) {
+        self.add_type(ty, reg);
+        self.attributes.insert(attr);
+    }
+
+    fn add_type(&mut self, ty: Option<&MemoryValue>, reg: &Registry) {
+        let class = match ty {
+            None => return,
+            Some(MemoryValue::Id(id)) => Some(*id),
+            Some(MemoryValue::String(name)) => name_class(name.as_ref(), reg),
+            Some(_) => None,
+        };
+        match class {
+            Some(id) => {
+                self.classes.insert(id);
+            }
+            None => {
+                self.all_classes = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::query::{expr::Expr, select::AggregationOp};
+
+    use super::*;
+
+    #[test]
+    fn test_result_cache_invalidation() {
+        let reg = Registry::new();
+        let attr_type = reg.require_attr_by_name("factor/type").unwrap().local_id;
+        let attr_title = reg.require_attr_by_name("factor/title").unwrap().local_id;
+        let class = reg.entity_by_name("factor/Attribute").unwrap().schema.id;
+        let ttl = Duration::from_secs(60);
+
+        let query = Select::new().with_filter(Expr::eq(
+            Expr::attr_ident("factor/type"),
+            Expr::literal("factor/Attribute"),
+        ));
+        let mut cache = ResultCache::new(2);
+        cache.insert("a".to_string(), ttl, &query, &reg, Vec::new());
+        assert!(cache.get("a").is_some());
+
+        // Changes to other classes do not affect the result.
+        let mut touched = TouchedSet::default();
+        touched.add_attribute(attr_title, Some(&MemoryValue::Id(Id::random())), &reg);
+        cache.invalidate(&touched);
+        assert!(cache.get("a").is_some());
+
+        let mut touched = TouchedSet::default();
+        touched.add_attribute(attr_title, Some(&MemoryValue::Id(class)), &reg);
+        cache.invalidate(&touched);
+        assert!(cache.get("a").is_none());
+
+        // Aggregations only depend on the attributes of the filter.
+        let count = query.with_aggregate(AggregationOp::Count, "count".to_string());
+        cache.insert("b".to_string(), ttl, &count, &reg, Vec::new());
+        let mut touched = TouchedSet::default();
+        touched.add_attribute(attr_title, Some(&MemoryValue::Id(class)), &reg);
+        cache.invalidate(&touched);
+        assert!(cache.get("b").is_some());
+
+        let mut touched = TouchedSet::default();
+        touched.add_attribute(attr_type, Some(&MemoryValue::Id(class)), &reg);
+        cache.invalidate(&touched);
+        assert!(cache.get("b").is_none());
+    }
+
+    #[test]
+    fn test_result_cache_expires() {
+        let reg = Registry::new();
+        let mut cache = ResultCache::new(2);
+        cache.insert(
+            "a".to_string(),
+            Duration::from_millis(1),
+            &Select::new(),
+            &reg,
+            Vec::new(),
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(cache.get("a").is_none());
+        assert!(cache.is_empty());
+    }
+}
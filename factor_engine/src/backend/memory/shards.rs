@@ -0,0 +1,377 @@
+use std::{
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use factor_core::data::Id;
+use fnv::{FnvHashMap, FnvHasher};
+
+use crate::{registry::LocalIndexId, util::stable_map::StableMapKey};
+
+use super::{
+    index::{Index, MemoryIndexMap},
+    memory_data::MemoryTuple,
+};
+
+pub(super) type EntityMap = FnvHashMap<Id, MemoryTuple>;
+
+/// Entities, split into shards by id.
+///
+/// Every shard has its own lock, so writes to entities in different shards
+/// don't contend.
+/// Shards are always locked in ascending order, followed by the indexes in
+/// the order of their ids, which prevents deadlocks between writers that
+/// hold multiple locks. See [`ShardsWrite`].
+pub(super) struct EntityShards {
+    shards: Box<[RwLock<EntityMap>]>,
+}
+
+impl EntityShards {
+    /// Number of shards.
+    pub const COUNT: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            shards: (0..Self::COUNT).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    pub fn shard_of(id: &Id) -> usize {
+        let mut hasher = FnvHasher::default();
+        id.hash(&mut hasher);
+        // The low byte of the hash is enough to pick a shard.
+        usize::from(hasher.finish().to_le_bytes()[0]) % Self::COUNT
+    }
+
+    /// Lock the shard of a single entity for reading.
+    pub fn read(&self, id: &Id) -> RwLockReadGuard<'_, EntityMap> {
+        self.shards[Self::shard_of(id)].read().unwrap()
+    }
+
+    /// Start a write that locks the shards of the given entities up front,
+    /// and other shards and indexes once they are accessed.
+    pub fn write<'a>(
+        &'a self,
+        indexes: &'a MemoryIndexMap,
+        ids: impl IntoIterator<Item = Id>,
+    ) -> ShardsWrite<'a> {
+        let mut write = ShardsWrite {
+            shards: self,
+            indexes,
+            guards: (0..Self::COUNT).map(|_| None).collect(),
+            index_guards: (0..indexes.len()).map(|_| None).collect(),
+            highest: None,
+        };
+        let mut shards = ids
+            .into_iter()
+            .map(|id| Self::shard_of(&id))
+            .collect::<Vec<_>>();
+        shards.sort_unstable();
+        for shard in shards {
+            write
+                .lock(shard)
+                .expect("shards locked in ascending order never conflict");
+        }
+        write
+    }
+
+    /// Start a write that holds all shards and indexes.
+    pub fn write_all<'a>(&'a self, indexes: &'a MemoryIndexMap) -> ShardsWrite<'a> {
+        let mut write = self.write(indexes, None);
+        write
+            .lock_all()
+            .expect("shards locked in ascending order never conflict");
+        write
+    }
+
+    /// Lock all shards and indexes for reading.
+    pub fn snapshot<'a>(&'a self, indexes: &'a MemoryIndexMap) -> Snapshot<'a> {
+        let shards = self
+            .shards
+            .iter()
+            .map(|shard| LockRef::Locked(shard.read().unwrap()))
+            .collect();
+        let indexes = indexes
+            .iter()
+            .map(|index| LockRef::Locked(index.read().unwrap()))
+            .collect();
+        Snapshot {
+            shards,
+            indexes,
+            committed: true,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&Id, &mut MemoryTuple)> {
+        self.shards
+            .iter_mut()
+            .flat_map(|shard| shard.get_mut().unwrap().iter_mut())
+    }
+
+    pub fn clear(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard.get_mut().unwrap().clear();
+        }
+    }
+}
+
+/// A shard or index could not be locked without risking a deadlock.
+///
+/// The write must be reverted and retried with all shards locked, see
+/// [`EntityShards::write_all`].
+#[derive(Debug)]
+pub(super) struct LockConflict;
+
+impl std::fmt::Display for LockConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "entity shard or index is locked by a concurrent write")
+    }
+}
+
+impl std::error::Error for LockConflict {}
+
+/// Write access to the shards and indexes of a batch.
+///
+/// Shards and indexes stay locked until the write is dropped, so the changes
+/// of a batch can be reverted without other writers seeing or conflicting
+/// with them.
+///
+/// Locks are ordered: all shards come before all indexes.
+/// Waiting for a lock is only safe if no lock above it is held, otherwise
+/// two writers could wait for each other. Such locks are only tried, and
+/// accessing an entity or index fails with [`LockConflict`] if it is busy.
+pub(super) struct ShardsWrite<'a> {
+    shards: &'a EntityShards,
+    indexes: &'a MemoryIndexMap,
+    guards: Vec<Option<RwLockWriteGuard<'a, EntityMap>>>,
+    index_guards: Vec<Option<RwLockWriteGuard<'a, Index>>>,
+    /// Position of the highest held lock.
+    /// Shards are at their number, indexes follow after the shards.
+    highest: Option<usize>,
+}
+
+impl<'a> ShardsWrite<'a> {
+    /// Whether the lock at the given position may be waited for.
+    fn may_block(&self, position: usize) -> bool {
+        self.highest.map_or(true, |highest| highest < position)
+    }
+
+    fn lock(&mut self, shard: usize) -> Result<&mut EntityMap, LockConflict> {
+        if self.guards[shard].is_none() {
+            let shards: &'a EntityShards = self.shards;
+            let lock = &shards.shards[shard];
+            let guard = if self.may_block(shard) {
+                lock.write().unwrap()
+            } else {
+                lock.try_write().map_err(|_| LockConflict)?
+            };
+            self.guards[shard] = Some(guard);
+            self.highest = self.highest.max(Some(shard));
+        }
+        Ok(self.guards[shard].as_deref_mut().unwrap())
+    }
+
+    fn lock_index(&mut self, index: LocalIndexId) -> Result<&mut Index, LockConflict> {
+        let pos = index.as_index();
+        if self.index_guards[pos].is_none() {
+            let indexes: &'a MemoryIndexMap = self.indexes;
+            let lock = indexes.get(index);
+            let guard = if self.may_block(EntityShards::COUNT + pos) {
+                lock.write().unwrap()
+            } else {
+                lock.try_write().map_err(|_| LockConflict)?
+            };
+            self.index_guards[pos] = Some(guard);
+            self.highest = self.highest.max(Some(EntityShards::COUNT + pos));
+        }
+        Ok(self.index_guards[pos].as_deref_mut().unwrap())
+    }
+
+    /// Lock all shards and indexes that are not held yet.
+    pub fn lock_all(&mut self) -> Result<(), LockConflict> {
+        for shard in 0..EntityShards::COUNT {
+            self.lock(shard)?;
+        }
+        for pos in 0..self.index_guards.len() {
+            self.lock_index(LocalIndexId::from_index(pos))?;
+        }
+        Ok(())
+    }
+
+    /// Lock the shard of an entity.
+    pub fn lock_entity(&mut self, id: &Id) -> Result<(), LockConflict> {
+        self.lock(EntityShards::shard_of(id)).map(|_| ())
+    }
+
+    pub fn get(&mut self, id: &Id) -> Result<Option<&MemoryTuple>, LockConflict> {
+        Ok(self.lock(EntityShards::shard_of(id))?.get(id))
+    }
+
+    pub fn get_mut(&mut self, id: &Id) -> Result<Option<&mut MemoryTuple>, LockConflict> {
+        Ok(self.lock(EntityShards::shard_of(id))?.get_mut(id))
+    }
+
+    pub fn contains_key(&mut self, id: &Id) -> Result<bool, LockConflict> {
+        Ok(self.lock(EntityShards::shard_of(id))?.contains_key(id))
+    }
+
+    pub fn insert(
+        &mut self,
+        id: Id,
+        tuple: MemoryTuple,
+    ) -> Result<Option<MemoryTuple>, LockConflict> {
+        Ok(self.lock(EntityShards::shard_of(&id))?.insert(id, tuple))
+    }
+
+    pub fn remove(&mut self, id: &Id) -> Result<Option<MemoryTuple>, LockConflict> {
+        Ok(self.lock(EntityShards::shard_of(id))?.remove(id))
+    }
+
+    /// Get an entity whose shard is already held.
+    ///
+    /// Panics if the shard is not held.
+    pub fn locked(&self, id: &Id) -> Option<&MemoryTuple> {
+        self.guards[EntityShards::shard_of(id)]
+            .as_ref()
+            .expect("entity shard is not locked")
+            .get(id)
+    }
+
+    /// The shard of an entity that is already held.
+    ///
+    /// Panics if the shard is not held.
+    pub fn locked_mut(&mut self, id: &Id) -> &mut EntityMap {
+        self.guards[EntityShards::shard_of(id)]
+            .as_deref_mut()
+            .expect("entity shard is not locked")
+    }
+
+    /// Lock an index for writing.
+    ///
+    /// The index stays locked until the write is dropped.
+    pub fn index_mut(&mut self, index: LocalIndexId) -> Result<&mut Index, LockConflict> {
+        self.lock_index(index)
+    }
+
+    /// An index that is already held.
+    ///
+    /// Panics if the index is not held.
+    pub fn locked_index_mut(&mut self, index: LocalIndexId) -> &mut Index {
+        self.index_guards[index.as_index()]
+            .as_deref_mut()
+            .expect("index is not locked")
+    }
+
+    /// Read an index.
+    ///
+    /// Uses the held lock if the index was already changed by this write,
+    /// otherwise the index is only locked for reading while `f` runs.
+    pub fn read_index<R>(
+        &self,
+        index: LocalIndexId,
+        f: impl FnOnce(&Index) -> R,
+    ) -> Result<R, LockConflict> {
+        let pos = index.as_index();
+        if let Some(guard) = &self.index_guards[pos] {
+            return Ok(f(guard));
+        }
+        let lock = self.indexes.get(index);
+        let guard = if self.may_block(EntityShards::COUNT + pos) {
+            lock.read().unwrap()
+        } else {
+            lock.try_read().map_err(|_| LockConflict)?
+        };
+        Ok(f(&guard))
+    }
+
+    /// Iterate over all entities.
+    ///
+    /// Panics if not all shards are held, see [`Self::lock_all`].
+    pub fn iter(&self) -> impl Iterator<Item = (&Id, &MemoryTuple)> {
+        self.guards
+            .iter()
+            .flat_map(|guard| guard.as_ref().expect("entity shard is not locked").iter())
+    }
+
+    /// Read access to all entities and indexes.
+    ///
+    /// Panics if not all shards and indexes are held, see [`Self::lock_all`].
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        let shards = self
+            .guards
+            .iter()
+            .map(|guard| LockRef::Borrowed(guard.as_deref().expect("entity shard is not locked")))
+            .collect();
+        let indexes = self
+            .index_guards
+            .iter()
+            .map(|guard| LockRef::Borrowed(guard.as_deref().expect("index is not locked")))
+            .collect();
+        Snapshot {
+            shards,
+            indexes,
+            committed: false,
+        }
+    }
+}
+
+enum LockRef<'a, T> {
+    Locked(RwLockReadGuard<'a, T>),
+    Borrowed(&'a T),
+}
+
+impl<'a, T> Deref for LockRef<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Locked(guard) => guard,
+            Self::Borrowed(shard) => shard,
+        }
+    }
+}
+
+/// Read access to all entities and indexes, used by queries.
+///
+/// Writers hold the shards of changed entities and the changed indexes
+/// until the batch is done, so the indexes are consistent with the entities
+/// while all shards and indexes are held.
+pub(super) struct Snapshot<'a> {
+    shards: Vec<LockRef<'a, EntityMap>>,
+    indexes: Vec<LockRef<'a, Index>>,
+    committed: bool,
+}
+
+impl<'a> Snapshot<'a> {
+    /// Whether the snapshot only contains committed data.
+    ///
+    /// Snapshots of a write also contain its pending changes.
+    pub fn is_committed(&self) -> bool {
+        self.committed
+    }
+
+    pub fn get(&self, id: &Id) -> Option<&MemoryTuple> {
+        self.shards[EntityShards::shard_of(id)].get(id)
+    }
+
+    pub fn contains_key(&self, id: &Id) -> bool {
+        self.shards[EntityShards::shard_of(id)].contains_key(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Id, &MemoryTuple)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &MemoryTuple> {
+        self.shards.iter().flat_map(|shard| shard.values())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn index(&self, index: LocalIndexId) -> &Index {
+        &self.indexes[index.as_index()]
+    }
+}
@@ -1,23 +1,42 @@
-use std::{borrow::Cow, str::FromStr};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+};
 
 use anyhow::{anyhow, bail, Context};
 
 use factor_core::{
-    data::{patch::Patch, DataMap, Id, IdOrIdent, Value, ValueMap, ValueType},
-    error::{EntityNotFound, UniqueConstraintViolation},
+    data::{
+        patch::Patch, value::IndexKey, DataMap, GeoPoint, Id, IdOrIdent, SharedDataMap, Value,
+        ValueMap, ValueType,
+    },
+    db::ClassStats,
+    error::{
+        EntityAlreadyExists, EntityNotFound, QuotaExceeded, ReferenceCycle,
+        UniqueConstraintViolation,
+    },
     query::{
         self,
         expr::Expr,
+        graph::{GraphDirection, GraphQuery},
         migrate::Migration,
         mutate::{Batch, EntityPatch},
         select::{AggregationOp, Item, Order, Page, Select},
     },
+    schema::builtin,
 };
 
 use crate::{
+    audit,
+    authorizer::Operation,
     backend::{
-        self, DbOp, TupleAction, TupleIndexInsert, TupleIndexOp, TupleIndexRemove,
-        TupleIndexReplace,
+        self, Audit, BackendStats, DbOp, IndexStats, TupleAction, TupleIndexInsert, TupleIndexOp,
+        VerifyIssue,
     },
     plan::{self, QueryPlan, ResolvedExpr, Sort},
     registry::{
@@ -27,26 +46,72 @@ use crate::{
 };
 
 use super::{
+    ident_cache::IdentCache,
     index::{self, MemoryIndexMap},
+    materialized::MaterializedViews,
     memory_data::{self, MemoryExpr, MemoryTuple, MemoryValue, SharedStr},
+    result_cache::{ResultCache, TouchedSet},
+    shards::{EntityShards, LockConflict, ShardsWrite, Snapshot},
+    type_index::{self, TypeIndex},
+    view::EntityView,
 };
 
 /// Memory store for building a backend.
 ///
 /// The [MemoryDb] is a simple memory-only backend, but the store can also
 /// be used by other backends as a caching layer or for other purposes.
+///
+/// Batches and queries only need a shared reference.
+/// Entities are sharded by id, and batches only lock the shards of the
+/// entities they touch, so batches that change unrelated entities run
+/// concurrently. Queries lock all shards for reading, see [`Snapshot`].
+/// Migrations need exclusive access.
 pub struct MemoryStore {
     interner: super::interner::Interner,
     registry: crate::registry::SharedRegistry,
-    entities: fnv::FnvHashMap<Id, MemoryTuple>,
+    entities: EntityShards,
+    /// Every index has its own lock.
+    /// Writers lock an index after the shard of the changed entity, and hold
+    /// it until the batch is committed or reverted, see [`ShardsWrite`].
     indexes: MemoryIndexMap,
+    /// Entities per type.
+    /// Kept up to date with every change to `entities`.
+    type_index: Mutex<TypeIndex>,
+    /// Materialized entities of views.
+    /// Kept up to date with every change to `entities`.
+    views: Mutex<MaterializedViews>,
 
     ignore_index_constraints: bool,
+    /// Skip index updates and rebuild all indexes in bulk later.
+    /// See [`Self::set_defer_index_updates`].
+    defer_index_updates: bool,
+    /// Set when index updates were skipped.
+    indexes_stale: AtomicBool,
 
     revert_epoch: RevertEpoch,
     revert_ops: Option<(RevertEpoch, RevertList)>,
+
+    query_counters: super::query_stats::QueryCounters,
+    /// Cached select plans.
+    /// Behind a mutex, since queries run with a shared reference.
+    plan_cache: std::sync::Mutex<plan::PlanCache>,
+    /// Cached results of selects that opt in with
+    /// [`query::select::Select::cached`].
+    result_cache: std::sync::Mutex<ResultCache>,
+    /// Resolved entity names.
+    /// Kept up to date with every change to the ident index.
+    ident_cache: IdentCache,
 }
 
+/// Minimum number of consecutive creates in a batch that are validated in
+/// parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_VALIDATION_MIN_CREATES: usize = 64;
+
+/// Batches with more actions lock all entity shards up front, since they
+/// likely touch most shards anyway.
+const MAX_SHARDED_BATCH_ACTIONS: usize = 32;
+
 type TupleIter<'a> = Box<dyn Iterator<Item = Cow<'a, MemoryTuple>> + 'a>;
 
 impl MemoryStore {
@@ -54,25 +119,26 @@ impl MemoryStore {
         let mut s = Self {
             interner: super::interner::Interner::new(),
             registry: registry.clone(),
-            entities: fnv::FnvHashMap::default(),
+            entities: EntityShards::new(),
             indexes: self::index::new_memory_index_map(),
+            type_index: Default::default(),
+            views: Default::default(),
             revert_epoch: 0,
             revert_ops: None,
+            query_counters: Default::default(),
+            plan_cache: Default::default(),
+            result_cache: Default::default(),
+            ident_cache: Default::default(),
             // FIXME: set to false, add setter.
             ignore_index_constraints: false,
+            defer_index_updates: false,
+            indexes_stale: AtomicBool::new(false),
         };
 
         // FIXME: this is a temporary hack to work around the fact that
         // migrations are not yet used for internal schemas.
         // Remove once everything is properly done with migrations.
-        let indexes = {
-            registry
-                .read()
-                .unwrap()
-                .iter_indexes()
-                .cloned()
-                .collect::<Vec<_>>()
-        };
+        let indexes = { registry.load().iter_indexes().cloned().collect::<Vec<_>>() };
         for index in indexes {
             s.index_create(&index).unwrap();
         }
@@ -84,43 +150,195 @@ impl MemoryStore {
         self.ignore_index_constraints = ignore;
     }
 
+    /// Skip updating indexes on writes, and rebuild all indexes in a single
+    /// pass once the flag is cleared again.
+    ///
+    /// Much faster than maintaining the indexes when applying a large number
+    /// of changes, like when restoring a log.
+    /// Mutations that select entities rebuild the indexes first, but plain
+    /// selects see outdated indexes until the flag is cleared.
+    ///
+    /// The ident and alias indexes are always kept up to date, since they are
+    /// needed to resolve entity names.
+    /// Rebuilding does not check unique constraints, so this should be
+    /// combined with [`Self::set_ignore_index_constraints`].
+    pub fn set_defer_index_updates(&mut self, defer: bool) -> Result<(), anyhow::Error> {
+        self.defer_index_updates = defer;
+        if !defer {
+            self.flush_deferred_index_updates(&mut self.entities.write_all(&self.indexes))?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the indexes if updates were skipped.
+    fn flush_deferred_index_updates(&self, shards: &mut ShardsWrite) -> Result<(), anyhow::Error> {
+        if self.indexes_stale.load(Ordering::Relaxed) {
+            shards.lock_all()?;
+            let reg = self.registry.load_full();
+            self.rebuild_indexes(shards, &reg)?;
+        }
+        Ok(())
+    }
+
+    /// Whether updates to the given index are currently skipped.
+    fn skip_index_update(&self, index: LocalIndexId) -> bool {
+        if self.defer_index_updates && !Self::resolves_idents(index) {
+            self.indexes_stale.store(true, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn registry(&self) -> &crate::registry::SharedRegistry {
         &self.registry
     }
 
+    /// Whether the index is used to resolve entity names.
+    fn resolves_idents(index: LocalIndexId) -> bool {
+        index == registry::INDEX_IDENT_LOCAL || index == registry::INDEX_ALIASES_LOCAL
+    }
+
+    /// Resolve an entity by id or name.
+    ///
+    /// Names are looked up in the idents first, then in the aliases.
     fn resolve_ident(&self, ident: &IdOrIdent) -> Option<Id> {
         match ident {
             IdOrIdent::Id(id) => Some(*id),
-            IdOrIdent::Name(name) => {
-                self.indexes
+            IdOrIdent::Name(name) => self.ident_cache.get(name).or_else(|| {
+                let idents = self
+                    .indexes
                     .get(registry::INDEX_IDENT_LOCAL)
-                    .get_unique(&MemoryValue::String(SharedStr::from_string(
-                        name.to_string(),
-                    )))
+                    .read()
+                    .unwrap();
+                let aliases = self
+                    .indexes
+                    .get(registry::INDEX_ALIASES_LOCAL)
+                    .read()
+                    .unwrap();
+                self.lookup_committed_name(name, &idents, &aliases)
+            }),
+        }
+    }
+
+    /// Like [`Self::resolve_ident`], but reads the indexes of a snapshot,
+    /// which must not be locked again while the snapshot holds them.
+    fn resolve_ident_in(&self, snapshot: &Snapshot, ident: &IdOrIdent) -> Option<Id> {
+        let idents = snapshot.index(registry::INDEX_IDENT_LOCAL);
+        let aliases = snapshot.index(registry::INDEX_ALIASES_LOCAL);
+        match ident {
+            IdOrIdent::Id(id) => Some(*id),
+            IdOrIdent::Name(name) if snapshot.is_committed() => self
+                .ident_cache
+                .get(name)
+                .or_else(|| self.lookup_committed_name(name, idents, aliases)),
+            // The cache is bypassed, since the snapshot of a write can differ
+            // from the committed data.
+            IdOrIdent::Name(name) => Self::lookup_name(name, idents, aliases),
+        }
+    }
+
+    /// Look up a name in the idents first, then in the aliases.
+    fn lookup_name(name: &str, idents: &index::Index, aliases: &index::Index) -> Option<Id> {
+        let key = MemoryValue::String(SharedStr::from_string(name.to_string()));
+        idents.get_unique(&key).or_else(|| aliases.get_unique(&key))
+    }
+
+    /// Like [`Self::lookup_name`], but caches the result.
+    ///
+    /// Must only be called with the committed indexes, while they are locked
+    /// for reading, so a concurrent write can not be missed by the cache.
+    fn lookup_committed_name(
+        &self,
+        name: &str,
+        idents: &index::Index,
+        aliases: &index::Index,
+    ) -> Option<Id> {
+        let id = Self::lookup_name(name, idents, aliases)?;
+        self.ident_cache.insert(name, id);
+        Some(id)
+    }
+
+    /// Drop cached ident resolutions affected by applied or reverted changes.
+    ///
+    /// Must be called once the changes are final, while the changed indexes
+    /// are still locked for writing.
+    fn invalidate_idents(&self, changes: &[RevertOp]) {
+        for op in changes {
+            match op {
+                RevertOp::IndexValueInserted { index, value, .. }
+                | RevertOp::IndexValueRemoved { index, value, .. }
+                    if Self::resolves_idents(*index) =>
+                {
+                    if let MemoryValue::String(name) = value {
+                        self.ident_cache.invalidate(name.as_ref());
+                    }
+                }
+                _ => {}
             }
         }
     }
 
-    fn must_resolve_ident(&self, ident: &IdOrIdent) -> Result<Id, EntityNotFound> {
-        self.resolve_ident(ident).ok_or_else(|| EntityNotFound {
-            ident: ident.clone(),
-        })
+    /// Like [`Self::resolve_ident`], but reads the indexes through a write,
+    /// which may already hold them.
+    ///
+    /// The ident cache is bypassed, since the write may already have changed
+    /// the indexes.
+    /// The ident index comes before the alias index, so reading them in this
+    /// order keeps the lock order.
+    fn resolve_ident_locked(
+        &self,
+        shards: &ShardsWrite,
+        ident: &IdOrIdent,
+    ) -> Result<Option<Id>, LockConflict> {
+        match ident {
+            IdOrIdent::Id(id) => Ok(Some(*id)),
+            IdOrIdent::Name(name) => shards.read_index(registry::INDEX_IDENT_LOCAL, |idents| {
+                shards.read_index(registry::INDEX_ALIASES_LOCAL, |aliases| {
+                    Self::lookup_name(name, idents, aliases)
+                })
+            })?,
+        }
     }
 
-    fn resolve_entity(&self, ident: &IdOrIdent) -> Option<&MemoryTuple> {
-        let id = self.resolve_ident(ident)?;
-        self.entities.get(&id)
+    /// Resolve entity idents in reference values through a write, see
+    /// [`Registry::resolve_data_idents`].
+    fn resolve_ref_ident(
+        &self,
+        shards: &ShardsWrite,
+        name: &str,
+    ) -> Result<Option<Id>, anyhow::Error> {
+        Ok(self.resolve_ident_locked(shards, &IdOrIdent::new_str(name))?)
     }
 
-    fn must_get_entity(&self, id: Id) -> Result<&MemoryTuple, EntityNotFound> {
-        self.entities
-            .get(&id)
-            .ok_or_else(|| EntityNotFound { ident: id.into() })
+    fn must_resolve_ident(
+        &self,
+        shards: &ShardsWrite,
+        ident: &IdOrIdent,
+    ) -> Result<Id, anyhow::Error> {
+        self.resolve_ident_locked(shards, ident)?.ok_or_else(|| {
+            EntityNotFound {
+                ident: ident.clone(),
+            }
+            .into()
+        })
     }
 
-    fn must_resolve_entity(&self, ident: &IdOrIdent) -> Result<&MemoryTuple, EntityNotFound> {
-        self.resolve_entity(ident)
-            .ok_or_else(|| EntityNotFound::new(ident.clone()))
+    /// Read a single entity, only locking its shard.
+    fn read_entity(
+        &self,
+        ident: &IdOrIdent,
+        reg: &Registry,
+    ) -> Result<Option<DataMap>, anyhow::Error> {
+        let id = match self.resolve_ident(ident) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        self.entities
+            .read(&id)
+            .get(&id)
+            .map(|tuple| Self::tuple_to_read_data_map_with(reg, tuple))
+            .transpose()
     }
 
     // fn resolve_entity_mut(&mut self, ident: &Ident) -> Option<&mut MemoryTuple> {
@@ -135,10 +353,9 @@ impl MemoryStore {
     //     Ok(memory_to_id_map(tuple))
     // }
 
-    fn intern_data_map(&mut self, map: DataMap) -> Result<MemoryTuple, anyhow::Error> {
+    fn intern_data_map(&self, map: DataMap) -> Result<MemoryTuple, anyhow::Error> {
         // TODO: fix this... pass in the registry
-        let reg = self.registry.clone();
-        let reg = reg.read().unwrap();
+        let reg = self.registry.load_full();
 
         let map = map
             .0
@@ -153,8 +370,11 @@ impl MemoryStore {
     }
 
     fn tuple_to_data_map(&self, tuple: &MemoryTuple) -> DataMap {
-        let reg = self.registry.read().unwrap();
+        let reg = self.registry.load();
+        Self::tuple_to_data_map_with(&reg, tuple)
+    }
 
+    pub(super) fn tuple_to_data_map_with(reg: &Registry, tuple: &MemoryTuple) -> DataMap {
         let map: std::collections::BTreeMap<_, _> = tuple
             .0
             .iter()
@@ -168,6 +388,27 @@ impl MemoryStore {
         ValueMap(map)
     }
 
+    /// Convert a tuple to the entity data returned to readers, including the
+    /// values of computed attributes.
+    ///
+    /// See [`factor_core::schema::ComputedAttribute`].
+    fn tuple_to_read_data_map_with(
+        reg: &Registry,
+        tuple: &MemoryTuple,
+    ) -> Result<DataMap, anyhow::Error> {
+        let mut data = Self::tuple_to_data_map_with(reg, tuple);
+        reg.add_computed_attributes(&mut data)?;
+        Ok(data)
+    }
+
+    fn tuple_to_shared_data_map_with(reg: &Registry, tuple: &MemoryTuple) -> SharedDataMap {
+        tuple
+            .0
+            .iter()
+            .map(|(id, value)| (reg.attr(*id).name.clone(), value.into()))
+            .collect()
+    }
+
     // fn persist_tuple(&mut self, tuple: TuplePersist) -> Result<Id, anyhow::Error> {
     //     let ident_id = tuple
     //         .ident
@@ -273,13 +514,16 @@ impl MemoryStore {
     //
 
     pub(super) fn index_create(&mut self, schema: &RegisteredIndex) -> Result<(), anyhow::Error> {
-        let index = if schema.schema.unique {
+        let index = if schema.geo {
+            index::Index::Geo(index::MultiIndex::new())
+        } else if schema.schema.unique {
             index::Index::Unique(index::UniqueIndex::new())
         } else {
             index::Index::Multi(index::MultiIndex::new())
         };
 
-        self.indexes.append_checked(schema.local_id, index);
+        self.indexes
+            .append_checked(schema.local_id, RwLock::new(index));
         Ok(())
     }
 
@@ -290,129 +534,137 @@ impl MemoryStore {
         // Since the index list is addressed by numeric local index, the index
         // is not actually removed, but just it's data is cleared to free up
         // memory.
-        self.indexes.get_mut(schema.local_id).clear();
+        self.indexes
+            .get_mut(schema.local_id)
+            .get_mut()
+            .unwrap()
+            .clear();
+        if Self::resolves_idents(schema.local_id) {
+            self.ident_cache.clear();
+        }
 
         Ok(())
     }
 
-    fn tuple_index_insert(
-        &mut self,
+    /// Apply the index ops of an entity.
+    ///
+    /// Values are interned first, and the ops are then applied grouped by
+    /// index, so each index is only looked up once, instead of once per
+    /// attribute.
+    /// Ops of the same index keep their order.
+    ///
+    /// The shard of the entity is locked first, so readers never see index
+    /// entries that don't match the entity.
+    /// Indexes stay locked until the batch is done, so a revert can always
+    /// restore removed unique values.
+    fn apply_tuple_index_ops(
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
-        op: TupleIndexInsert,
+        ops: Vec<TupleIndexOp>,
         reverts: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let value = self.interner.intern_value(op.value);
-
-        let index_id = op.index;
+        shards.lock_entity(&id)?;
 
-        match self.indexes.get_mut(op.index) {
-            super::index::Index::Unique(idx) => {
-                if self.ignore_index_constraints {
-                    idx.insert_unchecked(value.clone(), id);
-                } else {
-                    idx.insert_unique(value.clone(), id).map_err(|_| {
-                        let index = reg
-                            .index_by_local_id(index_id)
-                            .expect("Invalid local index id");
-                        UniqueConstraintViolation {
-                            index: index.schema.ident.clone(),
-                            entity_id: id,
-                            // TODO: add attribute name!
-                            attribute: "?".to_string(),
-                            value: Some(value.to_value()),
-                        }
-                    })?;
-                }
+        // Entities only touch a few indexes, so a linear search is cheaper
+        // than a map.
+        let mut grouped = Vec::<(LocalIndexId, Vec<MemoryIndexOp>)>::new();
+        for op in ops {
+            let index = op.index();
+            if self.skip_index_update(index) {
+                continue;
             }
-            super::index::Index::Multi(idx) => {
-                idx.add(value.clone(), id);
+            let op = match op {
+                TupleIndexOp::Insert(op) => MemoryIndexOp::Insert {
+                    value: self.interner.intern_value(op.value),
+                    attribute: op.attribute,
+                },
+                TupleIndexOp::Replace(op) => MemoryIndexOp::Replace {
+                    old_value: self.interner.intern_value(op.old_value),
+                    value: self.interner.intern_value(op.value),
+                    attribute: op.attribute,
+                },
+                TupleIndexOp::Remove(op) => {
+                    MemoryIndexOp::Remove(self.interner.intern_value(op.value))
+                }
+            };
+
+            match grouped.iter_mut().find(|(other, _)| *other == index) {
+                Some((_, ops)) => ops.push(op),
+                None => grouped.push((index, vec![op])),
             }
         }
 
-        reverts.push(RevertOp::IndexValueInserted {
-            index: index_id,
-            entity_id: id,
-            value,
-        });
+        for (index_id, ops) in grouped {
+            let index = shards.index_mut(index_id)?;
+            for op in ops {
+                self.apply_index_op(index, index_id, id, op, reverts, reg)?;
+            }
+        }
 
         Ok(())
     }
 
-    fn tuple_index_replace(
-        &mut self,
+    fn apply_index_op(
+        &self,
+        index: &mut index::Index,
+        index_id: LocalIndexId,
         id: Id,
-        op: TupleIndexReplace,
-        revert: &mut RevertList,
+        op: MemoryIndexOp,
+        reverts: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let value = self.interner.intern_value(op.value);
-        let old_value = self.interner.intern_value(op.old_value);
-
-        let index_id = op.index;
-
-        let removed = match self.indexes.get_mut(op.index) {
-            super::index::Index::Unique(idx) => {
-                let removed = idx.remove(&old_value);
+        let (old_value, value) = match op {
+            MemoryIndexOp::Insert { value, attribute } => (None, Some((value, attribute))),
+            MemoryIndexOp::Replace {
+                old_value,
+                value,
+                attribute,
+            } => (Some(old_value), Some((value, attribute))),
+            MemoryIndexOp::Remove(value) => (Some(value), None),
+        };
 
-                if self.ignore_index_constraints {
-                    idx.insert_unchecked(value.clone(), id);
-                } else {
-                    idx.insert_unique(value.clone(), id).map_err(|_| {
-                        let index = reg
-                            .index_by_local_id(index_id)
-                            .expect("Invalid local index id");
-                        UniqueConstraintViolation {
-                            index: index.schema.ident.clone(),
-                            entity_id: id,
-                            // TODO: add attribute name!
-                            attribute: "?".to_string(),
-                            value: Some(value.to_value()),
-                        }
-                    })?;
+        if let Some(old_value) = old_value {
+            let removed = match index {
+                index::Index::Unique(idx) => idx.remove(&old_value).is_some(),
+                index::Index::Multi(idx) | index::Index::Geo(idx) => {
+                    idx.remove(&old_value, id).is_some()
                 }
-
-                removed.is_some()
-            }
-            super::index::Index::Multi(idx) => {
-                let removed = idx.remove(&old_value, id);
-                idx.add(value.clone(), id);
-                removed.is_some()
+            };
+            if removed {
+                reverts.push(RevertOp::IndexValueRemoved {
+                    index: index_id,
+                    entity_id: id,
+                    value: old_value,
+                });
             }
-        };
-
-        revert.push(RevertOp::IndexValueInserted {
-            index: index_id,
-            entity_id: id,
-            value,
-        });
-        if removed {
-            revert.push(RevertOp::IndexValueRemoved {
-                index: index_id,
-                entity_id: id,
-                value: old_value,
-            });
         }
 
-        Ok(())
-    }
-
-    fn tuple_index_remove(
-        &mut self,
-        id: Id,
-        op: TupleIndexRemove,
-        reverts: &mut RevertList,
-    ) -> Result<(), anyhow::Error> {
-        let value = self.interner.intern_value(op.value);
-        let index_id = op.index;
-
-        let removed = match self.indexes.get_mut(op.index) {
-            super::index::Index::Unique(idx) => idx.remove(&value).is_some(),
-            super::index::Index::Multi(idx) => idx.remove(&value, id).is_some(),
-        };
-
-        if removed {
-            reverts.push(RevertOp::IndexValueRemoved {
+        if let Some((value, attribute)) = value {
+            match index {
+                index::Index::Unique(idx) => {
+                    if self.ignore_index_constraints {
+                        idx.insert_unchecked(&value, id);
+                    } else {
+                        idx.insert_unique(&value, id).map_err(|_| {
+                            let index = reg
+                                .index_by_local_id(index_id)
+                                .expect("Invalid local index id");
+                            UniqueConstraintViolation {
+                                index: index.schema.ident.clone(),
+                                entity_id: id,
+                                attribute: reg.attr(attribute).schema.ident.clone(),
+                                value: Some(value.to_value()),
+                            }
+                        })?;
+                    }
+                }
+                index::Index::Multi(idx) | index::Index::Geo(idx) => {
+                    idx.add(&value, id);
+                }
+            }
+            reverts.push(RevertOp::IndexValueInserted {
                 index: index_id,
                 entity_id: id,
                 value,
@@ -422,76 +674,103 @@ impl MemoryStore {
         Ok(())
     }
 
-    fn apply_tuple_index_op(
-        &mut self,
-        tuple_id: Id,
-        op: TupleIndexOp,
-        revert: &mut RevertList,
-        reg: &Registry,
-    ) -> Result<(), anyhow::Error> {
-        match op {
-            TupleIndexOp::Insert(op) => self.tuple_index_insert(tuple_id, op, revert, reg),
-            TupleIndexOp::Replace(op) => self.tuple_index_replace(tuple_id, op, revert, reg),
-            TupleIndexOp::Remove(op) => self.tuple_index_remove(tuple_id, op, revert),
-        }
-    }
-
     fn tuple_create(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
         create: backend::TupleCreate,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        if self.entities.contains_key(&id) {
-            return Err(anyhow!("Entity id already exists: '{}'", id));
+        if shards.contains_key(&id)? {
+            return Err(EntityAlreadyExists { id }.into());
         }
 
-        for op in create.index_ops {
-            self.tuple_index_insert(id, op, revert, reg)?;
-        }
+        let index_ops = create
+            .index_ops
+            .into_iter()
+            .map(TupleIndexOp::Insert)
+            .collect();
+        self.apply_tuple_index_ops(shards, id, index_ops, revert, reg)?;
 
         let map = self.intern_data_map(create.data)?;
-        self.entities.insert(id, map);
+        {
+            // Checked under the same lock as the update, so concurrent
+            // creates can not exceed the quota.
+            let mut type_index = self.type_index.lock().unwrap();
+            self.check_class_quota(&type_index, &map, reg)?;
+            type_index.update(id, None, Some(&map));
+        }
+        shards.insert(id, map)?;
         revert.push(RevertOp::TupleCreated { id });
         Ok(())
     }
 
+    /// Reject a new entity if its class already has the maximum number of
+    /// entities.
+    ///
+    /// See [`factor_core::schema::Class::max_entities`].
+    fn check_class_quota(
+        &self,
+        type_index: &TypeIndex,
+        tuple: &MemoryTuple,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        if self.ignore_index_constraints {
+            return Ok(());
+        }
+        let class = match Self::entity_type_id(tuple, reg)?.and_then(|id| reg.entity_by_id(id)) {
+            Some(class) => class,
+            None => return Ok(()),
+        };
+        match class.schema.max_entities {
+            Some(limit) if type_index.count(class) >= limit => Err(QuotaExceeded {
+                class: class.schema.ident.clone(),
+                limit,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
     fn tuple_replace(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
         replace: backend::TupleReplace,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        for op in replace.index_ops {
-            self.apply_tuple_index_op(id, op, revert, reg)?;
-        }
+        self.apply_tuple_index_ops(shards, id, replace.index_ops, revert, reg)?;
 
-        let old = self.entities.remove(&id);
+        let old = shards.remove(&id)?;
         let map = self.intern_data_map(replace.data)?;
-        self.entities.insert(id, map);
+        self.type_index
+            .lock()
+            .unwrap()
+            .update(id, old.as_ref(), Some(&map));
+        shards.insert(id, map)?;
         revert.push(RevertOp::TupleReplaced { id, data: old });
         Ok(())
     }
 
     fn tuple_merge(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
         mut update: backend::TupleMerge,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        for op in std::mem::take(&mut update.index_ops) {
-            self.apply_tuple_index_op(id, op, revert, reg)?;
-        }
+        let index_ops = std::mem::take(&mut update.index_ops);
+        self.apply_tuple_index_ops(shards, id, index_ops, revert, reg)?;
 
-        let old = self
-            .entities
-            .get_mut(&id)
+        let old = shards
+            .get_mut(&id)?
             .ok_or_else(|| EntityNotFound::new(id.into()))?;
+        let old_type = old.get(&ATTR_TYPE_LOCAL).cloned();
 
-        let reg = self.registry.read().unwrap();
+        let reg = self.registry.load();
 
         let mut replaced_values = Vec::<(LocalAttributeId, Option<MemoryValue>)>::new();
 
@@ -503,14 +782,16 @@ impl MemoryStore {
             if let Some(old_value) = old.remove(&attr.local_id) {
                 // FIXME: this is hacky and only covers lists...
                 match (old_value, new_value) {
-                    (MemoryValue::List(mut old_items), Value::List(new_items)) => {
+                    (MemoryValue::List(old_items), Value::List(new_items)) => {
+                        let mut items = old_items.clone();
                         for item in new_items {
                             let item = self.interner.intern_value(item);
-                            if !old_items.contains(&item) {
-                                old_items.push(item);
+                            if !items.contains(&item) {
+                                items.push(item);
                             }
                         }
-                        old.0.insert(attr.local_id, MemoryValue::List(old_items));
+                        old.0.insert(attr.local_id, MemoryValue::List(items));
+                        replaced_values.push((attr.local_id, Some(MemoryValue::List(old_items))));
                     }
                     (old_value, new_value) => {
                         old.0
@@ -521,11 +802,17 @@ impl MemoryStore {
             } else {
                 old.0
                     .insert(attr.local_id, self.interner.intern_value(new_value));
+                // New values are removed on revert.
+                replaced_values.push((attr.local_id, None));
             };
         }
+        self.type_index.lock().unwrap().update_type(
+            id,
+            old_type.as_ref(),
+            old.get(&ATTR_TYPE_LOCAL),
+        );
 
         if !replaced_values.is_empty() {
-            // FIXME: this doesn't UNDO properly for new values.
             revert.push(RevertOp::TupleMerged {
                 id,
                 replaced_data: replaced_values,
@@ -536,21 +823,24 @@ impl MemoryStore {
     }
 
     fn tuple_remove_attrs(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
         mut rem: backend::TupleRemoveAttrs,
         revert: &mut RevertList,
+        reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        for op in std::mem::take(&mut rem.index_ops) {
-            self.tuple_index_remove(id, op, revert)?;
-        }
+        let index_ops = std::mem::take(&mut rem.index_ops)
+            .into_iter()
+            .map(TupleIndexOp::Remove)
+            .collect();
+        self.apply_tuple_index_ops(shards, id, index_ops, revert, reg)?;
 
-        let old = self
-            .entities
-            .get_mut(&id)
+        let old = shards
+            .get_mut(&id)?
             .ok_or_else(|| EntityNotFound::new(id.into()))?;
 
-        let reg = self.registry.read().unwrap();
+        let old_type = old.get(&ATTR_TYPE_LOCAL).cloned();
         let mut removed = Vec::new();
         for attr_id in rem.attrs {
             let attr = reg.require_attr(attr_id)?;
@@ -558,6 +848,11 @@ impl MemoryStore {
                 removed.push((attr.local_id, value));
             }
         }
+        self.type_index.lock().unwrap().update_type(
+            id,
+            old_type.as_ref(),
+            old.get(&ATTR_TYPE_LOCAL),
+        );
 
         if !removed.is_empty() {
             revert.push(RevertOp::TupleAttrsRemoved { id, attrs: removed });
@@ -567,17 +862,26 @@ impl MemoryStore {
     }
 
     fn tuple_delete(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         id: Id,
         del: backend::TupleDelete,
         revert: &mut RevertList,
+        reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        for op in del.index_ops {
-            self.tuple_index_remove(id, op, revert)?;
-        }
+        let index_ops = del
+            .index_ops
+            .into_iter()
+            .map(TupleIndexOp::Remove)
+            .collect();
+        self.apply_tuple_index_ops(shards, id, index_ops, revert, reg)?;
 
-        match self.entities.remove(&id) {
+        match shards.remove(&id)? {
             Some(data) => {
+                self.type_index
+                    .lock()
+                    .unwrap()
+                    .update(id, Some(&data), None);
                 revert.push(RevertOp::TupleDeleted { id, data });
                 Ok(())
             }
@@ -585,77 +889,121 @@ impl MemoryStore {
         }
     }
 
+    /// Ids of all entities that match the selector of a select mutation.
+    ///
+    /// The selector is planned like the filter of a regular select, so
+    /// indexes are used where possible.
+    /// Plans are not cached, since the registry can be a pending migration
+    /// that is still reverted.
+    ///
+    /// Locks all shards, since any entity can match.
+    fn select_ids(
+        &self,
+        shards: &mut ShardsWrite,
+        selector: &Expr,
+        reg: &Registry,
+    ) -> Result<Vec<Id>, anyhow::Error> {
+        shards.lock_all()?;
+        let snapshot = shards.snapshot();
+        let select = Select::new().with_filter(selector.clone());
+        let raw_plan = plan::plan_select(select, reg)?;
+        self.query_counters.record_scan_lookups(&raw_plan, reg);
+        let plan = self.build_query_plan(&snapshot, raw_plan, reg)?;
+        tracing::debug!(query_plan=?plan, "executing select mutation");
+        self.query_counters.record(&plan);
+        let ids = Self::run_query(&snapshot, plan)
+            .filter_map(|tuple| tuple.get_id())
+            .collect();
+        Ok(ids)
+    }
+
+    /// Patch all entities that match the selector.
+    ///
+    /// Patched entities must match the guard afterwards, see
+    /// [`query::mutate::MutateSelect::guard`].
     fn tuple_select_patch(
-        &mut self,
-        expr: &Expr,
+        &self,
+        shards: &mut ShardsWrite,
+        selector: &Expr,
         patch: &Patch,
+        guard: Option<Expr>,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        // TODO: should there be a helper function to plan a scan directly?
-        let select = Select::new().with_filter(expr.clone());
-        let raw_ops = plan::plan_select(select, reg)?;
-        let plan = self.build_query_plan(raw_ops, reg)?;
-        let ids: Vec<Id> = self
-            .run_query(plan)
-            .filter_map(|tuple| tuple.get_id())
-            .collect();
-
+        let ids = self.select_ids(shards, selector, reg)?;
+        let guard = guard
+            .map(|guard| self.build_memory_expr(shards, plan::resolve_expr(guard, reg)?, reg))
+            .transpose()?;
         for id in ids {
-            let mem_entity = self.entities.get(&id).unwrap();
+            let mem_entity = shards.locked(&id).unwrap();
+            let entity_id = mem_entity.get_id().unwrap();
             let entity = self.tuple_to_data_map(mem_entity);
 
             let ops = reg.validate_patch(
                 EntityPatch {
-                    id: mem_entity.get_id().unwrap(),
+                    id: entity_id,
                     patch: patch.clone(),
                 },
                 entity,
+                &|name| self.resolve_ref_ident(shards, name),
             )?;
 
-            self.apply_db_ops(ops, revert, reg)?;
+            self.apply_db_ops(shards, ops, revert, reg)?;
+
+            if let Some(guard) = &guard {
+                let matches = shards
+                    .locked(&entity_id)
+                    .map_or(true, |tuple| Self::entity_filter(tuple, guard));
+                if !matches {
+                    return Err(EntityNotFound::new(entity_id.into()).into());
+                }
+            }
         }
 
         Ok(())
     }
 
     fn tuple_select_remove(
-        &mut self,
-        selector: &MemoryExpr,
+        &self,
+        shards: &mut ShardsWrite,
+        selector: &Expr,
         rem: &backend::TupleRemoveAttrs,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        // TODO: use query logic instead of full table scan for speedup.
-        // TODO: figure out how to do this in one step. Two step process
-        // right now due to borrow checker errors.
-        //
+        // Two step process, the entities can not be modified while the
+        // query borrows them.
         let mut to_remove = Vec::new();
 
-        for (entity_id, entity) in self.entities.iter() {
-            if Self::entity_filter(entity, selector) {
-                let mut removed_attr_ids = Vec::new();
-                for attr_id in &rem.attrs {
-                    let attr = reg.require_attr(*attr_id)?;
-                    if entity.contains_key(&attr.local_id) {
-                        removed_attr_ids.push(attr);
-                    }
+        for entity_id in self.select_ids(shards, selector, reg)? {
+            let entity = shards.locked(&entity_id).unwrap();
+            let mut removed_attr_ids = Vec::new();
+            for attr_id in &rem.attrs {
+                let attr = reg.require_attr(*attr_id)?;
+                if entity.contains_key(&attr.local_id) {
+                    removed_attr_ids.push(attr);
                 }
+            }
 
-                if !removed_attr_ids.is_empty() {
-                    to_remove.push((*entity_id, removed_attr_ids));
-                }
+            if !removed_attr_ids.is_empty() {
+                to_remove.push((entity_id, removed_attr_ids));
             }
         }
 
         for (entity_id, removed_attr_ids) in to_remove {
-            let entity = self.entities.get_mut(&entity_id).unwrap();
+            let entity = shards.locked_mut(&entity_id).get_mut(&entity_id).unwrap();
+            let old_type = entity.get(&ATTR_TYPE_LOCAL).cloned();
 
             // FIXME: need to respect index changes via registry.build_remove!
             let attrs = removed_attr_ids
                 .into_iter()
                 .filter_map(|attr| Some((attr.local_id, entity.remove(&attr.local_id)?)))
                 .collect();
+            self.type_index.lock().unwrap().update_type(
+                entity_id,
+                old_type.as_ref(),
+                entity.get(&ATTR_TYPE_LOCAL),
+            );
 
             revert.push(RevertOp::TupleAttrsRemoved {
                 id: entity_id,
@@ -667,28 +1015,18 @@ impl MemoryStore {
     }
 
     fn tuple_select_delete(
-        &mut self,
-        selector: &MemoryExpr,
+        &self,
+        shards: &mut ShardsWrite,
+        selector: &Expr,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        // TODO: use query logic instead of full table scan for speedup.
-        // TODO: figure out how to do this in one step. Two step process
-        // right now due to borrow checker errors.
-        let mut to_remove = Vec::new();
-
-        for (entity_id, entity) in self.entities.iter() {
-            if Self::entity_filter(entity, selector) {
-                to_remove.push(*entity_id);
-            }
-        }
-
-        for entity_id in to_remove {
-            let mem_entity = self.entities.get(&entity_id).unwrap();
+        for entity_id in self.select_ids(shards, selector, reg)? {
+            let mem_entity = shards.locked(&entity_id).unwrap();
             let data = self.tuple_to_data_map(mem_entity);
 
             let ops = reg.validate_delete(entity_id, data)?;
-            self.apply_db_ops(ops, revert, reg)?;
+            self.apply_db_ops(shards, ops, revert, reg)?;
         }
 
         Ok(())
@@ -698,7 +1036,8 @@ impl MemoryStore {
     /// [RevertOp]s are collected into the provided revert list, which allows
     /// undoing operations.
     fn apply_db_ops(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         ops: Vec<DbOp>,
         revert: &mut RevertList,
         reg: &Registry,
@@ -708,23 +1047,23 @@ impl MemoryStore {
         for op in ops {
             match op {
                 DbOp::Tuple(tuple) => {
-                    let id = self.must_resolve_ident(&tuple.target)?;
+                    let id = self.must_resolve_ident(shards, &tuple.target)?;
 
                     match tuple.action {
                         TupleAction::Create(create) => {
-                            self.tuple_create(id, create, revert, reg)?;
+                            self.tuple_create(shards, id, create, revert, reg)?;
                         }
                         TupleAction::Replace(repl) => {
-                            self.tuple_replace(id, repl, revert, reg)?;
+                            self.tuple_replace(shards, id, repl, revert, reg)?;
                         }
                         TupleAction::Merge(update) => {
-                            self.tuple_merge(id, update, revert, reg)?;
+                            self.tuple_merge(shards, id, update, revert, reg)?;
                         }
                         TupleAction::RemoveAttrs(remove) => {
-                            self.tuple_remove_attrs(id, remove, revert)?;
+                            self.tuple_remove_attrs(shards, id, remove, revert, reg)?;
                         }
                         TupleAction::Delete(del) => {
-                            self.tuple_delete(id, del, revert)?;
+                            self.tuple_delete(shards, id, del, revert, reg)?;
                         }
                         TupleAction::Patch(_patch) => {
                             // will never exist as a real op.
@@ -732,67 +1071,130 @@ impl MemoryStore {
                         }
                     }
                 }
-                DbOp::Select(sel) => match sel.action {
-                    TupleAction::Create(_) => todo!(),
-                    TupleAction::Replace(_) => todo!(),
-                    TupleAction::Merge(_) => todo!(),
-                    TupleAction::RemoveAttrs(remove) => {
-                        let resolved = plan::resolve_expr(sel.selector, reg)?;
-                        let expr = self.build_memory_expr(resolved, reg)?;
-                        self.tuple_select_remove(&expr, &remove, revert, reg)?;
-                    }
-                    TupleAction::Delete(_) => todo!(),
-                    TupleAction::Patch(patch) => {
-                        self.tuple_select_patch(&sel.selector, &patch.patch, revert, reg)?;
+                DbOp::Select(sel) => {
+                    self.flush_deferred_index_updates(shards)?;
+                    match sel.action {
+                        TupleAction::Create(_) => todo!(),
+                        TupleAction::Replace(_) => todo!(),
+                        TupleAction::Merge(_) => todo!(),
+                        TupleAction::RemoveAttrs(remove) => {
+                            self.tuple_select_remove(shards, &sel.selector, &remove, revert, reg)?;
+                        }
+                        TupleAction::Delete(_) => {
+                            self.tuple_select_delete(shards, &sel.selector, revert, reg)?;
+                        }
+                        TupleAction::Patch(patch) => {
+                            self.tuple_select_patch(
+                                shards,
+                                &sel.selector,
+                                &patch.patch,
+                                revert,
+                                reg,
+                            )?;
+                        }
                     }
-                },
+                }
                 DbOp::IndexPopulate(pop) => {
                     let index = reg.require_index_by_id(pop.index_id)?;
-                    self.index_populate(reg, index, revert)?;
+                    if !self.skip_index_update(index.local_id) {
+                        self.index_populate(shards, reg, index, revert)?;
+                    }
                 }
                 DbOp::ValidateEntityExists(val) => {
                     if !self.ignore_index_constraints {
-                        self.must_get_entity(val.id)?;
+                        shards
+                            .get(&val.id)?
+                            .ok_or_else(|| EntityNotFound::new(val.id.into()))?;
                     }
                 }
                 DbOp::ValidateEntityType(val) => {
                     if !self.ignore_index_constraints {
-                        let entity = self.must_get_entity(val.id)?;
-                        let ty = if let Some(ty) = entity.get(&ATTR_TYPE_LOCAL) {
-                            match ty {
-                                MemoryValue::String(s) => {
-                                    let s = s.as_ref();
-
-                                    if let Ok(id) = Id::from_str(s) {
-                                        Some(id)
-                                    } else {
-                                        reg.entity_by_name(s).map(|x| x.schema.id)
-                                    }
-                                }
-                                MemoryValue::Id(id) => Some(*id),
-                                _ => {
-                                    bail!(
-                                "Invalid entity data: reference column contains invalid data type"
-                            );
-                                }
-                            }
-                        } else {
-                            None
-                        };
+                        let entity = shards
+                            .get(&val.id)?
+                            .ok_or_else(|| EntityNotFound::new(val.id.into()))?;
+                        let ty = Self::entity_type_id(entity, reg)?;
 
                         // TODO: provide actual validated entity id in first arg.
                         // Probably need to add it to the db op!
                         reg.validate_entity_type_constraint(Id::nil(), &val, ty)?;
                     }
                 }
+                DbOp::ValidateAcyclic(val) => {
+                    if !self.ignore_index_constraints {
+                        Self::validate_acyclic(&val, reg, |id, attr| {
+                            Ok(shards.get(id)?.and_then(|t| t.get(&attr)).cloned())
+                        })?;
+                    }
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Check that following an acyclic reference attribute from the
+    /// referenced entities does not lead back to the written entity.
+    ///
+    /// `value` loads the value of an attribute of a followed entity.
+    ///
+    /// See [`backend::ValidateAcyclic`].
+    fn validate_acyclic(
+        val: &backend::ValidateAcyclic,
+        reg: &Registry,
+        mut value: impl FnMut(&Id, LocalAttributeId) -> Result<Option<MemoryValue>, LockConflict>,
+    ) -> Result<(), anyhow::Error> {
+        let mut visited = BTreeSet::new();
+        let mut level = val.targets.clone();
+        for _ in 0..val.max_depth {
+            if level.contains(&val.entity) {
+                return Err(ReferenceCycle {
+                    entity: val.entity,
+                    attribute: reg.attr(val.attribute).schema.ident.clone(),
+                }
+                .into());
+            }
+
+            let mut next = Vec::new();
+            for id in level {
+                if !visited.insert(id) {
+                    continue;
+                }
+                if let Some(value) = value(&id, val.attribute)? {
+                    next.extend(Self::referenced_ids(&value));
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            level = next;
+        }
+        Ok(())
+    }
+
+    /// Resolve the class id of a stored entity.
+    fn entity_type_id(entity: &MemoryTuple, reg: &Registry) -> Result<Option<Id>, anyhow::Error> {
+        let ty = match entity.get(&ATTR_TYPE_LOCAL) {
+            Some(MemoryValue::String(s)) => {
+                let s = s.as_ref();
+
+                if let Ok(id) = Id::from_str(s) {
+                    Some(id)
+                } else {
+                    reg.entity_by_name(s).map(|x| x.schema.id)
+                }
+            }
+            Some(MemoryValue::Id(id)) => Some(*id),
+            Some(_) => {
+                bail!("Invalid entity data: reference column contains invalid data type");
+            }
+            None => None,
+        };
+        Ok(ty)
+    }
+
     fn index_populate(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         reg: &Registry,
         index: &RegisteredIndex,
         revert: &mut RevertList,
@@ -808,169 +1210,609 @@ impl MemoryStore {
             bail!("Multi-attribute indexes not supported yet");
         }
         let attr_id = attrs[0];
+        let tenant_attr = reg.require_attr_by_id(builtin::ATTR_TENANT)?.local_id;
 
         // FIXME: prevent accumulating all ops in memory.
-        // Indexes should be behind a separate lock!
+        shards.lock_all()?;
         let mut ops = Vec::new();
-        for (entity_id, data) in &self.entities {
-            if let Some(value) = data.0.get(&attr_id) {
-                let op = TupleIndexOp::Insert(TupleIndexInsert {
-                    index: index.local_id,
-                    value: value.into(),
-                    unique: index.schema.unique,
-                });
-                ops.push((*entity_id, op));
+        for (entity_id, data) in shards.iter() {
+            if index.is_partial() && !index.includes(&self.tuple_to_data_map(data), reg)? {
+                continue;
+            }
+            if let Some(value) = data.0.get(&attr_id).map(Value::from) {
+                if !index.indexes_value(&value) {
+                    continue;
+                }
+                let tenant = data.0.get(&tenant_attr).map(Value::from);
+                let entity_ops = index
+                    .index_keys(value, tenant.as_ref())
+                    .into_iter()
+                    .map(|key| {
+                        TupleIndexOp::Insert(TupleIndexInsert {
+                            index: index.local_id,
+                            attribute: attr_id,
+                            value: key,
+                            unique: index.schema.unique,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                ops.push((*entity_id, entity_ops));
             }
         }
 
-        for (tuple_id, op) in ops {
-            self.apply_tuple_index_op(tuple_id, op, revert, reg)?;
+        for (tuple_id, entity_ops) in ops {
+            self.apply_tuple_index_ops(shards, tuple_id, entity_ops, revert, reg)?;
         }
 
         Ok(())
     }
 
-    fn apply_create(
-        &mut self,
-        create: query::mutate::Create,
-        revert: &mut RevertList,
+    /// Rebuild all indexes from the stored entities.
+    ///
+    /// Index entries are collected and sorted, and each index is built in
+    /// bulk, which is a lot faster than inserting entries one by one.
+    /// Unique constraints are not checked.
+    ///
+    /// Requires all shards and indexes to be held.
+    fn rebuild_indexes(
+        &self,
+        shards: &mut ShardsWrite,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let ops = self.registry.read().unwrap().validate_create(create)?;
-        self.apply_db_ops(ops, revert, reg)?;
-        Ok(())
-    }
+        let tenant_attr = reg.require_attr_by_id(builtin::ATTR_TENANT)?.local_id;
 
-    fn apply_replace(
-        &mut self,
-        repl: query::mutate::Replace,
+        for index in reg.iter_indexes() {
+            // Always kept up to date.
+            if Self::resolves_idents(index.local_id) {
+                continue;
+            }
+
+            let attrs = index
+                .schema
+                .attributes
+                .iter()
+                .map(|id| reg.require_attr_by_id(*id).map(|a| a.local_id))
+                .collect::<Result<Vec<_>, _>>()?;
+            if attrs.len() != 1 {
+                // TODO: Implement multi-attribute indexes
+                bail!("Multi-attribute indexes not supported yet");
+            }
+            let attr_id = attrs[0];
+
+            let mut entries = Vec::new();
+            for (entity_id, data) in shards.iter() {
+                if index.is_partial() && !index.includes(&self.tuple_to_data_map(data), reg)? {
+                    continue;
+                }
+                if let Some(value) = data.0.get(&attr_id).map(Value::from) {
+                    if !index.indexes_value(&value) {
+                        continue;
+                    }
+                    let tenant = data.0.get(&tenant_attr).map(Value::from);
+                    for key in index.index_keys(value, tenant.as_ref()) {
+                        let key = MemoryValue::from_value_standalone(key).index_key();
+                        entries.push((key, *entity_id));
+                    }
+                }
+            }
+
+            shards.locked_index_mut(index.local_id).rebuild(entries);
+        }
+
+        self.indexes_stale.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn apply_create(
+        &self,
+        shards: &mut ShardsWrite,
+        create: query::mutate::Create,
+        revert: &mut RevertList,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        let ops = self
+            .registry
+            .load()
+            .validate_create(create, &|name| self.resolve_ref_ident(shards, name))?;
+        self.apply_db_ops(shards, ops, revert, reg)?;
+        Ok(())
+    }
+
+    /// Apply a run of consecutive creates.
+    ///
+    /// Once entity idents in reference values are resolved, validating a
+    /// create does not depend on the stored data, so large runs are validated
+    /// in parallel. The resulting operations are still applied sequentially,
+    /// in batch order.
+    #[cfg(feature = "parallel")]
+    fn apply_creates(
+        &self,
+        shards: &mut ShardsWrite,
+        mut creates: Vec<query::mutate::Create>,
+        revert: &mut RevertList,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        use rayon::prelude::*;
+
+        // Generated ids must be assigned in batch order.
+        let mut sequential = creates.len() < PARALLEL_VALIDATION_MIN_CREATES
+            || creates.iter().any(|create| create.id.is_nil());
+        // Idents that don't resolve yet may refer to entities created earlier
+        // in the run.
+        if !sequential {
+            for create in &mut creates {
+                sequential |= !reg.resolve_data_idents(&mut create.data, &|name| {
+                    self.resolve_ref_ident(shards, name)
+                })?;
+            }
+        }
+        if sequential {
+            for create in creates {
+                self.apply_create(shards, create, revert, reg)?;
+            }
+            return Ok(());
+        }
+
+        let validated = creates
+            .into_par_iter()
+            .map(|create| reg.validate_create(create, &|_| Ok(None)))
+            .collect::<Vec<_>>();
+        for ops in validated {
+            self.apply_db_ops(shards, ops?, revert, reg)?;
+        }
+        Ok(())
+    }
+
+    fn apply_replace(
+        &self,
+        shards: &mut ShardsWrite,
+        repl: query::mutate::Replace,
         revert: &mut RevertList,
         registry: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let old = self
-            .entities
-            .get(&repl.id)
+        let old = shards
+            .get(&repl.id)?
             .map(|tuple| self.tuple_to_data_map(tuple));
 
-        let ops = self.registry.read().unwrap().validate_replace(repl, old)?;
-        self.apply_db_ops(ops, revert, registry)?;
+        let ops = self
+            .registry
+            .load()
+            .validate_replace(repl, old, &|name| self.resolve_ref_ident(shards, name))?;
+        self.apply_db_ops(shards, ops, revert, registry)?;
         Ok(())
     }
 
     fn apply_merge(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         merge: query::mutate::Merge,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        if let Some(old_tuple) = self.entities.get(&merge.id) {
+        if let Some(old_tuple) = shards.get(&merge.id)? {
             let old = self.tuple_to_data_map(old_tuple);
-            let ops = self.registry.read().unwrap().validate_merge(merge, old)?;
-            self.apply_db_ops(ops, revert, reg)
+            let merge = merge.resolve_clocks(Some(&old));
+            let ops = self
+                .registry
+                .load()
+                .validate_merge(merge, &old, &|name| self.resolve_ref_ident(shards, name))?;
+            self.apply_db_ops(shards, ops, revert, reg)
         } else {
+            let merge = merge.resolve_clocks(None);
             let create = query::mutate::Create {
                 id: merge.id,
                 data: merge.data,
             };
-            self.apply_create(create, revert, reg)
+            self.apply_create(shards, create, revert, reg)
         }
     }
 
     fn apply_patch(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         epatch: query::mutate::EntityPatch,
         revert: &mut RevertList,
         registry: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let current_entity = self.entity(epatch.id.into())?;
+        let tuple = shards
+            .get(&epatch.id)?
+            .ok_or_else(|| EntityNotFound::new(epatch.id.into()))?;
+        let current_entity = Self::tuple_to_read_data_map_with(&self.registry.load(), tuple)?;
 
         let ops = self
             .registry
-            .read()
-            .unwrap()
-            .validate_patch(epatch, current_entity)?;
-        self.apply_db_ops(ops, revert, registry)?;
+            .load()
+            .validate_patch(epatch, current_entity, &|name| {
+                self.resolve_ref_ident(shards, name)
+            })?;
+        self.apply_db_ops(shards, ops, revert, registry)?;
         Ok(())
     }
 
     fn apply_delete(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         delete: query::mutate::Delete,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
-        let old = self
-            .entities
-            .get(&delete.id)
+        let old = shards
+            .get(&delete.id)?
             .ok_or_else(|| EntityNotFound::new(delete.id.into()))
             .map(|t| self.tuple_to_data_map(t))?;
 
         let ops = reg.validate_delete(delete.id, old)?;
-        self.apply_db_ops(ops, revert, reg)?;
+        self.apply_db_ops(shards, ops, revert, reg)?;
         Ok(())
     }
 
     fn apply_mutate_select(
-        &mut self,
+        &self,
+        shards: &mut ShardsWrite,
         sel: query::mutate::MutateSelect,
         revert: &mut RevertList,
         reg: &Registry,
     ) -> Result<(), anyhow::Error> {
+        self.flush_deferred_index_updates(shards)?;
+
         match sel.action {
             query::mutate::MutateSelectAction::Delete => {
-                let resolved = plan::resolve_expr(sel.filter, reg)?;
-                let expr = self.build_memory_expr(resolved, reg)?;
-                self.tuple_select_delete(&expr, revert, reg)?;
+                self.tuple_select_delete(shards, &sel.filter, revert, reg)?;
             }
             query::mutate::MutateSelectAction::Patch(patch) => {
-                self.tuple_select_patch(&sel.filter, &patch, revert, reg)?;
+                self.tuple_select_patch(shards, &sel.filter, &patch, sel.guard, revert, reg)?;
             }
         }
 
         Ok(())
     }
 
+    /// Check that an existing entity matches the filter of a guard.
+    ///
+    /// See [`query::mutate::Guard`].
+    fn apply_guard(
+        &self,
+        shards: &mut ShardsWrite,
+        guard: query::mutate::Guard,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        let filter = self.build_memory_expr(shards, plan::resolve_expr(guard.filter, reg)?, reg)?;
+        let tuple = match shards.get(&guard.id)? {
+            Some(tuple) => tuple,
+            None => return Ok(()),
+        };
+        if Self::entity_filter(tuple, &filter) {
+            Ok(())
+        } else {
+            Err(EntityNotFound::new(guard.id.into()).into())
+        }
+    }
+
     /// Apply a batch of operations.
+    ///
+    /// Returns the audit entries created for the batch, if `audit` is set.
     fn apply_batch_impl(
-        &mut self,
+        &self,
         batch: query::mutate::Batch,
+        audit: Option<&Audit>,
         reg: &Registry,
-    ) -> Result<RevertList, anyhow::Error> {
-        // FIXME: rollback when errors happen.
+    ) -> Result<(RevertList, Vec<query::mutate::Create>), anyhow::Error> {
+        let (revert, mut results) = self.apply_actions(batch, false, audit, reg);
+        // Without savepoints, the whole batch is a single segment.
+        let entries = results.pop().expect("batch has one segment")?;
+        Ok((revert, entries))
+    }
+
+    /// Apply the actions of a batch.
+    ///
+    /// If `savepoints` is true, [`query::mutate::Mutate::Savepoint`] markers
+    /// split the batch into segments, and a failing segment only reverts its
+    /// own changes. Otherwise the markers are ignored, and the first error
+    /// reverts all changes.
+    ///
+    /// If `audit` is set, the changes of each segment are recorded in audit
+    /// entries that are created at the end of the segment.
+    ///
+    /// Returns the changes that were kept, and the result of each segment,
+    /// as returned by [`Batch::into_segments`], with the created audit
+    /// entries.
+    ///
+    /// Small batches first only lock the shards of the entities they touch.
+    /// If that would risk a deadlock, the batch is reverted and applied again
+    /// with all shards locked.
+    fn apply_actions(
+        &self,
+        batch: query::mutate::Batch,
+        savepoints: bool,
+        audit: Option<&Audit>,
+        reg: &Registry,
+    ) -> (RevertList, SegmentResults) {
+        let lock_all = self.defer_index_updates
+            || batch.actions.len() > MAX_SHARDED_BATCH_ACTIONS
+            || batch
+                .actions
+                .iter()
+                .any(|action| matches!(action, query::mutate::Mutate::Select(_)));
+        if !lock_all {
+            let ids = batch
+                .actions
+                .iter()
+                .filter_map(query::mutate::Mutate::entity_id);
+            let mut shards = self.entities.write(&self.indexes, ids);
+            if let Ok(res) =
+                self.apply_actions_with(&mut shards, batch.clone(), savepoints, audit, reg)
+            {
+                return res;
+            }
+        }
+
+        let mut shards = self.entities.write_all(&self.indexes);
+        self.apply_actions_with(&mut shards, batch, savepoints, audit, reg)
+            .expect("no lock conflicts while all shards are held")
+    }
 
+    /// Apply the actions of a batch with the given shards.
+    ///
+    /// Fails with [`LockConflict`] after reverting all changes if a shard
+    /// could not be locked.
+    fn apply_actions_with(
+        &self,
+        shards: &mut ShardsWrite,
+        batch: query::mutate::Batch,
+        savepoints: bool,
+        audit: Option<&Audit>,
+        reg: &Registry,
+    ) -> Result<(RevertList, SegmentResults), LockConflict> {
         let mut revert = Vec::new();
+        let mut results = Vec::new();
+        // Position in the revert list at the last savepoint.
+        let mut savepoint = 0;
+        let mut segment = Ok(());
+        let mut changes = Vec::new();
+
+        let mut actions = batch.actions.into_iter().peekable();
+        while let Some(action) = actions.next() {
+            if savepoints && matches!(action, query::mutate::Mutate::Savepoint) {
+                let segment = std::mem::replace(&mut segment, Ok(()));
+                let changes = std::mem::take(&mut changes);
+                let res = self.finish_segment(
+                    shards,
+                    segment,
+                    changes,
+                    audit,
+                    savepoint,
+                    &mut revert,
+                    reg,
+                );
+                results.push(self.check_conflict(shards, res, &mut revert)?);
+                savepoint = revert.len();
+                continue;
+            }
+            if segment.is_err() {
+                // Skip the remaining actions of a failed segment.
+                continue;
+            }
 
-        for action in batch.actions {
+            let operation = audit.and(audit::action_operation(&action));
+            let action_start = revert.len();
             let res = match action {
+                #[cfg(feature = "parallel")]
                 query::mutate::Mutate::Create(create) => {
-                    self.apply_create(create, &mut revert, reg)
+                    let mut creates = vec![create];
+                    while let Some(query::mutate::Mutate::Create(_)) = actions.peek() {
+                        if let Some(query::mutate::Mutate::Create(create)) = actions.next() {
+                            creates.push(create);
+                        }
+                    }
+                    self.apply_creates(shards, creates, &mut revert, reg)
+                }
+                #[cfg(not(feature = "parallel"))]
+                query::mutate::Mutate::Create(create) => {
+                    self.apply_create(shards, create, &mut revert, reg)
+                }
+                query::mutate::Mutate::Replace(repl) => {
+                    self.apply_replace(shards, repl, &mut revert, reg)
+                }
+                query::mutate::Mutate::Merge(merge) => {
+                    self.apply_merge(shards, merge, &mut revert, reg)
+                }
+                query::mutate::Mutate::Delete(del) => {
+                    self.apply_delete(shards, del, &mut revert, reg)
+                }
+                query::mutate::Mutate::Patch(patch) => {
+                    self.apply_patch(shards, patch, &mut revert, reg)
                 }
-                query::mutate::Mutate::Replace(repl) => self.apply_replace(repl, &mut revert, reg),
-                query::mutate::Mutate::Merge(merge) => self.apply_merge(merge, &mut revert, reg),
-                query::mutate::Mutate::Delete(del) => self.apply_delete(del, &mut revert, reg),
-                query::mutate::Mutate::Patch(patch) => self.apply_patch(patch, &mut revert, reg),
                 query::mutate::Mutate::Select(sel) => {
-                    self.apply_mutate_select(sel, &mut revert, reg)
+                    self.apply_mutate_select(shards, sel, &mut revert, reg)
                 }
+                query::mutate::Mutate::Savepoint => Ok(()),
+                query::mutate::Mutate::Guard(guard) => self.apply_guard(shards, guard, reg),
             };
 
-            if let Err(err) = res {
-                // An error happened, so revert changes before returning.
-                self.apply_revert(revert);
-                return Err(err);
+            match res {
+                Ok(()) => {
+                    if let Some(operation) = operation {
+                        self.collect_changes(
+                            shards,
+                            operation,
+                            &revert[action_start..],
+                            reg,
+                            &mut changes,
+                        );
+                    }
+                }
+                Err(err) => {
+                    // An error happened, so revert the changes since the last
+                    // savepoint.
+                    let segment_changes = revert.split_off(savepoint);
+                    self.apply_revert(shards, segment_changes);
+                    segment = self.check_conflict(shards, Err(err), &mut revert)?;
+                    if !savepoints {
+                        break;
+                    }
+                }
+            }
+        }
+        let res = self.finish_segment(shards, segment, changes, audit, savepoint, &mut revert, reg);
+        results.push(self.check_conflict(shards, res, &mut revert)?);
+
+        self.update_views(shards, revert.iter().filter_map(RevertOp::entity_id));
+        self.invalidate_results(shards, &revert, reg);
+        self.invalidate_idents(&revert);
+
+        Ok((revert, results))
+    }
+
+    /// Revert all remaining changes if `res` failed with a [`LockConflict`].
+    fn check_conflict<T>(
+        &self,
+        shards: &mut ShardsWrite,
+        res: Result<T, anyhow::Error>,
+        revert: &mut RevertList,
+    ) -> Result<Result<T, anyhow::Error>, LockConflict> {
+        match res {
+            Err(err) if err.is::<LockConflict>() => {
+                self.apply_revert(shards, std::mem::take(revert));
+                Err(LockConflict)
+            }
+            res => Ok(res),
+        }
+    }
+
+    /// Create the audit entries of a segment.
+    ///
+    /// Reverts the segment if the entries can not be created.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_segment(
+        &self,
+        shards: &mut ShardsWrite,
+        segment: Result<(), anyhow::Error>,
+        changes: Vec<audit::Change>,
+        audit: Option<&Audit>,
+        savepoint: usize,
+        revert: &mut RevertList,
+        reg: &Registry,
+    ) -> Result<Vec<query::mutate::Create>, anyhow::Error> {
+        segment?;
+        let audit = match audit {
+            Some(audit) => audit,
+            None => return Ok(Vec::new()),
+        };
+
+        let res = audit::audit_entries(reg, &changes, audit).and_then(|entries| {
+            for entry in &entries {
+                self.apply_create(shards, entry.clone(), revert, reg)?;
             }
+            Ok(entries)
+        });
+        if res.is_err() {
+            let segment_changes = revert.split_off(savepoint);
+            self.apply_revert(shards, segment_changes);
         }
+        res
+    }
 
-        Ok(revert)
+    /// Record the changes of a single action for the audit log.
+    ///
+    /// `action_changes` are the revert operations of the action, which hold
+    /// the previous values. Entities are recorded in the order of their first
+    /// change.
+    fn collect_changes(
+        &self,
+        shards: &ShardsWrite,
+        operation: Operation,
+        action_changes: &[RevertOp],
+        reg: &Registry,
+        changes: &mut Vec<audit::Change>,
+    ) {
+        let mut old = fnv::FnvHashMap::<Id, Option<MemoryTuple>>::default();
+        let mut ids = Vec::new();
+        // Undo the operations on copies of the current data, in reverse
+        // order, like [Self::apply_revert].
+        for op in action_changes.iter().rev() {
+            let id = match op.entity_id() {
+                Some(id) => id,
+                None => continue,
+            };
+            let state = old.entry(id).or_insert_with(|| {
+                ids.push(id);
+                shards.locked(&id).cloned()
+            });
+            match op {
+                RevertOp::TupleCreated { .. } => *state = None,
+                RevertOp::TupleReplaced { data, .. } => *state = data.clone(),
+                RevertOp::TupleMerged { replaced_data, .. } => {
+                    if let Some(tuple) = state {
+                        for (attr_id, value) in replaced_data {
+                            match value {
+                                Some(value) => tuple.insert(*attr_id, value.clone()),
+                                None => tuple.remove(attr_id),
+                            };
+                        }
+                    }
+                }
+                RevertOp::TupleAttrsRemoved { attrs, .. } => {
+                    if let Some(tuple) = state {
+                        for (attr_id, value) in attrs {
+                            tuple.insert(*attr_id, value.clone());
+                        }
+                    }
+                }
+                RevertOp::TupleDeleted { data, .. } => *state = Some(data.clone()),
+                RevertOp::IndexValueInserted { .. } | RevertOp::IndexValueRemoved { .. } => {}
+            }
+        }
+
+        // The ids were collected in reverse.
+        for id in ids.into_iter().rev() {
+            let old = old
+                .remove(&id)
+                .flatten()
+                .map(|tuple| Self::tuple_to_data_map_with(reg, &tuple));
+            let new = shards
+                .locked(&id)
+                .map(|tuple| Self::tuple_to_data_map_with(reg, tuple));
+            changes.push(audit::Change {
+                entity: id,
+                operation,
+                old,
+                new,
+            });
+        }
     }
 
-    pub fn apply_batch(&mut self, batch: Batch) -> Result<(), anyhow::Error> {
-        let shared_reg = self.registry().clone();
-        let reg = shared_reg.read().unwrap();
-        self.apply_batch_impl(batch, &reg)?;
+    pub fn apply_batch(&self, batch: Batch) -> Result<(), anyhow::Error> {
+        let reg = self.registry.load_full();
+        self.apply_batch_impl(batch, None, &reg)?;
         Ok(())
     }
 
+    /// Apply a batch and create an audit entry for every change.
+    ///
+    /// See [`backend::Backend::apply_batch_audited`].
+    pub fn apply_batch_audited(&self, batch: Batch, audit: &Audit) -> Result<(), anyhow::Error> {
+        let reg = self.registry.load_full();
+        self.apply_batch_impl(batch, Some(audit), &reg)?;
+        Ok(())
+    }
+
+    /// Apply a batch with partial rollback at savepoints.
+    ///
+    /// Returns the result of each segment, as returned by
+    /// [`Batch::into_segments`].
+    pub fn apply_batch_with_savepoints(
+        &self,
+        batch: Batch,
+        audit: Option<&Audit>,
+    ) -> Vec<Result<(), anyhow::Error>> {
+        let reg = self.registry.load_full();
+        self.apply_actions(batch, true, audit, &reg)
+            .1
+            .into_iter()
+            .map(|res| res.map(|_| ()))
+            .collect()
+    }
+
     fn persist_revert_epoch(&mut self, revert: RevertList) -> RevertEpoch {
         self.revert_epoch = self.revert_epoch.wrapping_add(1);
         let epoch = self.revert_epoch;
@@ -983,32 +1825,90 @@ impl MemoryStore {
     /// The returned [RevertEpoch] can be passed to [Self::revert_changes] to
     /// apply the revert.
     pub fn apply_batch_revertable(&mut self, batch: Batch) -> Result<RevertEpoch, anyhow::Error> {
-        let shared_reg = self.registry().clone();
-        let reg = shared_reg.read().unwrap();
-        let ops = self.apply_batch_impl(batch, &reg)?;
+        let reg = self.registry.load_full();
+        let (ops, _) = self.apply_batch_impl(batch, None, &reg)?;
         let epoch = self.persist_revert_epoch(ops);
         Ok(epoch)
     }
 
+    /// Like [Self::apply_batch_revertable], but also creates an audit entry
+    /// for every change.
+    ///
+    /// Returns the created audit entries.
+    pub fn apply_batch_audited_revertable(
+        &mut self,
+        batch: Batch,
+        audit: &Audit,
+    ) -> Result<(RevertEpoch, Vec<query::mutate::Create>), anyhow::Error> {
+        let reg = self.registry.load_full();
+        let (ops, entries) = self.apply_batch_impl(batch, Some(audit), &reg)?;
+        let epoch = self.persist_revert_epoch(ops);
+        Ok((epoch, entries))
+    }
+
+    /// Apply a batch with partial rollback at savepoints, and retain a revert
+    /// list for the segments that were applied.
+    ///
+    /// Returns the result of each segment with the created audit entries.
+    ///
+    /// See [Self::apply_batch_with_savepoints] and
+    /// [Self::apply_batch_revertable].
+    pub fn apply_batch_with_savepoints_revertable(
+        &mut self,
+        batch: Batch,
+        audit: Option<&Audit>,
+    ) -> (RevertEpoch, SegmentResults) {
+        let reg = self.registry.load_full();
+        let (ops, results) = self.apply_actions(batch, true, audit, &reg);
+        let epoch = self.persist_revert_epoch(ops);
+        (epoch, results)
+    }
+
     /// Revert a list of changes.
-    fn apply_revert(&mut self, revert: RevertList) {
+    ///
+    /// The shards of all changed entities and all changed indexes must be
+    /// held.
+    fn apply_revert(&self, shards: &mut ShardsWrite, revert: RevertList) {
+        // Skipped index updates are not part of the revert list, so indexes
+        // must be rebuilt from the reverted data.
+        if self.defer_index_updates {
+            self.indexes_stale.store(true, Ordering::Relaxed);
+        }
+
+        let changed = if self.views.lock().unwrap().is_empty() {
+            Vec::new()
+        } else {
+            revert.iter().filter_map(RevertOp::entity_id).collect()
+        };
+        let reg = self.registry.load_full();
+        self.invalidate_results(shards, &revert, &reg);
+        self.invalidate_idents(&revert);
+
         // NOTE: MUST revert in reverse order to preserve consistency.
         for op in revert.into_iter().rev() {
             match op {
                 RevertOp::TupleCreated { id } => {
-                    self.entities.remove(&id);
+                    let new = shards.locked_mut(&id).remove(&id);
+                    self.type_index
+                        .lock()
+                        .unwrap()
+                        .update(id, new.as_ref(), None);
                 }
                 RevertOp::TupleReplaced { id, data } => {
-                    if let Some(old) = data {
-                        self.entities.insert(id, old);
+                    let mut type_index = self.type_index.lock().unwrap();
+                    let new = if let Some(old) = data {
+                        type_index.update(id, None, Some(&old));
+                        shards.locked_mut(&id).insert(id, old)
                     } else {
-                        self.entities.remove(&id);
-                    }
+                        shards.locked_mut(&id).remove(&id)
+                    };
+                    type_index.update(id, new.as_ref(), None);
                 }
                 RevertOp::TupleMerged { id, replaced_data } => {
-                    let data = self.entities.get_mut(&id).expect(
+                    let data = shards.locked_mut(&id).get_mut(&id).expect(
                         "Consistency error: can't revert change because tuple was not found",
                     );
+                    let new_type = data.get(&ATTR_TYPE_LOCAL).cloned();
 
                     for (attr_id, value_opt) in replaced_data {
                         if let Some(value) = value_opt {
@@ -1017,41 +1917,153 @@ impl MemoryStore {
                             data.remove(&attr_id);
                         }
                     }
+                    self.type_index.lock().unwrap().update_type(
+                        id,
+                        new_type.as_ref(),
+                        data.get(&ATTR_TYPE_LOCAL),
+                    );
                 }
                 RevertOp::TupleAttrsRemoved { id, attrs } => {
-                    let data = self.entities.get_mut(&id).expect(
+                    let data = shards.locked_mut(&id).get_mut(&id).expect(
                         "Consistency error: can't revert change because tuple was not found",
                     );
+                    let new_type = data.get(&ATTR_TYPE_LOCAL).cloned();
                     for (attr_id, value) in attrs {
                         data.insert(attr_id, value);
                     }
+                    self.type_index.lock().unwrap().update_type(
+                        id,
+                        new_type.as_ref(),
+                        data.get(&ATTR_TYPE_LOCAL),
+                    );
                 }
                 RevertOp::TupleDeleted { id, data } => {
-                    self.entities.insert(id, data);
+                    self.type_index
+                        .lock()
+                        .unwrap()
+                        .update(id, None, Some(&data));
+                    shards.locked_mut(&id).insert(id, data);
                 }
                 RevertOp::IndexValueInserted {
                     index,
                     entity_id,
                     value,
                 } => {
-                    match self.indexes.get_mut(index) {
+                    let data = shards.locked_index_mut(index);
+                    match data {
                         super::index::Index::Unique(idx) => idx.remove(&value),
-                        super::index::Index::Multi(idx) => idx.remove(&value, entity_id),
+                        super::index::Index::Multi(idx) | super::index::Index::Geo(idx) => {
+                            idx.remove(&value, entity_id)
+                        }
                     };
                 }
                 RevertOp::IndexValueRemoved {
                     index,
                     entity_id,
                     value,
-                } => match self.indexes.get_mut(index) {
-                    super::index::Index::Unique(idx) => idx
-                        .insert_unique(value, entity_id)
-                        .map_err(|_| ())
-                        .expect("Consistentcy error"),
-                    super::index::Index::Multi(idx) => idx.add(value, entity_id),
-                },
+                } => {
+                    let data = shards.locked_index_mut(index);
+                    match data {
+                        super::index::Index::Unique(idx) => idx
+                            .insert_unique(&value, entity_id)
+                            .map_err(|_| ())
+                            .expect("Consistency error: can't revert change because the unique value is taken"),
+                        super::index::Index::Multi(idx) | super::index::Index::Geo(idx) => {
+                            idx.add(&value, entity_id)
+                        }
+                    }
+                }
             }
         }
+
+        self.update_views(shards, changed);
+    }
+
+    /// Update the materialized views for changed entities.
+    fn update_views(&self, shards: &ShardsWrite, ids: impl IntoIterator<Item = Id>) {
+        let mut views = self.views.lock().unwrap();
+        if views.is_empty() {
+            return;
+        }
+        for id in ids {
+            views.update(id, shards.locked(&id));
+        }
+    }
+
+    /// Drop cached select results that may be affected by the given changes.
+    ///
+    /// Must be called while the shards contain the data after the changes.
+    fn invalidate_results(&self, shards: &ShardsWrite, changes: &[RevertOp], reg: &Registry) {
+        let mut cache = self.result_cache.lock().unwrap();
+        if cache.is_empty() {
+            return;
+        }
+
+        let mut touched = TouchedSet::default();
+        for op in changes {
+            let id = match op.entity_id() {
+                Some(id) => id,
+                None => continue,
+            };
+            let current = shards.locked(&id);
+            let current_type = current.and_then(|tuple| tuple.get(&ATTR_TYPE_LOCAL));
+            match op {
+                RevertOp::TupleReplaced {
+                    data: Some(old), ..
+                }
+                | RevertOp::TupleDeleted { data: old, .. } => {
+                    touched.add_tuple(old, reg);
+                }
+                RevertOp::TupleMerged { replaced_data, .. } => {
+                    for (attr, old) in replaced_data {
+                        let old_type = if *attr == ATTR_TYPE_LOCAL {
+                            old.as_ref()
+                        } else {
+                            current_type
+                        };
+                        touched.add_attribute(*attr, old_type, reg);
+                        touched.add_attribute(*attr, current_type, reg);
+                    }
+                    continue;
+                }
+                RevertOp::TupleAttrsRemoved { attrs, .. } => {
+                    for (attr, old) in attrs {
+                        let old_type = if *attr == ATTR_TYPE_LOCAL {
+                            Some(old)
+                        } else {
+                            current_type
+                        };
+                        touched.add_attribute(*attr, old_type, reg);
+                        touched.add_attribute(*attr, current_type, reg);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(tuple) = current {
+                touched.add_tuple(tuple, reg);
+            }
+        }
+        cache.invalidate(&touched);
+    }
+
+    /// Materialize all views of the registry from scratch.
+    fn rebuild_views(&self, snapshot: &Snapshot, reg: &Registry) -> Result<(), anyhow::Error> {
+        let mut views = MaterializedViews::default();
+        for view in reg.iter_views() {
+            let filter = view
+                .query
+                .filter
+                .clone()
+                .map(|filter| {
+                    self.build_memory_expr_in(snapshot, plan::resolve_expr(filter, reg)?, reg)
+                })
+                .transpose()
+                .with_context(|| format!("Invalid filter for view '{}'", view.ident))?;
+            views.insert(view.ident.clone(), filter, snapshot.iter());
+        }
+        *self.views.lock().unwrap() = views;
+        Ok(())
     }
 
     /// Revert the last change to the database.
@@ -1067,7 +2079,7 @@ impl MemoryStore {
                         "Invalid revert epoch - epoch does not match last change"
                     ))
                 } else {
-                    self.apply_revert(ops);
+                    self.apply_revert(&mut self.entities.write_all(&self.indexes), ops);
                     Ok(())
                 }
             }
@@ -1079,7 +2091,7 @@ impl MemoryStore {
         mig: Migration,
         is_internal: bool,
     ) -> Result<RevertList, anyhow::Error> {
-        let mut reg = self.registry.read().unwrap().clone();
+        let mut reg = Registry::clone(&self.registry.load());
         let (mig, ops) = crate::schema_builder::build_migration(&mut reg, mig, is_internal)?;
 
         let mut revert = Vec::new();
@@ -1108,6 +2120,14 @@ impl MemoryStore {
                         .context(format!("Registry does not contain index '{}'", del.name,))?;
                     self.index_delete(index)?;
                 }
+                query::migrate::SchemaAction::PolicyCreate(_) => {}
+                query::migrate::SchemaAction::PolicyDelete(_) => {}
+                query::migrate::SchemaAction::ViewCreate(_) => {}
+                query::migrate::SchemaAction::ViewDelete(_) => {}
+                query::migrate::SchemaAction::RuleCreate(_) => {}
+                query::migrate::SchemaAction::RuleDelete(_) => {}
+                query::migrate::SchemaAction::MixinCreate(_) => {}
+                query::migrate::SchemaAction::MixinDelete(_) => {}
                 query::migrate::SchemaAction::AttributeChangeType(action) => {
                     // FIXME: this should be done via an OP created by the schema builder.
                     let attr = reg.require_attr_by_name(&action.attribute)?;
@@ -1116,11 +2136,21 @@ impl MemoryStore {
             }
         }
 
-        if let Err(err) = self.apply_db_ops(ops, &mut revert, &reg) {
-            self.apply_revert(revert);
+        // Views are materialized again, since migrations can change both the
+        // view queries and the stored data.
+        let mut shards = self.entities.write_all(&self.indexes);
+        let res = self
+            .apply_db_ops(&mut shards, ops, &mut revert, &reg)
+            .and_then(|_| self.rebuild_views(&shards.snapshot(), &reg));
+        if let Err(err) = res {
+            self.apply_revert(&mut shards, revert);
             Err(err)
         } else {
-            *self.registry.write().unwrap() = reg;
+            self.invalidate_idents(&revert);
+            drop(shards);
+            self.registry.store(std::sync::Arc::new(reg));
+            self.plan_cache.get_mut().unwrap().clear();
+            self.result_cache.get_mut().unwrap().clear();
             Ok(revert)
         }
     }
@@ -1138,16 +2168,257 @@ impl MemoryStore {
     }
 
     pub fn entity(&self, id: IdOrIdent) -> Result<DataMap, anyhow::Error> {
-        self.must_resolve_entity(&id)
-            .map_err(anyhow::Error::from)
-            .map(|tuple| self.tuple_to_data_map(tuple))
+        self.read_entity(&id, &self.registry.load())?
+            .ok_or_else(|| EntityNotFound::new(id).into())
     }
 
     pub fn entity_opt(&self, id: IdOrIdent) -> Result<Option<DataMap>, anyhow::Error> {
-        let opt = self
-            .resolve_entity(&id)
-            .map(|tuple| self.tuple_to_data_map(tuple));
-        Ok(opt)
+        self.read_entity(&id, &self.registry.load())
+    }
+
+    /// Load multiple entities.
+    /// Missing entities are mapped to `None`.
+    pub fn entities(
+        &self,
+        ids: Vec<IdOrIdent>,
+    ) -> Result<HashMap<IdOrIdent, Option<DataMap>>, anyhow::Error> {
+        let reg = self.registry.load();
+        ids.into_iter()
+            .map(|id| {
+                let data = self.read_entity(&id, &reg)?;
+                Ok((id, data))
+            })
+            .collect()
+    }
+
+    /// Collect statistics about the stored entities and indexes.
+    ///
+    /// Memory usage is a rough estimate that ignores allocator and hash map
+    /// overhead.
+    pub fn stats(&self) -> BackendStats {
+        let reg = self.registry.load();
+        let snapshot = self.entities.snapshot(&self.indexes);
+
+        let entities_by_class = self.type_index.lock().unwrap().by_class(&reg);
+        let mut entity_memory = 0;
+        for tuple in snapshot.values() {
+            entity_memory += std::mem::size_of::<Id>() + tuple.estimated_size();
+        }
+
+        let indexes = reg
+            .iter_indexes()
+            .filter(|index| !index.is_deleted)
+            .map(|index| {
+                let data = snapshot.index(index.local_id);
+                IndexStats {
+                    ident: index.schema.ident.clone(),
+                    unique: index.schema.unique,
+                    entries: data.len() as u64,
+                    memory_usage: data.estimated_size() as u64,
+                    usage: self.query_counters.index_usage(index.local_id),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let memory_usage = entity_memory as u64
+            + indexes.iter().map(|i| i.memory_usage).sum::<u64>()
+            + self.interner.estimated_size() as u64;
+
+        BackendStats {
+            entity_count: snapshot.len() as u64,
+            entities_by_class,
+            indexes,
+            memory_usage: Some(memory_usage),
+            size_log: None,
+            size_data: None,
+            queries: self.query_counters.stats(&reg),
+        }
+    }
+
+    /// Live entity counts of all registered classes, sorted by class ident.
+    pub fn class_stats(&self) -> Vec<ClassStats> {
+        let reg = self.registry.load();
+        let type_index = self.type_index.lock().unwrap();
+        let mut stats = reg
+            .iter_entities()
+            .filter(|class| !class.is_deleted)
+            .map(|class| ClassStats {
+                class: class.schema.ident.clone(),
+                entities: type_index.count(class),
+                max_entities: class.schema.max_entities,
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.class.cmp(&b.class));
+        stats
+    }
+
+    /// Check the indexes and entities for inconsistencies.
+    ///
+    /// See [`backend::Backend::verify`].
+    pub fn verify(&self) -> Vec<VerifyIssue> {
+        let reg = self.registry.load();
+        let snapshot = self.entities.snapshot(&self.indexes);
+        let mut issues = self.verify_indexes(&snapshot, &reg);
+        issues.extend(self.verify_entities(&snapshot, &reg));
+        issues
+    }
+
+    /// Check the internal consistency of the indexes.
+    ///
+    /// Verifies that every index entry points to an existing entity with the
+    /// indexed value, that every indexed value has an entry and that values
+    /// of unique indexes, like idents, are not shared by multiple entities.
+    ///
+    /// Unlike [`Self::verify`], entities are not validated against the
+    /// schema, which makes this cheap enough to run after every test.
+    pub fn check_consistency(&self) -> Vec<VerifyIssue> {
+        let reg = self.registry.load();
+        self.verify_indexes(&self.entities.snapshot(&self.indexes), &reg)
+    }
+
+    fn verify_indexes(&self, snapshot: &Snapshot, reg: &Registry) -> Vec<VerifyIssue> {
+        let mut issues = Vec::new();
+        let tenant_attr = reg
+            .require_attr_by_id(builtin::ATTR_TENANT)
+            .map(|attr| attr.local_id)
+            .ok();
+
+        for index in reg.iter_indexes().filter(|index| !index.is_deleted) {
+            let attr_id = match index.schema.attributes.as_slice() {
+                [attr] => match reg.require_attr_by_id(*attr) {
+                    Ok(attr) => attr.local_id,
+                    Err(_) => continue,
+                },
+                // TODO: verify multi-attribute indexes once they are implemented.
+                _ => continue,
+            };
+
+            let expected = snapshot
+                .iter()
+                .filter_map(|(id, tuple)| {
+                    let value = tuple.get(&attr_id).map(Value::from)?;
+                    if !index.indexes_value(&value) {
+                        return None;
+                    }
+                    if index.is_partial()
+                        && !index.includes(&self.tuple_to_data_map(tuple), reg).ok()?
+                    {
+                        return None;
+                    }
+                    let tenant = tenant_attr
+                        .filter(|_| index.schema.tenant_scoped)
+                        .and_then(|attr| tuple.get(&attr))
+                        .map(Value::from);
+                    let keys = index.index_keys(value, tenant.as_ref());
+                    Some(
+                        keys.into_iter()
+                            .map(move |key| (Cow::Owned(IndexKey::from_value(&key)), *id)),
+                    )
+                })
+                .flatten()
+                .collect::<BTreeSet<(Cow<IndexKey>, Id)>>();
+            let actual = snapshot
+                .index(index.local_id)
+                .iter_entries()
+                .map(|(key, id)| (Cow::Borrowed(key), id))
+                .collect::<BTreeSet<_>>();
+
+            if index.schema.unique {
+                let mut by_key = BTreeMap::<&IndexKey, Vec<Id>>::new();
+                for (key, entity) in &expected {
+                    by_key.entry(key.as_ref()).or_default().push(*entity);
+                }
+                for (key, entities) in by_key {
+                    if entities.len() > 1 {
+                        issues.push(VerifyIssue::DuplicateUniqueValue {
+                            index: index.schema.ident.clone(),
+                            value: key.decode().unwrap_or(Value::Unit),
+                            entities,
+                        });
+                    }
+                }
+            }
+
+            for (key, entity) in expected.difference(&actual) {
+                issues.push(VerifyIssue::IndexEntryMissing {
+                    index: index.schema.ident.clone(),
+                    entity: *entity,
+                    value: key.decode().unwrap_or(Value::Unit),
+                });
+            }
+            for (key, entity) in actual.difference(&expected) {
+                let index = index.schema.ident.clone();
+                let entity = *entity;
+                let value = key.decode().unwrap_or(Value::Unit);
+                issues.push(if snapshot.contains_key(&entity) {
+                    VerifyIssue::IndexEntryStale {
+                        index,
+                        entity,
+                        value,
+                    }
+                } else {
+                    VerifyIssue::IndexEntryDangling {
+                        index,
+                        entity,
+                        value,
+                    }
+                });
+            }
+        }
+
+        issues
+    }
+
+    fn verify_entities(&self, snapshot: &Snapshot, reg: &Registry) -> Vec<VerifyIssue> {
+        let mut entities = snapshot.iter().collect::<Vec<_>>();
+        entities.sort_by_key(|(id, _)| **id);
+
+        entities
+            .into_iter()
+            .filter_map(|(id, tuple)| {
+                let data = Self::tuple_to_data_map_with(reg, tuple);
+                let res = reg
+                    .validate_entity(data)
+                    .and_then(|ops| Self::verify_references(snapshot, *id, ops, reg));
+                res.err().map(|err| VerifyIssue::InvalidEntity {
+                    entity: *id,
+                    error: format!("{:#}", err),
+                })
+            })
+            .collect()
+    }
+
+    /// Check the reference validations produced by
+    /// [`Registry::validate_entity`].
+    fn verify_references(
+        snapshot: &Snapshot,
+        entity_id: Id,
+        ops: Vec<DbOp>,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        for op in ops {
+            match op {
+                DbOp::ValidateEntityExists(val) => {
+                    snapshot
+                        .get(&val.id)
+                        .ok_or_else(|| EntityNotFound::new(val.id.into()))?;
+                }
+                DbOp::ValidateEntityType(val) => {
+                    let target = snapshot
+                        .get(&val.id)
+                        .ok_or_else(|| EntityNotFound::new(val.id.into()))?;
+                    let ty = Self::entity_type_id(target, reg)?;
+                    reg.validate_entity_type_constraint(entity_id, &val, ty)?;
+                }
+                DbOp::ValidateAcyclic(val) => {
+                    Self::validate_acyclic(&val, reg, |id, attr| {
+                        Ok(snapshot.get(id).and_then(|t| t.get(&attr)).cloned())
+                    })?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     fn apply_sort<'a>(items: &mut [Cow<'a, MemoryTuple>], sorts: &[Sort<MemoryExpr>]) {
@@ -1194,41 +2465,49 @@ impl MemoryStore {
         }
     }
 
-    fn run_query(&self, op: plan::QueryPlan<MemoryValue, MemoryExpr>) -> TupleIter<'_> {
+    fn run_query<'a>(
+        snapshot: &'a Snapshot,
+        op: plan::QueryPlan<MemoryValue, MemoryExpr>,
+    ) -> TupleIter<'a> {
         match op {
             QueryPlan::EmptyRelation => Box::new(Vec::new().into_iter()),
             QueryPlan::SelectEntity { id } => {
-                if let Some(entity) = self.entities.get(&id) {
+                if let Some(entity) = snapshot.get(&id) {
                     Box::new(vec![Cow::Borrowed(entity)].into_iter())
                 } else {
                     Box::new(Vec::new().into_iter())
                 }
             }
+            QueryPlan::SelectEntities { ids } => {
+                let out = ids
+                    .into_iter()
+                    .filter_map(move |id| snapshot.get(&id).map(Cow::Borrowed));
+                Box::new(out)
+            }
             QueryPlan::Scan { filter } => {
                 if let Some(filter) = filter {
-                    let out = self
-                        .entities
+                    let out = snapshot
                         .values()
                         .map(Cow::Borrowed)
                         .filter(move |tuple| Self::entity_filter(tuple, &filter));
                     Box::new(out)
                 } else {
-                    Box::new(self.entities.values().map(Cow::Borrowed))
+                    Box::new(snapshot.values().map(Cow::Borrowed))
                 }
             }
             QueryPlan::Filter { expr, input } => {
-                let input = self.run_query(*input);
+                let input = Self::run_query(snapshot, *input);
                 let out = input.filter(move |tuple| Self::entity_filter(tuple, &expr));
                 Box::new(out)
             }
             QueryPlan::Limit { limit, input } => {
-                let input = self.run_query(*input);
+                let input = Self::run_query(snapshot, *input);
                 let out = input.take(limit.try_into().unwrap_or(usize::MAX));
                 Box::new(out)
             }
             QueryPlan::Merge { left, right } => {
-                let left = self.run_query(*left);
-                let right = self.run_query(*right);
+                let left = Self::run_query(snapshot, *left);
+                let right = Self::run_query(snapshot, *right);
                 let out = left.chain(right);
                 Box::new(out)
             }
@@ -1238,12 +2517,14 @@ impl MemoryStore {
                 until,
                 direction,
             } => {
-                let iter = match self.indexes.get(index) {
+                let iter = match snapshot.index(index) {
                     index::Index::Unique(index) => index.range(from, until, direction),
-                    index::Index::Multi(index) => index.range(from, until, direction),
+                    index::Index::Multi(index) | index::Index::Geo(index) => {
+                        index.range(from, until, direction)
+                    }
                 };
 
-                let out = iter.filter_map(|id| self.entities.get(&id).map(Cow::Borrowed));
+                let out = iter.filter_map(move |id| snapshot.get(&id).map(Cow::Borrowed));
                 Box::new(out)
             }
             QueryPlan::IndexScanPrefix {
@@ -1251,40 +2532,42 @@ impl MemoryStore {
                 prefix,
                 direction,
             } => {
-                let iter = match self.indexes.get(index) {
+                let iter = match snapshot.index(index) {
                     index::Index::Unique(index) => index.range_prefix(prefix, direction),
-                    index::Index::Multi(index) => index.range_prefix(prefix, direction),
+                    index::Index::Multi(index) | index::Index::Geo(index) => {
+                        index.range_prefix(prefix, direction)
+                    }
                 };
 
-                let out = iter.filter_map(|id| self.entities.get(&id).map(Cow::Borrowed));
+                let out = iter.filter_map(move |id| snapshot.get(&id).map(Cow::Borrowed));
                 Box::new(out)
             }
             QueryPlan::Sort { sorts, input } => {
-                let input = self.run_query(*input);
+                let input = Self::run_query(snapshot, *input);
                 let mut items: Vec<_> = input.collect();
                 Self::apply_sort(&mut items, &sorts);
                 Box::new(items.into_iter())
             }
             QueryPlan::Skip { count, input } => {
-                let input = self.run_query(*input);
+                let input = Self::run_query(snapshot, *input);
                 let out = input.skip(count as usize);
                 Box::new(out)
             }
-            QueryPlan::IndexSelect { index, value } => match self.indexes.get(index) {
+            QueryPlan::IndexSelect { index, value } => match snapshot.index(index) {
                 index::Index::Unique(index) => {
                     let out = index
                         .get(&value)
-                        .and_then(|id| self.entities.get(&id))
+                        .and_then(|id| snapshot.get(&id))
                         .map(Cow::Borrowed)
                         .into_iter();
                     Box::new(out)
                 }
-                index::Index::Multi(index) => {
+                index::Index::Multi(index) | index::Index::Geo(index) => {
                     let out = index
                         .get(&value)
                         .into_iter()
                         .flatten()
-                        .filter_map(|id| self.entities.get(id))
+                        .filter_map(move |id| snapshot.get(id))
                         .map(Cow::Borrowed);
                     Box::new(out)
                 }
@@ -1293,7 +2576,7 @@ impl MemoryStore {
                 aggregations,
                 input,
             } => {
-                let input = self.run_query(*input);
+                let input = Self::run_query(snapshot, *input);
 
                 if aggregations.len() == 1 && aggregations[0].op == AggregationOp::Count {
                     let count: u64 = input.count().try_into().unwrap();
@@ -1317,20 +2600,43 @@ impl MemoryStore {
 
     fn build_query_plan(
         &self,
+        snapshot: &Snapshot,
         plan: QueryPlan<Value, ResolvedExpr>,
         reg: &Registry,
     ) -> Result<QueryPlan<MemoryValue, MemoryExpr>, anyhow::Error> {
         let plan = match plan {
             QueryPlan::EmptyRelation => QueryPlan::EmptyRelation,
             QueryPlan::SelectEntity { id } => QueryPlan::SelectEntity { id },
-            QueryPlan::Scan { filter } => QueryPlan::Scan {
-                filter: filter
-                    .map(|expr| self.build_memory_expr(expr, reg))
-                    .transpose()?,
+            QueryPlan::SelectEntities { ids } => QueryPlan::SelectEntities { ids },
+            QueryPlan::Scan { filter } => match filter.map(take_class_filter) {
+                // Only scan the entities of the matching types.
+                Some((Some(classes), rest)) => {
+                    let input = QueryPlan::SelectEntities {
+                        ids: self
+                            .type_index
+                            .lock()
+                            .unwrap()
+                            .entities_of(&classes, reg)
+                            .collect(),
+                    };
+                    match rest {
+                        Some(expr) => QueryPlan::Filter {
+                            expr: self.build_memory_expr_in(snapshot, expr, reg)?,
+                            input: Box::new(input),
+                        },
+                        None => input,
+                    }
+                }
+                Some((None, rest)) => QueryPlan::Scan {
+                    filter: rest
+                        .map(|expr| self.build_memory_expr_in(snapshot, expr, reg))
+                        .transpose()?,
+                },
+                None => QueryPlan::Scan { filter: None },
             },
             QueryPlan::Merge { left, right } => {
-                let left = Box::new(self.build_query_plan(*left, reg)?);
-                let right = Box::new(self.build_query_plan(*right, reg)?);
+                let left = Box::new(self.build_query_plan(snapshot, *left, reg)?);
+                let right = Box::new(self.build_query_plan(snapshot, *right, reg)?);
 
                 QueryPlan::Merge { left, right }
             }
@@ -1355,28 +2661,28 @@ impl MemoryStore {
                 direction,
             },
             QueryPlan::Sort { sorts, input } => QueryPlan::Sort {
-                input: Box::new(self.build_query_plan(*input, reg)?),
+                input: Box::new(self.build_query_plan(snapshot, *input, reg)?),
                 sorts: sorts
                     .into_iter()
                     .map(|s| -> Result<Sort<MemoryExpr>, anyhow::Error> {
                         Ok(Sort {
-                            on: self.build_memory_expr(s.on, reg)?,
+                            on: self.build_memory_expr_in(snapshot, s.on, reg)?,
                             order: s.order,
                         })
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             },
             QueryPlan::Filter { expr, input } => QueryPlan::Filter {
-                expr: self.build_memory_expr(expr, reg)?,
-                input: Box::new(self.build_query_plan(*input, reg)?),
+                expr: self.build_memory_expr_in(snapshot, expr, reg)?,
+                input: Box::new(self.build_query_plan(snapshot, *input, reg)?),
             },
             QueryPlan::Limit { limit, input } => QueryPlan::Limit {
                 limit,
-                input: Box::new(self.build_query_plan(*input, reg)?),
+                input: Box::new(self.build_query_plan(snapshot, *input, reg)?),
             },
             QueryPlan::Skip { count, input } => QueryPlan::Skip {
                 count,
-                input: Box::new(self.build_query_plan(*input, reg)?),
+                input: Box::new(self.build_query_plan(snapshot, *input, reg)?),
             },
             QueryPlan::IndexSelect { index, value } => QueryPlan::IndexSelect {
                 index,
@@ -1387,39 +2693,103 @@ impl MemoryStore {
                 input,
             } => QueryPlan::Aggregate {
                 aggregations,
-                input: Box::new(self.build_query_plan(*input, reg)?),
+                input: Box::new(self.build_query_plan(snapshot, *input, reg)?),
             },
         };
         Ok(plan)
     }
 
+    /// Build and record the query plan for a select.
+    fn plan_select(
+        &self,
+        snapshot: &Snapshot,
+        query: query::select::Select,
+        reg: &Registry,
+    ) -> Result<QueryPlan<MemoryValue, MemoryExpr>, anyhow::Error> {
+        tracing::trace!(?query, "building query");
+        let key = plan::PlanCache::fingerprint(&query);
+        let cached = key
+            .as_ref()
+            .and_then(|key| self.plan_cache.lock().unwrap().get(key));
+        let raw_plan = match cached {
+            Some(plan) => plan,
+            None => {
+                let plan = plan::plan_select(query, reg)?;
+                if let Some(key) = key {
+                    self.plan_cache.lock().unwrap().insert(key, plan.clone());
+                }
+                plan
+            }
+        };
+        self.query_counters.record_scan_lookups(&raw_plan, reg);
+        let mem_plan = self.build_query_plan(snapshot, raw_plan, reg)?;
+        tracing::debug!(query_plan=?mem_plan, "executing plan");
+        self.query_counters.record(&mem_plan);
+        Ok(mem_plan)
+    }
+
     pub fn select(
         &self,
         query: query::select::Select,
     ) -> Result<query::select::Page<Item>, anyhow::Error> {
         // TODO: query validation and planning
 
-        let span = tracing::debug_span!("executing select");
+        let span = tracing::debug_span!("executing select", rows = tracing::field::Empty);
         let _guard = span.enter();
 
-        let reg = self.registry().read().unwrap();
+        let reg = self.registry().load();
+        let items = self
+            .select_cached(query, &reg)?
+            .into_iter()
+            .map(Item::new)
+            .collect::<Vec<Item>>();
 
-        tracing::trace!(?query, "building query");
-        let raw_plan = plan::plan_select(query, &reg)?;
-        let mem_plan = self.build_query_plan(raw_plan, &reg)?;
-        tracing::debug!(query_plan=?mem_plan, "executing plan");
+        span.record("rows", items.len());
+        tracing::trace!(item_count=%items.len() ,"select complete");
+
+        Ok(Page {
+            next_cursor: None,
+            items,
+        })
+    }
+
+    /// Read the materialized entities of a view.
+    ///
+    /// `filter` further restricts the entities, eg. for policies.
+    /// See [`factor_core::schema::ViewSchema`].
+    pub fn select_view(
+        &self,
+        name: &str,
+        filter: Option<Expr>,
+    ) -> Result<query::select::Page<Item>, anyhow::Error> {
+        let span = tracing::debug_span!("executing view select", rows = tracing::field::Empty);
+        let _guard = span.enter();
 
-        let items = self
-            .run_query(mem_plan)
+        let reg = self.registry().load();
+        let view = reg
+            .view_by_name(name)
+            .ok_or_else(|| anyhow!("View '{}' not found", name))?;
+        let snapshot = self.entities.snapshot(&self.indexes);
+        let ids = self
+            .views
+            .lock()
+            .unwrap()
+            .entities(name)
+            .ok_or_else(|| anyhow!("View '{}' is not materialized", name))?;
+        let plan = plan::plan_view_select(view.query.clone(), ids, filter, &reg)?;
+        let mem_plan = self.build_query_plan(&snapshot, plan, &reg)?;
+        self.query_counters.record(&mem_plan);
+
+        let items = Self::run_query(&snapshot, mem_plan)
             .map(|tuple| {
                 Ok(Item {
-                    data: self.tuple_to_data_map(tuple.as_ref()),
+                    data: Self::tuple_to_read_data_map_with(&reg, tuple.as_ref())?,
                     joins: Vec::new(),
                 })
             })
             .collect::<Result<Vec<Item>, anyhow::Error>>()?;
 
-        tracing::trace!(item_count=%items.len() ,"select complete");
+        span.record("rows", items.len());
 
         Ok(Page {
             next_cursor: None,
@@ -1427,33 +2797,315 @@ impl MemoryStore {
         })
     }
 
+    /// Find the shortest path between two entities.
+    ///
+    /// Entities that don't match `filter` are never part of the path.
+    /// See [`GraphQuery`].
+    pub fn shortest_path(
+        &self,
+        query: &GraphQuery,
+        filter: Option<Expr>,
+    ) -> Result<Option<Vec<Id>>, anyhow::Error> {
+        let span = tracing::debug_span!("executing graph query", visited = tracing::field::Empty);
+        let _guard = span.enter();
+
+        let reg = self.registry().load();
+        let attr = reg.require_attr_by_name(&query.via)?;
+        let is_ref = |ty: &ValueType| matches!(ty, ValueType::Ref | ValueType::RefConstrained(_));
+        let is_list = match &attr.schema.value_type {
+            ValueType::List(inner) if is_ref(inner) => true,
+            ty if is_ref(ty) => false,
+            other => bail!(
+                "Graph queries require a reference attribute, but '{}' has type {:?}",
+                query.via,
+                other
+            ),
+        };
+        let snapshot = self.entities.snapshot(&self.indexes);
+        let filter = filter
+            .map(|filter| {
+                self.build_memory_expr_in(&snapshot, plan::resolve_expr(filter, &reg)?, &reg)
+            })
+            .transpose()?;
+        let is_visible = |id: &Id| {
+            snapshot.get(id).map_or(false, |tuple| {
+                filter
+                    .as_ref()
+                    .map_or(true, |filter| Self::entity_filter(tuple, filter))
+            })
+        };
+
+        if !is_visible(&query.from) || !is_visible(&query.to) {
+            return Ok(None);
+        }
+        if query.from == query.to {
+            return Ok(Some(vec![query.from]));
+        }
+
+        // Maps every reached entity to the entity it was first reached from.
+        let mut parents = fnv::FnvHashMap::<Id, Id>::default();
+        parents.insert(query.from, query.from);
+        let mut level = vec![query.from];
+        for _ in 0..query.max_depth {
+            let mut next = Vec::new();
+            let edges = self.graph_edges(
+                &snapshot,
+                &level,
+                attr.local_id,
+                is_list,
+                query.direction,
+                &reg,
+            );
+            for (source, target) in edges {
+                if parents.contains_key(&target) || !is_visible(&target) {
+                    continue;
+                }
+                parents.insert(target, source);
+                if target == query.to {
+                    span.record("visited", parents.len());
+                    let mut path = vec![target];
+                    let mut current = target;
+                    while current != query.from {
+                        current = parents[&current];
+                        path.push(current);
+                    }
+                    path.reverse();
+                    return Ok(Some(path));
+                }
+                next.push(target);
+            }
+            if next.is_empty() {
+                break;
+            }
+            level = next;
+        }
+
+        span.record("visited", parents.len());
+        Ok(None)
+    }
+
+    /// Collect the edges of a graph query that start at the given entities.
+    ///
+    /// Returns `(source, target)` pairs, where the source is part of
+    /// `level`.
+    fn graph_edges(
+        &self,
+        snapshot: &Snapshot,
+        level: &[Id],
+        attr: LocalAttributeId,
+        is_list: bool,
+        direction: GraphDirection,
+        reg: &Registry,
+    ) -> Vec<(Id, Id)> {
+        let mut edges = Vec::new();
+        if matches!(direction, GraphDirection::Outgoing | GraphDirection::Both) {
+            for id in level {
+                if let Some(value) = snapshot.get(id).and_then(|t| t.get(&attr)) {
+                    edges.extend(Self::referenced_ids(value).map(|target| (*id, target)));
+                }
+            }
+        }
+        if matches!(direction, GraphDirection::Incoming | GraphDirection::Both) {
+            // Indexes store list values as a whole, so they can only be used
+            // to find references of single value attributes.
+            let index = reg.indexes_for_attribute(attr).into_iter().find(|index| {
+                !index.is_partial()
+                    && !index.geo
+                    && !index.schema.multi_valued
+                    && !index.schema.tenant_scoped
+                    && index.schema.attributes.len() == 1
+            });
+            match index {
+                Some(index) if !is_list && !self.indexes_stale.load(Ordering::Relaxed) => {
+                    for id in level {
+                        let key = MemoryValue::Id(*id);
+                        match snapshot.index(index.local_id) {
+                            index::Index::Unique(idx) => {
+                                edges.extend(idx.get(&key).map(|source| (*id, source)))
+                            }
+                            index::Index::Multi(idx) | index::Index::Geo(idx) => edges.extend(
+                                idx.get(&key)
+                                    .into_iter()
+                                    .flatten()
+                                    .map(|source| (*id, *source)),
+                            ),
+                        }
+                    }
+                }
+                _ => {
+                    // Without an index, all entities are scanned once per
+                    // level.
+                    let targets = level.iter().copied().collect::<BTreeSet<_>>();
+                    for (source, tuple) in snapshot.iter() {
+                        if let Some(value) = tuple.get(&attr) {
+                            edges.extend(
+                                Self::referenced_ids(value)
+                                    .filter(|target| targets.contains(target))
+                                    .map(|target| (target, *source)),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    /// The entity ids referenced by a reference or a list of references.
+    fn referenced_ids(value: &MemoryValue) -> impl Iterator<Item = Id> + '_ {
+        let items = match value {
+            MemoryValue::List(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        items.iter().filter_map(|item| match item {
+            MemoryValue::Id(id) => Some(*id),
+            _ => None,
+        })
+    }
+
     pub fn select_map(&self, query: query::select::Select) -> Result<Vec<DataMap>, anyhow::Error> {
         // TODO: query validation and planning
 
-        let span = tracing::debug_span!("executing select");
+        let span = tracing::debug_span!("executing select", rows = tracing::field::Empty);
         let _guard = span.enter();
 
-        let reg = self.registry().read().unwrap();
+        let reg = self.registry().load();
+        let items = self.select_cached(query, &reg)?;
 
-        tracing::trace!(?query, "building query");
-        let raw_plan = plan::plan_select(query, &reg)?;
-        let mem_plan = self.build_query_plan(raw_plan, &reg)?;
-        tracing::debug!(query_plan=?mem_plan, "executing plan");
+        span.record("rows", items.len());
+        tracing::trace!(item_count=%items.len() ,"select complete");
 
-        let items = self
-            .run_query(mem_plan)
-            .map(|tuple| self.tuple_to_data_map(tuple.as_ref()))
+        Ok(items)
+    }
+
+    /// Run a select, serving the result from the result cache if the query
+    /// opts in with [`query::select::Select::cached`].
+    fn select_cached(
+        &self,
+        query: query::select::Select,
+        reg: &Registry,
+    ) -> Result<Vec<DataMap>, anyhow::Error> {
+        let cache = query
+            .cache_ttl()
+            .and_then(|ttl| Some((plan::PlanCache::fingerprint(&query)?, ttl)));
+        if let Some((key, _)) = &cache {
+            if let Some(items) = self.result_cache.lock().unwrap().get(key) {
+                tracing::trace!("serving select from result cache");
+                return Ok(items);
+            }
+        }
+
+        // The snapshot is held until the result is cached, so a concurrent
+        // write can not invalidate the cache before the stale result is
+        // inserted.
+        let snapshot = self.entities.snapshot(&self.indexes);
+        // The query is only kept around if the result is cached.
+        let (mem_plan, cache) = match cache {
+            Some((key, ttl)) => (
+                self.plan_select(&snapshot, query.clone(), reg)?,
+                Some((key, ttl, query)),
+            ),
+            None => (self.plan_select(&snapshot, query, reg)?, None),
+        };
+        let items = Self::run_query(&snapshot, mem_plan)
+            .map(|tuple| Self::tuple_to_read_data_map_with(reg, tuple.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some((key, ttl, query)) = cache {
+            self.result_cache
+                .lock()
+                .unwrap()
+                .insert(key, ttl, &query, reg, items.clone());
+        }
+        Ok(items)
+    }
+
+    /// Execute a select and return the rows as [`SharedDataMap`]s.
+    ///
+    /// Like [`Self::select_map`], but attribute names are shared with the
+    /// registry instead of being copied for every row.
+    pub fn select_map_shared(
+        &self,
+        query: query::select::Select,
+    ) -> Result<Vec<SharedDataMap>, anyhow::Error> {
+        let span = tracing::debug_span!("executing select", rows = tracing::field::Empty);
+        let _guard = span.enter();
+
+        let reg = self.registry().load();
+        let snapshot = self.entities.snapshot(&self.indexes);
+        let mem_plan = self.plan_select(&snapshot, query, &reg)?;
+
+        let items = Self::run_query(&snapshot, mem_plan)
+            .map(|tuple| Self::tuple_to_shared_data_map_with(&reg, tuple.as_ref()))
             .collect::<Vec<_>>();
 
+        span.record("rows", items.len());
         tracing::trace!(item_count=%items.len() ,"select complete");
 
         Ok(items)
     }
 
+    /// Execute a select and pass a borrowed view of each row to `visit`.
+    ///
+    /// Unlike [`Self::select_map`], the entity data is not copied, so large
+    /// result sets can be processed without deep copies of all values.
+    ///
+    /// Returns the number of visited rows.
+    pub fn select_visit<F>(
+        &self,
+        query: query::select::Select,
+        mut visit: F,
+    ) -> Result<usize, anyhow::Error>
+    where
+        F: FnMut(EntityView<'_>),
+    {
+        let span = tracing::debug_span!("executing select", rows = tracing::field::Empty);
+        let _guard = span.enter();
+
+        let reg = self.registry().load();
+        let snapshot = self.entities.snapshot(&self.indexes);
+        let mem_plan = self.plan_select(&snapshot, query, &reg)?;
+
+        let mut rows = 0;
+        for tuple in Self::run_query(&snapshot, mem_plan) {
+            visit(EntityView::new(&reg, tuple.as_ref()));
+            rows += 1;
+        }
+
+        span.record("rows", rows);
+        tracing::trace!(item_count=%rows ,"select complete");
+
+        Ok(rows)
+    }
+
+    /// Build an expression in a write, resolving idents with
+    /// [`Self::resolve_ident_locked`].
     fn build_memory_expr(
         &self,
+        shards: &ShardsWrite,
+        expr: ResolvedExpr,
+        reg: &Registry,
+    ) -> Result<MemoryExpr, anyhow::Error> {
+        Self::build_memory_expr_with(expr, reg, &|ident| self.resolve_ident_locked(shards, ident))
+    }
+
+    /// Like [`Self::build_memory_expr`], but resolves idents with the indexes
+    /// of a snapshot.
+    fn build_memory_expr_in(
+        &self,
+        snapshot: &Snapshot,
         expr: ResolvedExpr,
         reg: &Registry,
+    ) -> Result<MemoryExpr, anyhow::Error> {
+        Self::build_memory_expr_with(expr, reg, &|ident| {
+            Ok(self.resolve_ident_in(snapshot, ident))
+        })
+    }
+
+    fn build_memory_expr_with(
+        expr: ResolvedExpr,
+        reg: &Registry,
+        resolve_ident: &dyn Fn(&IdOrIdent) -> Result<Option<Id>, LockConflict>,
     ) -> Result<MemoryExpr, anyhow::Error> {
         use ResolvedExpr as E;
 
@@ -1462,30 +3114,28 @@ impl MemoryStore {
             E::List(items) => {
                 let items = items
                     .into_iter()
-                    .map(|e| self.build_memory_expr(e, reg))
+                    .map(|e| Self::build_memory_expr_with(e, reg, resolve_ident))
                     .collect::<Result<Vec<_>, _>>()?;
                 Ok(MemoryExpr::List(items))
             }
             E::Attr(attr) => Ok(MemoryExpr::Attr(attr)),
             E::Ident(ident) => {
-                let id = self
-                    .resolve_ident(&ident)
-                    .ok_or_else(|| EntityNotFound::new(ident))?;
+                let id = resolve_ident(&ident)?.ok_or_else(|| EntityNotFound::new(ident))?;
                 Ok(MemoryExpr::Ident(id))
             }
             E::UnaryOp { op, expr } => Ok(MemoryExpr::UnaryOp {
                 op,
-                expr: Box::new(self.build_memory_expr(*expr, reg)?),
+                expr: Box::new(Self::build_memory_expr_with(*expr, reg, resolve_ident)?),
             }),
             E::BinaryOp(op) => Ok(MemoryExpr::BinaryOp {
-                left: Box::new(self.build_memory_expr(op.left, reg)?),
+                left: Box::new(Self::build_memory_expr_with(op.left, reg, resolve_ident)?),
                 op: op.op,
-                right: Box::new(self.build_memory_expr(op.right, reg)?),
+                right: Box::new(Self::build_memory_expr_with(op.right, reg, resolve_ident)?),
             }),
             E::If { value, then, or } => Ok(MemoryExpr::If {
-                value: Box::new(self.build_memory_expr(*value, reg)?),
-                then: Box::new(self.build_memory_expr(*then, reg)?),
-                or: Box::new(self.build_memory_expr(*or, reg)?),
+                value: Box::new(Self::build_memory_expr_with(*value, reg, resolve_ident)?),
+                then: Box::new(Self::build_memory_expr_with(*then, reg, resolve_ident)?),
+                or: Box::new(Self::build_memory_expr_with(*or, reg, resolve_ident)?),
             }),
             E::InLiteral { value, items } => {
                 let items = items
@@ -1493,11 +3143,35 @@ impl MemoryStore {
                     .map(MemoryValue::from_value_standalone)
                     .collect();
                 Ok(MemoryExpr::InLiteral {
-                    value: Box::new(self.build_memory_expr(*value, reg)?),
+                    value: Box::new(Self::build_memory_expr_with(*value, reg, resolve_ident)?),
                     items,
                 })
             }
             E::Regex(e) => Ok(MemoryExpr::Regex(e)),
+            E::WithinRadius {
+                value,
+                center,
+                meters,
+            } => Ok(MemoryExpr::WithinRadius {
+                value: Box::new(Self::build_memory_expr_with(*value, reg, resolve_ident)?),
+                center,
+                meters,
+            }),
+            E::JsonPath { value, path } => Ok(MemoryExpr::JsonPath {
+                value: Box::new(Self::build_memory_expr_with(*value, reg, resolve_ident)?),
+                path,
+            }),
+            // Single tuples are checked against all type values of the
+            // classes, since the expression must also match entities
+            // created later, like in materialized views.
+            E::InheritsClass(classes) => Ok(MemoryExpr::InLiteral {
+                value: Box::new(MemoryExpr::Attr(ATTR_TYPE_LOCAL)),
+                items: classes
+                    .iter()
+                    .filter_map(|class| reg.entity(class))
+                    .flat_map(type_index::type_values)
+                    .collect(),
+            }),
         }
     }
 
@@ -1524,11 +3198,7 @@ impl MemoryStore {
             E::Ident(id) => Cow::Owned(MemoryValue::Id(*id)),
             E::UnaryOp { op, expr } => {
                 let value = Self::eval_expr(entity, expr);
-                match op {
-                    query::expr::UnaryOp::Not => {
-                        Cow::Owned(MemoryValue::Bool(!value.as_bool_discard_other()))
-                    }
-                }
+                Cow::Owned(MemoryValue::eval_unary(op, &value))
             }
             E::BinaryOp { left, op, right } => match op {
                 query::expr::BinaryOp::And => {
@@ -1574,6 +3244,11 @@ impl MemoryStore {
 
                     Cow::Owned(MemoryValue::Bool(is_match))
                 }
+                op if op.is_computed() => {
+                    let left = Self::eval_expr(entity, left);
+                    let right = Self::eval_expr(entity, right);
+                    Cow::Owned(MemoryValue::eval_computed(op, &left, &right))
+                }
                 other => {
                     let left = Self::eval_expr(entity, left);
                     let right = Self::eval_expr(entity, right);
@@ -1611,7 +3286,11 @@ impl MemoryStore {
                         BinaryOp::And
                         | BinaryOp::Or
                         | BinaryOp::RegexMatch
-                        | BinaryOp::RegexMatchCaseInsensitive => {
+                        | BinaryOp::RegexMatchCaseInsensitive
+                        | BinaryOp::Add
+                        | BinaryOp::Sub
+                        | BinaryOp::Mul
+                        | BinaryOp::Concat => {
                             // Covered above in separate matches.
                             unreachable!()
                         }
@@ -1633,13 +3312,61 @@ impl MemoryStore {
                 Cow::Owned(MemoryValue::Bool(items.contains(&*value)))
             }
             E::Regex(_) => Cow::Owned(MemoryValue::Unit),
+            E::WithinRadius {
+                value,
+                center,
+                meters,
+            } => {
+                let value = Self::eval_expr(entity, value);
+                let flag = GeoPoint::try_from(value.to_value())
+                    .map(|point| point.distance(center) <= *meters)
+                    .unwrap_or(false);
+                Cow::Owned(MemoryValue::Bool(flag))
+            }
+            E::JsonPath { value, path } => {
+                let nested = match Self::eval_expr(entity, value) {
+                    Cow::Borrowed(value) => value.get_path(path).map(Cow::Borrowed),
+                    Cow::Owned(value) => value.get_path(path).cloned().map(Cow::Owned),
+                };
+                nested.unwrap_or(cowal_unit())
+            }
         }
     }
 
-    fn entity_filter(entity: &MemoryTuple, expr: &memory_data::MemoryExpr) -> bool {
+    pub(super) fn entity_filter(entity: &MemoryTuple, expr: &memory_data::MemoryExpr) -> bool {
         Self::eval_expr(entity, expr).as_bool_discard_other()
     }
 
+    /// Evaluate an expression against entity data that is not in the store.
+    ///
+    /// Values are coerced to the attribute types like on write, so the result
+    /// matches a select of the written entity.
+    /// Idents can only be used with ids, since names are resolved by the store.
+    pub(crate) fn eval_data_expr(
+        expr: Expr,
+        data: &DataMap,
+        reg: &Registry,
+    ) -> Result<Value, anyhow::Error> {
+        let expr = Self::build_memory_expr_with(plan::resolve_expr(expr, reg)?, reg, &|ident| {
+            Ok(ident.as_id())
+        })?;
+        let tuple = data
+            .0
+            .iter()
+            .filter_map(|(key, value)| {
+                let attr = reg.attr_by_name(key)?;
+                let mut coerced = value.clone();
+                // Invalid values are rejected by validation.
+                let value = match coerced.coerce_mut(&attr.schema.value_type) {
+                    Ok(()) => coerced,
+                    Err(_) => value.clone(),
+                };
+                Some((attr.local_id, MemoryValue::from_value_standalone(value)))
+            })
+            .collect();
+        Ok(Self::eval_expr(&MemoryTuple(tuple), &expr).to_value())
+    }
+
     pub fn purge_all_data(&mut self) {
         /*
         self.entities.retain(|id, entity| {
@@ -1653,15 +3380,21 @@ impl MemoryStore {
         });
         */
         self.entities.clear();
+        self.type_index.get_mut().unwrap().clear();
+        self.views.get_mut().unwrap().clear();
+        self.result_cache.get_mut().unwrap().clear();
         self.interner.clear();
         self.indexes = index::new_memory_index_map();
-        self.registry.write().unwrap().reset();
+        *self.indexes_stale.get_mut() = false;
+        self.ident_cache.clear();
+        let mut reg = Registry::new();
+        reg.set_naming_policy(self.registry.load().naming_policy().clone());
+        self.registry.store(std::sync::Arc::new(reg));
+        self.plan_cache.get_mut().unwrap().clear();
 
         let indexes = {
             self.registry
-                .clone()
-                .read()
-                .unwrap()
+                .load()
                 .iter_indexes()
                 .cloned()
                 .collect::<Vec<_>>()
@@ -1677,7 +3410,7 @@ impl MemoryStore {
         new_type: &ValueType,
         revert: &mut RevertList,
     ) -> Result<(), anyhow::Error> {
-        for (id, tuple) in &mut self.entities {
+        for (id, tuple) in self.entities.iter_mut() {
             if let Some(memory_value) = tuple.get_mut(&attr.local_id) {
                 let mut value = memory_value.to_value();
                 value.coerce_mut(new_type)?;
@@ -1743,11 +3476,67 @@ enum RevertOp {
     },
 }
 
+impl RevertOp {
+    /// The entity changed by the operation.
+    /// `None` for index operations.
+    fn entity_id(&self) -> Option<Id> {
+        match self {
+            Self::TupleCreated { id }
+            | Self::TupleReplaced { id, .. }
+            | Self::TupleMerged { id, .. }
+            | Self::TupleAttrsRemoved { id, .. }
+            | Self::TupleDeleted { id, .. } => Some(*id),
+            Self::IndexValueInserted { .. } | Self::IndexValueRemoved { .. } => None,
+        }
+    }
+}
+
 type RevertList = Vec<RevertOp>;
 
+/// The result of each segment of a batch, with the created audit entries.
+pub type SegmentResults = Vec<Result<Vec<query::mutate::Create>, anyhow::Error>>;
+
+/// A [`TupleIndexOp`] with interned values.
+enum MemoryIndexOp {
+    Insert {
+        value: MemoryValue,
+        attribute: LocalAttributeId,
+    },
+    Replace {
+        old_value: MemoryValue,
+        value: MemoryValue,
+        attribute: LocalAttributeId,
+    },
+    Remove(MemoryValue),
+}
+
+/// Split a [`ResolvedExpr::InheritsClass`] clause off a filter.
+///
+/// Only direct clauses of a top-level AND are considered.
+/// Returns the classes and the remaining filter.
+fn take_class_filter(expr: ResolvedExpr) -> (Option<registry::ClassSet>, Option<ResolvedExpr>) {
+    match expr {
+        ResolvedExpr::InheritsClass(classes) => (Some(classes), None),
+        ResolvedExpr::BinaryOp(bin) if bin.op == query::expr::BinaryOp::And => match *bin {
+            plan::BinaryExpr {
+                left: ResolvedExpr::InheritsClass(classes),
+                right: rest,
+                ..
+            }
+            | plan::BinaryExpr {
+                left: rest,
+                right: ResolvedExpr::InheritsClass(classes),
+                ..
+            } => (Some(classes), Some(rest)),
+            bin => (None, Some(ResolvedExpr::BinaryOp(Box::new(bin)))),
+        },
+        other => (None, Some(other)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use factor_core::query::expr::BinaryOp;
+    use factor_core::{map, query::expr::BinaryOp};
 
     use super::*;
 
@@ -1771,4 +3560,629 @@ mod tests {
         let flag = MemoryStore::eval_expr(&tuple, &expr);
         assert!(flag.as_bool_discard_other());
     }
+
+    #[test]
+    fn test_memory_store_verify() {
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+
+        let id = Id::random();
+        store
+            .apply_batch(Batch::new().and_create(query::mutate::Create {
+                id,
+                data: map! { "factor/ident": "test/verify" },
+            }))
+            .unwrap();
+
+        let entity_issues = |store: &MemoryStore| {
+            store
+                .verify()
+                .into_iter()
+                .filter(|issue| match issue {
+                    VerifyIssue::IndexEntryMissing { entity, .. }
+                    | VerifyIssue::IndexEntryStale { entity, .. }
+                    | VerifyIssue::InvalidEntity { entity, .. } => *entity == id,
+                    _ => false,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(entity_issues(&store), Vec::new());
+
+        store
+            .indexes
+            .get_mut(registry::INDEX_IDENT_LOCAL)
+            .get_mut()
+            .unwrap()
+            .clear();
+        assert_eq!(
+            entity_issues(&store),
+            vec![VerifyIssue::IndexEntryMissing {
+                index: store
+                    .registry
+                    .load()
+                    .index_by_local_id(registry::INDEX_IDENT_LOCAL)
+                    .unwrap()
+                    .schema
+                    .ident
+                    .clone(),
+                entity: id,
+                value: Value::from("test/verify"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_memory_store_apply_create_batch() {
+        let store = MemoryStore::new(Registry::new().into_shared());
+
+        let create_batch = |range: std::ops::Range<usize>| {
+            let ids = range.clone().map(|_| Id::random()).collect::<Vec<_>>();
+            let batch = range.zip(&ids).fold(Batch::new(), |batch, (index, id)| {
+                batch.and_create(query::mutate::Create {
+                    id: *id,
+                    data: map! { "factor/ident": format!("test/e{index}") },
+                })
+            });
+            (ids, batch)
+        };
+
+        let (ids, batch) = create_batch(0..100);
+        store.apply_batch(batch).unwrap();
+        assert!(ids
+            .iter()
+            .all(|id| store.entities.read(id).contains_key(id)));
+        assert_eq!(store.check_consistency(), Vec::new());
+
+        // The last create violates the unique ident index, so the whole batch
+        // is reverted.
+        let (ids, batch) = create_batch(100..200);
+        let batch = batch.and_create(query::mutate::Create {
+            id: Id::random(),
+            data: map! { "factor/ident": "test/e0" },
+        });
+        store.apply_batch(batch).unwrap_err();
+        assert!(ids
+            .iter()
+            .all(|id| !store.entities.read(id).contains_key(id)));
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_deferred_index_updates() {
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        store.set_ignore_index_constraints(true);
+        store.set_defer_index_updates(true).unwrap();
+
+        // Indexes created while updates are deferred are populated by the
+        // rebuild.
+        store
+            .migrate(
+                Migration::new().attr_create(
+                    factor_core::schema::Attribute::new("test/deferred", ValueType::Int)
+                        .with_indexed(true),
+                ),
+            )
+            .unwrap();
+
+        let ids = (0..10).map(|_| Id::random()).collect::<Vec<_>>();
+        let batch = (0i64..).zip(&ids).fold(Batch::new(), |batch, (index, id)| {
+            batch.and_create(query::mutate::Create {
+                id: *id,
+                data: map! {
+                    "factor/ident": format!("test/e{index}"),
+                    "test/deferred": index % 2,
+                },
+            })
+        });
+        store.apply_batch(batch).unwrap();
+
+        // Names still resolve.
+        assert_eq!(
+            store.resolve_ident(&IdOrIdent::from("test/e3")),
+            Some(ids[3])
+        );
+        assert!(store.indexes_stale.load(Ordering::Relaxed));
+        assert_ne!(store.check_consistency(), Vec::new());
+
+        store.set_defer_index_updates(false).unwrap();
+        store.set_ignore_index_constraints(false);
+        assert!(!store.indexes_stale.load(Ordering::Relaxed));
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_wide_entity_index_ops() {
+        use query::mutate::Mutate;
+
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        let attrs = (0..4).map(|i| format!("test/wide{i}")).collect::<Vec<_>>();
+        let migration = attrs.iter().fold(Migration::new(), |mig, attr| {
+            mig.attr_create(
+                factor_core::schema::Attribute::new(attr.clone(), ValueType::Int)
+                    .with_indexed(true),
+            )
+        });
+        store.migrate(migration).unwrap();
+
+        let values = |offset: i64| {
+            let mut data = map! { "factor/ident": "test/wide" };
+            for (value, attr) in (offset..).zip(&attrs) {
+                data.insert(attr.clone(), value.into());
+            }
+            data
+        };
+        let id = Id::random();
+        store
+            .apply_batch(Mutate::create(id, values(0)).into())
+            .unwrap();
+        assert_eq!(store.check_consistency(), Vec::new());
+
+        let epoch = store
+            .apply_batch_revertable(Mutate::merge(id, values(10)).into())
+            .unwrap();
+        assert_eq!(store.check_consistency(), Vec::new());
+        store.revert_changes(epoch).unwrap();
+        assert_eq!(store.check_consistency(), Vec::new());
+
+        // The create violates the unique ident index, so the index changes
+        // of the merge are reverted too.
+        store
+            .apply_batch(
+                Batch::new()
+                    .and_merge(query::mutate::Merge::new(id, values(20)))
+                    .and_create(query::mutate::Create {
+                        id: Id::random(),
+                        data: map! { "factor/ident": "test/wide" },
+                    }),
+            )
+            .unwrap_err();
+        assert_eq!(store.check_consistency(), Vec::new());
+
+        store.apply_batch(Mutate::delete(id).into()).unwrap();
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_select_delete_uses_index() {
+        use query::mutate::{Mutate, MutateSelect, MutateSelectAction};
+
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        store
+            .migrate(
+                Migration::new().attr_create(
+                    factor_core::schema::Attribute::new("test/indexed", ValueType::Int)
+                        .with_indexed(true),
+                ),
+            )
+            .unwrap();
+        for value in 0..10 {
+            store
+                .apply_batch(Mutate::create(Id::random(), map! { "test/indexed": value }).into())
+                .unwrap();
+        }
+
+        let before = store.stats().queries;
+        store
+            .apply_batch(Batch::new().and_select(MutateSelect {
+                filter: Expr::eq(Expr::attr_ident("test/indexed"), Expr::literal(3)),
+                variables: Default::default(),
+                action: MutateSelectAction::Delete,
+                guard: None,
+            }))
+            .unwrap();
+        let after = store.stats().queries;
+        assert_eq!(after.full_scans, before.full_scans);
+        assert!(after.index_reads > before.index_reads);
+
+        let page = store.select(Select::new()).unwrap();
+        assert_eq!(page.items.len(), 9);
+        assert!(page
+            .items
+            .iter()
+            .all(|item| item.data.get("test/indexed") != Some(&Value::from(3))));
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_ident_cache() {
+        use query::mutate::Mutate;
+
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        let id = Id::random();
+        let name = IdOrIdent::from("test/cached");
+        store
+            .apply_batch(Mutate::create(id, map! { "factor/ident": "test/cached" }).into())
+            .unwrap();
+        assert_eq!(store.resolve_ident(&name), Some(id));
+        assert_eq!(store.ident_cache.len(), 1);
+        // Misses are not cached.
+        assert_eq!(store.resolve_ident(&IdOrIdent::from("test/missing")), None);
+        assert_eq!(store.ident_cache.len(), 1);
+
+        // Renames invalidate the old name.
+        let renamed = IdOrIdent::from("test/renamed");
+        let epoch = store
+            .apply_batch_revertable(
+                Mutate::merge(id, map! { "factor/ident": "test/renamed" }).into(),
+            )
+            .unwrap();
+        assert_eq!(store.resolve_ident(&name), None);
+        assert_eq!(store.resolve_ident(&renamed), Some(id));
+
+        // Reverts restore the old name.
+        store.revert_changes(epoch).unwrap();
+        assert_eq!(store.resolve_ident(&renamed), None);
+        assert_eq!(store.resolve_ident(&name), Some(id));
+
+        store.apply_batch(Mutate::delete(id).into()).unwrap();
+        assert_eq!(store.resolve_ident(&name), None);
+    }
+
+    #[test]
+    fn test_memory_store_ident_cache_failed_batch() {
+        const ROUNDS: usize = 200;
+
+        let store = &MemoryStore::new(Registry::new().into_shared());
+        let name = &IdOrIdent::from("test/pending");
+        let done = &AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    assert_eq!(store.resolve_ident(name), None);
+                }
+            });
+
+            for _ in 0..ROUNDS {
+                let id = Id::random();
+                // Resolves the new ident inside the batch, then fails.
+                let batch = Batch::new()
+                    .and_create(query::mutate::Create {
+                        id,
+                        data: map! { "factor/ident": "test/pending" },
+                    })
+                    .and_guard(query::mutate::Guard {
+                        id,
+                        filter: Expr::neq(
+                            Expr::attr_ident("factor/id"),
+                            Expr::Ident(IdOrIdent::from("test/pending")),
+                        ),
+                    });
+                assert!(store.apply_batch(batch).is_err());
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(store.resolve_ident(name), None);
+        assert_eq!(store.ident_cache.len(), 0);
+    }
+
+    #[test]
+    fn test_memory_store_plan_cache() {
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        let select = || {
+            Select::new().with_filter(Expr::eq(
+                Expr::attr_ident("factor/title"),
+                Expr::literal("a"),
+            ))
+        };
+
+        store.select(select()).unwrap();
+        store.select(select()).unwrap();
+        assert_eq!(store.plan_cache.get_mut().unwrap().len(), 1);
+
+        // Schema changes invalidate cached plans.
+        store
+            .migrate(
+                Migration::new().attr_create(factor_core::schema::Attribute::new(
+                    "test/plan_cache",
+                    ValueType::Int,
+                )),
+            )
+            .unwrap();
+        assert!(store.plan_cache.get_mut().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_store_migrate_swaps_registry() {
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        let snapshot = store.registry.load_full();
+
+        store
+            .migrate(
+                Migration::new().attr_create(factor_core::schema::Attribute::new(
+                    "test/swap",
+                    ValueType::Int,
+                )),
+            )
+            .unwrap();
+
+        // Readers holding the old snapshot are not affected.
+        assert!(snapshot.attr_by_name("test/swap").is_none());
+        assert!(store.registry.load().attr_by_name("test/swap").is_some());
+    }
+
+    #[test]
+    fn test_memory_store_class_counts_revert() {
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        store
+            .migrate(Migration::new().entity_create(factor_core::schema::Class::new("test/A")))
+            .unwrap();
+        let count = |store: &MemoryStore| {
+            store
+                .class_stats()
+                .into_iter()
+                .find(|stats| stats.class == "test/A")
+                .unwrap()
+                .entities
+        };
+
+        let id = Id::random();
+        store
+            .apply_batch(Batch::new().and_create(query::mutate::Create {
+                id,
+                data: map! { "factor/type": "test/A" },
+            }))
+            .unwrap();
+        assert_eq!(count(&store), 1);
+
+        // The second create fails, so the whole batch is reverted.
+        store
+            .apply_batch(
+                Batch::new()
+                    .and_create(query::mutate::Create {
+                        id: Id::random(),
+                        data: map! { "factor/type": "test/A" },
+                    })
+                    .and_create(query::mutate::Create {
+                        id,
+                        data: map! { "factor/type": "test/A" },
+                    }),
+            )
+            .unwrap_err();
+        assert_eq!(count(&store), 1);
+
+        store
+            .apply_batch(Batch::new().and_delete(query::mutate::Delete { id }))
+            .unwrap();
+        assert_eq!(count(&store), 0);
+    }
+
+    #[test]
+    fn test_memory_store_savepoints_revert_failed_segment() {
+        let store = MemoryStore::new(Registry::new().into_shared());
+        let create = |id: Id, ident: &str| query::mutate::Create {
+            id,
+            data: map! { "factor/ident": ident },
+        };
+
+        let (id1, id2, id3, id4) = (Id::random(), Id::random(), Id::random(), Id::random());
+        let results = store.apply_batch_with_savepoints(
+            Batch::new()
+                .and_create(create(id1, "test/a"))
+                .and_savepoint()
+                // Applied, but reverted since the segment fails.
+                .and_create(create(id2, "test/b"))
+                .and_create(create(id3, "test/a"))
+                .and_savepoint()
+                .and_create(create(id4, "test/b")),
+            None,
+        );
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+
+        assert!(store.entities.read(&id1).contains_key(&id1));
+        assert!(!store.entities.read(&id2).contains_key(&id2));
+        assert!(!store.entities.read(&id3).contains_key(&id3));
+        assert!(store.entities.read(&id4).contains_key(&id4));
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_check_consistency() {
+        let store = MemoryStore::new(Registry::new().into_shared());
+        let ident_index = store
+            .registry
+            .load()
+            .index_by_local_id(registry::INDEX_IDENT_LOCAL)
+            .unwrap()
+            .schema
+            .ident
+            .clone();
+
+        let id = Id::random();
+        store
+            .apply_batch(Batch::new().and_create(query::mutate::Create {
+                id,
+                data: map! { "factor/ident": "test/consistency" },
+            }))
+            .unwrap();
+        assert_eq!(store.check_consistency(), Vec::new());
+
+        // A second entity with the same ident.
+        let id2 = Id::random();
+        let tuple = store.entities.read(&id)[&id].clone();
+        store
+            .entities
+            .write(&store.indexes, None)
+            .insert(id2, tuple)
+            .unwrap();
+        let issues = store.check_consistency();
+        let mut entities = vec![id, id2];
+        entities.sort();
+        assert!(issues.contains(&VerifyIssue::DuplicateUniqueValue {
+            index: ident_index.clone(),
+            value: Value::from("test/consistency"),
+            entities,
+        }));
+
+        // Index entries of removed entities.
+        let mut shards = store.entities.write_all(&store.indexes);
+        shards.remove(&id2).unwrap();
+        shards.remove(&id).unwrap();
+        drop(shards);
+        assert_eq!(
+            store.check_consistency(),
+            vec![VerifyIssue::IndexEntryDangling {
+                index: ident_index,
+                entity: id,
+                value: Value::from("test/consistency"),
+            }]
+        );
+    }
+
+    /// A random id whose entity shard matches `filter`.
+    fn id_in_shard(filter: impl Fn(usize) -> bool) -> Id {
+        loop {
+            let id = Id::random();
+            if filter(EntityShards::shard_of(&id)) {
+                return id;
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_store_sharded_batches() {
+        use std::{sync::mpsc, time::Duration};
+
+        let store = &MemoryStore::new(Registry::new().into_shared());
+        let held = Id::random();
+        let other = id_in_shard(|shard| shard != EntityShards::shard_of(&held));
+        let create = |id: Id| {
+            Batch::new().and_create(query::mutate::Create {
+                id,
+                data: map! { "factor/title": "a" },
+            })
+        };
+
+        let shards = store.entities.write(&store.indexes, Some(held));
+        std::thread::scope(|scope| {
+            // Entities in other shards can be written.
+            let (sender, receiver) = mpsc::channel();
+            scope.spawn(move || sender.send(store.apply_batch(create(other))));
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap()
+                .unwrap();
+
+            // Entities in the held shard wait for the shard.
+            let (sender, receiver) = mpsc::channel();
+            scope.spawn(move || sender.send(store.apply_batch(create(held))));
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(shards);
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap()
+                .unwrap();
+        });
+
+        assert!(store.entity(held.into()).is_ok());
+        assert!(store.entity(other.into()).is_ok());
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_sharded_batch_lock_conflict() {
+        use std::{sync::mpsc, time::Duration};
+
+        let mut store = MemoryStore::new(Registry::new().into_shared());
+        store
+            .migrate(
+                Migration::new().attr_create(factor_core::schema::Attribute::new(
+                    "test/ref",
+                    ValueType::Ref,
+                )),
+            )
+            .unwrap();
+        let store = &store;
+
+        let target = id_in_shard(|shard| shard < EntityShards::COUNT - 1);
+        let source = id_in_shard(|shard| shard > EntityShards::shard_of(&target));
+        store
+            .apply_batch(Batch::new().and_create(query::mutate::Create {
+                id: target,
+                data: map! { "factor/title": "target" },
+            }))
+            .unwrap();
+
+        // The batch only knows the source up front, and can't wait for the
+        // lower shard of the target while holding the source shard.
+        // It is reverted and applied again with all shards.
+        let shards = store.entities.write(&store.indexes, Some(target));
+        std::thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            scope.spawn(move || {
+                let batch = Batch::new().and_create(query::mutate::Create {
+                    id: source,
+                    data: map! { "test/ref": target },
+                });
+                sender.send(store.apply_batch(batch))
+            });
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(shards);
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap()
+                .unwrap();
+        });
+
+        assert_eq!(
+            store.entity(source.into()).unwrap().get("test/ref"),
+            Some(&Value::Id(target))
+        );
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
+
+    #[test]
+    fn test_memory_store_concurrent_unique_conflict() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 50;
+
+        let store = &MemoryStore::new(Registry::new().into_shared());
+        let owner = Id::random();
+        store
+            .apply_batch(Batch::new().and_create(query::mutate::Create {
+                id: owner,
+                data: map! { "factor/ident": "test/unique" },
+            }))
+            .unwrap();
+
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                scope.spawn(move || {
+                    for round in 0..ROUNDS {
+                        let batch = if thread % 2 == 0 {
+                            // Releases the ident, then fails and takes it
+                            // back in the revert.
+                            Batch::new()
+                                .and_merge(query::mutate::Merge::new(
+                                    owner,
+                                    map! { "factor/ident": format!("test/renamed-{thread}-{round}") },
+                                ))
+                                .and_guard(query::mutate::Guard {
+                                    id: owner,
+                                    filter: Expr::literal(false),
+                                })
+                        } else {
+                            // The ident is never released by a committed
+                            // batch, so it can never be taken.
+                            Batch::new().and_create(query::mutate::Create {
+                                id: Id::random(),
+                                data: map! { "factor/ident": "test/unique" },
+                            })
+                        };
+                        assert!(store.apply_batch(batch).is_err());
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            store.resolve_ident(&IdOrIdent::from("test/unique")),
+            Some(owner)
+        );
+        assert_eq!(store.check_consistency(), Vec::new());
+    }
 }
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use factor_core::data::Id;
+use fnv::{FnvHashMap, FnvHashSet};
+
+use crate::registry::{ClassSet, RegisteredEntity, Registry, ATTR_TYPE_LOCAL};
+
+use super::memory_data::{MemoryTuple, MemoryValue, SharedStr};
+
+/// Live entities per class.
+///
+/// Entities are keyed by the stored `factor/type` value, which can be a
+/// class id or an ident, so updating the index does not need the registry.
+/// Keys are only resolved to classes when reading.
+/// There are few distinct types, so resolving all keys is cheap, and
+/// type inheritance filters only need a bitset test per type.
+#[derive(Default, Debug)]
+pub(super) struct TypeIndex {
+    entities: FnvHashMap<MemoryValue, FnvHashSet<Id>>,
+}
+
+impl TypeIndex {
+    /// Update the index for an entity that changed from `old` to `new`.
+    ///
+    /// `None` means the entity did not exist before or does not exist
+    /// anymore.
+    pub fn update(&mut self, id: Id, old: Option<&MemoryTuple>, new: Option<&MemoryTuple>) {
+        self.update_type(
+            id,
+            old.and_then(|tuple| tuple.get(&ATTR_TYPE_LOCAL)),
+            new.and_then(|tuple| tuple.get(&ATTR_TYPE_LOCAL)),
+        );
+    }
+
+    /// Update the index for an entity whose type changed from `old` to
+    /// `new`.
+    pub fn update_type(&mut self, id: Id, old: Option<&MemoryValue>, new: Option<&MemoryValue>) {
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            if let Some(ids) = self.entities.get_mut(old) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.entities.remove(old);
+                }
+            }
+        }
+        if let Some(new) = new {
+            self.entities.entry(new.clone()).or_default().insert(id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    /// Number of entities of a class.
+    pub fn count(&self, class: &RegisteredEntity) -> u64 {
+        type_values(class)
+            .iter()
+            .filter_map(|key| self.entities.get(key))
+            .map(|ids| ids.len() as u64)
+            .sum()
+    }
+
+    /// Ids of all entities whose type is one of the classes.
+    pub fn entities_of<'a>(
+        &'a self,
+        classes: &'a ClassSet,
+        reg: &'a Registry,
+    ) -> impl Iterator<Item = Id> + 'a {
+        self.entities
+            .iter()
+            .filter(move |(ty, _)| {
+                resolve_class(ty, reg).map_or(false, |class| classes.contains(class.local_id))
+            })
+            .flat_map(|(_, ids)| ids.iter().copied())
+    }
+
+    /// Entity counts per class ident.
+    ///
+    /// Type values that do not reference a registered class are kept if
+    /// they are idents.
+    pub fn by_class(&self, reg: &Registry) -> BTreeMap<String, u64> {
+        let mut classes = BTreeMap::<String, u64>::new();
+        for (ty, ids) in &self.entities {
+            let class = match ty {
+                MemoryValue::String(s) if Id::from_str(s.as_ref()).is_err() => {
+                    Some(s.as_ref().to_string())
+                }
+                other => resolve_class(other, reg).map(|e| e.schema.ident.clone()),
+            };
+            if let Some(class) = class {
+                *classes.entry(class).or_default() += ids.len() as u64;
+            }
+        }
+        classes
+    }
+}
+
+/// All values that can be stored as the `factor/type` of a class.
+pub(super) fn type_values(class: &RegisteredEntity) -> [MemoryValue; 3] {
+    [
+        MemoryValue::Id(class.schema.id),
+        MemoryValue::String(SharedStr::from_string(class.schema.id.to_string())),
+        MemoryValue::String(SharedStr::from_string(class.schema.ident.clone())),
+    ]
+}
+
+fn resolve_class<'a>(ty: &MemoryValue, reg: &'a Registry) -> Option<&'a RegisteredEntity> {
+    match ty {
+        MemoryValue::String(s) => match Id::from_str(s.as_ref()) {
+            Ok(id) => reg.entity_by_id(id),
+            Err(_) => reg.entity_by_name(s.as_ref()),
+        },
+        MemoryValue::Id(id) => reg.entity_by_id(*id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::schema::Class;
+
+    use super::*;
+
+    fn ty(ident: &str) -> MemoryValue {
+        MemoryValue::String(SharedStr::from_string(ident.to_string()))
+    }
+
+    #[test]
+    fn test_type_index_update() {
+        let reg = Registry::new();
+        let mut index = TypeIndex::default();
+        let (a, b, c) = (Id::random(), Id::random(), Id::random());
+
+        index.update_type(a, None, Some(&ty("test/A")));
+        index.update_type(b, None, Some(&ty("test/A")));
+        index.update_type(c, None, Some(&ty("test/B")));
+        index.update_type(a, Some(&ty("test/A")), Some(&ty("test/B")));
+        index.update_type(a, Some(&ty("test/B")), Some(&ty("test/B")));
+        index.update_type(c, Some(&ty("test/B")), None);
+
+        let classes = index.by_class(&reg);
+        assert_eq!(classes.get("test/A"), Some(&1));
+        assert_eq!(classes.get("test/B"), Some(&1));
+
+        index.update_type(b, Some(&ty("test/A")), None);
+        assert_eq!(index.by_class(&reg).get("test/A"), None);
+    }
+
+    #[test]
+    fn test_type_index_entities_of() {
+        let mut reg = Registry::new();
+        let parent = Class {
+            id: Id::random(),
+            ..Class::new("test/Parent")
+        };
+        let child = Class {
+            id: Id::random(),
+            ..Class::new("test/Child").with_extend("test/Parent")
+        };
+        reg.register_class(parent.clone(), true).unwrap();
+        reg.register_class(child.clone(), true).unwrap();
+
+        let mut index = TypeIndex::default();
+        let (a, b, c) = (Id::random(), Id::random(), Id::random());
+        index.update_type(a, None, Some(&ty("test/Parent")));
+        // Types can also be stored as ids.
+        index.update_type(b, None, Some(&MemoryValue::Id(child.id)));
+        index.update_type(c, None, Some(&ty("test/Other")));
+
+        let classes = &reg.entity_by_name("test/Parent").unwrap().descendants;
+        let mut ids = index.entities_of(classes, &reg).collect::<Vec<_>>();
+        ids.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        let classes = &reg.entity_by_name("test/Child").unwrap().descendants;
+        assert_eq!(
+            index.entities_of(classes, &reg).collect::<Vec<_>>(),
+            vec![b]
+        );
+    }
+}
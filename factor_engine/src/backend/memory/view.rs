@@ -0,0 +1,63 @@
+use factor_core::data::{DataMap, Id, Value};
+
+use crate::registry::{Registry, ATTR_ID_LOCAL};
+
+use super::memory_data::{MemoryTuple, MemoryValue};
+
+/// Borrowed view of an entity stored in a [`super::store::MemoryStore`].
+///
+/// Gives access to query results without copying the entity data.
+/// See [`super::store::MemoryStore::select_visit`].
+pub struct EntityView<'a> {
+    reg: &'a Registry,
+    tuple: &'a MemoryTuple,
+}
+
+impl<'a> EntityView<'a> {
+    pub(super) fn new(reg: &'a Registry, tuple: &'a MemoryTuple) -> Self {
+        Self { reg, tuple }
+    }
+
+    fn value(&self, attr: &str) -> Option<&'a MemoryValue> {
+        let attr = self.reg.attr_by_name(attr)?;
+        self.tuple.0.get(&attr.local_id)
+    }
+
+    pub fn id(&self) -> Option<Id> {
+        match self.tuple.0.get(&ATTR_ID_LOCAL) {
+            Some(MemoryValue::Id(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, attr: &str) -> bool {
+        self.value(attr).is_some()
+    }
+
+    /// Get a copy of a single attribute value.
+    pub fn get(&self, attr: &str) -> Option<Value> {
+        self.value(attr).map(Value::from)
+    }
+
+    /// Get a string attribute without copying it.
+    pub fn get_str(&self, attr: &str) -> Option<&'a str> {
+        match self.value(attr)? {
+            MemoryValue::String(value) => Some(value.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Names of the attributes present on the entity.
+    pub fn attributes(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let reg = self.reg;
+        self.tuple
+            .0
+            .keys()
+            .map(move |id| reg.attr(*id).schema.ident.as_str())
+    }
+
+    /// Copy the full entity data.
+    pub fn to_data_map(&self) -> DataMap {
+        super::store::MemoryStore::tuple_to_data_map_with(self.reg, self.tuple)
+    }
+}
@@ -4,13 +4,16 @@ pub mod memory;
 #[cfg(feature = "log")]
 pub mod log;
 
+use std::collections::{BTreeMap, HashMap};
+
 use crate::{
-    registry::{LocalIndexId, SharedRegistry},
+    registry::{LocalAttributeId, LocalIndexId, SharedRegistry},
     util::VecSet,
 };
 
 use factor_core::{
     data::{patch::Patch, DataMap, Id, IdOrIdent, Value},
+    db::{ClassStats, EntityVersion, HealthReport},
     query::{self, expr::Expr, migrate::Migration, select::Item},
     schema,
 };
@@ -29,15 +32,75 @@ pub trait Dao: Send + 'static {
     // fn into_data_map(self) -> DataMap;
 }
 
+/// Storage of an [`crate::Engine`].
+///
+/// # Consistency
+///
+/// Batches and migrations are atomic for readers: every read observes the
+/// state either before or after a write.
+/// Reads must never observe a partially applied write, or a write that is
+/// reverted because it failed, eg. because it could not be persisted.
 pub trait Backend {
     fn registry(&self) -> &SharedRegistry;
 
     fn entity(&self, id: IdOrIdent) -> BackendFuture<Option<DataMap>>;
+
+    /// Load multiple entities at once.
+    /// Missing entities are mapped to `None`.
+    fn entities(&self, ids: Vec<IdOrIdent>) -> BackendFuture<HashMap<IdOrIdent, Option<DataMap>>>;
     fn select(&self, query: query::select::Select) -> BackendFuture<query::select::Page<Item>>;
 
     fn select_map(&self, query: query::select::Select) -> BackendFuture<Vec<DataMap>>;
 
     fn apply_batch(&self, batch: query::mutate::Batch) -> BackendFuture<()>;
+
+    /// Apply a batch and record an audit entry for every change.
+    ///
+    /// The old values are captured while the batch is applied, so concurrent
+    /// writes can not interfere, and the entries are written atomically with
+    /// the batch.
+    ///
+    /// Fails by default.
+    fn apply_batch_audited(
+        &self,
+        _batch: query::mutate::Batch,
+        _audit: Audit,
+    ) -> BackendFuture<()> {
+        Box::pin(futures::future::ready(Err(anyhow::anyhow!(
+            "Audit logs are not supported by this backend"
+        ))))
+    }
+
+    /// Apply a batch with partial rollback at savepoints.
+    ///
+    /// Returns the result of each segment, as returned by
+    /// [`query::mutate::Batch::into_segments`].
+    /// Audit entries are recorded per segment, see
+    /// [`Self::apply_batch_audited`].
+    ///
+    /// The default implementation applies each segment as a separate batch.
+    fn apply_batch_with_savepoints(
+        &self,
+        batch: query::mutate::Batch,
+        audit: Option<Audit>,
+    ) -> BackendFuture<Vec<Result<(), anyhow::Error>>> {
+        let futures = batch
+            .into_segments()
+            .into_iter()
+            .map(|segment| match audit {
+                Some(audit) => self.apply_batch_audited(segment, audit),
+                None => self.apply_batch(segment),
+            })
+            .collect::<Vec<_>>();
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(futures.len());
+            for future in futures {
+                results.push(future.await);
+            }
+            Ok(results)
+        })
+    }
+
     fn migrate(&self, migration: query::migrate::Migration) -> BackendFuture<()>;
 
     fn purge_all_data(&self) -> BackendFuture<()>;
@@ -53,11 +116,248 @@ pub trait Backend {
 
     /// The full database size in the backing storage.
     fn storage_usage(&self) -> BackendFuture<Option<u64>>;
+
+    /// Collect statistics about the stored data.
+    fn stats(&self) -> BackendFuture<BackendStats>;
+
+    /// Check the stored data for inconsistencies.
+    ///
+    /// Verifies that all indexes match the entity data and that all
+    /// entities are valid according to the current schema.
+    fn verify(&self) -> BackendFuture<Vec<VerifyIssue>>;
+
+    /// Check the health of the backend.
+    ///
+    /// Must not fail because of an unhealthy storage, but report it in the
+    /// [`HealthReport`].
+    fn health(&self) -> BackendFuture<HealthReport>;
+
+    /// Load all versions of an entity, oldest first.
+    ///
+    /// Only supported by backends that keep history.
+    fn entity_history(&self, _id: Id) -> BackendFuture<Vec<EntityVersion>> {
+        Box::pin(futures::future::ready(Err(anyhow::anyhow!(
+            "Entity history is not supported by this backend"
+        ))))
+    }
+
+    /// Live entity counts per class.
+    ///
+    /// See [`factor_core::db::Db::class_stats`].
+    fn class_stats(&self) -> BackendFuture<Vec<ClassStats>> {
+        Box::pin(futures::future::ready(Err(anyhow::anyhow!(
+            "Class stats are not supported by this backend"
+        ))))
+    }
+
+    /// Read the materialized entities of a view.
+    ///
+    /// `filter` further restricts the entities, eg. for policies.
+    fn select_view(
+        &self,
+        _name: String,
+        _filter: Option<Expr>,
+    ) -> BackendFuture<query::select::Page<Item>> {
+        Box::pin(futures::future::ready(Err(anyhow::anyhow!(
+            "Views are not supported by this backend"
+        ))))
+    }
+
+    /// Find the shortest path between two entities.
+    ///
+    /// `filter` restricts the entities that can be part of the path, eg. for
+    /// policies.
+    fn shortest_path(
+        &self,
+        _query: query::graph::GraphQuery,
+        _filter: Option<Expr>,
+    ) -> BackendFuture<Option<Vec<Id>>> {
+        Box::pin(futures::future::ready(Err(anyhow::anyhow!(
+            "Graph queries are not supported by this backend"
+        ))))
+    }
+}
+
+/// Audit log settings for [`Backend::apply_batch_audited`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Audit {
+    /// The user recorded as the actor of the changes.
+    pub actor: Option<Id>,
+}
+
+/// Introspection data returned by [`Backend::stats`].
+#[derive(serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackendStats {
+    /// Total number of entities, including schema entities.
+    pub entity_count: u64,
+    /// Number of entities per class ident.
+    /// Entities without a type are not included.
+    pub entities_by_class: BTreeMap<String, u64>,
+    pub indexes: Vec<IndexStats>,
+    /// Estimated memory usage in bytes.
+    pub memory_usage: Option<u64>,
+    /// Full size of the log in bytes, for log based backends.
+    /// See [`log::LogStore::size_log`].
+    pub size_log: Option<u64>,
+    /// Size of the data without log overhead and overwritten data.
+    /// See [`log::LogStore::size_data`].
+    pub size_data: Option<u64>,
+    /// Query execution statistics since the backend was opened.
+    pub queries: QueryStats,
+}
+
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexStats {
+    pub ident: String,
+    pub unique: bool,
+    /// Number of indexed values.
+    pub entries: u64,
+    /// Estimated memory usage in bytes.
+    pub memory_usage: u64,
+    /// Number of executed query plan nodes that read the index.
+    ///
+    /// Indexes that stay at zero are not used by any query.
+    pub usage: u64,
+}
+
+/// Query execution statistics, see [`BackendStats::queries`].
+#[derive(serde::Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of executed query plans.
+    pub queries: u64,
+    /// Number of full scans over all entities.
+    ///
+    /// A high number relative to the executed queries indicates that
+    /// queries can not use an index.
+    pub full_scans: u64,
+    /// Number of index lookups and index scans.
+    pub index_reads: u64,
+    /// Number of executions per plan node kind, like `scan` or
+    /// `index_select`.
+    pub nodes: BTreeMap<String, u64>,
+    /// Number of full scans that look up an attribute by value, per
+    /// attribute ident.
+    ///
+    /// Used for index suggestions, see
+    /// [`crate::Engine::index_suggestions`].
+    pub scan_lookups: BTreeMap<String, u64>,
+}
+
+/// An inconsistency found by [`Backend::verify`].
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VerifyIssue {
+    /// An entity value is not present in an index on the attribute.
+    IndexEntryMissing {
+        index: String,
+        entity: Id,
+        value: Value,
+    },
+    /// An index entry points to an entity that does not have the value.
+    IndexEntryStale {
+        index: String,
+        entity: Id,
+        value: Value,
+    },
+    /// An index entry points to an entity that does not exist.
+    IndexEntryDangling {
+        index: String,
+        entity: Id,
+        value: Value,
+    },
+    /// Multiple entities share a value of a unique index, like an ident.
+    DuplicateUniqueValue {
+        index: String,
+        value: Value,
+        entities: Vec<Id>,
+    },
+    /// The entity data does not conform to the schema.
+    InvalidEntity { entity: Id, error: String },
+    /// A log event could not be decoded.
+    LogEventInvalid { line: u64, error: String },
+    /// A log event id is not greater than the id of the previous event.
+    LogEventOutOfOrder {
+        line: u64,
+        id: u64,
+        previous_id: u64,
+    },
+}
+
+impl VerifyIssue {
+    /// Issues in the log can be repaired by rewriting the log, all other
+    /// issues are caused by the data itself.
+    pub fn is_log_issue(&self) -> bool {
+        matches!(
+            self,
+            Self::LogEventInvalid { .. } | Self::LogEventOutOfOrder { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IndexEntryMissing {
+                index,
+                entity,
+                value,
+            } => write!(
+                f,
+                "index '{}' is missing value {:?} of entity {}",
+                index, value, entity
+            ),
+            Self::IndexEntryStale {
+                index,
+                entity,
+                value,
+            } => write!(
+                f,
+                "index '{}' contains value {:?} for entity {}, but the entity does not have this value",
+                index, value, entity
+            ),
+            Self::IndexEntryDangling {
+                index,
+                entity,
+                value,
+            } => write!(
+                f,
+                "index '{}' contains value {:?} for entity {}, which does not exist",
+                index, value, entity
+            ),
+            Self::DuplicateUniqueValue {
+                index,
+                value,
+                entities,
+            } => write!(
+                f,
+                "unique index '{}' has value {:?} for multiple entities: {:?}",
+                index, value, entities
+            ),
+            Self::InvalidEntity { entity, error } => {
+                write!(f, "entity {} is invalid: {}", entity, error)
+            }
+            Self::LogEventInvalid { line, error } => {
+                write!(f, "log event on line {} could not be decoded: {}", line, error)
+            }
+            Self::LogEventOutOfOrder {
+                line,
+                id,
+                previous_id,
+            } => write!(
+                f,
+                "log event on line {} has id {}, which is not greater than the previous id {}",
+                line, id, previous_id
+            ),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct TupleIndexInsert {
     pub index: LocalIndexId,
+    /// The attribute the value belongs to.
+    /// Reported in unique constraint violations.
+    pub attribute: LocalAttributeId,
     pub value: Value,
     pub unique: bool,
 }
@@ -65,6 +365,9 @@ pub struct TupleIndexInsert {
 #[derive(Clone, Debug)]
 pub struct TupleIndexReplace {
     pub index: LocalIndexId,
+    /// The attribute the value belongs to.
+    /// Reported in unique constraint violations.
+    pub attribute: LocalAttributeId,
     pub old_value: Value,
     pub value: Value,
     pub unique: bool,
@@ -83,6 +386,16 @@ pub enum TupleIndexOp {
     Remove(TupleIndexRemove),
 }
 
+impl TupleIndexOp {
+    pub fn index(&self) -> LocalIndexId {
+        match self {
+            Self::Insert(op) => op.index,
+            Self::Replace(op) => op.index,
+            Self::Remove(op) => op.index,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TupleCreate {
     pub data: DataMap,
@@ -210,15 +523,30 @@ pub struct ValidateEntityExists {
 #[derive(Clone, Debug)]
 pub struct ValidateEntityType {
     pub id: Id,
+    /// The attribute that holds the reference.
+    pub attribute: LocalAttributeId,
     // TODO: this should be an Arc<_> to prevent cloning overhead
     // TODO: use LocalEntityId instead of string id?
     pub allowed_types: VecSet<Id>,
 }
 
+/// Validate that following `attribute` from the `targets` does not lead
+/// back to `entity` within `max_depth` levels.
+///
+/// See [`factor_core::data::value_type::ConstrainedRefType::acyclic_depth`].
+#[derive(Clone, Debug)]
+pub struct ValidateAcyclic {
+    pub entity: Id,
+    pub attribute: LocalAttributeId,
+    pub targets: Vec<Id>,
+    pub max_depth: u32,
+}
+
 #[derive(Clone, Debug)]
 pub enum DbOp {
     ValidateEntityExists(ValidateEntityExists),
     ValidateEntityType(ValidateEntityType),
+    ValidateAcyclic(ValidateAcyclic),
     Tuple(TupleOp),
     Select(SelectOp),
     IndexPopulate(IndexPopulate),
@@ -0,0 +1,464 @@
+//! External storage for large [`Value::Bytes`] values.
+//!
+//! See [`crate::Engine::with_blob_store`].
+
+pub mod store_memory;
+
+#[cfg(feature = "blob_fs")]
+pub mod store_file;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use futures::future::BoxFuture;
+
+use factor_core::{
+    data::{patch::PatchOp, DataMap, Id, IdOrIdent, Value},
+    query::{
+        mutate::{Batch, Mutate, MutateSelectAction},
+        select::{Item, Select},
+    },
+};
+
+use crate::backend::Backend;
+
+/// Storage for large binary values.
+///
+/// Blobs are addressed by a random [`Id`] assigned by the engine, and are
+/// never modified after they have been written.
+pub trait BlobStore: Send + Sync {
+    fn put(&self, id: Id, data: Vec<u8>) -> BoxFuture<'_, Result<(), anyhow::Error>>;
+
+    /// Load a blob.
+    /// Returns `None` if the blob does not exist.
+    fn get(&self, id: Id) -> BoxFuture<'_, Result<Option<Vec<u8>>, anyhow::Error>>;
+
+    /// Delete a blob.
+    /// Deleting a blob that does not exist is not an error.
+    fn delete(&self, id: Id) -> BoxFuture<'_, Result<(), anyhow::Error>>;
+
+    /// List the ids of all stored blobs.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Id>, anyhow::Error>>;
+}
+
+/// Marks a [`Value::Bytes`] value as a reference to a blob.
+const HANDLE_MAGIC: &[u8] = b"\0factor/blob\0";
+const HANDLE_LEN: usize = HANDLE_MAGIC.len() + 16 + 8;
+
+/// Reference to a blob, stored in place of the offloaded bytes.
+///
+/// Handles are regular [`Value::Bytes`] values, so they pass the type
+/// validation of the backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BlobHandle {
+    id: Id,
+    len: u64,
+}
+
+impl BlobHandle {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HANDLE_LEN);
+        out.extend_from_slice(HANDLE_MAGIC);
+        out.extend_from_slice(self.id.as_uuid().as_bytes());
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != HANDLE_LEN {
+            return None;
+        }
+        let rest = bytes.strip_prefix(HANDLE_MAGIC)?;
+        let (id, len) = rest.split_at(16);
+        Some(Self {
+            id: Id::from_uuid(uuid::Uuid::from_slice(id).ok()?),
+            len: u64::from_le_bytes(len.try_into().ok()?),
+        })
+    }
+}
+
+/// Mutable version of [`visit_bytes`].
+fn visit_bytes_mut(value: &mut Value, f: &mut impl FnMut(&mut Vec<u8>)) {
+    match value {
+        Value::Bytes(bytes) => f(bytes),
+        Value::List(items) => items.iter_mut().for_each(|item| visit_bytes_mut(item, f)),
+        Value::Map(map) => map.0.values_mut().for_each(|item| visit_bytes_mut(item, f)),
+        _ => {}
+    }
+}
+
+/// Call `f` for all byte values, including values nested in lists and maps.
+fn visit_bytes(value: &Value, f: &mut impl FnMut(&[u8])) {
+    match value {
+        Value::Bytes(bytes) => f(bytes),
+        Value::List(items) => items.iter().for_each(|item| visit_bytes(item, f)),
+        Value::Map(map) => map.0.values().for_each(|item| visit_bytes(item, f)),
+        _ => {}
+    }
+}
+
+fn collect_handles(data: &DataMap, out: &mut HashSet<Id>) {
+    for value in data.0.values() {
+        visit_bytes(value, &mut |bytes| {
+            if let Some(handle) = BlobHandle::decode(bytes) {
+                out.insert(handle.id);
+            }
+        });
+    }
+}
+
+/// Collect the data of select items, including joined items.
+pub(crate) fn items_data_mut(items: &mut [Item]) -> Vec<&mut DataMap> {
+    fn collect<'a>(items: &'a mut [Item], out: &mut Vec<&'a mut DataMap>) {
+        for item in items {
+            out.push(&mut item.data);
+            for join in &mut item.joins {
+                collect(&mut join.items, out);
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    collect(items, &mut out);
+    out
+}
+
+/// Blob store configuration of an engine.
+#[derive(Clone)]
+pub(crate) struct Blobs {
+    store: Arc<dyn BlobStore + 'static>,
+    threshold: usize,
+}
+
+impl Blobs {
+    pub fn new(store: Arc<dyn BlobStore + 'static>, threshold: usize) -> Self {
+        Self { store, threshold }
+    }
+
+    /// Replace byte values above the threshold with blob handles.
+    ///
+    /// Values that happen to look like a handle are always offloaded, so
+    /// stored handles are never ambiguous.
+    fn offload_value(&self, value: &mut Value, pending: &mut Vec<(Id, Vec<u8>)>) {
+        visit_bytes_mut(value, &mut |bytes| {
+            if bytes.len() > self.threshold || BlobHandle::decode(bytes).is_some() {
+                let handle = BlobHandle {
+                    id: Id::random(),
+                    len: u64::try_from(bytes.len()).unwrap_or(u64::MAX),
+                };
+                let data = std::mem::replace(bytes, handle.encode());
+                pending.push((handle.id, data));
+            }
+        });
+    }
+
+    /// Offload large values of a batch to the blob store.
+    ///
+    /// Must be called right before the batch is applied.
+    /// The returned [`BlobWrites`] must be finished after the batch was
+    /// applied.
+    pub async fn offload_batch(
+        &self,
+        backend: &(dyn Backend + Send + Sync),
+        batch: &mut Batch,
+    ) -> Result<BlobWrites, anyhow::Error> {
+        let mut pending = Vec::new();
+        let mut modified = Vec::new();
+
+        for action in &mut batch.actions {
+            match action {
+                Mutate::Create(create) => {
+                    create
+                        .data
+                        .0
+                        .values_mut()
+                        .for_each(|v| self.offload_value(v, &mut pending));
+                }
+                Mutate::Replace(replace) => {
+                    modified.push(replace.id);
+                    replace
+                        .data
+                        .0
+                        .values_mut()
+                        .for_each(|v| self.offload_value(v, &mut pending));
+                }
+                Mutate::Merge(merge) => {
+                    modified.push(merge.id);
+                    merge
+                        .data
+                        .0
+                        .values_mut()
+                        .for_each(|v| self.offload_value(v, &mut pending));
+                }
+                Mutate::Patch(patch) => {
+                    modified.push(patch.id);
+                    self.offload_patch_ops(&mut patch.patch.0, &mut pending);
+                }
+                Mutate::Delete(delete) => {
+                    modified.push(delete.id);
+                }
+                Mutate::Select(select) => {
+                    if let MutateSelectAction::Patch(patch) = &mut select.action {
+                        self.offload_patch_ops(&mut patch.0, &mut pending);
+                    }
+                }
+                Mutate::Savepoint | Mutate::Guard(_) => {}
+            }
+        }
+
+        // Remember the blobs referenced by modified entities, so blobs that
+        // are not referenced anymore can be deleted after the batch.
+        let mut previous = HashSet::new();
+        if !modified.is_empty() {
+            let ids = modified.iter().copied().map(IdOrIdent::from).collect();
+            for data in backend.entities(ids).await?.values().flatten() {
+                collect_handles(data, &mut previous);
+            }
+        }
+
+        let mut written = Vec::with_capacity(pending.len());
+        for (id, data) in pending {
+            if let Err(err) = self.store.put(id, data).await {
+                self.delete_all(written).await;
+                return Err(err);
+            }
+            written.push(id);
+        }
+
+        Ok(BlobWrites {
+            written,
+            modified,
+            previous,
+        })
+    }
+
+    fn offload_patch_ops(&self, ops: &mut [PatchOp], pending: &mut Vec<(Id, Vec<u8>)>) {
+        for op in ops {
+            match op {
+                PatchOp::Add { value, .. } => self.offload_value(value, pending),
+                PatchOp::Replace { new_value, .. } => self.offload_value(new_value, pending),
+                PatchOp::Remove { .. } => {}
+            }
+        }
+    }
+
+    /// Delete blobs, ignoring errors.
+    ///
+    /// Blobs that fail to delete are left for [`Self::collect_garbage`].
+    async fn delete_all(&self, ids: impl IntoIterator<Item = Id>) {
+        for id in ids {
+            if let Err(err) = self.store.delete(id).await {
+                tracing::warn!(blob=%id, error=?err, "could not delete blob");
+            }
+        }
+    }
+
+    /// Replace blob handles with the blob data.
+    ///
+    /// Values with handles of missing blobs are replaced with
+    /// [`Value::Unit`]. This happens for old entity versions and audit
+    /// entries, since blobs are deleted once the current entity does not
+    /// reference them anymore.
+    pub async fn rehydrate<'a>(
+        &self,
+        entities: impl IntoIterator<Item = &'a mut DataMap>,
+    ) -> Result<(), anyhow::Error> {
+        let entities: Vec<_> = entities.into_iter().collect();
+
+        let mut ids = HashSet::new();
+        for data in &entities {
+            collect_handles(data, &mut ids);
+        }
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut blobs = HashMap::new();
+        for id in ids {
+            match self.store.get(id).await? {
+                Some(data) => {
+                    blobs.insert(id, data);
+                }
+                None => {
+                    tracing::debug!(blob=%id, "referenced blob not found");
+                }
+            }
+        }
+
+        for data in entities {
+            for value in data.0.values_mut() {
+                let mut missing = false;
+                visit_bytes_mut(value, &mut |bytes| {
+                    if let Some(handle) = BlobHandle::decode(bytes) {
+                        match blobs.get(&handle.id) {
+                            Some(blob) => *bytes = blob.clone(),
+                            None => missing = true,
+                        }
+                    }
+                });
+                if missing {
+                    *value = Value::Unit;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete all blobs that are not referenced by any entity.
+    ///
+    /// Must not run concurrently with batches, since blobs are written
+    /// before the batch that references them is applied.
+    pub async fn collect_garbage(
+        &self,
+        backend: &(dyn Backend + Send + Sync),
+    ) -> Result<usize, anyhow::Error> {
+        let mut referenced = HashSet::new();
+        for data in backend.select_map(Select::new()).await? {
+            collect_handles(&data, &mut referenced);
+        }
+
+        let orphans: Vec<_> = self
+            .store
+            .list()
+            .await?
+            .into_iter()
+            .filter(|id| !referenced.contains(id))
+            .collect();
+        let count = orphans.len();
+        for id in orphans {
+            self.store.delete(id).await?;
+        }
+        Ok(count)
+    }
+}
+
+/// Blobs written for a batch that is being applied.
+pub(crate) struct BlobWrites {
+    written: Vec<Id>,
+    /// Entities modified or deleted by the batch.
+    modified: Vec<Id>,
+    /// Blobs referenced by the modified entities before the batch.
+    previous: HashSet<Id>,
+}
+
+impl BlobWrites {
+    /// Clean up after the batch was applied.
+    ///
+    /// Deletes the new blobs if the batch failed, or the blobs that are not
+    /// referenced by the modified entities anymore if it succeeded.
+    pub async fn finish(
+        self,
+        blobs: &Blobs,
+        backend: &(dyn Backend + Send + Sync),
+        applied: bool,
+    ) -> Result<(), anyhow::Error> {
+        if !applied {
+            blobs.delete_all(self.written).await;
+            return Ok(());
+        }
+        if self.previous.is_empty() {
+            return Ok(());
+        }
+
+        let ids = self.modified.into_iter().map(IdOrIdent::from).collect();
+        let mut current = HashSet::new();
+        for data in backend.entities(ids).await?.values().flatten() {
+            collect_handles(data, &mut current);
+        }
+        blobs
+            .delete_all(self.previous.difference(&current).copied())
+            .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::ValueType,
+        map,
+        query::migrate::Migration,
+        schema::{Attribute, Class},
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::{store_memory::MemoryBlobStore, *};
+
+    #[test]
+    fn test_blob_handle_roundtrip() {
+        let handle = BlobHandle {
+            id: Id::random(),
+            len: 1234,
+        };
+        let encoded = handle.encode();
+        assert_eq!(encoded.len(), HANDLE_LEN);
+        assert_eq!(BlobHandle::decode(&encoded), Some(handle));
+
+        assert_eq!(BlobHandle::decode(&encoded[1..]), None);
+        assert_eq!(BlobHandle::decode(&[0; HANDLE_LEN]), None);
+    }
+
+    #[tokio::test]
+    async fn test_engine_blob_store() {
+        let store = MemoryBlobStore::new();
+        let engine = Engine::new(MemoryDb::new()).with_blob_store(store.clone(), 8);
+        let backend = engine.backend().clone();
+        let db = engine.clone().into_client();
+        db.migrate(
+            Migration::new()
+                .attr_create(Attribute::new("test/data", ValueType::Bytes))
+                .entity_create(Class::new("test/File")),
+        )
+        .await
+        .unwrap();
+
+        let large = vec![7u8; 100];
+        let id = Id::random();
+        db.create(
+            id,
+            map! { "factor/type": "test/File", "test/data": Value::Bytes(large.clone()) },
+        )
+        .await
+        .unwrap();
+        assert_eq!(store.len(), 1);
+
+        // The backend only stores the handle.
+        let raw = backend.entity(id.into()).await.unwrap().unwrap();
+        let handle = match raw.get("test/data") {
+            Some(Value::Bytes(bytes)) => BlobHandle::decode(bytes).unwrap(),
+            other => panic!("expected bytes, got {:?}", other),
+        };
+        assert_eq!(handle.len, 100);
+
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data.get("test/data"), Some(&Value::Bytes(large.clone())));
+        let items = db.select_map(Select::new()).await.unwrap();
+        assert!(items
+            .iter()
+            .any(|item| item.get("test/data") == Some(&Value::Bytes(large.clone()))));
+
+        // Small values are stored inline, the replaced blob is deleted.
+        db.merge(id, map! { "test/data": Value::Bytes(vec![1, 2]) })
+            .await
+            .unwrap();
+        assert!(store.is_empty());
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data.get("test/data"), Some(&Value::Bytes(vec![1, 2])));
+
+        db.merge(id, map! { "test/data": Value::Bytes(large.clone()) })
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 1);
+        db.delete(id).await.unwrap();
+        assert!(store.is_empty());
+
+        // Orphaned blobs are removed by garbage collection.
+        store.put(Id::random(), vec![1]).await.unwrap();
+        assert_eq!(engine.collect_blobs().await.unwrap(), 1);
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Context;
+use futures::{future::BoxFuture, FutureExt};
+
+use factor_core::data::Id;
+
+/// Blob store that keeps each blob in a separate file in a directory.
+///
+/// Files are named after the blob id.
+pub struct FileBlobStore {
+    path: PathBuf,
+}
+
+impl FileBlobStore {
+    /// Open a blob store in the given directory.
+    /// The directory is created if it does not exist.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        tokio::fs::create_dir_all(&path)
+            .await
+            .with_context(|| format!("Could not create blob directory '{}'", path.display()))?;
+        Ok(Self { path })
+    }
+
+    fn blob_path(&self, id: Id) -> PathBuf {
+        self.path.join(id.to_string())
+    }
+}
+
+impl super::BlobStore for FileBlobStore {
+    fn put(&self, id: Id, data: Vec<u8>) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        async move {
+            // Write to a temporary file first, so a crash never leaves a
+            // partially written blob behind.
+            let path = self.blob_path(id);
+            let tmp_path = path.with_extension("tmp");
+            tokio::fs::write(&tmp_path, data).await?;
+            tokio::fs::rename(&tmp_path, &path).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn get(&self, id: Id) -> BoxFuture<'_, Result<Option<Vec<u8>>, anyhow::Error>> {
+        async move {
+            match tokio::fs::read(self.blob_path(id)).await {
+                Ok(data) => Ok(Some(data)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn delete(&self, id: Id) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        async move {
+            match tokio::fs::remove_file(self.blob_path(id)).await {
+                Ok(()) => Ok(()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(err) => Err(err.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Id>, anyhow::Error>> {
+        async move {
+            let mut ids = Vec::new();
+            let mut entries = tokio::fs::read_dir(&self.path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                // Skips temporary files and unrelated files.
+                if let Some(id) = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| Id::from_str(name).ok())
+                {
+                    ids.push(id);
+                }
+            }
+            Ok(ids)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blob::BlobStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_blob_store() {
+        let dir = std::env::temp_dir().join(format!("factor_blobs_{}", Id::random()));
+        let store = FileBlobStore::open(&dir).await.unwrap();
+
+        let id = Id::random();
+        store.put(id, vec![1, 2, 3]).await.unwrap();
+        assert_eq!(store.get(id).await.unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(store.list().await.unwrap(), vec![id]);
+
+        store.delete(id).await.unwrap();
+        assert_eq!(store.get(id).await.unwrap(), None);
+        store.delete(id).await.unwrap();
+        assert!(store.list().await.unwrap().is_empty());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
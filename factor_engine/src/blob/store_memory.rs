@@ -0,0 +1,55 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use futures::{
+    future::{ready, BoxFuture},
+    FutureExt,
+};
+
+use factor_core::data::Id;
+
+/// In-memory blob store.
+/// Only useful for testing.
+#[derive(Clone, Default)]
+pub struct MemoryBlobStore {
+    blobs: Arc<RwLock<HashMap<Id, Vec<u8>>>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored blobs.
+    pub fn len(&self) -> usize {
+        self.blobs.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl super::BlobStore for MemoryBlobStore {
+    fn put(&self, id: Id, data: Vec<u8>) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        self.blobs.write().unwrap().insert(id, data);
+        ready(Ok(())).boxed()
+    }
+
+    fn get(&self, id: Id) -> BoxFuture<'_, Result<Option<Vec<u8>>, anyhow::Error>> {
+        let data = self.blobs.read().unwrap().get(&id).cloned();
+        ready(Ok(data)).boxed()
+    }
+
+    fn delete(&self, id: Id) -> BoxFuture<'_, Result<(), anyhow::Error>> {
+        self.blobs.write().unwrap().remove(&id);
+        ready(Ok(())).boxed()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Id>, anyhow::Error>> {
+        let ids = self.blobs.read().unwrap().keys().copied().collect();
+        ready(Ok(ids)).boxed()
+    }
+}
@@ -1,27 +1,157 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use factor_core::{
-    data::{DataMap, IdOrIdent},
-    db::{Db, DbClient, DbFuture},
-    query::{self, migrate::Migration, mutate::Batch},
-    schema,
+    data::{DataMap, Id, IdOrIdent, Value},
+    db::{ClassStats, Db, DbClient, DbFuture, EntityVersion, HealthReport},
+    error::{EntityNotFound, PermissionDenied, PolicyViolation},
+    query::{
+        self,
+        migrate::Migration,
+        mutate::{ActionStatus, Batch, BatchReport, Mutate, MutateSelectAction},
+    },
+    schema::{self, AttrMapExt},
 };
 use futures::FutureExt;
 
-use crate::backend::Backend;
+use crate::{
+    admission::Admission,
+    authorizer::{self, Authorizer},
+    backend::{Audit, Backend, BackendStats, VerifyIssue},
+    blob::{self, BlobStore},
+    hooks::{self, Hook},
+    id_generator::{DefaultIdGenerator, IdGenerator},
+    index_suggestions::{self, IndexSuggestion},
+    policy::{self, AuthContext},
+    registry::{NamingPolicy, Registry},
+    rules, telemetry,
+};
 
 #[derive(Clone)]
 pub struct Engine {
     backend: Arc<dyn Backend + Send + Sync + 'static>,
+    hooks: Vec<Arc<dyn Hook + 'static>>,
+    authorizer: Option<Arc<dyn Authorizer + 'static>>,
+    auth: Option<Arc<AuthContext>>,
+    audit: bool,
+    id_generator: Arc<dyn IdGenerator + 'static>,
+    blobs: Option<blob::Blobs>,
+    admission: Option<Arc<Admission>>,
 }
 
 impl Engine {
     pub fn new(backend: impl Backend + Sync + Send + 'static) -> Self {
         Self {
             backend: Arc::new(backend),
+            hooks: Vec::new(),
+            authorizer: None,
+            auth: None,
+            audit: false,
+            id_generator: Arc::new(DefaultIdGenerator),
+            blobs: None,
+            admission: None,
         }
     }
 
+    /// Register a [`Hook`] that runs for every applied batch.
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Set the [`Authorizer`] that is consulted for all reads and mutations.
+    ///
+    /// Replaces a previously set authorizer.
+    pub fn with_authorizer(mut self, authorizer: impl Authorizer + 'static) -> Self {
+        self.authorizer = Some(Arc::new(authorizer));
+        self
+    }
+
+    /// Enforce the row-level security policies of the schema for the given
+    /// caller.
+    ///
+    /// Without an auth context, policies are not enforced.
+    /// Migrations and purges bypass the policies, so they fail with
+    /// [`PermissionDenied`] once an auth context is set.
+    ///
+    /// See [`schema::PolicySchema`].
+    pub fn with_auth_context(mut self, ctx: AuthContext) -> Self {
+        self.auth = Some(Arc::new(ctx));
+        self
+    }
+
+    /// Record every mutation in the audit log.
+    ///
+    /// Each changed entity produces a `factor/AuditEntry` entity with the
+    /// actor (see [`AuthContext::with_user`]), the timestamp, the changed
+    /// attributes and a patch that restores the previous values.
+    /// Entries are written in the same batch as the mutations and can be
+    /// queried with regular selects.
+    pub fn with_audit_log(mut self) -> Self {
+        self.audit = true;
+        self
+    }
+
+    /// Set the [`IdGenerator`] used for entities created with a nil id.
+    ///
+    /// Defaults to [`DefaultIdGenerator`].
+    pub fn with_id_generator(mut self, generator: impl IdGenerator + 'static) -> Self {
+        self.id_generator = Arc::new(generator);
+        self
+    }
+
+    /// Store [`factor_core::data::Value::Bytes`] values larger than
+    /// `threshold` bytes in the given [`BlobStore`].
+    ///
+    /// The backend only stores a small handle in place of the value, and
+    /// reads transparently load the data from the blob store.
+    /// Blobs of modified or deleted entities are removed after each batch.
+    /// See [`Self::collect_blobs`] for cleaning up blobs that were left
+    /// behind, eg. after a crash.
+    ///
+    /// Must be configured before any data is written, since previously
+    /// stored values are not offloaded.
+    pub fn with_blob_store(mut self, store: impl BlobStore + 'static, threshold: usize) -> Self {
+        self.blobs = Some(blob::Blobs::new(Arc::new(store), threshold));
+        self
+    }
+
+    /// Limit the number of batches that are applied concurrently.
+    ///
+    /// Batches beyond the limit wait for up to `timeout` for a slot and
+    /// then fail with [`factor_core::error::Overloaded`].
+    /// Without a timeout they are rejected immediately.
+    ///
+    /// Protects the backend from unbounded queuing on its write lock, and
+    /// lets clients back off instead.
+    /// The limit is shared by all clones of the engine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_pending` is 0.
+    pub fn with_max_pending_batches(
+        mut self,
+        max_pending: usize,
+        timeout: Option<Duration>,
+    ) -> Self {
+        self.admission = Some(Arc::new(Admission::new(max_pending, timeout)));
+        self
+    }
+
+    /// Enforce a [`NamingPolicy`] for schema items created by migrations.
+    ///
+    /// Replaces the policy of the backend registry, so it applies to all
+    /// clients of the backend.
+    pub fn with_naming_policy(self, policy: NamingPolicy) -> Self {
+        // Retries if the registry is replaced concurrently, so no changes
+        // are lost.
+        self.backend.registry().rcu(|reg| {
+            let mut reg = Registry::clone(reg);
+            reg.set_naming_policy(policy.clone());
+            reg
+        });
+        self
+    }
+
     pub fn into_client(self) -> Db {
         Db::new(self)
     }
@@ -31,41 +161,552 @@ impl Engine {
     }
 
     pub fn schema(&self) -> Result<schema::DbSchema, anyhow::Error> {
-        let reg = {
-            self.backend()
-                .registry()
-                .read()
-                .map_err(|_| anyhow::Error::msg("Could not retrieve registry"))?
-                .clone()
+        let reg = self.backend().registry().load();
+        Ok(reg.build_schema())
+    }
+
+    /// Run `f` with the current registry.
+    fn with_registry<T>(&self, f: impl FnOnce(&Registry) -> T) -> Result<T, anyhow::Error> {
+        let reg = self.backend.registry().load();
+        Ok(f(&reg))
+    }
+
+    fn authorize_read<'a>(
+        &self,
+        entities: impl IntoIterator<Item = &'a DataMap>,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(authorizer) = &self.authorizer {
+            self.with_registry(|reg| {
+                entities.into_iter().try_for_each(|data| {
+                    authorizer.authorize_with_context(
+                        &authorizer::read_access(reg, data),
+                        self.auth.as_deref(),
+                    )
+                })
+            })??;
+        }
+        Ok(())
+    }
+
+    /// Replace nil ids of created entities with generated ones.
+    ///
+    /// Ids are assigned before the batch is applied, so policies, hooks and
+    /// the stored log all see the final id.
+    fn assign_ids(&self, batch: &mut Batch) -> Result<(), anyhow::Error> {
+        let has_nil = batch
+            .actions
+            .iter()
+            .any(|action| matches!(action, Mutate::Create(create) if create.id.is_nil()));
+        if !has_nil {
+            return Ok(());
+        }
+
+        self.with_registry(|reg| {
+            for action in &mut batch.actions {
+                if let Mutate::Create(create) = action {
+                    if create.id.is_nil() {
+                        let class = create
+                            .data
+                            .get_type()
+                            .and_then(|ty| reg.entity_by_ident(&ty))
+                            .map(|class| &class.schema);
+                        create.id = self.id_generator.generate(class);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Hide entities that are not allowed by the policies.
+    fn apply_read_policies(&self, data: Option<DataMap>) -> Result<Option<DataMap>, anyhow::Error> {
+        match (&self.auth, data) {
+            (Some(ctx), Some(data)) => {
+                let allowed = self.with_registry(|reg| {
+                    policy::find_violation(reg, ctx, &data).map(|v| v.is_none())
+                })??;
+                Ok(if allowed { Some(data) } else { None })
+            }
+            (_, data) => Ok(data),
+        }
+    }
+
+    fn apply_select_policies(
+        &self,
+        mut query: query::select::Select,
+    ) -> Result<query::select::Select, anyhow::Error> {
+        if let Some(ctx) = &self.auth {
+            if let Some(filter) = self.with_registry(|reg| policy::select_filter(reg, ctx))?? {
+                query.filter = Some(match query.filter.take() {
+                    Some(existing) => existing.and_with(filter),
+                    None => filter,
+                });
+            }
+        }
+        Ok(query)
+    }
+
+    /// Enforce the policies for all mutations of a batch.
+    ///
+    /// Modified entities must be visible to the caller, and the resulting
+    /// entity data must satisfy the policies.
+    ///
+    /// Both are enforced with a [`Guard`](query::mutate::Guard) before and
+    /// after each modification, which are checked while the batch is
+    /// applied, so concurrent writes can not bypass the policies.
+    /// [`Mutate::Select`] only matches visible entities, and patched
+    /// entities are checked with [`query::mutate::MutateSelect::guard`].
+    ///
+    /// The resulting data is also checked up front, which reports the
+    /// violated policy in a [`PolicyViolation`].
+    async fn apply_batch_policies(
+        &self,
+        ctx: &AuthContext,
+        batch: &mut Batch,
+    ) -> Result<(), anyhow::Error> {
+        let filter = match self.with_registry(|reg| policy::select_filter(reg, ctx))?? {
+            Some(filter) => filter,
+            None => return Ok(()),
         };
 
-        Ok(reg.build_schema())
+        let ids = batch
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                Mutate::Create(_) | Mutate::Select(_) => None,
+                other => other.entity_id().map(IdOrIdent::from),
+            })
+            .collect::<Vec<_>>();
+        let mut existing = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.backend.entities(ids).await?
+        };
+        for (id, data) in &mut existing {
+            if let Some(entity) = data.take() {
+                *data = self.apply_read_policies(Some(entity))?;
+                if data.is_none() {
+                    return Err(EntityNotFound::new(id.clone()).into());
+                }
+            }
+        }
+
+        let reg = self.backend.registry().load();
+        let existing = |id: Id| existing.get(&IdOrIdent::from(id)).and_then(Option::as_ref);
+
+        for action in &batch.actions {
+            let (id, data) = match action {
+                Mutate::Create(create) => (create.id, create.data.clone()),
+                Mutate::Replace(replace) => (replace.id, replace.data.clone()),
+                Mutate::Merge(merge) => {
+                    let mut data = existing(merge.id).cloned().unwrap_or_default();
+                    data.extend(merge.data.clone());
+                    (merge.id, data)
+                }
+                Mutate::Patch(patch) => {
+                    let data = existing(patch.id).cloned().unwrap_or_default();
+                    (patch.id, patch.patch.clone().apply_map(data)?)
+                }
+                Mutate::Delete(_) | Mutate::Savepoint | Mutate::Guard(_) | Mutate::Select(_) => {
+                    continue
+                }
+            };
+            if let Some(policy) = policy::find_violation(&reg, ctx, &data)? {
+                return Err(PolicyViolation {
+                    entity: id,
+                    policy: policy.ident.clone(),
+                }
+                .into());
+            }
+        }
+
+        let mut actions = Vec::with_capacity(batch.actions.len());
+        for mut action in std::mem::take(&mut batch.actions) {
+            let (visible_before, valid_after) = match &mut action {
+                Mutate::Create(_) => (false, true),
+                Mutate::Replace(_) | Mutate::Merge(_) | Mutate::Patch(_) => (true, true),
+                Mutate::Delete(_) => (true, false),
+                Mutate::Select(select) => {
+                    select.filter = select.filter.clone().and_with(filter.clone());
+                    if matches!(select.action, MutateSelectAction::Patch(_)) {
+                        select.guard = Some(match select.guard.take() {
+                            Some(guard) => guard.and_with(filter.clone()),
+                            None => filter.clone(),
+                        });
+                    }
+                    (false, false)
+                }
+                Mutate::Savepoint | Mutate::Guard(_) => (false, false),
+            };
+            let id = action.entity_id();
+
+            if let (true, Some(id)) = (visible_before, id) {
+                actions.push(Mutate::guard(id, filter.clone()));
+            }
+            actions.push(action);
+            if let (true, Some(id)) = (valid_after, id) {
+                actions.push(Mutate::guard(id, filter.clone()));
+            }
+        }
+        batch.actions = actions;
+
+        Ok(())
+    }
+
+    async fn authorize_batch(
+        &self,
+        authorizer: &dyn Authorizer,
+        batch: &Batch,
+    ) -> Result<(), anyhow::Error> {
+        let ids = batch
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                Mutate::Create(_) | Mutate::Select(_) => None,
+                other => other.entity_id().map(IdOrIdent::from),
+            })
+            .collect::<Vec<_>>();
+        let existing = if ids.is_empty() {
+            HashMap::new()
+        } else {
+            self.backend.entities(ids).await?
+        };
+
+        let mut selected = HashMap::new();
+        for (index, action) in batch.actions.iter().enumerate() {
+            if let Mutate::Select(select) = action {
+                let query = query::select::Select {
+                    filter: Some(select.filter.clone()),
+                    variables: select.variables.clone(),
+                    ..query::select::Select::new()
+                };
+                selected.insert(index, self.backend.select_map(query).await?);
+            }
+        }
+
+        let accesses =
+            self.with_registry(|reg| authorizer::batch_accesses(reg, batch, &existing, &selected))?;
+        accesses
+            .iter()
+            .try_for_each(|access| authorizer.authorize_with_context(access, self.auth.as_deref()))
     }
 
     pub async fn entity(&self, id: IdOrIdent) -> Result<Option<DataMap>, anyhow::Error> {
-        self.backend.entity(id).await
+        let span = telemetry::span("entity");
+        telemetry::traced(span, |data| usize::from(data.is_some()), async move {
+            let data = self.backend.entity(id).await?;
+            let mut data = self.apply_read_policies(data)?;
+            self.authorize_read(data.as_ref())?;
+            if let Some(blobs) = &self.blobs {
+                blobs.rehydrate(data.as_mut()).await?;
+            }
+            Ok(data)
+        })
+        .await
+    }
+
+    pub async fn entities(
+        &self,
+        ids: Vec<IdOrIdent>,
+    ) -> Result<HashMap<IdOrIdent, Option<DataMap>>, anyhow::Error> {
+        let span = telemetry::span("entities");
+        telemetry::traced(
+            span,
+            |entities| entities.values().flatten().count(),
+            async move {
+                let mut entities = self.backend.entities(ids).await?;
+                if self.auth.is_some() {
+                    for data in entities.values_mut() {
+                        *data = self.apply_read_policies(data.take())?;
+                    }
+                }
+                self.authorize_read(entities.values().flatten())?;
+                if let Some(blobs) = &self.blobs {
+                    blobs.rehydrate(entities.values_mut().flatten()).await?;
+                }
+                Ok(entities)
+            },
+        )
+        .await
     }
 
     pub async fn select(
         &self,
         query: query::select::Select,
     ) -> Result<query::select::Page<query::select::Item>, anyhow::Error> {
-        self.backend.select(query).await
+        let span = telemetry::select_span("select", &query);
+        telemetry::traced(span, |page| page.items.len(), async move {
+            let query = self.apply_select_policies(query)?;
+            let mut page = self.backend.select(query).await?;
+            if let Some(authorizer) = &self.authorizer {
+                self.with_registry(|reg| {
+                    authorizer::authorize_items(
+                        authorizer.as_ref(),
+                        self.auth.as_deref(),
+                        reg,
+                        &page.items,
+                    )
+                })??;
+            }
+            if let Some(blobs) = &self.blobs {
+                blobs
+                    .rehydrate(blob::items_data_mut(&mut page.items))
+                    .await?;
+            }
+            Ok(page)
+        })
+        .await
+    }
+
+    /// Read a materialized view.
+    ///
+    /// Policies and the authorizer apply to the entities of the view like
+    /// for regular selects.
+    pub async fn select_view(
+        &self,
+        name: String,
+    ) -> Result<query::select::Page<query::select::Item>, anyhow::Error> {
+        let span = telemetry::span("select_view");
+        telemetry::traced(span, |page| page.items.len(), async move {
+            let filter = match &self.auth {
+                Some(ctx) => self.with_registry(|reg| policy::select_filter(reg, ctx))??,
+                None => None,
+            };
+            let mut page = self.backend.select_view(name, filter).await?;
+            if let Some(authorizer) = &self.authorizer {
+                self.with_registry(|reg| {
+                    authorizer::authorize_items(
+                        authorizer.as_ref(),
+                        self.auth.as_deref(),
+                        reg,
+                        &page.items,
+                    )
+                })??;
+            }
+            if let Some(blobs) = &self.blobs {
+                blobs
+                    .rehydrate(blob::items_data_mut(&mut page.items))
+                    .await?;
+            }
+            Ok(page)
+        })
+        .await
+    }
+
+    /// Find the shortest path between two entities.
+    ///
+    /// Entities hidden by policies are never part of the path, and the
+    /// authorizer must allow reading all entities on the path.
+    pub async fn shortest_path(
+        &self,
+        query: query::graph::GraphQuery,
+    ) -> Result<Option<Vec<Id>>, anyhow::Error> {
+        let span = telemetry::span("shortest_path");
+        telemetry::traced(span, |path| path.as_ref().map_or(0, Vec::len), async move {
+            let filter = match &self.auth {
+                Some(ctx) => self.with_registry(|reg| policy::select_filter(reg, ctx))??,
+                None => None,
+            };
+            let path = self.backend.shortest_path(query, filter).await?;
+            if let (Some(path), Some(_)) = (&path, &self.authorizer) {
+                let ids = path.iter().copied().map(IdOrIdent::from).collect();
+                let entities = self.backend.entities(ids).await?;
+                self.authorize_read(entities.values().flatten())?;
+            }
+            Ok(path)
+        })
+        .await
     }
 
     pub async fn select_map(
         &self,
         query: query::select::Select,
     ) -> Result<Vec<DataMap>, anyhow::Error> {
-        self.backend.select_map(query).await
+        let span = telemetry::select_span("select_map", &query);
+        telemetry::traced(span, Vec::len, async move {
+            let query = self.apply_select_policies(query)?;
+            let mut items = self.backend.select_map(query).await?;
+            self.authorize_read(&items)?;
+            if let Some(blobs) = &self.blobs {
+                blobs.rehydrate(&mut items).await?;
+            }
+            Ok(items)
+        })
+        .await
+    }
+
+    pub async fn batch(&self, mut batch: query::mutate::Batch) -> Result<(), anyhow::Error> {
+        let span = telemetry::span("batch");
+        let mutations = batch.actions.len();
+        telemetry::traced(span, |_| mutations, async move {
+            let _permit = match &self.admission {
+                Some(admission) => Some(admission.acquire().await?),
+                None => None,
+            };
+            let blob_writes = self.prepare_batch(&mut batch).await?;
+
+            if self.hooks.is_empty() && self.blobs.is_none() {
+                return self.apply_prepared(batch).await;
+            }
+
+            let res = self.apply_prepared(batch.clone()).await;
+            self.finish_batch(&batch, blob_writes, res.is_ok()).await?;
+            res
+        })
+        .await
+    }
+
+    /// Apply a batch with partial rollback at savepoints.
+    ///
+    /// Each segment is prepared separately, so a segment that is rejected,
+    /// eg. by a policy or a hook, is rolled back without affecting the
+    /// others. The prepared segments are applied by the backend in a single
+    /// write.
+    ///
+    /// See [`Db::batch_with_savepoints`].
+    pub async fn batch_with_savepoints(&self, batch: Batch) -> Result<BatchReport, anyhow::Error> {
+        let span = telemetry::span("batch_with_savepoints");
+        let mutations = batch.actions.len();
+        telemetry::traced(span, |_| mutations, async move {
+            let _permit = match &self.admission {
+                Some(admission) => Some(admission.acquire().await?),
+                None => None,
+            };
+
+            let mut segments = Vec::new();
+            for mut segment in batch.into_segments() {
+                let len = segment.actions.len();
+                let prepared = if segment.actions.is_empty() {
+                    Ok((segment, None))
+                } else {
+                    self.prepare_batch(&mut segment)
+                        .await
+                        .map(|blob_writes| (segment, blob_writes))
+                };
+                segments.push((len, prepared));
+            }
+
+            // Rejected segments are left empty, so the segments still match
+            // the backend results.
+            let mut prepared_batch = Batch::new();
+            for (index, (_, prepared)) in segments.iter().enumerate() {
+                if index > 0 {
+                    prepared_batch.actions.push(Mutate::Savepoint);
+                }
+                if let Ok((segment, _)) = prepared {
+                    prepared_batch
+                        .actions
+                        .extend(segment.actions.iter().cloned());
+                }
+            }
+            let results = self
+                .backend
+                .apply_batch_with_savepoints(prepared_batch, self.audit())
+                .await?;
+
+            let mut actions = Vec::with_capacity(mutations);
+            for (index, ((len, prepared), res)) in segments.into_iter().zip(results).enumerate() {
+                // The savepoint marker preceding the segment.
+                if index > 0 {
+                    actions.push(ActionStatus::Applied);
+                }
+                let res = match prepared {
+                    Ok((segment, blob_writes)) => {
+                        self.finish_batch(&segment, blob_writes, res.is_ok())
+                            .await?;
+                        res
+                    }
+                    Err(err) => Err(err),
+                };
+                let status = match res {
+                    Ok(()) => ActionStatus::Applied,
+                    Err(err) => ActionStatus::RolledBack {
+                        error: format!("{:#}", err),
+                    },
+                };
+                actions.extend(std::iter::repeat(status).take(len));
+            }
+            Ok(BatchReport { actions })
+        })
+        .await
+    }
+
+    /// Prepare a batch before it is applied by the backend.
+    ///
+    /// Assigns ids, applies policies, authorization and rules, and runs the
+    /// `before_*` hooks and blob offloading.
+    /// The returned [`blob::BlobWrites`] must be passed to
+    /// [`Self::finish_batch`].
+    async fn prepare_batch(
+        &self,
+        batch: &mut Batch,
+    ) -> Result<Option<blob::BlobWrites>, anyhow::Error> {
+        self.assign_ids(batch)?;
+        if let Some(ctx) = &self.auth {
+            self.apply_batch_policies(ctx, batch).await?;
+        }
+        if let Some(authorizer) = &self.authorizer {
+            self.authorize_batch(authorizer.as_ref(), batch).await?;
+        }
+        // Rules are trusted schema items, so their writes are not subject
+        // to policies and authorization.
+        rules::apply_rules(self.backend.as_ref(), self.id_generator.as_ref(), batch).await?;
+
+        for hook in &self.hooks {
+            hooks::run_before(hook.as_ref(), batch)?;
+        }
+        // Hooks see the full values, offloading happens right before
+        // the batch is stored.
+        let blob_writes = match &self.blobs {
+            Some(blobs) => Some(blobs.offload_batch(self.backend.as_ref(), batch).await?),
+            None => None,
+        };
+        Ok(blob_writes)
     }
 
-    pub async fn batch(&self, batch: query::mutate::Batch) -> Result<(), anyhow::Error> {
-        self.backend.apply_batch(batch).await
+    /// The audit log settings, if enabled.
+    fn audit(&self) -> Option<Audit> {
+        self.audit.then(|| Audit {
+            actor: self.auth.as_ref().and_then(|ctx| ctx.user()),
+        })
+    }
+
+    /// Apply a prepared batch, recording the changes in the audit log if
+    /// enabled.
+    async fn apply_prepared(&self, batch: Batch) -> Result<(), anyhow::Error> {
+        match self.audit() {
+            Some(audit) => self.backend.apply_batch_audited(batch, audit).await,
+            None => self.backend.apply_batch(batch).await,
+        }
+    }
+
+    /// Clean up after a prepared batch was applied, and run the
+    /// `after_batch` hooks if it was applied successfully.
+    async fn finish_batch(
+        &self,
+        batch: &Batch,
+        blob_writes: Option<blob::BlobWrites>,
+        applied: bool,
+    ) -> Result<(), anyhow::Error> {
+        if let (Some(blobs), Some(writes)) = (&self.blobs, blob_writes) {
+            writes.finish(blobs, self.backend.as_ref(), applied).await?;
+        }
+        if applied {
+            for hook in &self.hooks {
+                hook.after_batch(batch);
+            }
+        }
+        Ok(())
     }
 
     pub async fn migrate(&self, migration: query::migrate::Migration) -> Result<(), anyhow::Error> {
-        self.backend.migrate(migration).await
+        self.ensure_unrestricted("migrate")?;
+        let span = telemetry::span("migrate");
+        telemetry::traced(
+            span,
+            |_| 0,
+            async move { self.backend.migrate(migration).await },
+        )
+        .await
     }
 
     pub async fn migrations(&self) -> Result<Vec<Migration>, anyhow::Error> {
@@ -77,8 +718,99 @@ impl Engine {
     }
 
     pub async fn purge_all_data(&self) -> Result<(), anyhow::Error> {
+        self.ensure_unrestricted("purge_all_data")?;
         self.backend.purge_all_data().await
     }
+
+    /// Reject operations that bypass the policies for callers with an auth
+    /// context.
+    fn ensure_unrestricted(&self, operation: &'static str) -> Result<(), anyhow::Error> {
+        if self.auth.is_some() {
+            return Err(PermissionDenied { operation }.into());
+        }
+        Ok(())
+    }
+
+    /// Load all versions of an entity, oldest first.
+    ///
+    /// If any version is hidden by the policies, the entity is reported as
+    /// not found.
+    pub async fn entity_history(&self, id: Id) -> Result<Vec<EntityVersion>, anyhow::Error> {
+        let mut history = self.backend.entity_history(id).await?;
+        if self.auth.is_some() {
+            for data in history.iter().filter_map(|version| version.data.clone()) {
+                if self.apply_read_policies(Some(data))?.is_none() {
+                    return Err(EntityNotFound::new(id.into()).into());
+                }
+            }
+        }
+        self.authorize_read(history.iter().filter_map(|version| version.data.as_ref()))?;
+        if let Some(blobs) = &self.blobs {
+            // Blobs of old versions are deleted when the entity changes, so
+            // these values will be unit.
+            blobs
+                .rehydrate(
+                    history
+                        .iter_mut()
+                        .filter_map(|version| version.data.as_mut()),
+                )
+                .await?;
+        }
+        Ok(history)
+    }
+
+    /// Delete all blobs that are not referenced by any entity.
+    ///
+    /// Returns the number of deleted blobs.
+    /// Must not run concurrently with batches.
+    /// Does nothing if no blob store is configured.
+    ///
+    /// See [`Self::with_blob_store`].
+    pub async fn collect_blobs(&self) -> Result<usize, anyhow::Error> {
+        match &self.blobs {
+            Some(blobs) => blobs.collect_garbage(self.backend.as_ref()).await,
+            None => Ok(0),
+        }
+    }
+
+    /// Collect statistics about the stored data.
+    ///
+    /// See [`BackendStats`].
+    pub async fn stats(&self) -> Result<BackendStats, anyhow::Error> {
+        self.backend.stats().await
+    }
+
+    /// Suggest indexes for attributes that queries frequently look up with
+    /// full scans.
+    ///
+    /// Based on the query statistics since the backend was opened, see
+    /// [`QueryStats::scan_lookups`](crate::backend::QueryStats::scan_lookups).
+    pub async fn index_suggestions(&self) -> Result<Vec<IndexSuggestion>, anyhow::Error> {
+        let stats = self.backend.stats().await?;
+        let reg = self.backend.registry().load();
+        Ok(index_suggestions::suggest_indexes(&stats, &reg))
+    }
+
+    /// Live entity counts per class.
+    ///
+    /// See [`Backend::class_stats`].
+    pub async fn class_stats(&self) -> Result<Vec<ClassStats>, anyhow::Error> {
+        self.backend.class_stats().await
+    }
+
+    /// Check the stored data for inconsistencies.
+    ///
+    /// See [`Backend::verify`].
+    pub async fn verify(&self) -> Result<Vec<VerifyIssue>, anyhow::Error> {
+        self.backend.verify().await
+    }
+
+    /// Check the health of the backend.
+    ///
+    /// See [`Backend::health`].
+    pub async fn health(&self) -> Result<HealthReport, anyhow::Error> {
+        self.backend.health().await
+    }
 }
 
 impl DbClient for Engine {
@@ -86,6 +818,20 @@ impl DbClient for Engine {
         self
     }
 
+    /// Applies the variables as the [`AuthContext`], see
+    /// [`Engine::with_auth_context`].
+    fn with_auth_variables(
+        &self,
+        variables: &HashMap<String, Value>,
+    ) -> Option<Arc<dyn DbClient + Send + Sync + 'static>> {
+        let ctx = variables
+            .iter()
+            .fold(AuthContext::new(), |ctx, (name, value)| {
+                ctx.with_variable(name.clone(), value.clone())
+            });
+        Some(Arc::new(self.clone().with_auth_context(ctx)))
+    }
+
     fn schema(&self) -> DbFuture<'_, schema::DbSchema> {
         Box::pin(futures::future::ready(self.schema()))
     }
@@ -94,6 +840,10 @@ impl DbClient for Engine {
         Box::pin(async { self.entity(id).await })
     }
 
+    fn entities(&self, ids: Vec<IdOrIdent>) -> DbFuture<'_, HashMap<IdOrIdent, Option<DataMap>>> {
+        Box::pin(async { self.entities(ids).await })
+    }
+
     fn select(
         &self,
         query: query::select::Select,
@@ -109,6 +859,10 @@ impl DbClient for Engine {
         Box::pin(async { self.batch(batch).await })
     }
 
+    fn batch_with_savepoints(&self, batch: Batch) -> DbFuture<'_, BatchReport> {
+        Box::pin(async { self.batch_with_savepoints(batch).await })
+    }
+
     fn migrate(&self, migration: query::migrate::Migration) -> DbFuture<'_, ()> {
         Box::pin(async { self.migrate(migration).await })
     }
@@ -124,4 +878,24 @@ impl DbClient for Engine {
     fn purge_all_data(&self) -> DbFuture<'_, ()> {
         Box::pin(async { self.purge_all_data().await })
     }
+
+    fn entity_history(&self, id: Id) -> DbFuture<'_, Vec<EntityVersion>> {
+        Box::pin(async move { self.entity_history(id).await })
+    }
+
+    fn class_stats(&self) -> DbFuture<'_, Vec<ClassStats>> {
+        Box::pin(async { self.class_stats().await })
+    }
+
+    fn health(&self) -> DbFuture<'_, HealthReport> {
+        Box::pin(async { self.health().await })
+    }
+
+    fn select_view(&self, name: String) -> DbFuture<'_, query::select::Page<query::select::Item>> {
+        self.select_view(name).boxed()
+    }
+
+    fn shortest_path(&self, query: query::graph::GraphQuery) -> DbFuture<'_, Option<Vec<Id>>> {
+        self.shortest_path(query).boxed()
+    }
 }
@@ -0,0 +1,116 @@
+use factor_core::query::mutate::{Batch, Create, Delete, Mutate};
+
+/// Mutation hooks that run for every batch applied through an [`crate::Engine`].
+///
+/// `before_*` hooks can modify the batch or veto it by returning an error.
+/// The modified batch is then applied atomically, so changes added by a hook
+/// are never persisted without the original mutations.
+///
+/// Hooks are registered with [`crate::Engine::with_hook`] and run in
+/// registration order.
+///
+/// Deletes issued via [`Mutate::Select`] do not run `before_delete`, since the
+/// affected entities are only known during application.
+pub trait Hook: Send + Sync {
+    /// Runs for each [`Mutate::Create`] of a batch.
+    fn before_create(&self, _create: &mut Create) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Runs for each [`Mutate::Delete`] of a batch.
+    fn before_delete(&self, _delete: &Delete) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Runs after the per-mutation hooks.
+    ///
+    /// Can modify existing actions or add new ones, like setting a
+    /// modification timestamp for merges.
+    fn before_batch(&self, _batch: &mut Batch) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Runs after the batch was applied successfully.
+    fn after_batch(&self, _batch: &Batch) {}
+}
+
+/// Run all `before_*` hooks of a single hook.
+pub(crate) fn run_before(hook: &dyn Hook, batch: &mut Batch) -> Result<(), anyhow::Error> {
+    for action in &mut batch.actions {
+        match action {
+            Mutate::Create(create) => hook.before_create(create)?,
+            Mutate::Delete(delete) => hook.before_delete(delete)?,
+            Mutate::Replace(_)
+            | Mutate::Merge(_)
+            | Mutate::Patch(_)
+            | Mutate::Select(_)
+            | Mutate::Savepoint
+            | Mutate::Guard(_) => {}
+        }
+    }
+    hook.before_batch(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use factor_core::{
+        data::{Id, Value},
+        map,
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    struct TestHook {
+        protected: Id,
+        applied: Arc<AtomicUsize>,
+    }
+
+    impl Hook for TestHook {
+        fn before_create(&self, create: &mut Create) -> Result<(), anyhow::Error> {
+            create
+                .data
+                .insert("factor/description".to_string(), Value::from("created"));
+            Ok(())
+        }
+
+        fn before_delete(&self, delete: &Delete) -> Result<(), anyhow::Error> {
+            if delete.id == self.protected {
+                anyhow::bail!("entity is protected");
+            }
+            Ok(())
+        }
+
+        fn after_batch(&self, _batch: &Batch) {
+            self.applied.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_engine_hooks() {
+        let id = Id::random();
+        let applied = Arc::new(AtomicUsize::new(0));
+        let db = Engine::new(MemoryDb::new())
+            .with_hook(TestHook {
+                protected: id,
+                applied: applied.clone(),
+            })
+            .into_client();
+
+        db.create(id, map! { "factor/title": "a" }).await.unwrap();
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data["factor/description"], Value::from("created"));
+
+        let err = db.delete(id).await.unwrap_err();
+        assert_eq!(err.to_string(), "entity is protected");
+        assert!(db.entity(id).await.is_ok());
+
+        assert_eq!(applied.load(Ordering::SeqCst), 1);
+    }
+}
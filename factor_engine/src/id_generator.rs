@@ -0,0 +1,217 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use anyhow::bail;
+use factor_core::{
+    data::{Id, Timestamp},
+    schema::Class,
+};
+
+/// Generates ids for entities that are created with a nil id.
+///
+/// Set with [`crate::Engine::with_id_generator`].
+/// Defaults to [`DefaultIdGenerator`].
+pub trait IdGenerator: Send + Sync {
+    /// Generate a new id.
+    ///
+    /// `class` is the class of the new entity, if it has one.
+    fn generate(&self, class: Option<&Class>) -> Id;
+}
+
+/// Uses the [`factor_core::schema::IdStrategy`] of the class, or random ids
+/// for entities without a class.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultIdGenerator;
+
+impl IdGenerator for DefaultIdGenerator {
+    fn generate(&self, class: Option<&Class>) -> Id {
+        class
+            .map(|class| class.id_strategy)
+            .unwrap_or_default()
+            .generate()
+    }
+}
+
+/// Random UUIDv4 ids.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn generate(&self, _class: Option<&Class>) -> Id {
+        Id::random()
+    }
+}
+
+/// Time-ordered UUIDv7 ids. See [`Id::random_v7`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeOrderedIdGenerator;
+
+impl IdGenerator for TimeOrderedIdGenerator {
+    fn generate(&self, _class: Option<&Class>) -> Id {
+        Id::random_v7()
+    }
+}
+
+const SNOWFLAKE_NODE_BITS: u32 = 10;
+const SNOWFLAKE_SEQUENCE_BITS: u32 = 12;
+const SNOWFLAKE_MAX_SEQUENCE: u64 = (1 << SNOWFLAKE_SEQUENCE_BITS) - 1;
+
+/// Snowflake-style ids that are unique across multiple nodes without
+/// coordination.
+///
+/// The upper 64 bits of the id contain the millisecond timestamp, the node
+/// and a per-millisecond sequence number, so ids created by a single
+/// generator are strictly increasing. The lower 64 bits are zero.
+#[derive(Debug)]
+pub struct SnowflakeIdGenerator {
+    node: u64,
+    /// Last used (timestamp, sequence).
+    state: Mutex<(u64, u64)>,
+}
+
+impl SnowflakeIdGenerator {
+    /// Create a new generator.
+    ///
+    /// The node must be unique among all nodes that create ids concurrently
+    /// and smaller than 1024.
+    pub fn new(node: u16) -> Result<Self, anyhow::Error> {
+        let node = u64::from(node);
+        if node >= 1 << SNOWFLAKE_NODE_BITS {
+            bail!(
+                "Invalid snowflake node {}: must be smaller than {}",
+                node,
+                1u64 << SNOWFLAKE_NODE_BITS
+            );
+        }
+        Ok(Self {
+            node,
+            state: Mutex::new((0, 0)),
+        })
+    }
+
+    fn next(&self, now: u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let (last, sequence) = *state;
+        // Fall back to the last timestamp if the clock moved backwards, and
+        // move to the next millisecond if the sequence is exhausted.
+        *state = if now > last {
+            (now, 0)
+        } else if sequence < SNOWFLAKE_MAX_SEQUENCE {
+            (last, sequence + 1)
+        } else {
+            (last + 1, 0)
+        };
+        let (millis, sequence) = *state;
+        (millis << (SNOWFLAKE_NODE_BITS + SNOWFLAKE_SEQUENCE_BITS))
+            | (self.node << SNOWFLAKE_SEQUENCE_BITS)
+            | sequence
+    }
+}
+
+impl IdGenerator for SnowflakeIdGenerator {
+    fn generate(&self, _class: Option<&Class>) -> Id {
+        let value = self.next(Timestamp::now().as_millis());
+        Id::from_uuid(uuid::Uuid::from_u128(u128::from(value) << 64))
+    }
+}
+
+/// Sequential ids starting at 1.
+///
+/// Only useful for tests, where predictable ids simplify assertions.
+#[derive(Debug)]
+pub struct SequentialIdGenerator {
+    next: AtomicU64,
+}
+
+impl SequentialIdGenerator {
+    pub fn new() -> Self {
+        Self::starting_at(1)
+    }
+
+    pub fn starting_at(first: u64) -> Self {
+        Self {
+            next: AtomicU64::new(first),
+        }
+    }
+}
+
+impl Default for SequentialIdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn generate(&self, _class: Option<&Class>) -> Id {
+        let value = self.next.fetch_add(1, Ordering::Relaxed);
+        Id::from_uuid(uuid::Uuid::from_u128(u128::from(value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        map,
+        query::{migrate::Migration, select::Select},
+        schema::{AttrMapExt, IdStrategy},
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    #[test]
+    fn test_snowflake_id_generator() {
+        assert!(SnowflakeIdGenerator::new(1024).is_err());
+
+        let generator = SnowflakeIdGenerator::new(3).unwrap();
+        let a = generator.next(1_000);
+        let b = generator.next(1_000);
+        // Clock moved backwards.
+        let c = generator.next(999);
+        let d = generator.next(1_001);
+        assert!(a < b && b < c && c < d);
+        assert_eq!(a >> 22, 1_000);
+        assert_eq!((a >> 12) & 0x3ff, 3);
+        assert_eq!(c & SNOWFLAKE_MAX_SEQUENCE, 2);
+
+        assert!(generator.generate(None) < generator.generate(None));
+    }
+
+    #[test]
+    fn test_default_id_generator() {
+        let class = Class::new("test/A").with_id_strategy(IdStrategy::TimeOrdered);
+        let id = DefaultIdGenerator.generate(Some(&class));
+        assert_eq!(id.as_uuid().get_version_num(), 7);
+        let id = DefaultIdGenerator.generate(None);
+        assert_eq!(id.as_uuid().get_version_num(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_engine_id_generator() {
+        let db = Engine::new(MemoryDb::new())
+            .with_id_generator(SequentialIdGenerator::starting_at(10))
+            .into_client();
+        db.migrate(Migration::new().entity_create(Class::new("test/A")))
+            .await
+            .unwrap();
+
+        db.create(Id::nil(), map! { "factor/type": "test/A" })
+            .await
+            .unwrap();
+        db.create(Id::nil(), map! { "factor/title": "b" })
+            .await
+            .unwrap();
+
+        let expected = |value: u128| Id::from_uuid(uuid::Uuid::from_u128(value));
+        let a = db.entity(expected(10)).await.unwrap();
+        assert_eq!(a.get_type_name(), Some("test/A"));
+        let b = db.entity(expected(11)).await.unwrap();
+        assert_eq!(b.get("factor/title").and_then(|v| v.as_str()), Some("b"));
+
+        let items = db.select_map(Select::new()).await.unwrap();
+        assert_eq!(items.len(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+use factor_core::{data::Ident, schema::IndexSchema};
+
+use crate::{backend::BackendStats, registry::Registry};
+
+/// An index proposed by [`crate::Engine::index_suggestions`].
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct IndexSuggestion {
+    /// Ident of the attribute that queries look up.
+    pub attribute: String,
+    /// The proposed index.
+    /// Can be created with a migration as is.
+    pub index: IndexSchema,
+    /// Number of full scans that looked up the attribute.
+    pub scans: u64,
+    /// Estimated number of entity reads the index would have saved.
+    ///
+    /// A full scan reads all entities, while an index lookup only reads the
+    /// matching ones, so this is roughly the number of scans times the
+    /// number of entities.
+    pub estimated_benefit: u64,
+}
+
+impl IndexSuggestion {
+    /// Minimum number of full scans before an index is suggested for an
+    /// attribute.
+    pub const MIN_SCANS: u64 = 10;
+}
+
+/// Suggest indexes for attributes that full scans frequently look up.
+///
+/// Attributes that already have an index are skipped: the planner either
+/// uses it already, or can not use an index for the lookups at all, eg.
+/// for tenant scoped indexes.
+///
+/// Suggestions are sorted by estimated benefit, highest first.
+pub(crate) fn suggest_indexes(stats: &BackendStats, reg: &Registry) -> Vec<IndexSuggestion> {
+    let mut suggestions = stats
+        .queries
+        .scan_lookups
+        .iter()
+        .filter(|(_, scans)| **scans >= IndexSuggestion::MIN_SCANS)
+        .filter_map(|(ident, scans)| {
+            let attr = reg.attr_by_name(ident)?;
+            if !reg.indexes_for_attribute(attr.local_id).is_empty() {
+                return None;
+            }
+            let (namespace, name) = Ident::parse_parts(ident).ok()?;
+            Some(IndexSuggestion {
+                attribute: ident.clone(),
+                index: IndexSchema::new(namespace, format!("{}_index", name), vec![attr.schema.id]),
+                scans: *scans,
+                estimated_benefit: scans.saturating_mul(stats.entity_count),
+            })
+        })
+        .collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| {
+        b.estimated_benefit
+            .cmp(&a.estimated_benefit)
+            .then_with(|| a.attribute.cmp(&b.attribute))
+    });
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::{Id, ValueType},
+        map,
+        query::{
+            expr::Expr,
+            migrate::{IndexCreate, Migration, SchemaAction},
+            select::Select,
+        },
+        schema::Attribute,
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    #[tokio::test]
+    async fn test_engine_index_suggestions() {
+        let engine = Engine::new(MemoryDb::new());
+        let db = engine.clone().into_client();
+        db.migrate(
+            Migration::new()
+                .attr_create(Attribute::new("test/email", ValueType::String))
+                .attr_create(Attribute::new("test/name", ValueType::String).with_indexed(true))
+                .attr_create(Attribute::new("test/rare", ValueType::String)),
+        )
+        .await
+        .unwrap();
+        for email in ["a", "b", "c"] {
+            db.create(Id::random(), map! { "test/email": email })
+                .await
+                .unwrap();
+        }
+
+        let lookup = |attr: &str| Select::new().with_filter(Expr::eq(Expr::attr_ident(attr), "a"));
+        for _ in 0..10 {
+            db.select(lookup("test/email")).await.unwrap();
+            db.select(lookup("test/name")).await.unwrap();
+        }
+        db.select(lookup("test/rare")).await.unwrap();
+
+        let suggestions = engine.index_suggestions().await.unwrap();
+        assert_eq!(suggestions.len(), 1);
+        let suggestion = &suggestions[0];
+        assert_eq!(suggestion.attribute, "test/email");
+        assert_eq!(suggestion.scans, 10);
+        let entities = engine.stats().await.unwrap().entity_count;
+        assert!(entities >= 3);
+        assert_eq!(suggestion.estimated_benefit, 10 * entities);
+
+        // The suggested index can be created, and is used by later queries.
+        db.migrate(
+            Migration::new().action(SchemaAction::IndexCreate(IndexCreate {
+                schema: suggestion.index.clone(),
+            })),
+        )
+        .await
+        .unwrap();
+        assert!(engine.index_suggestions().await.unwrap().is_empty());
+        let before = engine.stats().await.unwrap().queries;
+        db.select(lookup("test/email")).await.unwrap();
+        let after = engine.stats().await.unwrap().queries;
+        assert_eq!(after.full_scans, before.full_scans);
+    }
+}
@@ -9,6 +9,37 @@ pub mod plan;
 mod db;
 pub use self::db::Engine;
 
+mod hooks;
+pub use self::hooks::Hook;
+
+mod admission;
+
+// Audit entries are recorded by the memory store.
+#[cfg(feature = "memory")]
+mod audit;
+
+mod rules;
+
+mod index_suggestions;
+pub use self::index_suggestions::IndexSuggestion;
+
+mod id_generator;
+pub use self::id_generator::{
+    DefaultIdGenerator, IdGenerator, RandomIdGenerator, SequentialIdGenerator,
+    SnowflakeIdGenerator, TimeOrderedIdGenerator,
+};
+
+mod authorizer;
+pub use self::authorizer::{Access, Authorizer, Operation};
+
+mod policy;
+pub use self::policy::AuthContext;
+
+pub mod blob;
+pub use self::blob::BlobStore;
+
+pub mod telemetry;
+
 pub mod util;
 
 #[cfg(test)]
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use factor_core::{data::Value, query::select::Select};
+
+use super::{QueryPlan, ResolvedExpr};
+
+/// Least recently used cache of planned select queries.
+///
+/// Queries are keyed by a fingerprint of the parts of the [`Select`] that
+/// are used for planning.
+/// Literal values are part of the fingerprint, since plans do not have
+/// parameter slots yet.
+///
+/// Plans depend on the schema, so the cache must be cleared whenever the
+/// registry changes.
+#[derive(Debug)]
+pub struct PlanCache {
+    capacity: usize,
+    /// Incremented on every access, to track recent use.
+    tick: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    last_used: u64,
+    plan: QueryPlan<Value, ResolvedExpr>,
+}
+
+impl PlanCache {
+    /// Default number of cached plans.
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Compute the cache key of a query.
+    ///
+    /// Returns `None` if the query can not be fingerprinted.
+    pub fn fingerprint(query: &Select) -> Option<String> {
+        serde_json::to_string(&(
+            &query.filter,
+            &query.sort,
+            &query.aggregate,
+            query.limit,
+            query.offset,
+        ))
+        .ok()
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<QueryPlan<Value, ResolvedExpr>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.tick;
+        Some(entry.plan.clone())
+    }
+
+    /// Add a plan, evicting the least recently used plan if the cache is full.
+    pub fn insert(&mut self, key: String, plan: QueryPlan<Value, ResolvedExpr>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                last_used: self.tick,
+                plan,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for PlanCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{data::Id, query::expr::Expr};
+
+    use super::*;
+
+    fn plan(limit: u64) -> QueryPlan<Value, ResolvedExpr> {
+        QueryPlan::Limit {
+            limit,
+            input: Box::new(QueryPlan::Scan { filter: None }),
+        }
+    }
+
+    #[test]
+    fn test_plan_cache_evicts_least_recently_used() {
+        let mut cache = PlanCache::new(2);
+        cache.insert("a".to_string(), plan(1));
+        cache.insert("b".to_string(), plan(2));
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c".to_string(), plan(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_plan_cache_fingerprint() {
+        let select = |title: &str| {
+            Select::new().with_filter(Expr::eq(
+                Expr::attr_ident("factor/title"),
+                Expr::literal(title),
+            ))
+        };
+        assert_eq!(
+            PlanCache::fingerprint(&select("a")),
+            PlanCache::fingerprint(&select("a").with_cursor(Id::random()))
+        );
+        assert_ne!(
+            PlanCache::fingerprint(&select("a")),
+            PlanCache::fingerprint(&select("b"))
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+// std::time::Instant is not supported on wasm32.
+use instant::Instant;
 
 use factor_core::{data::Value, query::expr::BinaryOp};
 
@@ -148,7 +151,8 @@ where
         ResolvedExpr::Literal(_)
         | ResolvedExpr::Regex(_)
         | ResolvedExpr::Attr(_)
-        | ResolvedExpr::Ident(_) => mapper(expr),
+        | ResolvedExpr::Ident(_)
+        | ResolvedExpr::InheritsClass(_) => mapper(expr),
         ResolvedExpr::List(list) => {
             let new_list = list
                 .into_iter()
@@ -191,6 +195,25 @@ where
 
             mapper(new)
         }
+        ResolvedExpr::WithinRadius {
+            value,
+            center,
+            meters,
+        } => {
+            let new = ResolvedExpr::WithinRadius {
+                value: Box::new(expr_map_all_recurse(*value, mapper)),
+                center,
+                meters,
+            };
+            mapper(new)
+        }
+        ResolvedExpr::JsonPath { value, path } => {
+            let new = ResolvedExpr::JsonPath {
+                value: Box::new(expr_map_all_recurse(*value, mapper)),
+                path,
+            };
+            mapper(new)
+        }
     }
 }
 
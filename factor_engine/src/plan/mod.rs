@@ -1,3 +1,4 @@
+mod cache;
 mod expr_optimize;
 mod optimizers;
 
@@ -6,14 +7,19 @@ use std::collections::HashSet;
 use anyhow::Context;
 
 use factor_core::{
-    data::{Id, IdOrIdent, Value},
+    data::{patch::PatchPath, GeoPoint, Id, IdOrIdent, Value},
     query::{
         expr::{BinaryOp, Expr, UnaryOp},
         select::{self, AggregationOp, Order, Select},
     },
+    schema::Collation,
 };
 
-use crate::registry::{LocalAttributeId, LocalIndexId, Registry, ATTR_TYPE_LOCAL};
+use crate::registry::{
+    ClassSet, LocalAttributeId, LocalIndexId, RegisteredIndex, Registry, ATTR_TYPE_LOCAL,
+};
+
+pub use self::cache::PlanCache;
 
 use self::{expr_optimize::OwnedExprOptimizer, optimizers::FalliblePlanOptimizer};
 
@@ -25,6 +31,11 @@ pub enum QueryPlan<V = Value, E = Expr> {
     SelectEntity {
         id: Id,
     },
+    /// A fixed list of entities, like the materialized entities of a view.
+    /// Missing entities are skipped.
+    SelectEntities {
+        ids: Vec<Id>,
+    },
     Scan {
         filter: Option<E>,
     },
@@ -73,6 +84,56 @@ pub enum QueryPlan<V = Value, E = Expr> {
     },
 }
 
+impl<V, E> QueryPlan<V, E> {
+    /// Name of the node kind, used for statistics.
+    pub fn node_name(&self) -> &'static str {
+        match self {
+            Self::EmptyRelation => "empty_relation",
+            Self::SelectEntity { .. } => "select_entity",
+            Self::SelectEntities { .. } => "select_entities",
+            Self::Scan { .. } => "scan",
+            Self::Filter { .. } => "filter",
+            Self::Limit { .. } => "limit",
+            Self::Skip { .. } => "skip",
+            Self::Merge { .. } => "merge",
+            Self::IndexSelect { .. } => "index_select",
+            Self::IndexScan { .. } => "index_scan",
+            Self::IndexScanPrefix { .. } => "index_scan_prefix",
+            Self::Sort { .. } => "sort",
+            Self::Aggregate { .. } => "aggregate",
+        }
+    }
+
+    /// The index read by this node, if any.
+    pub fn index(&self) -> Option<LocalIndexId> {
+        match self {
+            Self::IndexSelect { index, .. }
+            | Self::IndexScan { index, .. }
+            | Self::IndexScanPrefix { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// The direct input plans of this node.
+    pub fn inputs(&self) -> Vec<&Self> {
+        match self {
+            Self::Filter { input, .. }
+            | Self::Limit { input, .. }
+            | Self::Skip { input, .. }
+            | Self::Sort { input, .. }
+            | Self::Aggregate { input, .. } => vec![input.as_ref()],
+            Self::Merge { left, right } => vec![left.as_ref(), right.as_ref()],
+            Self::EmptyRelation
+            | Self::SelectEntity { .. }
+            | Self::SelectEntities { .. }
+            | Self::Scan { .. }
+            | Self::IndexSelect { .. }
+            | Self::IndexScan { .. }
+            | Self::IndexScanPrefix { .. } => Vec::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Aggregation {
     pub name: String,
@@ -102,6 +163,7 @@ impl<V: Clone, E: Clone> QueryPlan<V, E> {
         match self {
             Self::EmptyRelation => Self::EmptyRelation,
             Self::SelectEntity { .. } => self.clone(),
+            Self::SelectEntities { .. } => self.clone(),
             Self::Scan { .. } => self.clone(),
             Self::Filter { expr, input } => Self::Filter {
                 expr: expr.clone(),
@@ -145,6 +207,7 @@ impl<V: Clone, E: Clone> QueryPlan<V, E> {
             match self {
                 Self::EmptyRelation => None,
                 Self::SelectEntity { .. } => None,
+                Self::SelectEntities { .. } => None,
                 Self::Scan { .. } => None,
                 Self::Filter { expr, input } => Some(Self::Filter {
                     expr: expr.clone(),
@@ -235,6 +298,23 @@ pub enum ResolvedExpr<V = Value> {
         then: Box<Self>,
         or: Box<Self>,
     },
+    /// See [`Expr::WithinRadius`].
+    WithinRadius {
+        value: Box<Self>,
+        center: GeoPoint,
+        meters: f64,
+    },
+    /// See [`Expr::JsonPath`].
+    JsonPath {
+        value: Box<Self>,
+        path: PatchPath,
+    },
+    /// Entities whose type is one of the classes.
+    ///
+    /// Resolved from [`Expr::InheritsEntityType`] with the precomputed
+    /// class hierarchy. Backends can check the type with a bitset test
+    /// instead of comparing type names.
+    InheritsClass(ClassSet),
 }
 
 impl<V> ResolvedExpr<V> {
@@ -304,6 +384,29 @@ impl<V: PartialEq + Eq + std::hash::Hash> PartialEq for ResolvedExpr<V> {
                     or: r_or,
                 },
             ) => l_value == r_value && l_then == r_then && l_or == r_or,
+            (
+                Self::WithinRadius {
+                    value: l_value,
+                    center: l_center,
+                    meters: l_meters,
+                },
+                Self::WithinRadius {
+                    value: r_value,
+                    center: r_center,
+                    meters: r_meters,
+                },
+            ) => l_value == r_value && l_center == r_center && l_meters == r_meters,
+            (
+                Self::JsonPath {
+                    value: l_value,
+                    path: l_path,
+                },
+                Self::JsonPath {
+                    value: r_value,
+                    path: r_path,
+                },
+            ) => l_value == r_value && l_path == r_path,
+            (Self::InheritsClass(l0), Self::InheritsClass(r0)) => l0 == r0,
             _ => false,
         }
     }
@@ -349,6 +452,22 @@ impl<V> ResolvedExpr<V> {
         }
     }
 
+    /// Match `lower(attr) == literal`, as produced by [`Expr::eq_ci`].
+    pub fn as_binary_op_lower_attr_eq_value(&self) -> Option<(LocalAttributeId, &V)> {
+        let lower_attr = |expr: &Self| match expr {
+            ResolvedExpr::UnaryOp {
+                op: UnaryOp::Lower,
+                expr,
+            } => expr.as_attr().copied(),
+            _ => None,
+        };
+        match self.as_binary_op_eq()? {
+            (left, ResolvedExpr::Literal(v)) => Some((lower_attr(left)?, v)),
+            (ResolvedExpr::Literal(v), right) => Some((lower_attr(right)?, v)),
+            _ => None,
+        }
+    }
+
     pub fn as_in_literal_attr(&self) -> Option<(LocalAttributeId, &HashSet<V>)> {
         match self {
             ResolvedExpr::InLiteral { value, items } => {
@@ -372,10 +491,77 @@ fn optimize_expr(expr: ResolvedExpr) -> ResolvedExpr {
     expr_optimize::BinaryToInLiteral.optimize(expr)
 }
 
+/// Flatten nested AND expressions into their clauses.
+pub(crate) fn and_clauses<'a>(expr: &'a ResolvedExpr, clauses: &mut Vec<&'a ResolvedExpr>) {
+    if let Some((left, right)) = expr.as_binary_op_and() {
+        and_clauses(left, clauses);
+        and_clauses(right, clauses);
+    } else {
+        clauses.push(expr);
+    }
+}
+
+/// Attributes that full scans of the plan look up by value.
+///
+/// These are the lookups that an index on the attribute could serve, so
+/// frequently scanned attributes are candidates for new indexes.
+pub(crate) fn scan_lookup_attributes(
+    plan: &QueryPlan<Value, ResolvedExpr>,
+    attrs: &mut Vec<LocalAttributeId>,
+) {
+    if let QueryPlan::Scan {
+        filter: Some(filter),
+    } = plan
+    {
+        let mut clauses = Vec::new();
+        and_clauses(filter, &mut clauses);
+        for clause in clauses {
+            let attr = clause
+                .as_binary_op_attr_eq_value()
+                .or_else(|| clause.as_binary_op_lower_attr_eq_value())
+                .map(|(attr, _)| attr)
+                .or_else(|| clause.as_in_literal_attr().map(|(attr, _)| attr));
+            if let Some(attr) = attr {
+                if !attrs.contains(&attr) {
+                    attrs.push(attr);
+                }
+            }
+        }
+    }
+    for input in plan.inputs() {
+        scan_lookup_attributes(input, attrs);
+    }
+}
+
+/// Check if a query filter can be served by an index.
+///
+/// Always true for regular indexes.
+/// For partial indexes the check is conservative: every AND clause of the
+/// index filter must also be an AND clause of the query filter.
+fn filter_covers_index(filter: &ResolvedExpr, index: &RegisteredIndex, reg: &Registry) -> bool {
+    let index_filter = match &index.schema.filter {
+        Some(f) => match resolve_expr(f.clone(), reg) {
+            Ok(f) => optimize_expr(f),
+            Err(_) => return false,
+        },
+        None => return true,
+    };
+
+    let mut query_clauses = Vec::new();
+    and_clauses(filter, &mut query_clauses);
+    let mut index_clauses = Vec::new();
+    and_clauses(&index_filter, &mut index_clauses);
+
+    index_clauses
+        .into_iter()
+        .all(|clause| query_clauses.contains(&clause))
+}
+
 pub fn plan_select(
     query: Select,
     reg: &Registry,
 ) -> Result<QueryPlan<Value, ResolvedExpr>, anyhow::Error> {
+    let _span = tracing::debug_span!("plan_select").entered();
     let filter_unoptimized = query
         .filter
         .clone()
@@ -383,7 +569,62 @@ pub fn plan_select(
         .transpose()?;
     let filter = filter_unoptimized.map(optimize_expr);
 
-    let plan = Box::new(QueryPlan::<Value, ResolvedExpr>::Scan { filter });
+    let plan = plan_select_input(QueryPlan::Scan { filter }, &query, reg)?;
+
+    // run optimizers.
+
+    let optimizers: Vec<&dyn FalliblePlanOptimizer> = vec![
+        &optimizers::OptimizeEntitySelect,
+        &optimizers::FilterWithGeoIndex,
+        &optimizers::FilterWithIndex,
+    ];
+
+    let plan = optimizers.iter().try_fold(
+        plan,
+        |plan, opt| -> Result<QueryPlan<Value, ResolvedExpr>, anyhow::Error> {
+            if let Some(new) = opt.optimize(reg, &plan)? {
+                Ok(new)
+            } else {
+                Ok(plan)
+            }
+        },
+    )?;
+
+    tracing::debug!(?query, ?plan, "planned select query");
+
+    Ok(plan)
+}
+
+/// Plan a select that reads the materialized entities of a view.
+///
+/// The filter of the view query was already applied to the entities, so
+/// only the additional `filter` (eg. from policies), sorting, offset, limit
+/// and aggregations are planned.
+pub fn plan_view_select(
+    query: Select,
+    ids: Vec<Id>,
+    filter: Option<Expr>,
+    reg: &Registry,
+) -> Result<QueryPlan<Value, ResolvedExpr>, anyhow::Error> {
+    let input = QueryPlan::SelectEntities { ids };
+    let input = match filter {
+        Some(filter) => QueryPlan::Filter {
+            expr: optimize_expr(resolve_expr(filter, reg)?),
+            input: Box::new(input),
+        },
+        None => input,
+    };
+    plan_select_input(input, &query, reg)
+}
+
+/// Wrap the input of a select with sorting, offset, limit and
+/// aggregations.
+fn plan_select_input(
+    input: QueryPlan<Value, ResolvedExpr>,
+    query: &Select,
+    reg: &Registry,
+) -> Result<QueryPlan<Value, ResolvedExpr>, anyhow::Error> {
+    let plan = Box::new(input);
 
     let plan = if !query.sort.is_empty() {
         let sorts = plan_sort(reg, query.sort.clone())?;
@@ -430,27 +671,7 @@ pub fn plan_select(
         plan
     };
 
-    // run optimizers.
-
-    let optimizers: Vec<&dyn FalliblePlanOptimizer> = vec![
-        &optimizers::OptimizeEntitySelect,
-        &optimizers::FilterWithIndex,
-    ];
-
-    let plan = optimizers.iter().try_fold(
-        *plan,
-        |plan, opt| -> Result<QueryPlan<Value, ResolvedExpr>, anyhow::Error> {
-            if let Some(new) = opt.optimize(reg, &plan)? {
-                Ok(new)
-            } else {
-                Ok(plan)
-            }
-        },
-    )?;
-
-    tracing::debug!(?query, ?plan, "planned select query");
-
-    Ok(plan)
+    Ok(*plan)
 }
 
 fn plan_sort(
@@ -460,10 +681,19 @@ fn plan_sort(
     sorts
         .into_iter()
         .map(|s| {
-            Ok(Sort {
-                on: resolve_expr(s.on, reg)?,
-                order: s.order,
-            })
+            let on = match resolve_expr(s.on, reg)? {
+                // Sort case insensitive attributes by their normalized value.
+                ResolvedExpr::Attr(id)
+                    if reg.attr(id).schema.collation == Collation::CaseInsensitive =>
+                {
+                    ResolvedExpr::UnaryOp {
+                        op: UnaryOp::Lower,
+                        expr: Box::new(ResolvedExpr::Attr(id)),
+                    }
+                }
+                on => on,
+            };
+            Ok(Sort { on, order: s.order })
         })
         .collect::<Result<Vec<_>, anyhow::Error>>()
 }
@@ -478,15 +708,28 @@ pub fn resolve_expr(expr: Expr, reg: &Registry) -> Result<ResolvedExpr, anyhow::
                 .collect::<Result<Vec<_>, _>>()?;
             Ok(ResolvedExpr::List(items))
         }
-        Expr::Attr(ident) => Ok(ResolvedExpr::Attr(
-            reg.require_attr_by_ident(&ident)?.local_id,
-        )),
+        Expr::Attr(ident) => match reg.require_attr_by_ident(&ident) {
+            Ok(attr) => Ok(ResolvedExpr::Attr(attr.local_id)),
+            // Computed attributes are inlined.
+            Err(err) => match ident
+                .as_name()
+                .and_then(|name| reg.computed_attribute_expr(name))
+            {
+                Some(expr) => resolve_expr(expr, reg),
+                None => Err(err.into()),
+            },
+        },
         Expr::Ident(ident) => Ok(ResolvedExpr::Ident(ident)),
         Expr::Variable(_v) => Err(anyhow::anyhow!("Query variables not implemented yet")),
-        Expr::UnaryOp { op, expr } => Ok(ResolvedExpr::UnaryOp {
-            op,
-            expr: Box::new(resolve_expr(*expr, reg)?),
-        }),
+        Expr::UnaryOp { op, expr } => match resolve_expr(*expr, reg)? {
+            // Evaluate constant expressions directly, so optimizers can
+            // match on the literal.
+            ResolvedExpr::Literal(value) => Ok(ResolvedExpr::Literal(op.eval(&value))),
+            expr => Ok(ResolvedExpr::UnaryOp {
+                op,
+                expr: Box::new(expr),
+            }),
+        },
         // TODO: normalize BinaryOp::In into ResolvedExpr::InLiteral if possible.
         Expr::BinaryOp { left, op, right } => match op {
             BinaryOp::RegexMatch | BinaryOp::RegexMatchCaseInsensitive => {
@@ -518,18 +761,29 @@ pub fn resolve_expr(expr: Expr, reg: &Registry) -> Result<ResolvedExpr, anyhow::
             then: Box::new(resolve_expr(*then, reg)?),
             or: Box::new(resolve_expr(*or, reg)?),
         }),
+        Expr::WithinRadius {
+            value,
+            center,
+            meters,
+        } => Ok(ResolvedExpr::WithinRadius {
+            value: Box::new(resolve_expr(*value, reg)?),
+            center,
+            meters: meters.into_inner(),
+        }),
+        Expr::JsonPath { value, path } => Ok(ResolvedExpr::JsonPath {
+            value: Box::new(resolve_expr(*value, reg)?),
+            path: PatchPath::parse_json_path(&path)?,
+        }),
         Expr::InheritsEntityType(type_name) => {
-            // TODO: collecting strings here is stupid and redundant.
-            // Must be a cleaner way to structure this!
-            // Probably want a dedicted expr to check the type!
             let ty = reg.require_entity_by_name(&type_name)?;
-            let mut items: HashSet<_> = ty
-                .nested_children
-                .iter()
-                .filter_map(|id| Some(Value::from(reg.entity_by_id(*id)?.schema.ident.clone())))
+            Ok(ResolvedExpr::InheritsClass(ty.descendants.clone()))
+        }
+        Expr::TypeInNamespace(namespace) => {
+            let items = reg
+                .iter_entities()
+                .filter(|class| !class.is_deleted && class.namespace == namespace)
+                .map(|class| Value::from(class.schema.ident.clone()))
                 .collect();
-            items.insert(ty.schema.ident.clone().into());
-
             Ok(ResolvedExpr::InLiteral {
                 value: Box::new(ResolvedExpr::Attr(ATTR_TYPE_LOCAL)),
                 items,
@@ -540,7 +794,10 @@ pub fn resolve_expr(expr: Expr, reg: &Registry) -> Result<ResolvedExpr, anyhow::
 
 #[cfg(test)]
 mod tests {
-    use factor_core::schema::{builtin::AttrId, AttributeMeta};
+    use factor_core::schema::{
+        builtin::{AttrId, AttrIdent, AttrTitle},
+        AttributeMeta,
+    };
 
     use super::*;
 
@@ -560,6 +817,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_scan_lookup_attributes() {
+        let reg = Registry::new();
+        let title = reg
+            .attr_by_name(AttrTitle::QUALIFIED_NAME)
+            .unwrap()
+            .local_id;
+
+        let lookups = |filter: Expr| {
+            let plan = plan_select(Select::new().with_filter(filter), &reg).unwrap();
+            let mut attrs = Vec::new();
+            scan_lookup_attributes(&plan, &mut attrs);
+            attrs
+        };
+
+        assert_eq!(
+            lookups(Expr::and(
+                Expr::eq(AttrTitle::expr(), "a"),
+                Expr::gt(AttrIdent::expr(), "b")
+            )),
+            vec![title]
+        );
+        // Lookups served by an index are not reported.
+        assert_eq!(lookups(Expr::eq(AttrIdent::expr(), "a")), Vec::new());
+        assert_eq!(
+            lookups(Expr::or(
+                Expr::eq(AttrTitle::expr(), "a"),
+                Expr::eq(AttrIdent::expr(), "b")
+            )),
+            Vec::new()
+        );
+    }
+
     /* #[test]
     fn test_query_plan_simple_sort_uses_index() {
         let reg = Registry::new();
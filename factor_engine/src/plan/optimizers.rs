@@ -1,11 +1,11 @@
 use factor_core::{
     data::{Id, Value},
-    query::expr::BinaryOp,
+    query::{expr::BinaryOp, select::Order},
 };
 
 use crate::registry::{Registry, ATTR_ID_LOCAL};
 
-use super::{QueryPlan, ResolvedExpr};
+use super::{filter_covers_index, QueryPlan, ResolvedExpr};
 
 pub trait FalliblePlanOptimizer {
     fn optimize(
@@ -170,6 +170,13 @@ where
 fn expr_is_index_select_literal(expr: &ResolvedExpr) -> bool {
     match expr {
         _ if expr.as_binary_op_attr_eq_value().is_some() => true,
+        _ if matches!(
+            expr.as_binary_op_lower_attr_eq_value(),
+            Some((_, Value::String(_)))
+        ) =>
+        {
+            true
+        }
         ResolvedExpr::InLiteral { value, items: _ } if value.as_attr().is_some() => true,
         _ => false,
     }
@@ -190,22 +197,66 @@ impl FilterWithIndex {
 
                 let (index_filter, rest) = extract_expr_and(filter, expr_is_index_select_literal)?;
 
-                let (attr, values) =
-                    if let Some((attr, value)) = index_filter.as_binary_op_attr_eq_value() {
-                        (attr, vec![value.clone()])
-                    } else if let Some((attr, values)) = index_filter.as_in_literal_attr() {
-                        (attr, values.iter().cloned().collect())
-                    } else {
-                        // Should never happen...
-                        return None;
-                    };
-
-                let indexes = reg.indexes_for_attribute(attr);
-                if indexes.len() != 1 {
+                let (attr, values, case_insensitive) = if let Some((attr, value)) =
+                    index_filter.as_binary_op_attr_eq_value()
+                {
+                    (attr, vec![value.clone()], false)
+                } else if let Some((attr, values)) = index_filter.as_in_literal_attr() {
+                    (attr, values.iter().cloned().collect(), false)
+                } else if let Some((attr, value)) = index_filter.as_binary_op_lower_attr_eq_value()
+                {
+                    (attr, vec![value.clone()], true)
+                } else {
+                    // Should never happen...
+                    return None;
+                };
+
+                let indexes: Vec<_> = reg
+                    .indexes_for_attribute(attr)
+                    .into_iter()
+                    .filter(|index| filter_covers_index(filter, index, reg))
+                    // Sparse indexes do not contain unit values.
+                    .filter(|index| values.iter().all(|value| index.indexes_value(value)))
+                    .collect();
+                // Tenant scoped indexes can not be used for plain value
+                // lookups, since their keys are prefixed with the tenant.
+                // Geo indexes only contain geohashes, and multi-valued indexes
+                // only list items.
+                if indexes.len() != 1
+                    || indexes[0].schema.tenant_scoped
+                    || indexes[0].geo
+                    || indexes[0].schema.multi_valued
+                {
+                    return None;
+                }
+                let collation = indexes[0].collation;
+                // Case insensitive lookups need normalized index keys.
+                if case_insensitive && collation.is_binary() {
                     return None;
                 }
                 let index = indexes[0].local_id;
 
+                // Keys of case insensitive indexes are normalized, so the
+                // index can return values that only differ in case.
+                // Exact comparisons must be checked again.
+                let rest = if !case_insensitive && !collation.is_binary() {
+                    Some(match rest {
+                        Some(rest) => ResolvedExpr::and(index_filter, rest),
+                        None => index_filter,
+                    })
+                } else {
+                    rest
+                };
+
+                let mut values: Vec<_> = values
+                    .into_iter()
+                    .map(|value| collation.normalize(value))
+                    .collect();
+                if !collation.is_binary() {
+                    // Different values can map to the same key.
+                    values.sort();
+                    values.dedup();
+                }
                 let mut iter = values.into_iter();
 
                 let plan = QueryPlan::IndexSelect {
@@ -254,6 +305,78 @@ impl PlanOptimizer for FilterWithIndex {
     }
 }
 
+fn expr_is_within_radius_attr(expr: &ResolvedExpr) -> bool {
+    matches!(expr, ResolvedExpr::WithinRadius { value, .. } if value.as_attr().is_some())
+}
+
+/// Use a geo index for [`ResolvedExpr::WithinRadius`] filters.
+///
+/// Scans all geohash cells that cover the radius, and then filters out points
+/// that are in the cells but outside of the radius.
+pub struct FilterWithGeoIndex;
+
+impl FilterWithGeoIndex {
+    fn optimize_inner(
+        reg: &Registry,
+        plan: &QueryPlan<Value, ResolvedExpr>,
+    ) -> Option<QueryPlan<Value, ResolvedExpr>> {
+        match plan {
+            QueryPlan::Scan { filter } => {
+                let filter = filter.as_ref()?;
+
+                let (geo_filter, rest) = extract_expr_and(filter, expr_is_within_radius_attr)?;
+                let (attr, center, meters) = match &geo_filter {
+                    ResolvedExpr::WithinRadius {
+                        value,
+                        center,
+                        meters,
+                    } => (*value.as_attr()?, center, *meters),
+                    _ => return None,
+                };
+
+                let index = reg
+                    .indexes_for_attribute(attr)
+                    .into_iter()
+                    .find(|index| index.geo && filter_covers_index(filter, index, reg))?
+                    .local_id;
+                let mut cells = center.geohash_cover(meters)?.into_iter();
+
+                let scan = |cell: String| QueryPlan::IndexScanPrefix {
+                    index,
+                    direction: Order::Asc,
+                    prefix: Value::String(cell),
+                };
+                let first = scan(cells.next()?);
+                let input = cells.fold(first, |plan, cell| QueryPlan::Merge {
+                    left: Box::new(plan),
+                    right: Box::new(scan(cell)),
+                });
+
+                let expr = if let Some(rest) = rest {
+                    ResolvedExpr::and(geo_filter, rest)
+                } else {
+                    geo_filter
+                };
+                Some(QueryPlan::Filter {
+                    expr,
+                    input: Box::new(input),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PlanOptimizer for FilterWithGeoIndex {
+    fn optimize(
+        &self,
+        reg: &Registry,
+        plan: &QueryPlan<Value, ResolvedExpr>,
+    ) -> Option<QueryPlan<Value, ResolvedExpr>> {
+        plan.map_recurse(move |q| Self::optimize_inner(reg, q))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use factor_core::{
@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use factor_core::{
+    data::{DataMap, Id, Value},
+    query::expr::Expr,
+    schema::{AttrMapExt, PolicySchema},
+};
+
+use crate::registry::Registry;
+
+const CURRENT_USER: &str = "current_user";
+
+/// Identity of the caller, used to enforce row-level security policies (see
+/// [`PolicySchema`]) and passed to the [`crate::Authorizer`].
+///
+/// The variables are available in policy filters, eg `$current_user`.
+///
+/// Set with [`crate::Engine::with_auth_context`].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AuthContext {
+    variables: HashMap<String, Value>,
+}
+
+impl AuthContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_variable(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the current user, available as `$current_user`.
+    ///
+    /// The user is also recorded as the actor in the audit log.
+    pub fn with_user(self, user: Id) -> Self {
+        self.with_variable(CURRENT_USER, user)
+    }
+
+    pub fn user(&self) -> Option<Id> {
+        self.variables
+            .get(CURRENT_USER)
+            .and_then(|value| value.as_id())
+    }
+
+    pub fn variables(&self) -> &HashMap<String, Value> {
+        &self.variables
+    }
+}
+
+/// Build the filter that restricts a select to the entities allowed by all
+/// policies.
+///
+/// Returns `None` if no policies are defined.
+pub(crate) fn select_filter(
+    reg: &Registry,
+    ctx: &AuthContext,
+) -> Result<Option<Expr>, anyhow::Error> {
+    let filters = reg
+        .iter_policies()
+        .map(|policy| {
+            let filter = policy.filter.clone().bind_variables(&ctx.variables)?;
+            Ok(Expr::or(
+                Expr::not(Expr::InheritsEntityType(policy.class.clone())),
+                filter,
+            ))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    Ok(Expr::and_iter(filters))
+}
+
+/// Find the first policy that is violated by the given entity data.
+pub(crate) fn find_violation<'a>(
+    reg: &'a Registry,
+    ctx: &AuthContext,
+    data: &DataMap,
+) -> Result<Option<&'a PolicySchema>, anyhow::Error> {
+    let class = match data.get_type().and_then(|ty| reg.entity_by_ident(&ty)) {
+        Some(class) => class.schema.id,
+        None => return Ok(None),
+    };
+    for policy in reg.policies_for_class(class) {
+        let filter = policy.filter.clone().bind_variables(&ctx.variables)?;
+        if !matches_filter(&filter, data, reg)? {
+            return Ok(Some(policy));
+        }
+    }
+    Ok(None)
+}
+
+/// Check if entity data matches a filter.
+///
+/// Supports the same expressions as policy filters, but without variables.
+/// Used for the filters of partial indexes, see
+/// [`factor_core::schema::IndexSchema::filter`].
+pub(crate) fn matches_filter(
+    filter: &Expr,
+    data: &DataMap,
+    reg: &Registry,
+) -> Result<bool, anyhow::Error> {
+    Ok(eval_expr(filter, data, reg)?.as_bool() == Some(true))
+}
+
+/// Evaluate an expression against entity data.
+///
+/// Uses the evaluator of the memory backend, so the result matches a select
+/// of the written entity.
+///
+/// Used for computed attributes, see
+/// [`factor_core::schema::ComputedAttribute`].
+pub(crate) fn eval_expr(
+    expr: &Expr,
+    data: &DataMap,
+    reg: &Registry,
+) -> Result<Value, anyhow::Error> {
+    #[cfg(feature = "memory")]
+    {
+        crate::backend::memory::store::MemoryStore::eval_data_expr(expr.clone(), data, reg)
+    }
+    #[cfg(not(feature = "memory"))]
+    {
+        let _ = (expr, data, reg);
+        anyhow::bail!("Expression evaluation requires the 'memory' feature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::{patch::Patch, Id, ValueType},
+        db::ReadOnlyClient,
+        error::{EntityNotFound, PermissionDenied, PolicyViolation},
+        map,
+        query::{
+            migrate::Migration,
+            mutate::{Batch, MutateSelect, MutateSelectAction},
+            select::Select,
+        },
+        schema::{Attribute, Class},
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_engine_policies() {
+        let engine = Engine::new(MemoryDb::new());
+        let admin = engine.clone().into_client();
+        admin
+            .migrate(
+                Migration::new()
+                    .attr_create(Attribute::new("test/owner", ValueType::Ref))
+                    .entity_create(Class::new("test/Doc").with_attribute("test/owner", true))
+                    .policy_create(PolicySchema::new(
+                        "test",
+                        "doc_owner",
+                        "test/Doc",
+                        Expr::eq(Expr::attr_ident("test/owner"), Expr::var("current_user")),
+                    )),
+            )
+            .await
+            .unwrap();
+
+        // Owners are references, so the users must exist.
+        let alice = Id::random();
+        let bob = Id::random();
+        for user in [alice, bob] {
+            admin
+                .create(user, map! { "factor/title": "user" })
+                .await
+                .unwrap();
+        }
+        let as_user = |user: Id| {
+            engine
+                .clone()
+                .with_auth_context(AuthContext::new().with_variable("current_user", user))
+                .into_client()
+        };
+        let alice_db = as_user(alice);
+        let bob_db = as_user(bob);
+
+        let doc = Id::random();
+        alice_db
+            .create(doc, map! { "factor/type": "test/Doc", "test/owner": alice })
+            .await
+            .unwrap();
+        // Entities without a policy are not affected.
+        let other = Id::random();
+        bob_db
+            .create(other, map! { "factor/title": "other" })
+            .await
+            .unwrap();
+
+        assert_eq!(alice_db.entity(doc).await.unwrap().get_id(), Some(doc));
+        let err = bob_db.entity(doc).await.unwrap_err();
+        assert!(err.is::<EntityNotFound>());
+        alice_db.entity(other).await.unwrap();
+
+        let visible = |items: Vec<DataMap>| {
+            let mut ids = items.iter().filter_map(|i| i.get_id()).collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        let mut all = vec![doc, other, alice, bob];
+        all.sort();
+        let mut unowned = vec![other, alice, bob];
+        unowned.sort();
+        assert_eq!(
+            visible(alice_db.select_map(Select::new()).await.unwrap()),
+            all
+        );
+        assert_eq!(
+            visible(bob_db.select_map(Select::new()).await.unwrap()),
+            unowned
+        );
+        assert_eq!(visible(admin.select_map(Select::new()).await.unwrap()), all);
+
+        // Writes must satisfy the policy.
+        let err = bob_db
+            .create(
+                Id::random(),
+                map! { "factor/type": "test/Doc", "test/owner": alice },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is::<PolicyViolation>());
+        let err = alice_db
+            .merge(doc, map! { "test/owner": bob })
+            .await
+            .unwrap_err();
+        assert!(err.is::<PolicyViolation>());
+
+        // Entities of other users can not be modified.
+        let err = bob_db.delete(doc).await.unwrap_err();
+        assert!(err.is::<EntityNotFound>());
+
+        // Entities patched by a select must still satisfy the policy.
+        let give_away = Batch::new().and_select(MutateSelect {
+            filter: Expr::eq(Expr::attr_ident("test/owner"), Expr::literal(alice)),
+            variables: Default::default(),
+            action: MutateSelectAction::Patch(Patch::new().replace("test/owner", bob)),
+            guard: None,
+        });
+        let err = alice_db.batch(give_away).await.unwrap_err();
+        assert!(err.is::<EntityNotFound>());
+        assert_eq!(
+            admin.entity(doc).await.unwrap().get("test/owner"),
+            Some(&Value::Id(alice))
+        );
+
+        // Restricted clients can not remove their own policy or purge the
+        // data.
+        let err = bob_db
+            .migrate(Migration::new().policy_delete("test/doc_owner"))
+            .await
+            .unwrap_err();
+        assert!(err.is::<PermissionDenied>());
+        let err = bob_db.purge_all_data().await.unwrap_err();
+        assert!(err.is::<PermissionDenied>());
+        let err = bob_db.entity(doc).await.unwrap_err();
+        assert!(err.is::<EntityNotFound>());
+
+        // The auth context is applied below wrapping clients.
+        let bob_read_only = admin
+            .read_only()
+            .with_auth_variables(AuthContext::new().with_user(bob).variables())
+            .unwrap();
+        assert!(bob_read_only.client().as_any().is::<ReadOnlyClient>());
+        let err = bob_read_only.entity(doc).await.unwrap_err();
+        assert!(err.is::<EntityNotFound>());
+
+        alice_db.delete(doc).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_engine_policy_filter_matches_select() {
+        let engine = Engine::new(MemoryDb::new());
+        engine
+            .clone()
+            .into_client()
+            .migrate(
+                Migration::new()
+                    .entity_create(Class::new("test/Note").with_attribute("factor/title", true))
+                    .policy_create(PolicySchema::new(
+                        "test",
+                        "public_notes",
+                        "test/Note",
+                        Expr::regex_match(Expr::attr_ident("factor/title"), "^public"),
+                    )),
+            )
+            .await
+            .unwrap();
+        let db = engine
+            .with_auth_context(AuthContext::new().with_user(Id::random()))
+            .into_client();
+
+        // Writes are checked with the same evaluator as selects.
+        let note = Id::random();
+        db.create(
+            note,
+            map! { "factor/type": "test/Note", "factor/title": "public note" },
+        )
+        .await
+        .unwrap();
+        let err = db
+            .create(
+                Id::random(),
+                map! { "factor/type": "test/Note", "factor/title": "secret" },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is::<PolicyViolation>());
+
+        assert_eq!(db.entity(note).await.unwrap().get_id(), Some(note));
+        let items = db.select_map(Select::new()).await.unwrap();
+        assert_eq!(
+            items.iter().filter_map(|i| i.get_id()).collect::<Vec<_>>(),
+            vec![note]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::{anyhow, Context};
 use fnv::FnvHashMap;
 
@@ -27,6 +29,9 @@ impl LocalAttributeId {
 pub struct RegisteredAttribute {
     pub local_id: LocalAttributeId,
     pub schema: schema::Attribute,
+    /// The attribute ident, shared with data maps built from stored data.
+    /// See [`factor_core::data::SharedDataMap`].
+    pub name: Arc<str>,
     pub is_deleted: bool,
     pub namespace: String,
     pub plain_name: String,
@@ -117,6 +122,7 @@ impl AttributeRegistry {
 
         let local_id = self.items.insert_with(move |local_id| RegisteredAttribute {
             local_id,
+            name: Arc::from(schema.ident.as_str()),
             namespace: namespace.to_string(),
             plain_name: plain_name.to_string(),
             schema,
@@ -219,7 +225,9 @@ impl AttributeRegistry {
             self.validate_schema(&schema, true)?;
         }
 
-        self.items.get_mut(old_id).schema = schema;
+        let item = self.items.get_mut(old_id);
+        item.name = Arc::from(schema.ident.as_str());
+        item.schema = schema;
         Ok(old_id)
     }
 
@@ -253,8 +261,55 @@ impl AttributeRegistry {
 
         match &attr.value_type {
             x if x.is_scalar() => {}
+            ValueType::Object(_) | ValueType::List(_) | ValueType::Json => {}
+            other => {
+                return Err(anyhow!(
+                    "Invalid attribute type {:?} - attributes must be scalar values",
+                    other,
+                ));
+            }
+        }
+        Self::validate_nested_value_type(&attr.value_type)
+            .with_context(|| format!("Invalid type for attribute '{}'", attr.ident))?;
+
+        if !attr.collation.is_binary() {
+            let is_string = match &attr.value_type {
+                ValueType::List(item_type) => **item_type == ValueType::String,
+                other => *other == ValueType::String,
+            };
+            if !is_string {
+                return Err(anyhow!(
+                    "Invalid attribute '{}': collation {:?} requires a string type",
+                    attr.ident,
+                    attr.collation,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the nested types of container types.
+    fn validate_nested_value_type(ty: &ValueType) -> Result<(), anyhow::Error> {
+        match ty {
+            ValueType::List(item_type) => Self::validate_nested_value_type(item_type),
+            ValueType::Map(map_type) => {
+                if matches!(
+                    map_type.key,
+                    ValueType::List(_) | ValueType::Map(_) | ValueType::Object(_)
+                ) {
+                    return Err(anyhow!(
+                        "invalid map key type {:?} - map keys must be scalar values",
+                        map_type.key
+                    ));
+                }
+                Self::validate_nested_value_type(&map_type.value)
+            }
+            ValueType::Union(variants) => variants
+                .iter()
+                .try_for_each(Self::validate_nested_value_type),
             ValueType::Object(obj) => {
-                for field in &obj.fields {
+                for (index, field) in obj.fields.iter().enumerate() {
                     if field.name.len() > super::MAX_NAME_LEN {
                         return Err(anyhow!(
                             "attribute field name '{}' exceeds maximum field name length of {}",
@@ -262,22 +317,19 @@ impl AttributeRegistry {
                             super::MAX_NAME_LEN
                         ));
                     }
+                    if obj.fields[..index]
+                        .iter()
+                        .any(|other| other.name == field.name)
+                    {
+                        return Err(anyhow!("duplicate object field '{}'", field.name));
+                    }
+                    Self::validate_nested_value_type(&field.value_type)
+                        .with_context(|| format!("invalid type for field '{}'", field.name))?;
                 }
+                Ok(())
             }
-            ValueType::List(item_type) => {
-                if !item_type.is_scalar() {
-                    // FIXME: removed after allowing List<_>, validate if any checks need to be added
-                    // bail!("List item type '{:?}' is not a scalar", item_type);
-                }
-            }
-            other => {
-                return Err(anyhow!(
-                    "Invalid attribute type {:?} - attributes must be scalar values",
-                    other,
-                ));
-            }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     pub(super) fn remove(&mut self, id: Id) -> Result<(), anyhow::Error> {
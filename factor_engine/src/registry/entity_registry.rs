@@ -3,7 +3,10 @@ use std::collections::{HashMap, HashSet};
 use anyhow::{anyhow, bail, Context};
 use fnv::{FnvHashMap, FnvHashSet};
 
-use crate::util::stable_map::{StableMap, StableMapKey};
+use crate::util::{
+    stable_map::{StableMap, StableMapKey},
+    BitSet,
+};
 
 use factor_core::{
     data::{Id, IdOrIdent, Ident},
@@ -11,11 +14,30 @@ use factor_core::{
     schema,
 };
 
-use super::attribute_registry::AttributeRegistry;
+use super::attribute_registry::{AttributeRegistry, RegisteredAttribute};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct LocalEntityId(u32);
 
+/// A set of classes, stored as a bitmap of their local ids.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct ClassSet(BitSet);
+
+impl ClassSet {
+    pub fn insert(&mut self, id: LocalEntityId) {
+        self.0.insert(id.as_index());
+    }
+
+    #[inline]
+    pub fn contains(&self, id: LocalEntityId) -> bool {
+        self.0.contains(id.as_index())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = LocalEntityId> + '_ {
+        self.0.iter().map(LocalEntityId::from_index)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RegisteredEntity {
     pub local_id: LocalEntityId,
@@ -26,7 +48,39 @@ pub struct RegisteredEntity {
     pub extends: FnvHashSet<LocalEntityId>,
     /// Stores all child ids, including nested children.
     pub nested_children: FnvHashSet<Id>,
+    /// The class itself and all nested children.
+    ///
+    /// Allows checking type inheritance without resolving class names.
+    pub descendants: ClassSet,
     pub nested_attribute_names: FnvHashSet<String>,
+    /// The resolved attributes of the class and all its parents.
+    ///
+    /// Own attributes come first, in the order of `schema.attributes`.
+    /// Attributes declared by multiple classes are only listed once, with the
+    /// constraints of all declarations. They are required if any declaration
+    /// requires them.
+    ///
+    /// Must be refreshed with [`EntityRegistry::refresh_attributes`] when an
+    /// attribute or a parent class changes.
+    pub attributes: Vec<ResolvedClassAttribute>,
+}
+
+/// A class attribute with the resolved attribute schema.
+#[derive(Clone, Debug)]
+pub struct ResolvedClassAttribute {
+    pub attribute: RegisteredAttribute,
+    pub cardinality: schema::Cardinality,
+    pub constraints: Vec<CompiledConstraint>,
+}
+
+/// A [`schema::ValueConstraint`] prepared for validation.
+///
+/// Regexes are compiled once when the class is registered.
+#[derive(Clone, Debug)]
+pub struct CompiledConstraint {
+    pub constraint: schema::ValueConstraint,
+    /// Only set for [`schema::ValueConstraint::Regex`].
+    pub regex: Option<regex::Regex>,
 }
 
 #[derive(Clone, Debug)]
@@ -61,20 +115,26 @@ impl EntityRegistry {
         }
     }
 
-    fn add_entity_hierarchy_item(&mut self, parent: LocalEntityId, child_id: Id) {
+    fn add_entity_hierarchy_item(
+        &mut self,
+        parent: LocalEntityId,
+        child_id: Id,
+        child_local_id: LocalEntityId,
+    ) {
         if let Some(parent) = self.get_mut(parent) {
             parent.nested_children.insert(child_id);
+            parent.descendants.insert(child_local_id);
 
             let nested = parent.extends.clone();
             for nested_parent in nested {
-                self.add_entity_hierarchy_item(nested_parent, child_id);
+                self.add_entity_hierarchy_item(nested_parent, child_id, child_local_id);
             }
         }
     }
 
     fn add_entity_to_hierarchy(&mut self, entity: &RegisteredEntity) {
         for parent_id in &entity.extends {
-            self.add_entity_hierarchy_item(*parent_id, entity.schema.id);
+            self.add_entity_hierarchy_item(*parent_id, entity.schema.id, entity.local_id);
         }
     }
 
@@ -94,11 +154,11 @@ impl EntityRegistry {
             parent_ids.insert(parent.local_id);
             nested_attribute_names.extend(parent.nested_attribute_names.clone());
         }
-
         for field in &schema.attributes {
             let attr = attrs.must_get_by_name(&field.attribute)?;
             nested_attribute_names.insert(attr.schema.ident.clone());
         }
+        let attributes = self.resolve_attributes(&schema, attrs)?;
 
         Ok(RegisteredEntity {
             local_id: LocalEntityId(0),
@@ -108,10 +168,96 @@ impl EntityRegistry {
             plain_name,
             extends: parent_ids,
             nested_children: FnvHashSet::default(),
+            descendants: ClassSet::default(),
             nested_attribute_names,
+            attributes,
         })
     }
 
+    /// Resolve the attributes of a class and its parents.
+    ///
+    /// See [`RegisteredEntity::attributes`].
+    fn resolve_attributes(
+        &self,
+        schema: &schema::Class,
+        attrs: &AttributeRegistry,
+    ) -> Result<Vec<ResolvedClassAttribute>, anyhow::Error> {
+        let mut attributes = Vec::<ResolvedClassAttribute>::with_capacity(schema.attributes.len());
+        for field in &schema.attributes {
+            let attr = attrs.must_get_by_name(&field.attribute)?;
+
+            let mut constraints = Vec::with_capacity(field.constraints.len());
+            for constraint in &field.constraints {
+                let regex = match constraint {
+                    schema::ValueConstraint::Regex(raw) => {
+                        Some(regex::Regex::new(raw).with_context(|| {
+                            format!(
+                                "Invalid regex constraint for attribute '{}'",
+                                field.attribute
+                            )
+                        })?)
+                    }
+                    _ => None,
+                };
+                constraints.push(CompiledConstraint {
+                    constraint: constraint.clone(),
+                    regex,
+                });
+            }
+
+            attributes.push(ResolvedClassAttribute {
+                attribute: attr.clone(),
+                cardinality: field.cardinality(),
+                constraints,
+            });
+        }
+
+        // Parents are always registered before their children, so their
+        // attributes are already resolved.
+        for parent_name in &schema.extends {
+            let parent = self.must_get_by_name(parent_name)?;
+            for inherited in &parent.attributes {
+                let existing = attributes
+                    .iter_mut()
+                    .find(|a| a.attribute.local_id == inherited.attribute.local_id);
+                match existing {
+                    Some(existing) => {
+                        if inherited.cardinality == schema::Cardinality::Required {
+                            existing.cardinality = schema::Cardinality::Required;
+                        }
+                        existing
+                            .constraints
+                            .extend(inherited.constraints.iter().cloned());
+                    }
+                    None => attributes.push(inherited.clone()),
+                }
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Re-resolve the attributes of all classes that match `filter`.
+    ///
+    /// Classes are refreshed in registration order, so parents are always up
+    /// to date before their children.
+    pub(super) fn refresh_attributes(
+        &mut self,
+        attrs: &AttributeRegistry,
+        filter: impl Fn(&RegisteredEntity) -> bool,
+    ) -> Result<(), anyhow::Error> {
+        for index in 0..self.items.len() {
+            let local_id = LocalEntityId::from_index(index);
+            let item = self.items.get(local_id);
+            if item.is_deleted || !filter(item) {
+                continue;
+            }
+            let attributes = self.resolve_attributes(&item.schema, attrs)?;
+            self.items.get_mut(local_id).attributes = attributes;
+        }
+        Ok(())
+    }
+
     fn add(
         &mut self,
         schema: schema::Class,
@@ -122,9 +268,14 @@ impl EntityRegistry {
 
         let registered = self.build_registered_entity(schema.clone(), attrs)?;
 
-        let local_id = self.items.insert_with(move |local_id| RegisteredEntity {
-            local_id,
-            ..registered
+        let local_id = self.items.insert_with(move |local_id| {
+            let mut descendants = ClassSet::default();
+            descendants.insert(local_id);
+            RegisteredEntity {
+                local_id,
+                descendants,
+                ..registered
+            }
         });
 
         self.add_entity_to_hierarchy(&self.get(local_id).unwrap().clone());
@@ -261,10 +412,23 @@ impl EntityRegistry {
         };
 
         let new = self.build_registered_entity(entity, attrs)?;
-        *self.items.get_mut(local_id) = new;
+        let old = self.items.get_mut(local_id);
+        // Extends can't change, so the children stay the same.
+        *old = RegisteredEntity {
+            local_id,
+            nested_children: std::mem::take(&mut old.nested_children),
+            descendants: std::mem::take(&mut old.descendants),
+            ..new
+        };
 
         self.add_entity_to_hierarchy(&self.items.get(local_id).clone());
 
+        // Children cache the inherited attributes.
+        let descendants = self.items.get(local_id).descendants.clone();
+        self.refresh_attributes(attrs, |item| {
+            item.local_id != local_id && descendants.contains(item.local_id)
+        })?;
+
         // FIXME: also need to do additional cleanup of the hierarchy
         // (remove from nested_children if parent removed)!
 
@@ -311,6 +475,13 @@ impl EntityRegistry {
             if extended_ids.contains(&parent.schema.id) {
                 return Err(anyhow!("Can't specify the same parent type twice"));
             }
+            if entity.is_abstract && !parent.schema.is_abstract {
+                return Err(anyhow!(
+                    "Abstract class '{}' can't extend the concrete class '{}'",
+                    entity.ident,
+                    parent.schema.ident,
+                ));
+            }
             extended_ids.insert(parent.schema.id);
 
             for field in &parent.schema.attributes {
@@ -2,12 +2,12 @@ use anyhow::{anyhow, Context};
 use fnv::FnvHashMap;
 
 use factor_core::{
-    data::{Id, Ident},
+    data::{geo::GEOHASH_MAX_PRECISION, DataMap, GeoPoint, Id, Ident, Value, ValueType},
     error::IndexNotFound,
-    schema,
+    schema::{self, Collation},
 };
 
-use super::{attribute_registry::AttributeRegistry, LocalAttributeId};
+use super::{attribute_registry::AttributeRegistry, LocalAttributeId, Registry};
 use crate::util::stable_map::{StableMap, StableMapKey};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -28,6 +28,79 @@ pub struct RegisteredIndex {
     pub is_deleted: bool,
     pub namespace: String,
     pub plain_name: String,
+    /// Index on a single [`ValueType::GeoPoint`] attribute.
+    /// Keys are geohashes instead of the plain values.
+    pub geo: bool,
+    /// Collation of the indexed attribute.
+    /// Keys of case insensitive indexes are normalized.
+    pub collation: Collation,
+}
+
+impl RegisteredIndex {
+    /// Build the key stored in the index for an attribute value.
+    ///
+    /// Tenant scoped indexes prefix the value with the tenant of the entity,
+    /// so unique values only conflict within the same tenant.
+    ///
+    /// Geo indexes store the geohash of points, so nearby points share a
+    /// common key prefix.
+    ///
+    /// Values are normalized according to the [`Collation`] of the
+    /// attribute.
+    pub fn index_key(&self, value: Value, tenant: Option<&Value>) -> Value {
+        let value = match self.geo.then(|| GeoPoint::try_from(&value)) {
+            Some(Ok(point)) => Value::String(point.geohash(GEOHASH_MAX_PRECISION)),
+            _ => self.collation.normalize(value),
+        };
+        if self.schema.tenant_scoped {
+            Value::List(vec![tenant.cloned().unwrap_or(Value::Unit), value])
+        } else {
+            value
+        }
+    }
+
+    /// Build the keys stored in the index for an attribute value.
+    ///
+    /// Multi-valued indexes store a key for each distinct item of a list,
+    /// all other indexes a single key (see [`Self::index_key`]).
+    pub fn index_keys(&self, value: Value, tenant: Option<&Value>) -> Vec<Value> {
+        match value {
+            Value::List(items) if self.schema.multi_valued => {
+                let mut keys = Vec::with_capacity(items.len());
+                for item in items {
+                    let key = self.index_key(item, tenant);
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                keys
+            }
+            other => vec![self.index_key(other, tenant)],
+        }
+    }
+
+    /// Check if an attribute value is stored in the index.
+    ///
+    /// Sparse indexes skip unit values.
+    pub fn indexes_value(&self, value: &Value) -> bool {
+        !(self.schema.sparse && matches!(value, Value::Unit))
+    }
+
+    /// Whether the index has a filter.
+    /// See [`schema::IndexSchema::filter`].
+    pub fn is_partial(&self) -> bool {
+        self.schema.filter.is_some()
+    }
+
+    /// Check if an entity belongs into the index.
+    ///
+    /// Always true for indexes without a filter.
+    pub fn includes(&self, data: &DataMap, reg: &Registry) -> Result<bool, anyhow::Error> {
+        match &self.schema.filter {
+            Some(filter) => crate::policy::matches_filter(filter, data, reg),
+            None => Ok(true),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -74,6 +147,8 @@ impl IndexRegistry {
         &mut self,
         schema: schema::IndexSchema,
         local_attribute_ids: Vec<LocalAttributeId>,
+        geo: bool,
+        collation: Collation,
     ) -> Result<LocalIndexId, anyhow::Error> {
         assert!(self.items.len() < u32::MAX as usize - 1);
 
@@ -89,6 +164,8 @@ impl IndexRegistry {
             plain_name: plain_name.to_string(),
             schema,
             is_deleted: false,
+            geo,
+            collation,
         });
         self.uids.insert(uid, local_id);
         self.names.insert(ident, local_id);
@@ -197,7 +274,36 @@ impl IndexRegistry {
         attrs: &AttributeRegistry,
     ) -> Result<LocalIndexId, anyhow::Error> {
         let local_attribute_ids = self.validate_schema(&index, attrs)?;
-        self.add(index, local_attribute_ids)
+        let (geo, collation) = match index.attributes.as_slice() {
+            [attr] => {
+                let attr = &attrs.must_get_by_uid(*attr)?.schema;
+                (attr.value_type == ValueType::GeoPoint, attr.collation)
+            }
+            _ => (false, Collation::Binary),
+        };
+        if index.multi_valued {
+            let is_list = match index.attributes.as_slice() {
+                [attr] => matches!(
+                    attrs.must_get_by_uid(*attr)?.schema.value_type,
+                    ValueType::List(_)
+                ),
+                _ => false,
+            };
+            if !is_list || index.filter.is_some() {
+                return Err(anyhow!(
+                    "Invalid index '{}': multi-valued indexes must have a single list attribute \
+                     and can not have a filter",
+                    index.ident
+                ));
+            }
+        }
+        if geo && (index.unique || index.tenant_scoped) {
+            return Err(anyhow!(
+                "Invalid index '{}': geo indexes can not be unique or tenant scoped",
+                index.ident
+            ));
+        }
+        self.add(index, local_attribute_ids, geo, collation)
     }
 
     pub(super) fn remove(&mut self, id: Id) -> Result<(), anyhow::Error> {
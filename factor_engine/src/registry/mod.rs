@@ -1,35 +1,46 @@
 mod attribute_registry;
 mod entity_registry;
 mod index_registry;
+mod naming;
 
 use fnv::FnvHashSet;
 
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
 
 use anyhow::{anyhow, bail, Context};
 
 use factor_core::{
     data::{DataMap, Id, IdMap, IdOrIdent, Value, ValueType},
-    error::{AttributeNotFound, EntityNotFound, IndexNotFound, ReferenceConstraintViolation},
+    error::{
+        AttributeNotFound, EntityNotFound, IndexNotFound, ReferenceConstraintViolation,
+        ValueConstraintViolation,
+    },
     query,
     schema::{
         self,
-        builtin::{AttrId, AttrType},
-        AttrMapExt, AttributeMeta, Cardinality, DbSchema,
+        builtin::{
+            AttrClocks, AttrId, AttrTenant, AttrType, ATTR_AUDIT_ACTOR, ATTR_AUDIT_ENTITY,
+            ATTR_TENANT,
+        },
+        AttrMapExt, AttributeMeta, Cardinality, DbSchema, Mixin, PolicySchema, RuleAction,
+        RuleEvent, RuleSchema, ValueConstraint, ViewSchema,
     },
 };
 
 use crate::backend::{
     DbOp, TupleCreate, TupleDelete, TupleIndexInsert, TupleIndexOp, TupleIndexRemove,
-    TupleIndexReplace, TupleMerge, TupleOp, TupleReplace, ValidateEntityType,
+    TupleIndexReplace, TupleMerge, TupleOp, TupleReplace, ValidateAcyclic, ValidateEntityType,
 };
 
 use self::entity_registry::EntityRegistry;
 
 pub use self::{
     attribute_registry::{LocalAttributeId, RegisteredAttribute},
-    entity_registry::{LocalEntityId, RegisteredEntity},
+    entity_registry::{ClassSet, CompiledConstraint, LocalEntityId, RegisteredEntity},
     index_registry::{LocalIndexId, RegisteredIndex},
+    naming::{NamingPolicy, SchemaItemKind},
 };
 
 const MAX_NAME_LEN: usize = 50;
@@ -42,12 +53,36 @@ pub const ATTR_PARENT_LOCAL: LocalAttributeId = LocalAttributeId::from_u32(14);
 
 pub const INDEX_ENTITY_TYPE_LOCAL: LocalIndexId = LocalIndexId::from_u32(0);
 pub const INDEX_IDENT_LOCAL: LocalIndexId = LocalIndexId::from_u32(1);
+pub const INDEX_ALIASES_LOCAL: LocalIndexId = LocalIndexId::from_u32(3);
+
+/// Reference attributes that may point to ids which are not stored as
+/// entities, so the existence of the target is not validated.
+///
+/// Tenants are identified by ids chosen by the application, eg. an account
+/// id of an external identity provider, and are usually not entities of the
+/// database. [`factor_core::db::Db::with_tenant`] stamps every created entity
+/// with the tenant, so requiring a stored target would make all writes of a
+/// new tenant fail. Isolation only depends on comparing the id, never on the
+/// referenced entity.
+///
+/// Audit entries outlive the entities they record.
+fn is_unchecked_ref(attr: &RegisteredAttribute) -> bool {
+    matches!(
+        attr.schema.id,
+        ATTR_TENANT | ATTR_AUDIT_ENTITY | ATTR_AUDIT_ACTOR
+    )
+}
 
 #[derive(Clone, Debug)]
 pub struct Registry {
     entities: EntityRegistry,
     attrs: attribute_registry::AttributeRegistry,
     indexes: index_registry::IndexRegistry,
+    policies: Vec<PolicySchema>,
+    views: Vec<ViewSchema>,
+    rules: Vec<RuleSchema>,
+    mixins: Vec<Mixin>,
+    naming: NamingPolicy,
 }
 
 impl Registry {
@@ -56,6 +91,11 @@ impl Registry {
             attrs: attribute_registry::AttributeRegistry::new(),
             entities: entity_registry::EntityRegistry::new(),
             indexes: index_registry::IndexRegistry::new(),
+            policies: Vec::new(),
+            views: Vec::new(),
+            rules: Vec::new(),
+            mixins: Vec::new(),
+            naming: NamingPolicy::default(),
         };
         s.add_builtins();
         s
@@ -79,22 +119,40 @@ impl Registry {
                 .iter()
                 .map(|item| item.schema.clone())
                 .collect(),
+            policies: self.policies.clone(),
+            views: self.views.clone(),
+            rules: self.rules.clone(),
+            mixins: self.mixins.clone(),
         }
     }
 
     /// Reset all state.
     /// Removes all registered entities and attributes, but restores the
     /// builtins.
+    /// The naming policy is kept.
     pub fn reset(&mut self) {
         self.attrs.reset();
         self.entities = EntityRegistry::new();
         self.indexes.reset();
+        self.policies.clear();
+        self.views.clear();
+        self.rules.clear();
+        self.mixins.clear();
 
         self.add_builtins();
     }
 
+    pub fn naming_policy(&self) -> &NamingPolicy {
+        &self.naming
+    }
+
+    /// Set the [`NamingPolicy`] that is enforced for new schema items.
+    pub fn set_naming_policy(&mut self, policy: NamingPolicy) {
+        self.naming = policy;
+    }
+
     pub fn into_shared(self) -> SharedRegistry {
-        Arc::new(RwLock::new(self))
+        Arc::new(ArcSwap::from_pointee(self))
     }
 
     pub fn attr(&self, id: LocalAttributeId) -> &RegisteredAttribute {
@@ -116,6 +174,11 @@ impl Registry {
         self.attrs.must_get_by_uid(id)
     }
 
+    #[inline]
+    pub fn entity(&self, id: LocalEntityId) -> Option<&RegisteredEntity> {
+        self.entities.get(id)
+    }
+
     #[inline]
     pub fn entity_by_id(&self, id: Id) -> Option<&RegisteredEntity> {
         self.entities.get_by_uid(id)
@@ -163,6 +226,163 @@ impl Registry {
         self.entities.iter().skip(1)
     }
 
+    /// Find a computed attribute and the class that declares it.
+    ///
+    /// See [`schema::ComputedAttribute`].
+    pub fn computed_attribute(
+        &self,
+        ident: &str,
+    ) -> Option<(&RegisteredEntity, &schema::ComputedAttribute)> {
+        self.iter_entities()
+            .filter(|class| !class.is_deleted)
+            .find_map(|class| Some((class, class.schema.computed_attribute(ident)?)))
+    }
+
+    /// The expression of a computed attribute that can be evaluated for any
+    /// entity.
+    ///
+    /// Evaluates to unit for entities that are not of the declaring class or
+    /// one of its children.
+    pub fn computed_attribute_expr(&self, ident: &str) -> Option<query::expr::Expr> {
+        use query::expr::Expr;
+
+        let (class, computed) = self.computed_attribute(ident)?;
+        Some(Expr::If {
+            value: Box::new(Expr::InheritsEntityType(class.schema.ident.clone())),
+            then: Box::new(computed.expr.clone()),
+            or: Box::new(Expr::Literal(Value::Unit)),
+        })
+    }
+
+    /// All computed attributes of a class, including inherited ones.
+    pub fn class_computed_attributes<'a>(
+        &'a self,
+        class: &'a RegisteredEntity,
+        attrs: &mut Vec<&'a schema::ComputedAttribute>,
+    ) {
+        attrs.extend(&class.schema.computed_attributes);
+        for parent in &class.extends {
+            if let Some(parent) = self.entities.get(*parent) {
+                self.class_computed_attributes(parent, attrs);
+            }
+        }
+    }
+
+    /// Add the values of computed attributes to entity data.
+    ///
+    /// Attributes that evaluate to unit are omitted.
+    pub fn add_computed_attributes(&self, data: &mut DataMap) -> Result<(), anyhow::Error> {
+        let class = match data.get_type().and_then(|ty| self.entity_by_ident(&ty)) {
+            Some(class) => class,
+            None => return Ok(()),
+        };
+        let mut computed_attrs = Vec::new();
+        self.class_computed_attributes(class, &mut computed_attrs);
+
+        let values = computed_attrs
+            .into_iter()
+            .map(|computed| {
+                let value = crate::policy::eval_expr(&computed.expr, data, self)?;
+                Ok((computed.ident.clone(), value))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        for (ident, value) in values {
+            if value != Value::Unit {
+                data.insert(ident, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate the computed attributes of a registered class.
+    fn validate_computed_attributes(&self, class_id: Id) -> Result<(), anyhow::Error> {
+        let class = self.require_entity_by_id(class_id)?;
+        for (index, computed) in class.schema.computed_attributes.iter().enumerate() {
+            factor_core::data::Ident::parse_parts(&computed.ident)?;
+            if self.attr_by_name(&computed.ident).is_some() {
+                bail!(
+                    "Computed attribute '{}' of class '{}' conflicts with an existing attribute",
+                    computed.ident,
+                    class.schema.ident
+                );
+            }
+            let duplicate = class.schema.computed_attributes[..index]
+                .iter()
+                .any(|other| other.ident == computed.ident);
+            if duplicate {
+                bail!("Duplicate computed attribute '{}'", computed.ident);
+            }
+            if let Some((other, _)) = self.computed_attribute(&computed.ident) {
+                if other.schema.id != class.schema.id {
+                    bail!(
+                        "Computed attribute '{}' is already defined by class '{}'",
+                        computed.ident,
+                        other.schema.ident
+                    );
+                }
+            }
+
+            self.validate_computed_expr(class, &computed.expr)
+                .and_then(|_| crate::plan::resolve_expr(computed.expr.clone(), self))
+                .with_context(|| {
+                    format!(
+                        "Invalid expression for computed attribute '{}'",
+                        computed.ident
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Check that a computed attribute expression only uses attributes of
+    /// the class.
+    fn validate_computed_expr(
+        &self,
+        class: &RegisteredEntity,
+        expr: &query::expr::Expr,
+    ) -> Result<(), anyhow::Error> {
+        use query::expr::Expr;
+
+        match expr {
+            Expr::Literal(_) => Ok(()),
+            Expr::Attr(ident) => {
+                let attr = self.require_attr_by_ident(ident)?;
+                if attr.schema.ident != AttrId::QUALIFIED_NAME
+                    && !class.nested_attribute_names.contains(&attr.schema.ident)
+                {
+                    bail!(
+                        "Attribute '{}' does not belong to class '{}'",
+                        attr.schema.ident,
+                        class.schema.ident
+                    );
+                }
+                Ok(())
+            }
+            Expr::List(items) => items
+                .iter()
+                .try_for_each(|item| self.validate_computed_expr(class, item)),
+            Expr::UnaryOp { expr, .. } => self.validate_computed_expr(class, expr),
+            Expr::BinaryOp { left, right, .. } => {
+                self.validate_computed_expr(class, left)?;
+                self.validate_computed_expr(class, right)
+            }
+            Expr::If { value, then, or } => {
+                self.validate_computed_expr(class, value)?;
+                self.validate_computed_expr(class, then)?;
+                self.validate_computed_expr(class, or)
+            }
+            Expr::WithinRadius { value, .. } | Expr::JsonPath { value, .. } => {
+                self.validate_computed_expr(class, value)
+            }
+            Expr::InheritsEntityType(_)
+            | Expr::TypeInNamespace(_)
+            | Expr::Ident(_)
+            | Expr::Variable(_) => {
+                bail!("Unsupported expression: {:?}", expr)
+            }
+        }
+    }
+
     #[inline]
     pub fn require_attr_by_name(
         &self,
@@ -208,6 +428,246 @@ impl Registry {
         self.indexes.iter()
     }
 
+    pub fn policy_by_name(&self, name: &str) -> Option<&PolicySchema> {
+        self.policies.iter().find(|p| p.ident == name)
+    }
+
+    pub fn iter_policies(&self) -> impl Iterator<Item = &PolicySchema> {
+        self.policies.iter()
+    }
+
+    pub fn view_by_name(&self, name: &str) -> Option<&ViewSchema> {
+        self.views.iter().find(|v| v.ident == name)
+    }
+
+    pub fn iter_views(&self) -> impl Iterator<Item = &ViewSchema> {
+        self.views.iter()
+    }
+
+    pub fn rule_by_name(&self, name: &str) -> Option<&RuleSchema> {
+        self.rules.iter().find(|r| r.ident == name)
+    }
+
+    pub fn iter_rules(&self) -> impl Iterator<Item = &RuleSchema> {
+        self.rules.iter()
+    }
+
+    pub fn mixin_by_name(&self, name: &str) -> Option<&Mixin> {
+        self.mixins.iter().find(|m| m.ident == name)
+    }
+
+    pub fn iter_mixins(&self) -> impl Iterator<Item = &Mixin> {
+        self.mixins.iter()
+    }
+
+    /// Resolve the ident of a schema entity (attribute, class, index,
+    /// policy, view, rule or mixin) to its id.
+    pub fn resolve_ident(&self, name: &str) -> Option<Id> {
+        self.attr_by_name(name)
+            .map(|attr| attr.schema.id)
+            .or_else(|| self.entity_by_name(name).map(|entity| entity.schema.id))
+            .or_else(|| self.index_by_name(name).map(|index| index.schema.id))
+            .or_else(|| self.policy_by_name(name).map(|policy| policy.id))
+            .or_else(|| self.view_by_name(name).map(|view| view.id))
+            .or_else(|| self.rule_by_name(name).map(|rule| rule.id))
+            .or_else(|| self.mixin_by_name(name).map(|mixin| mixin.id))
+    }
+
+    /// Check if the id belongs to a registered schema entity.
+    fn is_schema_id(&self, id: Id) -> bool {
+        self.attrs.get_by_uid(id).is_some()
+            || self.entities.get_by_uid(id).is_some()
+            || self.indexes.get_by_uid(id).is_some()
+            || self.policies.iter().any(|policy| policy.id == id)
+            || self.views.iter().any(|view| view.id == id)
+            || self.rules.iter().any(|rule| rule.id == id)
+            || self.mixins.iter().any(|mixin| mixin.id == id)
+    }
+
+    /// Policies that apply to entities of the given class.
+    ///
+    /// Includes the policies of all parent classes.
+    pub fn policies_for_class(&self, class: Id) -> impl Iterator<Item = &PolicySchema> {
+        self.policies.iter().filter(move |policy| {
+            self.entity_by_name(&policy.class)
+                .map(|entity| entity.schema.id == class || entity.nested_children.contains(&class))
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn register_policy(&mut self, mut policy: PolicySchema) -> Result<Id, anyhow::Error> {
+        if self.policy_by_name(&policy.ident).is_some() {
+            bail!("Policy '{}' already exists", policy.ident);
+        }
+        self.require_entity_by_name(&policy.class)?;
+
+        policy.id = policy.id.non_nil_or_randomize();
+        let id = policy.id;
+        self.policies.push(policy);
+        Ok(id)
+    }
+
+    pub fn remove_policy(&mut self, name: &str) -> Result<PolicySchema, anyhow::Error> {
+        let index = self
+            .policies
+            .iter()
+            .position(|p| p.ident == name)
+            .ok_or_else(|| anyhow!("Policy '{}' not found", name))?;
+        Ok(self.policies.remove(index))
+    }
+
+    /// Rules that are triggered by the given event on entities of a class.
+    ///
+    /// Includes the rules of all parent classes.
+    pub fn rules_for_class(
+        &self,
+        class: Id,
+        event: RuleEvent,
+    ) -> impl Iterator<Item = &RuleSchema> {
+        self.rules.iter().filter(move |rule| {
+            rule.triggers_on(event)
+                && self
+                    .entity_by_name(&rule.class)
+                    .map(|entity| {
+                        entity.schema.id == class || entity.nested_children.contains(&class)
+                    })
+                    .unwrap_or(false)
+        })
+    }
+
+    pub fn register_rule(&mut self, mut rule: RuleSchema) -> Result<Id, anyhow::Error> {
+        if self.rule_by_name(&rule.ident).is_some() {
+            bail!("Rule '{}' already exists", rule.ident);
+        }
+        self.require_entity_by_name(&rule.class)?;
+        if rule.events.is_empty() {
+            bail!("Invalid rule '{}': no events specified", rule.ident);
+        }
+        for action in &rule.actions {
+            let data = match action {
+                RuleAction::Create { data } | RuleAction::Merge { data, .. } => data,
+            };
+            for attr in data.keys() {
+                self.require_attr_by_name(attr)
+                    .with_context(|| format!("Invalid action for rule '{}'", rule.ident))?;
+            }
+        }
+
+        rule.id = rule.id.non_nil_or_randomize();
+        let id = rule.id;
+        self.rules.push(rule);
+        Ok(id)
+    }
+
+    pub fn remove_rule(&mut self, name: &str) -> Result<RuleSchema, anyhow::Error> {
+        let index = self
+            .rules
+            .iter()
+            .position(|r| r.ident == name)
+            .ok_or_else(|| anyhow!("Rule '{}' not found", name))?;
+        Ok(self.rules.remove(index))
+    }
+
+    pub fn register_mixin(&mut self, mut mixin: Mixin) -> Result<Id, anyhow::Error> {
+        if self.mixin_by_name(&mixin.ident).is_some() {
+            bail!("Mixin '{}' already exists", mixin.ident);
+        }
+        if mixin.attributes.is_empty() {
+            bail!("Invalid mixin '{}': no attributes specified", mixin.ident);
+        }
+        let mut seen = FnvHashSet::default();
+        for attr in &mixin.attributes {
+            self.require_attr_by_name(&attr.attribute)
+                .with_context(|| format!("Invalid mixin '{}'", mixin.ident))?;
+            if !seen.insert(attr.attribute.as_str()) {
+                bail!(
+                    "Invalid mixin '{}': duplicate attribute '{}'",
+                    mixin.ident,
+                    attr.attribute
+                );
+            }
+        }
+
+        mixin.id = mixin.id.non_nil_or_randomize();
+        let id = mixin.id;
+        self.mixins.push(mixin);
+        Ok(id)
+    }
+
+    pub fn remove_mixin(&mut self, name: &str) -> Result<Mixin, anyhow::Error> {
+        let index = self
+            .mixins
+            .iter()
+            .position(|m| m.ident == name)
+            .ok_or_else(|| anyhow!("Mixin '{}' not found", name))?;
+        if let Some(class) = self
+            .entities
+            .iter()
+            .find(|e| !e.is_deleted && e.schema.mixins.iter().any(|m| m == name))
+        {
+            bail!(
+                "Can't delete mixin '{}': still in use by class '{}'",
+                name,
+                class.schema.ident
+            );
+        }
+        Ok(self.mixins.remove(index))
+    }
+
+    /// Add the attributes of the mixins of a class.
+    ///
+    /// Attributes the class already declares are kept as they are, so
+    /// expanding a class multiple times is a no-op.
+    pub fn expand_mixins(&self, class: &mut schema::Class) -> Result<(), anyhow::Error> {
+        for name in &class.mixins {
+            let mixin = self.mixin_by_name(name).ok_or_else(|| {
+                anyhow!(
+                    "Invalid class '{}': mixin '{}' not found",
+                    class.ident,
+                    name
+                )
+            })?;
+            for attr in &mixin.attributes {
+                if !class
+                    .attributes
+                    .iter()
+                    .any(|a| a.attribute == attr.attribute)
+                {
+                    class.attributes.push(attr.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn register_view(&mut self, mut view: ViewSchema) -> Result<Id, anyhow::Error> {
+        if self.view_by_name(&view.ident).is_some() {
+            bail!("View '{}' already exists", view.ident);
+        }
+        if !view.query.joins.is_empty() || view.query.cursor.is_some() {
+            bail!(
+                "Invalid view '{}': joins and cursors are not supported",
+                view.ident
+            );
+        }
+        crate::plan::plan_select(view.query.clone(), self)
+            .with_context(|| format!("Invalid query for view '{}'", view.ident))?;
+
+        view.id = view.id.non_nil_or_randomize();
+        let id = view.id;
+        self.views.push(view);
+        Ok(id)
+    }
+
+    pub fn remove_view(&mut self, name: &str) -> Result<ViewSchema, anyhow::Error> {
+        let index = self
+            .views
+            .iter()
+            .position(|v| v.ident == name)
+            .ok_or_else(|| anyhow!("View '{}' not found", name))?;
+        Ok(self.views.remove(index))
+    }
+
     pub fn indexes_for_attribute(&self, attribute_id: LocalAttributeId) -> Vec<&RegisteredIndex> {
         self.indexes.attribute_indexes(attribute_id)
     }
@@ -253,6 +713,9 @@ impl Registry {
             if index.id == schema::builtin::INDEX_IDENT {
                 assert_eq!(local_id, INDEX_IDENT_LOCAL);
             }
+            if index.id == schema::builtin::INDEX_ALIASES {
+                assert_eq!(local_id, INDEX_ALIASES_LOCAL);
+            }
         }
     }
 
@@ -303,6 +766,13 @@ impl Registry {
         &mut self,
         attr: schema::Attribute,
     ) -> Result<LocalAttributeId, anyhow::Error> {
+        if let Some((class, _)) = self.computed_attribute(&attr.ident) {
+            bail!(
+                "Attribute '{}' conflicts with a computed attribute of class '{}'",
+                attr.ident,
+                class.schema.ident
+            );
+        }
         self.attrs.register(attr, &self.entities)
     }
 
@@ -311,7 +781,14 @@ impl Registry {
         schema: schema::Attribute,
         validate: bool,
     ) -> Result<(), anyhow::Error> {
-        self.attrs.update(schema, validate)?;
+        let local_id = self.attrs.update(schema, validate)?;
+        // Classes cache the attribute schema.
+        self.entities.refresh_attributes(&self.attrs, |class| {
+            class
+                .attributes
+                .iter()
+                .any(|a| a.attribute.local_id == local_id)
+        })?;
         Ok(())
     }
 
@@ -349,18 +826,29 @@ impl Registry {
 
     pub fn register_class(
         &mut self,
-        entity: schema::Class,
+        mut entity: schema::Class,
         validate: bool,
     ) -> Result<LocalEntityId, anyhow::Error> {
-        self.entities.register(entity, validate, &self.attrs)
+        self.expand_mixins(&mut entity)?;
+        let id = entity.id;
+        let local_id = self.entities.register(entity, validate, &self.attrs)?;
+        if validate {
+            self.validate_computed_attributes(id)?;
+        }
+        Ok(local_id)
     }
 
     pub fn update_class(
         &mut self,
-        entity: schema::Class,
+        mut entity: schema::Class,
         validate: bool,
     ) -> Result<(), anyhow::Error> {
+        self.expand_mixins(&mut entity)?;
+        let id = entity.id;
         self.entities.update(entity, validate, &self.attrs)?;
+        if validate {
+            self.validate_computed_attributes(id)?;
+        }
         Ok(())
     }
 
@@ -398,9 +886,28 @@ impl Registry {
         &mut self,
         index: schema::IndexSchema,
     ) -> Result<LocalIndexId, anyhow::Error> {
+        if let Some(filter) = &index.filter {
+            self.validate_index_filter(&index, filter)?;
+        }
         self.indexes.register(index, &self.attrs)
     }
 
+    fn validate_index_filter(
+        &self,
+        index: &schema::IndexSchema,
+        filter: &query::expr::Expr,
+    ) -> Result<(), anyhow::Error> {
+        if index.attributes.len() != 1 {
+            bail!(
+                "Invalid index '{}': partial indexes must have a single attribute",
+                index.ident
+            );
+        }
+        crate::policy::matches_filter(filter, &DataMap::new(), self)
+            .with_context(|| format!("Invalid filter for index '{}'", index.ident))?;
+        Ok(())
+    }
+
     pub fn remove_index(&mut self, id: Id) -> Result<(), anyhow::Error> {
         self.indexes.remove(id)?;
         Ok(())
@@ -424,31 +931,102 @@ impl Registry {
         match ty {
             ValueType::Ref => {
                 // Check that referenced entity exists, but skip the ID field to prevent checking the current tuple.
-                if attr.local_id != ATTR_ID_LOCAL {
+                if attr.local_id != ATTR_ID_LOCAL && !is_unchecked_ref(attr) {
                     let id = value.as_id().unwrap();
-                    ops.push(DbOp::new_validate_entity_exists(id));
+                    // Schema entities live in the registry, not in the
+                    // backend.
+                    if !self.is_schema_id(id) {
+                        ops.push(DbOp::new_validate_entity_exists(id));
+                    }
                 }
             }
-            ValueType::RefConstrained(_con) => {
+            ValueType::RefConstrained(con) => {
                 if attr.local_id != ATTR_ID_LOCAL {
                     let id = value.as_id().unwrap();
                     // TODO: don't require cloning every time...
-                    let allowed_types = attr
-                        .ref_allowed_entity_types
-                        .clone()
-                        .expect("Internal error: attr.ref_allowed_entity_types must be set if attr has ref type constraints");
+                    // Allowed types are only pre-computed for top-level
+                    // references, nested ones are resolved here.
+                    let allowed_types = match &attr.ref_allowed_entity_types {
+                        Some(types) => types.clone(),
+                        None => con
+                            .allowed_entity_types
+                            .iter()
+                            .map(|ty| {
+                                self.entities
+                                    .must_get_by_ident(ty)
+                                    .map(|entity| entity.schema.id)
+                            })
+                            .collect::<Result<_, EntityNotFound>>()?,
+                    };
                     ops.push(DbOp::ValidateEntityType(ValidateEntityType {
                         id,
+                        attribute: attr.local_id,
                         allowed_types,
                     }));
                 }
             }
+            ValueType::List(item_type) => {
+                // NOTE: this unwrap is fine because the value was coerced to a list.
+                for item in value.as_list().unwrap() {
+                    self.build_attr_value_ops(attr, item_type, item, ops)?;
+                }
+            }
+            ValueType::Map(map_type) => {
+                if let Value::Map(map) = value {
+                    for (key, value) in map.iter() {
+                        self.build_attr_value_ops(attr, &map_type.key, key, ops)?;
+                        self.build_attr_value_ops(attr, &map_type.value, value, ops)?;
+                    }
+                }
+            }
+            ValueType::Object(obj) => {
+                if let Value::Map(map) = value {
+                    for field in &obj.fields {
+                        if let Some(value) = map.get(&Value::String(field.name.clone())) {
+                            self.build_attr_value_ops(attr, &field.value_type, value, ops)?;
+                        }
+                    }
+                }
+            }
             _ => {}
         }
 
         Ok(())
     }
 
+    /// Build the cycle checks for the acyclic reference attributes of an
+    /// entity.
+    ///
+    /// See [`factor_core::data::value_type::ConstrainedRefType::acyclic_depth`].
+    fn build_acyclic_ops(&self, entity: Id, data: &DataMap, ops: &mut Vec<DbOp>) {
+        for (key, value) in data.iter() {
+            let attr = match self.attr_by_name(key) {
+                Some(attr) => attr,
+                None => continue,
+            };
+            let max_depth = match &attr.schema.value_type {
+                ValueType::RefConstrained(con) => con.acyclic_depth,
+                ValueType::List(item_type) => match &**item_type {
+                    ValueType::RefConstrained(con) => con.acyclic_depth,
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(max_depth) = max_depth {
+                let targets = match value {
+                    Value::List(items) => items.iter().filter_map(Value::as_id).collect(),
+                    other => other.as_id().into_iter().collect(),
+                };
+                ops.push(DbOp::ValidateAcyclic(ValidateAcyclic {
+                    entity,
+                    attribute: attr.local_id,
+                    targets,
+                    max_depth,
+                }));
+            }
+        }
+    }
+
     fn validate_attr_value(
         &self,
         attr: &RegisteredAttribute,
@@ -458,15 +1036,130 @@ impl Registry {
         value
             .coerce_mut(&attr.schema.value_type)
             .context(format!("Invalid value for attribute {}", attr.schema.ident))?;
+        self.build_attr_value_ops(attr, &attr.schema.value_type, value, ops)?;
+
+        Ok(())
+    }
+
+    /// Replace idents in the reference values of entity data with the id of
+    /// the referenced entity.
+    ///
+    /// Schema idents are resolved by the registry, all other idents are
+    /// looked up in the stored entities with `resolve`.
+    /// Unknown idents are left untouched, so coercion rejects them.
+    ///
+    /// Returns `false` if an ident could not be resolved.
+    pub fn resolve_data_idents(
+        &self,
+        data: &mut DataMap,
+        resolve: &ResolveIdent,
+    ) -> Result<bool, anyhow::Error> {
+        let mut resolved = true;
+        for (key, value) in data.iter_mut() {
+            if let Some(attr) = self.attr_by_name(key) {
+                resolved &= self.resolve_ref_idents(&attr.schema.value_type, value, resolve)?;
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// See [`Self::resolve_data_idents`].
+    fn resolve_ref_idents(
+        &self,
+        ty: &ValueType,
+        value: &mut Value,
+        resolve: &ResolveIdent,
+    ) -> Result<bool, anyhow::Error> {
+        let mut resolved = true;
+        match ty {
+            ValueType::Ref | ValueType::RefConstrained(_) => {
+                if let Value::String(name) = value {
+                    if uuid::Uuid::parse_str(name).is_err() {
+                        let id = match self.resolve_ident(name) {
+                            Some(id) => Some(id),
+                            None => resolve(name)?,
+                        };
+                        match id {
+                            Some(id) => *value = Value::Id(id),
+                            None => resolved = false,
+                        }
+                    }
+                }
+            }
+            ValueType::List(item_type) => match value {
+                Value::List(items) => {
+                    for item in items {
+                        resolved &= self.resolve_ref_idents(item_type, item, resolve)?;
+                    }
+                }
+                // Single values are coerced to a list.
+                other => resolved = self.resolve_ref_idents(item_type, other, resolve)?,
+            },
+            ValueType::Map(map_type) => {
+                if let Value::Map(map) = value {
+                    for value in map.values_mut() {
+                        resolved &= self.resolve_ref_idents(&map_type.value, value, resolve)?;
+                    }
+                }
+            }
+            ValueType::Object(obj) => {
+                if let Value::Map(map) = value {
+                    for field in &obj.fields {
+                        if let Some(value) = map.get_mut(&Value::String(field.name.clone())) {
+                            resolved &=
+                                self.resolve_ref_idents(&field.value_type, value, resolve)?;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(resolved)
+    }
+
+    /// Check the [`ValueConstraint`]s of a class attribute.
+    ///
+    /// Length constraints apply to the value itself, other constraints are
+    /// checked against each item of list values.
+    // WARNING!: this function must only be called with a value that has already been
+    // coerced to the appropriate value type with `Value::coerce_mut`.
+    fn validate_value_constraints(
+        attr: &RegisteredAttribute,
+        constraints: &[CompiledConstraint],
+        value: &Value,
+    ) -> Result<(), anyhow::Error> {
+        for CompiledConstraint { constraint, regex } in constraints {
+            let items = match (constraint, value) {
+                (ValueConstraint::MinLength(_) | ValueConstraint::MaxLength(_), _) => {
+                    std::slice::from_ref(value)
+                }
+                (_, Value::List(items)) => items.as_slice(),
+                (_, _) => std::slice::from_ref(value),
+            };
 
-        if let ValueType::List(item_type) = &attr.schema.value_type {
-            // NOTE: this unwrap is fine because coerce_mut above has ensured that it is a list.
-            let items = value.as_list().unwrap();
             for item in items {
-                self.build_attr_value_ops(attr, item_type, item, ops)?;
+                let is_valid = match constraint {
+                    ValueConstraint::MinLength(_) | ValueConstraint::MaxLength(_) => {
+                        constraint.check_length(item)
+                    }
+                    ValueConstraint::Min(_) | ValueConstraint::Max(_) => {
+                        constraint.check_range(item)
+                    }
+                    ValueConstraint::Regex(_) => regex
+                        .as_ref()
+                        .zip(item.as_str())
+                        .map(|(re, s)| re.is_match(s)),
+                };
+
+                if is_valid != Some(true) {
+                    return Err(ValueConstraintViolation::new(
+                        attr.schema.ident.clone(),
+                        constraint.clone(),
+                        item.clone(),
+                    )
+                    .into());
+                }
             }
-        } else {
-            self.build_attr_value_ops(attr, &attr.schema.value_type, value, ops)?;
         }
 
         Ok(())
@@ -489,24 +1182,38 @@ impl Registry {
     // }
     //
 
+    /// Validate entity data against a class.
+    ///
+    /// `base` is the already stored and validated entity data that `data` is
+    /// merged into. Attributes of `base` are not validated again.
+    ///
+    /// Values of computed attributes are removed, so data that was read
+    /// from the database can be written back.
     fn validate_class_data(
         &self,
         data: &mut DataMap,
+        base: Option<&DataMap>,
         entity: &RegisteredEntity,
         ops: &mut Vec<DbOp>,
     ) -> Result<(), anyhow::Error> {
-        for field in &entity.schema.attributes {
-            // TODO: create a static list of fields for each entity so that
-            // we don't have to do this lookup each time.
-            let attr = self.attrs.must_get_by_name(&field.attribute)?;
+        let mut computed_attrs = Vec::new();
+        self.class_computed_attributes(entity, &mut computed_attrs);
+        for computed in computed_attrs {
+            data.remove(&computed.ident);
+        }
+
+        for resolved in &entity.attributes {
+            let attr = &resolved.attribute;
 
-            match (data.get_mut(&attr.schema.ident), field.cardinality()) {
+            match (data.get_mut(&attr.schema.ident), resolved.cardinality) {
                 // Handle optional fields that have a Unit value.
                 (Some(Value::Unit), Cardinality::Optional) => {
                     // Remove the unit value.
                     data.remove(&attr.schema.ident);
                 }
                 (None, Cardinality::Optional) => {}
+                (None, Cardinality::Required)
+                    if base.map_or(false, |base| base.contains_key(&attr.schema.ident)) => {}
                 (None, Cardinality::Required) => {
                     if attr.schema.value_type.is_list() {
                         data.insert(attr.schema.ident.clone(), Value::List(vec![]));
@@ -519,19 +1226,15 @@ impl Registry {
                 }
                 (Some(value), Cardinality::Optional) => {
                     self.validate_attr_value(attr, value, ops)?;
+                    Self::validate_value_constraints(attr, &resolved.constraints, value)?;
                 }
                 (Some(value), Cardinality::Required) => {
                     self.validate_attr_value(attr, value, ops)?;
+                    Self::validate_value_constraints(attr, &resolved.constraints, value)?;
                 }
             }
         }
 
-        // Validate extended parent fields.
-        for parent_id in &entity.extends {
-            let parent = self.entities.get(*parent_id).unwrap();
-            self.validate_class_data(data, parent, ops)?;
-        }
-
         // Validate extra attributes
         let mut to_remove = Vec::new();
         for (key, value) in data.iter_mut() {
@@ -539,7 +1242,8 @@ impl Registry {
                 continue;
             }
             if !entity.nested_attribute_names.contains(key) {
-                if entity.schema.strict {
+                // Clocks are maintained by the database for all classes.
+                if entity.schema.strict && key != AttrClocks::QUALIFIED_NAME {
                     bail!("Invalid attribute '{}' for entity '{}': entity type is strict and does not allow additional attributes", key, entity.schema.ident);
                 }
                 if value.is_nil() {
@@ -558,13 +1262,35 @@ impl Registry {
     }
 
     fn validate_attributes(
+        &self,
+        data: DataMap,
+        ops: &mut Vec<DbOp>,
+    ) -> Result<DataMap, anyhow::Error> {
+        self.validate_attributes_merged(data, None, ops)
+    }
+
+    /// Validate attributes that are merged into the stored entity data
+    /// `base`.
+    ///
+    /// See [`Self::validate_class_data`].
+    fn validate_attributes_merged(
         &self,
         mut data: DataMap,
+        base: Option<&DataMap>,
         ops: &mut Vec<DbOp>,
     ) -> Result<DataMap, anyhow::Error> {
-        if let Some(ty) = data.get_type() {
+        let ty = data
+            .get_type()
+            .or_else(|| base.and_then(|base| base.get_type()));
+        if let Some(ty) = ty {
             let entity = self.entities.must_get_by_ident(&ty)?;
-            self.validate_class_data(&mut data, entity, ops)?;
+            if entity.schema.is_abstract {
+                bail!(
+                    "Can't store entities of the abstract class '{}'",
+                    entity.schema.ident
+                );
+            }
+            self.validate_class_data(&mut data, base, entity, ops)?;
         } else {
             let mut to_remove = Vec::new();
             for (key, value) in &mut data.0 {
@@ -590,22 +1316,42 @@ impl Registry {
         attrs: &DataMap,
     ) -> Result<Vec<TupleIndexInsert>, anyhow::Error> {
         let mut ops = Vec::new();
+        let tenant = attrs.get(AttrTenant::QUALIFIED_NAME);
 
         for (attr_name, value) in attrs.iter() {
             let attr = self.require_attr_by_name(attr_name)?;
             for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
                 if index.schema.attributes.len() > 1 {
                     return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
                 }
+                if !index.indexes_value(value) {
+                    continue;
+                }
 
-                ops.push(TupleIndexInsert {
-                    index: index.local_id,
-                    value: value.clone(),
-                    unique: index.schema.unique,
-                });
+                for key in index.index_keys(value.clone(), tenant) {
+                    ops.push(TupleIndexInsert {
+                        index: index.local_id,
+                        attribute: attr.local_id,
+                        value: key,
+                        unique: index.schema.unique,
+                    });
+                }
             }
         }
 
+        for (index, attribute, key) in self.partial_index_keys(attrs)? {
+            ops.push(TupleIndexInsert {
+                index: index.local_id,
+                attribute,
+                value: key,
+                unique: index.schema.unique,
+            });
+        }
+
         Ok(ops)
     }
 
@@ -617,6 +1363,9 @@ impl Registry {
     ) -> Result<Vec<TupleIndexOp>, anyhow::Error> {
         let mut ops = Vec::new();
 
+        let tenant = attrs.get(AttrTenant::QUALIFIED_NAME);
+        let old_tenant = old.get(AttrTenant::QUALIFIED_NAME);
+
         let mut covered_attrs = fnv::FnvHashSet::<LocalAttributeId>::default();
 
         for (attr_name, value) in attrs.iter() {
@@ -624,27 +1373,25 @@ impl Registry {
             covered_attrs.insert(attr.local_id);
 
             for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
                 if index.schema.attributes.len() > 1 {
                     // FIXME: implement multi-attribute indexes.
                     return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
                 }
 
-                if let Some(old) = old.get(attr_name) {
-                    if old != value {
-                        ops.push(TupleIndexOp::Replace(TupleIndexReplace {
-                            index: index.local_id,
-                            value: value.clone(),
-                            old_value: old.clone(),
-                            unique: index.schema.unique,
-                        }));
-                    }
-                } else {
-                    ops.push(TupleIndexOp::Insert(TupleIndexInsert {
-                        index: index.local_id,
-                        value: value.clone(),
-                        unique: index.schema.unique,
-                    }));
-                }
+                let keys = Some(value)
+                    .filter(|value| index.indexes_value(value))
+                    .map(|value| index.index_keys(value.clone(), tenant))
+                    .unwrap_or_default();
+                let old_keys = old
+                    .get(attr_name)
+                    .filter(|old| index.indexes_value(old))
+                    .map(|old| index.index_keys(old.clone(), old_tenant))
+                    .unwrap_or_default();
+                Self::build_index_change_ops(index, attr.local_id, keys, old_keys, &mut ops);
             }
         }
 
@@ -655,17 +1402,109 @@ impl Registry {
             }
 
             for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
                 if index.schema.attributes.len() > 1 {
                     // FIXME: implement multi-attribute indexes.
                     return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
                 }
-                ops.push(TupleIndexOp::Remove(TupleIndexRemove {
-                    index: index.local_id,
-                    value: value.clone(),
-                }));
+                if !index.indexes_value(value) {
+                    continue;
+                }
+                for key in index.index_keys(value.clone(), old_tenant) {
+                    ops.push(TupleIndexOp::Remove(TupleIndexRemove {
+                        index: index.local_id,
+                        value: key,
+                    }));
+                }
             }
         }
 
+        self.build_partial_index_ops(attrs, old, &mut ops)?;
+
+        Ok(ops)
+    }
+
+    /// Build the index operations for merging `data` into the entity `old`.
+    ///
+    /// Only the merged attributes and the `removed` attributes are
+    /// considered, all other index entries stay untouched.
+    fn build_index_ops_merge(
+        &self,
+        data: &DataMap,
+        removed: &[String],
+        old: &DataMap,
+    ) -> Result<Vec<TupleIndexOp>, anyhow::Error> {
+        let mut ops = Vec::new();
+
+        let old_tenant = old.get(AttrTenant::QUALIFIED_NAME);
+        let tenant = data.get(AttrTenant::QUALIFIED_NAME).or(old_tenant);
+
+        for (attr_name, value) in data.iter() {
+            let attr = self.require_attr_by_name(attr_name)?;
+            for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
+                if index.schema.attributes.len() > 1 {
+                    // FIXME: implement multi-attribute indexes.
+                    return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
+                }
+
+                let keys = Some(value)
+                    .filter(|value| index.indexes_value(value))
+                    .map(|value| index.index_keys(value.clone(), tenant))
+                    .unwrap_or_default();
+                let old_keys = old
+                    .get(attr_name)
+                    .filter(|old| index.indexes_value(old))
+                    .map(|old| index.index_keys(old.clone(), old_tenant))
+                    .unwrap_or_default();
+                Self::build_index_change_ops(index, attr.local_id, keys, old_keys, &mut ops);
+            }
+        }
+
+        for attr_name in removed {
+            let value = match old.get(attr_name) {
+                Some(value) => value,
+                None => continue,
+            };
+            let attr = self.require_attr_by_name(attr_name)?;
+            for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
+                if index.schema.attributes.len() > 1 {
+                    // FIXME: implement multi-attribute indexes.
+                    return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
+                }
+                if !index.indexes_value(value) {
+                    continue;
+                }
+                for key in index.index_keys(value.clone(), old_tenant) {
+                    ops.push(TupleIndexOp::Remove(TupleIndexRemove {
+                        index: index.local_id,
+                        value: key,
+                    }));
+                }
+            }
+        }
+
+        if self.indexes.iter().any(|index| index.is_partial()) {
+            let mut merged = old.clone();
+            for (attr_name, value) in data.iter() {
+                merged.insert(attr_name.clone(), value.clone());
+            }
+            for attr_name in removed {
+                merged.remove(attr_name);
+            }
+            self.build_partial_index_ops(&merged, old, &mut ops)?;
+        }
+
         Ok(ops)
     }
 
@@ -675,36 +1514,203 @@ impl Registry {
         attrs: &DataMap,
     ) -> Result<Vec<TupleIndexRemove>, anyhow::Error> {
         let mut ops = Vec::new();
+        let tenant = attrs.get(AttrTenant::QUALIFIED_NAME);
 
         for (attr_name, value) in attrs.iter() {
             let attr = self.attr_by_name(attr_name).unwrap();
             for index in self.indexes.attribute_indexes(attr.local_id) {
+                // Partial indexes depend on the whole entity.
+                if index.is_partial() {
+                    continue;
+                }
                 if index.schema.attributes.len() > 1 {
                     return Err(anyhow!("Multi-attribute indexes are not implemented yet!"));
                 }
-                ops.push(TupleIndexRemove {
-                    index: index.local_id,
-                    value: value.clone(),
-                });
+                if !index.indexes_value(value) {
+                    continue;
+                }
+                for key in index.index_keys(value.clone(), tenant) {
+                    ops.push(TupleIndexRemove {
+                        index: index.local_id,
+                        value: key,
+                    });
+                }
             }
         }
 
+        for (index, _, key) in self.partial_index_keys(attrs)? {
+            ops.push(TupleIndexRemove {
+                index: index.local_id,
+                value: key,
+            });
+        }
+
         Ok(ops)
     }
 
+    /// Build the operation that moves an index entry from `old_key` to `key`.
+    ///
+    /// `None` means that the entity is not in the index.
+    fn build_index_change_op(
+        index: &RegisteredIndex,
+        attribute: LocalAttributeId,
+        key: Option<Value>,
+        old_key: Option<Value>,
+    ) -> Option<TupleIndexOp> {
+        match (key, old_key) {
+            (Some(key), Some(old_key)) if key == old_key => None,
+            (Some(key), Some(old_key)) => Some(TupleIndexOp::Replace(TupleIndexReplace {
+                index: index.local_id,
+                attribute,
+                value: key,
+                old_value: old_key,
+                unique: index.schema.unique,
+            })),
+            (Some(key), None) => Some(TupleIndexOp::Insert(TupleIndexInsert {
+                index: index.local_id,
+                attribute,
+                value: key,
+                unique: index.schema.unique,
+            })),
+            (None, Some(old_key)) => Some(TupleIndexOp::Remove(TupleIndexRemove {
+                index: index.local_id,
+                value: old_key,
+            })),
+            (None, None) => None,
+        }
+    }
+
+    /// Build the operations that move the index entries of an attribute from
+    /// `old_keys` to `keys`.
+    ///
+    /// Keys in both lists keep their entry.
+    fn build_index_change_ops(
+        index: &RegisteredIndex,
+        attribute: LocalAttributeId,
+        mut keys: Vec<Value>,
+        mut old_keys: Vec<Value>,
+        ops: &mut Vec<TupleIndexOp>,
+    ) {
+        keys.retain(|key| match old_keys.iter().position(|old| old == key) {
+            Some(pos) => {
+                old_keys.remove(pos);
+                false
+            }
+            None => true,
+        });
+        if keys.len() <= 1 && old_keys.len() <= 1 {
+            ops.extend(Self::build_index_change_op(
+                index,
+                attribute,
+                keys.pop(),
+                old_keys.pop(),
+            ));
+            return;
+        }
+        ops.extend(old_keys.into_iter().map(|old_key| {
+            TupleIndexOp::Remove(TupleIndexRemove {
+                index: index.local_id,
+                value: old_key,
+            })
+        }));
+        ops.extend(keys.into_iter().map(|key| {
+            TupleIndexOp::Insert(TupleIndexInsert {
+                index: index.local_id,
+                attribute,
+                value: key,
+                unique: index.schema.unique,
+            })
+        }));
+    }
+
+    /// Index keys of an entity in all partial indexes that include it,
+    /// together with the indexed attribute.
+    fn partial_index_keys(
+        &self,
+        data: &DataMap,
+    ) -> Result<Vec<(&RegisteredIndex, LocalAttributeId, Value)>, anyhow::Error> {
+        let tenant = data.get(AttrTenant::QUALIFIED_NAME);
+        let mut keys = Vec::new();
+        for index in self.indexes.iter().filter(|index| index.is_partial()) {
+            let attr = match index.schema.attributes.as_slice() {
+                [attr] => self.require_attr_by_id(*attr)?,
+                _ => continue,
+            };
+            let value = match data.get(&attr.schema.ident) {
+                Some(value) if index.indexes_value(value) => value,
+                _ => continue,
+            };
+            if index.includes(data, self)? {
+                keys.push((index, attr.local_id, index.index_key(value.clone(), tenant)));
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Build the operations for partial indexes for an entity that changed
+    /// from `old` to `new`.
+    ///
+    /// Entities move in or out of a partial index when the filter result
+    /// changes, even if the indexed attribute stays the same.
+    fn build_partial_index_ops(
+        &self,
+        new: &DataMap,
+        old: &DataMap,
+        ops: &mut Vec<TupleIndexOp>,
+    ) -> Result<(), anyhow::Error> {
+        let mut old_keys = self.partial_index_keys(old)?;
+        for (index, attribute, key) in self.partial_index_keys(new)? {
+            let old_key = old_keys
+                .iter()
+                .position(|(old_index, _, _)| old_index.local_id == index.local_id)
+                .map(|pos| old_keys.remove(pos).2);
+            ops.extend(Self::build_index_change_op(
+                index,
+                attribute,
+                Some(key),
+                old_key,
+            ));
+        }
+        for (index, attribute, old_key) in old_keys {
+            ops.extend(Self::build_index_change_op(
+                index,
+                attribute,
+                None,
+                Some(old_key),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Use the given id, or generate a new one with the
+    /// [`schema::IdStrategy`] of the entity's class if it is nil.
+    fn entity_id_or_generate(&self, id: Id, data: &DataMap) -> Id {
+        if !id.is_nil() {
+            return id;
+        }
+        data.get_type()
+            .and_then(|ty| self.entity_by_ident(&ty))
+            .map(|class| class.schema.id_strategy)
+            .unwrap_or_default()
+            .generate()
+    }
+
     pub fn validate_create(
         &self,
-        create: query::mutate::Create,
+        mut create: query::mutate::Create,
+        resolve: &ResolveIdent,
     ) -> Result<Vec<DbOp>, anyhow::Error> {
-        let id = create.id.non_nil_or_randomize();
+        let id = self.entity_id_or_generate(create.id, &create.data);
+        self.resolve_data_idents(&mut create.data, resolve)?;
 
         let mut ops = Vec::new();
         let mut data = self.validate_attributes(create.data, &mut ops)?;
         data.insert(AttrId::QUALIFIED_NAME.into(), id.into());
+        self.build_acyclic_ops(id, &data, &mut ops);
 
         let index_ops = self.build_index_ops_create(&data)?;
         ops.push(DbOp::Tuple(TupleOp::new(
-            create.id,
+            id,
             TupleCreate { data, index_ops },
         )));
 
@@ -713,23 +1719,29 @@ impl Registry {
 
     pub fn validate_replace(
         &self,
-        replace: query::mutate::Replace,
+        mut replace: query::mutate::Replace,
         old_opt: Option<DataMap>,
+        resolve: &ResolveIdent,
     ) -> Result<Vec<DbOp>, anyhow::Error> {
         let old = if let Some(old) = old_opt {
             old
         } else {
-            return self.validate_create(query::mutate::Create {
-                id: replace.id,
-                data: replace.data,
-            });
+            return self.validate_create(
+                query::mutate::Create {
+                    id: replace.id,
+                    data: replace.data,
+                },
+                resolve,
+            );
         };
 
-        let id = replace.id.non_nil_or_randomize();
+        let id = self.entity_id_or_generate(replace.id, &replace.data);
+        self.resolve_data_idents(&mut replace.data, resolve)?;
 
         let mut ops = Vec::new();
         let mut data = self.validate_attributes(replace.data, &mut ops)?;
         data.insert(AttrId::QUALIFIED_NAME.into(), id.into());
+        self.build_acyclic_ops(id, &data, &mut ops);
 
         let index_ops = self.build_index_ops_update(&data, &old)?;
 
@@ -747,12 +1759,15 @@ impl Registry {
         &self,
         epatch: query::mutate::EntityPatch,
         current_entity: DataMap,
+        resolve: &ResolveIdent,
     ) -> Result<Vec<DbOp>, anyhow::Error> {
         debug_assert_eq!(Some(epatch.id), current_entity.get_id());
 
-        let new_entity = epatch.patch.apply_map(current_entity.clone())?;
+        let mut new_entity = epatch.patch.apply_map(current_entity.clone())?;
+        self.resolve_data_idents(&mut new_entity, resolve)?;
         let mut ops = Vec::new();
         let data = self.validate_attributes(new_entity, &mut ops)?;
+        self.build_acyclic_ops(epatch.id, &data, &mut ops);
 
         let index_ops = self.build_index_ops_update(&data, &current_entity)?;
 
@@ -766,24 +1781,56 @@ impl Registry {
         Ok(ops)
     }
 
+    /// Validate a merge into the stored entity `old`.
+    ///
+    /// Only the merged attributes are validated and included in the tuple
+    /// data, unless the merge changes the entity type or tenant.
     pub fn validate_merge(
         &self,
-        merge: query::mutate::Merge,
-        old: DataMap,
+        mut merge: query::mutate::Merge,
+        old: &DataMap,
+        resolve: &ResolveIdent,
     ) -> Result<Vec<DbOp>, anyhow::Error> {
-        let id = merge.id.non_nil_or_randomize();
-
-        // TODO: Avoid clone
-        // The old data is cloned below to allow for build_index_ops below.
-        // There is a more performant way to do this...
-        let mut values = old.clone();
-        // FIXME: can't use extend here, have to respect list patching etc.
-        values.0.extend(merge.data.0.into_iter());
+        let id = self.entity_id_or_generate(merge.id, &merge.data);
+        self.resolve_data_idents(&mut merge.data, resolve)?;
+
+        // Nil values remove the attribute.
+        let removed = merge
+            .data
+            .iter()
+            .filter(|(_, value)| value.is_nil())
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        // A new type requires validating the stored attributes again, and a
+        // new tenant changes all index keys.
+        let type_changed = match (merge.data.get_type(), old.get_type()) {
+            (Some(new), old) => Some(new) != old,
+            (None, _) => false,
+        };
+        let tenant_changed = match merge.data.get(AttrTenant::QUALIFIED_NAME) {
+            Some(new) => Some(new) != old.get(AttrTenant::QUALIFIED_NAME),
+            None => false,
+        };
+        let full_update = type_changed || tenant_changed;
+
         let mut ops = Vec::new();
-        let mut data = self.validate_attributes(values, &mut ops)?;
+        let mut data = if full_update {
+            let mut values = old.clone();
+            // FIXME: can't use extend here, have to respect list patching etc.
+            values.0.extend(merge.data.0);
+            self.validate_attributes(values, &mut ops)?
+        } else {
+            self.validate_attributes_merged(merge.data, Some(old), &mut ops)?
+        };
         data.insert(AttrId::QUALIFIED_NAME.into(), id.into());
+        self.build_acyclic_ops(id, &data, &mut ops);
 
-        let index_ops = self.build_index_ops_update(&data, &old)?;
+        let index_ops = if full_update {
+            self.build_index_ops_update(&data, old)?
+        } else {
+            self.build_index_ops_merge(&data, &removed, old)?
+        };
         ops.push(DbOp::Tuple(TupleOp::new(
             id,
             TupleMerge { data, index_ops },
@@ -801,6 +1848,19 @@ impl Registry {
         Ok(ops)
     }
 
+    /// Validate already stored entity data against the current schema.
+    ///
+    /// Returns the reference checks that must be verified against the
+    /// stored entities.
+    pub fn validate_entity(&self, data: DataMap) -> Result<Vec<DbOp>, anyhow::Error> {
+        let mut ops = Vec::new();
+        let data = self.validate_attributes(data, &mut ops)?;
+        if let Some(id) = data.get_id() {
+            self.build_acyclic_ops(id, &data, &mut ops);
+        }
+        Ok(ops)
+    }
+
     pub(crate) fn validate_entity_type_constraint(
         &self,
         entity_id: Id,
@@ -823,8 +1883,7 @@ impl Registry {
 
         Err(ReferenceConstraintViolation {
             entity: entity_id,
-            // TODO: add attribute!
-            attribute: "?".to_string(),
+            attribute: self.attr(val.attribute).schema.ident.clone(),
             expected_type,
             actual_type,
         })
@@ -837,4 +1896,14 @@ impl Default for Registry {
     }
 }
 
-pub type SharedRegistry = Arc<RwLock<Registry>>;
+/// A registry shared between the engine and backends.
+///
+/// Readers load an immutable snapshot and never block.
+/// Schema changes are applied to a copy, which replaces the current snapshot
+/// with an atomic pointer swap once the change is committed.
+pub type SharedRegistry = Arc<ArcSwap<Registry>>;
+
+/// Resolves the ident of a stored entity to its id.
+///
+/// See [`Registry::resolve_data_idents`].
+pub type ResolveIdent<'a> = dyn Fn(&str) -> Result<Option<Id>, anyhow::Error> + 'a;
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use factor_core::data::Ident;
+
+/// The kinds of schema items that are subject to a [`NamingPolicy`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum SchemaItemKind {
+    Attribute,
+    Class,
+    Index,
+    Policy,
+    View,
+    Rule,
+    Mixin,
+}
+
+impl std::fmt::Display for SchemaItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Attribute => "attribute",
+            Self::Class => "class",
+            Self::Index => "index",
+            Self::Policy => "policy",
+            Self::View => "view",
+            Self::Rule => "rule",
+            Self::Mixin => "mixin",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Naming rules for schema items created by migrations.
+///
+/// Allows enforcing organization conventions, like a maximum ident length
+/// or a namespace prefix for all classes.
+///
+/// The policy can only tighten the builtin rules: idents may only contain
+/// `[a-zA-Z0-9._]`, and attribute idents are limited to 50 characters.
+/// The default policy adds no rules.
+/// Only new items are checked, so existing items and internal migrations
+/// are not affected when the policy changes.
+#[derive(Clone, Default, Debug)]
+pub struct NamingPolicy {
+    max_len: Option<usize>,
+    allowed_chars: Option<String>,
+    namespace_prefixes: BTreeMap<SchemaItemKind, String>,
+}
+
+impl NamingPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum length of idents, including the namespace.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Set the characters allowed in namespaces and names.
+    pub fn with_allowed_chars(mut self, chars: impl Into<String>) -> Self {
+        self.allowed_chars = Some(chars.into());
+        self
+    }
+
+    /// Require the namespace of all items of the given kind to start with
+    /// `prefix`.
+    pub fn with_namespace_prefix(
+        mut self,
+        kind: SchemaItemKind,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.namespace_prefixes.insert(kind, prefix.into());
+        self
+    }
+
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    pub fn allowed_chars(&self) -> Option<&str> {
+        self.allowed_chars.as_deref()
+    }
+
+    pub fn namespace_prefix(&self, kind: SchemaItemKind) -> Option<&str> {
+        self.namespace_prefixes.get(&kind).map(|p| p.as_str())
+    }
+
+    /// Validate the ident of a new schema item.
+    pub fn validate(&self, kind: SchemaItemKind, ident: &str) -> Result<(), anyhow::Error> {
+        let (namespace, name) = Ident::parse_parts(ident)?;
+
+        if let Some(max_len) = self.max_len {
+            if ident.len() > max_len {
+                bail!(
+                    "Invalid {} ident '{}': exceeds the maximum length of {}",
+                    kind,
+                    ident,
+                    max_len
+                );
+            }
+        }
+        if let Some(allowed) = &self.allowed_chars {
+            if let Some(c) = namespace
+                .chars()
+                .chain(name.chars())
+                .find(|c| !allowed.contains(*c))
+            {
+                bail!(
+                    "Invalid {} ident '{}': character '{}' is not allowed (allowed: {})",
+                    kind,
+                    ident,
+                    c,
+                    allowed
+                );
+            }
+        }
+        if let Some(prefix) = self.namespace_prefix(kind) {
+            if !namespace.starts_with(prefix) {
+                bail!(
+                    "Invalid {} ident '{}': the namespace must start with '{}'",
+                    kind,
+                    ident,
+                    prefix
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::ValueType,
+        query::migrate::Migration,
+        schema::{Attribute, Class},
+    };
+
+    use crate::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    #[test]
+    fn test_naming_policy_validate() {
+        let policy = NamingPolicy::default();
+        assert!(policy.validate(SchemaItemKind::Class, "acme/Post").is_ok());
+        assert!(policy.validate(SchemaItemKind::Class, "Post").is_err());
+
+        let policy = NamingPolicy::new()
+            .with_max_len(12)
+            .with_allowed_chars("abcdefghijklmnopqrstuvwxyz.")
+            .with_namespace_prefix(SchemaItemKind::Class, "acme");
+
+        assert!(policy.validate(SchemaItemKind::Class, "acme/post").is_ok());
+        // Too long.
+        assert!(policy
+            .validate(SchemaItemKind::Class, "acme.blog/post")
+            .is_err());
+        assert!(policy.validate(SchemaItemKind::Class, "acme/Post").is_err());
+        assert!(policy
+            .validate(SchemaItemKind::Class, "other/post")
+            .is_err());
+        // The prefix only applies to classes.
+        assert!(policy
+            .validate(SchemaItemKind::Attribute, "other/post")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_engine_naming_policy() {
+        let db = Engine::new(MemoryDb::new())
+            .with_naming_policy(
+                NamingPolicy::new().with_namespace_prefix(SchemaItemKind::Class, "acme"),
+            )
+            .into_client();
+
+        db.migrate(Migration::new().entity_create(Class::new("acme.blog/Post")))
+            .await
+            .unwrap();
+        db.migrate(Migration::new().entity_create(Class::new("blog/Post")))
+            .await
+            .unwrap_err();
+        db.migrate(Migration::new().attr_create(Attribute::new("blog/title", ValueType::String)))
+            .await
+            .unwrap();
+
+        // The policy survives a reset.
+        db.purge_all_data().await.unwrap();
+        db.migrate(Migration::new().entity_create(Class::new("blog/Post")))
+            .await
+            .unwrap_err();
+    }
+}
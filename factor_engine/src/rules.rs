@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+use factor_core::{
+    data::{DataMap, Id, IdOrIdent, Value},
+    query::{
+        expr::Expr,
+        mutate::{Batch, Mutate},
+    },
+    schema::{AttrMapExt, RuleAction, RuleEvent, RuleSchema},
+};
+
+use crate::{backend::Backend, id_generator::IdGenerator, policy, registry::Registry};
+
+const ENTITY_VARIABLE: &str = "entity";
+
+/// A change of an entity that triggers a rule.
+struct Trigger {
+    rule: RuleSchema,
+    entity: Id,
+    data: DataMap,
+}
+
+/// Determine the rules triggered by the mutations of a batch.
+///
+/// Returns the triggers and the state of all entities touched by the batch
+/// after it is applied.
+async fn collect_triggers(
+    backend: &(dyn Backend + Send + Sync),
+    reg: &Registry,
+    batch: &Batch,
+) -> Result<(Vec<Trigger>, HashMap<Id, Option<DataMap>>), anyhow::Error> {
+    let ids = batch
+        .actions
+        .iter()
+        .filter_map(|action| match action {
+            Mutate::Create(_) | Mutate::Select(_) => None,
+            other => other.entity_id().map(IdOrIdent::from),
+        })
+        .collect::<Vec<_>>();
+    let mut state: HashMap<Id, Option<DataMap>> = if ids.is_empty() {
+        HashMap::new()
+    } else {
+        backend
+            .entities(ids)
+            .await?
+            .into_iter()
+            .filter_map(|(id, data)| id.as_id().map(|id| (id, data)))
+            .collect()
+    };
+
+    let mut triggers = Vec::new();
+    for action in &batch.actions {
+        let current = |id: Id| state.get(&id).cloned().flatten();
+        let (id, event, data) = match action {
+            Mutate::Create(create) => (create.id, RuleEvent::Create, create.data.clone()),
+            Mutate::Replace(replace) => (replace.id, RuleEvent::Update, replace.data.clone()),
+            Mutate::Merge(merge) => {
+                let (event, mut data) = match current(merge.id) {
+                    Some(data) => (RuleEvent::Update, data),
+                    None => (RuleEvent::Create, DataMap::new()),
+                };
+                data.extend(merge.data.clone());
+                (merge.id, event, data)
+            }
+            Mutate::Patch(patch) => {
+                let data = patch
+                    .patch
+                    .clone()
+                    .apply_map(current(patch.id).unwrap_or_default())?;
+                (patch.id, RuleEvent::Update, data)
+            }
+            Mutate::Delete(delete) => {
+                state.insert(delete.id, None);
+                continue;
+            }
+            Mutate::Select(_) | Mutate::Savepoint | Mutate::Guard(_) => continue,
+        };
+
+        if let Some(class) = data.get_type().and_then(|ty| reg.entity_by_ident(&ty)) {
+            triggers.extend(
+                reg.rules_for_class(class.schema.id, event)
+                    .map(|rule| Trigger {
+                        rule: rule.clone(),
+                        entity: id,
+                        data: data.clone(),
+                    }),
+            );
+        }
+        state.insert(id, Some(data));
+    }
+    Ok((triggers, state))
+}
+
+/// Evaluate the attribute values of a rule action against the current data
+/// of the written entity.
+///
+/// Attributes that evaluate to unit are skipped.
+fn eval_data(
+    data: &BTreeMap<String, Expr>,
+    variables: &HashMap<String, Value>,
+    target: &DataMap,
+    reg: &Registry,
+) -> Result<DataMap, anyhow::Error> {
+    let mut out = DataMap::new();
+    for (attr, expr) in data {
+        let expr = expr.clone().bind_variables(variables)?;
+        let value = policy::eval_expr(&expr, target, reg)?;
+        if value != Value::Unit {
+            out.insert(attr.clone(), value);
+        }
+    }
+    Ok(out)
+}
+
+/// Append the actions of all rules triggered by a batch to the batch.
+///
+/// See [`RuleSchema`].
+pub(crate) async fn apply_rules(
+    backend: &(dyn Backend + Send + Sync),
+    id_generator: &dyn IdGenerator,
+    batch: &mut Batch,
+) -> Result<(), anyhow::Error> {
+    let reg = backend.registry().load_full();
+    if reg.iter_rules().next().is_none() {
+        return Ok(());
+    }
+
+    let (triggers, mut state) = collect_triggers(backend, &reg, batch).await?;
+    for trigger in triggers {
+        let mut variables = trigger
+            .data
+            .0
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        variables.insert(ENTITY_VARIABLE.to_string(), Value::Id(trigger.entity));
+
+        for action in &trigger.rule.actions {
+            let mutation = match action {
+                RuleAction::Create { data } => {
+                    let data = eval_data(data, &variables, &DataMap::new(), &reg)?;
+                    let class = data
+                        .get_type()
+                        .and_then(|ty| reg.entity_by_ident(&ty))
+                        .map(|class| &class.schema);
+                    let id = id_generator.generate(class);
+                    state.insert(id, Some(data.clone()));
+                    Mutate::create(id, data)
+                }
+                RuleAction::Merge { entity, data } => {
+                    let expr = entity.clone().bind_variables(&variables)?;
+                    let id = policy::eval_expr(&expr, &trigger.data, &reg)?
+                        .as_id()
+                        .with_context(|| {
+                            format!(
+                                "Rule '{}' did not produce a valid entity id",
+                                trigger.rule.ident
+                            )
+                        })?;
+                    if !state.contains_key(&id) {
+                        let current = backend.entity(id.into()).await?;
+                        state.insert(id, current);
+                    }
+                    let current = state.get(&id).cloned().flatten().unwrap_or_default();
+                    let data = eval_data(data, &variables, &current, &reg)?;
+
+                    let mut merged = current;
+                    merged.extend(data.clone());
+                    state.insert(id, Some(merged));
+                    Mutate::merge(id, data)
+                }
+            };
+            batch.actions.push(mutation);
+        }
+    }
+    Ok(())
+}
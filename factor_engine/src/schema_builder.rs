@@ -4,7 +4,7 @@ use anyhow::{anyhow, bail};
 
 use crate::{
     backend::{DbOp, IndexPopulate, SelectOp, TupleDelete, TuplePatch, TupleRemoveAttrs},
-    registry::Registry,
+    registry::{Registry, SchemaItemKind},
 };
 
 use factor_core::{
@@ -84,6 +84,10 @@ fn build_attribute_index(attr: &Attribute) -> IndexSchema {
         attributes: vec![attr.id],
         description: None,
         unique: attr.unique,
+        tenant_scoped: false,
+        sparse: false,
+        filter: None,
+        multi_valued: false,
     }
 }
 
@@ -416,6 +420,7 @@ fn build_entity_attribute_add(
     entity.schema.attributes.push(ClassAttribute {
         attribute: attr.schema.ident.into(),
         required: add.cardinality.is_required(),
+        constraints: Vec::new(),
     });
 
     let action = ResolvedAction {
@@ -536,6 +541,8 @@ fn build_entity_upsert(
         );
     }
     schema.id = old.schema.id;
+    // Registered classes contain the attributes of their mixins.
+    reg.expand_mixins(&mut schema)?;
 
     if schema == old.schema {
         // Entity has not changed, nothing to do.
@@ -554,6 +561,9 @@ fn build_entity_upsert(
     if old.schema.strict != schema.strict {
         bail!("Entity upsert with changed strict setting is not supported");
     }
+    if old.schema.is_abstract != schema.is_abstract {
+        bail!("Entity upsert with changed abstract setting is not supported");
+    }
 
     let mut merge = to_value_map(&old.schema)?;
 
@@ -628,6 +638,20 @@ fn build_entity_delete(
     _is_internal: bool,
 ) -> Result<Vec<ResolvedAction>, anyhow::Error> {
     let schema = reg.require_entity_by_name(&del.name)?;
+    if let Some(policy) = reg.iter_policies().find(|p| p.class == del.name) {
+        return Err(anyhow!(
+            "Can't delete class '{}': still in use by policy '{}'",
+            del.name,
+            policy.ident
+        ));
+    }
+    if let Some(rule) = reg.iter_rules().find(|r| r.class == del.name) {
+        return Err(anyhow!(
+            "Can't delete class '{}': still in use by rule '{}'",
+            del.name,
+            rule.ident
+        ));
+    }
 
     let ops = if del.delete_all {
         vec![DbOp::Select(SelectOp::new(
@@ -676,6 +700,107 @@ fn build_index_delete(
     Ok(vec![action])
 }
 
+fn build_policy_create(
+    reg: &mut Registry,
+    mut create: migrate::PolicyCreate,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    create.schema.id = reg.register_policy(create.schema.clone())?;
+    let action = ResolvedAction::new(SchemaAction::PolicyCreate(create));
+    Ok(vec![action])
+}
+
+fn build_policy_delete(
+    reg: &mut Registry,
+    del: migrate::PolicyDelete,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    reg.remove_policy(&del.name)?;
+    let action = ResolvedAction::new(SchemaAction::PolicyDelete(del));
+    Ok(vec![action])
+}
+
+fn build_view_create(
+    reg: &mut Registry,
+    mut create: migrate::ViewCreate,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    create.schema.id = reg.register_view(create.schema.clone())?;
+    let action = ResolvedAction::new(SchemaAction::ViewCreate(create));
+    Ok(vec![action])
+}
+
+fn build_view_delete(
+    reg: &mut Registry,
+    del: migrate::ViewDelete,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    reg.remove_view(&del.name)?;
+    let action = ResolvedAction::new(SchemaAction::ViewDelete(del));
+    Ok(vec![action])
+}
+
+fn build_rule_create(
+    reg: &mut Registry,
+    mut create: migrate::RuleCreate,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    create.schema.id = reg.register_rule(create.schema.clone())?;
+    let action = ResolvedAction::new(SchemaAction::RuleCreate(create));
+    Ok(vec![action])
+}
+
+fn build_rule_delete(
+    reg: &mut Registry,
+    del: migrate::RuleDelete,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    reg.remove_rule(&del.name)?;
+    let action = ResolvedAction::new(SchemaAction::RuleDelete(del));
+    Ok(vec![action])
+}
+
+fn build_mixin_create(
+    reg: &mut Registry,
+    mut create: migrate::MixinCreate,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    create.schema.id = reg.register_mixin(create.schema.clone())?;
+    let action = ResolvedAction::new(SchemaAction::MixinCreate(create));
+    Ok(vec![action])
+}
+
+fn build_mixin_delete(
+    reg: &mut Registry,
+    del: migrate::MixinDelete,
+) -> Result<Vec<ResolvedAction>, anyhow::Error> {
+    reg.remove_mixin(&del.name)?;
+    let action = ResolvedAction::new(SchemaAction::MixinDelete(del));
+    Ok(vec![action])
+}
+
+/// Check the idents of new schema items against the naming policy of the
+/// registry.
+///
+/// Upserts of existing items are not checked, so items that predate the
+/// policy can still be updated.
+fn validate_naming(reg: &Registry, action: &SchemaAction) -> Result<(), anyhow::Error> {
+    let (kind, ident) = match action {
+        SchemaAction::AttributeCreate(create) => (SchemaItemKind::Attribute, &create.schema.ident),
+        SchemaAction::AttributeUpsert(upsert)
+            if reg.attr_by_name(&upsert.schema.ident).is_none() =>
+        {
+            (SchemaItemKind::Attribute, &upsert.schema.ident)
+        }
+        SchemaAction::EntityCreate(create) => (SchemaItemKind::Class, &create.schema.ident),
+        SchemaAction::EntityUpsert(upsert)
+            if reg.entity_by_name(&upsert.schema.ident).is_none() =>
+        {
+            (SchemaItemKind::Class, &upsert.schema.ident)
+        }
+        SchemaAction::IndexCreate(create) => (SchemaItemKind::Index, &create.schema.ident),
+        SchemaAction::PolicyCreate(create) => (SchemaItemKind::Policy, &create.schema.ident),
+        SchemaAction::ViewCreate(create) => (SchemaItemKind::View, &create.schema.ident),
+        SchemaAction::RuleCreate(create) => (SchemaItemKind::Rule, &create.schema.ident),
+        SchemaAction::MixinCreate(create) => (SchemaItemKind::Mixin, &create.schema.ident),
+        _ => return Ok(()),
+    };
+    reg.naming_policy().validate(kind, ident)
+}
+
 fn build_action(
     reg: &mut Registry,
     action: SchemaAction,
@@ -699,8 +824,28 @@ fn build_action(
         }
         SchemaAction::EntityUpsert(upsert) => build_entity_upsert(reg, upsert, is_internal),
         SchemaAction::EntityDelete(del) => build_entity_delete(reg, del, is_internal),
-        SchemaAction::IndexCreate(create) => build_index_create(reg, create),
+        SchemaAction::IndexCreate(create) => {
+            let mut actions = build_index_create(reg, create)?;
+            // Standalone indexes can be created for attributes that already
+            // have data.
+            for action in &mut actions {
+                if let SchemaAction::IndexCreate(create) = &action.action {
+                    action.ops.push(DbOp::IndexPopulate(IndexPopulate {
+                        index_id: create.schema.id,
+                    }));
+                }
+            }
+            Ok(actions)
+        }
         SchemaAction::IndexDelete(del) => build_index_delete(reg, del),
+        SchemaAction::PolicyCreate(create) => build_policy_create(reg, create),
+        SchemaAction::PolicyDelete(del) => build_policy_delete(reg, del),
+        SchemaAction::ViewCreate(create) => build_view_create(reg, create),
+        SchemaAction::ViewDelete(del) => build_view_delete(reg, del),
+        SchemaAction::RuleCreate(create) => build_rule_create(reg, create),
+        SchemaAction::RuleDelete(del) => build_rule_delete(reg, del),
+        SchemaAction::MixinCreate(create) => build_mixin_create(reg, create),
+        SchemaAction::MixinDelete(del) => build_mixin_delete(reg, del),
     }
 }
 
@@ -709,7 +854,7 @@ fn build_action(
 /// NOTE: is_internal must be set to false for regular migrations, and to true
 /// for internal migrations driven by the factor db.
 /// With is_internal = false, any changes to builtin entities/attributes are
-/// rejected.
+/// rejected, and new items must follow the naming policy of the registry.
 pub fn build_migration(
     reg: &mut Registry,
     mut mig: Migration,
@@ -719,6 +864,9 @@ pub fn build_migration(
     let mut ops = Vec::new();
 
     for action in mig.actions {
+        if !is_internal {
+            validate_naming(reg, &action)?;
+        }
         let resolved = build_action(reg, action, is_internal)?;
         for sub_action in resolved {
             actions.push(sub_action.action);
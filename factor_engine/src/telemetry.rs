@@ -0,0 +1,98 @@
+//! Tracing instrumentation.
+//!
+//! All [`crate::Engine`] operations run in an `info` level span named
+//! `factor`, with the following fields:
+//! * `otel.name`: the operation, eg `select` or `batch`
+//! * `fingerprint`: the [`Select::fingerprint`] of queries
+//! * `rows`: number of returned entities, or number of mutations for batches
+//! * `duration_ms`: duration of the operation
+//!
+//! Enable the `otel` feature to export spans to OpenTelemetry with
+//! [`otel_layer`].
+
+use std::future::Future;
+
+use factor_core::query::select::Select;
+use tracing::{field::Empty, Instrument, Span};
+
+pub(crate) fn span(operation: &'static str) -> Span {
+    tracing::info_span!(
+        "factor",
+        otel.name = operation,
+        fingerprint = Empty,
+        rows = Empty,
+        duration_ms = Empty,
+    )
+}
+
+pub(crate) fn select_span(operation: &'static str, query: &Select) -> Span {
+    let span = span(operation);
+    if !span.is_disabled() {
+        span.record("fingerprint", query.fingerprint().as_str());
+    }
+    span
+}
+
+/// Run an operation in the span and record the row count and duration.
+pub(crate) async fn traced<T, F>(
+    span: Span,
+    rows: impl FnOnce(&T) -> usize,
+    operation: F,
+) -> Result<T, anyhow::Error>
+where
+    F: Future<Output = Result<T, anyhow::Error>>,
+{
+    let start = instant::Instant::now();
+    let res = operation.instrument(span.clone()).await;
+    let duration_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    span.record("duration_ms", duration_ms);
+    match &res {
+        Ok(value) => {
+            span.record("rows", rows(value));
+        }
+        Err(err) => {
+            tracing::debug!(parent: &span, error = %err, "operation failed");
+        }
+    }
+    res
+}
+
+/// Build a [`tracing_subscriber`] layer that exports spans with the given
+/// OpenTelemetry tracer.
+///
+/// ```ignore
+/// let tracer = opentelemetry_jaeger::new_agent_pipeline().install_simple()?;
+/// tracing_subscriber::registry()
+///     .with(factor_engine::telemetry::otel_layer(tracer))
+///     .init();
+/// ```
+#[cfg(feature = "otel")]
+pub fn otel_layer<S, T>(tracer: T) -> tracing_opentelemetry::OpenTelemetryLayer<S, T>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    T: opentelemetry::trace::Tracer + tracing_opentelemetry::PreSampledTracer + 'static,
+{
+    tracing_opentelemetry::layer().with_tracer(tracer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_traced() {
+        let value = traced(span("test"), |v: &Vec<u8>| v.len(), async {
+            Ok(vec![1, 2])
+        })
+        .await
+        .unwrap();
+        assert_eq!(value, vec![1, 2]);
+
+        let err = traced(span("test"), |_: &()| 0, async {
+            Err(anyhow::anyhow!("failed"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "failed");
+    }
+}
@@ -5,26 +5,32 @@ use crate::{backend::Backend, Engine};
 
 use factor_core::{
     data::{
-        patch::Patch, value::ValueCoercionError, value_type::ConstrainedRefType, Id, IdOrIdent,
-        Value, ValueType,
+        patch::Patch,
+        value::ValueCoercionError,
+        value_type::{ConstrainedRefType, ObjectField, ObjectType},
+        GeoPoint, HlcTimestamp, Id, IdOrIdent, TimeUnit, Timestamp, Value, ValueMap, ValueType,
+    },
+    db::{Db, ReadOnlyClient, TenantClient},
+    error::{
+        EntityNotFound, Error, QuotaExceeded, ReadOnly, ReferenceConstraintViolation,
+        ReferenceCycle, UniqueConstraintViolation, ValueConstraintViolation,
     },
-    db::Db,
-    error::{EntityNotFound, ReferenceConstraintViolation, UniqueConstraintViolation},
     map,
     query::{
         self,
         expr::Expr,
+        graph::{GraphDirection, GraphQuery},
         migrate::{
-            AttributeCreateIndex, EntityAttributeAdd, EntityAttributeChangeCardinality, Migration,
-            SchemaAction,
+            AttributeCreateIndex, EntityAttributeAdd, EntityAttributeChangeCardinality,
+            EntityAttributeRemove, IndexCreate, Migration, SchemaAction,
         },
-        mutate::Batch,
+        mutate::{ActionStatus, Batch, Create, Guard, Merge},
         select::{Order, Select},
     },
     schema::{
         self,
-        builtin::{AttrId, AttrTitle},
-        AttrMapExt, AttributeMeta, Class, ClassAttribute,
+        builtin::{AttrClocks, AttrId, AttrTenant, AttrTitle},
+        AttrMapExt, AttributeMeta, Class, ClassAttribute, ValueConstraint,
     },
 };
 
@@ -81,6 +87,7 @@ async fn test_db_with_test_schema(db: &Db) {
             test_query_regex,
             test_attr_corcions,
             test_merge_list_attr,
+            test_merge_updates_indexes,
             test_patch,
             test_patch_replace_skip_existing,
             test_query_contains_with_two_lists,
@@ -90,7 +97,33 @@ async fn test_db_with_test_schema(db: &Db) {
             test_sort_simple,
             test_query_entity_select_ident,
             test_query_entity_is_type_nested,
+            test_query_type_in_namespace,
             test_entity_delete_not_found,
+            test_entity_aliases,
+            test_get_many,
+            test_batch_with_savepoints,
+            test_read_only,
+            test_with_tenant,
+            test_guard,
+            test_tenant_scoped_unique_index,
+            test_geo_index_within_radius,
+            test_partial_index,
+            test_sparse_index,
+            test_materialized_view,
+            test_select_cached,
+            test_json_attr_path,
+            test_select_computed_expressions,
+            test_select_date_functions,
+            test_attribute_collation_case_insensitive,
+            test_class_id_strategy,
+            test_class_quota,
+            test_class_computed_attributes,
+            test_rule_bumps_counter,
+            test_class_mixins,
+            test_abstract_classes,
+            test_reference_acyclic,
+            test_shortest_path,
+            test_merge_with_clock,
             test_entity_attr_add_with_default,
             test_entity_attr_change_cardinality_from_required_to_optional,
             test_attribute_create_index,
@@ -102,8 +135,12 @@ async fn test_db_with_test_schema(db: &Db) {
             test_select_delete,
             test_aggregate_count,
             test_reference_validation,
+            test_reference_ident_resolution,
+            test_nested_object_list_validation,
+            test_typed_errors,
             test_reference_validation_constrained_type,
             test_attr_disallows_multiple_values,
+            test_class_attribute_value_constraints,
         ]
     );
 }
@@ -157,9 +194,15 @@ async fn apply_test_schema(db: &Db) {
             attributes: vec![ClassAttribute {
                 attribute: "test/int_list".into(),
                 required: false,
+                constraints: Vec::new(),
             }],
             extends: Vec::new(),
             strict: false,
+            id_strategy: schema::IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         })
         .entity_create(Class {
             id: Id::nil(),
@@ -169,9 +212,15 @@ async fn apply_test_schema(db: &Db) {
             attributes: vec![ClassAttribute {
                 attribute: "test/int_list".into(),
                 required: false,
+                constraints: Vec::new(),
             }],
             extends: Vec::new(),
             strict: false,
+            id_strategy: schema::IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         })
         .entity_create(Class {
             id: Id::nil(),
@@ -181,6 +230,11 @@ async fn apply_test_schema(db: &Db) {
             attributes: vec![],
             extends: vec![ENTITY_FILE.into()],
             strict: false,
+            id_strategy: schema::IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         })
         .entity_create(Class {
             id: Id::nil(),
@@ -190,12 +244,15 @@ async fn apply_test_schema(db: &Db) {
             attributes: vec![],
             extends: vec![ENTITY_IMAGE.into()],
             strict: false,
+            id_strategy: schema::IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         })
         .attr_create(Attribute::new(
             format!("{}/{}", NS_TEST, "ref_image"),
-            ValueType::RefConstrained(ConstrainedRefType {
-                allowed_entity_types: vec!["test/Image".into()],
-            }),
+            ValueType::RefConstrained(ConstrainedRefType::new(vec!["test/Image".into()])),
         ));
 
     db.migrate(mig).await.unwrap();
@@ -315,6 +372,7 @@ async fn test_attribute_create_index(db: &Db) {
         unique: false,
         index: false,
         strict: true,
+        collation: schema::Collation::Binary,
     }))
     .await
     .unwrap();
@@ -373,6 +431,7 @@ async fn test_attribute_create_unique_index_fails_with_duplicate_values(db: &Db)
         unique: false,
         index: false,
         strict: true,
+        collation: schema::Collation::Binary,
     }))
     .await
     .unwrap();
@@ -407,6 +466,7 @@ async fn test_attribute_create_unique_index_fails_with_duplicate_values(db: &Db)
         .expect_err("Expected migration to faild due to unique index constraints");
 
     assert!(err.is::<UniqueConstraintViolation>());
+    assert_eq!(err.attribute(), Some(attr_name.as_str()));
 
     let schema = db.schema().await.unwrap();
     let attr = schema
@@ -439,6 +499,7 @@ async fn test_attr_union_add_variant(db: &Db) {
         unique: false,
         index: false,
         strict: false,
+        collation: schema::Collation::Binary,
     }))
     .await
     .unwrap();
@@ -495,181 +556,1700 @@ async fn test_entity_delete_not_found(db: &Db) {
     assert!(err.is::<EntityNotFound>());
 }
 
-async fn test_entity_attr_add_with_default(db: &Db) {
-    let ty = "t/AddTest";
-    db.migrate(Migration::new().entity_create(Class {
-        id: Id::nil(),
-        ident: ty.to_string(),
-        title: None,
-        description: None,
-        attributes: vec![ClassAttribute {
-            attribute: AttrTitle::QUALIFIED_NAME.to_string(),
-            required: true,
-        }],
-        extends: vec![],
-        strict: false,
-    }))
+async fn test_entity_aliases(db: &Db) {
+    let id = Id::random();
+    db.create(
+        id,
+        map! {
+            "factor/ident": "test/alias_a",
+            "factor/aliases": vec!["test/alias_b", "test/alias_c"],
+        },
+    )
     .await
     .unwrap();
+    for name in ["test/alias_a", "test/alias_b", "test/alias_c"] {
+        assert_eq!(db.entity(name).await.unwrap().get_id(), Some(id));
+    }
 
-    let id_no_default = Id::random();
-    db.create(
-        id_no_default,
+    // Aliases are unique across entities.
+    let err = db
+        .create(
+            Id::random(),
+            map! { "factor/aliases": vec!["test/alias_x", "test/alias_c"] },
+        )
+        .await
+        .expect_err("Must fail");
+    assert!(err.is::<UniqueConstraintViolation>());
+
+    // Renaming keeps the old ident resolvable.
+    db.merge(
+        id,
         map! {
-            "factor/type": ty,
-            "factor/title": "hello",
+            "factor/ident": "test/alias_renamed",
+            "factor/aliases": vec!["test/alias_a", "test/alias_b"],
         },
     )
     .await
     .unwrap();
+    for name in ["test/alias_renamed", "test/alias_a", "test/alias_b"] {
+        assert_eq!(db.entity(name).await.unwrap().get_id(), Some(id));
+    }
+    let err = db.entity("test/alias_c").await.expect_err("Must fail");
+    assert!(err.is::<EntityNotFound>());
 
-    let id_with_default = Id::random();
+    db.delete(id).await.unwrap();
+    let err = db.entity("test/alias_a").await.expect_err("Must fail");
+    assert!(err.is::<EntityNotFound>());
+}
+
+async fn test_get_many(db: &Db) {
+    let id1 = Id::random();
+    let id2 = Id::random();
+    let missing = Id::random();
+    db.create(id1, map! {"factor/title": "a"}).await.unwrap();
     db.create(
-        id_with_default,
+        id2,
         map! {
-            "factor/type": ty,
-            "factor/title": "hello",
-            "test/int": 100,
+            "factor/title": "b",
+            "factor/ident": "get-many-ident",
         },
     )
     .await
     .unwrap();
 
-    // Migration should fail without a default value.
+    let ident = IdOrIdent::new_str("get-many-ident");
+    let entities = db
+        .get_many(vec![id1.into(), ident.clone(), missing.into()])
+        .await
+        .unwrap();
+
+    assert_eq!(entities.len(), 3);
+    assert_eq!(
+        entities[&IdOrIdent::from(id1)].as_ref().unwrap().get_id(),
+        Some(id1)
+    );
+    assert_eq!(entities[&ident].as_ref().unwrap().get_id(), Some(id2));
+    assert_eq!(entities[&IdOrIdent::from(missing)], None);
+}
+
+async fn test_batch_with_savepoints(db: &Db) {
+    let id1 = Id::random();
+    let id2 = Id::random();
+    let id3 = Id::random();
+    let id4 = Id::random();
+    let batch = Batch::new()
+        .and_create(Create {
+            id: id1,
+            data: map! {"factor/ident": "savepoint-a"},
+        })
+        .and_savepoint()
+        // Applied, but rolled back with the rest of the segment.
+        .and_create(Create {
+            id: id4,
+            data: map! {"factor/ident": "savepoint-b"},
+        })
+        // Fails because the ident is already taken.
+        .and_create(Create {
+            id: id2,
+            data: map! {"factor/ident": "savepoint-a"},
+        })
+        .and_savepoint()
+        .and_create(Create {
+            id: id3,
+            data: map! {"factor/ident": "savepoint-c"},
+        });
+    let report = db.batch_with_savepoints(batch).await.unwrap();
+
+    assert!(!report.is_complete());
+    assert_eq!(report.actions.len(), 6);
+    assert_eq!(report.actions[0], ActionStatus::Applied);
+    assert_eq!(report.actions[1], ActionStatus::Applied);
+    assert!(matches!(report.actions[2], ActionStatus::RolledBack { .. }));
+    assert!(matches!(report.actions[3], ActionStatus::RolledBack { .. }));
+    assert_eq!(report.actions[4], ActionStatus::Applied);
+    assert_eq!(report.actions[5], ActionStatus::Applied);
+
+    db.entity(id1).await.unwrap();
+    db.entity(id3).await.unwrap();
+    let err = db.entity(id2).await.expect_err("Must fail");
+    assert!(err.is::<EntityNotFound>());
+    let err = db.entity(id4).await.expect_err("Must fail");
+    assert!(err.is::<EntityNotFound>());
+
+    // Read-only clients reject the whole batch.
+    let id5 = Id::random();
+    let batch = Batch::new().and_savepoint().and_create(Create {
+        id: id5,
+        data: map! {"factor/ident": "savepoint-e"},
+    });
     let err = db
-        .migrate(
-            Migration::new().action(SchemaAction::EntityAttributeAdd(EntityAttributeAdd {
-                entity: ty.into(),
-                attribute: "test/int".into(),
-                cardinality: schema::Cardinality::Required,
-                default_value: None,
-            })),
-        )
+        .read_only()
+        .batch_with_savepoints(batch)
         .await
         .expect_err("Must fail");
-    assert!(err.to_string().contains("requires a default value"));
+    assert!(err.is::<ReadOnly>());
+    let err = db.entity(id5).await.expect_err("Must fail");
+    assert!(err.is::<EntityNotFound>());
+}
 
-    // Now supply a default value.
-    db.migrate(
-        Migration::new().action(SchemaAction::EntityAttributeAdd(EntityAttributeAdd {
-            entity: ty.into(),
-            attribute: "test/int".into(),
-            cardinality: schema::Cardinality::Required,
-            default_value: Some(42u64.into()),
-        })),
+async fn test_read_only(db: &Db) {
+    let id = Id::random();
+    db.create(id, map! {"factor/title": "a"}).await.unwrap();
+
+    let ro = db.read_only();
+    assert_eq!(ro.entity(id).await.unwrap().get_id(), Some(id));
+
+    let client = ro
+        .client()
+        .as_any()
+        .downcast_ref::<ReadOnlyClient>()
+        .unwrap();
+    assert!(client.inner().as_any().is::<Engine>());
+    assert!(ro.find_client::<Engine>().is_some());
+
+    let err = ro.delete(id).await.unwrap_err();
+    assert!(err.is::<ReadOnly>());
+    let err = ro.migrate(Migration::new()).await.unwrap_err();
+    assert!(err.is::<ReadOnly>());
+    let err = ro.purge_all_data().await.unwrap_err();
+    assert!(err.is::<ReadOnly>());
+
+    db.entity(id).await.unwrap();
+}
+
+async fn test_with_tenant(db: &Db) {
+    let tenant_a = Id::random();
+    let tenant_b = Id::random();
+    let a = db.with_tenant(tenant_a);
+    let b = db.with_tenant(tenant_b);
+
+    let id = Id::random();
+    a.create(id, map! {"factor/title": "tenant-a"})
+        .await
+        .unwrap();
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(
+        data.get(AttrTenant::QUALIFIED_NAME).and_then(|v| v.as_id()),
+        Some(tenant_a)
+    );
+
+    assert_eq!(a.entity(id).await.unwrap().get_id(), Some(id));
+    let err = b.entity(id).await.unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+
+    let client = a.client().as_any().downcast_ref::<TenantClient>().unwrap();
+    assert!(client.inner().as_any().is::<Engine>());
+
+    assert_eq!(a.select(Select::new()).await.unwrap().items.len(), 1);
+    assert!(b.select(Select::new()).await.unwrap().items.is_empty());
+
+    let err = b.delete(id).await.unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+    let err = b
+        .merge(id, map! {"factor/description": "b"})
+        .await
+        .unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+
+    // Entities can not be created for another tenant.
+    b.create(
+        Id::random(),
+        map! {"factor/title": "tenant-b", "factor/tenant": tenant_a},
+    )
+    .await
+    .unwrap_err();
+
+    // Guards of other tenants never match.
+    let err = b
+        .batch(Batch::new().and_guard(Guard {
+            id,
+            filter: Expr::literal(true),
+        }))
+        .await
+        .unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+
+    // Only the segment touching an entity of another tenant is rolled back,
+    // and the statuses line up with the original batch.
+    let id_b = Id::random();
+    let report = b
+        .batch_with_savepoints(
+            Batch::new()
+                .and_merge(Merge::new(id, map! {"factor/description": "b"}))
+                .and_savepoint()
+                .and_create(Create {
+                    id: id_b,
+                    data: map! {"factor/title": "tenant-b"},
+                }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.actions.len(), 3);
+    assert!(matches!(report.actions[0], ActionStatus::RolledBack { .. }));
+    assert_eq!(report.actions[1], ActionStatus::Applied);
+    assert_eq!(report.actions[2], ActionStatus::Applied);
+    assert_eq!(b.entity(id_b).await.unwrap().get_id(), Some(id_b));
+
+    // Assigning another tenant fails the whole batch.
+    let id_c = Id::random();
+    b.batch_with_savepoints(
+        Batch::new()
+            .and_create(Create {
+                id: id_c,
+                data: map! {"factor/title": "tenant-b"},
+            })
+            .and_savepoint()
+            .and_create(Create {
+                id: Id::random(),
+                data: map! {"factor/title": "tenant-b", "factor/tenant": tenant_a},
+            }),
+    )
+    .await
+    .unwrap_err();
+    let err = db.entity(id_c).await.unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+
+    // The schema is shared, so tenants can not migrate it.
+    a.migrate(Migration::new()).await.unwrap_err();
+
+    b.delete(id_b).await.unwrap();
+    a.delete(id).await.unwrap();
+}
+
+async fn test_guard(db: &Db) {
+    let id = Id::random();
+    db.create(id, map! {"factor/title": "a"}).await.unwrap();
+    let title_is = |title: &str| Expr::eq(AttrTitle::expr(), title);
+
+    db.batch(
+        Batch::new()
+            .and_guard(Guard {
+                id,
+                filter: title_is("a"),
+            })
+            .and_merge(Merge::new(id, map! {"factor/title": "b"})),
     )
     .await
     .unwrap();
 
-    // Ensure that the attribute was added to the schema.
-    db.schema()
+    // Guards observe the preceding actions of the batch.
+    let err = db
+        .batch(
+            Batch::new()
+                .and_merge(Merge::new(id, map! {"factor/title": "c"}))
+                .and_guard(Guard {
+                    id,
+                    filter: title_is("b"),
+                })
+                .and_delete(query::mutate::Delete { id }),
+        )
+        .await
+        .unwrap_err();
+    assert!(err.is::<EntityNotFound>());
+    assert_eq!(
+        db.entity(id)
+            .await
+            .unwrap()
+            .get(AttrTitle::QUALIFIED_NAME)
+            .and_then(|v| v.as_str()),
+        Some("b")
+    );
+
+    // Missing entities are accepted.
+    db.batch(Batch::new().and_guard(Guard {
+        id: Id::random(),
+        filter: Expr::literal(false),
+    }))
+    .await
+    .unwrap();
+}
+
+async fn test_tenant_scoped_unique_index(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "tenant_unique");
+    db.migrate(Migration::new().attr_create(Attribute::new(attr.clone(), ValueType::String)))
+        .await
+        .unwrap();
+    let attr_id = db.schema().await.unwrap().attr_by_ident(&attr).unwrap().id;
+    let index = schema::IndexSchema::new(NS_TEST, "tenant_unique_idx", vec![attr_id])
+        .with_unique(true)
+        .with_tenant_scoped(true);
+    db.migrate(Migration::new().action(SchemaAction::IndexCreate(IndexCreate { schema: index })))
+        .await
+        .unwrap();
+
+    let a = db.with_tenant(Id::random());
+    let b = db.with_tenant(Id::random());
+    a.create(Id::random(), map! {"test/tenant_unique": "x"})
+        .await
+        .unwrap();
+    // The same value is allowed for different tenants.
+    b.create(Id::random(), map! {"test/tenant_unique": "x"})
+        .await
+        .unwrap();
+    db.create(Id::random(), map! {"test/tenant_unique": "x"})
+        .await
+        .unwrap();
+
+    let err = a
+        .create(Id::random(), map! {"test/tenant_unique": "x"})
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+    assert_eq!(err.attribute(), Some("test/tenant_unique"));
+}
+
+async fn test_geo_index_within_radius(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "location");
+    db.migrate(Migration::new().attr_create(Attribute::new(attr.clone(), ValueType::GeoPoint)))
+        .await
+        .unwrap();
+    let attr_id = db.schema().await.unwrap().attr_by_ident(&attr).unwrap().id;
+    let index = schema::IndexSchema::new(NS_TEST, "location_geo_idx", vec![attr_id]);
+    db.migrate(Migration::new().action(SchemaAction::IndexCreate(IndexCreate { schema: index })))
+        .await
+        .unwrap();
+
+    let center = GeoPoint::new(48.2082, 16.3738).unwrap();
+    let center_id = Id::random();
+    db.create(center_id, map! {"test/location": center})
+        .await
+        .unwrap();
+    // Roughly 870 meters north, passed as a string.
+    let nearby_id = Id::random();
+    db.create(nearby_id, map! {"test/location": "48.216, 16.3738"})
+        .await
+        .unwrap();
+    // Berlin.
+    db.create(
+        Id::random(),
+        map! {"test/location": GeoPoint::new(52.52, 13.405).unwrap()},
+    )
+    .await
+    .unwrap();
+
+    let nearby = db.entity(nearby_id).await.unwrap();
+    assert_eq!(
+        nearby.get("test/location"),
+        Some(&GeoPoint::new(48.216, 16.3738).unwrap().into())
+    );
+
+    let select = |meters: f64| {
+        Select::new().with_filter(Expr::within_radius(Expr::attr_ident(&attr), center, meters))
+    };
+
+    let mut ids = db
+        .select_map(select(1_000.0))
         .await
         .unwrap()
-        .class_by_ident(&ty)
-        .unwrap()
-        .attributes
-        .iter()
-        .find(|a| a.attribute == "test/int")
-        .expect("attribute not added to schema!");
+        .into_iter()
+        .map(|data| data.get_id().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    let mut expected = vec![center_id, nearby_id];
+    expected.sort();
+    assert_eq!(ids, expected);
 
-    // The previously created entity should now have the new attribute with the default value.
-    let entity = db.entity(id_no_default).await.unwrap();
-    let val = entity.get("test/int").unwrap().as_int().unwrap();
-    assert_eq!(val, 42);
+    let items = db.select_map(select(500.0)).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].get_id(), Some(center_id));
 
-    // The entity with a correct value should still have the old one.
-    let entity = db.entity(id_with_default).await.unwrap();
-    let val = entity.get("test/int").unwrap().as_int().unwrap();
-    assert_eq!(val, 100);
+    assert_eq!(db.select_map(select(1_000_000.0)).await.unwrap().len(), 3);
 }
 
-async fn test_entity_attr_change_cardinality_from_required_to_optional(f: &Db) {
-    f.migrate(
+async fn test_partial_index(db: &Db) {
+    let code = format!("{}/{}", NS_TEST, "partial_code");
+    let archived = format!("{}/{}", NS_TEST, "partial_archived");
+    db.migrate(
         Migration::new()
-            .attr_create(Attribute {
-                id: Id::nil(),
-                ident: "test/tochange".into(),
-                title: None,
-                description: None,
-                value_type: ValueType::Bool,
-                unique: false,
-                index: false,
-                strict: false,
-            })
-            .entity_create(Class {
-                id: Id::nil(),
-                ident: "test/MutableEntity".into(),
-                title: None,
-                description: None,
-                attributes: vec![ClassAttribute {
-                    attribute: "test/tochange".into(),
-                    required: true,
-                }],
-                extends: vec![],
-                strict: false,
-            }),
+            .attr_create(Attribute::new(code.clone(), ValueType::String))
+            .attr_create(Attribute::new(archived.clone(), ValueType::Bool)),
+    )
+    .await
+    .unwrap();
+    let code_id = db.schema().await.unwrap().attr_by_ident(&code).unwrap().id;
+    let index = schema::IndexSchema::new(NS_TEST, "partial_code_idx", vec![code_id])
+        .with_unique(true)
+        .with_filter(Expr::eq(Expr::attr_ident(&archived), false));
+    db.migrate(Migration::new().action(SchemaAction::IndexCreate(IndexCreate { schema: index })))
+        .await
+        .unwrap();
+
+    let active = Id::random();
+    db.create(
+        active,
+        map! {"test/partial_code": "x", "test/partial_archived": false},
+    )
+    .await
+    .unwrap();
+    // Entities that do not match the filter are not indexed.
+    let archived_id = Id::random();
+    db.create(
+        archived_id,
+        map! {"test/partial_code": "x", "test/partial_archived": true},
+    )
+    .await
+    .unwrap();
+    let err = db
+        .create(
+            Id::random(),
+            map! {"test/partial_code": "x", "test/partial_archived": false},
+        )
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+    let err = db
+        .merge(archived_id, map! {"test/partial_archived": false})
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+
+    let page = db
+        .select(Select::new().with_filter(Expr::and(
+            Expr::eq(Expr::attr_ident(&code), "x"),
+            Expr::eq(Expr::attr_ident(&archived), false),
+        )))
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].data.get_id(), Some(active));
+
+    // Queries that do not imply the index filter must not use the index.
+    let page = db
+        .select(Select::new().with_filter(Expr::eq(Expr::attr_ident(&code), "x")))
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+
+    // Leaving the filter removes the entity from the index.
+    db.merge(active, map! {"test/partial_archived": true})
+        .await
+        .unwrap();
+    db.merge(archived_id, map! {"test/partial_archived": false})
+        .await
+        .unwrap();
+}
+
+async fn test_sparse_index(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "sparse_value");
+    let class = format!("{}/{}", NS_TEST, "SparseEntity");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(attr.clone(), ValueType::Json))
+            .entity_create(Class::new(class.clone()).with_attribute(attr.clone(), true)),
+    )
+    .await
+    .unwrap();
+    let attr_id = db.schema().await.unwrap().attr_by_ident(&attr).unwrap().id;
+    let index =
+        schema::IndexSchema::new(NS_TEST, "sparse_value_idx", vec![attr_id]).with_sparse(true);
+    db.migrate(Migration::new().action(SchemaAction::IndexCreate(IndexCreate { schema: index })))
+        .await
+        .unwrap();
+
+    let id = Id::random();
+    db.create(
+        id,
+        map! {"factor/type": class.clone(), "test/sparse_value": "a"},
+    )
+    .await
+    .unwrap();
+    for _ in 0..2 {
+        db.create(
+            Id::random(),
+            map! {"factor/type": class.clone(), "test/sparse_value": Value::Unit},
+        )
+        .await
+        .unwrap();
+    }
+
+    let items = db
+        .select(Select::new().with_filter(Expr::eq(Expr::attr_ident(&attr), "a")))
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].data.get_id(), Some(id));
+
+    // Unit values are not in the index, so the query must not use it.
+    let items = db
+        .select(Select::new().with_filter(Expr::eq(Expr::attr_ident(&attr), Value::Unit)))
+        .await
+        .unwrap()
+        .items;
+    assert_eq!(items.len(), 2);
+
+    db.replace(
+        id,
+        map! {"factor/type": class.clone(), "test/sparse_value": Value::Unit},
+    )
+    .await
+    .unwrap();
+    let items = db
+        .select(Select::new().with_filter(Expr::eq(Expr::attr_ident(&attr), "a")))
+        .await
+        .unwrap()
+        .items;
+    assert!(items.is_empty());
+}
+
+async fn test_materialized_view(db: &Db) {
+    async fn view_ids(db: &Db, view: &str) -> Vec<Id> {
+        db.select_view(view)
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|item| item.data.get_id().unwrap())
+            .collect()
+    }
+
+    async fn view_count(db: &Db, view: &str) -> u64 {
+        db.select_view(view)
+            .await
+            .unwrap()
+            .items
+            .pop()
+            .unwrap()
+            .data
+            .get("factor/count")
+            .unwrap()
+            .as_uint()
+            .unwrap()
+    }
+
+    let status = format!("{}/{}", NS_TEST, "view_status");
+    let score = format!("{}/{}", NS_TEST, "view_score");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(status.clone(), ValueType::String))
+            .attr_create(Attribute::new(score.clone(), ValueType::Int)),
+    )
+    .await
+    .unwrap();
+
+    let open = |value: i64| map! {"test/view_status": "open", "test/view_score": value};
+    let first = Id::random();
+    db.create(first, open(1)).await.unwrap();
+
+    let filter = Expr::eq(Expr::attr_ident(&status), "open");
+    let view = schema::ViewSchema::new(
+        NS_TEST,
+        "open_by_score",
+        Select::new()
+            .with_filter(filter.clone())
+            .with_sort(Expr::attr_ident(&score), Order::Desc),
+    );
+    let count_view = schema::ViewSchema::new(
+        NS_TEST,
+        "open_count",
+        Select::new()
+            .with_filter(filter)
+            .with_aggregate(query::select::AggregationOp::Count, "count".to_string()),
+    );
+    db.migrate(
+        Migration::new()
+            .view_create(view.clone())
+            .view_create(count_view.clone()),
+    )
+    .await
+    .unwrap();
+
+    // Entities that existed before the view was created are included.
+    assert_eq!(view_ids(db, &view.ident).await, vec![first]);
+
+    let second = Id::random();
+    db.create(second, open(2)).await.unwrap();
+    db.create(
+        Id::random(),
+        map! {"test/view_status": "closed", "test/view_score": 3},
+    )
+    .await
+    .unwrap();
+    assert_eq!(view_ids(db, &view.ident).await, vec![second, first]);
+    assert_eq!(view_count(db, &count_view.ident).await, 2);
+
+    db.merge(first, map! {"test/view_status": "closed"})
+        .await
+        .unwrap();
+    assert_eq!(view_ids(db, &view.ident).await, vec![second]);
+
+    // Failed batches do not change the view.
+    let batch = Batch::new()
+        .and_merge(query::mutate::Merge::new(
+            first,
+            map! {"test/view_status": "open"},
+        ))
+        .and_create(Create {
+            id: second,
+            data: open(5),
+        });
+    db.batch(batch).await.unwrap_err();
+    assert_eq!(view_ids(db, &view.ident).await, vec![second]);
+
+    db.delete(second).await.unwrap();
+    assert!(view_ids(db, &view.ident).await.is_empty());
+    assert_eq!(view_count(db, &count_view.ident).await, 0);
+
+    db.migrate(Migration::new().view_delete(&view.ident))
+        .await
+        .unwrap();
+    db.select_view(&view.ident).await.unwrap_err();
+}
+
+async fn test_select_cached(db: &Db) {
+    async fn scores(db: &Db, query: &Select) -> Vec<i64> {
+        db.select_map(query.clone())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|data| data.get("test/cached_score").unwrap().as_int().unwrap())
+            .collect()
+    }
+
+    async fn count(db: &Db, query: &Select) -> u64 {
+        db.select_map(query.clone()).await.unwrap()[0]
+            .get("factor/count")
+            .unwrap()
+            .as_uint()
+            .unwrap()
+    }
+
+    let attr = format!("{}/{}", NS_TEST, "cached_score");
+    let class = format!("{}/{}", NS_TEST, "Cached");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(attr.clone(), ValueType::Int))
+            .entity_create(Class::new(class.clone()).with_attribute(attr.clone(), false)),
+    )
+    .await
+    .unwrap();
+
+    let ttl = std::time::Duration::from_secs(60);
+    let select = Select::new()
+        .with_filter(Expr::is_entity_name(&class))
+        .with_sort(Expr::attr_ident(&attr), Order::Asc)
+        .cached(ttl);
+    let select_count = Select::new()
+        .with_filter(Expr::is_entity_name(&class))
+        .with_aggregate(query::select::AggregationOp::Count, "count".to_string())
+        .cached(ttl);
+
+    let first = Id::random();
+    db.create(
+        first,
+        map! {"factor/type": class.clone(), "test/cached_score": 1},
+    )
+    .await
+    .unwrap();
+    assert_eq!(scores(db, &select).await, vec![1]);
+    assert_eq!(count(db, &select_count).await, 1);
+
+    // Writes to the queried class invalidate the cached results.
+    db.merge(first, map! {"test/cached_score": 3})
+        .await
+        .unwrap();
+    assert_eq!(scores(db, &select).await, vec![3]);
+    let second = Id::random();
+    db.create(
+        second,
+        map! {"factor/type": class.clone(), "test/cached_score": 2},
+    )
+    .await
+    .unwrap();
+    assert_eq!(scores(db, &select).await, vec![2, 3]);
+    assert_eq!(count(db, &select_count).await, 2);
+
+    // Entities of other classes do not match.
+    db.create(Id::random(), map! {"test/cached_score": 5})
+        .await
+        .unwrap();
+    assert_eq!(scores(db, &select).await, vec![2, 3]);
+
+    db.delete(first).await.unwrap();
+    assert_eq!(scores(db, &select).await, vec![2]);
+    assert_eq!(count(db, &select_count).await, 1);
+}
+
+async fn test_json_attr_path(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "payload");
+    db.migrate(Migration::new().attr_create(Attribute::new(attr.clone(), ValueType::Json)))
+        .await
+        .unwrap();
+
+    let payload = factor_core::data::value::to_value(serde_json::json!({
+        "a": { "b": 42, "tags": ["x", "y"] },
+    }))
+    .unwrap();
+    let id = Id::random();
+    db.create(id, map! {"test/payload": payload.clone()})
+        .await
+        .unwrap();
+    db.create(
+        Id::random(),
+        map! {"test/payload": factor_core::data::value::to_value(serde_json::json!({"a": 1})).unwrap()},
+    )
+    .await
+    .unwrap();
+
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(data.get(&attr), Some(&payload));
+
+    let select = |path: &str, value: Value| {
+        Select::new().with_filter(Expr::eq(
+            Expr::json_path(Expr::attr_ident(&attr), path),
+            value,
+        ))
+    };
+
+    let items = db.select_map(select("$.a.b", 42.into())).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].get_id(), Some(id));
+
+    let items = db
+        .select_map(select("$.a.tags[1]", "y".into()))
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 1);
+
+    let items = db.select_map(select("$.a.c", 42.into())).await.unwrap();
+    assert!(items.is_empty());
+
+    // Bytes can not be represented as JSON.
+    let err = db
+        .create(
+            Id::random(),
+            map! {"test/payload": vec![1u8, 2, 3].as_slice()},
+        )
+        .await
+        .unwrap_err();
+    assert!(err.is::<ValueCoercionError>());
+}
+
+async fn test_select_computed_expressions(db: &Db) {
+    let price = format!("{}/{}", NS_TEST, "unitPrice");
+    let quantity = format!("{}/{}", NS_TEST, "quantity");
+    let name = format!("{}/{}", NS_TEST, "productName");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(price.clone(), ValueType::Float))
+            .attr_create(Attribute::new(quantity.clone(), ValueType::Int))
+            .attr_create(Attribute::new(name.clone(), ValueType::String)),
+    )
+    .await
+    .unwrap();
+
+    let a = Id::random();
+    db.create(
+        a,
+        map! {"test/unitPrice": 12.5, "test/quantity": 10, "test/productName": "Apple"},
+    )
+    .await
+    .unwrap();
+    let b = Id::random();
+    db.create(
+        b,
+        map! {"test/unitPrice": 2.0, "test/quantity": 3, "test/productName": "Kiwi"},
+    )
+    .await
+    .unwrap();
+
+    let select_ids = |filter: Expr| async move {
+        let mut ids = db
+            .select_map(Select::new().with_filter(filter))
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|item| item.get_id())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+
+    let total = Expr::mul(Expr::attr_ident(&price), Expr::attr_ident(&quantity));
+    assert_eq!(select_ids(Expr::gt(total.clone(), 100u64)).await, vec![a]);
+    assert_eq!(select_ids(Expr::eq(total, 6.0)).await, vec![b]);
+
+    let adjusted = Expr::sub(Expr::add(Expr::attr_ident(&quantity), 5), 1);
+    assert_eq!(select_ids(Expr::eq(adjusted, 7)).await, vec![b]);
+
+    let lower = Expr::lower(Expr::attr_ident(&name));
+    assert_eq!(select_ids(Expr::eq(lower, "apple")).await, vec![a]);
+
+    let label = Expr::upper(Expr::concat(Expr::attr_ident(&name), "!"));
+    assert_eq!(select_ids(Expr::eq(label, "KIWI!")).await, vec![b]);
+
+    let mut expected = vec![a, b];
+    expected.sort();
+    let len = Expr::length(Expr::attr_ident(&name));
+    assert_eq!(select_ids(Expr::gte(len.clone(), 4u64)).await, expected);
+    assert_eq!(select_ids(Expr::eq(len, 5u64)).await, vec![a]);
+
+    // Arithmetic on non-numeric values produces unit.
+    let invalid = Expr::add(Expr::attr_ident(&name), 1);
+    assert!(select_ids(Expr::eq(invalid, 1)).await.is_empty());
+}
+
+async fn test_select_date_functions(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "publishedAt");
+    db.migrate(Migration::new().attr_create(Attribute::new(attr.clone(), ValueType::DateTime)))
+        .await
+        .unwrap();
+
+    let a = Id::random();
+    let a_time = Timestamp::parse_rfc3339("2021-03-04T10:20:00Z").unwrap();
+    db.create(a, map! {"test/publishedAt": a_time})
+        .await
+        .unwrap();
+    let b = Id::random();
+    let b_time = Timestamp::parse_rfc3339("2022-06-01T23:30:00Z").unwrap();
+    db.create(b, map! {"test/publishedAt": b_time})
+        .await
+        .unwrap();
+
+    let select_ids = |filter: Expr| async move {
+        db.select_map(Select::new().with_filter(filter))
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|item| item.get_id())
+            .collect::<Vec<_>>()
+    };
+    let published = || Expr::attr_ident(&attr);
+
+    assert_eq!(
+        select_ids(Expr::eq(Expr::year(published()), 2022)).await,
+        vec![b]
+    );
+    assert_eq!(
+        select_ids(Expr::eq(Expr::month(published()), 3u64)).await,
+        vec![a]
+    );
+    assert_eq!(
+        select_ids(Expr::eq(Expr::day(published()), 1u64)).await,
+        vec![b]
+    );
+
+    let day = Timestamp::parse_rfc3339("2022-06-01T00:00:00Z").unwrap();
+    let truncated = Expr::date_trunc(TimeUnit::Day, published());
+    assert_eq!(select_ids(Expr::eq(truncated, day)).await, vec![b]);
+}
+
+async fn test_attribute_collation_case_insensitive(db: &Db) {
+    let attr = format!("{}/{}", NS_TEST, "userName");
+    db.migrate(
+        Migration::new().attr_create(
+            Attribute::new(attr.clone(), ValueType::String)
+                .with_unique(true)
+                .with_collation(schema::Collation::CaseInsensitive),
+        ),
+    )
+    .await
+    .unwrap();
+
+    let alice = Id::random();
+    db.create(alice, map! {"test/userName": "Alice"})
+        .await
+        .unwrap();
+    let err = db
+        .create(Id::random(), map! {"test/userName": "ALICE"})
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+    db.create(Id::random(), map! {"test/userName": "Bob"})
+        .await
+        .unwrap();
+    db.create(Id::random(), map! {"test/userName": "adam"})
+        .await
+        .unwrap();
+
+    let attr = attr.as_str();
+    let select_names = |select: Select| async move {
+        db.select_map(select)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|item| item.get(attr).and_then(|v| v.as_str()).map(String::from))
+            .collect::<Vec<_>>()
+    };
+
+    let items =
+        select_names(Select::new().with_filter(Expr::eq_ci(Expr::attr_ident(attr), "aLiCe"))).await;
+    assert_eq!(items, vec!["Alice".to_string()]);
+
+    // Plain equality is still exact.
+    let items =
+        select_names(Select::new().with_filter(Expr::eq(Expr::attr_ident(attr), "alice"))).await;
+    assert!(items.is_empty());
+    let items =
+        select_names(Select::new().with_filter(Expr::eq(Expr::attr_ident(attr), "Alice"))).await;
+    assert_eq!(items, vec!["Alice".to_string()]);
+
+    let items = select_names(
+        Select::new()
+            .with_filter(Expr::neq(Expr::attr_ident(attr), Value::Unit))
+            .with_sort(Expr::attr_ident(attr), Order::Asc),
+    )
+    .await;
+    assert_eq!(items, vec!["adam", "Alice", "Bob"]);
+
+    // Collation is only valid for strings.
+    let err = db
+        .migrate(
+            Migration::new().attr_create(
+                Attribute::new(format!("{}/{}", NS_TEST, "ciInt"), ValueType::Int)
+                    .with_collation(schema::Collation::CaseInsensitive),
+            ),
+        )
+        .await;
+    assert!(err.is_err());
+}
+
+async fn test_class_id_strategy(db: &Db) {
+    let class = format!("{}/{}", NS_TEST, "TimeOrdered");
+    db.migrate(Migration::new().entity_create(
+        Class::new(class.clone()).with_id_strategy(schema::IdStrategy::TimeOrdered),
+    ))
+    .await
+    .unwrap();
+
+    db.create(Id::nil(), map! {"factor/type": class.clone()})
+        .await
+        .unwrap();
+    let items = db
+        .select_map(Select::new().with_filter(Expr::is_entity_name(&class)))
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 1);
+    let id = items[0].get_id().unwrap();
+    assert_eq!(id.as_uuid().get_version_num(), 7);
+}
+
+async fn test_class_quota(db: &Db) {
+    let class = format!("{}/{}", NS_TEST, "Limited");
+    db.migrate(Migration::new().entity_create(Class::new(class.clone()).with_max_entities(2)))
+        .await
+        .unwrap();
+
+    let id1 = Id::random();
+    db.create(id1, map! {"factor/type": class.clone()})
+        .await
+        .unwrap();
+    db.create(Id::random(), map! {"factor/type": class.clone()})
+        .await
+        .unwrap();
+    let err = db
+        .create(Id::random(), map! {"factor/type": class.clone()})
+        .await
+        .expect_err("Must fail");
+    assert!(matches!(&err, Error::QuotaExceeded(e) if e.class == class && e.limit == 2));
+    assert!(err.is::<QuotaExceeded>());
+
+    let stats = db.class_stats().await.unwrap();
+    let limited = stats.iter().find(|s| s.class == class).unwrap();
+    assert_eq!(limited.entities, 2);
+    assert_eq!(limited.max_entities, Some(2));
+
+    db.delete(id1).await.unwrap();
+    db.create(Id::random(), map! {"factor/type": class.clone()})
+        .await
+        .unwrap();
+    let stats = db.class_stats().await.unwrap();
+    let limited = stats.iter().find(|s| s.class == class).unwrap();
+    assert_eq!(limited.entities, 2);
+}
+
+async fn test_class_computed_attributes(db: &Db) {
+    let first = format!("{}/{}", NS_TEST, "firstName");
+    let last = format!("{}/{}", NS_TEST, "lastName");
+    let full = format!("{}/{}", NS_TEST, "fullName");
+    let class = format!("{}/{}", NS_TEST, "Person");
+    let full_name = Expr::concat(
+        Expr::concat(Expr::attr_ident(&first), " "),
+        Expr::attr_ident(&last),
+    );
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(first.clone(), ValueType::String))
+            .attr_create(Attribute::new(last.clone(), ValueType::String))
+            .entity_create(
+                Class::new(class.clone())
+                    .with_attribute(first.clone(), true)
+                    .with_attribute(last.clone(), true)
+                    .with_computed_attribute(full.clone(), full_name),
+            ),
+    )
+    .await
+    .unwrap();
+
+    let a = Id::random();
+    db.create(
+        a,
+        map! {"factor/type": class.clone(), "test/firstName": "Ada", "test/lastName": "Lovelace"},
+    )
+    .await
+    .unwrap();
+    let b = Id::random();
+    db.create(
+        b,
+        map! {"factor/type": class.clone(), "test/firstName": "Alan", "test/lastName": "Turing"},
+    )
+    .await
+    .unwrap();
+
+    let data = db.entity(a).await.unwrap();
+    assert_eq!(data.get(&full), Some(&Value::from("Ada Lovelace")));
+
+    // Filters and sorts are inlined.
+    let items = db
+        .select_map(
+            Select::new()
+                .with_filter(Expr::eq(Expr::attr_ident(&full), "Alan Turing"))
+                .with_sort(Expr::attr_ident(&full), Order::Asc),
+        )
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].get_id(), Some(b));
+    assert_eq!(items[0].get(&full), Some(&Value::from("Alan Turing")));
+
+    // Read data can be written back, computed values are not stored.
+    let mut data = db.entity(a).await.unwrap();
+    data.insert(last.clone(), "Byron".into());
+    db.replace(a, data.clone()).await.unwrap();
+    db.merge(a, data).await.unwrap();
+    let data = db.entity(a).await.unwrap();
+    assert_eq!(data.get(&full), Some(&Value::from("Ada Byron")));
+
+    // Computed attributes can only use attributes of the class.
+    let err = db
+        .migrate(
+            Migration::new().entity_create(
+                Class::new(format!("{}/{}", NS_TEST, "Invalid"))
+                    .with_attribute(first.clone(), true)
+                    .with_computed_attribute(
+                        format!("{}/{}", NS_TEST, "invalid"),
+                        Expr::attr_ident(&last),
+                    ),
+            ),
+        )
+        .await;
+    assert!(err.is_err());
+
+    // Computed attributes can not shadow stored attributes.
+    let err = db
+        .migrate(Migration::new().attr_create(Attribute::new(full.clone(), ValueType::String)))
+        .await;
+    assert!(err.is_err());
+}
+
+async fn test_rule_bumps_counter(db: &Db) {
+    let count = format!("{}/{}", NS_TEST, "commentCount");
+    let post = format!("{}/{}", NS_TEST, "commentPost");
+    let post_class = format!("{}/{}", NS_TEST, "CountedPost");
+    let comment_class = format!("{}/{}", NS_TEST, "CountedComment");
+    let rule = schema::RuleSchema::new(
+        NS_TEST,
+        "bumpCommentCount",
+        comment_class.clone(),
+        vec![schema::RuleEvent::Create],
+    )
+    .with_action(schema::RuleAction::Merge {
+        entity: Expr::var(post.clone()),
+        data: [(count.clone(), Expr::add(Expr::attr_ident(&count), 1))]
+            .into_iter()
+            .collect(),
+    });
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(count.clone(), ValueType::Int))
+            .attr_create(Attribute::new(post.clone(), ValueType::Ref))
+            .entity_create(Class::new(post_class.clone()).with_attribute(count.clone(), true))
+            .entity_create(Class::new(comment_class.clone()).with_attribute(post.clone(), true))
+            .rule_create(rule),
+    )
+    .await
+    .unwrap();
+
+    let post_id = Id::random();
+    db.create(
+        post_id,
+        map! {"factor/type": post_class.clone(), "test/commentCount": 0},
+    )
+    .await
+    .unwrap();
+
+    let comment = |id: Id| Create {
+        id,
+        data: map! {"factor/type": comment_class.clone(), "test/commentPost": post_id},
+    };
+    db.create(Id::random(), comment(Id::random()).data)
+        .await
+        .unwrap();
+    db.batch(
+        Batch::new()
+            .and_create(comment(Id::random()))
+            .and_create(comment(Id::random())),
+    )
+    .await
+    .unwrap();
+
+    let data = db.entity(post_id).await.unwrap();
+    assert_eq!(data.get(&count), Some(&Value::Int(3)));
+
+    // Rule writes are part of the batch and roll back with it.
+    let err = db
+        .batch(
+            Batch::new()
+                .and_create(comment(Id::random()))
+                .and_delete(query::mutate::Delete { id: Id::random() }),
+        )
+        .await;
+    assert!(err.is_err());
+    let data = db.entity(post_id).await.unwrap();
+    assert_eq!(data.get(&count), Some(&Value::Int(3)));
+
+    db.migrate(Migration::new().rule_delete(format!("{}/bumpCommentCount", NS_TEST)))
+        .await
+        .unwrap();
+    db.create(Id::random(), comment(Id::random()).data)
+        .await
+        .unwrap();
+    let data = db.entity(post_id).await.unwrap();
+    assert_eq!(data.get(&count), Some(&Value::Int(3)));
+}
+
+async fn test_reference_acyclic(db: &Db) {
+    let parent = format!("{}/{}", NS_TEST, "parentNode");
+    let class = format!("{}/{}", NS_TEST, "Node");
+    // The class must exist before the attribute that references it.
+    db.migrate(
+        Migration::new()
+            .entity_create(Class::new(class.clone()))
+            .attr_create(Attribute::new(
+                parent.clone(),
+                ValueType::RefConstrained(
+                    ConstrainedRefType::new(vec![class.clone().into()]).with_acyclic_depth(10),
+                ),
+            ))
+            .entity_upsert(Class::new(class.clone()).with_attribute(parent.clone(), false)),
+    )
+    .await
+    .unwrap();
+
+    let a = Id::random();
+    let b = Id::random();
+    let c = Id::random();
+    db.create(a, map! {"factor/type": class.clone()})
+        .await
+        .unwrap();
+    db.create(b, map! {"factor/type": class.clone(), "test/parentNode": a})
+        .await
+        .unwrap();
+    db.create(c, map! {"factor/type": class.clone(), "test/parentNode": b})
+        .await
+        .unwrap();
+
+    let err = db
+        .merge(a, map! {"test/parentNode": c})
+        .await
+        .expect_err("Must fail");
+    assert!(matches!(&err, Error::ReferenceCycle(e) if e.entity == a && e.attribute == parent));
+    assert!(err.is::<ReferenceCycle>());
+
+    let err = db
+        .merge(a, map! {"test/parentNode": a})
+        .await
+        .expect_err("Must fail");
+    assert!(err.is::<ReferenceCycle>());
+
+    // Moving a subtree is fine.
+    db.merge(c, map! {"test/parentNode": a}).await.unwrap();
+}
+
+async fn test_shortest_path(db: &Db) {
+    let follows = format!("{}/{}", NS_TEST, "follows");
+    let class = format!("{}/{}", NS_TEST, "Person");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(
+                follows.clone(),
+                ValueType::List(Box::new(ValueType::Ref)),
+            ))
+            .entity_create(Class::new(class.clone()).with_attribute(follows.clone(), false)),
+    )
+    .await
+    .unwrap();
+
+    let [a, b, c, d, e] = [(); 5].map(|_| Id::random());
+    for (id, targets) in [
+        (d, vec![]),
+        (c, vec![d]),
+        (e, vec![d]),
+        (b, vec![c]),
+        (a, vec![b, e]),
+    ] {
+        db.create(
+            id,
+            map! {"factor/type": class.clone(), "test/follows": targets},
+        )
+        .await
+        .unwrap();
+    }
+
+    let path = db
+        .shortest_path(GraphQuery::new(a, d, follows.clone(), 5))
+        .await
+        .unwrap();
+    assert_eq!(path, Some(vec![a, e, d]));
+
+    let path = db
+        .shortest_path(GraphQuery::new(a, d, follows.clone(), 1))
+        .await
+        .unwrap();
+    assert_eq!(path, None);
+
+    let path = db
+        .shortest_path(GraphQuery::new(a, a, follows.clone(), 1))
+        .await
+        .unwrap();
+    assert_eq!(path, Some(vec![a]));
+
+    // References are directed by default.
+    let path = db
+        .shortest_path(GraphQuery::new(d, a, follows.clone(), 5))
+        .await
+        .unwrap();
+    assert_eq!(path, None);
+
+    let path = db
+        .shortest_path(
+            GraphQuery::new(d, a, follows.clone(), 5).with_direction(GraphDirection::Incoming),
+        )
+        .await
+        .unwrap();
+    assert_eq!(path, Some(vec![d, e, a]));
+
+    let path = db
+        .shortest_path(
+            GraphQuery::new(c, e, follows.clone(), 5).with_direction(GraphDirection::Both),
+        )
+        .await
+        .unwrap();
+    assert_eq!(path, Some(vec![c, d, e]));
+
+    db.shortest_path(GraphQuery::new(a, d, AttrTitle::QUALIFIED_NAME, 5))
+        .await
+        .expect_err("Must fail for non-reference attributes");
+}
+
+async fn test_merge_with_clock(db: &Db) {
+    let status = format!("{}/{}", NS_TEST, "clock_status");
+    let note = format!("{}/{}", NS_TEST, "clock_note");
+    let class = format!("{}/{}", NS_TEST, "ClockTask");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(status.clone(), ValueType::String))
+            .attr_create(Attribute::new(note.clone(), ValueType::String))
+            .entity_create(Class {
+                strict: true,
+                ..Class::new(class.clone())
+                    .with_attribute(status.clone(), false)
+                    .with_attribute(note.clone(), false)
+            }),
+    )
+    .await
+    .unwrap();
+
+    let id = Id::random();
+    let clocked = |data, millis, node| {
+        Batch::with_action(Merge::new(id, data).with_clock(HlcTimestamp::new(millis, 0, node)))
+    };
+
+    db.batch(clocked(
+        map! {"factor/type": class.clone(), "test/clock_status": "b"},
+        20,
+        2,
+    ))
+    .await
+    .unwrap();
+
+    // An older concurrent write only applies attributes that were not
+    // written later.
+    db.batch(clocked(
+        map! {"test/clock_status": "a", "test/clock_note": "x"},
+        10,
+        1,
+    ))
+    .await
+    .unwrap();
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(data.get(&status), Some(&Value::from("b")));
+    assert_eq!(data.get(&note), Some(&Value::from("x")));
+
+    // Equal timestamps are ordered by node.
+    db.batch(clocked(map! {"test/clock_status": "c"}, 20, 3))
+        .await
+        .unwrap();
+    // Re-applying a write is a no-op.
+    db.batch(clocked(map! {"test/clock_status": "d"}, 20, 3))
+        .await
+        .unwrap();
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(data.get(&status), Some(&Value::from("c")));
+
+    let clocks = match data.get(AttrClocks::QUALIFIED_NAME) {
+        Some(Value::Map(clocks)) => clocks.clone(),
+        other => panic!("Expected clocks, got {:?}", other),
+    };
+    assert_eq!(
+        clocks.get(&Value::from(status.clone())),
+        Some(&Value::from(HlcTimestamp::new(20, 0, 3).to_string()))
+    );
+
+    // Merges without a clock always apply.
+    db.merge(id, map! {"test/clock_status": "e"}).await.unwrap();
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(data.get(&status), Some(&Value::from("e")));
+}
+
+async fn test_entity_attr_add_with_default(db: &Db) {
+    let ty = "t/AddTest";
+    db.migrate(Migration::new().entity_create(Class {
+        id: Id::nil(),
+        ident: ty.to_string(),
+        title: None,
+        description: None,
+        attributes: vec![ClassAttribute {
+            attribute: AttrTitle::QUALIFIED_NAME.to_string(),
+            required: true,
+            constraints: Vec::new(),
+        }],
+        extends: vec![],
+        strict: false,
+        id_strategy: schema::IdStrategy::Random,
+        max_entities: None,
+        computed_attributes: Vec::new(),
+        mixins: Vec::new(),
+        is_abstract: false,
+    }))
+    .await
+    .unwrap();
+
+    let id_no_default = Id::random();
+    db.create(
+        id_no_default,
+        map! {
+            "factor/type": ty,
+            "factor/title": "hello",
+        },
+    )
+    .await
+    .unwrap();
+
+    let id_with_default = Id::random();
+    db.create(
+        id_with_default,
+        map! {
+            "factor/type": ty,
+            "factor/title": "hello",
+            "test/int": 100,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Migration should fail without a default value.
+    let err = db
+        .migrate(
+            Migration::new().action(SchemaAction::EntityAttributeAdd(EntityAttributeAdd {
+                entity: ty.into(),
+                attribute: "test/int".into(),
+                cardinality: schema::Cardinality::Required,
+                default_value: None,
+            })),
+        )
+        .await
+        .expect_err("Must fail");
+    assert!(err.to_string().contains("requires a default value"));
+
+    // Now supply a default value.
+    db.migrate(
+        Migration::new().action(SchemaAction::EntityAttributeAdd(EntityAttributeAdd {
+            entity: ty.into(),
+            attribute: "test/int".into(),
+            cardinality: schema::Cardinality::Required,
+            default_value: Some(42u64.into()),
+        })),
+    )
+    .await
+    .unwrap();
+
+    // Ensure that the attribute was added to the schema.
+    db.schema()
+        .await
+        .unwrap()
+        .class_by_ident(&ty)
+        .unwrap()
+        .attributes
+        .iter()
+        .find(|a| a.attribute == "test/int")
+        .expect("attribute not added to schema!");
+
+    // The previously created entity should now have the new attribute with the default value.
+    let entity = db.entity(id_no_default).await.unwrap();
+    let val = entity.get("test/int").unwrap().as_int().unwrap();
+    assert_eq!(val, 42);
+
+    // The entity with a correct value should still have the old one.
+    let entity = db.entity(id_with_default).await.unwrap();
+    let val = entity.get("test/int").unwrap().as_int().unwrap();
+    assert_eq!(val, 100);
+}
+
+async fn test_entity_attr_change_cardinality_from_required_to_optional(f: &Db) {
+    f.migrate(
+        Migration::new()
+            .attr_create(Attribute {
+                id: Id::nil(),
+                ident: "test/tochange".into(),
+                title: None,
+                description: None,
+                value_type: ValueType::Bool,
+                unique: false,
+                index: false,
+                strict: false,
+                collation: schema::Collation::Binary,
+            })
+            .entity_create(Class {
+                id: Id::nil(),
+                ident: "test/MutableEntity".into(),
+                title: None,
+                description: None,
+                attributes: vec![ClassAttribute {
+                    attribute: "test/tochange".into(),
+                    required: true,
+                    constraints: Vec::new(),
+                }],
+                extends: vec![],
+                strict: false,
+                id_strategy: schema::IdStrategy::Random,
+                max_entities: None,
+                computed_attributes: Vec::new(),
+                mixins: Vec::new(),
+                is_abstract: false,
+            }),
+    )
+    .await
+    .unwrap();
+
+    f.migrate(
+        Migration::new().action(SchemaAction::EntityAttributeChangeCardinality(
+            EntityAttributeChangeCardinality {
+                entity_type: "test/MutableEntity".into(),
+                attribute: "test/tochange".into(),
+                new_cardinality: schema::Cardinality::Optional,
+            },
+        )),
+    )
+    .await
+    .unwrap();
+}
+
+async fn test_query_entity_select_ident(db: &Db) {
+    let id = Id::random();
+    db.create(
+        id,
+        map! {
+            "factor/title": "hello",
+            "factor/ident": "hello-ident",
+        },
+    )
+    .await
+    .unwrap();
+
+    let page = db
+        .select(
+            Select::new().with_filter(Expr::eq(AttrId::expr(), Expr::Ident("hello-ident".into()))),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].data.get_id().unwrap(), id);
+}
+
+async fn test_query_entity_is_type_nested(db: &Db) {
+    let id1 = Id::random();
+    db.create(id1, map! {"factor/type": ENTITY_FILE})
+        .await
+        .unwrap();
+
+    let id2 = Id::random();
+    db.create(id2, map! {"factor/type": ENTITY_IMAGE})
+        .await
+        .unwrap();
+
+    let id3 = Id::random();
+    db.create(id3, map! {"factor/type": ENTITY_IMAGE_JPEG})
+        .await
+        .unwrap();
+
+    let page = db
+        .select(Select::new().with_filter(Expr::InheritsEntityType(ENTITY_FILE.to_string())))
+        .await
+        .unwrap();
+
+    assert_eq!(page.items.len(), 3);
+}
+
+async fn test_query_type_in_namespace(db: &Db) {
+    let comment = Id::random();
+    db.create(comment, map! {"factor/type": ENTITY_COMMENT})
+        .await
+        .unwrap();
+    let file = Id::random();
+    db.create(file, map! {"factor/type": ENTITY_FILE})
+        .await
+        .unwrap();
+    db.create(Id::random(), map! {"factor/title": "untyped"})
+        .await
+        .unwrap();
+
+    let page = db
+        .select(Select::new().with_filter(Expr::type_in_namespace(NS_TEST)))
+        .await
+        .unwrap();
+    let mut ids = page
+        .items
+        .iter()
+        .map(|item| item.data.get_id().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    let mut expected = vec![comment, file];
+    expected.sort();
+    assert_eq!(ids, expected);
+
+    let page = db
+        .select(Select::new().with_filter(Expr::type_in_namespace("unknown")))
+        .await
+        .unwrap();
+    assert!(page.items.is_empty());
+}
+
+async fn test_class_mixins(db: &Db) {
+    let created = format!("{}/{}", NS_TEST, "mixinCreatedAt");
+    let updated = format!("{}/{}", NS_TEST, "mixinUpdatedAt");
+    let mixin = format!("{}/{}", NS_TEST, "timestamps");
+    let class = format!("{}/{}", NS_TEST, "MixinPost");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(created.clone(), ValueType::Int))
+            .attr_create(Attribute::new(updated.clone(), ValueType::Int))
+            .mixin_create(
+                schema::Mixin::new(mixin.clone())
+                    .with_attribute(ClassAttribute::new_required(created.clone()))
+                    .with_attribute(ClassAttribute::new_required(updated.clone())),
+            )
+            // The class declaration takes precedence over the mixin.
+            .entity_create(
+                Class::new(class.clone())
+                    .with_attribute(updated.clone(), false)
+                    .with_mixin(mixin.clone()),
+            ),
+    )
+    .await
+    .unwrap();
+
+    let schema = db.schema().await.unwrap();
+    let attrs = &schema.class_by_ident(&class).unwrap().attributes;
+    assert_eq!(
+        attrs
+            .iter()
+            .map(|a| (a.attribute.as_str(), a.required))
+            .collect::<Vec<_>>(),
+        vec![(updated.as_str(), false), (created.as_str(), true)]
+    );
+
+    db.create(
+        Id::random(),
+        map! {"factor/type": class.clone(), "test/mixinCreatedAt": 1},
+    )
+    .await
+    .unwrap();
+    db.create(Id::random(), map! {"factor/type": class.clone()})
+        .await
+        .expect_err("Must fail: required mixin attribute is missing");
+
+    db.migrate(Migration::new().mixin_delete(mixin.clone()))
+        .await
+        .expect_err("Must fail: mixin is still in use");
+}
+
+async fn test_abstract_classes(db: &Db) {
+    let name = format!("{}/{}", NS_TEST, "shapeName");
+    let shape = format!("{}/{}", NS_TEST, "Shape");
+    let circle = format!("{}/{}", NS_TEST, "Circle");
+    let square = format!("{}/{}", NS_TEST, "Square");
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new(name.clone(), ValueType::String))
+            .entity_create(
+                Class::new(shape.clone())
+                    .into_abstract()
+                    .with_attribute(name.clone(), true),
+            )
+            .entity_create(Class::new(circle.clone()).with_extend(shape.clone()))
+            .entity_create(Class::new(square.clone()).with_extend(shape.clone())),
+    )
+    .await
+    .unwrap();
+
+    // Abstract classes can't extend concrete classes.
+    db.migrate(
+        Migration::new().entity_create(
+            Class::new(format!("{}/{}", NS_TEST, "RoundShape"))
+                .into_abstract()
+                .with_extend(circle.clone()),
+        ),
+    )
+    .await
+    .expect_err("Must fail: abstract class extends a concrete class");
+
+    db.create(
+        Id::random(),
+        map! {"factor/type": shape.clone(), "test/shapeName": "a"},
     )
     .await
-    .unwrap();
+    .expect_err("Must fail: abstract classes can't be instantiated");
 
-    f.migrate(
-        Migration::new().action(SchemaAction::EntityAttributeChangeCardinality(
-            EntityAttributeChangeCardinality {
-                entity_type: "test/MutableEntity".into(),
-                attribute: "test/tochange".into(),
-                new_cardinality: schema::Cardinality::Optional,
-            },
-        )),
+    let circle_id = Id::random();
+    db.create(
+        circle_id,
+        map! {"factor/type": circle.clone(), "test/shapeName": "c"},
     )
     .await
     .unwrap();
-}
-
-async fn test_query_entity_select_ident(db: &Db) {
-    let id = Id::random();
+    let square_id = Id::random();
     db.create(
-        id,
-        map! {
-            "factor/title": "hello",
-            "factor/ident": "hello-ident",
-        },
+        square_id,
+        map! {"factor/type": square.clone(), "test/shapeName": "s"},
     )
     .await
     .unwrap();
 
     let page = db
-        .select(
-            Select::new().with_filter(Expr::eq(AttrId::expr(), Expr::Ident("hello-ident".into()))),
-        )
-        .await
-        .unwrap();
-
-    assert_eq!(page.items.len(), 1);
-    assert_eq!(page.items[0].data.get_id().unwrap(), id);
-}
-
-async fn test_query_entity_is_type_nested(db: &Db) {
-    let id1 = Id::random();
-    db.create(id1, map! {"factor/type": ENTITY_FILE})
-        .await
-        .unwrap();
-
-    let id2 = Id::random();
-    db.create(id2, map! {"factor/type": ENTITY_IMAGE})
-        .await
-        .unwrap();
-
-    let id3 = Id::random();
-    db.create(id3, map! {"factor/type": ENTITY_IMAGE_JPEG})
-        .await
-        .unwrap();
-
-    let page = db
-        .select(Select::new().with_filter(Expr::InheritsEntityType(ENTITY_FILE.to_string())))
+        .select(Select::new().with_filter(Expr::InheritsEntityType(shape.clone())))
         .await
         .unwrap();
-
-    assert_eq!(page.items.len(), 3);
+    let mut ids = page
+        .items
+        .iter()
+        .map(|item| item.data.get_id().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    let mut expected = vec![circle_id, square_id];
+    expected.sort();
+    assert_eq!(ids, expected);
 }
 
 async fn test_merge_list_attr(db: &Db) {
@@ -699,6 +2279,53 @@ async fn test_merge_list_attr(db: &Db) {
     assert_eq!(values, &v);
 }
 
+async fn test_merge_updates_indexes(db: &Db) {
+    db.migrate(
+        Migration::new()
+            .attr_create(Attribute::new("test/merge_unique", ValueType::String).with_unique(true)),
+    )
+    .await
+    .unwrap();
+
+    let id = Id::random();
+    db.create(
+        id,
+        map! {
+            "factor/type": ENTITY_COMMENT,
+            "test/merge_unique": "a",
+        },
+    )
+    .await
+    .unwrap();
+
+    // Merging other attributes keeps the index entry.
+    db.merge(id, map! { "factor/title": "hello" })
+        .await
+        .unwrap();
+    let err = db
+        .create(Id::random(), map! { "test/merge_unique": "a" })
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+
+    // Merging the indexed attribute replaces the index entry.
+    db.merge(id, map! { "test/merge_unique": "b" })
+        .await
+        .unwrap();
+    db.create(Id::random(), map! { "test/merge_unique": "a" })
+        .await
+        .unwrap();
+    let err = db
+        .create(Id::random(), map! { "test/merge_unique": "b" })
+        .await
+        .unwrap_err();
+    assert!(err.is::<UniqueConstraintViolation>());
+
+    let data = db.entity(id).await.unwrap();
+    assert_eq!(data["factor/title"], Value::from("hello"));
+    assert_eq!(data["test/merge_unique"], Value::from("b"));
+}
+
 async fn test_patch(db: &Db) {
     let id = Id::random();
     db.create(
@@ -1425,6 +3052,7 @@ async fn test_select_delete(db: &Db) {
         filter: Expr::lt(Expr::attr_ident("test/int"), 6),
         variables: Default::default(),
         action: query::mutate::MutateSelectAction::Delete,
+        guard: None,
     }))
     .await
     .unwrap();
@@ -1537,6 +3165,190 @@ async fn test_reference_validation(db: &Db) {
     assert_eq!(err.ident.as_id().unwrap(), Id::nil());
 }
 
+async fn test_reference_ident_resolution(db: &Db) {
+    let attr_id = db
+        .schema()
+        .await
+        .unwrap()
+        .attr_by_ident(ATTR_REF)
+        .unwrap()
+        .id;
+
+    let id = Id::random();
+    db.create(
+        id,
+        map! {
+            ATTR_REF: ATTR_REF,
+        },
+    )
+    .await
+    .unwrap();
+    let entity = db.entity(id).await.unwrap();
+    assert_eq!(entity.get(ATTR_REF), Some(&Value::Id(attr_id)));
+
+    // Idents and aliases of regular entities.
+    let target = Id::random();
+    db.create(
+        target,
+        map! {
+            "factor/ident": "test/ref_target",
+            "factor/aliases": vec!["test/ref_target_alias"],
+        },
+    )
+    .await
+    .unwrap();
+    for name in ["test/ref_target", "test/ref_target_alias"] {
+        let id = Id::random();
+        db.create(id, map! { ATTR_REF: name }).await.unwrap();
+        let entity = db.entity(id).await.unwrap();
+        assert_eq!(entity.get(ATTR_REF), Some(&Value::Id(target)));
+    }
+
+    db.merge(id, map! { ATTR_REF: "test/ref_target" })
+        .await
+        .unwrap();
+    let entity = db.entity(id).await.unwrap();
+    assert_eq!(entity.get(ATTR_REF), Some(&Value::Id(target)));
+
+    // Entities created earlier in the same batch.
+    let batch_target = Id::random();
+    let batch_ref = Id::random();
+    db.batch(
+        Batch::new()
+            .and_create(Create {
+                id: batch_target,
+                data: map! { "factor/ident": "test/ref_batch_target" },
+            })
+            .and_create(Create {
+                id: batch_ref,
+                data: map! { ATTR_REF: "test/ref_batch_target" },
+            }),
+    )
+    .await
+    .unwrap();
+    let entity = db.entity(batch_ref).await.unwrap();
+    assert_eq!(entity.get(ATTR_REF), Some(&Value::Id(batch_target)));
+
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                ATTR_REF: "test/does_not_exist",
+            },
+        )
+        .await;
+    assert!(err.is_err());
+}
+
+async fn test_nested_object_list_validation(db: &Db) {
+    let waypoint = |label: Value, target: Value| {
+        Value::Map(
+            ValueMap::new()
+                .with_insert("label", label)
+                .with_insert("target", target),
+        )
+    };
+    let object = |fields: Vec<(&str, ValueType)>| {
+        ValueType::Object(ObjectType {
+            name: None,
+            fields: fields
+                .into_iter()
+                .map(|(name, value_type)| ObjectField {
+                    name: name.to_string(),
+                    value_type,
+                })
+                .collect(),
+        })
+    };
+
+    // Duplicate field names are rejected.
+    db.migrate(Migration::new().attr_create(Attribute::new(
+        "test/waypoints_invalid",
+        ValueType::new_list(object(vec![
+            ("label", ValueType::String),
+            ("label", ValueType::Int),
+        ])),
+    )))
+    .await
+    .unwrap_err();
+
+    db.migrate(Migration::new().attr_create(Attribute::new(
+        "test/waypoints",
+        ValueType::new_list(object(vec![
+            ("label", ValueType::String),
+            ("target", ValueType::Ref),
+        ])),
+    )))
+    .await
+    .unwrap();
+
+    let target = Id::random();
+    db.create(target, map! {}).await.unwrap();
+
+    let id = Id::random();
+    db.create(
+        id,
+        map! {
+            "test/waypoints": Value::List(vec![
+                waypoint("a".into(), target.into()),
+                waypoint("b".into(), target.into()),
+            ]),
+        },
+    )
+    .await
+    .unwrap();
+
+    // Nested references are validated.
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "test/waypoints": Value::List(vec![
+                    waypoint("a".into(), Id::nil().into()),
+                ]),
+            },
+        )
+        .await
+        .unwrap_err()
+        .downcast::<EntityNotFound>()
+        .unwrap();
+    assert_eq!(err.ident.as_id().unwrap(), Id::nil());
+
+    // Errors report the path of the invalid element.
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "test/waypoints": Value::List(vec![
+                    waypoint("a".into(), target.into()),
+                    waypoint(Value::List(vec![]), target.into()),
+                ]),
+            },
+        )
+        .await
+        .unwrap_err();
+    let err = err.downcast_ref::<ValueCoercionError>().unwrap();
+    assert!(err.to_string().contains("at /1/label"));
+}
+
+async fn test_typed_errors(db: &Db) {
+    let id = Id::random();
+    match db.entity(id).await.unwrap_err() {
+        Error::NotFound(err) => assert_eq!(err.ident, id.into()),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+
+    db.create(id, map! {}).await.unwrap();
+    let err = db.create(id, map! {}).await.unwrap_err();
+    assert!(matches!(err, Error::Conflict(err) if err.id == id));
+
+    let err = db
+        .create(Id::random(), map! { "test/int": "x" })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Coercion(_)));
+}
+
 async fn test_reference_validation_constrained_type(db: &Db) {
     let id1 = Id::random();
     db.create(
@@ -1571,8 +3383,7 @@ async fn test_reference_validation_constrained_type(db: &Db) {
     let id3 = Id::random();
     db.create(id3, map! {}).await.unwrap();
 
-    // TODO: test error details
-    let _err = db
+    let err = db
         .create(
             Id::random(),
             map! {
@@ -1581,9 +3392,151 @@ async fn test_reference_validation_constrained_type(db: &Db) {
         )
         .await
         .err()
+        .unwrap();
+    assert_eq!(err.attribute(), Some(ATTR_REF_IMAGE));
+    let err = err.downcast::<ReferenceConstraintViolation>().unwrap();
+    assert_eq!(err.attribute, ATTR_REF_IMAGE);
+    assert_eq!(err.actual_type, None);
+}
+
+async fn test_class_attribute_value_constraints(db: &Db) {
+    let ty = "test/Constrained";
+    db.migrate(Migration::new().entity_create(Class {
+        id: Id::nil(),
+        ident: ty.into(),
+        title: None,
+        description: None,
+        attributes: vec![
+            ClassAttribute::new_required("test/text")
+                .with_constraint(ValueConstraint::MinLength(2))
+                .with_constraint(ValueConstraint::Regex("^[a-z]+$".into())),
+            ClassAttribute::new_optional("test/int")
+                .with_constraint(ValueConstraint::Min(Value::Int(0)))
+                .with_constraint(ValueConstraint::Max(Value::Int(10))),
+        ],
+        extends: vec![],
+        strict: false,
+        id_strategy: schema::IdStrategy::Random,
+        max_entities: None,
+        computed_attributes: Vec::new(),
+        mixins: Vec::new(),
+        is_abstract: false,
+    }))
+    .await
+    .unwrap();
+
+    db.create(
+        Id::random(),
+        map! {
+            "factor/type": ty,
+            "test/text": "ab",
+            "test/int": 10,
+        },
+    )
+    .await
+    .unwrap();
+
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "factor/type": ty,
+                "test/text": "a",
+            },
+        )
+        .await
+        .err()
+        .unwrap()
+        .downcast::<ValueConstraintViolation>()
+        .unwrap();
+    assert_eq!(err.attribute, "test/text");
+    assert_eq!(err.constraint, ValueConstraint::MinLength(2));
+
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "factor/type": ty,
+                "test/text": "AB",
+            },
+        )
+        .await
+        .err()
+        .unwrap()
+        .downcast::<ValueConstraintViolation>()
+        .unwrap();
+    assert_eq!(err.constraint, ValueConstraint::Regex("^[a-z]+$".into()));
+
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "factor/type": ty,
+                "test/text": "ab",
+                "test/int": 11,
+            },
+        )
+        .await
+        .err()
+        .unwrap()
+        .downcast::<ValueConstraintViolation>()
+        .unwrap();
+    assert_eq!(err.constraint, ValueConstraint::Max(Value::Int(10)));
+
+    // Child classes inherit the constraints.
+    let child = "test/ConstrainedChild";
+    db.migrate(Migration::new().entity_create(Class {
+        id: Id::nil(),
+        ident: child.into(),
+        title: None,
+        description: None,
+        attributes: vec![],
+        extends: vec![ty.into()],
+        strict: false,
+        id_strategy: schema::IdStrategy::Random,
+        max_entities: None,
+        computed_attributes: Vec::new(),
+        mixins: Vec::new(),
+        is_abstract: false,
+    }))
+    .await
+    .unwrap();
+
+    let err = db
+        .create(
+            Id::random(),
+            map! {
+                "factor/type": child,
+                "test/text": "a",
+            },
+        )
+        .await
+        .err()
         .unwrap()
-        .downcast::<ReferenceConstraintViolation>()
+        .downcast::<ValueConstraintViolation>()
         .unwrap();
+    assert_eq!(err.constraint, ValueConstraint::MinLength(2));
+
+    // Removing the attribute from the parent also removes it from the child.
+    db.migrate(Migration::new().action(SchemaAction::EntityAttributeRemove(
+        EntityAttributeRemove {
+            entity_type: ty.into(),
+            attribute: "test/text".into(),
+            delete_values: false,
+        },
+    )))
+    .await
+    .unwrap();
+
+    db.create(
+        Id::random(),
+        map! {
+            "factor/type": child,
+            "test/text": "a",
+        },
+    )
+    .await
+    .unwrap();
 }
 
 async fn test_attr_type_list(db: &Db) {
@@ -1596,6 +3549,7 @@ async fn test_attr_type_list(db: &Db) {
         unique: false,
         index: false,
         strict: false,
+        collation: schema::Collation::Binary,
     }))
     .await
     .unwrap();
@@ -1630,6 +3584,7 @@ async fn test_convert_attr_to_list(db: &Db) {
         unique: false,
         index: false,
         strict: false,
+        collation: schema::Collation::Binary,
     }))
     .await
     .unwrap();
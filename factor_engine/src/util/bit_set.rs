@@ -0,0 +1,49 @@
+/// A set of small integers, stored as a bitmap.
+///
+/// Memory usage grows with the largest value, so this is only suitable for
+/// dense values like local ids.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self { words: Vec::new() }
+    }
+
+    /// Add a value.
+    ///
+    /// Returns false if the value was already present.
+    pub fn insert(&mut self, value: usize) -> bool {
+        let (word, bit) = (value / 64, value % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let mask = 1u64 << bit;
+        let is_new = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        is_new
+    }
+
+    #[inline]
+    pub fn contains(&self, value: usize) -> bool {
+        self.words
+            .get(value / 64)
+            .map(|word| word & (1u64 << (value % 64)) != 0)
+            .unwrap_or(false)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// Iterate over the values in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(index, word)| {
+            (0..64)
+                .filter(move |bit| word & (1u64 << bit) != 0)
+                .map(move |bit| index * 64 + bit)
+        })
+    }
+}
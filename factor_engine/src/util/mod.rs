@@ -1,4 +1,7 @@
 pub mod stable_map;
 
+mod bit_set;
+pub use bit_set::BitSet;
+
 mod vec_set;
 pub use vec_set::VecSet;
@@ -72,7 +72,7 @@ where
 
     /// Iterate over the entries.
     #[inline]
-    pub fn iter(&self) -> std::slice::Iter<V> {
+    pub fn iter(&self) -> std::slice::Iter<'_, V> {
         self.values.iter()
     }
 
@@ -133,11 +133,11 @@ where
         self.0.get_mut(key)
     }
 
-    /* /// Iterate over the entries.
+    /// Iterate over the entries.
     #[inline]
     pub fn iter(&self) -> std::slice::Iter<V> {
         self.0.iter()
-    } */
+    }
 
     /* /// Iterate over mutable entries.
     #[inline]
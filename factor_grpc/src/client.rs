@@ -0,0 +1,168 @@
+use factor_core::{
+    data::{DataMap, IdOrIdent},
+    db::{Db, DbClient, DbFuture},
+    error::TransientError,
+    query::{
+        migrate::Migration,
+        mutate::Batch,
+        select::{Item, Page, Select},
+    },
+    schema::DbSchema,
+};
+use futures::FutureExt;
+use tonic::{transport::Channel, Code, Status};
+
+use crate::{
+    convert,
+    proto::{self, factor_db_client::FactorDbClient},
+};
+
+/// Connect to a remote database served over gRPC.
+///
+/// See [`GrpcDbClient::new`].
+pub fn connect(url: &str) -> Result<Db, anyhow::Error> {
+    GrpcDbClient::new(url).map(Db::new)
+}
+
+/// A [`DbClient`] for a database served by [`crate::serve`].
+#[derive(Clone)]
+pub struct GrpcDbClient {
+    client: FactorDbClient<Channel>,
+}
+
+impl GrpcDbClient {
+    /// Create a client for the server at `url`, eg `http://127.0.0.1:7701`.
+    ///
+    /// The connection is established lazily on the first request, so this
+    /// must be called within a tokio runtime.
+    pub fn new(url: &str) -> Result<Self, anyhow::Error> {
+        let channel = tonic::transport::Endpoint::from_shared(url.to_string())?.connect_lazy();
+        Ok(Self {
+            client: FactorDbClient::new(channel),
+        })
+    }
+
+    fn client(&self) -> FactorDbClient<Channel> {
+        self.client.clone()
+    }
+}
+
+fn error(status: Status) -> anyhow::Error {
+    match status.code() {
+        Code::Unavailable => TransientError::Unavailable(status.message().to_string()).into(),
+        code => anyhow::anyhow!(
+            "Request failed with status {:?}: {}",
+            code,
+            status.message()
+        ),
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(
+    res: tonic::Response<proto::Json>,
+) -> Result<T, anyhow::Error> {
+    Ok(serde_json::from_str(&res.into_inner().json)?)
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<proto::Json, anyhow::Error> {
+    Ok(proto::Json {
+        json: serde_json::to_string(value)?,
+    })
+}
+
+impl DbClient for GrpcDbClient {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn schema(&self) -> DbFuture<'_, DbSchema> {
+        async move {
+            let res = self.client().schema(proto::Empty {}).await.map_err(error)?;
+            from_json(res)
+        }
+        .boxed()
+    }
+
+    fn entity(&self, id: IdOrIdent) -> DbFuture<'_, Option<DataMap>> {
+        async move {
+            let req = proto::EntityRequest { id: id.to_string() };
+            let res = self.client().entity(req).await.map_err(error)?;
+            res.into_inner()
+                .entity
+                .map(convert::entity_from_proto)
+                .transpose()
+        }
+        .boxed()
+    }
+
+    fn select(&self, query: Select) -> DbFuture<'_, Page<Item>> {
+        async move {
+            let res = self
+                .client()
+                .select(to_json(&query)?)
+                .await
+                .map_err(error)?;
+            from_json(res)
+        }
+        .boxed()
+    }
+
+    fn select_map(&self, query: Select) -> DbFuture<'_, Vec<DataMap>> {
+        async move {
+            let res = self
+                .client()
+                .select_map(to_json(&query)?)
+                .await
+                .map_err(error)?;
+            res.into_inner()
+                .entities
+                .into_iter()
+                .map(convert::entity_from_proto)
+                .collect()
+        }
+        .boxed()
+    }
+
+    fn batch(&self, batch: Batch) -> DbFuture<'_, ()> {
+        async move {
+            let batch = convert::batch_to_proto(batch)?;
+            self.client().batch(batch).await.map_err(error)?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn migrate(&self, migration: Migration) -> DbFuture<'_, ()> {
+        async move {
+            self.client()
+                .migrate(to_json(&migration)?)
+                .await
+                .map_err(error)?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn migrations(&self) -> DbFuture<'_, Vec<Migration>> {
+        async move {
+            let res = self
+                .client()
+                .migrations(proto::Empty {})
+                .await
+                .map_err(error)?;
+            from_json(res)
+        }
+        .boxed()
+    }
+
+    fn storage_usage(&self) -> DbFuture<'_, Option<u64>> {
+        futures::future::ready(Ok(None)).boxed()
+    }
+
+    fn purge_all_data(&self) -> DbFuture<'_, ()> {
+        futures::future::ready(Err(anyhow::anyhow!(
+            "purge_all_data is not supported for remote databases"
+        )))
+        .boxed()
+    }
+}
@@ -0,0 +1,232 @@
+//! Conversions between factordb types and protobuf messages.
+
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use factor_core::{
+    data::{DataMap, Id, Value, ValueMap},
+    query::mutate::{Batch, Create, Delete, EntityPatch, Guard, Merge, Mutate, Replace},
+};
+
+use crate::proto::{self, mutate::Action, value::Kind};
+
+pub fn value_to_proto(value: Value) -> proto::Value {
+    let kind = match value {
+        Value::Unit => Kind::Unit(proto::Unit {}),
+        Value::Bool(v) => Kind::Bool(v),
+        Value::UInt(v) => Kind::Uint(v),
+        Value::Int(v) => Kind::Int(v),
+        Value::Float(v) => Kind::Float(v.0),
+        Value::String(v) => Kind::String(v),
+        Value::Bytes(v) => Kind::Bytes(v),
+        Value::List(items) => Kind::List(proto::ValueList {
+            items: items.into_iter().map(value_to_proto).collect(),
+        }),
+        Value::Map(map) => Kind::Map(proto::ValueMap {
+            entries: map
+                .0
+                .into_iter()
+                .map(|(key, value)| proto::MapEntry {
+                    key: Some(value_to_proto(key)),
+                    value: Some(value_to_proto(value)),
+                })
+                .collect(),
+        }),
+        Value::Id(id) => Kind::Id(id.to_string()),
+    };
+    proto::Value { kind: Some(kind) }
+}
+
+/// Values without a kind are converted to [`Value::Unit`].
+pub fn value_from_proto(value: proto::Value) -> Result<Value, anyhow::Error> {
+    let value = match value.kind {
+        None | Some(Kind::Unit(_)) => Value::Unit,
+        Some(Kind::Bool(v)) => Value::Bool(v),
+        Some(Kind::Uint(v)) => Value::UInt(v),
+        Some(Kind::Int(v)) => Value::Int(v),
+        Some(Kind::Float(v)) => Value::from(v),
+        Some(Kind::String(v)) => Value::String(v),
+        Some(Kind::Bytes(v)) => Value::Bytes(v),
+        Some(Kind::List(list)) => Value::List(
+            list.items
+                .into_iter()
+                .map(value_from_proto)
+                .collect::<Result<_, _>>()?,
+        ),
+        Some(Kind::Map(map)) => {
+            let entries = map
+                .entries
+                .into_iter()
+                .map(|entry| {
+                    let key = value_from_proto(entry.key.unwrap_or_default())?;
+                    let value = value_from_proto(entry.value.unwrap_or_default())?;
+                    Ok((key, value))
+                })
+                .collect::<Result<BTreeMap<_, _>, anyhow::Error>>()?;
+            Value::Map(ValueMap(entries))
+        }
+        Some(Kind::Id(id)) => Value::Id(parse_id(&id)?),
+    };
+    Ok(value)
+}
+
+pub fn entity_to_proto(data: DataMap) -> proto::Entity {
+    proto::Entity {
+        attributes: data
+            .0
+            .into_iter()
+            .map(|(key, value)| (key, value_to_proto(value)))
+            .collect(),
+    }
+}
+
+pub fn entity_from_proto(entity: proto::Entity) -> Result<DataMap, anyhow::Error> {
+    let attributes = entity
+        .attributes
+        .into_iter()
+        .map(|(key, value)| Ok((key, value_from_proto(value)?)))
+        .collect::<Result<BTreeMap<_, _>, anyhow::Error>>()?;
+    Ok(ValueMap(attributes))
+}
+
+fn parse_id(id: &str) -> Result<Id, anyhow::Error> {
+    id.parse().with_context(|| format!("Invalid id '{}'", id))
+}
+
+fn entity_data(id: Id, data: DataMap) -> proto::EntityData {
+    proto::EntityData {
+        id: id.to_string(),
+        data: Some(entity_to_proto(data)),
+    }
+}
+
+fn entity_data_from_proto(data: proto::EntityData) -> Result<(Id, DataMap), anyhow::Error> {
+    let id = parse_id(&data.id)?;
+    let data = entity_from_proto(data.data.unwrap_or_default())?;
+    Ok((id, data))
+}
+
+pub fn batch_to_proto(batch: Batch) -> Result<proto::Batch, anyhow::Error> {
+    let actions = batch
+        .actions
+        .into_iter()
+        .map(|action| {
+            let action = match action {
+                Mutate::Create(v) => Action::Create(entity_data(v.id, v.data)),
+                Mutate::Replace(v) => Action::Replace(entity_data(v.id, v.data)),
+                Mutate::Merge(v) => Action::Merge(proto::MergeData {
+                    id: v.id.to_string(),
+                    data: Some(entity_to_proto(v.data)),
+                    clock: v.clock.map(|c| c.to_string()).unwrap_or_default(),
+                }),
+                Mutate::Patch(v) => Action::Patch(proto::EntityPatch {
+                    id: v.id.to_string(),
+                    patch_json: serde_json::to_string(&v.patch)?,
+                }),
+                Mutate::Delete(v) => Action::Delete(proto::Delete {
+                    id: v.id.to_string(),
+                }),
+                Mutate::Select(v) => Action::SelectJson(serde_json::to_string(&v)?),
+                Mutate::Savepoint => Action::Savepoint(proto::Empty {}),
+                Mutate::Guard(v) => Action::Guard(proto::Guard {
+                    id: v.id.to_string(),
+                    filter_json: serde_json::to_string(&v.filter)?,
+                }),
+            };
+            Ok(proto::Mutate {
+                action: Some(action),
+            })
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+    Ok(proto::Batch { actions })
+}
+
+pub fn batch_from_proto(batch: proto::Batch) -> Result<Batch, anyhow::Error> {
+    let actions = batch
+        .actions
+        .into_iter()
+        .map(|action| {
+            let action = match action.action.context("Missing mutate action")? {
+                Action::Create(data) => {
+                    let (id, data) = entity_data_from_proto(data)?;
+                    Mutate::Create(Create { id, data })
+                }
+                Action::Replace(data) => {
+                    let (id, data) = entity_data_from_proto(data)?;
+                    Mutate::Replace(Replace { id, data })
+                }
+                Action::Merge(merge) => {
+                    let id = parse_id(&merge.id)?;
+                    let data = entity_from_proto(merge.data.unwrap_or_default())?;
+                    let mut merge_action = Merge::new(id, data);
+                    if !merge.clock.is_empty() {
+                        merge_action = merge_action.with_clock(merge.clock.parse()?);
+                    }
+                    Mutate::Merge(merge_action)
+                }
+                Action::Patch(patch) => Mutate::Patch(EntityPatch {
+                    id: parse_id(&patch.id)?,
+                    patch: serde_json::from_str(&patch.patch_json).context("Invalid patch")?,
+                }),
+                Action::Delete(delete) => Mutate::Delete(Delete {
+                    id: parse_id(&delete.id)?,
+                }),
+                Action::SelectJson(json) => {
+                    Mutate::Select(serde_json::from_str(&json).context("Invalid select")?)
+                }
+                Action::Savepoint(_) => Mutate::Savepoint,
+                Action::Guard(guard) => Mutate::Guard(Guard {
+                    id: parse_id(&guard.id)?,
+                    filter: serde_json::from_str(&guard.filter_json).context("Invalid filter")?,
+                }),
+            };
+            Ok(action)
+        })
+        .collect::<Result<_, anyhow::Error>>()?;
+    Ok(Batch { actions })
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::{patch::Patch, HlcTimestamp},
+        map,
+        query::expr::Expr,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_batch_proto_roundtrip() {
+        let id = Id::random();
+        let mut nested = BTreeMap::new();
+        nested.insert(Value::from("k"), Value::from(vec![1u64, 2]));
+        let data = map! {
+            "factor/title": "a",
+            "test/float": 1.5,
+            "test/int": -3,
+            "test/ref": id,
+            "test/bytes": Value::Bytes(vec![1, 2]),
+            "test/map": Value::Map(ValueMap(nested)),
+            "test/unit": Value::Unit,
+        };
+        let batch = Batch::new()
+            .and_create(Create {
+                id,
+                data: data.clone(),
+            })
+            .and_merge(Merge::new(id, data).with_clock(HlcTimestamp::new(1, 2, 3)))
+            .and_patch(EntityPatch {
+                id,
+                patch: Patch::new().remove("factor/title"),
+            })
+            .and_guard(Guard {
+                id,
+                filter: Expr::eq(Expr::attr_ident("factor/title"), "a"),
+            })
+            .and_delete(Delete { id });
+
+        let converted = batch_from_proto(batch_to_proto(batch.clone()).unwrap()).unwrap();
+        assert_eq!(converted, batch);
+    }
+}
@@ -0,0 +1,66 @@
+//! gRPC server and client for factordb.
+//!
+//! The service is defined in `proto/factordb.proto`.
+//! Use [`serve`] to expose a [`Db`](factor_core::db::Db), and [`connect`] to
+//! use a remote database with the regular [`Db`](factor_core::db::Db) API.
+//!
+//! Building requires `protoc` to be installed.
+
+/// Generated protobuf messages, server and client.
+pub mod proto {
+    tonic::include_proto!("factordb");
+}
+
+pub mod convert;
+
+mod client;
+pub use self::client::{connect, GrpcDbClient};
+
+mod server;
+pub use self::server::{serve, FactorDbService};
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{data::Id, map, query::select::Select};
+    use factor_engine::{backend::memory::MemoryDb, Engine};
+
+    #[tokio::test]
+    async fn test_grpc_server_with_client() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(
+                    super::FactorDbService::new(Engine::new(MemoryDb::new()).into_client())
+                        .into_server(),
+                )
+                .serve_with_incoming_shutdown(
+                    tokio_stream::wrappers::TcpListenerStream::new(listener),
+                    async move {
+                        stopped.await.ok();
+                    },
+                ),
+        );
+
+        let db = super::connect(&url).unwrap();
+        let id = Id::random();
+        // Retry until the server accepts connections.
+        let mut attempts = 0;
+        while let Err(err) = db.create(id, map! { "factor/title": "a" }).await {
+            attempts += 1;
+            assert!(attempts < 50, "{}", err);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data.get("factor/title"), Some(&"a".into()));
+        assert!(db.entity(Id::random()).await.is_err());
+        assert_eq!(db.select_map(Select::new()).await.unwrap().len(), 1);
+        assert_eq!(db.select(Select::new()).await.unwrap().items.len(), 1);
+        db.schema().await.unwrap();
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use std::net::SocketAddr;
+
+use factor_core::{
+    data::IdOrIdent,
+    db::Db,
+    error::Error,
+    query::{migrate::Migration, select::Select},
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    convert,
+    proto::{self, factor_db_server::FactorDbServer},
+};
+
+/// gRPC service that serves a [`Db`].
+#[derive(Clone)]
+pub struct FactorDbService {
+    db: Db,
+}
+
+impl FactorDbService {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    pub fn into_server(self) -> FactorDbServer<Self> {
+        FactorDbServer::new(self)
+    }
+}
+
+/// Serve the database until the provided shutdown future resolves.
+pub async fn serve(
+    db: Db,
+    addr: SocketAddr,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    tonic::transport::Server::builder()
+        .add_service(FactorDbService::new(db).into_server())
+        .serve_with_shutdown(addr, shutdown)
+        .await?;
+    Ok(())
+}
+
+fn status(err: impl Into<Error>) -> Status {
+    let err = err.into();
+    let message = format!("{:#}", err);
+    match err {
+        Error::NotFound(_) => Status::not_found(message),
+        Error::ReadOnly(_) | Error::PermissionDenied(_) => Status::permission_denied(message),
+        Error::QuotaExceeded(_) => Status::resource_exhausted(message),
+        Error::Timeout | Error::Overloaded(_) | Error::Transient(_) => Status::unavailable(message),
+        _ => Status::invalid_argument(message),
+    }
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, Status> {
+    serde_json::from_str(json)
+        .map_err(|err| Status::invalid_argument(format!("Invalid request body: {}", err)))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<Response<proto::Json>, Status> {
+    let json = serde_json::to_string(value).map_err(|err| Status::internal(err.to_string()))?;
+    Ok(Response::new(proto::Json { json }))
+}
+
+#[tonic::async_trait]
+impl proto::factor_db_server::FactorDb for FactorDbService {
+    async fn schema(&self, _req: Request<proto::Empty>) -> Result<Response<proto::Json>, Status> {
+        to_json(&self.db.schema().await.map_err(status)?)
+    }
+
+    async fn entity(
+        &self,
+        req: Request<proto::EntityRequest>,
+    ) -> Result<Response<proto::EntityResponse>, Status> {
+        let id = IdOrIdent::new_string(req.into_inner().id);
+        let entity = self.db.client().entity(id).await.map_err(status)?;
+        Ok(Response::new(proto::EntityResponse {
+            entity: entity.map(convert::entity_to_proto),
+        }))
+    }
+
+    async fn select(&self, req: Request<proto::Json>) -> Result<Response<proto::Json>, Status> {
+        let query: Select = from_json(&req.into_inner().json)?;
+        to_json(&self.db.select(query).await.map_err(status)?)
+    }
+
+    async fn select_map(
+        &self,
+        req: Request<proto::Json>,
+    ) -> Result<Response<proto::EntityList>, Status> {
+        let query: Select = from_json(&req.into_inner().json)?;
+        let items = self.db.select_map(query).await.map_err(status)?;
+        Ok(Response::new(proto::EntityList {
+            entities: items.into_iter().map(convert::entity_to_proto).collect(),
+        }))
+    }
+
+    async fn batch(&self, req: Request<proto::Batch>) -> Result<Response<proto::Empty>, Status> {
+        let batch = convert::batch_from_proto(req.into_inner())
+            .map_err(|err| Status::invalid_argument(format!("{:#}", err)))?;
+        self.db.batch(batch).await.map_err(status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn migrate(&self, req: Request<proto::Json>) -> Result<Response<proto::Empty>, Status> {
+        let migration: Migration = from_json(&req.into_inner().json)?;
+        self.db.migrate(migration).await.map_err(status)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn migrations(
+        &self,
+        _req: Request<proto::Empty>,
+    ) -> Result<Response<proto::Json>, Status> {
+        to_json(&self.db.migrations().await.map_err(status)?)
+    }
+}
@@ -146,9 +146,27 @@ pub fn derive_attribute(tokens: TokenStream) -> TokenStream {
                     index: #index,
                     unique: #unique,
                     strict: #strict,
+                    collation: factdb::schema::Collation::Binary,
                 }
             }
         }
+
+        impl factdb::schema::SchemaItemMeta for #ident {
+            const STATIC_CHECK: factdb::schema::StaticItemCheck =
+                factdb::schema::StaticItemCheck::Attribute(factdb::schema::StaticAttributeCheck {
+                    ident: #full_name,
+                    value_type: <#type_ as factdb::ValueTypeDescriptor>::STATIC_TYPE,
+                    unique: #unique,
+                    index: #index,
+                    strict: #strict,
+                });
+
+            fn schema_item() -> factdb::schema::SchemaItem {
+                factdb::schema::SchemaItem::Attribute(
+                    <Self as factdb::AttributeMeta>::schema()
+                )
+            }
+        }
     };
     TokenStream::from(out)
 }
@@ -0,0 +1,238 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+
+struct CheckSchemaInput {
+    path: syn::LitStr,
+    items: Punctuated<syn::Type, syn::token::Comma>,
+}
+
+const USAGE: &str =
+    "Invalid macro input: expected factor_check_schema!(\"schema.json\", TypeA, TypeB, ...)";
+
+impl syn::parse::Parse for CheckSchemaInput {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let path: syn::LitStr = input.parse()?;
+        if input.is_empty() {
+            return Err(input.error(USAGE));
+        }
+        input.parse::<syn::token::Comma>()?;
+        let items = Punctuated::parse_terminated(input)?;
+
+        Ok(Self { path, items })
+    }
+}
+
+/// Build the compile time description of a value type in the schema file.
+///
+/// Returns `None` for types that can only be compared at runtime.
+/// See `factdb::data::value_type::StaticValueType`.
+fn static_value_type(value: &serde_json::Value) -> Option<(usize, String)> {
+    match value {
+        serde_json::Value::String(name) => Some((0, name.clone())),
+        serde_json::Value::Object(map) if map.len() == 1 => {
+            let (variant, inner) = map.iter().next()?;
+            match variant.as_str() {
+                "List" => static_value_type(inner).map(|(depth, name)| (depth + 1, name)),
+                "Ident" => Some((0, "Ident".to_string())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn json_str<'a>(value: &'a serde_json::Value, key: &str) -> Result<&'a str, String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("missing string field '{}'", key))
+}
+
+fn json_bool(value: &serde_json::Value, key: &str) -> bool {
+    value.get(key).and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+fn json_list<'a>(value: &'a serde_json::Value, key: &str) -> &'a [serde_json::Value] {
+    value
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+}
+
+/// Build `factdb::schema::StaticItemCheck` expressions for all attributes
+/// and classes of the schema file.
+fn expected_items(schema: &serde_json::Value) -> Result<Vec<proc_macro2::TokenStream>, String> {
+    let mut items = Vec::new();
+
+    for attr in json_list(schema, "attributes") {
+        let ident = json_str(attr, "factor/ident")?;
+        let value_type = match attr.get("factor/valueType").and_then(static_value_type) {
+            Some((list_depth, name)) => quote! {
+                Some(factdb::data::value_type::StaticValueType {
+                    list_depth: #list_depth,
+                    name: #name,
+                })
+            },
+            None => quote!(None),
+        };
+        let unique = json_bool(attr, "factor/unique");
+        let index = json_bool(attr, "factor/index");
+        let strict = json_bool(attr, "factor/isStrict");
+        items.push(quote! {
+            factdb::schema::StaticItemCheck::Attribute(factdb::schema::StaticAttributeCheck {
+                ident: #ident,
+                value_type: #value_type,
+                unique: #unique,
+                index: #index,
+                strict: #strict,
+            })
+        });
+    }
+
+    for class in json_list(schema, "classes") {
+        let ident = json_str(class, "factor/ident")?;
+        let attributes = json_list(class, "factor/entityAttributes")
+            .iter()
+            .map(|field| {
+                let attribute = json_str(field, "factor/attribute")?;
+                let required = json_bool(field, "factor/required");
+                Ok(quote!((#attribute, #required)))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let extends = json_list(class, "factor/extend")
+            .iter()
+            .map(|parent| {
+                parent
+                    .as_str()
+                    .ok_or_else(|| "invalid 'factor/extend' entry".to_string())
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let strict = json_bool(class, "factor/isStrict");
+        items.push(quote! {
+            factdb::schema::StaticItemCheck::Class(factdb::schema::StaticClassCheck {
+                ident: #ident,
+                attributes: &[ #( #attributes ),* ],
+                extends: &[ #( #extends ),* ],
+                strict: #strict,
+            })
+        });
+    }
+
+    Ok(items)
+}
+
+/// Build a const assertion that fails the build if a listed item does not
+/// match the schema file.
+fn static_assertion(item: &syn::Type, path: &str) -> proc_macro2::TokenStream {
+    // Const panics only accept plain string literals, which must not
+    // contain format placeholders.
+    let name = quote!(#item).to_string().replace('{', "").replace('}', "");
+    let path = path.replace('{', "").replace('}', "");
+    let msg = |problem: &str| format!("`{}` {} in the schema file '{}'", name, problem, path);
+    let missing = msg("is missing");
+    let value_type = msg("has a different value type");
+    let flags = msg("has different flags");
+    let attributes = msg("has different attributes");
+    let extends = msg("extends different classes");
+
+    quote! {
+        match factdb::schema::check_static_item(
+            EXPECTED,
+            &<#item as factdb::schema::SchemaItemMeta>::STATIC_CHECK,
+        ) {
+            factdb::schema::StaticCheckResult::Ok => {}
+            factdb::schema::StaticCheckResult::Missing => panic!(#missing),
+            factdb::schema::StaticCheckResult::ValueType => panic!(#value_type),
+            factdb::schema::StaticCheckResult::Flags => panic!(#flags),
+            factdb::schema::StaticCheckResult::Attributes => panic!(#attributes),
+            factdb::schema::StaticCheckResult::Extends => panic!(#extends),
+        }
+    }
+}
+
+/// Name of the generated test.
+///
+/// Derived from the schema path and the location of the invocation, so it is
+/// unique within a crate and stable across builds.
+fn test_name(path: &str) -> syn::Ident {
+    let call_site = proc_macro::Span::call_site();
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    call_site.file().hash(&mut hasher);
+    call_site.line().hash(&mut hasher);
+    call_site.column().hash(&mut hasher);
+    format_ident!("factor_check_schema_{:016x}", hasher.finish())
+}
+
+pub fn check_schema(tokens: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(tokens as CheckSchemaInput);
+    let test_name = test_name(&input.path.value());
+
+    // Load the schema file at compile time, so a missing or malformed file
+    // fails the build right away.
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let full_path = std::path::Path::new(&manifest_dir).join(input.path.value());
+    let contents = match std::fs::read_to_string(&full_path) {
+        Ok(c) => c,
+        Err(err) => {
+            let msg = format!(
+                "Could not read schema file '{}': {}",
+                full_path.display(),
+                err
+            );
+            return syn::Error::new(input.path.span(), msg)
+                .to_compile_error()
+                .into();
+        }
+    };
+    let expected = serde_json::from_str::<serde_json::Value>(&contents)
+        .map_err(|err| err.to_string())
+        .and_then(|schema| expected_items(&schema));
+    let expected = match expected {
+        Ok(items) => items,
+        Err(err) => {
+            let msg = format!("Invalid schema file '{}': {}", full_path.display(), err);
+            return syn::Error::new(input.path.span(), msg)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let assertions = input
+        .items
+        .iter()
+        .map(|item| static_assertion(item, &input.path.value()))
+        .collect::<Vec<_>>();
+    let full_path = full_path.to_string_lossy().to_string();
+    let items = input.items.iter();
+
+    // Idents, value types, flags and class attributes are compared at compile
+    // time, so a mismatch fails the build.
+    // The full definitions can only be built at runtime, so they are compared
+    // in a generated test.
+    TokenStream::from(quote! {
+        const _: () = {
+            // Referencing the file makes cargo rebuild when it changes.
+            const _: &str = include_str!(#full_path);
+            const EXPECTED: &[factdb::schema::StaticItemCheck] = &[ #( #expected ),* ];
+            #( #assertions )*
+        };
+
+        #[cfg(test)]
+        #[test]
+        fn #test_name() {
+            const SCHEMA_JSON: &str = include_str!(#full_path);
+            let actual = factdb::schema_bundle!( #( #items ),* );
+            if let Err(err) = factdb::schema::check_schema_json_compatibility(SCHEMA_JSON, &actual) {
+                panic!("{:#}", err);
+            }
+        }
+    })
+}
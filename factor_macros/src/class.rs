@@ -53,15 +53,63 @@ impl syn::parse::Parse for StructAttrs {
     }
 }
 
+/// A validation constraint specified on a field.
+enum FieldConstraint {
+    MinLength(u64),
+    MaxLength(u64),
+    Min(proc_macro2::TokenStream),
+    Max(proc_macro2::TokenStream),
+    Regex(String),
+}
+
+impl FieldConstraint {
+    fn to_tokens(&self) -> proc_macro2::TokenStream {
+        match self {
+            Self::MinLength(v) => quote!(factdb::ValueConstraint::MinLength(#v)),
+            Self::MaxLength(v) => quote!(factdb::ValueConstraint::MaxLength(#v)),
+            Self::Min(v) => quote!(factdb::ValueConstraint::Min(#v)),
+            Self::Max(v) => quote!(factdb::ValueConstraint::Max(#v)),
+            Self::Regex(v) => quote!(factdb::ValueConstraint::Regex(#v.to_string())),
+        }
+    }
+}
+
+/// Parse a (possibly negative) numeric literal into a `factdb::Value`
+/// expression.
+fn parse_number_value(input: syn::parse::ParseStream) -> syn::Result<proc_macro2::TokenStream> {
+    let negative = if input.peek(syn::token::Sub) {
+        input.parse::<syn::token::Sub>()?;
+        true
+    } else {
+        false
+    };
+
+    let lit: syn::Lit = input.parse()?;
+    match lit {
+        syn::Lit::Int(v) => {
+            let v = v.base10_parse::<i64>()?;
+            let v = if negative { -v } else { v };
+            Ok(quote!(factdb::Value::Int(#v)))
+        }
+        syn::Lit::Float(v) => {
+            let v = v.base10_parse::<f64>()?;
+            let v = if negative { -v } else { v };
+            Ok(quote!(factdb::Value::Float((#v).into())))
+        }
+        other => Err(syn::Error::new(other.span(), "expected a numeric literal")),
+    }
+}
+
 struct FieldAttrs {
     attribute: Option<syn::Path>,
     extend: bool,
     is_relation: bool,
     ignored: bool,
+    constraints: Vec<FieldConstraint>,
     // relation: Option<syn::Path>,
 }
 
-const FIELD_USAGE: &str = "Invalid #[factor()] macro key: expected #[factor(attr = Attribute)]";
+const FIELD_USAGE: &str = "Invalid #[factor()] macro key: expected #[factor(attr = Attribute [, min_len = N, max_len = N, min = N, max = N, regex = \"...\"])]";
 
 impl syn::parse::Parse for FieldAttrs {
     fn parse(outer: syn::parse::ParseStream) -> syn::Result<Self> {
@@ -73,6 +121,7 @@ impl syn::parse::Parse for FieldAttrs {
             extend: false,
             is_relation: false,
             ignored: false,
+            constraints: Vec::new(),
         };
         // let mut relation = None;
 
@@ -102,6 +151,31 @@ impl syn::parse::Parse for FieldAttrs {
                 "ignore" => {
                     attrs.ignored = true;
                 }
+                "min_len" => {
+                    let _eq: syn::token::Eq = input.parse()?;
+                    let v = input.parse::<syn::LitInt>()?.base10_parse::<u64>()?;
+                    attrs.constraints.push(FieldConstraint::MinLength(v));
+                }
+                "max_len" => {
+                    let _eq: syn::token::Eq = input.parse()?;
+                    let v = input.parse::<syn::LitInt>()?.base10_parse::<u64>()?;
+                    attrs.constraints.push(FieldConstraint::MaxLength(v));
+                }
+                "min" => {
+                    let _eq: syn::token::Eq = input.parse()?;
+                    let v = parse_number_value(&input)?;
+                    attrs.constraints.push(FieldConstraint::Min(v));
+                }
+                "max" => {
+                    let _eq: syn::token::Eq = input.parse()?;
+                    let v = parse_number_value(&input)?;
+                    attrs.constraints.push(FieldConstraint::Max(v));
+                }
+                "regex" => {
+                    let _eq: syn::token::Eq = input.parse()?;
+                    let s = input.parse::<syn::LitStr>()?;
+                    attrs.constraints.push(FieldConstraint::Regex(s.value()));
+                }
                 _other => Err(input.error(FIELD_USAGE))?,
             }
 
@@ -223,6 +297,9 @@ pub fn derive_class(tokens: TokenStream) -> TokenStream {
     let field_count = fields.named.len();
     let mut schema_attributes = Vec::with_capacity(field_count);
     let mut schema_extends: Vec<proc_macro2::TokenStream> = Vec::new();
+    // Attributes and parents for `SchemaItemMeta::STATIC_CHECK`.
+    let mut static_attributes = Vec::with_capacity(field_count);
+    let mut static_extends = Vec::<proc_macro2::TokenStream>::new();
 
     let mut serialize_fields = Vec::<proc_macro2::TokenStream>::new();
     // let mut deserialize_fields = Vec::<proc_macro2::TokenStream>::new();
@@ -256,6 +333,9 @@ pub fn derive_class(tokens: TokenStream) -> TokenStream {
             schema_extends.push(quote! {
                 <#field_ty as factdb::ClassMeta>::QUALIFIED_NAME.to_string(),
             });
+            static_extends.push(quote! {
+                <#field_ty as factdb::ClassMeta>::QUALIFIED_NAME,
+            });
         } else if field_attrs.is_relation {
             todo!()
             // if let Some(_inner_ty) = option_inner(&field.ty) {
@@ -287,13 +367,29 @@ pub fn derive_class(tokens: TokenStream) -> TokenStream {
                 _ => quote!(true),
             };
 
+            let constraints = field_attrs
+                .constraints
+                .iter()
+                .map(FieldConstraint::to_tokens)
+                .collect::<Vec<_>>();
+
             if *field_name == "id" {
+                if !constraints.is_empty() {
+                    panic!("#[derive(Entity)]: constraints are not supported on the id field");
+                }
                 have_id = true;
             } else {
+                let static_required = !is_option(&field.ty);
+                static_attributes.push(quote! {
+                    (<#prop as factdb::AttributeMeta>::QUALIFIED_NAME, #static_required),
+                });
                 schema_attributes.push(quote! {
                     factdb::ClassAttribute {
                         attribute: <#prop as factdb::AttributeMeta>::QUALIFIED_NAME.to_string(),
                         required: #required,
+                        constraints: vec![
+                            #( #constraints, )*
+                        ],
                     },
                 });
 
@@ -339,10 +435,35 @@ pub fn derive_class(tokens: TokenStream) -> TokenStream {
                         #( #schema_extends )*
                     ],
                     strict: false,
+                    id_strategy: factdb::schema::IdStrategy::Random,
+                    max_entities: None,
+                    computed_attributes: Vec::new(),
+                    mixins: Vec::new(),
+                    is_abstract: false,
                 }
             }
         }
 
+        impl factdb::schema::SchemaItemMeta for #struct_ident {
+            const STATIC_CHECK: factdb::schema::StaticItemCheck =
+                factdb::schema::StaticItemCheck::Class(factdb::schema::StaticClassCheck {
+                    ident: #full_name,
+                    attributes: &[
+                        #( #static_attributes )*
+                    ],
+                    extends: &[
+                        #( #static_extends )*
+                    ],
+                    strict: false,
+                });
+
+            fn schema_item() -> factdb::schema::SchemaItem {
+                factdb::schema::SchemaItem::Entity(
+                    <Self as factdb::ClassMeta>::schema()
+                )
+            }
+        }
+
         impl factdb::ClassContainer for #struct_ident {
             fn id(&self) -> factdb::Id {
                 *#id_accessor
@@ -3,6 +3,7 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 
 mod attribute;
+mod check_schema;
 mod class;
 
 /// Find an attribute with the format `#[factor(...)]`.
@@ -20,6 +21,24 @@ pub fn derive_class(tokens: TokenStream) -> TokenStream {
     class::derive_class(tokens)
 }
 
+/// Check derived attributes and classes against a committed schema file.
+///
+/// The file must contain a JSON serialized `DbSchema` and is resolved relative
+/// to the crate root.
+/// A missing or malformed file fails the build.
+/// Idents, value types, flags and class attributes of the listed items are
+/// checked at compile time, so a mismatch also fails the build.
+/// The full definitions are compared in a generated test that fails with a
+/// diff if they diverge from the file.
+///
+/// ```ignore
+/// factor_check_schema!("schema.json", AttrTitle, Todo, Project);
+/// ```
+#[proc_macro]
+pub fn factor_check_schema(tokens: TokenStream) -> TokenStream {
+    check_schema::check_schema(tokens)
+}
+
 // TODO: write an Object derive.
 
 // #[proc_macro_derive(Object, attributes(factor))]
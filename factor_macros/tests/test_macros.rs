@@ -1,5 +1,5 @@
-use factdb::{AttributeMeta, ClassAttribute, ClassMeta, Id, ValueType};
-use factor_macros::{Attribute, Class};
+use factdb::{AttributeMeta, ClassAttribute, ClassMeta, Id, Value, ValueConstraint, ValueType};
+use factor_macros::{factor_check_schema, Attribute, Class};
 
 use factor_core::schema::builtin::AttrDescription;
 
@@ -28,6 +28,21 @@ struct Entity1 {
     pub length: Vec<u64>,
 }
 
+#[derive(Attribute)]
+#[factor(namespace = "test")]
+struct AttrScore(i64);
+
+#[derive(Class, serde::Serialize, serde::Deserialize)]
+#[factor(namespace = "test")]
+struct Validated {
+    #[factor(attr = AttrId)]
+    pub id: Id,
+    #[factor(attr = AttrSomeTitle, min_len = 1, max_len = 100, regex = "^[a-z]+$")]
+    pub text: String,
+    #[factor(attr = AttrScore, min = -10, max = 100)]
+    pub score: i64,
+}
+
 #[derive(Class, serde::Serialize, serde::Deserialize)]
 #[factor(namespace = "test")]
 struct Child {
@@ -49,6 +64,7 @@ fn test_attr_derive() {
             title: None,
             index: false,
             strict: false,
+            collation: factdb::schema::Collation::Binary,
             unique: false,
             value_type: ValueType::String,
         },
@@ -68,18 +84,26 @@ fn test_entity_derive() {
                 ClassAttribute {
                     attribute: AttrSomeTitle::QUALIFIED_NAME.to_string(),
                     required: true,
+                    constraints: Vec::new(),
                 },
                 ClassAttribute {
                     attribute: AttrDescription::QUALIFIED_NAME.to_string(),
                     required: false,
+                    constraints: Vec::new(),
                 },
                 ClassAttribute {
                     attribute: AttrLength::QUALIFIED_NAME.to_string(),
                     required: true,
+                    constraints: Vec::new(),
                 },
             ],
             extends: Vec::new(),
             strict: false,
+            id_strategy: factdb::schema::IdStrategy::Random,
+            max_entities: None,
+            computed_attributes: Vec::new(),
+            mixins: Vec::new(),
+            is_abstract: false,
         },
         Entity1::schema(),
     );
@@ -88,6 +112,48 @@ fn test_entity_derive() {
     assert_eq!(schema.extends, vec![Entity1::QUALIFIED_NAME.to_string()]);
 }
 
+#[test]
+fn test_entity_derive_field_constraints() {
+    let schema = Validated::schema();
+    assert_eq!(
+        schema.attributes,
+        vec![
+            ClassAttribute {
+                attribute: AttrSomeTitle::QUALIFIED_NAME.to_string(),
+                required: true,
+                constraints: vec![
+                    ValueConstraint::MinLength(1),
+                    ValueConstraint::MaxLength(100),
+                    ValueConstraint::Regex("^[a-z]+$".to_string()),
+                ],
+            },
+            ClassAttribute {
+                attribute: AttrScore::QUALIFIED_NAME.to_string(),
+                required: true,
+                constraints: vec![
+                    ValueConstraint::Min(Value::Int(-10)),
+                    ValueConstraint::Max(Value::Int(100)),
+                ],
+            },
+        ]
+    );
+}
+
+factor_check_schema!("tests/schema.json", AttrSomeTitle, Entity1);
+
+#[test]
+fn test_schema_bundle() {
+    let schema = factdb::schema_bundle!(AttrSomeTitle, AttrLength, Entity1, Child);
+    assert_eq!(
+        schema.attributes,
+        vec![AttrSomeTitle::schema(), AttrLength::schema()]
+    );
+    assert_eq!(schema.classes, vec![Entity1::schema(), Child::schema()]);
+
+    let mig = factdb::Migration::new().upsert_schema(schema);
+    assert_eq!(mig.actions.len(), 4);
+}
+
 // #[test]
 // fn test_derive_entity_serialize() {
 //     let e = Child {
@@ -0,0 +1,267 @@
+//! Property based conformance tests for backends.
+//!
+//! Random schemas and operation sequences are executed against the memory
+//! backend, which serves as the oracle, and against the backend under test.
+//! All results must match.
+//! Failing sequences are shrunk to a minimal reproduction by proptest.
+//!
+//! See [`check_backend_conformance`].
+
+use factdb::{
+    data::{DataMap, Value, ValueType},
+    schema::Attribute,
+    AttrMapExt, Db, Expr, Id, Migration, Select,
+};
+use factor_engine::{backend::memory::MemoryDb, Engine};
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestCaseError, TestRunner},
+};
+
+/// Namespace of the generated attributes.
+const NS: &str = "prop";
+
+/// Number of distinct entity ids used by the generated operations.
+///
+/// Kept small, so operations frequently touch the same entities.
+pub(crate) const ENTITY_POOL: u8 = 6;
+
+/// A random schema with a sequence of operations.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub attributes: Vec<AttrSpec>,
+    pub ops: Vec<Op>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AttrSpec {
+    pub value_type: PropType,
+    pub unique: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PropType {
+    Int,
+    String,
+    Bool,
+}
+
+/// A generated operation.
+///
+/// Attributes are referenced by position and wrap around the number of
+/// attributes in the schema.
+/// Values are generated independently of the attribute type, so operations
+/// also cover coercion failures.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Create {
+        entity: u8,
+        values: Vec<(usize, Value)>,
+    },
+    Merge {
+        entity: u8,
+        values: Vec<(usize, Value)>,
+    },
+    Delete {
+        entity: u8,
+    },
+    SelectEq {
+        attr: usize,
+        value: Value,
+    },
+    SelectAll,
+}
+
+/// Result of an operation, compared between backends.
+///
+/// Errors are only compared by their presence, since messages differ
+/// between backends.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Ok,
+    Err,
+    Rows(Vec<DataMap>),
+}
+
+fn attr_name(index: usize) -> String {
+    format!("{}/attr{}", NS, index)
+}
+
+fn entity_id(entity: u8) -> Id {
+    Id::from_uuid(uuid::Uuid::from_u128(1 + u128::from(entity)))
+}
+
+fn prop_type() -> impl Strategy<Value = PropType> {
+    prop_oneof![
+        Just(PropType::Int),
+        Just(PropType::String),
+        Just(PropType::Bool),
+    ]
+}
+
+fn prop_value() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        (-3i64..3).prop_map(Value::from),
+        prop::sample::select(vec!["a", "b", "c"]).prop_map(Value::from),
+        any::<bool>().prop_map(Value::from),
+    ]
+}
+
+fn prop_values() -> impl Strategy<Value = Vec<(usize, Value)>> {
+    prop::collection::vec((0..4usize, prop_value()), 0..3)
+}
+
+fn prop_op() -> impl Strategy<Value = Op> {
+    let entity = 0..ENTITY_POOL;
+    prop_oneof![
+        (entity.clone(), prop_values()).prop_map(|(entity, values)| Op::Create { entity, values }),
+        (entity.clone(), prop_values()).prop_map(|(entity, values)| Op::Merge { entity, values }),
+        entity.prop_map(|entity| Op::Delete { entity }),
+        (0..4usize, prop_value()).prop_map(|(attr, value)| Op::SelectEq { attr, value }),
+        Just(Op::SelectAll),
+    ]
+}
+
+/// Strategy for random [`Scenario`]s.
+pub fn scenario() -> impl Strategy<Value = Scenario> {
+    let attribute = (prop_type(), prop::bool::weighted(0.3))
+        .prop_map(|(value_type, unique)| AttrSpec { value_type, unique });
+    (
+        prop::collection::vec(attribute, 1..4),
+        prop::collection::vec(prop_op(), 0..30),
+    )
+        .prop_map(|(attributes, ops)| Scenario { attributes, ops })
+}
+
+impl Scenario {
+    pub fn migration(&self) -> Migration {
+        self.attributes
+            .iter()
+            .enumerate()
+            .fold(Migration::new(), |mig, (index, spec)| {
+                let value_type = match spec.value_type {
+                    PropType::Int => ValueType::Int,
+                    PropType::String => ValueType::String,
+                    PropType::Bool => ValueType::Bool,
+                };
+                mig.attr_create(
+                    Attribute::new(attr_name(index), value_type).with_unique(spec.unique),
+                )
+            })
+    }
+
+    fn data(&self, values: &[(usize, Value)]) -> DataMap {
+        let mut data = DataMap::new();
+        for (attr, value) in values {
+            data.insert(attr_name(attr % self.attributes.len()), value.clone());
+        }
+        data
+    }
+
+    /// Execute a single operation.
+    pub async fn run_op(&self, db: &Db, op: &Op) -> Outcome {
+        let res = match op {
+            Op::Create { entity, values } => db.create(entity_id(*entity), self.data(values)).await,
+            Op::Merge { entity, values } => db.merge(entity_id(*entity), self.data(values)).await,
+            Op::Delete { entity } => db.delete(entity_id(*entity)).await,
+            Op::SelectEq { attr, value } => {
+                let attr = attr_name(attr % self.attributes.len());
+                let filter = Expr::eq(Expr::attr_ident(&attr), Expr::literal(value.clone()));
+                return Self::select(db, Select::new().with_filter(filter)).await;
+            }
+            Op::SelectAll => return Self::select(db, Select::new()).await,
+        };
+        match res {
+            Ok(()) => Outcome::Ok,
+            Err(_) => Outcome::Err,
+        }
+    }
+
+    /// Select entities of the entity pool, sorted by id.
+    async fn select(db: &Db, select: Select) -> Outcome {
+        let pool = (0..ENTITY_POOL).map(entity_id).collect::<Vec<_>>();
+        match db.select_map(select).await {
+            Ok(items) => {
+                let mut items = items
+                    .into_iter()
+                    .filter(|data| data.get_id().map_or(false, |id| pool.contains(&id)))
+                    .collect::<Vec<_>>();
+                items.sort_by_key(|data| data.get_id());
+                Outcome::Rows(items)
+            }
+            Err(_) => Outcome::Err,
+        }
+    }
+
+    /// Run the scenario against both databases and compare all results.
+    pub async fn check(&self, oracle: &Db, db: &Db) -> Result<(), String> {
+        let migration = self.migration();
+        oracle
+            .migrate(migration.clone())
+            .await
+            .map_err(|err| format!("oracle migration failed: {:#}", err))?;
+        db.migrate(migration)
+            .await
+            .map_err(|err| format!("migration failed: {:#}", err))?;
+
+        for (index, op) in self.ops.iter().enumerate() {
+            let expected = self.run_op(oracle, op).await;
+            let actual = self.run_op(db, op).await;
+            if expected != actual {
+                return Err(format!(
+                    "operation {} ({:?}) returned {:?}, expected {:?}",
+                    index, op, actual, expected
+                ));
+            }
+        }
+
+        let expected = Self::select(oracle, Select::new()).await;
+        let actual = Self::select(db, Select::new()).await;
+        if expected != actual {
+            return Err(format!(
+                "final state differs: {:?}, expected {:?}",
+                actual, expected
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Check a backend against the memory backend with random scenarios.
+///
+/// `new_db` must return a new, empty database for each scenario.
+///
+/// Panics with the minimal failing scenario if the results differ.
+pub fn check_backend_conformance<F>(config: Config, new_db: F)
+where
+    F: Fn() -> Db,
+{
+    let mut runner = TestRunner::new(config);
+    let res = runner.run(&scenario(), |scenario| {
+        futures::executor::block_on(async {
+            let oracle = Engine::new(MemoryDb::new()).into_client();
+            scenario
+                .check(&oracle, &new_db())
+                .await
+                .map_err(TestCaseError::fail)
+        })
+    });
+    if let Err(err) = res {
+        panic!("backend conformance failed: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_engine::backend::log::{store_memory::MemoryLogStore, LogDb};
+
+    use super::*;
+
+    #[test]
+    fn test_log_backend_conformance() {
+        check_backend_conformance(Config::with_cases(32), || {
+            let log = futures::executor::block_on(LogDb::open(MemoryLogStore::new())).unwrap();
+            Engine::new(log).into_client()
+        });
+    }
+}
@@ -1,6 +1,9 @@
+pub mod conformance;
+pub mod simulation;
+
 use factdb::{
     macros::{Attribute, Class},
-    AttributeMeta, ClassMeta, DataMap, Db, Expr, Id, Migration, Select,
+    AttributeMeta, ClassMeta, Db, Expr, Id, Migration, Select,
 };
 use factor_core::schema::builtin::{AttrDescription, AttrTitle};
 use serde::{Deserialize, Serialize};
@@ -47,12 +50,12 @@ impl Todo {
 pub async fn select_single_todo_with_title_eq(
     db: &Db,
     title: String,
-) -> Result<DataMap, anyhow::Error> {
+) -> Result<Option<Todo>, anyhow::Error> {
     let filter = Expr::eq(AttrTitle::expr(), title);
     let select = Select::new().with_filter(filter).with_limit(1);
 
-    let mut page = db.select(select).await.unwrap();
-    Ok(page.items.remove(0).data)
+    let mut page = db.select_as::<Todo>(select).await?;
+    Ok(page.items.pop())
 }
 
 pub async fn apply_schema(db: &Db) -> Result<(), anyhow::Error> {
@@ -63,3 +66,109 @@ pub async fn apply_schema(db: &Db) -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use factdb::{map, AttrMapExt};
+    use factor_engine::{backend::memory::MemoryDb, Engine};
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_typed_crud() {
+        futures::executor::block_on(async {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            apply_schema(&db).await.unwrap();
+
+            let mut todo = Todo::new_from_index(1);
+            db.create_typed(&todo).await.unwrap();
+            db.create(Id::random(), map! { "factor/title": "untyped" })
+                .await
+                .unwrap();
+
+            let found = db.find::<Todo>(todo.id).await.unwrap().unwrap();
+            assert_eq!(found.title, "1");
+            assert!(db.find::<Todo>(Id::random()).await.unwrap().is_none());
+
+            todo.title = "updated".to_string();
+            todo.done = true;
+            db.update_typed(&todo).await.unwrap();
+
+            let found = db
+                .find_by::<Todo>(Expr::eq(AttrTitle::expr(), "updated"))
+                .await
+                .unwrap();
+            assert_eq!(found.len(), 1);
+            assert!(found[0].done);
+
+            // Entities of other types are filtered out.
+            let all = db.find_by::<Todo>(Expr::literal(true)).await.unwrap();
+            assert_eq!(all.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_select_as() {
+        futures::executor::block_on(async {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            apply_schema(&db).await.unwrap();
+
+            let todo = Todo::new_from_index(2);
+            db.create_typed(&todo).await.unwrap();
+            db.create(Id::random(), map! { "factor/title": "3" })
+                .await
+                .unwrap();
+
+            let found = select_single_todo_with_title_eq(&db, "2".to_string())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(found.id, todo.id);
+            assert_eq!(found.description.as_deref(), Some("2"));
+
+            // Entities of other types are filtered out.
+            let other = select_single_todo_with_title_eq(&db, "3".to_string())
+                .await
+                .unwrap();
+            assert!(other.is_none());
+
+            let page = db.select_as::<Todo>(Select::new()).await.unwrap();
+            assert_eq!(page.items.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_select_pages() {
+        futures::executor::block_on(async {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            apply_schema(&db).await.unwrap();
+
+            for index in 0..5 {
+                db.create_typed(&Todo::new_from_index(index)).await.unwrap();
+            }
+
+            let select = Select::new()
+                .with_filter(Expr::is_entity::<Todo>())
+                .with_limit(2);
+            let pages = db
+                .select_pages(select)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(
+                pages.iter().map(|p| p.items.len()).collect::<Vec<_>>(),
+                vec![2, 2, 1]
+            );
+
+            let mut ids = pages
+                .into_iter()
+                .flat_map(|p| p.items)
+                .map(|item| item.data.get_id().unwrap())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 5);
+        });
+    }
+}
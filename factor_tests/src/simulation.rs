@@ -0,0 +1,312 @@
+//! Deterministic simulation of concurrent database access.
+//!
+//! A number of tasks, each executing a sequence of random operations, run
+//! concurrently on a single-threaded executor.
+//! The executor picks the next task to poll with a seeded RNG, so the same
+//! seed always produces the same interleaving.
+//!
+//! The operations are recorded in the order they completed and replayed
+//! sequentially against a fresh memory backend, which must produce the same
+//! results.
+//!
+//! See [`Simulation`].
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use factdb::{data::Value, Db};
+use factor_engine::{backend::memory::MemoryDb, Engine};
+use futures::{
+    future::LocalBoxFuture,
+    task::{waker_ref, ArcWake},
+    FutureExt,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::conformance::{AttrSpec, Op, Outcome, PropType, Scenario, ENTITY_POOL};
+
+/// Configuration for a simulation run.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub seed: u64,
+    /// Number of concurrent tasks.
+    pub tasks: usize,
+    /// Number of operations executed by each task.
+    pub ops_per_task: usize,
+}
+
+/// A completed operation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub task: usize,
+    pub op: Op,
+    pub outcome: Outcome,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            tasks: 4,
+            ops_per_task: 20,
+        }
+    }
+
+    pub fn with_tasks(mut self, tasks: usize) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    pub fn with_ops_per_task(mut self, ops_per_task: usize) -> Self {
+        self.ops_per_task = ops_per_task;
+        self
+    }
+
+    fn scenario() -> Scenario {
+        let attr = |value_type, unique| AttrSpec { value_type, unique };
+        Scenario {
+            attributes: vec![
+                attr(PropType::Int, true),
+                attr(PropType::String, false),
+                attr(PropType::Bool, false),
+            ],
+            ops: Vec::new(),
+        }
+    }
+
+    fn random_value(rng: &mut StdRng) -> Value {
+        match rng.gen_range(0..3) {
+            0 => Value::from(rng.gen_range(-3i64..3)),
+            1 => Value::from(["a", "b", "c"][rng.gen_range(0..3)]),
+            _ => Value::from(rng.gen::<bool>()),
+        }
+    }
+
+    fn random_values(rng: &mut StdRng, attributes: usize) -> Vec<(usize, Value)> {
+        (0..rng.gen_range(0..3))
+            .map(|_| (rng.gen_range(0..attributes), Self::random_value(rng)))
+            .collect()
+    }
+
+    fn random_op(rng: &mut StdRng, attributes: usize) -> Op {
+        let entity = rng.gen_range(0..ENTITY_POOL);
+        match rng.gen_range(0..5) {
+            0 => Op::Create {
+                entity,
+                values: Self::random_values(rng, attributes),
+            },
+            1 => Op::Merge {
+                entity,
+                values: Self::random_values(rng, attributes),
+            },
+            2 => Op::Delete { entity },
+            3 => Op::SelectEq {
+                attr: rng.gen_range(0..attributes),
+                value: Self::random_value(rng),
+            },
+            _ => Op::SelectAll,
+        }
+    }
+
+    /// Run the simulation against the database and return the history of
+    /// completed operations.
+    ///
+    /// The database must be empty.
+    pub fn run(&self, db: &Db) -> Result<Vec<HistoryEntry>, String> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let scenario = Self::scenario();
+
+        futures::executor::block_on(db.migrate(scenario.migration()))
+            .map_err(|err| format!("migration failed: {:#}", err))?;
+
+        let task_ops = (0..self.tasks)
+            .map(|_| {
+                (0..self.ops_per_task)
+                    .map(|_| Self::random_op(&mut rng, scenario.attributes.len()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let history = RefCell::new(Vec::new());
+        let tasks = task_ops
+            .into_iter()
+            .enumerate()
+            .map(|(task, ops)| {
+                let scenario = &scenario;
+                let history = &history;
+                async move {
+                    for op in ops {
+                        YieldNow::default().await;
+                        let outcome = scenario.run_op(db, &op).await;
+                        history
+                            .borrow_mut()
+                            .push(HistoryEntry { task, op, outcome });
+                    }
+                }
+                .boxed_local()
+            })
+            .collect();
+        run_tasks(&mut rng, tasks)?;
+
+        Ok(history.into_inner())
+    }
+
+    /// Run the simulation and verify the results by replaying the history
+    /// sequentially against the memory backend.
+    pub fn check(&self, db: &Db) -> Result<Vec<HistoryEntry>, String> {
+        let history = self
+            .run(db)
+            .map_err(|err| format!("seed {}: {}", self.seed, err))?;
+
+        let scenario = Self::scenario();
+        let oracle = Engine::new(MemoryDb::new()).into_client();
+        futures::executor::block_on(async {
+            oracle
+                .migrate(scenario.migration())
+                .await
+                .map_err(|err| format!("oracle migration failed: {:#}", err))?;
+
+            for (index, entry) in history.iter().enumerate() {
+                let expected = scenario.run_op(&oracle, &entry.op).await;
+                if expected != entry.outcome {
+                    return Err(format!(
+                        "seed {}: operation {} of task {} ({:?}) returned {:?}, expected {:?}",
+                        self.seed, index, entry.task, entry.op, entry.outcome, expected
+                    ));
+                }
+            }
+
+            let expected = scenario.run_op(&oracle, &Op::SelectAll).await;
+            let actual = scenario.run_op(db, &Op::SelectAll).await;
+            if expected != actual {
+                return Err(format!(
+                    "seed {}: final state differs: {:?}, expected {:?}",
+                    self.seed, actual, expected
+                ));
+            }
+            Ok(())
+        })?;
+
+        Ok(history)
+    }
+}
+
+/// Wake flag of a task.
+#[derive(Default)]
+struct TaskWaker {
+    woken: AtomicBool,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Poll the tasks to completion.
+///
+/// Each step polls a random task out of all woken tasks.
+fn run_tasks(rng: &mut StdRng, tasks: Vec<LocalBoxFuture<'_, ()>>) -> Result<(), String> {
+    let mut tasks = tasks
+        .into_iter()
+        .map(|task| {
+            let waker = Arc::new(TaskWaker::default());
+            waker.woken.store(true, Ordering::SeqCst);
+            Some((task, waker))
+        })
+        .collect::<Vec<_>>();
+
+    loop {
+        let woken = tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| {
+                let (_, waker) = task.as_ref()?;
+                if waker.woken.load(Ordering::SeqCst) {
+                    Some(index)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if woken.is_empty() {
+            return if tasks.iter().all(Option::is_none) {
+                Ok(())
+            } else {
+                Err("deadlock: no task can make progress".to_string())
+            };
+        }
+
+        let index = woken[rng.gen_range(0..woken.len())];
+        let (task, waker) = tasks[index].as_mut().unwrap();
+        waker.woken.store(false, Ordering::SeqCst);
+        let task_waker = waker_ref(waker);
+        let mut cx = Context::from_waker(&task_waker);
+        if task.as_mut().poll(&mut cx).is_ready() {
+            tasks[index] = None;
+        }
+    }
+}
+
+/// Future that yields to the executor once.
+#[derive(Default)]
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_engine::backend::log::{store_memory::MemoryLogStore, LogDb};
+
+    use super::*;
+
+    #[test]
+    fn test_simulation_is_deterministic() {
+        let run = |seed| {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            Simulation::new(seed).run(&db).unwrap()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_simulation_memory_backend() {
+        for seed in 0..20 {
+            let db = Engine::new(MemoryDb::new()).into_client();
+            Simulation::new(seed).check(&db).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_simulation_log_backend() {
+        for seed in 0..20 {
+            let log = futures::executor::block_on(LogDb::open(MemoryLogStore::new())).unwrap();
+            let db = Engine::new(log).into_client();
+            Simulation::new(seed).check(&db).unwrap();
+        }
+    }
+}
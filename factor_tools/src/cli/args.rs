@@ -0,0 +1,112 @@
+use anyhow::{anyhow, bail};
+
+/// Minimal argument parser for the cli.
+///
+/// Flags and options are removed from the argument list when taken, so all
+/// options must be taken before the positional arguments.
+#[derive(Clone, Debug)]
+pub struct Args {
+    items: Vec<String>,
+}
+
+impl Args {
+    pub fn new(items: Vec<String>) -> Self {
+        Self { items }
+    }
+
+    /// Take an option with a value, in the form `--name value` or
+    /// `--name=value`.
+    pub fn take_value(&mut self, name: &str) -> Result<Option<String>, anyhow::Error> {
+        let prefix = format!("{}=", name);
+
+        let index = match self
+            .items
+            .iter()
+            .position(|x| x == name || x.starts_with(&prefix))
+        {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        let item = self.items.remove(index);
+        if let Some(value) = item.strip_prefix(&prefix) {
+            Ok(Some(value.to_string()))
+        } else if index < self.items.len() {
+            Ok(Some(self.items.remove(index)))
+        } else {
+            Err(anyhow!("Missing value for option {}", name))
+        }
+    }
+
+    /// Take an option and parse the value.
+    pub fn take_parsed<T>(&mut self, name: &str) -> Result<Option<T>, anyhow::Error>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.take_value(name)? {
+            Some(raw) => raw
+                .parse()
+                .map(Some)
+                .map_err(|err| anyhow!("Invalid value for {}: {}", name, err)),
+            None => Ok(None),
+        }
+    }
+
+    /// Take a boolean flag.
+    pub fn take_flag(&mut self, name: &str) -> bool {
+        if let Some(index) = self.items.iter().position(|x| x == name) {
+            self.items.remove(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take the next positional argument.
+    pub fn take_positional(&mut self) -> Option<String> {
+        if self.items.is_empty() {
+            None
+        } else {
+            Some(self.items.remove(0))
+        }
+    }
+
+    pub fn require_positional(&mut self, name: &str) -> Result<String, anyhow::Error> {
+        match self.take_positional() {
+            Some(v) if !v.starts_with("--") => Ok(v),
+            Some(other) => Err(anyhow!("Unknown option '{}'", other)),
+            None => Err(anyhow!("Missing argument <{}>", name)),
+        }
+    }
+
+    /// Ensure that all arguments were consumed.
+    pub fn finish(self) -> Result<(), anyhow::Error> {
+        if !self.items.is_empty() {
+            bail!("Unexpected arguments: {}", self.items.join(" "));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let mut args = Args::new(
+            ["a", "--lang", "ts", "--yes", "--limit=5", "b"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+        );
+        assert_eq!(args.take_value("--lang").unwrap(), Some("ts".to_string()));
+        assert_eq!(args.take_parsed::<u64>("--limit").unwrap(), Some(5));
+        assert!(args.take_flag("--yes"));
+        assert!(!args.take_flag("--no"));
+        assert_eq!(args.require_positional("x").unwrap(), "a");
+        assert_eq!(args.require_positional("y").unwrap(), "b");
+        args.finish().unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+use std::time::{Duration, Instant};
+
+use factor_core::{
+    data::{Id, ValueType},
+    db::Db,
+    map,
+    query::{expr::Expr, migrate::Migration, select::Select},
+    schema::Attribute,
+};
+use factor_engine::{backend::memory::MemoryDb, Engine};
+
+use super::{table, Args};
+
+const DEFAULT_COUNT: u64 = 10_000;
+const DEFAULT_ITERATIONS: u64 = 1_000;
+
+/// Number of distinct `bench/group` values used by the scan workload.
+const GROUPS: u64 = 100;
+
+const ATTR_NAME: &str = "bench/name";
+const ATTR_GROUP: &str = "bench/group";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Workload {
+    /// Create entities one by one.
+    Insert,
+    /// Load single entities by id.
+    Get,
+    /// Select with a filter on a non-indexed attribute.
+    Scan,
+    /// Select with a filter on a unique attribute.
+    Index,
+}
+
+impl Workload {
+    const ALL: &'static [Self] = &[Self::Insert, Self::Get, Self::Scan, Self::Index];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Get => "get",
+            Self::Scan => "scan",
+            Self::Index => "index",
+        }
+    }
+}
+
+impl std::str::FromStr for Workload {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|w| w.as_str() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown workload '{}': expected insert, get, scan or index",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+struct WorkloadReport {
+    workload: &'static str,
+    operations: u64,
+    ops_per_sec: f64,
+    p50_us: f64,
+    p90_us: f64,
+    p99_us: f64,
+    max_us: f64,
+}
+
+/// `bench [--db <DB_FILE | URL>] [--token <TOKEN>] [--count <N>] [--iterations <N>]
+///   [--workload <insert|get|scan|index>]... [--format <table|json>]`
+///
+/// Runs against a fresh in-memory database unless `--db` is given.
+/// Benchmark entities are written to the target database, so a scratch
+/// database should be used.
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let target = args.take_value("--db")?;
+    let token = args.take_value("--token")?;
+    let count = args.take_parsed("--count")?.unwrap_or(DEFAULT_COUNT);
+    let iterations = args
+        .take_parsed("--iterations")?
+        .unwrap_or(DEFAULT_ITERATIONS);
+    let mut workloads = Vec::new();
+    while let Some(workload) = args.take_parsed::<Workload>("--workload")? {
+        workloads.push(workload);
+    }
+    let json = match args.take_value("--format")?.as_deref() {
+        None | Some("table") => false,
+        Some("json") => true,
+        Some(other) => anyhow::bail!("Invalid format '{}': expected table or json", other),
+    };
+    args.finish()?;
+
+    if count == 0 {
+        anyhow::bail!("--count must be greater than 0");
+    }
+    if workloads.is_empty() {
+        workloads = Workload::ALL.to_vec();
+    }
+
+    super::block_on(async move {
+        let db = match &target {
+            Some(target) => super::connect(target, token).await?,
+            None => Engine::new(MemoryDb::new()).into_client(),
+        };
+        let reports = run_workloads(&db, &workloads, count, iterations).await?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            print!("{}", render_reports(&reports));
+        }
+        Ok(())
+    })
+}
+
+async fn run_workloads(
+    db: &Db,
+    workloads: &[Workload],
+    count: u64,
+    iterations: u64,
+) -> Result<Vec<WorkloadReport>, anyhow::Error> {
+    db.migrate(
+        Migration::new()
+            .attr_upsert(Attribute::new(ATTR_NAME, ValueType::String).with_unique(true))
+            .attr_upsert(Attribute::new(ATTR_GROUP, ValueType::UInt)),
+    )
+    .await?;
+
+    // Names are prefixed with a run id, so repeated runs against the same
+    // database don't violate the unique constraint.
+    let run_id = Id::random();
+    let name = |index: u64| format!("{}-{}", run_id, index);
+
+    // The inserted entities are required by all other workloads, so the
+    // insert workload always runs, but is only reported if requested.
+    let mut ids = Vec::with_capacity(count as usize);
+    let mut latencies = Vec::with_capacity(count as usize);
+    let started = Instant::now();
+    for index in 0..count {
+        let id = Id::random();
+        let start = Instant::now();
+        db.create(
+            id,
+            map! { ATTR_NAME: name(index), ATTR_GROUP: index % GROUPS },
+        )
+        .await?;
+        latencies.push(start.elapsed());
+        ids.push(id);
+    }
+    let insert = summarize(Workload::Insert, latencies, started.elapsed());
+
+    let mut reports = Vec::new();
+    for workload in workloads {
+        let report = match workload {
+            Workload::Insert => insert.clone(),
+            Workload::Get => {
+                measure(*workload, iterations, |i| {
+                    let id = ids[pick(i, count) as usize];
+                    async move {
+                        db.entity(id).await?;
+                        Ok::<_, anyhow::Error>(())
+                    }
+                })
+                .await?
+            }
+            Workload::Scan => {
+                measure(*workload, iterations, |i| {
+                    let filter = Expr::eq(Expr::attr_ident(ATTR_GROUP), i % GROUPS);
+                    async move {
+                        db.select(Select::new().with_filter(filter)).await?;
+                        Ok::<_, anyhow::Error>(())
+                    }
+                })
+                .await?
+            }
+            Workload::Index => {
+                measure(*workload, iterations, |i| {
+                    let filter = Expr::eq(Expr::attr_ident(ATTR_NAME), name(pick(i, count)));
+                    async move {
+                        let page = db
+                            .select(Select::new().with_filter(filter).with_limit(1))
+                            .await?;
+                        if page.items.is_empty() {
+                            anyhow::bail!("Index lookup returned no entity");
+                        }
+                        Ok::<_, anyhow::Error>(())
+                    }
+                })
+                .await?
+            }
+        };
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Run an operation `iterations` times and record the latency of each call.
+async fn measure<F, Fut>(
+    workload: Workload,
+    iterations: u64,
+    mut op: F,
+) -> Result<WorkloadReport, anyhow::Error>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: std::future::Future<Output = Result<(), anyhow::Error>>,
+{
+    let mut latencies = Vec::with_capacity(iterations as usize);
+    let started = Instant::now();
+    for i in 0..iterations {
+        let start = Instant::now();
+        op(i).await?;
+        latencies.push(start.elapsed());
+    }
+    Ok(summarize(workload, latencies, started.elapsed()))
+}
+
+/// Spread accesses over all entities instead of reading them in insertion
+/// order.
+fn pick(iteration: u64, count: u64) -> u64 {
+    iteration.wrapping_mul(7919) % count
+}
+
+fn summarize(workload: Workload, mut latencies: Vec<Duration>, total: Duration) -> WorkloadReport {
+    latencies.sort();
+    let micros = |d: Duration| d.as_secs_f64() * 1_000_000.0;
+
+    let operations = latencies.len() as u64;
+    let ops_per_sec = if total.is_zero() {
+        0.0
+    } else {
+        operations as f64 / total.as_secs_f64()
+    };
+
+    WorkloadReport {
+        workload: workload.as_str(),
+        operations,
+        ops_per_sec,
+        p50_us: micros(percentile(&latencies, 50.0)),
+        p90_us: micros(percentile(&latencies, 90.0)),
+        p99_us: micros(percentile(&latencies, 99.0)),
+        max_us: micros(latencies.last().copied().unwrap_or_default()),
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn render_reports(reports: &[WorkloadReport]) -> String {
+    let header = ["workload", "ops", "ops/s", "p50", "p90", "p99", "max"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<_>>();
+    let rows = reports
+        .iter()
+        .map(|r| {
+            vec![
+                r.workload.to_string(),
+                r.operations.to_string(),
+                format!("{:.0}", r.ops_per_sec),
+                format_micros(r.p50_us),
+                format_micros(r.p90_us),
+                format_micros(r.p99_us),
+                format_micros(r.max_us),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table::render_table(&header, &rows)
+}
+
+fn format_micros(us: f64) -> String {
+    if us >= 1000.0 {
+        format!("{:.2}ms", us / 1000.0)
+    } else {
+        format!("{:.1}µs", us)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let values = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&values, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&values, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&values, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_bench_memory() {
+        let db = Engine::new(MemoryDb::new()).into_client();
+        let reports = run_workloads(&db, Workload::ALL, 20, 5).await.unwrap();
+
+        assert_eq!(
+            reports.iter().map(|r| r.workload).collect::<Vec<_>>(),
+            vec!["insert", "get", "scan", "index"]
+        );
+        assert_eq!(reports[0].operations, 20);
+        assert!(reports[1..].iter().all(|r| r.operations == 5));
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use factor_core::{
+    data::DataMap,
+    query::{
+        migrate::Migration,
+        select::{Order, Select},
+    },
+    schema::builtin::AttrId,
+};
+use factor_engine::backend::{log::LogDb, Backend};
+
+use super::Args;
+
+/// `compact --db <DB_FILE>`
+///
+/// A backup of the original log is kept until the compacted log was
+/// verified to restore to the same state.
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let path = PathBuf::from(args.take_value("--db")?.context("Missing option --db")?);
+    args.finish()?;
+
+    if !path.is_file() {
+        anyhow::bail!("Database file '{}' does not exist", path.display());
+    }
+
+    super::block_on(async move {
+        let log = super::open_log(&path).await?;
+        let size_before = file_size(&path)?;
+        let state_before = snapshot(&log).await?;
+
+        let mut backup = path.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        std::fs::copy(&path, &backup)
+            .with_context(|| format!("Could not create backup '{}'", backup.display()))?;
+
+        let stats = log.compact().await?;
+        drop(log);
+
+        let restored = super::open_log(&path).await?;
+        let state_after = snapshot(&restored).await?;
+        drop(restored);
+
+        if state_before != state_after {
+            std::fs::rename(&backup, &path).with_context(|| {
+                format!(
+                    "Compacted log is invalid and the backup could not be restored from '{}'",
+                    backup.display()
+                )
+            })?;
+            anyhow::bail!("Compacted log does not restore to the same state - kept original log");
+        }
+        std::fs::remove_file(&backup)?;
+
+        let size_after = file_size(&path)?;
+        println!(
+            "Compacted {} events into {} ({} migrations, {} entities)",
+            stats.events_before,
+            stats.events_after,
+            state_after.0.len(),
+            state_after.1.len(),
+        );
+        println!(
+            "Size: {} -> {} bytes ({:.1}% smaller)",
+            size_before,
+            size_after,
+            saved_percent(size_before, size_after)
+        );
+        Ok(())
+    })
+}
+
+fn file_size(path: &Path) -> Result<u64, anyhow::Error> {
+    let meta = std::fs::metadata(path)
+        .with_context(|| format!("Could not read file '{}'", path.display()))?;
+    Ok(meta.len())
+}
+
+fn saved_percent(before: u64, after: u64) -> f64 {
+    if before == 0 {
+        0.0
+    } else {
+        (before.saturating_sub(after) as f64 / before as f64) * 100.0
+    }
+}
+
+/// All migrations and entities of the database, used to verify that the
+/// compacted log restores to an identical state.
+async fn snapshot(log: &LogDb) -> Result<(Vec<Migration>, Vec<DataMap>), anyhow::Error> {
+    let migrations = Backend::migrations(log).await?;
+    let entities =
+        Backend::select_map(log, Select::new().with_sort(AttrId::expr(), Order::Asc)).await?;
+    Ok((migrations, entities))
+}
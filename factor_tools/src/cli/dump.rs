@@ -0,0 +1,55 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+
+use super::Args;
+
+/// `dump [--out <FILE>] <DB_FILE>`
+pub fn run_dump(mut args: Args) -> Result<(), anyhow::Error> {
+    let out = args.take_value("--out")?.map(PathBuf::from);
+    let db_path = PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    super::block_on(async move {
+        let db = super::open_db(&db_path).await?;
+
+        let stats = if let Some(out) = out {
+            let file = File::create(&out)
+                .with_context(|| format!("Could not create file '{}'", out.display()))?;
+            crate::dump::dump(&db, BufWriter::new(file)).await?
+        } else {
+            crate::dump::dump(&db, std::io::stdout().lock()).await?
+        };
+
+        eprintln!(
+            "Dumped {} migrations and {} entities",
+            stats.migrations, stats.entities
+        );
+        Ok(())
+    })
+}
+
+/// `restore <DUMP_FILE> <DB_FILE>`
+pub fn run_restore(mut args: Args) -> Result<(), anyhow::Error> {
+    let dump_path = PathBuf::from(args.require_positional("DUMP_FILE")?);
+    let db_path = PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    let file = File::open(&dump_path)
+        .with_context(|| format!("Could not open file '{}'", dump_path.display()))?;
+
+    super::block_on(async move {
+        let log = super::open_log(&db_path).await?;
+        let stats = crate::dump::restore(&log, BufReader::new(file)).await?;
+
+        eprintln!(
+            "Restored {} migrations and {} entities",
+            stats.migrations, stats.entities
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,62 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use anyhow::Context;
+use factor_core::query::{select::Select, sql::ParsedSqlQuery};
+
+use crate::export::{Export, ExportFormat};
+
+use super::Args;
+
+enum Source {
+    Class(String),
+    Query(Select),
+}
+
+/// `export --db <DB_FILE | URL> [--token <TOKEN>] [--format <csv|parquet>] [--out <FILE>]
+///   (--class <CLASS> | --column <ATTR>... <QUERY>)`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let target = args
+        .take_value("--db")?
+        .ok_or_else(|| anyhow::anyhow!("Missing option --db"))?;
+    let token = args.take_value("--token")?;
+    let format = args
+        .take_parsed::<ExportFormat>("--format")?
+        .unwrap_or(ExportFormat::Csv);
+    let out = args.take_value("--out")?.map(PathBuf::from);
+    let class = args.take_value("--class")?;
+    let mut columns = Vec::new();
+    while let Some(column) = args.take_value("--column")? {
+        columns.push(column);
+    }
+    let source = match class {
+        Some(_) if !columns.is_empty() => {
+            anyhow::bail!("--column can not be combined with --class")
+        }
+        Some(class) => Source::Class(class),
+        None => match super::query::parse_query(&args.require_positional("QUERY")?)? {
+            ParsedSqlQuery::Select(select) => Source::Query(select),
+            ParsedSqlQuery::Mutate(_) => anyhow::bail!("Only SELECT queries can be exported"),
+        },
+    };
+    args.finish()?;
+
+    super::block_on(async move {
+        let db = super::connect(&target, token).await?;
+        let schema = db.schema().await?;
+        let spec = match source {
+            Source::Class(class) => Export::class(&schema, &class)?,
+            Source::Query(select) => Export::select(&schema, select, &columns)?,
+        };
+
+        let stats = if let Some(out) = out {
+            let file = File::create(&out)
+                .with_context(|| format!("Could not create file '{}'", out.display()))?;
+            crate::export::export(&db, &spec, format, BufWriter::new(file)).await?
+        } else {
+            crate::export::export(&db, &spec, format, std::io::stdout().lock()).await?
+        };
+
+        eprintln!("Exported {} rows", stats.rows);
+        Ok(())
+    })
+}
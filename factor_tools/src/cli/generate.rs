@@ -0,0 +1,134 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::{python::PythonStyle, CodegenLanguage};
+
+use super::Args;
+
+/// How long to wait for more file events before regenerating.
+/// Editors often emit several events for a single save.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// `generate-schema --language <LANG> [--out <FILE>] [--python-style <STYLE>]
+/// [--go-package <NAME>] [--watch] <SCHEMA_FILE>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let language: CodegenLanguage = args
+        .take_parsed("--language")?
+        .context("Missing option --language")?;
+    run_language(language, args)
+}
+
+pub fn run_language(language: CodegenLanguage, mut args: Args) -> Result<(), anyhow::Error> {
+    let out = args.take_value("--out")?.map(PathBuf::from);
+    let python_style: Option<PythonStyle> = args.take_parsed("--python-style")?;
+    let go_package = args.take_value("--go-package")?;
+    let watch = args.take_flag("--watch");
+    let schema_path = PathBuf::from(args.require_positional("SCHEMA_FILE")?);
+    args.finish()?;
+
+    if python_style.is_some() && language != CodegenLanguage::Python {
+        anyhow::bail!("--python-style is only supported for --language python");
+    }
+    if go_package.is_some() && language != CodegenLanguage::Go {
+        anyhow::bail!("--go-package is only supported for --language go");
+    }
+
+    let generate = || -> Result<(), anyhow::Error> {
+        let code = match language {
+            CodegenLanguage::Python => crate::python::generate_schema_from_file(
+                &schema_path,
+                python_style.unwrap_or(PythonStyle::Dataclass),
+            )?,
+            CodegenLanguage::Go => crate::go::generate_schema_from_file(
+                &schema_path,
+                go_package.as_deref().unwrap_or(crate::go::DEFAULT_PACKAGE),
+            )?,
+            _ => language.generate_schema_from_file(&schema_path)?,
+        };
+
+        if let Some(out) = &out {
+            std::fs::write(out, code)
+                .with_context(|| format!("Could not write file '{}'", out.display()))?;
+        } else {
+            print!("{code}");
+        }
+        Ok(())
+    };
+
+    if !watch {
+        return generate();
+    }
+
+    let out = out
+        .as_deref()
+        .context("--watch requires --out, since the generated code is rewritten on each change")?;
+    watch_file(&schema_path, || match generate() {
+        Ok(()) => eprintln!(
+            "Generated '{}' from '{}'",
+            out.display(),
+            schema_path.display()
+        ),
+        // Keep watching, the schema is probably just being edited.
+        Err(err) => eprintln!("Error: {:#}", err),
+    })
+}
+
+/// Run `on_change` once, and then again every time the file changes.
+///
+/// Blocks until the watcher fails.
+fn watch_file(path: &Path, mut on_change: impl FnMut()) -> Result<(), anyhow::Error> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid schema path '{}'", path.display()))?
+        .to_owned();
+    // Watch the parent directory instead of the file itself, because many
+    // editors save by writing a new file and renaming it over the old one,
+    // which would silently end a watch on the original file.
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let _ = tx.send(res);
+    })
+    .context("Could not create file watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Could not watch directory '{}'", dir.display()))?;
+
+    on_change();
+    eprintln!("Watching '{}' for changes...", path.display());
+
+    let is_relevant = |res: notify::Result<notify::Event>| -> Result<bool, anyhow::Error> {
+        let event = res.context("File watcher failed")?;
+        let touches_file = event
+            .paths
+            .iter()
+            .any(|p| p.file_name() == Some(file_name.as_os_str()));
+        Ok(touches_file && !matches!(event.kind, EventKind::Access(_)))
+    };
+
+    while let Ok(res) = rx.recv() {
+        if !is_relevant(res)? {
+            continue;
+        }
+        // Drain follow-up events belonging to the same save.
+        while let Ok(res) = rx.recv_timeout(WATCH_DEBOUNCE) {
+            is_relevant(res)?;
+        }
+        // The file may be briefly missing while an editor replaces it.
+        if path.is_file() {
+            on_change();
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use anyhow::Context;
+
+use crate::import::{ImportFormat, ImportMapping};
+
+use super::Args;
+
+/// `import --mapping <MAPPING_FILE> [--format <csv|jsonl>] <INPUT_FILE> <DB_FILE>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let mapping_path = args
+        .take_value("--mapping")?
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Missing option --mapping"))?;
+    let format = args.take_parsed::<ImportFormat>("--format")?;
+    let input_path = PathBuf::from(args.require_positional("INPUT_FILE")?);
+    let db_path = PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    // Guess the format from the file extension.
+    let format = match format {
+        Some(format) => format,
+        None => match input_path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => ext.parse()?,
+            None => anyhow::bail!("Could not detect the input format, use --format"),
+        },
+    };
+
+    let mapping_raw = std::fs::read_to_string(&mapping_path)
+        .with_context(|| format!("Could not read file '{}'", mapping_path.display()))?;
+    let mapping: ImportMapping = serde_json::from_str(&mapping_raw)
+        .with_context(|| format!("Invalid mapping file '{}'", mapping_path.display()))?;
+    let file = File::open(&input_path)
+        .with_context(|| format!("Could not open file '{}'", input_path.display()))?;
+
+    super::block_on(async move {
+        let log = super::open_log(&db_path).await?;
+        let report = crate::import::import(&log, &mapping, format, BufReader::new(file)).await?;
+
+        for error in &report.errors {
+            eprintln!("line {}: {}", error.line, error.message);
+        }
+        eprintln!(
+            "Imported {} rows, skipped {} invalid rows",
+            report.imported,
+            report.errors.len()
+        );
+        Ok(())
+    })
+}
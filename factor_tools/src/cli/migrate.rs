@@ -0,0 +1,143 @@
+use std::{
+    hash::Hasher,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use factor_core::{query::migrate::Migration, schema::DbSchema};
+
+use super::Args;
+
+/// `migrate [--yes] [--name <NAME>] [--token <TOKEN>] <FILE> <DB_FILE | URL>`
+///
+/// `FILE` can either be a schema file or a JSON migration.
+/// Migrations are only applied once: if a migration with the same name is
+/// already recorded in the database, nothing is done.
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let yes = args.take_flag("--yes");
+    let name = args.take_value("--name")?;
+    let token = args.take_value("--token")?;
+    let path = PathBuf::from(args.require_positional("FILE")?);
+    let target = args.require_positional("DB")?;
+    args.finish()?;
+
+    let source = MigrationSource::read(&path)?;
+
+    super::block_on(async move {
+        let db = super::connect(&target, token).await?;
+
+        let mut migration = match source {
+            MigrationSource::Schema { name, schema } => {
+                let current = db.schema().await?;
+                let mut migration = current.diff(&schema).to_migration(false);
+                migration.name = Some(name);
+                migration
+            }
+            MigrationSource::Migration(migration) => migration,
+        };
+        if let Some(name) = name {
+            migration.name = Some(name);
+        }
+
+        if let Some(name) = &migration.name {
+            let applied = db
+                .migrations()
+                .await?
+                .iter()
+                .any(|m| m.name.as_deref() == Some(name.as_str()));
+            if applied {
+                println!("Migration '{}' was already applied", name);
+                return Ok(());
+            }
+        }
+
+        if migration.actions.is_empty() {
+            println!("Database is up to date");
+            return Ok(());
+        }
+
+        println!(
+            "Migration '{}':",
+            migration.name.as_deref().unwrap_or("<unnamed>")
+        );
+        for action in &migration.actions {
+            println!("  {}", action);
+        }
+
+        if !yes && !confirm(&format!("Apply {} actions?", migration.actions.len()))? {
+            anyhow::bail!("Aborted");
+        }
+
+        db.migrate(migration).await?;
+        println!("Migration applied");
+        Ok(())
+    })
+}
+
+enum MigrationSource {
+    Schema {
+        /// Default migration name, see [`default_name`].
+        name: String,
+        schema: DbSchema,
+    },
+    Migration(Migration),
+}
+
+impl MigrationSource {
+    fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read file '{}'", path.display()))?;
+        let value: serde_json::Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid JSON in '{}'", path.display()))?;
+
+        if value.get("factor/migrations").is_some() {
+            let schema = crate::schema_file::parse_static_schema(&contents)
+                .with_context(|| format!("Invalid schema file '{}'", path.display()))?;
+            Ok(Self::Schema {
+                schema: crate::schema_file::build_db_schema(&schema)?,
+                name: default_name(&schema.ident, &contents),
+            })
+        } else if value.get("actions").is_some() {
+            let migration = serde_json::from_value(value)
+                .with_context(|| format!("Invalid migration file '{}'", path.display()))?;
+            Ok(Self::Migration(migration))
+        } else {
+            anyhow::bail!(
+                "'{}' is neither a schema file nor a migration",
+                path.display()
+            )
+        }
+    }
+}
+
+/// Name of the migration generated from a schema file.
+///
+/// Derived from the file contents, so applying the same file again is
+/// recognized as already applied.
+fn default_name(ident: &str, contents: &str) -> String {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(contents.as_bytes());
+    format!("{}-{:016x}", ident, hasher.finish())
+}
+
+fn confirm(question: &str) -> Result<bool, anyhow::Error> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_name_is_stable() {
+        let name = default_name("test", "{}");
+        assert_eq!(name, default_name("test", "{}"));
+        assert!(name.starts_with("test-"));
+        assert_ne!(name, default_name("test", "{ }"));
+    }
+}
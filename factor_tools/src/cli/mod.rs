@@ -0,0 +1,172 @@
+//! Implementation of the `factor_tools` command line interface.
+
+mod args;
+mod bench;
+mod compact;
+mod dump;
+mod export;
+mod generate;
+mod import;
+mod migrate;
+mod query;
+mod repl;
+mod schema;
+mod serve;
+mod stats;
+mod sync;
+mod table;
+mod verify;
+
+use std::path::Path;
+
+use anyhow::Context;
+use factor_core::db::Db;
+use factor_engine::{
+    backend::log::{convert_json::JsonConverter, store_file::FileLogStore, LogDb},
+    Engine,
+};
+
+pub use self::args::Args;
+
+const USAGE: &str = "Usage: factor_tools <COMMAND>
+
+Commands:
+  generate-schema --language <rust|typescript|python|go> [--out <FILE>] <SCHEMA_FILE>
+      Generate code for a schema file.
+      --python-style <dataclass|pydantic> selects the kind of Python classes.
+      --go-package <NAME> sets the Go package name (default: schema).
+      --watch regenerates the --out file whenever the schema file changes.
+  rust <SCHEMA_FILE>
+      Alias for generate-schema --language rust.
+  bench [--db <DB_FILE | URL>] [--token <TOKEN>] [--count <N>] [--iterations <N>]
+        [--workload <insert|get|scan|index>]... [--format <table|json>]
+      Measure throughput and latency percentiles of common operations.
+      Uses a fresh in-memory database unless --db is given.
+      All workloads run if none are selected.
+  compact --db <DB_FILE>
+      Rewrite the log of a database file so it only contains the current state.
+  dump [--out <FILE>] <DB_FILE>
+      Write all migrations and entities of a database as JSON-lines.
+  export --db <DB_FILE | URL> [--token <TOKEN>] [--format <csv|parquet>] [--out <FILE>]
+         (--class <CLASS> | --column <ATTR>... <QUERY>)
+      Export a class or the result of a query to CSV or Parquet.
+      Class exports contain all attributes of the class, query exports the given columns.
+  restore <DUMP_FILE> <DB_FILE>
+      Import a dump into a new database.
+  import --mapping <MAPPING_FILE> [--format <csv|jsonl>] <INPUT_FILE> <DB_FILE>
+      Import rows from CSV or JSON-lines, mapping columns to attributes.
+      The mapping file is JSON, see factor_tools::import::ImportMapping.
+      Invalid rows are skipped and reported.
+  migrate [--yes] [--name <NAME>] [--token <TOKEN>] <FILE> <DB_FILE | URL>
+      Apply a schema file or a JSON migration to a database.
+      Shows the planned changes and asks for confirmation unless --yes is given.
+  query --db <DB_FILE | URL> [--token <TOKEN>] [--limit <N>] [--sort <ATTR[:desc]>]...
+        [--format <table|json>] <QUERY>
+      Run a SQL SELECT statement or a filter expression and print the results.
+  repl [--token <TOKEN>] <DB_FILE | URL>
+      Interactive shell for a database file or a server started with `serve`.
+  schema diff [--token <TOKEN>] [--emit-migration <FILE>] [--delete-removed] <OLD> <NEW>
+      Show the differences between two schemas.
+      OLD and NEW can be schema files (*.json), database files or urls.
+  schema lint [--token <TOKEN>] [--deny-warnings] <SCHEMA>
+      Check a schema for common problems.
+      Exits with an error if any errors (or warnings with --deny-warnings) are found.
+  serve [--bind <ADDR>] [--token <TOKEN>] [--api-key <KEY>]... [--read-key <KEY>]...
+        [--jwt-secret <SECRET>] [--jwt-claim <NAME>]... [--max-body-size <BYTES>]
+        [--read-only] <DB_FILE>
+      Serve a database file over a HTTP/JSON API.
+      The token may also be provided via FACTOR_SERVE_TOKEN.
+      --read-key adds API keys that only allow reads.
+      --jwt-secret accepts HS256 JSON web tokens, see factor_tools::server::JwtConfig.
+      The secret may also be provided via FACTOR_SERVE_JWT_SECRET.
+      --jwt-claim makes a token claim available as a policy variable.
+      --max-body-size limits the size of request bodies (default: 16 MiB).
+  stats [--format <table|json>] <DB_FILE>
+      Show entity counts per class, index sizes, log size, memory estimates
+      and index suggestions.
+  sync [--token <TOKEN>] [--source <NAME>] [--interval <SECONDS>] <URL> <DB_FILE>
+      Pull new changes of a database served with `serve` into a database file.
+      Requires a token with full access. The sync position is stored in the database
+      file under the source name (default: the url), so repeated runs resume.
+      If the source was compacted or purged, the database is cleared and synced
+      again from the start.
+      --interval keeps pulling changes with the given delay.
+  verify [--repair] <DB_FILE>
+      Check the log, indexes and entities of a database for inconsistencies.
+      --repair rewrites a log with invalid events from the restorable state.
+      Alias: fsck.
+";
+
+/// Run the cli with the given arguments (excluding the binary name).
+pub fn run(args: Vec<String>) -> Result<(), anyhow::Error> {
+    let mut args = Args::new(args);
+    let command = match args.take_positional() {
+        Some(c) => c,
+        None => {
+            eprint!("{}", USAGE);
+            anyhow::bail!("Missing command");
+        }
+    };
+
+    match command.as_str() {
+        "generate-schema" => generate::run(args),
+        "rust" => generate::run_language(crate::CodegenLanguage::Rust, args),
+        "bench" => bench::run(args),
+        "compact" => compact::run(args),
+        "dump" => dump::run_dump(args),
+        "restore" => dump::run_restore(args),
+        "export" => export::run(args),
+        "import" => import::run(args),
+        "migrate" => migrate::run(args),
+        "query" => query::run(args),
+        "repl" => repl::run(args),
+        "schema" => schema::run(args),
+        "serve" => serve::run(args),
+        "stats" => stats::run(args),
+        "sync" => sync::run(args),
+        "verify" | "fsck" => verify::run(args),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        other => {
+            eprint!("{}", USAGE);
+            anyhow::bail!("Unknown command '{}'", other)
+        }
+    }
+}
+
+/// Open a log database file.
+///
+/// The file is created if it does not exist yet.
+async fn open_log(path: &Path) -> Result<LogDb, anyhow::Error> {
+    let store = FileLogStore::open(JsonConverter, path)
+        .await
+        .with_context(|| format!("Could not open database file '{}'", path.display()))?;
+    LogDb::open(store)
+        .await
+        .with_context(|| format!("Could not restore database from '{}'", path.display()))
+}
+
+async fn open_db(path: &Path) -> Result<Db, anyhow::Error> {
+    let log = open_log(path).await?;
+    Ok(Engine::new(log).into_client())
+}
+
+/// Connect to a remote database if `target` is a url, or open a local file.
+async fn connect(target: &str, token: Option<String>) -> Result<Db, anyhow::Error> {
+    if target.contains("://") {
+        let client = crate::remote::HttpDbClient::new(target, token)?;
+        Ok(Db::new(client))
+    } else {
+        open_db(target.as_ref()).await
+    }
+}
+
+fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Could not create tokio runtime")
+        .block_on(f)
+}
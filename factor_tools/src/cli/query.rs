@@ -0,0 +1,105 @@
+use factor_core::query::{
+    expr::Expr,
+    select::{Order, Select},
+    sql::{parse_sql, ParsedSqlQuery, SqlParseError},
+};
+
+use super::{table, Args};
+
+/// `query --db <DB_FILE | URL> [--token <TOKEN>] [--limit <N>] [--sort <ATTR[:desc]>]...
+///   [--format <table|json>] <QUERY>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let target = args
+        .take_value("--db")?
+        .ok_or_else(|| anyhow::anyhow!("Missing option --db"))?;
+    let token = args.take_value("--token")?;
+    let limit = args.take_parsed::<u64>("--limit")?;
+    let mut sorts = Vec::new();
+    while let Some(sort) = args.take_value("--sort")? {
+        sorts.push(parse_sort(&sort));
+    }
+    let json = match args.take_value("--format")?.as_deref() {
+        None | Some("table") => false,
+        Some("json") => true,
+        Some(other) => anyhow::bail!("Invalid format '{}': expected table or json", other),
+    };
+    let input = args.require_positional("QUERY")?;
+    args.finish()?;
+
+    let mut select = match parse_query(&input)? {
+        ParsedSqlQuery::Select(select) => select,
+        ParsedSqlQuery::Mutate(_) => {
+            anyhow::bail!("Only SELECT queries are supported, use the repl for mutations")
+        }
+    };
+    if let Some(limit) = limit {
+        select.limit = limit;
+    }
+    for (attr, order) in sorts {
+        select = select.with_sort(Expr::attr_ident(&attr), order);
+    }
+
+    super::block_on(async move {
+        let db = super::connect(&target, token).await?;
+        let items = db.select(select).await?.take_data();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&items)?);
+        } else {
+            print!("{}", table::render_entities(&items));
+        }
+        Ok(())
+    })
+}
+
+/// Parse a SQL statement or a plain filter expression.
+///
+/// Input starting with SELECT, UPDATE or DELETE is parsed as a full
+/// statement, everything else as a filter.
+pub(super) fn parse_query(input: &str) -> Result<ParsedSqlQuery, SqlParseError> {
+    let first_word = input
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match first_word.as_str() {
+        "select" | "update" | "delete" => parse_sql(input),
+        _ => Ok(ParsedSqlQuery::Select(
+            Select::new().with_filter(Expr::parse_sql_filter(input)?),
+        )),
+    }
+}
+
+/// Parse a sort specification in the form `attr` or `attr:desc`.
+fn parse_sort(value: &str) -> (String, Order) {
+    match value.rsplit_once(':') {
+        Some((attr, order)) if order.eq_ignore_ascii_case("desc") => {
+            (attr.to_string(), Order::Desc)
+        }
+        Some((attr, order)) if order.eq_ignore_ascii_case("asc") => (attr.to_string(), Order::Asc),
+        _ => (value.to_string(), Order::Asc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query(r#""test/int" > 5"#).unwrap().as_select(),
+            Some(&Select::new().with_filter(Expr::gt(Expr::attr_ident("test/int"), 5u64)))
+        );
+        assert!(parse_query(r#"delete from entities where "test/int" > 5"#)
+            .unwrap()
+            .as_mutate()
+            .is_some());
+
+        assert_eq!(
+            parse_sort("test/int:desc"),
+            ("test/int".to_string(), Order::Desc)
+        );
+        assert_eq!(parse_sort("test/int"), ("test/int".to_string(), Order::Asc));
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::{BufRead, Write};
+
+use factor_core::{
+    db::Db,
+    query::{mutate::Mutate, sql::ParsedSqlQuery},
+    schema::DbSchema,
+};
+
+use super::{table, Args};
+
+const DEFAULT_LIMIT: u64 = 100;
+
+const HELP: &str = r#"Enter a SQL statement or a filter expression.
+
+Examples:
+  SELECT * FROM entities WHERE "factor/type" = 'my/Class' LIMIT 10
+  "factor/type" = 'my/Class' AND "my/attr" > 5
+
+Commands:
+  \d            List classes
+  \d <IDENT>    Describe a class or attribute
+  \da           List attributes
+  \limit <N>    Set the default result limit for queries (0 = unlimited)
+  \? \h         Show this help
+  \q            Quit
+"#;
+
+/// `repl [--token <TOKEN>] <DB_FILE | URL>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let token = args.take_value("--token")?;
+    let target = args.require_positional("DB")?;
+    args.finish()?;
+
+    super::block_on(async move {
+        let db = super::connect(&target, token).await?;
+        let mut repl = Repl {
+            db,
+            limit: DEFAULT_LIMIT,
+        };
+
+        eprintln!("Connected to '{}'. Type \\? for help.", target);
+
+        let stdin = std::io::stdin();
+        let mut line = String::new();
+        loop {
+            print!("factor> ");
+            std::io::stdout().flush()?;
+
+            line.clear();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                println!();
+                break;
+            }
+            let input = line.trim();
+            if input.is_empty() {
+                continue;
+            }
+
+            match repl.eval(input).await {
+                Ok(Some(output)) => print!("{}", output),
+                Ok(None) => break,
+                Err(err) => eprintln!("Error: {:#}", err),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+struct Repl {
+    db: Db,
+    limit: u64,
+}
+
+impl Repl {
+    /// Evaluate a single input line.
+    ///
+    /// Returns `None` if the repl should exit.
+    async fn eval(&mut self, input: &str) -> Result<Option<String>, anyhow::Error> {
+        if let Some(command) = input.strip_prefix('\\') {
+            return self.eval_command(command).await;
+        }
+
+        match super::query::parse_query(input)? {
+            ParsedSqlQuery::Select(mut select) => {
+                if select.limit == 0 {
+                    select.limit = self.limit;
+                }
+                let data = self.db.select(select).await?.take_data();
+                Ok(Some(table::render_entities(&data)))
+            }
+            ParsedSqlQuery::Mutate(mutate) => {
+                self.db.mutate(Mutate::Select(mutate)).await?;
+                Ok(Some("OK\n".to_string()))
+            }
+        }
+    }
+
+    async fn eval_command(&mut self, command: &str) -> Result<Option<String>, anyhow::Error> {
+        let mut parts = command.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        let arg = parts.next();
+        if parts.next().is_some() {
+            anyhow::bail!("Too many arguments for \\{}", name);
+        }
+
+        let output = match (name, arg) {
+            ("q", None) => return Ok(None),
+            ("?" | "h", None) => HELP.to_string(),
+            ("d", None) => list_classes(&self.db.schema().await?),
+            ("d", Some(ident)) => describe(&self.db.schema().await?, ident)?,
+            ("da", None) => list_attributes(&self.db.schema().await?),
+            ("limit", Some(limit)) => {
+                self.limit = limit
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid limit '{}'", limit))?;
+                format!("Limit set to {}\n", self.limit)
+            }
+            _ => anyhow::bail!("Unknown command \\{}. Type \\? for help.", command),
+        };
+        Ok(Some(output))
+    }
+}
+
+fn value_type_name(ty: &factor_core::data::ValueType) -> String {
+    serde_json::to_string(ty).unwrap_or_default()
+}
+
+fn list_classes(schema: &DbSchema) -> String {
+    let mut classes = schema.classes.iter().collect::<Vec<_>>();
+    classes.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+    let rows = classes
+        .into_iter()
+        .map(|class| {
+            vec![
+                class.ident.clone(),
+                class.title.clone().unwrap_or_default(),
+                class.attributes.len().to_string(),
+                class.extends.join(", "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table::render_table(
+        &[
+            "class".to_string(),
+            "title".to_string(),
+            "attributes".to_string(),
+            "extends".to_string(),
+        ],
+        &rows,
+    )
+}
+
+fn list_attributes(schema: &DbSchema) -> String {
+    let mut attrs = schema.attributes.iter().collect::<Vec<_>>();
+    attrs.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+    let rows = attrs
+        .into_iter()
+        .map(|attr| {
+            vec![
+                attr.ident.clone(),
+                value_type_name(&attr.value_type),
+                attr.unique.to_string(),
+                attr.index.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table::render_table(
+        &[
+            "attribute".to_string(),
+            "type".to_string(),
+            "unique".to_string(),
+            "index".to_string(),
+        ],
+        &rows,
+    )
+}
+
+/// Describe a class or an attribute.
+fn describe(schema: &DbSchema, ident: &str) -> Result<String, anyhow::Error> {
+    if let Some(class) = schema.class_by_ident(ident) {
+        let mut out = format!("Class {}\n", class.ident);
+        if let Some(description) = &class.description {
+            out.push_str(&format!("{}\n", description));
+        }
+        if !class.extends.is_empty() {
+            out.push_str(&format!("Extends: {}\n", class.extends.join(", ")));
+        }
+        out.push('\n');
+
+        let rows = class
+            .attributes
+            .iter()
+            .map(|field| {
+                let ty = schema
+                    .attr_by_ident(&field.attribute)
+                    .map(|a| value_type_name(&a.value_type))
+                    .unwrap_or_default();
+                vec![
+                    field.attribute.clone(),
+                    ty,
+                    field.required.to_string(),
+                    field
+                        .constraints
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ]
+            })
+            .collect::<Vec<_>>();
+        out.push_str(&table::render_table(
+            &[
+                "attribute".to_string(),
+                "type".to_string(),
+                "required".to_string(),
+                "constraints".to_string(),
+            ],
+            &rows,
+        ));
+        Ok(out)
+    } else if let Some(attr) = schema.attr_by_ident(ident) {
+        Ok(format!("{}\n", serde_json::to_string_pretty(attr)?))
+    } else {
+        anyhow::bail!("No class or attribute named '{}'", ident)
+    }
+}
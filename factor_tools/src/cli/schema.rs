@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use factor_core::schema::DbSchema;
+
+use crate::lint::LintSeverity;
+
+use super::Args;
+
+/// `schema <SUBCOMMAND>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let command = args.require_positional("SUBCOMMAND")?;
+    match command.as_str() {
+        "diff" => run_diff(args),
+        "lint" => run_lint(args),
+        other => anyhow::bail!("Unknown schema command '{}'", other),
+    }
+}
+
+/// `schema diff [--token <TOKEN>] [--emit-migration <FILE>] [--delete-removed] <OLD> <NEW>`
+fn run_diff(mut args: Args) -> Result<(), anyhow::Error> {
+    let token = args.take_value("--token")?;
+    let emit_migration = args.take_value("--emit-migration")?.map(PathBuf::from);
+    let delete_removed = args.take_flag("--delete-removed");
+    let old = args.require_positional("OLD")?;
+    let new = args.require_positional("NEW")?;
+    args.finish()?;
+
+    super::block_on(async move {
+        let old = load_schema(&old, token.clone()).await?;
+        let new = load_schema(&new, token).await?;
+        let diff = old.diff(&new);
+
+        if diff.is_empty() {
+            println!("Schemas are identical");
+        } else {
+            print!("{}", diff);
+        }
+
+        if let Some(path) = emit_migration {
+            let migration = diff.to_migration(delete_removed);
+            let json = serde_json::to_string_pretty(&migration)?;
+            std::fs::write(&path, json)
+                .with_context(|| format!("Could not write file '{}'", path.display()))?;
+        }
+
+        Ok(())
+    })
+}
+
+/// `schema lint [--token <TOKEN>] [--deny-warnings] <SCHEMA>`
+///
+/// Fails if any errors are found, or any warnings if `--deny-warnings` is set.
+fn run_lint(mut args: Args) -> Result<(), anyhow::Error> {
+    let token = args.take_value("--token")?;
+    let deny_warnings = args.take_flag("--deny-warnings");
+    let source = args.require_positional("SCHEMA")?;
+    args.finish()?;
+
+    let schema = super::block_on(load_schema(&source, token))?;
+    let violations = crate::lint::lint_schema(&schema);
+    for violation in &violations {
+        println!("{}", violation);
+    }
+
+    let failures = violations
+        .iter()
+        .filter(|v| deny_warnings || v.severity == LintSeverity::Error)
+        .count();
+    if failures > 0 {
+        anyhow::bail!("Schema lint failed with {} violations", failures);
+    }
+    if violations.is_empty() {
+        println!("No issues found");
+    }
+    Ok(())
+}
+
+/// Load a schema from a schema file, a database file or a database url.
+///
+/// Files ending in `.json` are treated as schema files, which can either
+/// be a static schema definition or a serialized [`DbSchema`].
+/// Builtin items are excluded.
+async fn load_schema(source: &str, token: Option<String>) -> Result<DbSchema, anyhow::Error> {
+    if source.contains("://") || !source.ends_with(".json") {
+        let db = super::connect(source, token).await?;
+        return Ok(db.schema().await?.without_builtins());
+    }
+
+    let path = Path::new(source);
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read file '{}'", path.display()))?;
+    let value: serde_json::Value = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid JSON in '{}'", path.display()))?;
+
+    let schema = if value.get("factor/migrations").is_some() {
+        let schema = crate::schema_file::parse_static_schema(&contents)
+            .with_context(|| format!("Invalid schema file '{}'", path.display()))?;
+        crate::schema_file::build_db_schema(&schema)?
+    } else {
+        serde_json::from_value::<DbSchema>(value)
+            .with_context(|| format!("Invalid schema file '{}'", path.display()))?
+    };
+    Ok(schema.without_builtins())
+}
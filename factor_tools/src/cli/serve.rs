@@ -0,0 +1,69 @@
+use std::net::SocketAddr;
+
+use crate::server::{ApiKey, JwtConfig, ServerConfig};
+
+use super::Args;
+
+/// `serve [--bind <ADDR>] [--token <TOKEN>] [--api-key <KEY>]... [--read-key <KEY>]...
+///  [--jwt-secret <SECRET>] [--jwt-claim <NAME>]... [--max-body-size <BYTES>] [--read-only]
+///  <DB_FILE>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let bind: SocketAddr = match args.take_parsed("--bind")? {
+        Some(addr) => addr,
+        None => ServerConfig::DEFAULT_BIND.parse()?,
+    };
+    let auth_token = match args.take_value("--token")? {
+        Some(token) => Some(token),
+        None => std::env::var("FACTOR_SERVE_TOKEN").ok(),
+    };
+    let mut api_keys = Vec::new();
+    while let Some(key) = args.take_value("--api-key")? {
+        api_keys.push(ApiKey::new(key));
+    }
+    while let Some(key) = args.take_value("--read-key")? {
+        api_keys.push(ApiKey::new(key).with_read_only(true));
+    }
+    let mut jwt_claims = Vec::new();
+    while let Some(claim) = args.take_value("--jwt-claim")? {
+        jwt_claims.push(claim);
+    }
+    let jwt = match args.take_value("--jwt-secret")? {
+        Some(secret) => Some(secret),
+        None => std::env::var("FACTOR_SERVE_JWT_SECRET").ok(),
+    }
+    .map(|secret| JwtConfig {
+        variables: jwt_claims,
+        ..JwtConfig::new(secret)
+    });
+    let max_body_size = args
+        .take_parsed("--max-body-size")?
+        .unwrap_or(ServerConfig::DEFAULT_MAX_BODY_SIZE);
+    let read_only = args.take_flag("--read-only");
+    let db_path = args.require_positional("DB_FILE")?;
+    args.finish()?;
+
+    let config = ServerConfig {
+        bind,
+        auth_token,
+        api_keys,
+        jwt,
+        read_only,
+        max_body_size,
+    };
+
+    super::block_on(async move {
+        let db = super::open_db(db_path.as_ref()).await?;
+
+        eprintln!(
+            "Serving '{}' on http://{}{}",
+            db_path,
+            config.bind,
+            if config.read_only { " (read-only)" } else { "" }
+        );
+
+        let shutdown = async {
+            tokio::signal::ctrl_c().await.ok();
+        };
+        crate::server::serve(db, config, shutdown).await
+    })
+}
@@ -0,0 +1,186 @@
+use factor_engine::{backend::BackendStats, Engine, IndexSuggestion};
+
+use super::{table, Args};
+
+/// `stats [--format <table|json>] <DB_FILE>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let json = match args.take_value("--format")?.as_deref() {
+        None | Some("table") => false,
+        Some("json") => true,
+        Some(other) => anyhow::bail!("Invalid format '{}': expected table or json", other),
+    };
+    let path = std::path::PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    if !path.is_file() {
+        anyhow::bail!("Database file '{}' does not exist", path.display());
+    }
+
+    super::block_on(async move {
+        let log = super::open_log(&path).await?;
+        let engine = Engine::new(log);
+        let stats = engine.stats().await?;
+        let index_suggestions = engine.index_suggestions().await?;
+
+        if json {
+            let output = StatsOutput {
+                stats: &stats,
+                index_suggestions: &index_suggestions,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            print!("{}", render_stats(&stats));
+            print!("{}", render_index_suggestions(&index_suggestions));
+        }
+        Ok(())
+    })
+}
+
+/// JSON output of the `stats` command.
+#[derive(serde::Serialize)]
+struct StatsOutput<'a> {
+    #[serde(flatten)]
+    stats: &'a BackendStats,
+    index_suggestions: &'a [IndexSuggestion],
+}
+
+fn render_stats(stats: &BackendStats) -> String {
+    let optional_bytes = |v: Option<u64>| v.map(format_bytes).unwrap_or_else(|| "-".to_string());
+
+    let mut out = format!("Entities:          {}\n", stats.entity_count);
+    out.push_str(&format!(
+        "Memory (estimate): {}\n",
+        optional_bytes(stats.memory_usage)
+    ));
+    out.push_str(&format!(
+        "Log size:          {}\n",
+        optional_bytes(stats.size_log)
+    ));
+    out.push_str(&format!(
+        "Data size:         {}\n",
+        optional_bytes(stats.size_data)
+    ));
+    if let (Some(log), Some(data)) = (stats.size_log, stats.size_data) {
+        if log > 0 {
+            out.push_str(&format!(
+                "Log overhead:      {:.1}%\n",
+                (log.saturating_sub(data) as f64 / log as f64) * 100.0
+            ));
+        }
+    }
+
+    let classes = stats
+        .entities_by_class
+        .iter()
+        .map(|(class, count)| vec![class.clone(), count.to_string()])
+        .collect::<Vec<_>>();
+    out.push('\n');
+    out.push_str(&table::render_table(
+        &["class".to_string(), "entities".to_string()],
+        &classes,
+    ));
+
+    let mut indexes = stats.indexes.iter().collect::<Vec<_>>();
+    indexes.sort_by(|a, b| a.ident.cmp(&b.ident));
+    let indexes = indexes
+        .into_iter()
+        .map(|index| {
+            vec![
+                index.ident.clone(),
+                index.unique.to_string(),
+                index.entries.to_string(),
+                format_bytes(index.memory_usage),
+            ]
+        })
+        .collect::<Vec<_>>();
+    out.push('\n');
+    out.push_str(&table::render_table(
+        &[
+            "index".to_string(),
+            "unique".to_string(),
+            "entries".to_string(),
+            "memory".to_string(),
+        ],
+        &indexes,
+    ));
+
+    out
+}
+
+/// Renders nothing if there are no suggestions.
+fn render_index_suggestions(suggestions: &[IndexSuggestion]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    let rows = suggestions
+        .iter()
+        .map(|suggestion| {
+            vec![
+                suggestion.attribute.clone(),
+                suggestion.index.ident.clone(),
+                suggestion.scans.to_string(),
+                suggestion.estimated_benefit.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let mut out = "\nIndex suggestions:\n".to_string();
+    out.push_str(&table::render_table(
+        &[
+            "attribute".to_string(),
+            "index".to_string(),
+            "scans".to_string(),
+            "benefit".to_string(),
+        ],
+        &rows,
+    ));
+    out
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(10), "10 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_render_index_suggestions() {
+        assert_eq!(render_index_suggestions(&[]), "");
+
+        let suggestion = IndexSuggestion {
+            attribute: "test/email".to_string(),
+            index: factor_core::schema::IndexSchema::new(
+                "test",
+                "email_index",
+                vec![factor_core::data::Id::nil()],
+            ),
+            scans: 12,
+            estimated_benefit: 1200,
+        };
+        let out = render_index_suggestions(&[suggestion]);
+        assert!(out.contains("test/email_index"));
+        assert!(out.contains("1200"));
+    }
+}
@@ -0,0 +1,41 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::remote::HttpDbClient;
+
+use super::Args;
+
+/// `sync [--token <TOKEN>] [--source <NAME>] [--interval <SECONDS>] <URL> <DB_FILE>`
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let token = args.take_value("--token")?;
+    let source = args.take_value("--source")?;
+    let interval = args
+        .take_parsed::<u64>("--interval")?
+        .map(Duration::from_secs);
+    let url = args.require_positional("URL")?;
+    let db_path = PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    let source = source.unwrap_or_else(|| url.clone());
+    let client = HttpDbClient::new(&url, token)?;
+
+    super::block_on(async move {
+        let log = super::open_log(&db_path).await?;
+        loop {
+            let stats = crate::sync::pull(&client, &source, &log).await?;
+            if stats.full_resync {
+                eprintln!(
+                    "The log of '{}' was compacted or purged, the database was synced again from the start",
+                    source
+                );
+            }
+            eprintln!(
+                "Applied {} events from '{}' (at event {})",
+                stats.events, source, stats.cursor
+            );
+            match interval {
+                Some(interval) => tokio::time::sleep(interval).await,
+                None => return Ok(()),
+            }
+        }
+    })
+}
@@ -0,0 +1,114 @@
+use factor_core::data::{DataMap, Value};
+
+/// Maximum width of a single cell.
+const MAX_CELL_WIDTH: usize = 40;
+
+/// Render entities as a text table.
+///
+/// Columns are the union of all attributes, with `factor/id` first.
+pub fn render_entities(items: &[DataMap]) -> String {
+    let mut columns = Vec::<String>::new();
+    for item in items {
+        for key in item.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    columns.sort_by(|a, b| (a != "factor/id", a).cmp(&(b != "factor/id", b)));
+
+    let rows = items
+        .iter()
+        .map(|item| {
+            columns
+                .iter()
+                .map(|col| item.get(col).map(format_value).unwrap_or_default())
+                .collect()
+        })
+        .collect::<Vec<Vec<String>>>();
+
+    render_table(&columns, &rows)
+}
+
+/// Render a text table with the given header and rows.
+pub fn render_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let cells = |row: &[String]| -> Vec<String> { row.iter().map(|c| truncate(c)).collect() };
+
+    let header = cells(header);
+    let rows = rows.iter().map(|r| cells(r)).collect::<Vec<_>>();
+
+    let widths = (0..header.len())
+        .map(|index| {
+            rows.iter()
+                .filter_map(|r| r.get(index))
+                .chain(std::iter::once(&header[index]))
+                .map(|c| c.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let format_row = |row: &[String]| -> String {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        format!("{}\n", line.trim_end())
+    };
+
+    let mut out = format_row(&header);
+    out.push_str(
+        &widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    out.push('\n');
+    for row in &rows {
+        out.push_str(&format_row(row));
+    }
+    out.push_str(&format!(
+        "({} {})\n",
+        rows.len(),
+        if rows.len() == 1 { "row" } else { "rows" }
+    ));
+    out
+}
+
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Id(id) => id.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+fn truncate(value: &str) -> String {
+    let value = value.replace('\n', " ");
+    if value.chars().count() > MAX_CELL_WIDTH {
+        let mut s = value.chars().take(MAX_CELL_WIDTH - 3).collect::<String>();
+        s.push_str("...");
+        s
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::map;
+
+    use super::*;
+
+    #[test]
+    fn test_render_entities() {
+        let out = render_entities(&[map! { "b": 1u64, "a": "x" }, map! { "a": "long value" }]);
+        assert_eq!(
+            out,
+            "a          | b\n-----------+--\nx          | 1\nlong value |\n(2 rows)\n"
+        );
+    }
+}
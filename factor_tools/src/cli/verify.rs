@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use factor_engine::backend::{
+    log::{convert_json::JsonConverter, store_file::FileLogStore},
+    Backend, VerifyIssue,
+};
+
+use super::Args;
+
+/// `verify [--repair] <DB_FILE>`
+///
+/// Log issues are repaired by rewriting the log from the state that could be
+/// restored, which drops all events after the first invalid one.
+/// Index and entity issues are only reported.
+pub fn run(mut args: Args) -> Result<(), anyhow::Error> {
+    let repair = args.take_flag("--repair");
+    let path = PathBuf::from(args.require_positional("DB_FILE")?);
+    args.finish()?;
+
+    if !path.is_file() {
+        anyhow::bail!("Database file '{}' does not exist", path.display());
+    }
+
+    super::block_on(async move {
+        let store = FileLogStore::open(JsonConverter, &path)
+            .await
+            .with_context(|| format!("Could not open database file '{}'", path.display()))?;
+        let log_issues = store.verify_events().await?;
+        drop(store);
+        print_issues(&log_issues);
+
+        let log = super::open_log(&path).await?;
+        let data_issues = Backend::verify(&log).await?;
+        print_issues(&data_issues);
+
+        if log_issues.is_empty() && data_issues.is_empty() {
+            println!("No issues found");
+            return Ok(());
+        }
+
+        if !repair {
+            anyhow::bail!("Found {} issues", log_issues.len() + data_issues.len());
+        }
+
+        if !log_issues.is_empty() {
+            let mut backup = path.clone().into_os_string();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            std::fs::copy(&path, &backup)
+                .with_context(|| format!("Could not create backup '{}'", backup.display()))?;
+
+            let stats = log.compact().await?;
+            println!(
+                "Rewrote the log with {} events (backup: '{}')",
+                stats.events_after,
+                backup.display()
+            );
+        }
+
+        if !data_issues.is_empty() {
+            anyhow::bail!(
+                "{} index or entity issues can not be repaired automatically",
+                data_issues.len()
+            );
+        }
+        Ok(())
+    })
+}
+
+fn print_issues(issues: &[VerifyIssue]) {
+    for issue in issues {
+        println!("{}", issue);
+    }
+}
@@ -0,0 +1,134 @@
+//! Resolved schema shared by the code generators.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use factor_core::schema::{Attribute, Class, ClassAttribute, StaticSchema};
+
+/// A static schema with all migrations applied, indexed by ident.
+#[derive(Default)]
+pub struct ResolvedSchema {
+    pub attributes: HashMap<String, Attribute>,
+    pub classes: HashMap<String, Class>,
+    /// Idents of items that can be referenced, but should not be generated.
+    pub external: HashSet<String>,
+}
+
+impl ResolvedSchema {
+    /// Resolve a static schema.
+    ///
+    /// If `with_builtins` is true, the builtin attributes and classes are
+    /// available as external items.
+    pub fn build(schema: &StaticSchema, with_builtins: bool) -> Result<Self, anyhow::Error> {
+        let loaded = crate::schema_file::build_db_schema(schema)?;
+
+        let mut resolved = Self::default();
+        for attr in loaded.attributes {
+            resolved.attributes.insert(attr.ident.clone(), attr);
+        }
+        for class in loaded.classes {
+            resolved.classes.insert(class.ident.clone(), class);
+        }
+
+        if with_builtins {
+            let builtins = factor_core::schema::builtin::builtin_db_schema();
+            for attr in builtins.attributes {
+                resolved.external.insert(attr.ident.clone());
+                resolved.attributes.insert(attr.ident.clone(), attr);
+            }
+            for class in builtins.classes {
+                resolved.external.insert(class.ident.clone());
+                resolved.classes.insert(class.ident.clone(), class);
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Attributes to generate code for, sorted by ident.
+    pub fn local_attributes(&self) -> Vec<&Attribute> {
+        let mut attrs = self
+            .attributes
+            .values()
+            .filter(|a| !self.external.contains(&a.ident))
+            .collect::<Vec<_>>();
+        attrs.sort_by(|a, b| a.ident.cmp(&b.ident));
+        attrs
+    }
+
+    /// Classes to generate code for, sorted by ident.
+    pub fn local_classes(&self) -> Vec<&Class> {
+        let mut classes = self
+            .classes
+            .values()
+            .filter(|c| !self.external.contains(&c.ident))
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.ident.cmp(&b.ident));
+        classes
+    }
+
+    pub fn parent(&self, class: &Class, parent_ident: &str) -> Result<&Class, anyhow::Error> {
+        self.classes.get(parent_ident).with_context(|| {
+            format!(
+                "Invalid class '{}': parent '{}' not found",
+                class.ident, parent_ident
+            )
+        })
+    }
+
+    pub fn class_attribute(
+        &self,
+        class: &Class,
+        attr_ident: &str,
+    ) -> Result<&Attribute, anyhow::Error> {
+        self.attributes.get(attr_ident).with_context(|| {
+            format!(
+                "Invalid class '{}': attribute '{}' not found",
+                class.ident, attr_ident
+            )
+        })
+    }
+
+    /// All fields of a class, including the fields of parent classes.
+    ///
+    /// Parent fields come first. If a field is declared multiple times, the
+    /// declaration closest to the class wins.
+    pub fn all_fields<'a>(
+        &'a self,
+        class: &'a Class,
+    ) -> Result<Vec<(&'a ClassAttribute, &'a Attribute)>, anyhow::Error> {
+        let mut fields = Vec::new();
+        self.collect_fields(class, &mut fields, &mut Vec::new())?;
+        Ok(fields)
+    }
+
+    fn collect_fields<'a>(
+        &'a self,
+        class: &'a Class,
+        fields: &mut Vec<(&'a ClassAttribute, &'a Attribute)>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<(), anyhow::Error> {
+        if stack.contains(&class.ident.as_str()) {
+            anyhow::bail!("Invalid class '{}': circular inheritance", class.ident);
+        }
+        stack.push(&class.ident);
+
+        for parent_ident in &class.extends {
+            let parent = self.parent(class, parent_ident)?;
+            self.collect_fields(parent, fields, stack)?;
+        }
+        for field in &class.attributes {
+            let attr = self.class_attribute(class, &field.attribute)?;
+            match fields
+                .iter_mut()
+                .find(|(f, _)| f.attribute == field.attribute)
+            {
+                Some(existing) => *existing = (field, attr),
+                None => fields.push((field, attr)),
+            }
+        }
+
+        stack.pop();
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+//! Backup and restore of databases as JSON-lines.
+//!
+//! A dump contains one [`DumpRecord`] per line.
+//! All applied migrations come first, followed by the entities.
+//! The schema is stored as the list of migrations, so restoring a dump also
+//! restores the migration history.
+
+use std::io::{BufRead, Write};
+
+use anyhow::Context;
+use factor_core::{
+    data::DataMap,
+    db::Db,
+    query::{
+        expr::Expr,
+        migrate::Migration,
+        mutate::{Batch, Mutate},
+        select::{Order, Select},
+    },
+    schema::{builtin::AttrId, AttrMapExt, AttributeMeta},
+};
+use factor_engine::backend::{log::LogDb, Backend};
+
+/// Number of entities loaded per query or batch.
+const CHUNK_SIZE: u64 = 1000;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DumpRecord {
+    Migration { migration: Migration },
+    Entity { data: DataMap },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DumpStats {
+    pub migrations: u64,
+    pub entities: u64,
+}
+
+/// Write all migrations and entities of the database to `writer`.
+///
+/// Entities are loaded in chunks ordered by id and written as they are
+/// loaded.
+pub async fn dump(db: &Db, mut writer: impl Write) -> Result<DumpStats, anyhow::Error> {
+    let mut stats = DumpStats::default();
+
+    for migration in db.migrations().await? {
+        write_record(&mut writer, &DumpRecord::Migration { migration })?;
+        stats.migrations += 1;
+    }
+
+    // Page by id instead of by offset, so entities created or deleted
+    // while dumping do not shift the pages and skip or repeat entities.
+    let mut last_id = None;
+    loop {
+        let mut query = Select::new()
+            .with_sort(AttrId::expr(), Order::Asc)
+            .with_limit(CHUNK_SIZE);
+        if let Some(last_id) = last_id {
+            query = query.with_filter(Expr::gt(AttrId::expr(), last_id));
+        }
+        let items = db.select_map(query).await?;
+        let count = items.len() as u64;
+        let last = items
+            .last()
+            .map(|data| data.get_id().context("Entity without id"))
+            .transpose()?;
+
+        for data in items {
+            write_record(&mut writer, &DumpRecord::Entity { data })?;
+        }
+        stats.entities += count;
+
+        if count < CHUNK_SIZE {
+            break;
+        }
+        last_id = last;
+    }
+
+    writer.flush()?;
+    Ok(stats)
+}
+
+fn write_record(writer: &mut impl Write, record: &DumpRecord) -> Result<(), anyhow::Error> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Restore a dump created with [`dump`] into an empty database.
+///
+/// Entities are imported with [`LogDb::bulk_load`], so references between
+/// them are not validated.
+pub async fn restore(db: &LogDb, reader: impl BufRead) -> Result<DumpStats, anyhow::Error> {
+    if !Backend::migrations(db).await?.is_empty()
+        || !Backend::select_map(db, Select::new().with_limit(1))
+            .await?
+            .is_empty()
+    {
+        anyhow::bail!("Can only restore into an empty database");
+    }
+
+    let mut stats = DumpStats::default();
+    let mut batch = Batch::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: DumpRecord = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record on line {}", index + 1))?;
+
+        match record {
+            DumpRecord::Migration { migration } => {
+                if stats.entities > 0 {
+                    anyhow::bail!(
+                        "Invalid dump: migration on line {} follows entity data",
+                        index + 1
+                    );
+                }
+                Backend::migrate(db, migration)
+                    .await
+                    .with_context(|| format!("Could not apply migration on line {}", index + 1))?;
+                stats.migrations += 1;
+            }
+            DumpRecord::Entity { data } => {
+                let id = data
+                    .get_id()
+                    .with_context(|| format!("Entity on line {} has no id", index + 1))?;
+                batch.actions.push(Mutate::create(id, data));
+                stats.entities += 1;
+
+                if batch.actions.len() as u64 >= CHUNK_SIZE {
+                    db.bulk_load(std::mem::replace(&mut batch, Batch::new()))
+                        .await?;
+                }
+            }
+        }
+    }
+
+    if !batch.actions.is_empty() {
+        db.bulk_load(batch).await?;
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::{Id, Value, ValueType},
+        map,
+        schema::{Attribute, Class},
+    };
+    use factor_engine::{backend::log::store_memory::MemoryLogStore, Engine};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dump_restore_roundtrip() {
+        let source = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let source = Engine::new(source).into_client();
+        source
+            .migrate(Migration::new().attr_create(Attribute::new("test/text", ValueType::String)))
+            .await
+            .unwrap();
+        let id = Id::random();
+        source
+            .create(id, map! { "test/text": "hello" })
+            .await
+            .unwrap();
+
+        let mut out = Vec::new();
+        let stats = dump(&source, &mut out).await.unwrap();
+        assert_eq!(
+            DumpStats {
+                migrations: 1,
+                entities: 1
+            },
+            stats
+        );
+
+        let target = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let restored = restore(&target, out.as_slice()).await.unwrap();
+        assert_eq!(stats, restored);
+
+        let target = Engine::new(target).into_client();
+        assert_eq!(
+            source.entity(id).await.unwrap(),
+            target.entity(id).await.unwrap()
+        );
+        assert_eq!(
+            source.migrations().await.unwrap(),
+            target.migrations().await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_restore_computed_attributes() {
+        let source = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let source = Engine::new(source).into_client();
+        source
+            .migrate(
+                Migration::new()
+                    .attr_create(Attribute::new("test/name", ValueType::String))
+                    .entity_create(
+                        Class::new("test/Person")
+                            .with_attribute("test/name", true)
+                            .with_computed_attribute(
+                                "test/greeting",
+                                Expr::concat("Hello ", Expr::attr_ident("test/name")),
+                            ),
+                    ),
+            )
+            .await
+            .unwrap();
+        let id = Id::random();
+        source
+            .create(
+                id,
+                map! { "factor/type": "test/Person", "test/name": "Ada" },
+            )
+            .await
+            .unwrap();
+
+        let mut out = Vec::new();
+        dump(&source, &mut out).await.unwrap();
+        let target = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        restore(&target, out.as_slice()).await.unwrap();
+
+        let target = Engine::new(target).into_client();
+        let data = target.entity(id).await.unwrap();
+        assert_eq!(source.entity(id).await.unwrap(), data);
+        assert_eq!(Some(&Value::from("Hello Ada")), data.get("test/greeting"));
+    }
+
+    #[tokio::test]
+    async fn test_dump_multiple_chunks() {
+        let source = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let source = Engine::new(source).into_client();
+        let count = CHUNK_SIZE + 1;
+        let mut batch = Batch::new();
+        for _ in 0..count {
+            batch
+                .actions
+                .push(Mutate::create(Id::random(), map! { "factor/title": "a" }));
+        }
+        source.batch(batch).await.unwrap();
+
+        let mut out = Vec::new();
+        let stats = dump(&source, &mut out).await.unwrap();
+        assert_eq!(count, stats.entities);
+
+        // Every entity is written exactly once.
+        let ids = out
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| match serde_json::from_slice(line).unwrap() {
+                DumpRecord::Entity { data } => data.get_id().unwrap(),
+                other => panic!("unexpected record: {:?}", other),
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(count, ids.len() as u64);
+    }
+}
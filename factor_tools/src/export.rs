@@ -0,0 +1,522 @@
+//! Bulk export of entities to CSV and Parquet.
+//!
+//! Entities are loaded in chunks and written as they are loaded, so large
+//! exports don't need to fit into memory.
+//! Each exported attribute becomes a column, and the column types are
+//! derived from the attribute types of the schema.
+
+use std::{io::Write, sync::Arc};
+
+use anyhow::Context;
+use factor_core::{
+    data::{DataMap, Value, ValueType},
+    db::Db,
+    query::{
+        expr::Expr,
+        select::{Order, Select},
+    },
+    schema::{
+        builtin::{AttrId, AttrType},
+        AttributeMeta, DbSchema,
+    },
+};
+use parquet::{
+    basic::{ConvertedType, Repetition, Type as PhysicalType},
+    column::writer::ColumnWriterImpl,
+    data_type::{BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+
+/// Number of entities loaded per query.
+///
+/// Each chunk is written as a separate Parquet row group.
+const CHUNK_SIZE: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub const ALL: &'static [Self] = &[Self::Csv, Self::Parquet];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            other => Err(anyhow::anyhow!(
+                "Unknown export format '{}': expected one of {}",
+                other,
+                Self::ALL
+                    .iter()
+                    .map(|f| f.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportColumn {
+    pub attribute: String,
+    pub value_type: ValueType,
+}
+
+/// The entities and columns of an export.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Export {
+    pub query: Select,
+    pub columns: Vec<ExportColumn>,
+}
+
+impl Export {
+    /// Export all entities of a class, including entities of child classes.
+    ///
+    /// The columns are the id and type, followed by all attributes of the
+    /// class and its parents.
+    pub fn class(schema: &DbSchema, class: &str) -> Result<Self, anyhow::Error> {
+        if schema.class_by_ident(class).is_none() {
+            anyhow::bail!("Class '{}' not found", class);
+        }
+
+        let mut attributes = vec![
+            AttrId::QUALIFIED_NAME.to_string(),
+            AttrType::QUALIFIED_NAME.to_string(),
+        ];
+        collect_class_attributes(schema, class, &mut attributes)?;
+
+        let query = Select::new().with_filter(Expr::InheritsEntityType(class.to_string()));
+        Self::select(schema, query, &attributes)
+    }
+
+    /// Export the result of a select, with one column per attribute.
+    pub fn select(
+        schema: &DbSchema,
+        query: Select,
+        attributes: &[String],
+    ) -> Result<Self, anyhow::Error> {
+        if attributes.is_empty() {
+            anyhow::bail!("An export needs at least one column");
+        }
+        let columns = attributes
+            .iter()
+            .map(|ident| {
+                let attr = schema
+                    .attr_by_ident(ident)
+                    .with_context(|| format!("Attribute '{}' not found", ident))?;
+                Ok(ExportColumn {
+                    attribute: attr.ident.clone(),
+                    value_type: attr.value_type.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        Ok(Self { query, columns })
+    }
+}
+
+/// Collect the attributes of a class and its parents, parents first.
+fn collect_class_attributes(
+    schema: &DbSchema,
+    class: &str,
+    out: &mut Vec<String>,
+) -> Result<(), anyhow::Error> {
+    let class = schema
+        .class_by_ident(class)
+        .with_context(|| format!("Class '{}' not found", class))?;
+    for parent in &class.extends {
+        collect_class_attributes(schema, parent, out)?;
+    }
+    for attr in &class.attributes {
+        if !out.contains(&attr.attribute) {
+            out.push(attr.attribute.clone());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportStats {
+    pub rows: u64,
+}
+
+/// Write the entities of an export to `writer`.
+///
+/// Limit and offset of the export query are respected.
+/// Queries without a sort order are sorted by id, so that the chunks are
+/// stable.
+pub async fn export(
+    db: &Db,
+    export: &Export,
+    format: ExportFormat,
+    writer: impl Write,
+) -> Result<ExportStats, anyhow::Error> {
+    let mut sink = match format {
+        ExportFormat::Csv => Sink::Csv(csv_writer(writer, &export.columns)?),
+        ExportFormat::Parquet => Sink::Parquet(parquet_writer(writer, &export.columns)?),
+    };
+
+    let mut query = export.query.clone();
+    if query.sort.is_empty() {
+        query = query.with_sort(AttrId::expr(), Order::Asc);
+    }
+
+    let mut stats = ExportStats::default();
+    loop {
+        let chunk_size = if export.query.limit > 0 {
+            CHUNK_SIZE.min(export.query.limit - stats.rows)
+        } else {
+            CHUNK_SIZE
+        };
+        if chunk_size == 0 {
+            break;
+        }
+
+        let chunk = query
+            .clone()
+            .with_limit(chunk_size)
+            .with_offset(export.query.offset + stats.rows);
+        let rows = db.select_map(chunk).await?;
+        let count = rows.len() as u64;
+
+        match &mut sink {
+            Sink::Csv(writer) => write_csv_rows(writer, &export.columns, &rows)?,
+            Sink::Parquet(writer) => write_parquet_rows(writer, &export.columns, &rows)?,
+        }
+        stats.rows += count;
+
+        if count < chunk_size {
+            break;
+        }
+    }
+
+    match sink {
+        Sink::Csv(mut writer) => writer.flush()?,
+        Sink::Parquet(writer) => {
+            writer.close()?;
+        }
+    }
+    Ok(stats)
+}
+
+enum Sink<W: Write> {
+    Csv(csv::Writer<W>),
+    Parquet(SerializedFileWriter<W>),
+}
+
+fn csv_writer<W: Write>(
+    writer: W,
+    columns: &[ExportColumn],
+) -> Result<csv::Writer<W>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(columns.iter().map(|c| c.attribute.as_str()))?;
+    Ok(writer)
+}
+
+fn write_csv_rows<W: Write>(
+    writer: &mut csv::Writer<W>,
+    columns: &[ExportColumn],
+    rows: &[DataMap],
+) -> Result<(), anyhow::Error> {
+    for row in rows {
+        let record = columns
+            .iter()
+            .map(|column| csv_cell(row.get(column.attribute.as_str())))
+            .collect::<Result<Vec<_>, _>>()?;
+        writer.write_record(&record)?;
+    }
+    Ok(())
+}
+
+/// Render a value as a CSV cell.
+///
+/// Missing values become empty cells, and lists, maps and bytes are encoded
+/// as JSON.
+fn csv_cell(value: Option<&Value>) -> Result<String, anyhow::Error> {
+    let cell = match value {
+        None | Some(Value::Unit) => String::new(),
+        Some(Value::Bool(v)) => v.to_string(),
+        Some(Value::UInt(v)) => v.to_string(),
+        Some(Value::Int(v)) => v.to_string(),
+        Some(Value::Float(v)) => v.to_string(),
+        Some(Value::String(v)) => v.clone(),
+        Some(Value::Id(v)) => v.to_string(),
+        Some(other @ (Value::Bytes(_) | Value::List(_) | Value::Map(_))) => {
+            serde_json::to_string(other)?
+        }
+    };
+    Ok(cell)
+}
+
+/// Parquet representation of a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParquetKind {
+    Bool,
+    Int,
+    UInt,
+    Float,
+    Timestamp,
+    String,
+    Bytes,
+    /// Values without a matching Parquet type are stored as JSON strings.
+    Json,
+}
+
+impl ParquetKind {
+    fn for_type(ty: &ValueType) -> Self {
+        match ty {
+            ValueType::Bool => Self::Bool,
+            ValueType::Int => Self::Int,
+            ValueType::UInt => Self::UInt,
+            ValueType::Float => Self::Float,
+            ValueType::DateTime => Self::Timestamp,
+            ValueType::String
+            | ValueType::Url
+            | ValueType::Ref
+            | ValueType::RefConstrained(_)
+            | ValueType::Ident(_) => Self::String,
+            ValueType::Bytes => Self::Bytes,
+            _ => Self::Json,
+        }
+    }
+
+    fn parquet_type(self, name: &str) -> Result<Type, anyhow::Error> {
+        let (physical, converted) = match self {
+            Self::Bool => (PhysicalType::BOOLEAN, ConvertedType::NONE),
+            Self::Int => (PhysicalType::INT64, ConvertedType::NONE),
+            Self::UInt => (PhysicalType::INT64, ConvertedType::UINT_64),
+            Self::Float => (PhysicalType::DOUBLE, ConvertedType::NONE),
+            Self::Timestamp => (PhysicalType::INT64, ConvertedType::TIMESTAMP_MILLIS),
+            Self::String => (PhysicalType::BYTE_ARRAY, ConvertedType::UTF8),
+            Self::Bytes => (PhysicalType::BYTE_ARRAY, ConvertedType::NONE),
+            Self::Json => (PhysicalType::BYTE_ARRAY, ConvertedType::JSON),
+        };
+        let ty = Type::primitive_type_builder(name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_converted_type(converted)
+            .build()?;
+        Ok(ty)
+    }
+}
+
+fn parquet_writer<W: Write>(
+    writer: W,
+    columns: &[ExportColumn],
+) -> Result<SerializedFileWriter<W>, anyhow::Error> {
+    let mut fields = columns
+        .iter()
+        .map(|column| {
+            ParquetKind::for_type(&column.value_type)
+                .parquet_type(&column.attribute)
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let schema = Type::group_type_builder("schema")
+        .with_fields(&mut fields)
+        .build()?;
+    let properties = WriterProperties::builder().build();
+    let writer = SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(properties))?;
+    Ok(writer)
+}
+
+/// Write rows as a single row group.
+fn write_parquet_rows<W: Write>(
+    writer: &mut SerializedFileWriter<W>,
+    columns: &[ExportColumn],
+    rows: &[DataMap],
+) -> Result<(), anyhow::Error> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let mut row_group = writer.next_row_group()?;
+    for column in columns {
+        let mut column_writer = row_group
+            .next_column()?
+            .context("Parquet schema does not match the export columns")?;
+        let attr = column.attribute.as_str();
+        match ParquetKind::for_type(&column.value_type) {
+            ParquetKind::Bool => {
+                write_parquet_column::<BoolType>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::Bool(v) => Some(*v),
+                        _ => None,
+                    }
+                })
+            }
+            ParquetKind::Int => {
+                write_parquet_column::<Int64Type>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::Int(v) => Some(*v),
+                        Value::UInt(v) => i64::try_from(*v).ok(),
+                        _ => None,
+                    }
+                })
+            }
+            // Unsigned values are stored with the same bits, as defined by
+            // the Parquet format.
+            ParquetKind::UInt | ParquetKind::Timestamp => {
+                write_parquet_column::<Int64Type>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::UInt(v) => Some(*v as i64),
+                        Value::Int(v) if *v >= 0 => Some(*v),
+                        _ => None,
+                    }
+                })
+            }
+            ParquetKind::Float => {
+                write_parquet_column::<DoubleType>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::Float(v) => Some(v.into_inner()),
+                        Value::Int(v) => Some(*v as f64),
+                        Value::UInt(v) => Some(*v as f64),
+                        _ => None,
+                    }
+                })
+            }
+            ParquetKind::String => {
+                write_parquet_column::<ByteArrayType>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::String(v) => Some(ByteArray::from(v.as_str())),
+                        Value::Id(v) => Some(ByteArray::from(v.to_string().as_str())),
+                        _ => None,
+                    }
+                })
+            }
+            ParquetKind::Bytes => {
+                write_parquet_column::<ByteArrayType>(column_writer.typed(), attr, rows, |value| {
+                    match value {
+                        Value::Bytes(v) => Some(ByteArray::from(v.clone())),
+                        _ => None,
+                    }
+                })
+            }
+            ParquetKind::Json => {
+                write_parquet_column::<ByteArrayType>(column_writer.typed(), attr, rows, |value| {
+                    serde_json::to_vec(value).ok().map(ByteArray::from)
+                })
+            }
+        }?;
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+fn write_parquet_column<T: DataType>(
+    writer: &mut ColumnWriterImpl<'_, T>,
+    attribute: &str,
+    rows: &[DataMap],
+    convert: impl Fn(&Value) -> Option<T::T>,
+) -> Result<(), anyhow::Error> {
+    let mut values = Vec::with_capacity(rows.len());
+    let mut definition_levels = Vec::with_capacity(rows.len());
+    for row in rows {
+        match row.get(attribute) {
+            None | Some(Value::Unit) => definition_levels.push(0),
+            Some(value) => {
+                let value = convert(value).with_context(|| {
+                    format!(
+                        "Value {:?} of attribute '{}' does not match the column type",
+                        value, attribute
+                    )
+                })?;
+                values.push(value);
+                definition_levels.push(1);
+            }
+        }
+    }
+    writer.write_batch(&values, Some(&definition_levels), None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::Id,
+        map,
+        query::migrate::Migration,
+        schema::{Attribute, Class},
+    };
+    use factor_engine::{
+        backend::log::{store_memory::MemoryLogStore, LogDb},
+        Engine,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_class_csv() {
+        let log = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let db = Engine::new(log).into_client();
+        db.migrate(
+            Migration::new()
+                .attr_create(Attribute::new("test/name", ValueType::String))
+                .attr_create(Attribute::new(
+                    "test/tags",
+                    ValueType::List(Box::new(ValueType::String)),
+                ))
+                .entity_create(
+                    Class::new("test/Item")
+                        .with_attribute("test/name", true)
+                        .with_attribute("test/tags", false),
+                ),
+        )
+        .await
+        .unwrap();
+
+        let id = Id::random();
+        db.create(
+            id,
+            map! {
+                "factor/type": "test/Item",
+                "test/name": "a, b",
+                "test/tags": vec!["x"],
+            },
+        )
+        .await
+        .unwrap();
+
+        let spec = Export::class(&db.schema().await.unwrap(), "test/Item").unwrap();
+        let mut out = Vec::new();
+        let stats = export(&db, &spec, ExportFormat::Csv, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(stats, ExportStats { rows: 1 });
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "factor/id,factor/type,test/name,test/tags\n{},test/Item,\"a, b\",\"[\"\"x\"\"]\"\n",
+                id
+            )
+        );
+    }
+
+    #[test]
+    fn test_parquet_kind() {
+        assert_eq!(ParquetKind::for_type(&ValueType::UInt), ParquetKind::UInt);
+        assert_eq!(
+            ParquetKind::for_type(&ValueType::DateTime),
+            ParquetKind::Timestamp
+        );
+        assert_eq!(ParquetKind::for_type(&ValueType::Ref), ParquetKind::String);
+        assert_eq!(
+            ParquetKind::for_type(&ValueType::List(Box::new(ValueType::Int))),
+            ParquetKind::Json
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Random entities for seeding development databases and benchmarks.
+//!
+//! Values are generated from the schema: they match the attribute types and
+//! the [`ValueConstraint`]s of the class, and all required attributes are
+//! set.
+//! References point to generated entities of an allowed class. If no such
+//! entity exists yet, one is generated first, so the generated entities can
+//! be created in order in a single batch.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use factor_core::{
+    data::{DataMap, Id, IdOrIdent, Value, ValueMap, ValueType},
+    query::mutate::{Batch, Mutate},
+    schema::{
+        builtin::{AttrId, AttrType},
+        AttributeMeta, Class, ClassAttribute, DbSchema, ValueConstraint,
+    },
+};
+use rand::{seq::SliceRandom, Rng};
+
+/// Default length range of generated strings, bytes and lists.
+const DEFAULT_LEN: (u64, u64) = (4, 12);
+/// Default range of generated numbers.
+const DEFAULT_RANGE: (f64, f64) = (0.0, 1000.0);
+/// Probability that an optional attribute is set.
+const OPTIONAL_PROBABILITY: f64 = 0.75;
+
+/// Generate `n` random entities of `class`.
+///
+/// Entities of referenced classes that are needed to satisfy references are
+/// generated as well, and come before the entities that reference them.
+/// The result can be applied with [`into_batch`].
+///
+/// Unique attributes get random values, which can collide for types with
+/// a small value range, like booleans.
+///
+/// Fails if a value for a required attribute can not be generated. This is
+/// the case for [`ValueConstraint::Regex`], for references that would form
+/// a cycle, and for unconstrained references if no entity was generated
+/// yet.
+pub fn generate(schema: &DbSchema, class: &str, n: usize) -> Result<Vec<DataMap>, anyhow::Error> {
+    generate_with_rng(schema, class, n, &mut rand::thread_rng())
+}
+
+/// Like [`generate`], but with a custom random number generator.
+///
+/// Use a seeded generator for reproducible fixtures.
+pub fn generate_with_rng<R: Rng>(
+    schema: &DbSchema,
+    class: &str,
+    n: usize,
+    rng: &mut R,
+) -> Result<Vec<DataMap>, anyhow::Error> {
+    let mut gen = Generator {
+        schema,
+        rng,
+        generated: HashMap::new(),
+        entities: Vec::new(),
+        stack: Vec::new(),
+    };
+    for _ in 0..n {
+        gen.entity(class)?;
+    }
+    Ok(gen.entities)
+}
+
+/// Build a batch that creates the given entities in order.
+pub fn into_batch(entities: Vec<DataMap>) -> Batch {
+    entities
+        .into_iter()
+        .map(Mutate::create_from_map)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+struct Generator<'a, R> {
+    schema: &'a DbSchema,
+    rng: &'a mut R,
+    /// Ids of the generated entities by class.
+    generated: HashMap<String, Vec<Id>>,
+    entities: Vec<DataMap>,
+    /// Classes that are currently being generated.
+    ///
+    /// Used to detect required reference cycles.
+    stack: Vec<String>,
+}
+
+impl<'a, R: Rng> Generator<'a, R> {
+    fn entity(&mut self, class: &str) -> Result<Id, anyhow::Error> {
+        let schema = self.schema;
+        let class = schema
+            .class_by_ident(class)
+            .with_context(|| format!("Class '{}' not found", class))?;
+
+        self.stack.push(class.ident.clone());
+        let id = class.id_strategy.generate();
+        let mut data = DataMap::new();
+        data.insert(AttrId::QUALIFIED_NAME.to_string(), id.into());
+        data.insert(
+            AttrType::QUALIFIED_NAME.to_string(),
+            class.ident.clone().into(),
+        );
+
+        for field in class_attributes(schema, class)? {
+            if !field.required && !self.rng.gen_bool(OPTIONAL_PROBABILITY) {
+                continue;
+            }
+            let attr = schema
+                .attr_by_ident(&field.attribute)
+                .with_context(|| format!("Attribute '{}' not found", field.attribute))?;
+            let value = self
+                .value(&attr.value_type, &field.constraints)
+                .with_context(|| format!("Invalid attribute '{}'", attr.ident))?;
+            match value {
+                Some(value) => {
+                    data.insert(attr.ident.clone(), value);
+                }
+                None if field.required => {
+                    bail!(
+                        "Can not generate a value for required attribute '{}' of class '{}'",
+                        attr.ident,
+                        class.ident
+                    );
+                }
+                None => {}
+            }
+        }
+        self.stack.pop();
+
+        self.generated
+            .entry(class.ident.clone())
+            .or_default()
+            .push(id);
+        self.entities.push(data);
+        Ok(id)
+    }
+
+    /// Generate a value of the given type.
+    ///
+    /// Returns `None` if no valid value can be generated.
+    fn value(
+        &mut self,
+        ty: &ValueType,
+        constraints: &[ValueConstraint],
+    ) -> Result<Option<Value>, anyhow::Error> {
+        if constraints
+            .iter()
+            .any(|c| matches!(c, ValueConstraint::Regex(_)))
+        {
+            return Ok(None);
+        }
+
+        let value = match ty {
+            ValueType::Any | ValueType::String => self.string(constraints).into(),
+            ValueType::Unit => Value::Unit,
+            ValueType::Bool => self.rng.gen::<bool>().into(),
+            ValueType::Int => {
+                let (min, max) = number_range(constraints);
+                let (min, max) = (min.ceil() as i64, max.floor() as i64);
+                if max < min {
+                    return Ok(None);
+                }
+                Value::Int(self.rng.gen_range(min..=max))
+            }
+            ValueType::UInt => {
+                let (min, max) = number_range(constraints);
+                let (min, max) = (min.max(0.0).ceil() as u64, max.floor() as u64);
+                if max < min {
+                    return Ok(None);
+                }
+                Value::UInt(self.rng.gen_range(min..=max))
+            }
+            ValueType::Float => {
+                let (min, max) = number_range(constraints);
+                Value::from(self.rng.gen_range(min..=max))
+            }
+            ValueType::Bytes => {
+                let len = self.len(constraints);
+                Value::Bytes((0..len).map(|_| self.rng.gen()).collect())
+            }
+            ValueType::List(item) => {
+                let len = self.len(constraints);
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    match self.value(item, &[])? {
+                        Some(value) => items.push(value),
+                        None => return Ok(None),
+                    }
+                }
+                Value::List(items)
+            }
+            ValueType::Map(map) => {
+                let mut items = ValueMap::new();
+                for _ in 0..self.len(&[]) {
+                    match (self.value(&map.key, &[])?, self.value(&map.value, &[])?) {
+                        (Some(key), Some(value)) => {
+                            items.insert(key, value);
+                        }
+                        _ => return Ok(None),
+                    }
+                }
+                Value::Map(items)
+            }
+            ValueType::Union(variants) => match variants.choose(self.rng) {
+                Some(variant) => return self.value(variant, constraints),
+                None => return Ok(None),
+            },
+            ValueType::Object(obj) => {
+                let mut fields = ValueMap::new();
+                for field in &obj.fields {
+                    match self.value(&field.value_type, &[])? {
+                        Some(value) => {
+                            fields.insert(Value::String(field.name.clone()), value);
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                Value::Map(fields)
+            }
+            ValueType::DateTime => {
+                // Within the last year.
+                let now = factor_core::data::Timestamp::now().as_millis();
+                Value::UInt(now - self.rng.gen_range(0..365 * 24 * 60 * 60 * 1000))
+            }
+            ValueType::Url => format!("https://example.com/{}", self.string(&[])).into(),
+            ValueType::GeoPoint => Value::List(vec![
+                Value::from(self.rng.gen_range(-90.0..=90.0_f64)),
+                Value::from(self.rng.gen_range(-180.0..=180.0_f64)),
+            ]),
+            ValueType::Json => {
+                let mut map = ValueMap::new();
+                map.insert(Value::from("value"), self.string(&[]).into());
+                Value::Map(map)
+            }
+            ValueType::Ref => match self.any_reference() {
+                Some(id) => id.into(),
+                None => return Ok(None),
+            },
+            ValueType::RefConstrained(con) => match self.reference(&con.allowed_entity_types)? {
+                Some(id) => id.into(),
+                None => return Ok(None),
+            },
+            ValueType::Ident(con) => match self.reference(&con.allowed_entity_types)? {
+                Some(id) => id.to_string().into(),
+                None => return Ok(None),
+            },
+            ValueType::EmbeddedEntity => Value::Map(ValueMap::new()),
+            ValueType::Const(value) => value.clone(),
+        };
+        Ok(Some(value))
+    }
+
+    fn len(&mut self, constraints: &[ValueConstraint]) -> u64 {
+        let (mut min, mut max) = DEFAULT_LEN;
+        for constraint in constraints {
+            match constraint {
+                ValueConstraint::MinLength(len) => {
+                    min = *len;
+                    max = max.max(min);
+                }
+                ValueConstraint::MaxLength(len) => {
+                    max = *len;
+                    min = min.min(max);
+                }
+                _ => {}
+            }
+        }
+        self.rng.gen_range(min..=max)
+    }
+
+    fn string(&mut self, constraints: &[ValueConstraint]) -> String {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+        (0..self.len(constraints))
+            .map(|_| char::from(*CHARS.choose(self.rng).unwrap()))
+            .collect()
+    }
+
+    /// Pick an entity of one of the allowed classes.
+    ///
+    /// Previously generated entities are reused. Otherwise a new entity is
+    /// generated, unless the class is already being generated.
+    fn reference(&mut self, allowed: &[IdOrIdent]) -> Result<Option<Id>, anyhow::Error> {
+        if allowed.is_empty() {
+            return Ok(self.any_reference());
+        }
+        let class = allowed.choose(self.rng).unwrap();
+        let class = self
+            .schema
+            .resolve_class(class)
+            .with_context(|| format!("Referenced class '{}' not found", class))?;
+
+        if let Some(ids) = self.generated.get(&class.ident) {
+            if let Some(id) = ids.choose(self.rng) {
+                return Ok(Some(*id));
+            }
+        }
+        if self.stack.contains(&class.ident) {
+            return Ok(None);
+        }
+        self.entity(&class.ident).map(Some)
+    }
+
+    /// Pick any generated entity.
+    fn any_reference(&mut self) -> Option<Id> {
+        let ids = self.generated.values().flatten().collect::<Vec<_>>();
+        ids.choose(self.rng).map(|id| **id)
+    }
+}
+
+/// Collect the attributes of a class, including inherited ones.
+///
+/// Attributes of the class take precedence over the ones of parents.
+fn class_attributes<'s>(
+    schema: &'s DbSchema,
+    class: &'s Class,
+) -> Result<Vec<&'s ClassAttribute>, anyhow::Error> {
+    let mut attributes = class.attributes.iter().collect::<Vec<_>>();
+    for parent in &class.extends {
+        let parent = schema
+            .class_by_ident(parent)
+            .with_context(|| format!("Parent class '{}' not found", parent))?;
+        for attr in class_attributes(schema, parent)? {
+            if !attributes.iter().any(|a| a.attribute == attr.attribute) {
+                attributes.push(attr);
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+fn number_range(constraints: &[ValueConstraint]) -> (f64, f64) {
+    let bound = |value: &Value| match value {
+        Value::UInt(v) => Some(*v as f64),
+        Value::Int(v) => Some(*v as f64),
+        Value::Float(v) => Some(**v),
+        _ => None,
+    };
+    let min = constraints.iter().find_map(|c| match c {
+        ValueConstraint::Min(v) => bound(v),
+        _ => None,
+    });
+    let max = constraints.iter().find_map(|c| match c {
+        ValueConstraint::Max(v) => bound(v),
+        _ => None,
+    });
+
+    let width = DEFAULT_RANGE.1 - DEFAULT_RANGE.0;
+    match (min, max) {
+        (Some(min), Some(max)) => (min, max.max(min)),
+        (Some(min), None) => (min, min + width),
+        (None, Some(max)) => (max - width, max),
+        (None, None) => DEFAULT_RANGE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        data::value_type::ConstrainedRefType,
+        query::migrate::Migration,
+        schema::{AttrMapExt, Attribute},
+    };
+    use factor_engine::{backend::memory::MemoryDb, Engine};
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_generate_fixtures() {
+        let mig = Migration::new()
+            .attr_create(Attribute::new("test/name", ValueType::String))
+            .attr_create(Attribute::new("test/age", ValueType::UInt))
+            .attr_create(Attribute::new(
+                "test/tags",
+                ValueType::new_list(ValueType::String),
+            ))
+            .attr_create(Attribute::new(
+                "test/owner",
+                ValueType::RefConstrained(ConstrainedRefType::new(vec!["test/User".into()])),
+            ))
+            .entity_create(
+                Class::new("test/User")
+                    .with_attributes(vec![
+                        ClassAttribute::new_required("test/name")
+                            .with_constraint(ValueConstraint::MinLength(3))
+                            .with_constraint(ValueConstraint::MaxLength(5)),
+                        ClassAttribute::new_optional("test/age")
+                            .with_constraint(ValueConstraint::Max(Value::UInt(120))),
+                    ])
+                    .with_attribute("test/tags", false),
+            )
+            .entity_create(
+                Class::new("test/Item")
+                    .with_attribute("test/name", true)
+                    .with_attribute("test/owner", true),
+            );
+        let db = Engine::new(MemoryDb::new()).into_client();
+        db.migrate(mig).await.unwrap();
+        let schema = db.schema().await.unwrap();
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let entities = generate_with_rng(&schema, "test/Item", 10, &mut rng).unwrap();
+        // The owner is generated first.
+        assert_eq!(entities.len(), 11);
+        assert_eq!(entities[0].get_type_name(), Some("test/User"));
+        let name = entities[0]["test/name"].as_str().unwrap();
+        assert!((3..=5).contains(&name.len()));
+
+        db.batch(into_batch(entities.clone())).await.unwrap();
+        for data in &entities {
+            let id = data.get_id().unwrap();
+            assert!(db.entity(id).await.is_ok());
+        }
+
+        assert!(generate(&schema, "test/Missing", 1).is_err());
+    }
+}
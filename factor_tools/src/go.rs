@@ -0,0 +1,260 @@
+use std::path::PathBuf;
+
+use factor_core::{
+    data::{Value, ValueType},
+    schema::{Class, StaticSchema},
+};
+use inflector::Inflector;
+
+use crate::codegen::ResolvedSchema;
+
+/// Package name used if none is specified.
+pub const DEFAULT_PACKAGE: &str = "schema";
+
+/// Generate Go structs for a static schema file.
+///
+/// Mirrors [`crate::rust::generate_schema`].
+/// Fields of parent classes are flattened into each struct, since embedded
+/// structs with overlapping fields are ignored by `encoding/json`.
+/// Optional scalar fields are pointers, so they can be omitted.
+pub fn generate_schema(schema: &StaticSchema, package: &str) -> Result<String, anyhow::Error> {
+    let schema = ResolvedSchema::build(schema, true)?;
+
+    let mut out = String::new();
+    out.push_str("// Code generated by factordb. DO NOT EDIT.\n\n");
+    out.push_str(&format!("package {}\n\n", package));
+    out.push_str("// EntityId is the id of an entity.\ntype EntityId = string\n");
+
+    let attr_consts = schema
+        .local_attributes()
+        .into_iter()
+        .map(|attr| (format!("Attr{}", go_name(&attr.ident)), &attr.ident))
+        .collect::<Vec<_>>();
+    out.push_str(&render_const_block(&attr_consts));
+
+    let classes = schema.local_classes();
+    let type_consts = classes
+        .iter()
+        .map(|class| (format!("Type{}", go_name(&class.ident)), &class.ident))
+        .collect::<Vec<_>>();
+    out.push_str(&render_const_block(&type_consts));
+
+    for class in classes {
+        out.push('\n');
+        out.push_str(&render_struct(&schema, class)?);
+    }
+
+    Ok(out)
+}
+
+pub fn generate_schema_from_json(contents: &str, package: &str) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::parse_static_schema(contents)?;
+    generate_schema(&schema, package)
+}
+
+pub fn generate_schema_from_file(
+    path: impl Into<PathBuf>,
+    package: &str,
+) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::read_static_schema(path)?;
+    generate_schema(&schema, package)
+}
+
+/// Render a const block, aligned like gofmt does.
+fn render_const_block(items: &[(String, &String)]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    let width = items.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    let mut out = "\nconst (\n".to_string();
+    for (name, value) in items {
+        out.push_str(&format!(
+            "\t{:width$} = {}\n",
+            name,
+            string_literal(value),
+            width = width
+        ));
+    }
+    out.push_str(")\n");
+    out
+}
+
+fn render_struct(schema: &ResolvedSchema, class: &Class) -> Result<String, anyhow::Error> {
+    let name = go_name(&class.ident);
+
+    let mut fields = vec![
+        (
+            "Id".to_string(),
+            "EntityId".to_string(),
+            "factor/id,omitempty".to_string(),
+        ),
+        (
+            "Type".to_string(),
+            "string".to_string(),
+            "factor/type,omitempty".to_string(),
+        ),
+    ];
+    for (field, attr) in schema.all_fields(class)? {
+        let mut ty = value_type_to_go_type(&attr.value_type);
+        let mut tag = attr.ident.clone();
+        if !field.required {
+            if !is_nullable(&attr.value_type) {
+                ty = format!("*{}", ty);
+            }
+            tag.push_str(",omitempty");
+        }
+        fields.push((go_name(&attr.ident), ty, tag));
+    }
+
+    let name_width = fields.iter().map(|f| f.0.len()).max().unwrap_or(0);
+    let type_width = fields.iter().map(|f| f.1.len()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "// {} is an entity of class {}.\n",
+        name,
+        string_literal(&class.ident)
+    ));
+    if let Some(doc) = class.description.as_deref().or(class.title.as_deref()) {
+        out.push_str("//\n");
+        for line in doc.lines() {
+            out.push_str(format!("// {}", line).trim_end());
+            out.push('\n');
+        }
+    }
+    out.push_str(&format!("type {} struct {{\n", name));
+    for (field_name, ty, tag) in &fields {
+        out.push_str(&format!(
+            "\t{:name_width$} {:type_width$} `json:{}`\n",
+            field_name,
+            ty,
+            string_literal(tag),
+            name_width = name_width,
+            type_width = type_width,
+        ));
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// Exported Go identifier for a factor ident.
+fn go_name(ident: &str) -> String {
+    ident.replace('/', "_").to_pascal_case()
+}
+
+/// JSON string literals are also valid Go string literals.
+fn string_literal(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Types that can already represent a missing value.
+fn is_nullable(ty: &ValueType) -> bool {
+    matches!(
+        ty,
+        ValueType::Any
+            | ValueType::Union(_)
+            | ValueType::Bytes
+            | ValueType::List(_)
+            | ValueType::Map(_)
+            | ValueType::Object(_)
+            | ValueType::EmbeddedEntity
+            | ValueType::Json
+    )
+}
+
+fn value_type_to_go_type(ty: &ValueType) -> String {
+    match ty {
+        ValueType::Any | ValueType::Union(_) | ValueType::Json => "any".to_string(),
+        ValueType::Unit => "struct{}".to_string(),
+        ValueType::Bool => "bool".to_string(),
+        ValueType::Int => "int64".to_string(),
+        ValueType::UInt => "uint64".to_string(),
+        ValueType::Float => "float64".to_string(),
+        ValueType::String => "string".to_string(),
+        // Bytes are serialized as a list of numbers, so []byte (base64) can't
+        // be used.
+        ValueType::Bytes => "[]int".to_string(),
+        ValueType::List(inner) => format!("[]{}", value_type_to_go_type(inner)),
+        ValueType::Map(map) => format!(
+            "map[{}]{}",
+            value_type_to_go_type(&map.key),
+            value_type_to_go_type(&map.value)
+        ),
+        ValueType::Object(_) | ValueType::EmbeddedEntity => "map[string]any".to_string(),
+        // Unix timestamp.
+        ValueType::DateTime => "uint64".to_string(),
+        ValueType::Url => "string".to_string(),
+        // Latitude, longitude.
+        ValueType::GeoPoint => "[2]float64".to_string(),
+        ValueType::Ref | ValueType::RefConstrained(_) => "EntityId".to_string(),
+        ValueType::Ident(_) => "string".to_string(),
+        ValueType::Const(value) => match value {
+            Value::Unit => "struct{}",
+            Value::Bool(_) => "bool",
+            Value::UInt(_) => "uint64",
+            Value::Int(_) => "int64",
+            Value::Float(_) => "float64",
+            Value::String(_) => "string",
+            _ => "any",
+        }
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_schema_go_codegen() {
+        let schema = r#"
+{
+"factor/ident": "TestSchema",
+"factor/migrations": [
+{
+"factor/commits": [
+    {
+        "factor/subject": "test/name",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": "String"
+        }
+    },
+    {
+        "factor/subject": "test/tags",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": {"List": "String"}
+        }
+    },
+    {
+        "factor/subject": "test/Person",
+        "factor/set": {
+            "factor/type": "factor/Class",
+            "factor/entityAttributes": [
+                {
+                    "factor/attribute": "test/name",
+                    "factor/required": false
+                },
+                {
+                    "factor/attribute": "test/tags",
+                    "factor/required": true
+                }
+            ]
+        }
+    }
+]
+}
+]
+}
+"#;
+        let code = generate_schema_from_json(schema, DEFAULT_PACKAGE).unwrap();
+
+        assert!(code.starts_with("// Code generated by factordb. DO NOT EDIT.\n\npackage schema\n"));
+        assert!(code.contains("\tAttrTestName = \"test/name\"\n"));
+        assert!(code.contains("\tTypeTestPerson = \"test/Person\"\n"));
+        assert!(code.contains("\tTestName *string  `json:\"test/name,omitempty\"`\n"));
+        assert!(code.contains("\tTestTags []string `json:\"test/tags\"`\n"));
+    }
+}
@@ -0,0 +1,469 @@
+//! Bulk import of entities from CSV or JSON-lines.
+//!
+//! An [`ImportMapping`] maps the columns of the input to attributes.
+//! Each row is converted and validated against the schema before it is
+//! loaded. Invalid rows are skipped and reported with their line number,
+//! valid rows are loaded in chunks with [`LogDb::bulk_load`].
+
+use std::{collections::HashMap, io::BufRead, str::FromStr};
+
+use anyhow::Context;
+use factor_core::{
+    data::{DataMap, Id, Value, ValueType},
+    query::mutate::{Batch, Mutate},
+    schema::{builtin::AttrType, AttributeMeta, DbSchema},
+};
+use factor_engine::backend::{log::LogDb, Backend};
+
+/// Number of rows loaded per batch.
+const CHUNK_SIZE: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+impl ImportFormat {
+    pub const ALL: &'static [Self] = &[Self::Csv, Self::JsonLines];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "ndjson" => Ok(Self::JsonLines),
+            other => Err(anyhow::anyhow!(
+                "Unknown import format '{}': expected one of {}",
+                other,
+                Self::ALL
+                    .iter()
+                    .map(|f| f.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Conversion applied to a column value before it is coerced to the type of
+/// the attribute.
+///
+/// Conversions of string values leave other values unchanged.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnCoercion {
+    /// Remove leading and trailing whitespace.
+    Trim,
+    /// Parse the string as JSON, eg. for lists or maps in CSV files.
+    Json,
+    /// Split the string into a list of strings.
+    Split { separator: String },
+    /// Parse `true`/`false`, `yes`/`no` or `1`/`0` as a boolean.
+    Bool,
+}
+
+impl ColumnCoercion {
+    fn apply(&self, value: Value) -> Result<Value, String> {
+        let s = match value {
+            Value::String(s) => s,
+            other => return Ok(other),
+        };
+        match self {
+            Self::Trim => Ok(Value::String(s.trim().to_string())),
+            Self::Json => serde_json::from_str(&s).map_err(|err| format!("Invalid JSON: {}", err)),
+            Self::Split { .. } if s.is_empty() => Ok(Value::List(Vec::new())),
+            Self::Split { separator } => Ok(Value::List(
+                s.split(separator.as_str())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            )),
+            Self::Bool => match s.to_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => Err(format!("Invalid boolean '{}'", s)),
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    /// Name of the CSV column or JSON field.
+    pub column: String,
+    /// Ident of the attribute.
+    pub attribute: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coercions: Vec<ColumnCoercion>,
+}
+
+/// Maps the columns of an import to attributes.
+///
+/// Columns without a mapping are ignored.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImportMapping {
+    /// Class of the imported entities.
+    ///
+    /// Required attributes of the class must be present in every row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Column holding the entity id.
+    ///
+    /// Random ids are generated if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_column: Option<String>,
+    pub columns: Vec<ColumnMapping>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportRowError {
+    /// Line of the row in the input, starting at 1.
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: u64,
+    /// Rows that were skipped because they are invalid.
+    pub errors: Vec<ImportRowError>,
+}
+
+/// Column values of a single input row.
+type RawRow = HashMap<String, Value>;
+
+/// Input rows with their line number.
+///
+/// Rows that can't be parsed are returned as an error message, I/O errors
+/// abort the import.
+type Rows<'a> = Box<dyn Iterator<Item = Result<(u64, Result<RawRow, String>), anyhow::Error>> + 'a>;
+
+/// Converts rows to entity data according to a mapping.
+struct RowMapper<'a> {
+    mapping: &'a ImportMapping,
+    /// Type of the attribute of each column mapping.
+    types: Vec<&'a ValueType>,
+    required: Vec<String>,
+}
+
+impl<'a> RowMapper<'a> {
+    fn new(schema: &'a DbSchema, mapping: &'a ImportMapping) -> Result<Self, anyhow::Error> {
+        let types = mapping
+            .columns
+            .iter()
+            .map(|column| {
+                schema
+                    .attr_by_ident(&column.attribute)
+                    .map(|attr| &attr.value_type)
+                    .with_context(|| {
+                        format!(
+                            "Attribute '{}' of column '{}' not found",
+                            column.attribute, column.column
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut required = Vec::new();
+        if let Some(class) = &mapping.class {
+            collect_required_attributes(schema, class, &mut required)?;
+        }
+
+        Ok(Self {
+            mapping,
+            types,
+            required,
+        })
+    }
+
+    fn map_row(&self, row: RawRow) -> Result<(Id, DataMap), String> {
+        let id = match &self.mapping.id_column {
+            Some(column) => match row.get(column).cloned() {
+                Some(Value::Id(id)) => id,
+                Some(Value::String(s)) => Id::from_str(s.trim())
+                    .map_err(|_| format!("Invalid id '{}' in column '{}'", s, column))?,
+                Some(other) => {
+                    return Err(format!("Invalid id {:?} in column '{}'", other, column))
+                }
+                None => return Err(format!("Missing id column '{}'", column)),
+            },
+            None => Id::random(),
+        };
+
+        let mut data = DataMap::new();
+        if let Some(class) = &self.mapping.class {
+            data.insert(AttrType::QUALIFIED_NAME.to_string(), class.clone().into());
+        }
+        for (column, ty) in self.mapping.columns.iter().zip(&self.types) {
+            let mut value = match row.get(&column.column) {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            for coercion in &column.coercions {
+                value = coercion
+                    .apply(value)
+                    .map_err(|err| format!("Column '{}': {}", column.column, err))?;
+            }
+            value
+                .coerce_mut(ty)
+                .map_err(|err| format!("Column '{}': {}", column.column, err))?;
+            data.insert(column.attribute.clone(), value);
+        }
+
+        if let Some(missing) = self.required.iter().find(|attr| !data.contains_key(*attr)) {
+            return Err(format!("Missing required attribute '{}'", missing));
+        }
+
+        Ok((id, data))
+    }
+}
+
+/// Collect the required attributes of a class and its parents.
+fn collect_required_attributes(
+    schema: &DbSchema,
+    class: &str,
+    out: &mut Vec<String>,
+) -> Result<(), anyhow::Error> {
+    let class = schema
+        .class_by_ident(class)
+        .with_context(|| format!("Class '{}' not found", class))?;
+    for parent in &class.extends {
+        collect_required_attributes(schema, parent, out)?;
+    }
+    out.extend(
+        class
+            .attributes
+            .iter()
+            .filter(|attr| attr.required)
+            .map(|attr| attr.attribute.clone()),
+    );
+    Ok(())
+}
+
+/// Read the rows of a CSV file with a header line.
+///
+/// Empty cells are treated as missing values.
+fn csv_rows<'a>(reader: impl BufRead + 'a) -> Result<Rows<'a>, anyhow::Error> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+
+    let rows = reader.into_records().map(move |record| match record {
+        Ok(record) => {
+            let line = record.position().map_or(0, |pos| pos.line());
+            let row = headers
+                .iter()
+                .zip(record.iter())
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(header, cell)| (header.to_string(), Value::String(cell.to_string())))
+                .collect();
+            Ok((line, Ok(row)))
+        }
+        Err(err) if err.is_io_error() => Err(err.into()),
+        Err(err) => {
+            let line = err.position().map_or(0, |pos| pos.line());
+            Ok((line, Err(err.to_string())))
+        }
+    });
+    Ok(Box::new(rows))
+}
+
+/// Read the rows of a JSON-lines file.
+///
+/// `null` values are treated as missing values.
+fn json_rows<'a>(reader: impl BufRead + 'a) -> Rows<'a> {
+    let rows = reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line_number = index as u64 + 1;
+            let row = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&line?)
+                .map_err(|err| format!("Invalid JSON object: {}", err))
+                .and_then(|object| {
+                    object
+                        .into_iter()
+                        .filter(|(_, value)| !value.is_null())
+                        .map(|(key, value)| {
+                            serde_json::from_value::<Value>(value)
+                                .map(|value| (key, value))
+                                .map_err(|err| err.to_string())
+                        })
+                        .collect::<Result<RawRow, String>>()
+                });
+            Ok((line_number, row))
+        });
+    Box::new(rows)
+}
+
+/// Import rows into a database.
+///
+/// Rows that can't be mapped or don't match the schema are skipped and
+/// returned in the report.
+/// Like with [`crate::dump::restore`], unique constraints and references
+/// are not validated.
+pub async fn import(
+    db: &LogDb,
+    mapping: &ImportMapping,
+    format: ImportFormat,
+    reader: impl BufRead,
+) -> Result<ImportReport, anyhow::Error> {
+    let schema = Backend::registry(db).load().build_schema();
+    let mapper = RowMapper::new(&schema, mapping)?;
+
+    let rows = match format {
+        ImportFormat::Csv => csv_rows(reader)?,
+        ImportFormat::JsonLines => json_rows(reader),
+    };
+
+    let mut report = ImportReport::default();
+    let mut batch = Batch::new();
+    for row in rows {
+        let (line, row) = row?;
+        match row.and_then(|row| mapper.map_row(row)) {
+            Ok((id, data)) => batch.actions.push(Mutate::create(id, data)),
+            Err(message) => {
+                report.errors.push(ImportRowError { line, message });
+                continue;
+            }
+        }
+
+        if batch.actions.len() >= CHUNK_SIZE {
+            report.imported += batch.actions.len() as u64;
+            db.bulk_load(std::mem::replace(&mut batch, Batch::new()))
+                .await
+                .with_context(|| format!("Could not load rows up to line {}", line))?;
+        }
+    }
+
+    if !batch.actions.is_empty() {
+        report.imported += batch.actions.len() as u64;
+        db.bulk_load(batch).await?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{
+        query::migrate::Migration,
+        schema::{Attribute, Class},
+    };
+    use factor_engine::{backend::log::store_memory::MemoryLogStore, Engine};
+
+    use super::*;
+
+    async fn test_db() -> LogDb {
+        let log = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        Backend::migrate(
+            &log,
+            Migration::new()
+                .attr_create(Attribute::new("test/name", ValueType::String))
+                .attr_create(Attribute::new("test/count", ValueType::Int))
+                .attr_create(Attribute::new(
+                    "test/tags",
+                    ValueType::List(Box::new(ValueType::String)),
+                ))
+                .entity_create(
+                    Class::new("test/Item")
+                        .with_attribute("test/name", true)
+                        .with_attribute("test/count", false)
+                        .with_attribute("test/tags", false),
+                ),
+        )
+        .await
+        .unwrap();
+        log
+    }
+
+    fn mapping() -> ImportMapping {
+        ImportMapping {
+            class: Some("test/Item".to_string()),
+            id_column: Some("id".to_string()),
+            columns: vec![
+                ColumnMapping {
+                    column: "name".to_string(),
+                    attribute: "test/name".to_string(),
+                    coercions: vec![ColumnCoercion::Trim],
+                },
+                ColumnMapping {
+                    column: "count".to_string(),
+                    attribute: "test/count".to_string(),
+                    coercions: Vec::new(),
+                },
+                ColumnMapping {
+                    column: "tags".to_string(),
+                    attribute: "test/tags".to_string(),
+                    coercions: vec![ColumnCoercion::Split {
+                        separator: ";".to_string(),
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_csv() {
+        let log = test_db().await;
+        let a = Id::random();
+        let b = Id::random();
+        let input = format!(
+            "id,name,count,tags\n{},  first ,5,x;y\n{},second,nan,\n{},,1,\n",
+            a,
+            b,
+            Id::random()
+        );
+
+        let report = import(&log, &mapping(), ImportFormat::Csv, input.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(
+            report.errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+
+        let db = Engine::new(log).into_client();
+        let data = db.entity(a).await.unwrap();
+        assert_eq!(data.get("test/name"), Some(&Value::from("first")));
+        assert_eq!(data.get("test/count"), Some(&Value::Int(5)));
+        assert_eq!(
+            data.get("test/tags"),
+            Some(&Value::from(vec!["x".to_string(), "y".to_string()]))
+        );
+        assert!(db.entity(b).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_json_lines() {
+        let log = test_db().await;
+        let a = Id::random();
+        let input = format!(
+            "{{\"id\": \"{}\", \"name\": \"a\", \"count\": 2, \"tags\": null}}\n\nnot json\n",
+            a
+        );
+
+        let report = import(&log, &mapping(), ImportFormat::JsonLines, input.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, 3);
+
+        let db = Engine::new(log).into_client();
+        assert_eq!(
+            db.entity(a).await.unwrap().get("test/count"),
+            Some(&Value::Int(2))
+        );
+    }
+}
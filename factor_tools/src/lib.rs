@@ -1,2 +1,77 @@
+pub mod cli;
+pub mod codegen;
+pub mod dump;
+pub mod export;
+pub mod fixtures;
+pub mod go;
+pub mod import;
+pub mod lint;
+pub mod python;
+pub use factor_client_http as remote;
 pub mod rust;
+pub mod schema_file;
+pub mod server;
+pub mod sync;
 pub mod typescript;
+
+use std::path::Path;
+
+/// Target language for schema code generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodegenLanguage {
+    Rust,
+    Typescript,
+    Python,
+    Go,
+}
+
+impl CodegenLanguage {
+    pub const ALL: &'static [Self] = &[Self::Rust, Self::Typescript, Self::Python, Self::Go];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Typescript => "typescript",
+            Self::Python => "python",
+            Self::Go => "go",
+        }
+    }
+
+    /// Generate code for the given schema file.
+    pub fn generate_schema_from_file(&self, path: &Path) -> Result<String, anyhow::Error> {
+        match self {
+            Self::Rust => rust::generate_schema_from_file(path, true),
+            Self::Typescript => typescript::generate_schema_from_file(path, None),
+            Self::Python => python::generate_schema_from_file(path, python::PythonStyle::Dataclass),
+            Self::Go => go::generate_schema_from_file(path, go::DEFAULT_PACKAGE),
+        }
+    }
+}
+
+impl std::fmt::Display for CodegenLanguage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for CodegenLanguage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" | "rs" => Ok(Self::Rust),
+            "typescript" | "ts" => Ok(Self::Typescript),
+            "python" | "py" => Ok(Self::Python),
+            "go" | "golang" => Ok(Self::Go),
+            other => Err(anyhow::anyhow!(
+                "Unknown language '{}': expected one of {}",
+                other,
+                Self::ALL
+                    .iter()
+                    .map(|l| l.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
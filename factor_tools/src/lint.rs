@@ -0,0 +1,247 @@
+//! Lint checks for schemas.
+
+use std::collections::HashMap;
+
+use factor_core::{
+    data::{Ident, ValueType},
+    schema::{builtin::NS_FACTOR, DbSchema},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintViolation {
+    pub severity: LintSeverity,
+    /// Name of the check that produced the violation.
+    pub rule: &'static str,
+    /// Ident of the offending attribute or class.
+    pub ident: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            LintSeverity::Warning => "warning",
+            LintSeverity::Error => "error",
+        };
+        write!(
+            f,
+            "{}[{}]: '{}': {}",
+            severity, self.rule, self.ident, self.message
+        )
+    }
+}
+
+/// Run all lint checks over a schema.
+///
+/// Builtin items must not be included in the schema, since they would be
+/// reported as using the reserved namespace.
+pub fn lint_schema(schema: &DbSchema) -> Vec<LintViolation> {
+    let mut violations = Vec::new();
+    let mut push = |severity, rule, ident: &str, message: String| {
+        violations.push(LintViolation {
+            severity,
+            rule,
+            ident: ident.to_string(),
+            message,
+        });
+    };
+
+    // Unique idents.
+    let mut seen = HashMap::<&str, usize>::new();
+    let idents = schema
+        .attributes
+        .iter()
+        .map(|a| a.ident.as_str())
+        .chain(schema.classes.iter().map(|c| c.ident.as_str()));
+    for ident in idents {
+        *seen.entry(ident).or_default() += 1;
+    }
+    let mut duplicates = seen
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    for (ident, count) in duplicates {
+        push(
+            LintSeverity::Error,
+            "unique-ident",
+            ident,
+            format!("ident is used by {} items", count),
+        );
+    }
+
+    for attr in &schema.attributes {
+        let ident = attr.ident.as_str();
+        match Ident::parse_parts(ident) {
+            Ok((namespace, name)) => {
+                if namespace == NS_FACTOR {
+                    push(
+                        LintSeverity::Error,
+                        "reserved-namespace",
+                        ident,
+                        format!("the {}/ namespace is reserved", NS_FACTOR),
+                    );
+                }
+                if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+                    push(
+                        LintSeverity::Warning,
+                        "naming",
+                        ident,
+                        "attribute names should start with a lowercase letter".to_string(),
+                    );
+                }
+            }
+            Err(err) => push(LintSeverity::Error, "invalid-ident", ident, err.to_string()),
+        }
+
+        if attr.description.is_none() {
+            push(
+                LintSeverity::Warning,
+                "missing-description",
+                ident,
+                "attribute has no description".to_string(),
+            );
+        }
+
+        if is_ref_type(&attr.value_type) && !attr.index && !attr.unique {
+            push(
+                LintSeverity::Warning,
+                "unindexed-ref",
+                ident,
+                "reference attributes should be indexed".to_string(),
+            );
+        }
+    }
+
+    for class in &schema.classes {
+        let ident = class.ident.as_str();
+        match Ident::parse_parts(ident) {
+            Ok((namespace, name)) => {
+                if namespace == NS_FACTOR {
+                    push(
+                        LintSeverity::Error,
+                        "reserved-namespace",
+                        ident,
+                        format!("the {}/ namespace is reserved", NS_FACTOR),
+                    );
+                }
+                if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    push(
+                        LintSeverity::Warning,
+                        "naming",
+                        ident,
+                        "class names should start with an uppercase letter".to_string(),
+                    );
+                }
+            }
+            Err(err) => push(LintSeverity::Error, "invalid-ident", ident, err.to_string()),
+        }
+
+        if class.description.is_none() {
+            push(
+                LintSeverity::Warning,
+                "missing-description",
+                ident,
+                "class has no description".to_string(),
+            );
+        }
+
+        for field in &class.attributes {
+            if schema.attr_by_ident(&field.attribute).is_none() && !is_builtin(&field.attribute) {
+                push(
+                    LintSeverity::Error,
+                    "unknown-attribute",
+                    ident,
+                    format!("attribute '{}' does not exist", field.attribute),
+                );
+            }
+        }
+        for parent in &class.extends {
+            if schema.class_by_ident(parent).is_none() && !is_builtin(parent) {
+                push(
+                    LintSeverity::Error,
+                    "unknown-class",
+                    ident,
+                    format!("parent class '{}' does not exist", parent),
+                );
+            }
+        }
+    }
+
+    violations
+}
+
+fn is_builtin(ident: &str) -> bool {
+    matches!(Ident::parse_parts(ident), Ok((NS_FACTOR, _)))
+}
+
+fn is_ref_type(ty: &ValueType) -> bool {
+    match ty {
+        ValueType::Ref | ValueType::RefConstrained(_) => true,
+        ValueType::List(inner) => is_ref_type(inner),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::schema::{Attribute, Class};
+
+    use super::*;
+
+    #[test]
+    fn test_lint_schema() {
+        let mut title = Attribute::new("test/title", ValueType::String);
+        title.description = Some("Title".to_string());
+        let mut class = Class::new("test/Post").with_attribute("test/title", true);
+        class.description = Some("A post".to_string());
+
+        let mut schema = DbSchema {
+            attributes: vec![title],
+            classes: vec![class],
+            indexes: vec![],
+            policies: vec![],
+            views: vec![],
+            rules: vec![],
+            mixins: vec![],
+        };
+        assert_eq!(lint_schema(&schema), Vec::new());
+
+        schema
+            .attributes
+            .push(Attribute::new("test/Parent", ValueType::Ref));
+        schema.classes[0].extends.push("test/Base".to_string());
+
+        let rules = lint_schema(&schema)
+            .into_iter()
+            .map(|v| (v.severity, v.rule, v.ident))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                (LintSeverity::Warning, "naming", "test/Parent".to_string()),
+                (
+                    LintSeverity::Warning,
+                    "missing-description",
+                    "test/Parent".to_string()
+                ),
+                (
+                    LintSeverity::Warning,
+                    "unindexed-ref",
+                    "test/Parent".to_string()
+                ),
+                (
+                    LintSeverity::Error,
+                    "unknown-class",
+                    "test/Post".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::path::PathBuf;
+
+use factor_core::{
+    data::{Value, ValueType},
+    schema::{Attribute, Class, ClassAttribute, StaticSchema},
+};
+use inflector::Inflector;
+
+use crate::codegen::ResolvedSchema;
+
+/// The kind of Python classes to generate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PythonStyle {
+    /// Standard library dataclasses, with `to_dict` and `from_dict` helpers.
+    Dataclass,
+    /// Pydantic models, using the attribute idents as field aliases.
+    Pydantic,
+}
+
+impl std::str::FromStr for PythonStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dataclass" | "dataclasses" => Ok(Self::Dataclass),
+            "pydantic" => Ok(Self::Pydantic),
+            other => Err(anyhow::anyhow!(
+                "Unknown python style '{}': expected dataclass or pydantic",
+                other
+            )),
+        }
+    }
+}
+
+const DATACLASS_HELPERS: &str = r#"def _to_dict(obj: typing.Any) -> typing.Dict[str, typing.Any]:
+    data: typing.Dict[str, typing.Any] = dict(getattr(obj, "extra", {}))
+    data["factor/type"] = obj.FACTOR_TYPE
+    for field, attr in obj.FIELDS.items():
+        value = getattr(obj, field)
+        if value is not None:
+            data[attr] = value
+    return data
+
+
+def _from_dict(cls: typing.Any, data: typing.Mapping[str, typing.Any]) -> typing.Any:
+    fields = {attr: field for field, attr in cls.FIELDS.items()}
+    kwargs: typing.Dict[str, typing.Any] = {}
+    extra: typing.Dict[str, typing.Any] = {}
+    for key, value in data.items():
+        if key in fields:
+            kwargs[fields[key]] = value
+        elif key != "factor/type":
+            extra[key] = value
+    if not cls.STRICT:
+        kwargs["extra"] = extra
+    return cls(**kwargs)
+"#;
+
+/// Generate Python classes for a static schema file.
+///
+/// Mirrors [`crate::rust::generate_schema`].
+/// Fields of parent classes are flattened into each class, so the generated
+/// classes don't use inheritance.
+pub fn generate_schema(schema: &StaticSchema, style: PythonStyle) -> Result<String, anyhow::Error> {
+    let schema = ResolvedSchema::build(schema, true)?;
+
+    let mut out = String::new();
+    out.push_str("# This file was auto-generated by factordb.\n");
+    out.push_str("# DO NOT EDIT MANUALLY\n\n");
+    out.push_str("from __future__ import annotations\n\n");
+    match style {
+        PythonStyle::Dataclass => out.push_str("import dataclasses\nimport typing\n\n"),
+        PythonStyle::Pydantic => out.push_str("import typing\n\nimport pydantic\n\n"),
+    }
+    out.push_str("EntityId = typing.NewType(\"EntityId\", str)\n\n");
+
+    for attr in schema.local_attributes() {
+        out.push_str(&format!(
+            "{} = {}\n",
+            constant_name(&attr.ident),
+            string_literal(&attr.ident)
+        ));
+    }
+    out.push('\n');
+
+    if style == PythonStyle::Dataclass {
+        out.push('\n');
+        out.push_str(DATACLASS_HELPERS);
+    }
+
+    for class in schema.local_classes() {
+        out.push_str("\n\n");
+        out.push_str(&format!(
+            "TY_{} = {}\n\n\n",
+            constant_name(&class.ident),
+            string_literal(&class.ident)
+        ));
+        let fields = sorted_fields(&schema, class)?;
+        match style {
+            PythonStyle::Dataclass => out.push_str(&render_dataclass(class, &fields)),
+            PythonStyle::Pydantic => out.push_str(&render_pydantic(class, &fields)),
+        }
+    }
+
+    Ok(out)
+}
+
+pub fn generate_schema_from_json(
+    contents: &str,
+    style: PythonStyle,
+) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::parse_static_schema(contents)?;
+    generate_schema(&schema, style)
+}
+
+pub fn generate_schema_from_file(
+    path: impl Into<PathBuf>,
+    style: PythonStyle,
+) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::read_static_schema(path)?;
+    generate_schema(&schema, style)
+}
+
+struct Field {
+    name: String,
+    attribute: String,
+    ty: String,
+    required: bool,
+}
+
+/// Resolve the fields of a class, with required fields first.
+///
+/// Python requires fields without a default to come before the others.
+fn sorted_fields(schema: &ResolvedSchema, class: &Class) -> Result<Vec<Field>, anyhow::Error> {
+    let mut fields = schema
+        .all_fields(class)?
+        .into_iter()
+        .map(|(field, attr)| build_field(field, attr))
+        .collect::<Vec<_>>();
+    fields.sort_by_key(|f| !f.required);
+    Ok(fields)
+}
+
+fn build_field(field: &ClassAttribute, attr: &Attribute) -> Field {
+    let ty = value_type_to_python_type(&attr.value_type);
+    Field {
+        name: attr.ident.to_snake_case(),
+        attribute: attr.ident.clone(),
+        ty: if field.required {
+            ty
+        } else {
+            format!("typing.Optional[{}]", ty)
+        },
+        required: field.required,
+    }
+}
+
+fn render_docstring(class: &Class) -> String {
+    match class.description.as_deref().or(class.title.as_deref()) {
+        Some(doc) => format!("    \"\"\"{}\"\"\"\n\n", doc.replace("\"\"\"", "'''")),
+        None => String::new(),
+    }
+}
+
+fn render_dataclass(class: &Class, fields: &[Field]) -> String {
+    let name = class_name(&class.ident);
+
+    let mut out = format!("@dataclasses.dataclass\nclass {}:\n", name);
+    out.push_str(&render_docstring(class));
+    out.push_str(&format!(
+        "    FACTOR_TYPE: typing.ClassVar[str] = {}\n",
+        string_literal(&class.ident)
+    ));
+    out.push_str(&format!(
+        "    STRICT: typing.ClassVar[bool] = {}\n",
+        if class.strict { "True" } else { "False" }
+    ));
+    out.push_str("    FIELDS: typing.ClassVar[typing.Dict[str, str]] = {\n");
+    out.push_str("        \"id\": \"factor/id\",\n");
+    for field in fields {
+        out.push_str(&format!(
+            "        {}: {},\n",
+            string_literal(&field.name),
+            string_literal(&field.attribute)
+        ));
+    }
+    out.push_str("    }\n\n");
+
+    for field in fields.iter().filter(|f| f.required) {
+        out.push_str(&format!("    {}: {}\n", field.name, field.ty));
+    }
+    out.push_str("    id: typing.Optional[EntityId] = None\n");
+    for field in fields.iter().filter(|f| !f.required) {
+        out.push_str(&format!("    {}: {} = None\n", field.name, field.ty));
+    }
+    if !class.strict {
+        out.push_str(
+            "    extra: typing.Dict[str, typing.Any] = dataclasses.field(default_factory=dict)\n",
+        );
+    }
+
+    out.push_str(
+        "\n    def to_dict(self) -> typing.Dict[str, typing.Any]:\n        return _to_dict(self)\n",
+    );
+    out.push_str(&format!(
+        "\n    @classmethod\n    def from_dict(cls, data: typing.Mapping[str, typing.Any]) -> {}:\n",
+        name
+    ));
+    out.push_str("        return _from_dict(cls, data)\n");
+    out
+}
+
+fn render_pydantic(class: &Class, fields: &[Field]) -> String {
+    let mut out = format!("class {}(pydantic.BaseModel):\n", class_name(&class.ident));
+    out.push_str(&render_docstring(class));
+    out.push_str(&format!(
+        "    model_config = pydantic.ConfigDict(populate_by_name=True, extra={})\n\n",
+        if class.strict {
+            "\"ignore\""
+        } else {
+            "\"allow\""
+        }
+    ));
+    out.push_str(&format!(
+        "    FACTOR_TYPE: typing.ClassVar[str] = {}\n\n",
+        string_literal(&class.ident)
+    ));
+
+    let type_literal = string_literal(&class.ident);
+    out.push_str(
+        "    id: typing.Optional[EntityId] = pydantic.Field(default=None, alias=\"factor/id\")\n",
+    );
+    out.push_str(&format!(
+        "    factor_type: typing.Literal[{}] = pydantic.Field(default={}, alias=\"factor/type\")\n",
+        type_literal, type_literal
+    ));
+    for field in fields {
+        let default = if field.required { "" } else { "default=None, " };
+        out.push_str(&format!(
+            "    {}: {} = pydantic.Field({}alias={})\n",
+            field.name,
+            field.ty,
+            default,
+            string_literal(&field.attribute)
+        ));
+    }
+    out
+}
+
+fn class_name(ident: &str) -> String {
+    ident.replace('/', "_").to_class_case()
+}
+
+fn constant_name(ident: &str) -> String {
+    ident.replace('/', "_").to_screaming_snake_case()
+}
+
+/// JSON string literals are also valid Python string literals.
+fn string_literal(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+fn value_type_to_python_type(ty: &ValueType) -> String {
+    match ty {
+        ValueType::Any | ValueType::Json => "typing.Any".to_string(),
+        ValueType::Unit => "None".to_string(),
+        ValueType::Bool => "bool".to_string(),
+        ValueType::Int | ValueType::UInt => "int".to_string(),
+        ValueType::Float => "float".to_string(),
+        ValueType::String => "str".to_string(),
+        ValueType::Bytes => "typing.List[int]".to_string(),
+        ValueType::List(inner) => format!("typing.List[{}]", value_type_to_python_type(inner)),
+        ValueType::Map(map) => format!(
+            "typing.Dict[{}, {}]",
+            value_type_to_python_type(&map.key),
+            value_type_to_python_type(&map.value)
+        ),
+        ValueType::Union(variants) => format!(
+            "typing.Union[{}]",
+            variants
+                .iter()
+                .map(value_type_to_python_type)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ValueType::Object(_) | ValueType::EmbeddedEntity => {
+            "typing.Dict[str, typing.Any]".to_string()
+        }
+        // Unix timestamp.
+        ValueType::DateTime => "int".to_string(),
+        ValueType::Url => "str".to_string(),
+        // Latitude, longitude.
+        ValueType::GeoPoint => "typing.Tuple[float, float]".to_string(),
+        ValueType::Ref | ValueType::RefConstrained(_) => "EntityId".to_string(),
+        ValueType::Ident(_) => "str".to_string(),
+        ValueType::Const(value) => match value_to_python_literal(value) {
+            Some(literal) => format!("typing.Literal[{}]", literal),
+            None => "typing.Any".to_string(),
+        },
+    }
+}
+
+fn value_to_python_literal(value: &Value) -> Option<String> {
+    match value {
+        Value::Unit => Some("None".to_string()),
+        Value::Bool(true) => Some("True".to_string()),
+        Value::Bool(false) => Some("False".to_string()),
+        Value::UInt(v) => Some(v.to_string()),
+        Value::Int(v) => Some(v.to_string()),
+        Value::String(v) => Some(string_literal(v)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+{
+"factor/ident": "TestSchema",
+"factor/migrations": [
+{
+"factor/commits": [
+    {
+        "factor/subject": "test/name",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": "String"
+        }
+    },
+    {
+        "factor/subject": "test/owner",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": "Ref"
+        }
+    },
+    {
+        "factor/subject": "test/Person",
+        "factor/set": {
+            "factor/type": "factor/Class",
+            "factor/entityAttributes": [
+                {
+                    "factor/attribute": "test/name",
+                    "factor/required": true
+                }
+            ]
+        }
+    },
+    {
+        "factor/subject": "test/Pet",
+        "factor/set": {
+            "factor/type": "factor/Class",
+            "factor/entityAttributes": [
+                {
+                    "factor/attribute": "test/owner",
+                    "factor/required": false
+                }
+            ],
+            "factor/extend": ["test/Person"]
+        }
+    }
+]
+}
+]
+}
+"#;
+
+    #[test]
+    fn test_static_schema_python_codegen() {
+        let code = generate_schema_from_json(SCHEMA, PythonStyle::Dataclass).unwrap();
+
+        assert!(code.contains("TEST_NAME = \"test/name\"\n"));
+        assert!(code.contains("@dataclasses.dataclass\nclass TestPet:\n"));
+        assert!(code.contains(
+            "    test_name: str\n    id: typing.Optional[EntityId] = None\n    test_owner: typing.Optional[EntityId] = None\n"
+        ));
+
+        let code = generate_schema_from_json(SCHEMA, PythonStyle::Pydantic).unwrap();
+
+        assert!(code.contains("class TestPerson(pydantic.BaseModel):\n"));
+        assert!(code.contains("    test_name: str = pydantic.Field(alias=\"test/name\")\n"));
+    }
+}
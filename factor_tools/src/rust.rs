@@ -1,17 +1,14 @@
-use std::collections::HashSet;
 use std::fmt::Write;
-use std::{collections::HashMap, path::PathBuf};
+use std::path::PathBuf;
 
-use anyhow::Context;
 use factor_core::{
-    data::{from_value_map, ValueType},
-    schema::{
-        builtin::AttrIdent, AttrMapExt, Attribute, AttributeMeta, Class, ClassMeta, StaticSchema,
-    },
-    simple_db::SimpleDb,
+    data::{Value, ValueType},
+    schema::{StaticSchema, ValueConstraint},
 };
 use inflector::Inflector;
 
+use crate::codegen::ResolvedSchema;
+
 pub struct RustAttribute {
     pub name: String,
     pub value: Option<String>,
@@ -347,14 +344,7 @@ impl Module {
     }
 }
 
-#[derive(Default)]
-pub struct Schema {
-    pub attributes: HashMap<String, Attribute>,
-    pub classes: HashMap<String, Class>,
-    pub external: HashSet<String>,
-}
-
-fn value_type_to_rust_type(value_type: &ValueType, schema: &Schema) -> String {
+fn value_type_to_rust_type(value_type: &ValueType, schema: &ResolvedSchema) -> String {
     match value_type {
         ValueType::Any => todo!(),
         ValueType::Unit => todo!(),
@@ -377,6 +367,8 @@ fn value_type_to_rust_type(value_type: &ValueType, schema: &Schema) -> String {
         ValueType::Object(_) => todo!(),
         ValueType::DateTime => "factdb::Timestamp".to_string(),
         ValueType::Url => "url::Url".to_string(),
+        ValueType::GeoPoint => "factdb::data::GeoPoint".to_string(),
+        ValueType::Json => "factdb::Value".to_string(),
         ValueType::Ref => "String".to_string(),
         ValueType::Ident(_constraints) => todo!(),
         ValueType::RefConstrained(_constraints) => todo!(),
@@ -404,6 +396,8 @@ fn value_type_to_expr(ty: &ValueType) -> Expr {
         ValueType::Object(_) => todo!(),
         ValueType::DateTime => Expr::other("factdb::ValueType::DateTime"),
         ValueType::Url => Expr::other("factdb::ValueType::Url"),
+        ValueType::GeoPoint => Expr::other("factdb::ValueType::GeoPoint"),
+        ValueType::Json => Expr::other("factdb::ValueType::Json"),
         ValueType::Ref => Expr::other("factdb::ValueType::Ref"),
         ValueType::Ident(_) => todo!(),
         ValueType::RefConstrained(_) => todo!(),
@@ -412,64 +406,61 @@ fn value_type_to_expr(ty: &ValueType) -> Expr {
     }
 }
 
+fn value_to_expr(value: &Value) -> Result<Expr, anyhow::Error> {
+    let expr = match value {
+        Value::Bool(v) => Expr::other(format!("factdb::Value::Bool({v})")),
+        Value::UInt(v) => Expr::other(format!("factdb::Value::UInt({v})")),
+        Value::Int(v) => Expr::other(format!("factdb::Value::Int({v})")),
+        Value::Float(v) => Expr::other(format!("factdb::Value::Float(({:?}f64).into())", **v)),
+        Value::String(v) => Expr::other(format!("factdb::Value::String({v:?}.to_string())")),
+        other => anyhow::bail!("Unsupported value in constraint: {other:?}"),
+    };
+    Ok(expr)
+}
+
+fn value_constraints_to_expr(constraints: &[ValueConstraint]) -> Result<Expr, anyhow::Error> {
+    let items = constraints
+        .iter()
+        .map(|c| {
+            let item = match c {
+                ValueConstraint::MinLength(v) => {
+                    format!("factdb::ValueConstraint::MinLength({v})")
+                }
+                ValueConstraint::MaxLength(v) => {
+                    format!("factdb::ValueConstraint::MaxLength({v})")
+                }
+                ValueConstraint::Min(v) => {
+                    format!(
+                        "factdb::ValueConstraint::Min({})",
+                        value_to_expr(v)?.render()
+                    )
+                }
+                ValueConstraint::Max(v) => {
+                    format!(
+                        "factdb::ValueConstraint::Max({})",
+                        value_to_expr(v)?.render()
+                    )
+                }
+                ValueConstraint::Regex(v) => {
+                    format!("factdb::ValueConstraint::Regex({v:?}.to_string())")
+                }
+            };
+            Ok(item)
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?
+        .join(", ");
+    Ok(Expr::other(format!("vec![{items}]")))
+}
+
 pub fn generate_schema(
     schema: &StaticSchema,
     with_builtins: bool,
 ) -> Result<String, anyhow::Error> {
-    let mut db = SimpleDb::new();
-
-    for migration in &schema.migrations {
-        for commit in &migration.commits {
-            db = db.apply_pre_commit(commit.clone())?;
-        }
-    }
-    let mut schema = Schema::default();
-
-    for raw_attr in db.entities_by_type(Attribute::QUALIFIED_NAME) {
-        let id = raw_attr.get_id().unwrap();
-        let ident = raw_attr
-            .get(AttrIdent::QUALIFIED_NAME)
-            .and_then(|x| x.as_str())
-            .with_context(|| format!("Invalid attribute with id '{id}': attribtue has no ident!"))?
-            .to_string();
-
-        let attr: Attribute = from_value_map(raw_attr.clone())
-            .with_context(|| format!("Invalid attribute '{ident}'"))?;
-        schema.attributes.insert(ident, attr);
-    }
-
-    for raw_class in db.entities_by_type(Class::QUALIFIED_NAME) {
-        let id = raw_class.get_id().unwrap();
-        let ident = raw_class
-            .get(AttrIdent::QUALIFIED_NAME)
-            .and_then(|x| x.as_str())
-            .with_context(|| format!("Invalid class with id '{id}': class has no ident!"))?
-            .to_string();
-
-        let class: Class = from_value_map(raw_class.clone())
-            .with_context(|| format!("Invalid class '{ident}'"))?;
-        schema.classes.insert(ident, class);
-    }
-
-    if with_builtins {
-        let builtins = factor_core::schema::builtin::builtin_db_schema();
-        for attr in builtins.attributes {
-            schema.external.insert(attr.ident.clone());
-            schema.attributes.insert(attr.ident.clone(), attr);
-        }
-        for class in builtins.classes {
-            schema.external.insert(class.ident.clone());
-            schema.classes.insert(class.ident.clone(), class);
-        }
-    }
+    let schema = ResolvedSchema::build(schema, with_builtins)?;
 
     let mut module = Module::default();
 
-    for attr in schema
-        .attributes
-        .values()
-        .filter(|a| !schema.external.contains(&a.ident))
-    {
+    for attr in schema.local_attributes() {
         let (namespace, plain_name) = attr.parse_split_ident().unwrap();
         let type_name = format!("Attr{}", plain_name.to_pascal_case());
         let rust_type = value_type_to_rust_type(&attr.value_type, &schema);
@@ -544,6 +535,13 @@ pub fn generate_schema(
                             ("unique".to_string(), Expr::Bool(attr.unique)),
                             ("index".to_string(), Expr::Bool(attr.index)),
                             ("strict".to_string(), Expr::Bool(attr.strict)),
+                            (
+                                "collation".to_string(),
+                                Expr::Other(format!(
+                                    "factdb::schema::Collation::{:?}",
+                                    attr.collation
+                                )),
+                            ),
                         ],
                     }
                     .render(),
@@ -555,11 +553,7 @@ pub fn generate_schema(
         module.items.push(Item::Impl(impl_));
     }
 
-    for class in schema
-        .classes
-        .values()
-        .filter(|c| !schema.external.contains(&c.ident))
-    {
+    for class in schema.local_classes() {
         let (namespace, plain_name) = class.parse_split_ident().unwrap();
         let class_type_name = plain_name.to_pascal_case();
 
@@ -575,12 +569,7 @@ pub fn generate_schema(
         });
 
         for parent_ident in &class.extends {
-            let parent_class = schema.classes.get(parent_ident).with_context(|| {
-                format!(
-                    "Invalid class '{}': parent '{}' not found",
-                    class.ident, parent_ident
-                )
-            })?;
+            let parent_class = schema.parent(class, parent_ident)?;
             let parent_name = parent_class.parse_split_ident().unwrap().1.to_snake_case();
             let parent_type = parent_class.parse_split_ident().unwrap().1.to_pascal_case();
 
@@ -593,12 +582,7 @@ pub fn generate_schema(
 
         for field in &class.attributes {
             let attr_name = &field.attribute;
-            let attr = schema.attributes.get(attr_name).with_context(|| {
-                format!(
-                    "Invalid class '{}': attribute '{}' not found",
-                    class.ident, attr_name
-                )
-            })?;
+            let attr = schema.class_attribute(class, attr_name)?;
 
             let field_name = attr_name.to_snake_case();
             let ty = value_type_to_rust_type(&attr.value_type, &schema);
@@ -630,6 +614,29 @@ pub fn generate_schema(
             fields: StructFields::Named(fields),
         };
 
+        let class_attributes = class
+            .attributes
+            .iter()
+            .map(|attr| {
+                let literal = StructLiteral {
+                    name: "factdb::ClassAttribute".to_string(),
+                    fields: vec![
+                        (
+                            "attribute".to_string(),
+                            Expr::other(format!("\"{}\".to_string()", attr.attribute)),
+                        ),
+                        ("required".to_string(), Expr::Bool(attr.required)),
+                        (
+                            "constraints".to_string(),
+                            value_constraints_to_expr(&attr.constraints)?,
+                        ),
+                    ],
+                };
+                Ok(literal.render())
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?
+            .join(", ");
+
         let impl_ = RustImpl {
             trait_name: "factdb::ClassMeta".to_string(),
             type_name: class_type_name,
@@ -673,6 +680,22 @@ pub fn generate_schema(
                             ("title".to_string(), Expr::Other("None".to_string())),
                             ("description".to_string(), Expr::Other("None".to_string())),
                             ("strict".to_string(), Expr::Bool(class.strict)),
+                            (
+                                "id_strategy".to_string(),
+                                Expr::Other(format!(
+                                    "factdb::schema::IdStrategy::{:?}",
+                                    class.id_strategy
+                                )),
+                            ),
+                            (
+                                "max_entities".to_string(),
+                                Expr::Other(format!("{:?}", class.max_entities)),
+                            ),
+                            // TODO: render computed attribute expressions
+                            ("computed_attributes".to_string(), Expr::other("Vec::new()")),
+                            // Attributes of mixins are already expanded.
+                            ("mixins".to_string(), Expr::other("Vec::new()")),
+                            ("is_abstract".to_string(), Expr::Bool(class.is_abstract)),
                             (
                                 "extends".to_string(),
                                 Expr::Other(format!(
@@ -687,28 +710,7 @@ pub fn generate_schema(
                             ),
                             (
                                 "attributes".to_string(),
-                                Expr::Other(format!(
-                                    "vec![{}]",
-                                    class
-                                        .attributes
-                                        .iter()
-                                        .map(|attr| StructLiteral {
-                                            name: "factdb::ClassAttribute".to_string(),
-                                            fields: vec![
-                                                (
-                                                    "attribute".to_string(),
-                                                    Expr::other(format!(
-                                                        "\"{}\".to_string()",
-                                                        attr.attribute
-                                                    )),
-                                                ),
-                                                ("required".to_string(), Expr::Bool(attr.required)),
-                                            ],
-                                        }
-                                        .render())
-                                        .collect::<Vec<_>>()
-                                        .join(", ")
-                                )),
+                                Expr::Other(format!("vec![{}]", class_attributes)),
                             ),
                         ],
                     }
@@ -734,8 +736,7 @@ pub fn generate_schema_from_json(
     contents: &str,
     with_builtins: bool,
 ) -> Result<String, anyhow::Error> {
-    let jd = &mut serde_json::Deserializer::from_str(contents);
-    let schema: StaticSchema = serde_path_to_error::deserialize(jd)?;
+    let schema = crate::schema_file::parse_static_schema(contents)?;
     generate_schema(&schema, with_builtins)
 }
 
@@ -743,10 +744,7 @@ pub fn generate_schema_from_file(
     path: impl Into<PathBuf>,
     with_builtins: bool,
 ) -> Result<String, anyhow::Error> {
-    let path = path.into();
-    let contents = std::fs::read_to_string(&path)
-        .with_context(|| format!("Could not read file '{}'", path.display()))?;
-    let schema: StaticSchema = serde_json::from_str(&contents)?;
+    let schema = crate::schema_file::read_static_schema(path)?;
     generate_schema(&schema, with_builtins)
 }
 
@@ -797,7 +795,17 @@ mod tests {
 ]
 }
 "#;
-        let code = generate_schema_from_json(schema, true).unwrap();
-        eprintln!("{code}");
+        generate_schema_from_json(schema, true).unwrap();
+    }
+
+    #[test]
+    fn test_value_constraints_to_expr_rejects_unsupported_values() {
+        let expr = value_constraints_to_expr(&[ValueConstraint::Min(Value::UInt(1))]).unwrap();
+        assert_eq!(
+            expr.render(),
+            "vec![factdb::ValueConstraint::Min(factdb::Value::UInt(1))]"
+        );
+
+        assert!(value_constraints_to_expr(&[ValueConstraint::Max(Value::List(vec![]))]).is_err());
     }
 }
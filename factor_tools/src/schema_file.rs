@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use factor_core::{
+    data::from_value_map,
+    schema::{
+        builtin::AttrIdent, AttrMapExt, Attribute, AttributeMeta, Class, ClassMeta, DbSchema,
+        StaticSchema,
+    },
+    simple_db::SimpleDb,
+};
+
+/// Parse a [`StaticSchema`] from JSON.
+///
+/// Errors contain the path of the invalid value.
+pub fn parse_static_schema(contents: &str) -> Result<StaticSchema, anyhow::Error> {
+    let jd = &mut serde_json::Deserializer::from_str(contents);
+    let schema: StaticSchema = serde_path_to_error::deserialize(jd)?;
+    Ok(schema)
+}
+
+/// Read and parse a [`StaticSchema`] from a JSON file.
+pub fn read_static_schema(path: impl Into<PathBuf>) -> Result<StaticSchema, anyhow::Error> {
+    let path = path.into();
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Could not read file '{}'", path.display()))?;
+    parse_static_schema(&contents)
+        .with_context(|| format!("Invalid schema file '{}'", path.display()))
+}
+
+/// Apply all migrations of a [`StaticSchema`] and collect the resulting
+/// attributes and classes.
+///
+/// Builtin items are not included.
+pub fn build_db_schema(schema: &StaticSchema) -> Result<DbSchema, anyhow::Error> {
+    let mut db = SimpleDb::new();
+
+    for migration in &schema.migrations {
+        for commit in &migration.commits {
+            db = db.apply_pre_commit(commit.clone())?;
+        }
+    }
+
+    let mut out = DbSchema::default();
+
+    for raw_attr in db.entities_by_type(Attribute::QUALIFIED_NAME) {
+        let id = raw_attr.get_id().unwrap();
+        let ident = raw_attr
+            .get(AttrIdent::QUALIFIED_NAME)
+            .and_then(|x| x.as_str())
+            .with_context(|| format!("Invalid attribute with id '{id}': attribtue has no ident!"))?
+            .to_string();
+
+        let attr: Attribute = from_value_map(raw_attr.clone())
+            .with_context(|| format!("Invalid attribute '{ident}'"))?;
+        out.attributes.push(attr);
+    }
+
+    for raw_class in db.entities_by_type(Class::QUALIFIED_NAME) {
+        let id = raw_class.get_id().unwrap();
+        let ident = raw_class
+            .get(AttrIdent::QUALIFIED_NAME)
+            .and_then(|x| x.as_str())
+            .with_context(|| format!("Invalid class with id '{id}': class has no ident!"))?
+            .to_string();
+
+        let class: Class = from_value_map(raw_class.clone())
+            .with_context(|| format!("Invalid class '{ident}'"))?;
+        out.classes.push(class);
+    }
+
+    // SimpleDb uses a HashMap, so sort for deterministic output.
+    out.attributes.sort_by(|a, b| a.ident.cmp(&b.ident));
+    out.classes.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+    Ok(out)
+}
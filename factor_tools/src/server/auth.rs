@@ -0,0 +1,253 @@
+//! Authentication of API requests.
+
+use factor_core::data::{Id, Value};
+use factor_engine::AuthContext;
+use subtle::ConstantTimeEq;
+
+use super::ServerConfig;
+
+/// A static API key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKey {
+    pub key: String,
+    /// User the key belongs to, available as `$current_user` in policies.
+    pub user: Option<Id>,
+    /// Only allow reads.
+    pub read_only: bool,
+}
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            user: None,
+            read_only: false,
+        }
+    }
+
+    pub fn with_user(mut self, user: Id) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
+
+/// Policy variable of the current user.
+///
+/// Only ever set from the user of an API key or the `sub` claim of a token.
+const CURRENT_USER: &str = "current_user";
+
+/// Validation of JSON web tokens signed with HMAC-SHA256.
+///
+/// Tokens must contain an `exp` claim.
+/// Claims are mapped as follows:
+/// * `sub`: the id of the user, available as `$current_user` in policies
+/// * `scope`: space separated scopes. If present, tokens without the `write`
+///   scope are read-only.
+/// * String, number and boolean claims listed in [`Self::variables`] are
+///   available as policy variables with the claim name, eg `$tenant`.
+///   All other claims are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JwtConfig {
+    pub secret: Vec<u8>,
+    /// Claims that are available as policy variables.
+    ///
+    /// `current_user` can not be set by a claim.
+    pub variables: Vec<String>,
+}
+
+impl JwtConfig {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            variables: Vec::new(),
+        }
+    }
+
+    pub fn with_variable(mut self, claim: impl Into<String>) -> Self {
+        self.variables.push(claim.into());
+        self
+    }
+
+    fn is_variable(&self, claim: &str) -> bool {
+        claim != CURRENT_USER && self.variables.iter().any(|v| v == claim)
+    }
+
+    fn validate(&self, token: &str) -> Result<Identity, String> {
+        let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+        let data = jsonwebtoken::decode::<serde_json::Map<String, serde_json::Value>>(
+            token,
+            &jsonwebtoken::DecodingKey::from_secret(&self.secret),
+            &validation,
+        )
+        .map_err(|err| format!("Invalid token: {}", err))?;
+
+        let mut identity = Identity::default();
+        for (name, value) in data.claims {
+            match (name.as_str(), value) {
+                ("sub", serde_json::Value::String(sub)) => {
+                    let user = sub
+                        .parse()
+                        .map_err(|_| "Invalid token: 'sub' must be an entity id".to_string())?;
+                    identity.user = Some(user);
+                }
+                ("scope", serde_json::Value::String(scope)) => {
+                    identity.read_only = !scope.split(' ').any(|s| s == "write");
+                }
+                (claim, _) if !self.is_variable(claim) => {}
+                (_, serde_json::Value::String(v)) => identity.variables.push((name, v.into())),
+                (_, serde_json::Value::Bool(v)) => identity.variables.push((name, v.into())),
+                (_, serde_json::Value::Number(v)) => {
+                    let value = if let Some(v) = v.as_u64() {
+                        Value::UInt(v)
+                    } else if let Some(v) = v.as_i64() {
+                        Value::Int(v)
+                    } else {
+                        Value::from(v.as_f64().unwrap_or_default())
+                    };
+                    identity.variables.push((name, value));
+                }
+                _ => {}
+            }
+        }
+        Ok(identity)
+    }
+}
+
+/// The authenticated caller of a request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Identity {
+    pub user: Option<Id>,
+    pub read_only: bool,
+    pub variables: Vec<(String, Value)>,
+}
+
+impl Identity {
+    /// Build the [`AuthContext`] for the engine.
+    ///
+    /// Returns `None` for anonymous callers.
+    pub fn auth_context(&self) -> Option<AuthContext> {
+        if self.user.is_none() && self.variables.is_empty() {
+            return None;
+        }
+        let mut ctx = AuthContext::new();
+        for (name, value) in &self.variables {
+            if name != CURRENT_USER {
+                ctx = ctx.with_variable(name.clone(), value.clone());
+            }
+        }
+        if let Some(user) = self.user {
+            ctx = ctx.with_user(user);
+        }
+        Some(ctx)
+    }
+}
+
+/// Authenticate a request based on the bearer token.
+///
+/// All requests are accepted if no authentication is configured.
+/// Tokens and API keys are compared in constant time.
+pub(crate) fn authenticate(config: &ServerConfig, token: Option<&str>) -> Result<Identity, String> {
+    if config.auth_token.is_none() && config.api_keys.is_empty() && config.jwt.is_none() {
+        return Ok(Identity::default());
+    }
+    let token = token.ok_or_else(|| "Missing or invalid bearer token".to_string())?;
+
+    if config
+        .auth_token
+        .as_deref()
+        .map_or(false, |auth_token| secret_eq(auth_token, token))
+    {
+        return Ok(Identity::default());
+    }
+    if let Some(key) = config
+        .api_keys
+        .iter()
+        .find(|key| secret_eq(&key.key, token))
+    {
+        return Ok(Identity {
+            user: key.user,
+            read_only: key.read_only,
+            variables: Vec::new(),
+        });
+    }
+    match &config.jwt {
+        Some(jwt) => jwt.validate(token),
+        None => Err("Missing or invalid bearer token".to_string()),
+    }
+}
+
+/// Compare secrets without leaking the position of the first difference.
+fn secret_eq(secret: &str, token: &str) -> bool {
+    secret.as_bytes().ct_eq(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(secret: &[u8], claims: serde_json::Value) -> String {
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let user = Id::random();
+        let config = ServerConfig {
+            api_keys: vec![ApiKey::new("reader").with_read_only(true)],
+            jwt: Some(JwtConfig::new("secret").with_variable("tenant")),
+            ..Default::default()
+        };
+
+        assert!(authenticate(&config, None).is_err());
+        assert!(authenticate(&config, Some("invalid")).is_err());
+        assert!(authenticate(&config, Some("reader")).unwrap().read_only);
+
+        // Year 3000.
+        let exp = 32_503_680_000u64;
+        let jwt = token(
+            b"secret",
+            serde_json::json!({ "sub": user.to_string(), "exp": exp, "tenant": "a" }),
+        );
+        let identity = authenticate(&config, Some(&jwt)).unwrap();
+        assert_eq!(identity.user, Some(user));
+        assert!(!identity.read_only);
+        let ctx = identity.auth_context().unwrap();
+        assert_eq!(ctx.user(), Some(user));
+        assert_eq!(ctx.variables().get("tenant"), Some(&Value::from("a")));
+
+        // Only listed claims become variables, and the user always comes
+        // from `sub`.
+        let other = Id::random();
+        let spoofed = token(
+            b"secret",
+            serde_json::json!({
+                "exp": exp,
+                "role": "admin",
+                "current_user": other.to_string(),
+            }),
+        );
+        let identity = authenticate(&config, Some(&spoofed)).unwrap();
+        assert_eq!(identity.user, None);
+        assert!(identity.variables.is_empty());
+        assert!(identity.auth_context().is_none());
+
+        let read = token(
+            b"secret",
+            serde_json::json!({ "sub": user.to_string(), "exp": exp, "scope": "read" }),
+        );
+        assert!(authenticate(&config, Some(&read)).unwrap().read_only);
+
+        let forged = token(b"other", serde_json::json!({ "exp": exp }));
+        assert!(authenticate(&config, Some(&forged)).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+//! A minimal HTTP/JSON API for a [`Db`].
+//!
+//! Endpoints:
+//! * `GET /healthz`: the [`HealthReport`](factor_core::db::HealthReport) of
+//!   the database. Responds with 503 if the database is unhealthy. Does not
+//!   require authentication.
+//! * `GET /schema`: the current [`DbSchema`](factor_core::schema::DbSchema)
+//! * `GET /migrations`: all applied migrations
+//! * `GET /entity/<ID_OR_IDENT>`: a single entity
+//! * `PATCH /entity/<ID_OR_IDENT>`: apply a JSON Patch (RFC 6902) to an
+//!   entity, see [`factor_core::data::json_patch`]
+//! * `POST /select`: run a [`Select`] query
+//! * `POST /batch`: apply a [`Batch`]
+//! * `POST /migrate`: apply a [`Migration`]. Only available for
+//!   unrestricted callers.
+//! * `GET /sync/events?since=<EVENT_ID>&limit=<N>`: log events for
+//!   replication, see [`crate::sync`]. Only available for unrestricted
+//!   callers of a [`LogDb`].
+//!
+//! All bodies are JSON. Errors are returned as `{"error": "<message>"}`.
+//! Request bodies larger than [`ServerConfig::max_body_size`] are rejected
+//! with 413.
+//!
+//! Requests are authenticated with bearer tokens, see [`ServerConfig`].
+
+mod auth;
+pub use self::auth::{ApiKey, JwtConfig};
+/// Response of the `/sync/events` endpoint.
+pub use factor_engine::backend::log::EventPage;
+
+use std::{
+    convert::{Infallible, TryFrom},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use factor_core::{
+    data::{json_patch::JsonPatch, patch::Patch, IdOrIdent},
+    db::Db,
+    error::{EntityNotFound, Error},
+    query::{migrate::Migration, mutate::Batch, select::Select},
+    schema::AttrMapExt,
+};
+use factor_engine::{
+    backend::log::{EventId, LogDb},
+    Engine,
+};
+use hyper::{
+    body::HttpBody,
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    /// Bearer token that must be provided in the `Authorization` header.
+    /// Grants full access.
+    ///
+    /// If no token, API key or JWT config is set, all requests are accepted.
+    pub auth_token: Option<String>,
+    /// API keys accepted as bearer tokens.
+    pub api_keys: Vec<ApiKey>,
+    /// Accept JSON web tokens as bearer tokens.
+    pub jwt: Option<JwtConfig>,
+    /// Reject all requests that would modify the database.
+    pub read_only: bool,
+    /// Maximum size of a request body in bytes.
+    pub max_body_size: usize,
+}
+
+impl ServerConfig {
+    pub const DEFAULT_BIND: &'static str = "127.0.0.1:7700";
+    pub const DEFAULT_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: Self::DEFAULT_BIND.parse().unwrap(),
+            auth_token: None,
+            api_keys: Vec::new(),
+            jwt: None,
+            read_only: false,
+            max_body_size: Self::DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+/// Serve the database until the provided shutdown future resolves.
+pub async fn serve(
+    db: Db,
+    config: ServerConfig,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    let listener = std::net::TcpListener::bind(config.bind)?;
+    serve_listener(db, config, listener, shutdown).await
+}
+
+/// Like [`serve`], but accepts connections on an already bound listener.
+///
+/// Allows binding port 0 and reading back the assigned address.
+/// [`ServerConfig::bind`] is ignored.
+pub async fn serve_listener(
+    db: Db,
+    config: ServerConfig,
+    listener: std::net::TcpListener,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    let api = Arc::new(Api { db, config });
+
+    let make_service = make_service_fn(move |_conn| {
+        let api = api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let api = api.clone();
+                async move { Ok::<_, Infallible>(api.handle(req).await) }
+            }))
+        }
+    });
+
+    hyper::Server::from_tcp(listener)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
+
+struct Api {
+    db: Db,
+    config: ServerConfig,
+}
+
+impl Api {
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        match self.route(req).await {
+            Ok(res) => res,
+            Err(err) => json_response(
+                err.status,
+                &serde_json::json!({
+                    "error": err.message,
+                }),
+            ),
+        }
+    }
+
+    async fn route(&self, req: Request<Body>) -> Result<Response<Body>, ApiError> {
+        // Health checks are used by load balancers and orchestrators, which
+        // can not provide credentials.
+        if req.method() == Method::GET && req.uri().path().trim_end_matches('/') == "/healthz" {
+            return Ok(self.health().await);
+        }
+
+        let identity = self.authenticate(&req)?;
+        let db = self.request_db(&identity)?;
+
+        let method = req.method().clone();
+        let path = req.uri().path().trim_end_matches('/').to_string();
+
+        match (method, path.as_str()) {
+            (Method::GET, "/schema") => Ok(json_ok(&db.schema().await?)),
+            (Method::GET, "/migrations") => Ok(json_ok(&db.migrations().await?)),
+            (Method::GET, p) if p.starts_with("/entity/") => {
+                let id = IdOrIdent::new_str(&p["/entity/".len()..]);
+                let entity = db.entity(id).await?;
+                Ok(json_ok(&entity))
+            }
+            (Method::PATCH, p) if p.starts_with("/entity/") => {
+                self.ensure_writable()?;
+                let ident = IdOrIdent::new_str(&p["/entity/".len()..]);
+                let id = match ident.as_id() {
+                    Some(id) => id,
+                    None => db
+                        .entity(ident.clone())
+                        .await?
+                        .get_id()
+                        .ok_or_else(|| Error::NotFound(EntityNotFound::new(ident)))?,
+                };
+                let patch: JsonPatch = self.read_json(req).await?;
+                db.patch(id, Patch::try_from(patch)?).await?;
+                Ok(json_ok(&()))
+            }
+            (Method::POST, "/select") => {
+                let query: Select = self.read_json(req).await?;
+                Ok(json_ok(&db.select(query).await?))
+            }
+            (Method::POST, "/batch") => {
+                self.ensure_writable()?;
+                let batch: Batch = self.read_json(req).await?;
+                db.batch(batch).await?;
+                Ok(json_ok(&()))
+            }
+            (Method::POST, "/migrate") => {
+                // Migrations can change or remove the policies that restrict
+                // the caller.
+                if identity.auth_context().is_some() {
+                    return Err(ApiError::new(
+                        StatusCode::FORBIDDEN,
+                        "Migrations require unrestricted access",
+                    ));
+                }
+                self.ensure_writable()?;
+                let migration: Migration = self.read_json(req).await?;
+                db.migrate(migration).await?;
+                Ok(json_ok(&()))
+            }
+            (Method::GET, "/sync/events") => {
+                if identity.auth_context().is_some() {
+                    return Err(ApiError::new(
+                        StatusCode::FORBIDDEN,
+                        "Sync requires unrestricted access",
+                    ));
+                }
+                let log = self.log_db().ok_or_else(|| {
+                    ApiError::new(
+                        StatusCode::NOT_FOUND,
+                        "Sync is only supported for log databases",
+                    )
+                })?;
+                let (since, limit) = sync_params(req.uri().query().unwrap_or_default())?;
+                Ok(json_ok(&log.event_page(since, limit).await?))
+            }
+            _ => Err(ApiError::new(StatusCode::NOT_FOUND, "Not found")),
+        }
+    }
+
+    async fn health(&self) -> Response<Body> {
+        match self.db.health().await {
+            Ok(report) if report.is_healthy() => json_ok(&report),
+            Ok(report) => json_response(StatusCode::SERVICE_UNAVAILABLE, &report),
+            Err(err) => json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &serde_json::json!({
+                    "error": format!("{:#}", err),
+                }),
+            ),
+        }
+    }
+
+    /// The [`LogDb`] backing the served database, if any.
+    fn log_db(&self) -> Option<&LogDb> {
+        self.db
+            .find_client::<Engine>()?
+            .backend()
+            .as_any()?
+            .downcast_ref::<LogDb>()
+    }
+
+    fn authenticate(&self, req: &Request<Body>) -> Result<auth::Identity, ApiError> {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        auth::authenticate(&self.config, token)
+            .map_err(|message| ApiError::new(StatusCode::UNAUTHORIZED, message))
+    }
+
+    /// Build the database handle for a caller.
+    ///
+    /// Fails if the caller has an auth context, but the database does not
+    /// support policies, see [`Db::with_auth_variables`].
+    /// Otherwise the caller would get unrestricted access.
+    fn request_db(&self, identity: &auth::Identity) -> Result<Db, ApiError> {
+        let db = match identity.auth_context() {
+            Some(ctx) => self
+                .db
+                .with_auth_variables(ctx.variables())
+                .ok_or_else(|| {
+                    ApiError::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "The database does not support access policies",
+                    )
+                })?,
+            None => self.db.clone(),
+        };
+        if identity.read_only {
+            Ok(db.read_only())
+        } else {
+            Ok(db)
+        }
+    }
+
+    /// Read a JSON request body of at most [`ServerConfig::max_body_size`]
+    /// bytes.
+    async fn read_json<T: serde::de::DeserializeOwned>(
+        &self,
+        req: Request<Body>,
+    ) -> Result<T, ApiError> {
+        let limit = self.config.max_body_size;
+        let too_large = || {
+            ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Request body exceeds the limit of {} bytes", limit),
+            )
+        };
+        let length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if length.map_or(false, |length| length > limit as u64) {
+            return Err(too_large());
+        }
+
+        let mut body = req.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk =
+                chunk.map_err(|err| ApiError::new(StatusCode::BAD_REQUEST, err.to_string()))?;
+            if bytes.len() + chunk.len() > limit {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        serde_json::from_slice(&bytes).map_err(|err| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request body: {}", err),
+            )
+        })
+    }
+
+    fn ensure_writable(&self) -> Result<(), ApiError> {
+        if self.config.read_only {
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "Server is running in read-only mode",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Default and maximum number of events returned by `/sync/events`.
+pub const SYNC_PAGE_SIZE: usize = 1000;
+
+/// Parse the `since` and `limit` query parameters of `/sync/events`.
+fn sync_params(query: &str) -> Result<(EventId, usize), ApiError> {
+    let mut since = 0;
+    let mut limit = SYNC_PAGE_SIZE;
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let invalid = |_| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid parameter '{}'", key),
+            )
+        };
+        match key {
+            "since" => since = value.parse().map_err(invalid)?,
+            "limit" => limit = value.parse::<usize>().map_err(invalid)?.min(SYNC_PAGE_SIZE),
+            _ => {}
+        }
+    }
+    Ok((since, limit))
+}
+
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status = match &err {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::ReadOnly(_) | Error::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            Error::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self::new(status, format!("{:#}", err))
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Error::from(err).into()
+    }
+}
+
+fn json_ok<T: serde::Serialize>(value: &T) -> Response<Body> {
+    json_response(StatusCode::OK, value)
+}
+
+fn json_response<T: serde::Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(err.to_string()))
+            .unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{data::Id, map};
+    use factor_engine::{backend::memory::MemoryDb, Engine};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_server_with_http_client() {
+        let config = ServerConfig {
+            auth_token: Some("secret".to_string()),
+            api_keys: vec![ApiKey::new("user-key").with_user(Id::random())],
+            max_body_size: 512,
+            ..Default::default()
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let url = url.as_str();
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_listener(
+            Engine::new(MemoryDb::new()).into_client(),
+            config,
+            listener,
+            async move {
+                stopped.await.ok();
+            },
+        ));
+
+        let db = factor_client_http::connect(url, Some("secret".to_string())).unwrap();
+        let id = Id::random();
+        // Retry until the server accepts connections.
+        let mut attempts = 0;
+        while let Err(err) = db.create(id, map! { "factor/title": "a" }).await {
+            attempts += 1;
+            assert!(attempts < 50, "{}", err);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data.get("factor/title"), Some(&"a".into()));
+
+        let patch = Request::builder()
+            .method(Method::PATCH)
+            .uri(format!("{}/entity/{}", url, id))
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::from(
+                r#"[{"op": "replace", "path": "/factor~1title", "value": "b"}]"#,
+            ))
+            .unwrap();
+        let res = hyper::Client::new().request(patch).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let data = db.entity(id).await.unwrap();
+        assert_eq!(data.get("factor/title"), Some(&"b".into()));
+        assert!(db.entity(Id::random()).await.is_err());
+        assert_eq!(db.select_map(Select::new()).await.unwrap().len(), 1);
+
+        let unauthorized = factor_client_http::connect(url, None).unwrap();
+        assert!(unauthorized.schema().await.is_err());
+
+        let client = factor_client_http::HttpDbClient::new(url, None).unwrap();
+        let health: factor_core::db::HealthReport = client.get("/healthz").await.unwrap();
+        assert!(health.is_healthy());
+
+        let large = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/select", url))
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::from(vec![b' '; 1024]))
+            .unwrap();
+        let res = hyper::Client::new().request(large).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Callers restricted by policies can not migrate.
+        let migrate = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/migrate", url))
+            .header(header::AUTHORIZATION, "Bearer user-key")
+            .body(Body::from(r#"{"actions": []}"#))
+            .unwrap();
+        let res = hyper::Client::new().request(migrate).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+//! Replicate a database from another instance.
+//!
+//! A replica pulls the log events of a source database served with
+//! [`crate::server`] and applies them to a local [`LogDb`].
+//! The position in the source log is stored in the replica log together
+//! with the applied changes (see [`LogDb::apply_synced_events`]), so an
+//! interrupted sync resumes where it stopped and events are never applied
+//! twice.
+//!
+//! Compacting or purging the source reassigns its event ids and starts a new
+//! log generation (see [`LogDb::generation`]). The replica is then purged and
+//! synced again from the start.
+
+use factor_engine::backend::{
+    log::{EventId, LogDb, SyncCursor},
+    Backend,
+};
+
+use crate::{
+    remote::HttpDbClient,
+    server::{EventPage, SYNC_PAGE_SIZE},
+};
+
+/// Statistics of a [`pull`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncStats {
+    /// Number of applied events.
+    pub events: u64,
+    /// Id of the last applied event of the source.
+    pub cursor: EventId,
+    /// The target was purged and synced again, because the source log was
+    /// compacted or purged.
+    pub full_resync: bool,
+}
+
+/// Pull all new events of the source database into `target`.
+///
+/// `source` is the name under which the sync cursor is stored.
+/// It must uniquely identify the source database, eg. by using its url.
+///
+/// The target must only contain data synced from the source, since it is
+/// purged when a full resync is required.
+pub async fn pull(
+    client: &HttpDbClient,
+    source: &str,
+    target: &LogDb,
+) -> Result<SyncStats, anyhow::Error> {
+    let mut cursor = target.sync_cursor(source).await;
+    let mut stats = SyncStats {
+        events: 0,
+        cursor: cursor.event_id,
+        full_resync: false,
+    };
+
+    loop {
+        let page: EventPage = client
+            .get(&format!(
+                "/sync/events?since={}&limit={}",
+                cursor.event_id, SYNC_PAGE_SIZE
+            ))
+            .await?;
+        // Event ids of different generations are unrelated, even if the
+        // source log grew past the cursor again.
+        let synced = cursor.event_id > 0;
+        if synced
+            && (cursor.generation != Some(page.generation) || page.last_event_id < cursor.event_id)
+        {
+            if stats.full_resync {
+                anyhow::bail!("The log of '{}' changed during a full resync", source);
+            }
+            Backend::purge_all_data(target).await?;
+            cursor = SyncCursor::default();
+            stats = SyncStats {
+                events: 0,
+                cursor: 0,
+                full_resync: true,
+            };
+            continue;
+        }
+        let last = match page.events.last() {
+            Some(event) => event.id(),
+            None => break,
+        };
+
+        stats.events += target
+            .apply_synced_events(source, page.generation, page.events)
+            .await?;
+        cursor = SyncCursor {
+            generation: Some(page.generation),
+            event_id: last,
+        };
+        stats.cursor = last;
+        if last >= page.last_event_id {
+            break;
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use factor_core::{data::Id, map};
+    use factor_engine::{backend::log::store_memory::MemoryLogStore, Engine};
+
+    use crate::server::{serve_listener, ServerConfig};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_pull() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let url = url.as_str();
+        let source_log = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let source = Engine::new(source_log.clone()).into_client();
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_listener(
+            source.clone(),
+            ServerConfig::default(),
+            listener,
+            async move {
+                stopped.await.ok();
+            },
+        ));
+
+        let ids = (0..3).map(|_| Id::random()).collect::<Vec<_>>();
+        for id in &ids {
+            source
+                .create(*id, map! { "factor/title": "a" })
+                .await
+                .unwrap();
+        }
+
+        let client = HttpDbClient::new(url, None).unwrap();
+        let replica_log = LogDb::open(MemoryLogStore::new()).await.unwrap();
+        let replica = Engine::new(replica_log.clone()).into_client();
+
+        // Retry until the server accepts connections.
+        let mut attempts = 0;
+        let stats = loop {
+            match pull(&client, url, &replica_log).await {
+                Ok(stats) => break stats,
+                Err(err) => {
+                    attempts += 1;
+                    assert!(attempts < 50, "{}", err);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                }
+            }
+        };
+        assert_eq!(
+            stats,
+            SyncStats {
+                events: 3,
+                cursor: 3,
+                full_resync: false,
+            }
+        );
+
+        source
+            .merge(ids[0], map! { "factor/title": "b" })
+            .await
+            .unwrap();
+        let stats = pull(&client, url, &replica_log).await.unwrap();
+        assert_eq!(
+            stats,
+            SyncStats {
+                events: 1,
+                cursor: 4,
+                full_resync: false,
+            }
+        );
+
+        let data = replica.entity(ids[0]).await.unwrap();
+        assert_eq!(data.get("factor/title"), Some(&"b".into()));
+        assert_eq!(replica.entity(ids[2]).await.unwrap().get_id(), Some(ids[2]));
+
+        // Compaction renumbers the source events, so the replica must be
+        // synced again from the start, even once the log grew past the
+        // cursor.
+        source_log.compact().await.unwrap();
+        let new_ids = (0..5).map(|_| Id::random()).collect::<Vec<_>>();
+        for id in &new_ids {
+            source
+                .create(*id, map! { "factor/title": "c" })
+                .await
+                .unwrap();
+        }
+        let last_event_id = source_log.last_event_id().await;
+        assert!(last_event_id > 4);
+
+        let stats = pull(&client, url, &replica_log).await.unwrap();
+        assert!(stats.full_resync);
+        assert_eq!(stats.cursor, last_event_id);
+        assert_eq!(
+            replica_log.sync_cursor(url).await,
+            SyncCursor {
+                generation: Some(source_log.generation().await),
+                event_id: last_event_id,
+            }
+        );
+        let data = replica.entity(ids[0]).await.unwrap();
+        assert_eq!(data.get("factor/title"), Some(&"b".into()));
+        for id in new_ids.iter().chain(&ids) {
+            assert_eq!(replica.entity(*id).await.unwrap().get_id(), Some(*id));
+        }
+
+        let stats = pull(&client, url, &replica_log).await.unwrap();
+        assert_eq!(
+            stats,
+            SyncStats {
+                events: 0,
+                cursor: last_event_id,
+                full_resync: false,
+            }
+        );
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -1,10 +1,39 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::PathBuf};
 
 use factor_core::{data, data::ValueType, schema};
 
 use anyhow::anyhow;
 use inflector::Inflector;
 
+/// Generate Typescript type definitions for a static schema file.
+///
+/// Mirrors [`crate::rust::generate_schema`].
+pub fn generate_schema(
+    schema: &schema::StaticSchema,
+    factor_import_path: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let db_schema = crate::schema_file::build_db_schema(schema)?;
+    let mut code = schema_to_typescript(&db_schema, factor_import_path)?;
+    code.push('\n');
+    Ok(code)
+}
+
+pub fn generate_schema_from_json(
+    contents: &str,
+    factor_import_path: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::parse_static_schema(contents)?;
+    generate_schema(&schema, factor_import_path)
+}
+
+pub fn generate_schema_from_file(
+    path: impl Into<PathBuf>,
+    factor_import_path: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let schema = crate::schema_file::read_static_schema(path)?;
+    generate_schema(&schema, factor_import_path)
+}
+
 /**
 Generate Typescript type definitions for a database schema.
 */
@@ -34,16 +63,43 @@ pub fn schema_to_typescript(
             path: source.to_string(),
             items: vec![
                 "EntityId".to_string(),
+                "Ref".to_string(),
+                "Ident".to_string(),
                 "Url".to_string(),
                 "Timestamp".to_string(),
-                "BaseEntityData".to_string(),
+                "BaseEntity".to_string(),
             ],
         };
         module.add(import);
     } else {
+        // Ids are branded strings, so they can't be mixed up with
+        // arbitrary strings.
         let id = Item::TypeAlias {
             name: "EntityId".to_string(),
-            ty: Type::String,
+            ty: Type::Intersection(vec![
+                Type::String,
+                Type::Object(ObjectType {
+                    fields: vec![FieldDef {
+                        name: "readonly __brand".to_string(),
+                        is_optional: false,
+                        ty: Type::Constant(Value::Str("EntityId".to_string())),
+                    }],
+                }),
+            ]),
+        };
+        // Reference to an entity of a specific class.
+        let reference = Item::TypeAlias {
+            name: "Ref<T extends string>".to_string(),
+            ty: Type::Intersection(vec![
+                Type::Ident("EntityId".to_string()),
+                Type::Object(ObjectType {
+                    fields: vec![FieldDef {
+                        name: "readonly __entityType".to_string(),
+                        is_optional: false,
+                        ty: Type::Ident("T".to_string()),
+                    }],
+                }),
+            ]),
         };
         let ident = Item::TypeAlias {
             name: "Ident".to_string(),
@@ -87,9 +143,15 @@ pub fn schema_to_typescript(
             },
         };
 
-        module
-            .items
-            .extend(vec![id, ident, id_or_ident, url, timestamp, base]);
+        module.items.extend(vec![
+            id,
+            reference,
+            ident,
+            id_or_ident,
+            url,
+            timestamp,
+            base,
+        ]);
     };
 
     module.add_newlines(1);
@@ -175,12 +237,29 @@ fn build_entity(
 
     let entity_name = entity.ident.replace('/', "_").to_class_case();
 
+    // Entities of abstract classes always have the type of a concrete
+    // child class.
+    let type_ty = if entity.is_abstract {
+        let children = concrete_descendants(entity, schema);
+        if children.is_empty() {
+            Type::Never
+        } else {
+            Type::Union(
+                children
+                    .into_iter()
+                    .map(|child| Type::Constant(Value::Str(child.ident.clone())))
+                    .collect(),
+            )
+        }
+    } else {
+        Type::Constant(Value::Str(entity.ident.clone()))
+    };
     field_defs.insert(
         0,
         FieldDef {
             name: "factor/type".to_string(),
             is_optional: false,
-            ty: Type::Constant(Value::Str(entity.ident.clone())),
+            ty: type_ty,
         },
     );
 
@@ -199,10 +278,35 @@ fn build_entity(
     Ok(vec![ty_const, interface, Item::Newlines(1)])
 }
 
+/// Non-abstract classes that directly or indirectly extend the class.
+fn concrete_descendants<'a>(
+    entity: &schema::Class,
+    schema: &'a schema::DbSchema,
+) -> Vec<&'a schema::Class> {
+    let mut parents = vec![entity.ident.as_str()];
+    let mut descendants = Vec::new();
+    while let Some(parent) = parents.pop() {
+        for class in &schema.classes {
+            if class.extends.iter().any(|ident| ident == parent)
+                && !descendants
+                    .iter()
+                    .any(|c: &&schema::Class| c.ident == class.ident)
+            {
+                descendants.push(class);
+                parents.push(&class.ident);
+            }
+        }
+    }
+    descendants.retain(|class| !class.is_abstract);
+    descendants
+}
+
 #[allow(dead_code)]
 #[derive(PartialEq, Eq, Debug)]
 enum Value {
     Str(String),
+    /// Raw literal code, like numbers or booleans.
+    Raw(String),
     Array(Vec<Self>),
     Object(Vec<(String, Self)>),
 }
@@ -212,6 +316,7 @@ impl Value {
         let prefix: String = " ".repeat(indent);
         let out = match self {
             Value::Str(value) => format!("\"{}\"", value),
+            Value::Raw(value) => value.clone(),
             Value::Array(items) => {
                 let values = items
                     .iter()
@@ -245,12 +350,14 @@ enum Type {
     Any,
     Null,
     Void,
+    Never,
     Bool,
     Number,
     String,
     Array(Box<Self>),
     Object(ObjectType),
     Union(Vec<Self>),
+    Intersection(Vec<Self>),
     Ident(String),
     Generic { name: String, args: Vec<Self> },
 }
@@ -261,6 +368,7 @@ impl Type {
             Type::Any => "any".to_string(),
             Type::Null => "null".to_string(),
             Type::Void => "void".to_string(),
+            Type::Never => "never".to_string(),
             Type::Bool => "boolean".to_string(),
             Type::Number => "number".to_string(),
             Type::String => "string".to_string(),
@@ -279,6 +387,11 @@ impl Type {
                 .map(|var| var.render(indent))
                 .collect::<Vec<_>>()
                 .join(" | "),
+            Type::Intersection(items) => items
+                .iter()
+                .map(|item| item.render(indent))
+                .collect::<Vec<_>>()
+                .join(" & "),
             Type::Ident(name) => name.clone(),
             Type::Generic { name, args } => {
                 let generics = args
@@ -434,7 +547,7 @@ fn field_ts_type(field: &schema::ClassAttribute, attr: &schema::Attribute) -> Ty
 
 fn value_to_ts_type(ty: &ValueType) -> Type {
     match ty {
-        ValueType::Any => Type::Any,
+        ValueType::Any | ValueType::Json => Type::Any,
         ValueType::Unit => Type::Void,
         ValueType::Bool => Type::Bool,
         ValueType::Int | ValueType::UInt | ValueType::Float => Type::Number,
@@ -464,24 +577,40 @@ fn value_to_ts_type(ty: &ValueType) -> Type {
         }
         ValueType::DateTime => Type::Ident("Timestamp".to_string()),
         ValueType::Url => Type::Ident("Url".to_string()),
+        // Latitude, longitude.
+        ValueType::GeoPoint => Type::Array(Box::new(Type::Number)),
         ValueType::Ref => Type::Ident("EntityId".to_string()),
         ValueType::Ident(_) => Type::Ident("Ident".to_string()),
-        ValueType::RefConstrained(_) => {
-            // TODO: use type alias for specific entity id if restricted to single type
-            Type::Ident("EntityId".to_string())
+        ValueType::RefConstrained(constraint) => {
+            let types = constraint
+                .allowed_entity_types
+                .iter()
+                .map(|ty| Type::Constant(Value::Str(ty.to_string())))
+                .collect::<Vec<_>>();
+            if types.is_empty() {
+                Type::Ident("EntityId".to_string())
+            } else {
+                Type::Generic {
+                    name: "Ref".to_string(),
+                    args: vec![Type::Union(types)],
+                }
+            }
         }
         ValueType::Const(v) => Type::Constant(value_to_ts_value(v)),
-        ValueType::EmbeddedEntity => todo!(),
+        ValueType::EmbeddedEntity => Type::Generic {
+            name: "Record".to_string(),
+            args: vec![Type::String, Type::Any],
+        },
     }
 }
 
 fn value_to_ts_value(v: &data::Value) -> Value {
     match v {
-        data::Value::Unit => todo!(),
-        data::Value::Bool(_) => todo!(),
-        data::Value::UInt(_) => todo!(),
-        data::Value::Int(_) => todo!(),
-        data::Value::Float(_) => todo!(),
+        data::Value::Unit => Value::Raw("null".to_string()),
+        data::Value::Bool(v) => Value::Raw(v.to_string()),
+        data::Value::UInt(v) => Value::Raw(v.to_string()),
+        data::Value::Int(v) => Value::Raw(v.to_string()),
+        data::Value::Float(v) => Value::Raw(v.to_string()),
         data::Value::String(s) => Value::Str(s.clone()),
         data::Value::Bytes(_) => todo!(),
         data::Value::List(_) => todo!(),
@@ -489,3 +618,88 @@ fn value_to_ts_value(v: &data::Value) -> Value {
         data::Value::Id(_) => todo!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_schema_typescript_codegen() {
+        let schema = r#"
+{
+"factor/ident": "TestSchema",
+"factor/migrations": [
+{
+"factor/commits": [
+    {
+        "factor/subject": "test/name",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": "String"
+        }
+    },
+    {
+        "factor/subject": "test/owner",
+        "factor/set": {
+            "factor/type": "factor/Attribute",
+            "factor/valueType": {"RefConstrained": {"allowed_entity_types": ["test/Person"]}}
+        }
+    },
+    {
+        "factor/subject": "test/Person",
+        "factor/set": {
+            "factor/type": "factor/Class",
+            "factor/entityAttributes": [
+                {
+                    "factor/attribute": "test/name",
+                    "factor/required": true
+                }
+            ]
+        }
+    },
+    {
+        "factor/subject": "test/Pet",
+        "factor/set": {
+            "factor/type": "factor/Class",
+            "factor/entityAttributes": [
+                {
+                    "factor/attribute": "test/owner",
+                    "factor/required": false
+                }
+            ]
+        }
+    }
+]
+}
+]
+}
+"#;
+        let code = generate_schema_from_json(schema, None).unwrap();
+
+        assert!(code.contains("export interface TestPerson extends BaseEntity {"));
+        assert!(code.contains("\"test/name\": string,"));
+        assert!(code.contains("\"test/owner\"?: Ref<\"test/Person\"> | null,"));
+        assert!(code.contains("export type EntityId = string & {readonly __brand: \"EntityId\",};"));
+    }
+
+    #[test]
+    fn test_abstract_class_typescript_codegen() {
+        let schema = schema::DbSchema {
+            attributes: vec![schema::Attribute::new("test/name", ValueType::String)],
+            classes: vec![
+                schema::Class::new("test/Animal")
+                    .into_abstract()
+                    .with_attribute("test/name", true),
+                schema::Class::new("test/Dog").with_extend("test/Animal"),
+                schema::Class::new("test/Cat").with_extend("test/Animal"),
+            ],
+            ..Default::default()
+        };
+        let code = schema_to_typescript(&schema, None).unwrap();
+
+        assert!(code.contains("\"factor/type\": \"test/Dog\" | \"test/Cat\","));
+        assert!(
+            code.contains("export interface TestDog extends Omit<TestAnimal, \"factor/type\"> {")
+        );
+    }
+}